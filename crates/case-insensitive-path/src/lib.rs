@@ -341,13 +341,43 @@ impl std::hash::Hash for CaseInsensitivePathBuf {
     }
 }
 
+/// [Utf8TypedPath::normalize], except that `..` climbing above the start of a relative path is kept instead of being
+/// dropped - `mods\..\..\x` keeps pointing outside of whatever it gets joined to, so that whoever joins it can tell and
+/// refuse it
+fn normalize_keeping_escapes(path: Utf8TypedPath<'_>) -> Utf8TypedPathBuf {
+    let relative = path
+        .components()
+        .next()
+        .is_none_or(|component| component.is_normal() || component.is_parent() || component.is_current());
+    let escapes = path
+        .components()
+        .filter(|component| relative && (component.is_normal() || component.is_parent()))
+        .fold((0_usize, 0_usize), |(depth, escapes), component| match component.is_parent() {
+            true if depth == 0 => (depth, escapes + 1),
+            true => (depth - 1, escapes),
+            false => (depth + 1, escapes),
+        })
+        .1;
+    match escapes {
+        0 => path.normalize(),
+        escapes => {
+            let normalized = path.normalize();
+            std::iter::repeat_n("..", escapes)
+                .chain(Some(normalized.as_str()).filter(|normalized| !normalized.is_empty()))
+                .fold(Utf8TypedPathBuf::windows(), |escaping, segment| {
+                    escaping.tap_mut(|escaping| escaping.push(segment))
+                })
+        }
+    }
+}
+
 impl FromStr for CaseInsensitivePathBuf {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         // yes, we're gonna assume windows. this is only valid for this project.
         // if you need this open an issue and we'll figure something out
-        let original = Utf8TypedPath::windows(&s).normalize();
+        let original = normalize_keeping_escapes(Utf8TypedPath::windows(&s));
         Utf8TypedPath::windows(&original.as_str().to_lowercase())
             .into_unix_encoding_checked()
             .context("normalizing to unix encoding for comparison")
@@ -471,6 +501,18 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parent_segments_climbing_out_are_kept() -> Result<()> {
+        [
+            ("mods\\Some Mod\\..\\Other.esp", "mods\\Other.esp"),
+            ("mods\\..\\..\\..\\home\\user\\.bashrc", "..\\..\\home\\user\\.bashrc"),
+            ("..\\mods\\..", ".."),
+            ("\\..\\etc\\passwd", "\\etc\\passwd"),
+        ]
+        .into_iter()
+        .try_for_each(|(raw, normalized)| CaseInsensitivePathBuf::from_str(raw).map(|parsed| assert_eq!(parsed.original.as_str(), normalized, "{raw}")))
+    }
+
     #[test]
     fn test_example_dir() -> Result<()> {
         let _cwd = tempfile::tempdir()?;
@@ -1,23 +1,39 @@
 use {
     crate::{
         progress_span::{ProgressDelta, ProgressState},
-        stream_compat::{UnboundedReceiverStream, UnboundedReceiverStreamExt},
+        stream_compat::ReceiverStreamExt,
     },
     hooks::{IoHook, read::ReadHookExt, write::WriteHookExt},
+    parking_lot::Mutex,
     std::{
         borrow::Cow,
         collections::{BTreeMap, BTreeSet, btree_map::Entry},
         io::{Read, Write},
         iter::once,
-        sync::{Arc, atomic::AtomicUsize},
+        sync::{
+            Arc,
+            atomic::{AtomicI64, AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
     },
     tap::prelude::*,
 };
 
+/// messages the receiver can fall behind by, updates that don't fit are held back (and coalesced) by the senders.
+/// every [ProgressCommunicator] is guaranteed one extra slot on top of it
+pub const CHANNEL_CAPACITY: usize = 4096;
+/// updates of a span are coalesced and sent at most this often...
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+/// ...unless this much piles up in the meantime
+pub const FLUSH_AMOUNT: i64 = 4 * 1024 * 1024;
+/// how long a sender waits before retrying a [Update::Start]/[Update::Finish]/[Update::Abandon] that did not fit
+const RETRY_INTERVAL: Duration = Duration::from_micros(100);
+
 static NEXT_SPAN_ID: AtomicUsize = AtomicUsize::new(0);
 
 pub mod hooks;
 pub mod stream_compat;
+pub mod throughput;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
 pub struct SpanId(usize);
@@ -62,6 +78,20 @@ impl SpanPath {
 pub struct ProgressMap {
     pub finished_pending: BTreeSet<SpanPath>,
     pub progress: BTreeMap<SpanPath, ProgressSpan>,
+    pub aggregation: Aggregation,
+    /// bytes below each parent span, kept up to date with [Aggregation::Bytes] only.
+    /// bytes of children that finished (and were removed) stay counted
+    pub byte_totals: BTreeMap<SpanPath, ProgressState>,
+}
+
+/// what the state of [ProgressKind::Parent] spans shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aggregation {
+    /// children opened (total) and finished (current)
+    #[default]
+    Children,
+    /// sum of the byte counters below, for the parents that have any
+    Bytes,
 }
 
 pub mod progress_span {
@@ -87,7 +117,7 @@ pub mod progress_span {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProgressKind {
     Bytes,
     Iter,
@@ -99,6 +129,9 @@ pub enum Update {
     Start(ProgressSpan),
     Update(progress_span::ProgressDelta),
     Finish,
+    /// the work was given up on (an error or a panic), the span is removed together with everything below it
+    /// instead of waiting for its children to finish
+    Abandon,
 }
 
 #[derive(Debug)]
@@ -107,32 +140,94 @@ pub struct ProgressMessage {
     pub update: Update,
 }
 
-#[derive(Clone)]
-struct CommunicatorInner(Arc<Sender>);
+struct CommunicatorInner(Mutex<Sender>);
+
+impl Clone for CommunicatorInner {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.0.lock().clone()))
+    }
+}
 
 impl CommunicatorInner {
     pub fn new() -> (ReceiverStream, Self) {
         let (tx, rx) = self::channel();
-        (rx.into_stream(), Self(Arc::new(tx)))
+        (rx.into_stream(), Self(Mutex::new(tx)))
+    }
+
+    /// hands the message back when the channel is full
+    fn try_send(&self, message: ProgressMessage) -> Option<ProgressMessage> {
+        self.0
+            .lock()
+            .try_send(message)
+            .err()
+            .and_then(|error| match error.is_full() {
+                true => Some(error.into_inner()),
+                false => {
+                    tracing::trace!("could not send a message:\n{:?}", error.into_inner());
+                    None
+                }
+            })
+    }
+
+    /// waits for the receiver to make room instead of dropping the message
+    fn send_lossless(&self, mut message: ProgressMessage) {
+        while let Some(rejected) = self.try_send(message) {
+            message = rejected;
+            std::thread::sleep(RETRY_INTERVAL);
+        }
     }
 }
 
-type Receiver = futures::channel::mpsc::UnboundedReceiver<ProgressMessage>;
-type ReceiverStream = UnboundedReceiverStream<ProgressMessage>;
-type Sender = futures::channel::mpsc::UnboundedSender<ProgressMessage>;
+type Receiver = futures::channel::mpsc::Receiver<ProgressMessage>;
+type ReceiverStream = stream_compat::ReceiverStream<ProgressMessage>;
+type Sender = futures::channel::mpsc::Sender<ProgressMessage>;
 
 fn channel() -> (Sender, Receiver) {
-    futures::channel::mpsc::unbounded()
+    futures::channel::mpsc::channel(CHANNEL_CAPACITY)
+}
+
+/// deltas not sent yet, io hooks report every chunk and the receiver only needs a few updates a second
+struct Pending {
+    total: AtomicI64,
+    current: AtomicI64,
+    last_flush: Mutex<Instant>,
+}
+
+impl Pending {
+    fn new() -> Self {
+        Self {
+            total: AtomicI64::new(0),
+            current: AtomicI64::new(0),
+            last_flush: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn add(&self, ProgressDelta { total, current }: ProgressDelta) -> i64 {
+        self.total.fetch_add(total, Ordering::AcqRel);
+        self.current.fetch_add(current, Ordering::AcqRel) + current
+    }
+
+    fn take(&self) -> ProgressDelta {
+        ProgressDelta {
+            total: self.total.swap(0, Ordering::AcqRel),
+            current: self.current.swap(0, Ordering::AcqRel),
+        }
+    }
 }
 
 pub struct ProgressCommunicator {
     span: SpanPath,
     communicator: CommunicatorInner,
+    pending: Pending,
+    abandoned: bool,
 }
 
 impl Drop for ProgressCommunicator {
     fn drop(&mut self) {
-        self.send(Update::Finish)
+        match self.abandoned || std::thread::panicking() {
+            true => self.send(Update::Abandon),
+            false => self.send(Update::Finish),
+        }
     }
 }
 
@@ -149,6 +244,7 @@ pub trait Progress: Sized {
             name: name.into(),
             state: ProgressState { total: expected, current: 0 },
             kind: ProgressKind::Bytes,
+            samples: Default::default(),
         });
         writer.hook_write(move |current| {
             // TODO: FIXME
@@ -166,6 +262,7 @@ pub trait Progress: Sized {
             name: name.into(),
             state: ProgressState { total: expected, current: 0 },
             kind: ProgressKind::Bytes,
+            samples: Default::default(),
         });
         reader.hook_read(move |current| {
             // TODO: FIXME
@@ -185,6 +282,7 @@ pub trait Progress: Sized {
             name: name.into(),
             state: ProgressState { total: expected, current: 0 },
             kind: ProgressKind::Bytes,
+            samples: Default::default(),
         });
         IoHook {
             inner: reader,
@@ -229,23 +327,79 @@ impl ProgressCommunicator {
             Self {
                 span: SpanPath(Arc::from([])),
                 communicator,
+                pending: Pending::new(),
+                abandoned: false,
             },
         )
     }
+    /// updates are coalesced (see [FLUSH_INTERVAL] and [FLUSH_AMOUNT]), the other messages are never lost
     fn send(&self, message: Update) {
-        if let Err(m) = self.communicator.0.unbounded_send(ProgressMessage {
+        match message {
+            Update::Update(delta) => {
+                let pending = self.pending.add(delta);
+                if pending.abs() >= FLUSH_AMOUNT
+                    || self
+                        .pending
+                        .last_flush
+                        .try_lock()
+                        .is_some_and(|last_flush| last_flush.elapsed() >= FLUSH_INTERVAL)
+                {
+                    self.send_pending(false)
+                }
+            }
+            lifecycle => {
+                if matches!(lifecycle, Update::Finish) {
+                    self.send_pending(true);
+                }
+                self.communicator.send_lossless(ProgressMessage {
+                    span: self.span.clone(),
+                    update: lifecycle,
+                })
+            }
+        }
+    }
+
+    fn send_pending(&self, lossless: bool) {
+        let delta = self.pending.take();
+        if delta.total == 0 && delta.current == 0 {
+            return;
+        }
+        *self.pending.last_flush.lock() = Instant::now();
+        let message = ProgressMessage {
             span: self.span.clone(),
-            update: message,
-        }) {
-            tracing::trace!("could not send a message:\n{m:?}");
+            update: Update::Update(delta),
+        };
+        match lossless {
+            true => self.communicator.send_lossless(message),
+            false => {
+                // the receiver is behind, the delta goes out with one of the next flushes
+                if let Some(ProgressMessage {
+                    update: Update::Update(delta), ..
+                }) = self.communicator.try_send(message)
+                {
+                    self.pending.add(delta);
+                }
+            }
         }
     }
 
+    /// sends the coalesced updates right away
+    pub fn flush(&self) {
+        self.send_pending(true)
+    }
+
+    /// for error paths - the span (and whatever is below it) is dropped from the progress right away, see [Update::Abandon]
+    pub fn abandon(mut self) {
+        self.abandoned = true;
+    }
+
     /// you should probably use [Self::child] unless you're writing a custom extension
     pub fn span_raw(&self, span: ProgressSpan) -> Self {
         let this = Self {
             span: self.span.child(),
             communicator: self.communicator.clone(),
+            pending: Pending::new(),
+            abandoned: false,
         };
         this.send(Update::Start(span));
         this
@@ -256,6 +410,7 @@ impl ProgressCommunicator {
             kind: ProgressKind::Parent,
             name: name.into(),
             state: ProgressState { total: 0, current: 0 },
+            samples: Default::default(),
         })
     }
 }
@@ -265,6 +420,26 @@ pub struct ProgressSpan {
     pub name: Cow<'static, str>,
     pub state: ProgressState,
     pub kind: ProgressKind,
+    /// filled in by [ProgressMap], whatever is sent along with [Update::Start] is ignored
+    pub samples: throughput::Samples,
+}
+
+impl ProgressSpan {
+    /// units per second, see [throughput::Samples::rate]
+    pub fn rate(&self) -> Option<f64> {
+        self.samples.rate()
+    }
+
+    pub fn throughput(&self) -> Option<throughput::Throughput> {
+        self.rate().map(|rate| throughput::Throughput {
+            rate,
+            remaining: self.state.total - self.state.current,
+        })
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        self.throughput().and_then(|throughput| throughput.eta())
+    }
 }
 
 impl ProgressMap {
@@ -274,6 +449,8 @@ impl ProgressMap {
             Self {
                 progress: Default::default(),
                 finished_pending: Default::default(),
+                aggregation: Default::default(),
+                byte_totals: Default::default(),
             },
             rx,
             communicator,
@@ -316,6 +493,32 @@ mod immutable_access {
         pub fn has_children(&self, span: &SpanPath) -> bool {
             self.children(span).next().is_some()
         }
+        /// parents combine the byte counters below them, everything else reports its own throughput
+        pub fn throughput(&self, span: &SpanPath) -> Option<throughput::Throughput> {
+            self.progress
+                .get(span)
+                .and_then(|progress| match progress.kind {
+                    ProgressKind::Parent => self
+                        .with_descendants(span)
+                        .filter(|(_, descendant)| matches!(descendant.kind, ProgressKind::Bytes))
+                        .filter_map(|(_, descendant)| descendant.throughput())
+                        .pipe(throughput::combined),
+                    ProgressKind::Bytes | ProgressKind::Iter => progress.throughput(),
+                })
+        }
+        /// state to show for the span, parents with byte totals (see [Aggregation::Bytes]) are shown as byte counters
+        pub fn displayed(&self, span: &SpanPath) -> Option<(ProgressKind, &ProgressState)> {
+            self.progress
+                .get(span)
+                .map(|progress| match (progress.kind, self.byte_totals.get(span)) {
+                    (ProgressKind::Parent, Some(bytes)) => (ProgressKind::Bytes, bytes),
+                    (kind, _) => (kind, &progress.state),
+                })
+        }
+        pub fn eta(&self, span: &SpanPath) -> Option<Duration> {
+            self.throughput(span)
+                .and_then(|throughput| throughput.eta())
+        }
     }
 }
 
@@ -355,27 +558,108 @@ mod mutable_access {
 // mutable access
 
 impl ProgressMap {
-    pub fn handle(&mut self, ProgressMessage { span, update }: ProgressMessage) {
+    pub fn handle(&mut self, message: ProgressMessage) {
+        self.handle_at(message, Instant::now())
+    }
+
+    /// meant to be called before any messages are handled - switching to [Aggregation::Bytes] later only counts the
+    /// byte counters that are still open
+    pub fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
+        self.byte_totals = match aggregation {
+            Aggregation::Children => Default::default(),
+            Aggregation::Bytes => self
+                .progress
+                .iter()
+                .filter(|(_, progress)| progress.kind == ProgressKind::Parent)
+                .filter_map(|(path, _)| {
+                    self.with_descendants(path)
+                        .filter(|(_, descendant)| descendant.kind == ProgressKind::Bytes)
+                        .map(|(_, descendant)| (descendant.state.total, descendant.state.current))
+                        .reduce(|(total, current), (next_total, next_current)| (total + next_total, current + next_current))
+                        .map(|(total, current)| (path.clone(), ProgressState { total, current }))
+                })
+                .collect(),
+        };
+    }
+
+    /// adds the change of a byte counter to all the parents above it
+    fn add_bytes(&mut self, span: &SpanPath, ProgressDelta { total, current }: ProgressDelta) {
+        std::iter::successors(span.parent(), SpanPath::parent)
+            .filter(|ancestor| {
+                self.progress
+                    .get(ancestor)
+                    .is_some_and(|progress| progress.kind == ProgressKind::Parent)
+            })
+            .for_each(|ancestor| {
+                self.byte_totals
+                    .entry(ancestor)
+                    .or_insert(ProgressState { total: 0, current: 0 })
+                    .pipe(|bytes| {
+                        bytes.total += total;
+                        bytes.current += current;
+                    })
+            })
+    }
+
+    fn remove(&mut self, span: &SpanPath) {
+        self.progress.remove(span);
+        self.byte_totals.remove(span);
+    }
+
+    /// messages from below an abandoned span keep coming until their communicators are dropped, they're ignored
+    fn is_orphan(&self, span: &SpanPath) -> bool {
+        span.parent()
+            .is_some_and(|parent| !parent.is_empty() && !self.progress.contains_key(&parent))
+    }
+
+    /// [Self::handle], with the time the message is sampled at
+    pub fn handle_at(&mut self, ProgressMessage { span, update }: ProgressMessage, now: Instant) {
+        if self.is_orphan(&span) {
+            return;
+        }
         let parent = span.parent();
+        let byte_delta = match (self.aggregation, &update) {
+            (Aggregation::Children, _) | (_, Update::Finish | Update::Abandon) => None,
+            (Aggregation::Bytes, Update::Start(started)) => (started.kind == ProgressKind::Bytes).then_some(ProgressDelta {
+                total: started.state.total,
+                current: started.state.current,
+            }),
+            (Aggregation::Bytes, Update::Update(delta)) => self
+                .progress
+                .get(&span)
+                .is_some_and(|progress| progress.kind == ProgressKind::Bytes)
+                .then_some(ProgressDelta {
+                    total: delta.total,
+                    current: delta.current,
+                }),
+        };
+        if let Some(byte_delta) = byte_delta {
+            self.add_bytes(&span, byte_delta);
+        }
 
         match update {
             Update::Start(progress_state) => match self.progress.entry(span) {
                 Entry::Vacant(vacant_entry) => {
-                    vacant_entry.insert(progress_state);
+                    vacant_entry.insert(progress_state.tap_mut(|started| started.samples = throughput::Samples::started_at(now, started.state.current)));
                     if let Some(parent) = parent {
-                        self.handle(ProgressMessage {
-                            span: parent,
-                            update: Update::Update(DELTA_NEW),
-                        })
+                        self.handle_at(
+                            ProgressMessage {
+                                span: parent,
+                                update: Update::Update(DELTA_NEW),
+                            },
+                            now,
+                        )
                     }
                 }
                 Entry::Occupied(occupied_entry) => occupied_entry.into_mut().pipe(|m| {
-                    progress_state.pipe(|ProgressSpan { name, state, kind }| {
+                    progress_state.pipe(|ProgressSpan { name, state, kind, samples: _ }| {
                         m.name = name;
                         m.kind = kind;
                         state
                             .pipe(|ProgressState { total, current }| ProgressDelta { total, current })
-                            .apply(&mut m.state)
+                            .apply(&mut m.state);
+                        m.samples.record(now, m.state.current);
                     });
                 }),
             },
@@ -383,31 +667,41 @@ impl ProgressMap {
                 Entry::Vacant(vacant_entry) => {
                     vacant_entry.insert(ProgressSpan {
                         name: Cow::Borrowed("<unknown>"),
+                        samples: throughput::Samples::started_at(now, delta.current),
                         state: delta.pipe(|ProgressDelta { total, current }| ProgressState { total, current }),
                         kind: ProgressKind::Iter,
                     });
                     if let Some(parent) = parent {
-                        self.handle(ProgressMessage {
-                            span: parent,
-                            update: Update::Update(DELTA_NEW),
-                        })
+                        self.handle_at(
+                            ProgressMessage {
+                                span: parent,
+                                update: Update::Update(DELTA_NEW),
+                            },
+                            now,
+                        )
                     }
                 }
                 Entry::Occupied(mut occupied_entry) => {
-                    delta.apply(&mut occupied_entry.get_mut().state);
+                    occupied_entry.get_mut().pipe(|progress| {
+                        delta.apply(&mut progress.state);
+                        progress.samples.record(now, progress.state.current);
+                    });
                     if occupied_entry
                         .get()
                         .pipe(|e| &e.state)
                         .pipe(|e| e.total == e.current)
                         && self.finished_pending.contains(&span)
                     {
-                        self.progress.remove(&span);
+                        self.remove(&span);
                         self.finished_pending.remove(&span);
                         if let Some(parent) = parent {
-                            self.handle(ProgressMessage {
-                                span: parent,
-                                update: Update::Update(DELTA_FINISHED),
-                            })
+                            self.handle_at(
+                                ProgressMessage {
+                                    span: parent,
+                                    update: Update::Update(DELTA_FINISHED),
+                                },
+                                now,
+                            )
                         }
                     }
                 }
@@ -417,15 +711,292 @@ impl ProgressMap {
                     self.finished_pending.insert(span);
                 }
                 false => {
-                    self.progress.remove(&span);
+                    self.remove(&span);
                     if let Some(parent) = parent {
-                        self.handle(ProgressMessage {
-                            span: parent,
-                            update: Update::Update(DELTA_FINISHED),
-                        })
+                        self.handle_at(
+                            ProgressMessage {
+                                span: parent,
+                                update: Update::Update(DELTA_FINISHED),
+                            },
+                            now,
+                        )
                     }
                 }
             },
+            Update::Abandon => {
+                if !self.progress.contains_key(&span) {
+                    return;
+                }
+                if self.aggregation == Aggregation::Bytes {
+                    // the parents above only wait for the bytes that were done
+                    self.progress
+                        .get(&span)
+                        .into_iter()
+                        .chain(
+                            self.with_descendants(&span)
+                                .map(|(_, descendant)| descendant),
+                        )
+                        .filter(|abandoned| abandoned.kind == ProgressKind::Bytes)
+                        .map(|abandoned| abandoned.state.total - abandoned.state.current)
+                        .sum::<i64>()
+                        .pipe(|remaining| self.add_bytes(&span, ProgressDelta { total: -remaining, current: 0 }));
+                }
+                self.with_descendants(&span)
+                    .map(|(descendant, _)| descendant.clone())
+                    .chain([span.clone()])
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .for_each(|abandoned| {
+                        self.remove(&abandoned);
+                        self.finished_pending.remove(&abandoned);
+                    });
+                if let Some(parent) = parent {
+                    self.handle_at(
+                        ProgressMessage {
+                            span: parent,
+                            update: Update::Update(DELTA_FINISHED),
+                        },
+                        now,
+                    )
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, futures::StreamExt, test_log::test};
+
+    #[test]
+    fn test_updates_are_coalesced() {
+        const UPDATES: i64 = 10_000_000;
+        const SPANS: i64 = 4;
+        let (_, messages, root) = ProgressMap::new();
+        let spans = (0..SPANS)
+            .map(|span| {
+                root.span_raw(ProgressSpan {
+                    name: format!("file-{span}").into(),
+                    state: ProgressState {
+                        total: UPDATES / SPANS,
+                        current: 0,
+                    },
+                    kind: ProgressKind::Bytes,
+                    samples: Default::default(),
+                })
+            })
+            .collect::<Vec<_>>();
+        // nothing reads the messages yet, whatever the senders queue up has to fit in the channel
+        let spans = spans
+            .into_iter()
+            .map(|span| {
+                std::thread::spawn(move || {
+                    (0..UPDATES / SPANS).for_each(|_| Progress::send(&span, Update::Update(ProgressDelta { total: 0, current: 1 })));
+                    span
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|sending| sending.join().expect("sending thread panicked"))
+            .collect::<Vec<_>>();
+        let receiving = std::thread::spawn(move || futures::executor::block_on(messages.collect::<Vec<_>>()));
+        drop(spans);
+        drop(root);
+        let received = receiving.join().expect("receiving thread panicked");
+
+        assert!(received.len() < CHANNEL_CAPACITY + 64, "[{}] messages for [{UPDATES}] updates", received.len());
+        let totals = received
+            .iter()
+            .filter_map(|ProgressMessage { span, update }| match update {
+                Update::Update(ProgressDelta { current, .. }) => Some((span.clone(), *current)),
+                _ => None,
+            })
+            .fold(BTreeMap::<SpanPath, i64>::new(), |mut totals, (span, current)| {
+                *totals.entry(span).or_default() += current;
+                totals
+            });
+        assert_eq!(totals.len(), SPANS as usize);
+        assert!(totals.values().all(|total| *total == UPDATES / SPANS), "{totals:?}");
+        let lifecycle = |started: bool| {
+            received
+                .iter()
+                .filter(|message| match started {
+                    true => matches!(message.update, Update::Start(_)),
+                    false => matches!(message.update, Update::Finish),
+                })
+                .count()
+        };
+        assert_eq!((lifecycle(true), lifecycle(false)), (SPANS as usize, SPANS as usize + 1));
+    }
+
+    #[test]
+    fn test_flush_sends_pending_updates() {
+        let (mut progress, mut messages, root) = ProgressMap::new();
+        let span = root.child("phase");
+        Progress::send(&span, Update::Update(ProgressDelta { total: 0, current: 3 }));
+        span.flush();
+        messages.close();
+        futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .for_each(|message| progress.handle(message));
+        assert_eq!(
+            progress
+                .progress
+                .iter()
+                // the root only collects the top level spans
+                .filter(|(path, _)| !path.is_empty())
+                .map(|(_, span)| (span.name.to_string(), span.state.current))
+                .collect::<Vec<_>>(),
+            [("phase".to_string(), 3)]
+        );
+    }
+
+    fn bytes(parent: &ProgressCommunicator, name: &'static str, total: i64) -> ProgressCommunicator {
+        parent.span_raw(ProgressSpan {
+            name: name.into(),
+            state: ProgressState { total, current: 0 },
+            kind: ProgressKind::Bytes,
+            samples: Default::default(),
+        })
+    }
+
+    fn advance(span: &ProgressCommunicator, by: i64) {
+        Progress::send(span, Update::Update(ProgressDelta { total: 0, current: by }));
+        span.flush();
+    }
+
+    /// (name, kind, current, total) of every span, as displayed
+    fn displayed(progress: &ProgressMap) -> Vec<(String, ProgressKind, i64, i64)> {
+        progress
+            .progress
+            .iter()
+            .filter(|(path, _)| !path.is_empty())
+            .filter_map(|(path, span)| {
+                progress
+                    .displayed(path)
+                    .map(|(kind, state)| (span.name.to_string(), kind, state.current, state.total))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parents_sum_up_the_bytes_below() {
+        let (mut progress, mut messages, root) = ProgressMap::new();
+        progress.set_aggregation(Aggregation::Bytes);
+        let install = root.child("install");
+        let downloads = install.child("downloads");
+        let directives = install.child("directives");
+        let (a, b, c) = (bytes(&downloads, "a", 100), bytes(&downloads, "b", 300), bytes(&directives, "c", 50));
+        advance(&a, 100);
+        advance(&b, 120);
+        advance(&c, 10);
+        // finished counters are removed, what they did stays counted
+        drop(a);
+        messages.close();
+        futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .for_each(|message| progress.handle(message));
+        assert_eq!(
+            displayed(&progress),
+            [
+                ("install".to_string(), ProgressKind::Bytes, 230, 450),
+                ("downloads".to_string(), ProgressKind::Bytes, 220, 400),
+                ("b".to_string(), ProgressKind::Bytes, 120, 300),
+                ("directives".to_string(), ProgressKind::Bytes, 10, 50),
+                ("c".to_string(), ProgressKind::Bytes, 10, 50),
+            ]
+        );
+        drop((b, c));
+    }
+
+    #[test]
+    fn test_parents_without_bytes_count_children() {
+        let (mut progress, mut messages, root) = ProgressMap::new();
+        let install = root.child("install");
+        let downloads = install.child("downloads");
+        let (a, b) = (bytes(&downloads, "a", 100), bytes(&downloads, "b", 300));
+        advance(&b, 30);
+        drop(a);
+        let empty = install.child("empty");
+        messages.close();
+        futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .for_each(|message| progress.handle(message));
+        let counted = [
+            ("install".to_string(), ProgressKind::Parent, 0, 2),
+            ("downloads".to_string(), ProgressKind::Parent, 1, 2),
+            ("b".to_string(), ProgressKind::Bytes, 30, 300),
+            ("empty".to_string(), ProgressKind::Parent, 0, 0),
+        ];
+        assert_eq!(displayed(&progress), counted);
+        // switching afterwards only finds the counters that are still open
+        progress.set_aggregation(Aggregation::Bytes);
+        assert_eq!(
+            displayed(&progress),
+            [
+                ("install".to_string(), ProgressKind::Bytes, 30, 300),
+                ("downloads".to_string(), ProgressKind::Bytes, 30, 300),
+                ("b".to_string(), ProgressKind::Bytes, 30, 300),
+                ("empty".to_string(), ProgressKind::Parent, 0, 0),
+            ]
+        );
+        progress.set_aggregation(Aggregation::Children);
+        assert_eq!(displayed(&progress), counted);
+        drop((b, empty));
+    }
+
+    fn handle_all(progress: &mut ProgressMap, messages: &mut ReceiverStream) {
+        messages.close();
+        futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .for_each(|message| progress.handle(message));
+    }
+
+    #[test]
+    fn test_abandoned_child_does_not_leave_zombies() {
+        let (mut progress, mut messages, root) = ProgressMap::new();
+        let install = root.child("install");
+        let archive = install.child("archive");
+        let file = bytes(&archive, "file", 100);
+        advance(&file, 40);
+        // waits for the archive
+        drop(install);
+        archive.abandon();
+        // still sending from below the abandoned span
+        advance(&file, 10);
+        drop(file);
+        handle_all(&mut progress, &mut messages);
+        assert!(displayed(&progress).is_empty(), "{:?}", displayed(&progress));
+        assert!(progress.finished_pending.is_empty(), "{:?}", progress.finished_pending);
+        drop(root);
+    }
+
+    #[test]
+    fn test_panicking_child_is_abandoned() {
+        let (mut progress, mut messages, root) = ProgressMap::new();
+        progress.set_aggregation(Aggregation::Bytes);
+        let install = root.child("install");
+        let (a, b) = (bytes(&install, "a", 100), bytes(&install, "b", 100));
+        advance(&a, 40);
+        advance(&b, 100);
+        drop(b);
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _a = a;
+            panic!("simulated failure while extracting a");
+        }));
+        assert!(panicked.is_err());
+        handle_all(&mut progress, &mut messages);
+        // the 60 bytes that were never done are not waited for
+        assert_eq!(displayed(&progress), [("install".to_string(), ProgressKind::Bytes, 140, 140)]);
+        assert_eq!(
+            progress
+                .progress
+                .values()
+                .find(|span| span.name == "install")
+                .map(|install| (install.state.current, install.state.total)),
+            Some((2, 2)),
+            "both children are counted as finished"
+        );
+        drop((install, root));
+    }
+}
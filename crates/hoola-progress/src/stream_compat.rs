@@ -1,5 +1,5 @@
 use {
-    futures::channel::mpsc::UnboundedReceiver,
+    futures::channel::mpsc::{Receiver, UnboundedReceiver},
     futures_util::{Stream, StreamExt},
     std::{
         pin::Pin,
@@ -40,3 +40,37 @@ impl<T> UnboundedReceiver<T> {
         UnboundedReceiverStream::new(self)
     }
 }
+
+#[derive(Debug)]
+pub struct ReceiverStream<T> {
+    inner: Receiver<T>,
+}
+
+impl<T> ReceiverStream<T> {
+    fn new(recv: Receiver<T>) -> Self {
+        Self { inner: recv }
+    }
+
+    pub fn into_inner(self) -> Receiver<T> {
+        self.inner
+    }
+
+    pub fn close(&mut self) {
+        self.inner.close()
+    }
+}
+
+impl<T> Stream for ReceiverStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+#[extension_traits::extension(pub trait ReceiverStreamExt)]
+impl<T> Receiver<T> {
+    fn into_stream(self) -> ReceiverStream<T> {
+        ReceiverStream::new(self)
+    }
+}
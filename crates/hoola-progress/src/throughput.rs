@@ -0,0 +1,88 @@
+//! rates and ETAs, derived from a short window of recent samples so that they follow the current speed
+//! instead of the average since the start
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// samples kept per span, together with [SAMPLE_INTERVAL] this is how far back the rate looks (~5s)
+pub const SAMPLES: usize = 20;
+/// updates coming quicker than this overwrite the latest sample instead of pushing out the older ones
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default)]
+pub struct Samples {
+    started: Option<Instant>,
+    recent: VecDeque<(Instant, i64)>,
+}
+
+impl Samples {
+    pub fn started_at(now: Instant, current: i64) -> Self {
+        Self {
+            started: Some(now),
+            recent: VecDeque::from([(now, current)]),
+        }
+    }
+
+    pub fn started(&self) -> Option<Instant> {
+        self.started
+    }
+
+    pub fn record(&mut self, now: Instant, current: i64) {
+        self.started.get_or_insert(now);
+        let recently_sampled = self
+            .recent
+            .len()
+            .checked_sub(2)
+            .and_then(|previous| self.recent.get(previous))
+            .is_some_and(|(at, _)| now.duration_since(*at) < SAMPLE_INTERVAL);
+        match recently_sampled {
+            true => {
+                if let Some(latest) = self.recent.back_mut() {
+                    *latest = (now, current);
+                }
+            }
+            false => {
+                if self.recent.len() == SAMPLES {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back((now, current));
+            }
+        }
+    }
+
+    /// units per second over the sampled window, [None] until there are two samples apart in time
+    pub fn rate(&self) -> Option<f64> {
+        self.recent
+            .front()
+            .zip(self.recent.back())
+            .map(|((since, from), (until, to))| (until.duration_since(*since).as_secs_f64(), (to - from) as f64))
+            .filter(|(elapsed, _)| *elapsed > 0.)
+            .map(|(elapsed, done)| done / elapsed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Throughput {
+    /// units per second
+    pub rate: f64,
+    pub remaining: i64,
+}
+
+impl Throughput {
+    pub fn eta(&self) -> Option<Duration> {
+        (self.rate > 0.)
+            .then(|| self.remaining.max(0) as f64 / self.rate)
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+    }
+}
+
+/// throughput of spans progressing together, the ETA is their remaining amount over their combined rate
+/// (so a slow source with a lot left to do weighs more than a fast one that's almost done)
+pub fn combined(spans: impl IntoIterator<Item = Throughput>) -> Option<Throughput> {
+    spans.into_iter().reduce(|acc, next| Throughput {
+        rate: acc.rate + next.rate,
+        remaining: acc.remaining + next.remaining,
+    })
+}
@@ -1,10 +1,21 @@
 use {
-    crate::{compression::ProcessArchive, path::PathBuf, utils::PathReadWrite},
+    crate::{
+        compression::{ArchiveHandle, ProcessArchive, SeekWithTempFileExt, sevenz},
+        install_modlist::download_cache::to_base_64_from_u64,
+        path::{CaseInsensitivePathBuf, ExistingPath, PathBuf, PathExistsUtf8Ext},
+        read_wrappers::ReadExt,
+        utils::PathReadWrite,
+    },
     anyhow::{Context, Result},
     itertools::Itertools,
+    std::str::FromStr,
+    tap::prelude::*,
     tracing::info,
 };
 
+/// separates the archives nested in one another within an inner path, the way wabbajack prints them
+const NESTED_SEPARATOR: char = '|';
+
 #[derive(clap::Args, Clone)]
 pub struct ArchiveCliCommand {
     #[command(subcommand)]
@@ -42,3 +53,231 @@ impl ArchiveCliCommand {
         }
     }
 }
+
+/// pulls single entries out of an archive of any kind hoolamike handles, e.g.
+/// `hoolamike extract Some-Mod.7z 'Data\Textures.bsa|textures\sky.dds' -o out`
+#[derive(clap::Args, Clone)]
+pub struct ExtractCli {
+    /// the archive, it's recognized by its first bytes when the extension doesn't tell
+    archive: std::path::PathBuf,
+    /// paths of the entries (case insensitive), entries of archives nested in the archive are separated with '|'
+    #[arg(required_unless_present = "list")]
+    inner_paths: Vec<String>,
+    /// the entries are written at their paths within the archive, under this directory
+    #[arg(short, long, default_value = ".")]
+    output: std::path::PathBuf,
+    /// prints the entries along with their sizes instead - of the nested archives, when inner paths are given
+    #[arg(long)]
+    list: bool,
+}
+
+fn parse_inner_path(inner_path: &str) -> Result<Vec<CaseInsensitivePathBuf>> {
+    inner_path
+        .split(NESTED_SEPARATOR)
+        .map(|segment| match segment.trim() {
+            "" => Err(anyhow::anyhow!("empty segment")),
+            segment => CaseInsensitivePathBuf::from_str(segment),
+        })
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("parsing inner path [{inner_path}]"))
+}
+
+/// `with` gets the innermost of the `nested` archives, the ones on the way are extracted into temp files
+fn with_nested_archive<T>(archive: &ExistingPath, nested: &[CaseInsensitivePathBuf], with: impl FnOnce(&ExistingPath) -> Result<T>) -> Result<T> {
+    match nested.split_first() {
+        None => with(archive),
+        Some((entry, nested)) => ArchiveHandle::with_guessed(archive, archive.as_path().extension(), |mut archive| {
+            archive.get_handle(entry).and_then(|mut handle| {
+                let size = handle.size()?;
+                match entry.extension() {
+                    Some(extension) => handle.seek_with_temp_file_blocking_raw_with_extension(extension, size),
+                    None => handle.seek_with_temp_file_blocking_raw(size),
+                }
+            })
+        })
+        .with_context(|| format!("extracting nested archive [{entry}]"))
+        .and_then(|(_, extracted)| {
+            extracted
+                .exists_utf8()
+                .and_then(|extracted| with_nested_archive(&extracted, nested, with))
+        }),
+    }
+}
+
+/// entries of the archive along with their sizes - 7z headers are read directly, other archives have every entry opened
+fn list_entries(archive: &ExistingPath) -> Result<Vec<(String, u64)>> {
+    match archive
+        .as_path()
+        .extension()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("7z") => sevenz::list_entries(archive.as_ref()).and_then(|entries| {
+            entries
+                .into_iter()
+                .map(|entry| {
+                    entry
+                        .size
+                        .context("no size in the headers")
+                        .map(|size| (entry.path, size))
+                })
+                .collect()
+        }),
+        _ => Err(anyhow::anyhow!("not a 7z archive")),
+    }
+    .or_else(|reason| {
+        ArchiveHandle::with_guessed(archive, archive.as_path().extension(), |mut archive| {
+            archive
+                .list_paths()
+                .and_then(|paths| archive.get_many_handles(paths.iter().collect_vec().as_slice()))
+                .and_then(|handles| {
+                    handles
+                        .into_iter()
+                        .map(|(path, mut handle)| {
+                            handle
+                                .size()
+                                .map(|size| (path.as_original_path().to_string(), size))
+                        })
+                        .collect()
+                })
+        })
+        .with_context(|| format!("trying because: {reason:?}"))
+    })
+    .with_context(|| format!("listing [{archive}]"))
+}
+
+/// writes the last entry of `inner_path` under `output`, returns where it went along with the size and hash of what got written
+fn extract(archive: &ExistingPath, inner_path: &[CaseInsensitivePathBuf], output: &std::path::Path) -> Result<(std::path::PathBuf, u64, u64)> {
+    let (entry, nested) = inner_path.split_last().context("empty inner path")?;
+    let destination = std::path::Path::new(entry.as_path().as_str()).pipe(|relative| {
+        match relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            true => Ok(output.join(relative)),
+            false => Err(anyhow::anyhow!("[{entry}] would be written outside of [{}]", output.display())),
+        }
+    })?;
+    with_nested_archive(archive, nested, |archive| {
+        ArchiveHandle::with_guessed(archive, archive.as_path().extension(), |mut archive| {
+            archive.get_handle(entry).and_then(|handle| {
+                destination
+                    .parent()
+                    .map(std::fs::create_dir_all)
+                    .transpose()
+                    .context("creating parent directory")
+                    .and_then(|_| std::fs::File::create(&destination).context("creating file"))
+                    .and_then(|mut file| {
+                        let mut reader = handle.and_hash();
+                        std::io::copy(&mut reader, &mut file)
+                            .context("writing extracted file")
+                            .map(|size| (size, reader.hash()))
+                    })
+                    .with_context(|| format!("writing [{}]", destination.display()))
+            })
+        })
+    })
+    .map(|(size, hash)| (destination, size, hash))
+    .with_context(|| format!("extracting [{entry}]"))
+}
+
+impl ExtractCli {
+    pub fn run(self) -> Result<()> {
+        let Self {
+            archive,
+            inner_paths,
+            output,
+            list,
+        } = self;
+        let archive = archive.exists_utf8()?;
+        let inner_paths = inner_paths
+            .iter()
+            .map(|inner_path| parse_inner_path(inner_path))
+            .collect::<Result<Vec<_>>>()?;
+        match list {
+            true => match inner_paths.is_empty() {
+                true => vec![vec![]],
+                false => inner_paths,
+            }
+            .into_iter()
+            .try_for_each(|nested| {
+                with_nested_archive(&archive, &nested, list_entries).map(|entries| {
+                    entries
+                        .into_iter()
+                        .for_each(|(path, size)| println!("{size:>12}  {path}"))
+                })
+            }),
+            false => inner_paths.iter().try_for_each(|inner_path| {
+                extract(&archive, inner_path, &output).map(|(destination, size, hash)| {
+                    println!("{size:>12}  {}  {}", to_base_64_from_u64(hash), destination.display());
+                })
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    fn zip(entries: &[(&str, &[u8])]) -> Result<Vec<u8>> {
+        let options = ::zip::write::SimpleFileOptions::default().compression_method(::zip::CompressionMethod::Deflated);
+        std::io::Cursor::new(vec![])
+            .pipe(::zip::ZipWriter::new)
+            .pipe(|mut writer| {
+                entries
+                    .iter()
+                    .try_for_each(|(name, contents)| {
+                        writer
+                            .start_file(*name, options)
+                            .context("starting entry")
+                            .and_then(|_| writer.write_all(contents).context("writing entry"))
+                    })
+                    .and_then(|_| writer.finish().context("finishing archive"))
+            })
+            .map(|cursor| cursor.into_inner())
+    }
+
+    #[test_log::test]
+    fn test_parse_inner_path() -> Result<()> {
+        assert_eq!(
+            parse_inner_path("Data\\Textures.bsa|textures\\sky.dds")?,
+            vec![
+                CaseInsensitivePathBuf::from_str("data\\textures.bsa")?,
+                CaseInsensitivePathBuf::from_str("Textures\\Sky.DDS")?,
+            ]
+        );
+        assert!(parse_inner_path("").is_err());
+        assert!(parse_inner_path("Data\\Textures.bsa|").is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_extracts_nested_entries() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        // no extension, it's recognized by its contents
+        let nested = zip(&[("Readme.txt", b"nested readme".as_slice())])?;
+        let archive = directory.path().join("Some-Mod.zip");
+        std::fs::write(
+            &archive,
+            zip(&[("Data/Inner.bin", nested.as_slice()), ("Textures/Sky.DDS", b"sky".as_slice())])?,
+        )?;
+        let archive = archive.exists_utf8()?;
+        let output = directory.path().join("out");
+
+        let (destination, size, hash) = extract(&archive, &parse_inner_path("textures\\sky.dds")?, &output)?;
+        assert_eq!(std::fs::read(&destination)?, b"sky");
+        assert_eq!((size, hash), (3, xxhash_rust::xxh64::xxh64(b"sky", 0)));
+
+        let (destination, ..) = extract(&archive, &parse_inner_path("DATA\\inner.bin|readme.TXT")?, &output)?;
+        assert_eq!(std::fs::read(&destination)?, b"nested readme");
+        assert!(destination.starts_with(&output));
+
+        assert_eq!(
+            with_nested_archive(&archive, &parse_inner_path("data\\inner.bin")?, list_entries)?,
+            vec![("Readme.txt".to_string(), 13)]
+        );
+        assert!(extract(&archive, &parse_inner_path("..\\..\\escape.txt")?, &output).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! destinations are never written in place: the bytes go to `<destination>.hoolamike-partial` next to it, which is synced and
+//! only then renamed over the destination. a crash leaves a partial file behind instead of a truncated destination passing for a
+//! finished one - downloads pick their partial files up where they stopped, directives throw theirs away
+
+use {
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    std::{
+        collections::BTreeSet,
+        io::SeekFrom,
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tokio::io::{AsyncSeekExt, AsyncWriteExt},
+    tracing::{info, warn},
+};
+
+pub const PARTIAL_SUFFIX: &str = ".hoolamike-partial";
+
+pub fn partial_path(destination: &Path) -> PathBuf {
+    destination
+        .as_os_str()
+        .to_owned()
+        .tap_mut(|path| path.push(PARTIAL_SUFFIX))
+        .pipe(PathBuf::from)
+}
+
+pub fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(PARTIAL_SUFFIX))
+}
+
+fn create_parent(destination: &Path) -> Result<()> {
+    destination.parent().map_or(Ok(()), |parent| {
+        std::fs::create_dir_all(parent).with_context(|| format!("creating [{}]", parent.display()))
+    })
+}
+
+/// runs `create` again with the parent of `destination` created, when the first attempt finds it missing. directives have
+/// their directories created up front ([crate::install_modlist::directives::output_directories]), this is for the rest
+fn with_parent<T>(destination: &Path, create: impl Fn() -> std::io::Result<T>) -> Result<T> {
+    create().or_else(|error| match error.kind() {
+        std::io::ErrorKind::NotFound => create_parent(destination).and_then(|_| create().map_err(Into::into)),
+        _ => Err(error.into()),
+    })
+}
+
+/// a temp file in the directory of `destination`, to be renamed over it once written
+pub fn temp_file_next_to(destination: &Path) -> Result<tempfile::NamedTempFile> {
+    destination
+        .parent()
+        .with_context(|| format!("[{}] has no parent", destination.display()))
+        .and_then(|directory| {
+            with_parent(destination, || {
+                tempfile::Builder::new()
+                    .prefix(".hoolamike-")
+                    .tempfile_in(directory)
+            })
+        })
+        .context("creating a temp file next to the destination")
+}
+
+fn rename_into_place(partial: &Path, destination: &Path) -> Result<()> {
+    std::fs::rename(partial, destination).with_context(|| format!("renaming [{}] to [{}]", partial.display(), destination.display()))
+}
+
+/// hands `write` an empty partial file, which replaces `destination` once it's written and synced. failed writes remove it
+pub fn write_atomically<T>(destination: &Path, write: impl FnOnce(&mut std::fs::File) -> Result<T>) -> Result<T> {
+    let partial = partial_path(destination);
+    with_parent(destination, || std::fs::File::create(&partial))
+        .with_context(|| format!("opening [{}] for writing", partial.display()))
+        .and_then(|mut file| {
+            write(&mut file).and_then(|written| {
+                file.sync_all()
+                    .context("syncing written file")
+                    .and_then(|_| rename_into_place(&partial, destination))
+                    .map(|_| written)
+            })
+        })
+        .tap_err(|_| {
+            std::fs::remove_file(&partial).ok();
+        })
+        .with_context(|| format!("writing [{}]", destination.display()))
+}
+
+/// bytes already in the partial file of `destination`
+pub async fn partial_len_async(destination: &Path) -> u64 {
+    tokio::fs::metadata(partial_path(destination))
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+/// opens the partial file of `destination` for writing, keeping its first `keep` bytes - the file is positioned right after them
+pub async fn open_partial_async(destination: &Path, keep: u64) -> Result<tokio::fs::File> {
+    let partial = partial_path(destination);
+    create_parent(destination)?;
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&partial)
+        .await
+        .with_context(|| format!("opening [{}] for writing", partial.display()))?;
+    file.set_len(keep)
+        .await
+        .context("dropping the unusable tail of the partial file")?;
+    file.seek(SeekFrom::Start(keep))
+        .await
+        .context("seeking past the kept part")?;
+    Ok(file)
+}
+
+/// syncs a partial file opened with [open_partial_async] without finishing it, so that an interrupted download picks up
+/// exactly the bytes written so far
+pub async fn keep_partial_async(mut file: tokio::fs::File) -> Result<()> {
+    file.flush().await.context("flushing partial file")?;
+    file.sync_all().await.context("syncing partial file")
+}
+
+/// syncs a partial file opened with [open_partial_async] and renames it over `destination`
+pub async fn commit_async(mut file: tokio::fs::File, destination: &Path) -> Result<()> {
+    file.flush().await.context("flushing written file")?;
+    file.sync_all().await.context("syncing written file")?;
+    drop(file);
+    rename_into_place(&partial_path(destination), destination)
+}
+
+/// removes partial files (of interrupted directives) out of `root` and `directories` (relative to it) - partial files sit right
+/// next to their destinations, so nothing below is looked at. `keep` (a downloads directory, where they're resumed) is skipped
+pub fn discard_partials(root: &Path, directories: &BTreeSet<PathBuf>, keep: &Path) -> Vec<PathBuf> {
+    // either of them might be relative
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let (root, keep) = (canonical(root), canonical(keep));
+    std::iter::once(root.clone())
+        .chain(directories.iter().map(|directory| root.join(directory)))
+        .filter(|directory| !directory.starts_with(&keep))
+        .flat_map(|directory| std::fs::read_dir(directory).into_iter().flatten())
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| entry.path())
+        .filter(|path| is_partial(path))
+        .filter(|partial| {
+            std::fs::remove_file(partial)
+                .map_err(|reason| warn!("could not remove [{}] left behind by an interrupted install: {reason}", partial.display()))
+                .is_ok()
+        })
+        .collect_vec()
+        .tap(|removed| {
+            if !removed.is_empty() {
+                info!("removed [{}] partially written files left behind by an interrupted install", removed.len())
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    #[test_log::test]
+    fn test_destination_is_replaced_only_after_a_successful_write() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let destination = directory.path().join("mods/foo/foo.esp");
+
+        write_atomically(&destination, |file| file.write_all(b"finished").context("writing"))?;
+        assert_eq!(std::fs::read_to_string(&destination)?, "finished");
+        assert!(!partial_path(&destination).exists());
+
+        let failed = write_atomically(&destination, |file| {
+            file.write_all(b"trunc").context("writing")?;
+            anyhow::bail!("crashed halfway")
+        });
+        assert!(failed.is_err());
+        assert_eq!(
+            std::fs::read_to_string(&destination)?,
+            "finished",
+            "a failed write leaves the destination alone"
+        );
+        assert!(!partial_path(&destination).exists(), "and cleans up after itself");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_partial_downloads_are_resumed() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let destination = directory.path().join("archive.7z");
+        assert_eq!(partial_len_async(&destination).await, 0);
+
+        let mut file = open_partial_async(&destination, 0).await?;
+        file.write_all(b"first half, garbage").await?;
+        drop(file);
+        assert!(!destination.exists());
+        assert_eq!(partial_len_async(&destination).await, 19);
+
+        let mut file = open_partial_async(&destination, 11).await?;
+        file.write_all(b" second half").await?;
+        commit_async(file, &destination).await?;
+        assert_eq!(std::fs::read_to_string(&destination)?, "first half, second half");
+        assert!(!partial_path(&destination).exists());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_leftover_partials_are_discarded_outside_of_downloads() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let installation = directory.path().join("installation");
+        let downloads = installation.join("downloads");
+        let leftover = partial_path(&installation.join("mods/foo/foo.esp"));
+        let download = partial_path(&downloads.join("archive.7z"));
+        let finished = installation.join("mods/foo/bar.esp");
+        let at_root = partial_path(&installation.join("ModOrganizer.ini"));
+        // no directive writes there, so it's not looked at
+        let elsewhere = partial_path(&installation.join("mods/foo/backup/foo.esp"));
+        [&leftover, &download, &finished, &at_root, &elsewhere]
+            .into_iter()
+            .try_for_each(|path| create_parent(path).and_then(|_| std::fs::write(path, "contents").context("writing")))?;
+
+        let directories = ["mods", "mods/foo", "downloads", "not/created"]
+            .map(PathBuf::from)
+            .into();
+        assert_eq!(discard_partials(&installation, &directories, &downloads).len(), 2);
+        assert!(!leftover.exists() && !at_root.exists());
+        assert!(download.exists() && finished.exists() && elsewhere.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,456 @@
+//! short synthetic workloads going through the same code paths the installation uses, measured at a few worker counts.
+//! it's only a sanity check - numbers are meant to be compared with each other, not with other machines
+
+use {
+    crate::{
+        compression::{ArchiveHandleKind, self_test::extract_fixture},
+        config_file::{HoolamikeConfig, edit_config, edit_config_file, yaml_section},
+        helpers::human_readable_size,
+        install_modlist::{directives::concurrency::ConcurrencyConfig, download_cache::hash_file_wabbajack},
+        utils::write_atomically,
+    },
+    anyhow::{Context, Result},
+    case_insensitive_path::PathExistsUtf8Ext,
+    rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    std::{
+        iter::successors,
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+    tap::prelude::*,
+    tracing::{info, info_span, warn},
+    wrapped_7zip::fixtures::{FIXTURES, Fixture},
+};
+
+const MEBIBYTE: u64 = 1024 * 1024;
+const HASHED_FILES: usize = 16;
+const HASHED_FILE_SIZE: u64 = 8 * MEBIBYTE;
+const WRITTEN_FILES: usize = 256;
+const WRITTEN_FILE_SIZE: u64 = 16 * 1024;
+const FIXTURE_EXTRACTIONS: usize = 32;
+/// more workers than that only adds contention, the extra throughput has to be worth it
+const GOOD_ENOUGH_FRACTION: f64 = 0.9;
+
+#[derive(clap::Args, Clone)]
+pub struct BenchCli {
+    /// directory the generated files are created in, defaults to the installation path from the config (it's the disk directives write to)
+    #[arg(long)]
+    scratch_directory: Option<PathBuf>,
+    /// writes the recommended values into the 'concurrency' section of the config
+    #[arg(long)]
+    apply: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub workers: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl Measurement {
+    /// bytes per second
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadReport {
+    pub name: &'static str,
+    pub measurements: Vec<Measurement>,
+    pub recommended: usize,
+}
+
+impl std::fmt::Display for WorkloadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} (recommended workers: {})", self.name, self.recommended)?;
+        self.measurements.iter().try_for_each(|measurement| {
+            writeln!(
+                f,
+                "  {:>3} workers: {}/s ({:.2?}){}",
+                measurement.workers,
+                human_readable_size(measurement.throughput() as u64),
+                measurement.elapsed,
+                if measurement.workers == self.recommended { " <-" } else { "" }
+            )
+        })
+    }
+}
+
+/// measures a single run of a workload, abstracted away so that tests don't depend on the speed of the machine
+pub trait Stopwatch {
+    /// runs the workload and returns the number of bytes it processed along with the time it took
+    fn time(&mut self, workers: usize, run: &mut dyn FnMut() -> Result<u64>) -> Result<(u64, Duration)>;
+}
+
+pub struct WallClock;
+
+impl Stopwatch for WallClock {
+    fn time(&mut self, _workers: usize, run: &mut dyn FnMut() -> Result<u64>) -> Result<(u64, Duration)> {
+        let started = Instant::now();
+        run().map(|bytes| (bytes, started.elapsed()))
+    }
+}
+
+/// powers of two up to the number of cpus
+pub fn levels(cpus: usize) -> Vec<usize> {
+    successors(Some(1usize), |level| level.checked_mul(2))
+        .take_while(|level| *level <= cpus.clamp(2, 32))
+        .collect()
+}
+
+/// the fewest workers reaching [GOOD_ENOUGH_FRACTION] of the best measured throughput
+pub fn recommend(measurements: &[Measurement]) -> usize {
+    let best = measurements
+        .iter()
+        .map(Measurement::throughput)
+        .fold(0f64, f64::max);
+    measurements
+        .iter()
+        .filter(|measurement| measurement.throughput() >= best * GOOD_ENOUGH_FRACTION)
+        .map(|measurement| measurement.workers)
+        .min()
+        .unwrap_or(1)
+}
+
+pub fn measure(name: &'static str, levels: &[usize], stopwatch: &mut impl Stopwatch, mut workload: impl FnMut(usize) -> Result<u64>) -> Result<WorkloadReport> {
+    let _span = info_span!("bench", %name).entered();
+    levels
+        .iter()
+        .map(|&workers| {
+            stopwatch
+                .time(workers, &mut || workload(workers))
+                .map(|(bytes, elapsed)| Measurement { workers, bytes, elapsed })
+                .tap_ok(|measurement| info!(?measurement, "measured"))
+                .with_context(|| format!("running [{name}] with [{workers}] workers"))
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|measurements| WorkloadReport {
+            name,
+            recommended: recommend(&measurements),
+            measurements,
+        })
+}
+
+fn pool(workers: usize) -> Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(workers)
+        .build()
+        .context("building thread pool")
+}
+
+/// not compressible, so that filesystems compressing on the fly don't skew the results
+fn generated_contents(size: u64) -> Vec<u8> {
+    (0..size)
+        .map(|idx| (idx.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 56) as u8)
+        .collect()
+}
+
+/// reading: archives are hashed the same way they are verified before installation
+fn hashing(scratch: &Path) -> Result<impl FnMut(usize) -> Result<u64>> {
+    let contents = generated_contents(HASHED_FILE_SIZE);
+    (0..HASHED_FILES)
+        .map(|idx| {
+            scratch.join(format!("hashed-{idx}.bin")).pipe(|path| {
+                std::fs::write(&path, &contents)
+                    .with_context(|| format!("writing [{}]", path.display()))
+                    .and_then(|_| path.exists_utf8())
+            })
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|files| {
+            // the hashing pool is sized once per process, so the workload gets a pool of its own
+            move |workers: usize| {
+                pool(workers)
+                    .and_then(|pool| {
+                        pool.install(|| {
+                            files
+                                .par_iter()
+                                .map(|file| hash_file_wabbajack(file))
+                                .collect::<Result<Vec<_>>>()
+                        })
+                    })
+                    .map(|hashes| hashes.len() as u64 * HASHED_FILE_SIZE)
+            }
+        })
+}
+
+/// writing: lots of small files, like inline files and loose mod files
+fn small_writes(scratch: &Path) -> impl FnMut(usize) -> Result<u64> {
+    let contents = generated_contents(WRITTEN_FILE_SIZE);
+    move |workers: usize| {
+        tempfile::Builder::new()
+            .prefix("small-writes-")
+            .tempdir_in(scratch)
+            .context("creating directory for small files")
+            .and_then(|directory| {
+                pool(workers).and_then(|pool| {
+                    pool.install(|| {
+                        (0..WRITTEN_FILES)
+                            .into_par_iter()
+                            .try_for_each(|idx| write_atomically(&directory.path().join(format!("{idx}.bin")), &contents))
+                    })
+                })
+            })
+            .map(|_| WRITTEN_FILES as u64 * WRITTEN_FILE_SIZE)
+    }
+}
+
+/// extraction: the embedded fixture is extracted a fixed number of times with the backend a real .7z would get
+fn extraction(fixture: &'static Fixture) -> Result<impl FnMut(usize) -> Result<u64>> {
+    ArchiveHandleKind::preferred_for_extension(Some(fixture.extension))
+        .with_context(|| format!("no preferred backend for [{}]", fixture.name))
+        .map(|backend| {
+            move |workers: usize| {
+                pool(workers)
+                    .and_then(|pool| {
+                        pool.install(|| {
+                            (0..FIXTURE_EXTRACTIONS)
+                                .into_par_iter()
+                                .try_for_each(|_| extract_fixture(backend, fixture))
+                        })
+                    })
+                    .map(|_| FIXTURE_EXTRACTIONS as u64 * fixture.bytes.len() as u64)
+            }
+        })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recommendation {
+    pub io_workers: usize,
+    pub extraction_workers: usize,
+}
+
+impl Recommendation {
+    /// copying a file reads it and writes it, so whichever of the two saturates first decides
+    pub fn new(reading: &WorkloadReport, writing: &WorkloadReport, extraction: &WorkloadReport) -> Self {
+        Self {
+            io_workers: reading.recommended.min(writing.recommended),
+            extraction_workers: extraction.recommended,
+        }
+    }
+}
+
+impl std::fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            io_workers,
+            extraction_workers,
+        } = self;
+        write!(f, "concurrency:\n  io_workers: {io_workers}\n  extraction_workers: {extraction_workers}")
+    }
+}
+
+/// sets the recommended values in the 'concurrency' section, leaving everything else (including cpu_workers) as it was
+pub fn apply_recommendation(
+    config: &str,
+    Recommendation {
+        io_workers,
+        extraction_workers,
+    }: Recommendation,
+) -> Result<String> {
+    edit_config(config, |config| {
+        yaml_section(config, "concurrency").map(|concurrency| {
+            concurrency.insert("io_workers".into(), serde_yaml::Value::Number(io_workers.into()));
+            concurrency.insert("extraction_workers".into(), serde_yaml::Value::Number(extraction_workers.into()));
+        })
+    })
+}
+
+fn apply_to_config_file(config_path: &Path, recommendation: Recommendation) -> Result<()> {
+    edit_config_file(config_path, |config| apply_recommendation(config, recommendation)).context("applying recommendation")
+}
+
+pub fn run_bench(BenchCli { scratch_directory, apply }: BenchCli, config_path: &Path) -> Result<()> {
+    let config = HoolamikeConfig::read(config_path)
+        .map(|(_, config)| config)
+        .tap_err(|_| warn!("no usable config, generated files go to the temp directory"))
+        .ok();
+    let scratch_directory = scratch_directory
+        .or_else(|| {
+            config
+                .as_ref()
+                .map(|config| config.installation.installation_path.clone())
+        })
+        .map_or_else(|| crate::temp_directory::run_directory().map(|directory| directory.to_path_buf()), Ok)?;
+    let levels = levels(num_cpus::get());
+    let fixture = FIXTURES
+        .iter()
+        .find(|fixture| fixture.extension == "7z")
+        .context("no 7z fixture is embedded")?;
+
+    std::fs::create_dir_all(&scratch_directory)
+        .and_then(|_| {
+            tempfile::Builder::new()
+                .prefix(".hoolamike-bench-")
+                .tempdir_in(&scratch_directory)
+        })
+        .with_context(|| format!("creating scratch directory in [{}]", scratch_directory.display()))
+        .tap_ok(|scratch| info!("benchmarking in [{}] with worker counts {levels:?}", scratch.path().display()))
+        .and_then(|scratch| {
+            let mut stopwatch = WallClock;
+            let reading = hashing(scratch.path()).and_then(|workload| measure("hashing (read)", &levels, &mut stopwatch, workload))?;
+            let writing = measure("small files (write)", &levels, &mut stopwatch, small_writes(scratch.path()))?;
+            let extracting = extraction(fixture).and_then(|workload| measure("7z extraction", &levels, &mut stopwatch, workload))?;
+            [&reading, &writing, &extracting]
+                .iter()
+                .for_each(|report| println!("{report}"));
+            Ok(Recommendation::new(&reading, &writing, &extracting))
+        })
+        .and_then(|recommendation| {
+            println!("recommended values:\n{recommendation}");
+            if let Some(ConcurrencyConfig {
+                io_workers,
+                cpu_workers: _,
+                extraction_workers,
+                ..
+            }) = config.as_ref().map(|config| &config.concurrency)
+            {
+                println!("currently configured: io_workers: {io_workers:?}, extraction_workers: {extraction_workers:?} (null means automatic)");
+            }
+            match apply {
+                true => apply_to_config_file(config_path, recommendation).tap_ok(|_| info!("recommendation written to [{}]", config_path.display())),
+                false => Ok(()),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, itertools::Itertools, std::collections::BTreeMap};
+
+    /// pretends every run of given worker count took the configured time, but still runs the workload
+    struct Mocked(BTreeMap<usize, u64>);
+
+    impl Stopwatch for Mocked {
+        fn time(&mut self, workers: usize, run: &mut dyn FnMut() -> Result<u64>) -> Result<(u64, Duration)> {
+            run().and_then(|bytes| {
+                self.0
+                    .get(&workers)
+                    .with_context(|| format!("no timing for [{workers}]"))
+                    .map(|millis| (bytes, Duration::from_millis(*millis)))
+            })
+        }
+    }
+
+    fn measurement(workers: usize, millis: u64) -> Measurement {
+        Measurement {
+            workers,
+            bytes: 1000,
+            elapsed: Duration::from_millis(millis),
+        }
+    }
+
+    #[test_log::test]
+    fn test_levels() {
+        assert_eq!(levels(1), [1, 2]);
+        assert_eq!(levels(6), [1, 2, 4]);
+        assert_eq!(levels(16), [1, 2, 4, 8, 16]);
+        assert_eq!(levels(128), [1, 2, 4, 8, 16, 32]);
+    }
+
+    #[test_log::test]
+    fn test_recommendation_picks_fewest_workers_close_to_the_best() {
+        // plateau after 4 workers, 8 is only marginally faster
+        assert_eq!(
+            recommend(&[measurement(1, 1000), measurement(2, 520), measurement(4, 300), measurement(8, 290)]),
+            4
+        );
+        // spinning disk, more workers only make it worse
+        assert_eq!(recommend(&[measurement(1, 100), measurement(2, 180), measurement(4, 400)]), 1);
+        // keeps scaling
+        assert_eq!(recommend(&[measurement(1, 800), measurement(2, 400), measurement(4, 200)]), 4);
+        assert_eq!(recommend(&[]), 1);
+    }
+
+    #[test_log::test]
+    fn test_harness_runs_every_level_with_mocked_timings() -> Result<()> {
+        let mut stopwatch = Mocked([(1, 500), (2, 250), (4, 240)].into_iter().collect());
+        let mut ran = vec![];
+        let report = measure("test", &[1, 2, 4], &mut stopwatch, |workers| {
+            ran.push(workers);
+            Ok(4000)
+        })?;
+        assert_eq!(ran, [1, 2, 4]);
+        assert_eq!(
+            report
+                .measurements
+                .iter()
+                .map(|m| m.elapsed.as_millis())
+                .collect_vec(),
+            [500, 250, 240]
+        );
+        assert_eq!(report.measurements[0].throughput(), 8_000.0);
+        assert_eq!(report.recommended, 2);
+        assert!(report.to_string().contains("2 workers: "));
+
+        assert!(measure("failing", &[1], &mut stopwatch, |_| -> Result<u64> { anyhow::bail!("disk full") }).is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_recommendation_takes_the_slower_of_reading_and_writing() {
+        let report = |recommended| WorkloadReport {
+            name: "test",
+            measurements: vec![],
+            recommended,
+        };
+        assert_eq!(
+            Recommendation::new(&report(8), &report(2), &report(4)),
+            Recommendation {
+                io_workers: 2,
+                extraction_workers: 4,
+            }
+        );
+    }
+
+    #[test_log::test]
+    fn test_apply_keeps_the_rest_of_the_config() -> Result<()> {
+        let recommendation = Recommendation {
+            io_workers: 3,
+            extraction_workers: 2,
+        };
+        let existing = HoolamikeConfig::write_default()?.replace("cpu_workers: null", "cpu_workers: 5");
+        let applied = apply_recommendation(&existing, recommendation)?.pipe_deref(serde_yaml::from_str::<HoolamikeConfig>)?;
+        assert_eq!(applied.concurrency.io_workers, Some(3));
+        assert_eq!(applied.concurrency.extraction_workers, Some(2));
+        assert_eq!(applied.concurrency.cpu_workers, Some(5));
+        assert_eq!(
+            applied.installation.installation_path,
+            HoolamikeConfig::default().installation.installation_path
+        );
+
+        let without_section = serde_yaml::to_string(&HoolamikeConfig::default())?
+            .pipe_deref(serde_yaml::from_str::<serde_yaml::Value>)?
+            .tap_mut(|config| {
+                config.as_mapping_mut().unwrap().remove("concurrency");
+            })
+            .pipe_ref(serde_yaml::to_string)?;
+        let applied = apply_recommendation(&without_section, recommendation)?.pipe_deref(serde_yaml::from_str::<HoolamikeConfig>)?;
+        assert_eq!(applied.concurrency.io_workers, Some(3));
+        assert_eq!(applied.concurrency.cpu_workers, None);
+
+        assert!(apply_recommendation("- not\n- a\n- mapping\n", recommendation).is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_apply_to_config_file_backs_up_the_original() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let config_path = directory.path().join("hoolamike.yaml");
+        let original = HoolamikeConfig::write_default()?;
+        std::fs::write(&config_path, &original)?;
+        apply_to_config_file(
+            &config_path,
+            Recommendation {
+                io_workers: 1,
+                extraction_workers: 1,
+            },
+        )?;
+        assert_eq!(std::fs::read_to_string(directory.path().join("hoolamike.yaml.bak"))?, original);
+        let (_, applied) = HoolamikeConfig::read(&config_path)?;
+        assert_eq!(applied.concurrency.io_workers, Some(1));
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+//! blocking work (hashing, reading archive entries, parsing downloaded pages) used to go through `spawn_blocking` from
+//! wherever it was needed. tokio happily grows its blocking pool up to 512 threads for that, which on a 4 core handheld
+//! means memory pressure and constant context switching. instead every kind of blocking work gets a fixed size pool,
+//! tasks above its size wait in the queue.
+
+use {
+    crate::install_modlist::directives::concurrency::ConcurrencyConfig,
+    anyhow::{Context, Result, anyhow},
+    futures::FutureExt,
+    once_cell::sync::OnceCell,
+    rayon::{ThreadPool, ThreadPoolBuilder},
+    std::{
+        convert::identity,
+        future::Future,
+        panic::AssertUnwindSafe,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    },
+    tap::prelude::*,
+    tracing::info,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSizes {
+    pub hashing: usize,
+    pub extraction: usize,
+    pub fs: usize,
+    /// cap for tokio's own blocking threads (tokio::fs and friends)
+    pub max_blocking_threads: usize,
+}
+
+impl ConcurrencyConfig {
+    pub fn blocking_pools(&self, cpus: usize) -> PoolSizes {
+        let cpus = cpus.max(1);
+        PoolSizes {
+            hashing: self.hashing_workers.unwrap_or(cpus).max(1),
+            extraction: self.extraction_workers.unwrap_or((cpus / 2).max(1)).max(1),
+            fs: self.fs_workers.unwrap_or(2).max(1),
+            max_blocking_threads: self
+                .max_blocking_threads
+                .unwrap_or((cpus * 4).clamp(16, 64))
+                .max(1),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    peak_queued: AtomicUsize,
+    peak_running: AtomicUsize,
+    completed: AtomicUsize,
+}
+
+impl Counters {
+    fn enter(counter: &AtomicUsize, peak: &AtomicUsize) {
+        let current = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        peak.fetch_max(current, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub threads: usize,
+    /// tasks waiting for a free thread
+    pub queued: usize,
+    pub running: usize,
+    pub peak_queued: usize,
+    pub peak_running: usize,
+    pub completed: usize,
+}
+
+#[derive(Debug)]
+pub struct BlockingPool {
+    name: &'static str,
+    threads: usize,
+    pool: ThreadPool,
+    counters: Arc<Counters>,
+}
+
+impl BlockingPool {
+    pub fn new(name: &'static str, threads: usize) -> Result<Self> {
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(move |idx| format!("{name}-{idx}"))
+            .build()
+            .with_context(|| format!("building [{name}] blocking pool with [{threads}] threads"))
+            .map(|pool| Self {
+                name,
+                threads,
+                pool,
+                counters: Default::default(),
+            })
+    }
+
+    /// runs the task on one of the pool's threads, inside the span it was called from
+    pub fn run<T, F>(&self, task: F) -> impl Future<Output = Result<T>> + Send + 'static
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (tx, rx) = futures::channel::oneshot::channel();
+        let span = tracing::Span::current();
+        let counters = self.counters.clone();
+        let name = self.name;
+        Counters::enter(&counters.queued, &counters.peak_queued);
+        self.pool.spawn(move || {
+            counters.queued.fetch_sub(1, Ordering::SeqCst);
+            Counters::enter(&counters.running, &counters.peak_running);
+            // rayon aborts the whole process when a spawned job panics
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| span.in_scope(task)))
+                .unwrap_or_else(|_| Err(anyhow!("task on the [{name}] blocking pool panicked")));
+            counters.running.fetch_sub(1, Ordering::SeqCst);
+            counters.completed.fetch_add(1, Ordering::SeqCst);
+            tx.send(result).ok();
+        });
+        rx.map(move |result| {
+            result
+                .with_context(|| format!("task on the [{name}] blocking pool was dropped"))
+                .and_then(identity)
+        })
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let Counters {
+            queued,
+            running,
+            peak_queued,
+            peak_running,
+            completed,
+        } = &*self.counters;
+        PoolStats {
+            threads: self.threads,
+            queued: queued.load(Ordering::SeqCst),
+            running: running.load(Ordering::SeqCst),
+            peak_queued: peak_queued.load(Ordering::SeqCst),
+            peak_running: peak_running.load(Ordering::SeqCst),
+            completed: completed.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockingPools {
+    pub sizes: PoolSizes,
+    /// archives are hashed before they are used and after they are downloaded
+    pub hashing: BlockingPool,
+    /// reading entries out of (nested) archives
+    pub extraction: BlockingPool,
+    /// small one-off jobs - decompressing and parsing downloaded metadata
+    pub fs: BlockingPool,
+}
+
+impl BlockingPools {
+    pub fn new(sizes: PoolSizes) -> Result<Self> {
+        Ok(Self {
+            sizes,
+            hashing: BlockingPool::new("hashing", sizes.hashing)?,
+            extraction: BlockingPool::new("extraction", sizes.extraction)?,
+            fs: BlockingPool::new("fs", sizes.fs)?,
+        })
+    }
+
+    /// logs how deep the queues got, a pool which always had tasks waiting is a candidate for more workers
+    pub fn report(&self) {
+        [&self.hashing, &self.extraction, &self.fs]
+            .into_iter()
+            .for_each(|pool| {
+                let PoolStats {
+                    threads,
+                    peak_queued,
+                    peak_running,
+                    completed,
+                    ..
+                } = pool.stats();
+                info!(threads, peak_queued, peak_running, completed, "[{}] blocking pool", pool.name);
+            })
+    }
+}
+
+static POOLS: OnceCell<BlockingPools> = OnceCell::new();
+/// used by whatever runs before [configure] (the gui fetching the gallery, etc.), so that it doesn't freeze the sizes
+static DEFAULT_POOLS: OnceCell<BlockingPools> = OnceCell::new();
+
+fn default_sizes() -> PoolSizes {
+    ConcurrencyConfig::default().blocking_pools(num_cpus::get())
+}
+
+fn configure_in(pools: &OnceCell<BlockingPools>, sizes: PoolSizes) -> Result<&BlockingPools> {
+    pools
+        .get_or_try_init(|| BlockingPools::new(sizes).tap_ok(|_| info!(?sizes, "blocking pools")))
+        .and_then(|pools| match pools.sizes == sizes {
+            true => Ok(pools),
+            false => Err(anyhow!("blocking pools are already running with {:?}, restart to use {sizes:?}", pools.sizes)),
+        })
+}
+
+/// sizes the pools from the config - they live for the whole process, so configuring them again with different sizes fails
+pub fn configure(config: &ConcurrencyConfig) -> Result<&'static BlockingPools> {
+    configure_in(&POOLS, config.blocking_pools(num_cpus::get()))
+}
+
+/// pools configured by [configure], or ones sized from the default config when nothing configured them yet
+pub fn pools() -> &'static BlockingPools {
+    POOLS.get().unwrap_or_else(|| {
+        DEFAULT_POOLS.get_or_init(|| {
+            default_sizes()
+                .pipe(BlockingPools::new)
+                .expect("building default blocking pools")
+        })
+    })
+}
+
+/// doesn't build the pools, runtimes created before [configure] must not decide their sizes
+pub fn max_blocking_threads() -> usize {
+    POOLS
+        .get()
+        .map(|pools| pools.sizes)
+        .unwrap_or_else(default_sizes)
+        .max_blocking_threads
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        futures::{StreamExt, TryStreamExt},
+        std::time::Duration,
+    };
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_pool_never_exceeds_its_size() -> Result<()> {
+        const THREADS: usize = 3;
+        const TASKS: usize = 200;
+        let pool = BlockingPool::new("flood", THREADS)?;
+        let observed = Arc::new(Counters::default());
+        let results = (0..TASKS)
+            .map(|idx| {
+                pool.run({
+                    let observed = observed.clone();
+                    move || {
+                        Counters::enter(&observed.running, &observed.peak_running);
+                        std::thread::sleep(Duration::from_millis(1));
+                        observed.running.fetch_sub(1, Ordering::SeqCst);
+                        Ok(idx)
+                    }
+                })
+            })
+            .pipe(futures::stream::iter)
+            // way more tasks in flight than the pool has threads
+            .buffer_unordered(TASKS)
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(results.len(), TASKS);
+        let stats = pool.stats();
+        assert!(observed.peak_running.load(Ordering::SeqCst) <= THREADS, "{observed:?}");
+        assert!(stats.peak_running <= THREADS, "{stats:?}");
+        assert!(stats.peak_queued > THREADS, "the flood should have queued up: {stats:?}");
+        assert_eq!(
+            stats,
+            PoolStats {
+                threads: THREADS,
+                queued: 0,
+                running: 0,
+                completed: TASKS,
+                ..stats
+            }
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_panicking_task_is_reported() -> Result<()> {
+        let pool = BlockingPool::new("panics", 1)?;
+        let crashed = pool.run(|| -> Result<()> { panic!("on purpose") }).await;
+        assert!(crashed.is_err());
+        assert_eq!(pool.run(|| Ok(1)).await?, 1, "the pool survives a panicking task");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_sizes_default_to_the_cpu_count() {
+        let sizes = ConcurrencyConfig::default().blocking_pools(4);
+        assert_eq!(
+            sizes,
+            PoolSizes {
+                hashing: 4,
+                extraction: 2,
+                fs: 2,
+                max_blocking_threads: 16,
+            }
+        );
+        let explicit = ConcurrencyConfig {
+            hashing_workers: Some(0),
+            max_blocking_threads: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(explicit.blocking_pools(4).hashing, 1);
+        assert_eq!(explicit.blocking_pools(4).max_blocking_threads, 8);
+    }
+
+    #[test_log::test]
+    fn test_reconfiguring_with_other_sizes_fails() -> Result<()> {
+        let pools = OnceCell::new();
+        let configured = ConcurrencyConfig {
+            hashing_workers: Some(3),
+            ..Default::default()
+        }
+        .blocking_pools(4);
+        assert_eq!(configure_in(&pools, configured)?.hashing.threads, 3);
+        assert_eq!(configure_in(&pools, configured)?.sizes, configured, "the same sizes again are fine");
+        let error = configure_in(&pools, ConcurrencyConfig::default().blocking_pools(4)).expect_err("the pools are already running");
+        assert!(format!("{error:?}").contains("restart"), "{error:?}");
+        Ok(())
+    }
+}
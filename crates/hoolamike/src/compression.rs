@@ -1,3 +1,8 @@
+//! archive backends, picked by extension (or by the first bytes when the extension doesn't tell).
+//! only bethesda archives (bsa, ba2, mpi) have their entries streamed straight into the destinations - zip and rar
+//! entries are extracted into temp files which are then moved into place, 7z entries are preheated into temp files (or
+//! extracted by the 7z binary straight into the destinations, when their blocks are too big to decode in process)
+
 use {
     crate::{
         install_modlist::directives::nested_archive_manager::{OPEN_FILE_PERMITS, WithPermit, max_open_files},
@@ -28,6 +33,8 @@ pub mod zip;
 pub mod detect_lzma_method_14;
 
 pub mod forward_only_seek;
+pub mod listing_cache;
+pub mod self_test;
 
 pub trait ProcessArchive: Sized {
     fn list_paths(&mut self) -> Result<Vec<PathBuf>>;
@@ -44,6 +51,7 @@ pub mod case_insensitive_lookup;
 impl ProcessArchive for ArchiveHandle<'_> {
     #[instrument(skip(self), fields(kind=?ArchiveHandleKind::from(&*self)))]
     fn list_paths(&mut self) -> Result<Vec<PathBuf>> {
+        let kind = ArchiveHandleKind::from(&*self);
         match self {
             ArchiveHandle::Wrapped7Zip(i) => i.list_paths(),
             ArchiveHandle::Bethesda(i) => i.list_paths(),
@@ -52,11 +60,13 @@ impl ProcessArchive for ArchiveHandle<'_> {
             ArchiveHandle::Zip(i) => i.list_paths(),
             ArchiveHandle::SevenzRust2(seven_zreader) => seven_zreader.list_paths(),
         }
-        .with_context(|| format!("when listing paths of an archive of kind [{kind:?}]", kind = ArchiveHandleKind::from(&*self)))
+        .tap_err(|e| self_test::record_failure(kind, e))
+        .with_context(|| format!("when listing paths of an archive of kind [{kind:?}]"))
     }
 
     #[instrument(skip(self), fields(kind=?ArchiveHandleKind::from(&*self)))]
     fn get_handle(&mut self, path: &Path) -> Result<self::ArchiveFileHandle> {
+        let kind = ArchiveHandleKind::from(&*self);
         match self {
             ArchiveHandle::Wrapped7Zip(i) => i.get_handle(path),
             ArchiveHandle::Bethesda(i) => i.get_handle(path),
@@ -65,15 +75,12 @@ impl ProcessArchive for ArchiveHandle<'_> {
             ArchiveHandle::Zip(i) => i.get_handle(path),
             ArchiveHandle::SevenzRust2(i) => i.get_handle(path),
         }
-        .with_context(|| {
-            format!(
-                "when getting a file handle out of an archive of kind [{kind:?}]",
-                kind = ArchiveHandleKind::from(&*self)
-            )
-        })
+        .tap_err(|e| self_test::record_failure(kind, e))
+        .with_context(|| format!("when getting a file handle out of an archive of kind [{kind:?}]"))
     }
     #[instrument(skip(self, paths), fields(kind=?ArchiveHandleKind::from(&*self), paths=%paths.len()))]
     fn get_many_handles(&mut self, paths: &[&Path]) -> Result<Vec<(PathBuf, self::ArchiveFileHandle)>> {
+        let kind = ArchiveHandleKind::from(&*self);
         match self {
             ArchiveHandle::Wrapped7Zip(i) => i.get_many_handles(paths),
             ArchiveHandle::Bethesda(i) => i.get_many_handles(paths),
@@ -82,12 +89,8 @@ impl ProcessArchive for ArchiveHandle<'_> {
             ArchiveHandle::Zip(i) => i.get_many_handles(paths),
             ArchiveHandle::SevenzRust2(i) => i.get_many_handles(paths),
         }
-        .with_context(|| {
-            format!(
-                "when getting multiple handles out of an archive of kind [{kind:?}]",
-                kind = ArchiveHandleKind::from(&*self)
-            )
-        })
+        .tap_err(|e| self_test::record_failure(kind, e))
+        .with_context(|| format!("when getting multiple handles out of an archive of kind [{kind:?}]"))
     }
 }
 
@@ -103,6 +106,13 @@ pub enum ArchiveFileHandle {
 }
 
 impl ArchiveFileHandle {
+    /// the temp file backends which can't stream extract the entry into, handles reading out of the archive come back as they were
+    pub fn into_extracted(self) -> std::result::Result<tempfile::NamedTempFile, Self> {
+        match self {
+            ArchiveFileHandle::Unrar(extracted) | ArchiveFileHandle::Zip(extracted) => Ok(extracted),
+            other => Err(other),
+        }
+    }
     #[tracing::instrument(skip(self))]
     pub fn size(&mut self) -> Result<u64> {
         match self {
@@ -132,6 +142,12 @@ static_assertions::assert_impl_all!(ArchiveFileHandle: Send, Sync);
 impl ArchiveHandle<'_> {
     /// this is literally bruteforce approach
     pub fn with_guessed<T, F: FnMut(Self) -> Result<T> + Send + Sync>(path: &ExistingPath, extension: Option<&str>, mut with_guessed: F) -> anyhow::Result<T> {
+        let extension = match ArchiveHandleKind::preferred_for_extension(extension) {
+            Some(_) => extension,
+            None => sniffed_extension(path)
+                .tap_some(|sniffed| tracing::debug!(?extension, %sniffed, "recognized archive by its contents"))
+                .or(extension),
+        };
         match extension.map(|b| b.to_lowercase()).as_deref() {
             Some("bsa" | "ba2" | "mpi") => bethesda_archive::BethesdaArchive::open(path)
                 .context("reading bsa")
@@ -163,6 +179,7 @@ impl ArchiveHandle<'_> {
                 .or_else(|reason| {
                     WRAPPED_7ZIP
                         .with(|wrapped| wrapped.open_file(path.as_os_path()).map(Self::Wrapped7Zip))
+                        .tap_err(|e| self_test::record_failure(ArchiveHandleKind::Wrapped7Zip, e))
                         .and_then(&mut with_guessed)
                         .with_context(|| format!("trying because: {reason:?}"))
                         .tap_err(|message| tracing::warn!("could not open archive with 7z: {message:?}"))
@@ -174,6 +191,7 @@ impl ArchiveHandle<'_> {
                             file.rewind().context("rewinding")?;
                             self::sevenz::SevenZipArchive::new(file)
                                 .context("opening archive with SevenzRust2 library")
+                                .map(|archive| archive.with_path(path.as_os_path()))
                                 .map(Box::new)
                                 .map(Self::SevenzRust2)
                         })
@@ -191,6 +209,7 @@ impl ArchiveHandle<'_> {
                 .or_else(|reason| {
                     WRAPPED_7ZIP
                         .with(|wrapped| wrapped.open_file(path.as_os_path()).map(Self::Wrapped7Zip))
+                        .tap_err(|e| self_test::record_failure(ArchiveHandleKind::Wrapped7Zip, e))
                         .and_then(&mut with_guessed)
                         .with_context(|| format!("trying because: {reason:?}"))
                         .tap_err(|message| tracing::warn!("could not open archive with 7z: {message:?}"))
@@ -214,6 +233,7 @@ impl ArchiveHandle<'_> {
                         .and_then(|(_, file)| {
                             self::sevenz::SevenZipArchive::new(file)
                                 .context("opening archive with SevenzRust2 library")
+                                .map(|archive| archive.with_path(path.as_os_path()))
                                 .map(Box::new)
                                 .map(Self::SevenzRust2)
                         })
@@ -224,6 +244,7 @@ impl ArchiveHandle<'_> {
                 .or_else(|reason| {
                     WRAPPED_7ZIP
                         .with(|wrapped| wrapped.open_file(path.as_os_path()).map(Self::Wrapped7Zip))
+                        .tap_err(|e| self_test::record_failure(ArchiveHandleKind::Wrapped7Zip, e))
                         .and_then(&mut with_guessed)
                         .with_context(|| format!("trying because: {reason:?}"))
                         .tap_err(|message| tracing::warn!("could not open archive with 7z: {message:?}"))
@@ -258,6 +279,7 @@ impl ArchiveHandle<'_> {
                             .and_then(|(_, file)| {
                                 self::sevenz::SevenZipArchive::new(file)
                                     .context("opening archive with SevenzRust2 library")
+                                    .map(|archive| archive.with_path(path.as_os_path()))
                                     .map(Box::new)
                                     .map(Self::SevenzRust2)
                             })
@@ -268,6 +290,7 @@ impl ArchiveHandle<'_> {
                     .or_else(|err| {
                         WRAPPED_7ZIP
                             .with(|wrapped| wrapped.open_file(path.as_os_path()).map(Self::Wrapped7Zip))
+                            .tap_err(|e| self_test::record_failure(ArchiveHandleKind::Wrapped7Zip, e))
                             .and_then(&mut with_guessed)
                             .with_context(|| format!("because: {err:#?}"))
                             .tap_err(|message| tracing::warn!("could not open archive with 7z: {message:?}"))
@@ -305,6 +328,63 @@ pub enum ArchiveHandle<'a> {
     Zip(self::zip::ZipArchive),
 }
 
+impl ArchiveHandleKind {
+    /// backend [ArchiveHandle::with_guessed] tries first for given extension, [None] when it's guesswork
+    pub fn preferred_for_extension(extension: Option<&str>) -> Option<Self> {
+        match extension.map(|e| e.to_lowercase()).as_deref() {
+            Some("bsa" | "ba2" | "mpi") => Some(Self::Bethesda),
+            Some("rar") => Some(Self::Unrar),
+            Some("7z") => Some(Self::SevenzRust2),
+            Some("zip") => Some(Self::Zip),
+            _ => None,
+        }
+    }
+    /// like [Self::preferred_for_extension], but 7z archives with blocks too big to be decoded in process go to the 7z binary.
+    /// `source_hash` is the hash of the archive descriptor, its (cached) listing is what the blocks are measured with
+    pub fn for_archive(source_hash: &str, archive: &std::path::Path, extension: Option<&str>) -> Option<Self> {
+        match Self::preferred_for_extension(extension) {
+            Some(Self::SevenzRust2)
+                if listing_cache::listing(source_hash, archive)
+                    .map(|listing| sevenz::exceeds_block_memory_limit(&listing))
+                    .unwrap_or(false) =>
+            {
+                Some(Self::Wrapped7Zip)
+            }
+            other => other,
+        }
+    }
+    /// whether file handles read straight out of the archive, other backends extract into a temp file first.
+    /// of the kinds [Self::preferred_for_extension] picks, that's only [Self::Bethesda]
+    pub fn streams_entries(self) -> bool {
+        match self {
+            ArchiveHandleKind::Bethesda | ArchiveHandleKind::CompressTools | ArchiveHandleKind::Wrapped7Zip => true,
+            ArchiveHandleKind::SevenzRust2 | ArchiveHandleKind::Unrar | ArchiveHandleKind::Zip => false,
+        }
+    }
+    /// whether every file handle is a temp file of its own ([ArchiveFileHandle::into_extracted]), which can be moved into place
+    pub fn extracts_to_temp_files(self) -> bool {
+        matches!(self, ArchiveHandleKind::Unrar | ArchiveHandleKind::Zip)
+    }
+}
+
+/// extension of the archive kind the file starts like, for archives whose name doesn't tell (nested archives extracted into
+/// temp files, downloads without an extension)
+pub fn sniffed_extension(path: &ExistingPath) -> Option<&'static str> {
+    use std::io::Read;
+    let mut magic = [0u8; 6];
+    path.open_file_read()
+        .ok()
+        .and_then(|(_, mut file)| file.read_exact(&mut magic).ok())
+        .and_then(|_| match magic {
+            [b'P', b'K', 3 | 5 | 7, 4 | 6 | 8, ..] => Some("zip"),
+            [b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C] => Some("7z"),
+            [b'R', b'a', b'r', b'!', 0x1A, 0x07] => Some("rar"),
+            [b'B', b'S', b'A', 0, ..] => Some("bsa"),
+            [b'B', b'T', b'D', b'X', ..] => Some("ba2"),
+            _ => None,
+        })
+}
+
 pub mod wrapped_7zip;
 
 #[extension_traits::extension(pub(crate) trait SeekWithTempFileExt)]
@@ -314,9 +394,10 @@ where
 {
     fn seek_with_temp_file_blocking_raw(mut self, expected_size: u64) -> Result<(u64, tempfile::TempPath)> {
         let _span = tracing::info_span!("seek_with_temp_file_blocking_raw").entered();
+        let temp_directory = crate::temp_directory::run_directory()?;
         tempfile::Builder::new()
             .prefix("seeked-file-")
-            .tempfile_in(*crate::consts::TEMP_FILE_DIR)
+            .tempfile_in(temp_directory)
             .context("creating a tempfile")
             .and_then(|mut temp_file| {
                 {
@@ -343,10 +424,11 @@ where
     }
     fn seek_with_temp_file_blocking_raw_with_extension(mut self, extension: &str, expected_size: u64) -> Result<(u64, tempfile::TempPath)> {
         let _span = tracing::info_span!("seek_with_temp_file_blocking_raw_with_extension").entered();
+        let temp_directory = crate::temp_directory::run_directory()?;
         tempfile::Builder::new()
             .prefix("seeked-file-")
             .suffix(&format!(".{extension}"))
-            .tempfile_in(*crate::consts::TEMP_FILE_DIR)
+            .tempfile_in(temp_directory)
             .context("creating a tempfile")
             .and_then(|mut temp_file| {
                 {
@@ -378,9 +460,10 @@ where
     }
     fn seek_with_temp_file_blocking(mut self, expected_size: u64, permit: tokio::sync::OwnedSemaphorePermit) -> Result<WithPermit<tempfile::TempPath>> {
         let _span = tracing::info_span!("seek_with_temp_file_blocking").entered();
+        let temp_directory = crate::temp_directory::run_directory()?;
         tempfile::Builder::new()
             .prefix("seeked-file-")
-            .tempfile_in(*crate::consts::TEMP_FILE_DIR)
+            .tempfile_in(temp_directory)
             .context("creating a tempfile")
             .and_then(|mut temp_file| {
                 {
@@ -415,13 +498,14 @@ where
             max_open_files=%max_open_files(),
         );
         let reader = Arc::new(std::sync::Mutex::new(self));
+        let temp_directory = crate::temp_directory::run_directory()?;
         WithPermit::new_blocking(&OPEN_FILE_PERMITS, {
             cloned![span];
             move || {
                 let span = span.entered();
                 tempfile::Builder::new()
                     .prefix("seeked-file-")
-                    .tempfile_in(*crate::consts::TEMP_FILE_DIR)
+                    .tempfile_in(temp_directory)
                     .context("creating a tempfile")
                     .and_then(|mut temp_file| {
                         {
@@ -480,4 +564,29 @@ mod tests {
         })
         .await
     }
+
+    #[test_log::test]
+    fn test_sniffed_extension() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        [
+            (b"PK\x03\x04\x14\x00".as_slice(), Some("zip")),
+            (b"7z\xBC\xAF\x27\x1C\x00\x04".as_slice(), Some("7z")),
+            (b"Rar!\x1A\x07\x01\x00".as_slice(), Some("rar")),
+            (b"BSA\x00\x68\x00".as_slice(), Some("bsa")),
+            (b"BTDX\x01\x00".as_slice(), Some("ba2")),
+            (b"<html>503</html>".as_slice(), None),
+            (b"PK".as_slice(), None),
+        ]
+        .into_iter()
+        .enumerate()
+        .try_for_each(|(index, (contents, expected))| {
+            directory
+                .path()
+                .join(format!("download-{index}"))
+                .pipe(|path| std::fs::write(&path, contents).map(|_| path))
+                .context("writing fixture")
+                .and_then(|path| ExistingPathBuf::new(&path))
+                .map(|path| assert_eq!(sniffed_extension(&path), expected, "{contents:?}"))
+        })
+    }
 }
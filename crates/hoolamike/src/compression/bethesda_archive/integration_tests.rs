@@ -1,5 +1,7 @@
 use {
-    super::*, case_insensitive_path::ExistingPathBuf, tracing::{info, info_span}
+    super::*,
+    case_insensitive_path::ExistingPathBuf,
+    tracing::{info, info_span},
 };
 
 const CARGO_MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
@@ -49,3 +51,51 @@ fn list_archives() -> Result<()> {
         Ok(())
     })
 }
+
+#[ignore]
+#[test_log::test]
+fn streamed_entries_match_preheated_entries() -> Result<()> {
+    use crate::{
+        compression::{ArchiveHandle, SeekWithTempFileExt},
+        install_modlist::directives::from_archive::extract_entries_to_files,
+        read_wrappers::ReadExt,
+    };
+    let hash_file = |path: &std::path::Path| -> Result<u64> {
+        let mut reader = std::fs::File::open(path)?.and_hash();
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok(reader.hash())
+    };
+    for file in std::fs::read_dir(test_data_directory())?.take(10) {
+        let file = file?.path();
+        let extension = file.extension().map(|e| e.to_string_lossy().to_string());
+        if !matches!(extension.as_deref(), Some("ba2" | "bsa")) {
+            continue;
+        }
+        let _span = info_span!("reading_archive", file = %file.display()).entered();
+        let archive = ExistingPathBuf::new(&file)?;
+        let entries = ArchiveHandle::with_guessed(&archive, extension.as_deref(), |mut archive| archive.list_paths())?;
+        let output_directory = tempfile::tempdir()?;
+        let expected = entries
+            .into_iter()
+            .take(5)
+            .enumerate()
+            .map(|(idx, entry)| -> Result<_> {
+                let (size, preheated) = ArchiveHandle::with_guessed(&archive, extension.as_deref(), |mut archive| {
+                    archive.get_handle(&entry).and_then(|mut handle| {
+                        handle
+                            .size()
+                            .and_then(|size| handle.seek_with_temp_file_blocking_raw(size))
+                    })
+                })?;
+                let expected = hash_file(&preheated)?;
+                Ok((entry, size, Some(expected), output_directory.path().join(format!("entry-{idx}"))))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let streamed = extract_entries_to_files(&archive, extension.as_deref(), expected.clone());
+        for ((entry, _, expected, output_path), streamed) in expected.into_iter().zip(streamed) {
+            assert_eq!(Some(streamed?), expected, "inline hash of [{entry:?}] differs from the preheated one");
+            assert_eq!(Some(hash_file(&output_path)?), expected, "streamed [{entry:?}] differs from the preheated one");
+        }
+    }
+    Ok(())
+}
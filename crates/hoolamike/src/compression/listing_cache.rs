@@ -0,0 +1,281 @@
+//! listings of downloaded archives (entries, their sizes and the 7z blocks they're in) are cached in the project root, keyed
+//! by the hash of the archive descriptor. parsing the headers of a big 7z archive takes seconds, planning the extraction
+//! needs them for every chunk of directives, and a resumed installation would need them all over again. a listing is only
+//! used for as long as the size and modification time of the archive match the ones it was made from
+
+use {
+    super::{ArchiveHandle, ProcessArchive},
+    crate::wabbajack_file::modlist_cache::CACHE_DIRECTORY,
+    anyhow::{Context, Result},
+    case_insensitive_path::ExistingPathBuf,
+    parking_lot::Mutex,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        io::Write,
+        path::{Path, PathBuf},
+        sync::{Arc, OnceLock},
+        time::UNIX_EPOCH,
+    },
+    tap::prelude::*,
+    tracing::{debug, warn},
+};
+
+/// inside of [CACHE_DIRECTORY]
+pub const LISTINGS_DIRECTORY: &str = "listings";
+/// bumped whenever the layout of a listing changes, listings written by other versions are treated as stale
+const CACHE_VERSION: u32 = 1;
+
+static LISTING_CACHE: OnceLock<ListingCache> = OnceLock::new();
+
+/// listings are persisted in the project root from now on, until then (or without it) they're only kept in memory
+pub fn configure(project_root: &Path) {
+    LISTING_CACHE.get_or_init(|| ListingCache::in_project_root(project_root));
+}
+
+/// see [ListingCache::listing]
+pub fn listing(source_hash: &str, archive: &Path) -> Result<Listing> {
+    LISTING_CACHE
+        .get_or_init(ListingCache::default)
+        .listing(source_hash, archive)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedEntry {
+    pub path: String,
+    /// [None] when the backend can't tell without extracting the entry
+    pub size: Option<u64>,
+    /// solid block of a 7z archive the entry is in
+    pub block_index: Option<usize>,
+}
+
+pub type Listing = Arc<Vec<ListedEntry>>;
+
+/// the archive file a listing was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    size: u64,
+    modified_seconds: u64,
+    modified_nanos: u32,
+}
+
+impl FileStamp {
+    fn of(archive: &Path) -> Result<Self> {
+        std::fs::metadata(archive)
+            .context("reading metadata")
+            .and_then(|metadata| {
+                metadata
+                    .modified()
+                    .context("reading modification time")
+                    .map(|modified| modified.duration_since(UNIX_EPOCH).unwrap_or_default())
+                    .map(|modified| Self {
+                        size: metadata.len(),
+                        modified_seconds: modified.as_secs(),
+                        modified_nanos: modified.subsec_nanos(),
+                    })
+            })
+            .with_context(|| format!("identifying [{}]", archive.display()))
+    }
+}
+
+#[derive(Serialize)]
+struct CachedListingRef<'a> {
+    version: u32,
+    stamp: FileStamp,
+    entries: &'a [ListedEntry],
+}
+
+#[derive(Deserialize)]
+struct CachedListing {
+    version: u32,
+    stamp: FileStamp,
+    entries: Vec<ListedEntry>,
+}
+
+/// lists the archive the hard way - 7z headers are read directly, other archives are opened with whichever backend manages
+fn list_entries(archive: &Path) -> Result<Vec<ListedEntry>> {
+    let extension = archive.extension().and_then(|extension| extension.to_str());
+    match extension.map(str::to_lowercase).as_deref() {
+        Some("7z") => super::sevenz::list_entries(archive),
+        _ => Err(anyhow::anyhow!("not a 7z archive")),
+    }
+    .or_else(|reason| {
+        ExistingPathBuf::new(archive)
+            .and_then(|archive| ArchiveHandle::with_guessed(&archive, extension, |mut archive| archive.list_paths()))
+            .map(|paths| {
+                paths
+                    .into_iter()
+                    .map(|path| ListedEntry {
+                        path: path.as_original_path().to_string(),
+                        size: None,
+                        block_index: None,
+                    })
+                    .collect()
+            })
+            .with_context(|| format!("trying because: {reason:?}"))
+    })
+    .with_context(|| format!("listing [{}]", archive.display()))
+}
+
+#[derive(Debug, Default)]
+pub struct ListingCache {
+    /// [None] keeps the listings in memory only
+    directory: Option<PathBuf>,
+    in_memory: Mutex<BTreeMap<String, (FileStamp, Listing)>>,
+}
+
+impl ListingCache {
+    pub fn in_project_root(project_root: &Path) -> Self {
+        Self {
+            directory: Some(project_root.join(CACHE_DIRECTORY).join(LISTINGS_DIRECTORY)),
+            in_memory: Default::default(),
+        }
+    }
+
+    /// hashes are base64, which can contain a `/`
+    fn entry_path(&self, source_hash: &str) -> Option<PathBuf> {
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{}.json", source_hash.replace('/', "_").replace('+', "-"))))
+    }
+
+    fn load(&self, source_hash: &str, stamp: &FileStamp) -> Option<Vec<ListedEntry>> {
+        self.entry_path(source_hash)
+            .filter(|entry_path| entry_path.exists())
+            .and_then(|entry_path| {
+                std::fs::read(&entry_path)
+                    .context("reading cached listing")
+                    .and_then(|cached| serde_json::from_slice::<CachedListing>(&cached).context("parsing cached listing"))
+                    .map(|cached| match cached.version == CACHE_VERSION && cached.stamp == *stamp {
+                        true => Some(cached.entries),
+                        false => None.tap(|_| debug!(?source_hash, "cached listing is stale")),
+                    })
+                    .with_context(|| format!("reading [{}]", entry_path.display()))
+                    .unwrap_or_else(|reason| {
+                        warn!("could not use the cached listing, listing the archive again: {reason:?}");
+                        None
+                    })
+            })
+    }
+
+    fn store(&self, source_hash: &str, stamp: FileStamp, entries: &[ListedEntry]) -> Result<()> {
+        match (self.directory.as_ref(), self.entry_path(source_hash)) {
+            (Some(directory), Some(entry_path)) => std::fs::create_dir_all(directory)
+                .with_context(|| format!("creating [{}]", directory.display()))
+                .and_then(|_| tempfile::NamedTempFile::new_in(directory).context("creating cache entry"))
+                .and_then(|mut temp| {
+                    serde_json::to_writer(
+                        &mut temp,
+                        &CachedListingRef {
+                            version: CACHE_VERSION,
+                            stamp,
+                            entries,
+                        },
+                    )
+                    .context("writing listing")
+                    .and_then(|_| temp.flush().context("flushing cache entry"))
+                    .and_then(|_| {
+                        temp.persist(&entry_path)
+                            .context("moving cache entry in place")
+                    })
+                })
+                .map(|_| ())
+                .with_context(|| format!("caching listing at [{}]", entry_path.display())),
+            _ => Ok(()),
+        }
+    }
+
+    /// listing of the archive described by `source_hash` - from memory, from the project root, or (when the archive changed
+    /// since, or it was never listed) out of the archive itself
+    pub fn listing(&self, source_hash: &str, archive: &Path) -> Result<Listing> {
+        FileStamp::of(archive)
+            .and_then(|stamp| {
+                if let Some((_, listing)) = self
+                    .in_memory
+                    .lock()
+                    .get(source_hash)
+                    .filter(|(cached, _)| *cached == stamp)
+                {
+                    return Ok(listing.clone());
+                }
+                self.load(source_hash, &stamp)
+                    .map(Ok)
+                    .unwrap_or_else(|| {
+                        list_entries(archive).tap_ok(|entries| {
+                            self.store(source_hash, stamp, entries)
+                                .unwrap_or_else(|reason| warn!("could not cache the listing: {reason:?}"))
+                        })
+                    })
+                    .map(Arc::new)
+                    .tap_ok(|listing| {
+                        self.in_memory
+                            .lock()
+                            .insert(source_hash.to_string(), (stamp, listing.clone()));
+                    })
+            })
+            .with_context(|| format!("listing archive [{source_hash}]"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ARCHIVE: &[u8] = include_bytes!("./example-files/data.7z");
+
+    #[test_log::test]
+    fn test_listing_survives_a_restart() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let archive = directory.path().join("data.7z");
+        std::fs::write(&archive, ARCHIVE)?;
+
+        let listed = ListingCache::in_project_root(directory.path()).listing("some/hash+=", &archive)?;
+        assert!(!listed.is_empty());
+        assert!(
+            listed
+                .iter()
+                .all(|entry| entry.size.is_some() && entry.block_index.is_some())
+        );
+        let entry_path = directory
+            .path()
+            .join(CACHE_DIRECTORY)
+            .join(LISTINGS_DIRECTORY)
+            .join("some_hash-=.json");
+        assert!(entry_path.exists());
+
+        // a new process, the archive itself is not read anymore
+        let mut cached = std::fs::read_to_string(&entry_path)?.parse::<serde_json::Value>()?;
+        cached["entries"][0]["path"] = "only/in/the/cache".into();
+        std::fs::write(&entry_path, cached.to_string())?;
+        let restarted = ListingCache::in_project_root(directory.path()).listing("some/hash+=", &archive)?;
+        assert_eq!(restarted[0].path, "only/in/the/cache");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_listing_of_a_changed_archive_is_stale() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let archive = directory.path().join("data.7z");
+        std::fs::write(&archive, ARCHIVE)?;
+        let cache = ListingCache::in_project_root(directory.path());
+        let listed = cache.listing("hash", &archive)?;
+        let entry_path = cache.entry_path("hash").context("cache has a directory")?;
+        std::fs::write(&entry_path, std::fs::read_to_string(&entry_path)?.replace(&listed[0].path, "only/in/the/cache"))?;
+
+        // the archive was replaced (downloaded again, say) - neither the listing in memory nor the one on disk apply to it
+        std::fs::File::options()
+            .write(true)
+            .open(&archive)?
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1234))?;
+        assert_eq!(cache.listing("hash", &archive)?, listed);
+        assert_eq!(ListingCache::in_project_root(directory.path()).listing("hash", &archive)?, listed);
+
+        std::fs::write(&entry_path, "definitely not json")?;
+        assert_eq!(
+            ListingCache::in_project_root(directory.path()).listing("hash", &archive)?,
+            listed,
+            "corrupt entry is ignored"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! a broken extraction backend (most often a buggy system 7z build) looks exactly like a broken download - errors are
+//! intermittent and point at random archives. Failures that look like the backend's fault are recorded in the state directory,
+//! and the next run extracts a couple of known-good archives through every backend to tell which one is to blame.
+
+use {
+    super::{ArchiveHandle, ArchiveHandleKind, ProcessArchive},
+    crate::{path::CaseInsensitivePathBuf, utils::ExistingPathRead},
+    anyhow::{Context, Result},
+    case_insensitive_path::ExistingPath,
+    chrono::{DateTime, Utc},
+    itertools::Itertools,
+    serde::{Deserialize, Serialize},
+    std::{
+        io::{BufRead, Write},
+        path::{Path, PathBuf},
+        sync::{Mutex, OnceLock},
+    },
+    tap::prelude::*,
+    tracing::{info, warn},
+    wrapped_7zip::fixtures::{FIXTURES, Fixture},
+};
+
+pub const FAILURE_LOG_FILE_NAME: &str = "extraction-backend-failures.jsonl";
+const MAX_RECORDED_MESSAGE_LENGTH: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    /// the backend itself misbehaved (crashed, is missing, can't handle the compression method, ...)
+    Backend,
+    /// the archive is broken, another backend wouldn't do any better
+    Corruption,
+    Unknown,
+}
+
+/// checked first - a crc error makes 7z exit with a failure status too
+const CORRUPTION_PATTERNS: &[&str] = &[
+    "crc failed",
+    "data error",
+    "headers error",
+    "unexpected end of archive",
+    "checksum",
+    "is not archive",
+    "can not open the file as archive",
+    "cannot open the file as archive",
+    "bad signature",
+    "corrupt",
+    "truncated",
+];
+
+const BACKEND_PATTERNS: &[&str] = &[
+    "segmentation fault",
+    "core dumped",
+    "illegal instruction",
+    "bus error",
+    "panicked",
+    // no exit code means the process was killed by a signal
+    "command failed with status [-1]",
+    "extraction failed with status [killed]",
+    "spawning command",
+    "no 7z binary",
+    "error while loading shared libraries",
+    "symbol lookup error",
+    "unsupported method",
+    "e_notimpl",
+    "not implemented",
+];
+
+pub fn classify(error: &anyhow::Error) -> FailureClass {
+    let message = error
+        .chain()
+        .map(|e| e.to_string())
+        .join("\n")
+        .to_lowercase();
+    let matches = |patterns: &[&str]| patterns.iter().any(|pattern| message.contains(pattern));
+    if matches(CORRUPTION_PATTERNS) {
+        FailureClass::Corruption
+    } else if matches(BACKEND_PATTERNS) {
+        FailureClass::Backend
+    } else {
+        FailureClass::Unknown
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecordedFailure {
+    pub backend: String,
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+static FAILURE_LOG: OnceLock<PathBuf> = OnceLock::new();
+static FAILURE_LOG_WRITE: Mutex<()> = Mutex::new(());
+
+/// appends the failure to the log when it's the backend's fault, returns whether it was recorded
+pub fn record_failure_in(log: &Path, backend: ArchiveHandleKind, error: &anyhow::Error) -> Result<bool> {
+    if classify(error) != FailureClass::Backend {
+        return Ok(false);
+    }
+    let _guard = FAILURE_LOG_WRITE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    RecordedFailure {
+        backend: format!("{backend:?}"),
+        message: format!("{error:?}")
+            .chars()
+            .take(MAX_RECORDED_MESSAGE_LENGTH)
+            .collect(),
+        at: Utc::now(),
+    }
+    .pipe_ref(serde_json::to_string)
+    .context("serializing failure")
+    .and_then(|line| {
+        log.parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .context("creating state directory")
+            .and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log)
+                    .context("opening failure log")
+            })
+            .and_then(|mut file| writeln!(file, "{line}").context("writing failure log"))
+    })
+    .with_context(|| format!("recording extraction failure in [{}]", log.display()))
+    .map(|_| true)
+}
+
+/// no-op until [startup_check] picks the state directory
+pub(crate) fn record_failure(backend: ArchiveHandleKind, error: &anyhow::Error) {
+    if let Some(log) = FAILURE_LOG.get() {
+        record_failure_in(log, backend, error)
+            .map(|recorded| {
+                if recorded {
+                    warn!(
+                        ?backend,
+                        "extraction failure looks like a problem with the backend, it will be checked on the next run"
+                    )
+                }
+            })
+            .unwrap_or_else(|e| warn!("{e:?}"))
+    }
+}
+
+pub fn recorded_failures(log: &Path) -> Result<Vec<RecordedFailure>> {
+    match log.exists() {
+        false => Ok(vec![]),
+        true => std::fs::File::open(log)
+            .context("opening failure log")
+            .map(std::io::BufReader::new)
+            .and_then(|reader| {
+                reader
+                    .lines()
+                    .map(|line| line.context("reading line"))
+                    .filter_ok(|line| !line.trim().is_empty())
+                    .map(|line| line.and_then(|line| serde_json::from_str(&line).context("parsing recorded failure")))
+                    .collect()
+            })
+            .with_context(|| format!("reading [{}]", log.display())),
+    }
+}
+
+/// backends taking part in the self-test, with extensions of fixtures they are expected to handle
+const BACKENDS: &[(ArchiveHandleKind, &[&str])] = &[
+    (ArchiveHandleKind::SevenzRust2, &["7z"]),
+    (ArchiveHandleKind::Unrar, &["rar"]),
+    (ArchiveHandleKind::CompressTools, &["7z", "rar"]),
+    (ArchiveHandleKind::Wrapped7Zip, &["7z", "rar"]),
+];
+
+#[derive(Debug)]
+pub struct BackendHealth {
+    pub backend: ArchiveHandleKind,
+    /// (fixture name, error)
+    pub failures: Vec<(&'static str, String)>,
+}
+
+impl BackendHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+pub fn run_self_test(fixtures: &[Fixture], check: impl Fn(ArchiveHandleKind, &Fixture) -> Result<()>) -> Vec<BackendHealth> {
+    BACKENDS
+        .iter()
+        .map(|&(backend, extensions)| BackendHealth {
+            backend,
+            failures: fixtures
+                .iter()
+                .filter(|fixture| extensions.contains(&fixture.extension))
+                .filter_map(|fixture| {
+                    check(backend, fixture)
+                        .err()
+                        .map(|e| (fixture.name, format!("{e:?}")))
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+pub fn recommendation(report: &[BackendHealth]) -> Option<&'static str> {
+    report
+        .iter()
+        .any(|health| health.backend == ArchiveHandleKind::Wrapped7Zip && !health.is_healthy())
+        .then_some(
+            "the system 7z binary failed to extract known-good archives - install the official 7-Zip build (7zz, https://www.7-zip.org/download.html) and \
+             make sure it's in PATH, hoolamike prefers it over 7z",
+        )
+}
+
+fn open_with(backend: ArchiveHandleKind, path: &ExistingPath) -> Result<ArchiveHandle<'static>> {
+    match backend {
+        ArchiveHandleKind::SevenzRust2 => path
+            .open_file_read()
+            .and_then(|(_, file)| super::sevenz::SevenZipArchive::new(file))
+            .map(Box::new)
+            .map(ArchiveHandle::SevenzRust2),
+        ArchiveHandleKind::CompressTools => path
+            .open_file_read()
+            .and_then(|(_, file)| super::compress_tools::ArchiveHandle::new(file))
+            .map(ArchiveHandle::CompressTools),
+        ArchiveHandleKind::Unrar => super::unrar_rs::ArchiveHandle::new(path).map(ArchiveHandle::Unrar),
+        ArchiveHandleKind::Zip => super::zip::ZipArchive::new(path).map(ArchiveHandle::Zip),
+        ArchiveHandleKind::Wrapped7Zip => crate::temp_directory::run_directory()
+            .and_then(::wrapped_7zip::Wrapped7Zip::find_bin)
+            .and_then(|wrapped| wrapped.open_file(path.as_os_path()))
+            .map(ArchiveHandle::Wrapped7Zip),
+        ArchiveHandleKind::Bethesda => anyhow::bail!("there are no fixtures for bethesda archives"),
+    }
+}
+
+/// extracts every entry of the fixture with given backend, reading each one to the end
+pub fn extract_fixture(backend: ArchiveHandleKind, fixture: &Fixture) -> Result<()> {
+    tempfile::Builder::new()
+        .prefix("self-test-")
+        .suffix(&format!(".{}", fixture.extension))
+        .tempfile_in(crate::temp_directory::run_directory()?)
+        .context("creating fixture file")
+        .and_then(|mut file| {
+            file.write_all(fixture.bytes)
+                .and_then(|_| file.flush())
+                .context("writing fixture file")
+                .map(|_| file.into_temp_path())
+        })
+        .and_then(|fixture_path| {
+            CaseInsensitivePathBuf::from_path(&fixture_path)
+                .and_then(|path| path.try_exists())
+                .and_then(|path| open_with(backend, &path))
+                .and_then(|mut archive| {
+                    archive
+                        .list_paths()
+                        .and_then(|paths| archive.get_many_handles(&paths.iter().collect_vec()))
+                })
+                .and_then(|handles| {
+                    anyhow::ensure!(!handles.is_empty(), "no entries were extracted");
+                    handles.into_iter().try_for_each(|(path, mut handle)| {
+                        std::io::copy(&mut handle, &mut std::io::sink())
+                            .with_context(|| format!("reading [{path}]"))
+                            .map(|_| ())
+                    })
+                })
+        })
+        .with_context(|| format!("extracting [{}] with [{backend:?}]", fixture.name))
+}
+
+/// runs the self-test when a previous run recorded backend failures, then starts recording failures of this run.
+/// never fails - it's only there to point at the culprit
+pub fn startup_check(state_directory: &Path) {
+    let log = state_directory.join(FAILURE_LOG_FILE_NAME);
+    match recorded_failures(&log) {
+        Err(e) => warn!("could not read recorded extraction failures: {e:?}"),
+        Ok(recorded) if recorded.is_empty() => {}
+        Ok(recorded) => {
+            warn!(
+                "a previous run recorded [{}] extraction failures which look like a problem with the extraction backend, testing backends",
+                recorded.len()
+            );
+            recorded
+                .iter()
+                .rev()
+                .take(3)
+                .for_each(|RecordedFailure { backend, message, at }| warn!(%backend, %at, "{message}"));
+            let report = run_self_test(FIXTURES, extract_fixture);
+            report.iter().for_each(|health| match health.is_healthy() {
+                true => info!(backend=?health.backend, "extraction backend is healthy"),
+                false => health
+                    .failures
+                    .iter()
+                    .for_each(|(fixture, error)| warn!(backend=?health.backend, %fixture, "extraction backend failed the self-test: {error}")),
+            });
+            if let Some(recommendation) = recommendation(&report) {
+                warn!("{recommendation}");
+            }
+            // a healthy report means the recorded failures are dealt with, an unhealthy one keeps them around for the next run
+            if report.iter().all(BackendHealth::is_healthy) {
+                std::fs::remove_file(&log)
+                    .with_context(|| format!("removing [{}]", log.display()))
+                    .unwrap_or_else(|e| warn!("{e:?}"));
+            }
+        }
+    }
+    FAILURE_LOG
+        .set(log)
+        .unwrap_or_else(|log| warn!("extraction failures are already recorded elsewhere, not recording in [{}]", log.display()));
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::anyhow};
+
+    #[test_log::test]
+    fn test_failures_are_classified_by_message() {
+        [
+            (
+                anyhow!("command failed with status [2]").context("ERROR: CRC Failed : small-file.json"),
+                FailureClass::Corruption,
+            ),
+            (anyhow!("Unexpected end of archive"), FailureClass::Corruption),
+            (
+                anyhow!("command failed with status [-1]").context("when executing [7z x archive.7z]"),
+                FailureClass::Backend,
+            ),
+            (
+                anyhow!("extraction failed with status [killed]").context("extracting from [archive.7z]"),
+                FailureClass::Backend,
+            ),
+            (
+                anyhow!("Segmentation fault (core dumped)").context("command failed with status [139]"),
+                FailureClass::Backend,
+            ),
+            (anyhow!("no 7z binary"), FailureClass::Backend),
+            (anyhow!("Unsupported Method : texture.dds"), FailureClass::Backend),
+            (anyhow!("some paths were not found"), FailureClass::Unknown),
+        ]
+        .into_iter()
+        .for_each(|(error, expected)| assert_eq!(classify(&error), expected, "{error:?}"));
+    }
+
+    #[test_log::test]
+    fn test_only_backend_failures_are_recorded() -> Result<()> {
+        let state = tempfile::tempdir()?;
+        let log = state.path().join("nested").join(FAILURE_LOG_FILE_NAME);
+        assert!(recorded_failures(&log)?.is_empty());
+        assert!(!record_failure_in(&log, ArchiveHandleKind::Wrapped7Zip, &anyhow!("Data Error : file.esp"))?);
+        assert!(!log.exists());
+        assert!(record_failure_in(&log, ArchiveHandleKind::Wrapped7Zip, &anyhow!("Illegal instruction"))?);
+        assert!(record_failure_in(&log, ArchiveHandleKind::SevenzRust2, &anyhow!("Unsupported method"))?);
+        let recorded = recorded_failures(&log)?;
+        assert_eq!(recorded.iter().map(|r| r.backend.as_str()).collect_vec(), ["Wrapped7Zip", "SevenzRust2"]);
+        assert!(recorded[0].message.contains("Illegal instruction"));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_self_test_reports_each_backend_on_matching_fixtures() {
+        let report = run_self_test(FIXTURES, |backend, fixture| match (backend, fixture.extension) {
+            (ArchiveHandleKind::Wrapped7Zip, "rar") => Err(anyhow!("Segmentation fault")),
+            _ => Ok(()),
+        });
+        assert_eq!(
+            report.iter().map(|health| health.backend).collect_vec(),
+            BACKENDS.iter().map(|(backend, _)| *backend).collect_vec()
+        );
+        let broken = report
+            .iter()
+            .filter(|health| !health.is_healthy())
+            .collect_vec();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].backend, ArchiveHandleKind::Wrapped7Zip);
+        assert_eq!(broken[0].failures[0].0, "example-1.rar");
+        assert!(recommendation(&report).is_some());
+        assert!(recommendation(&run_self_test(FIXTURES, |_, _| Ok(()))).is_none());
+    }
+
+    #[test_log::test]
+    fn test_embedded_fixture_extracts_with_builtin_backend() -> Result<()> {
+        FIXTURES
+            .iter()
+            .filter(|fixture| fixture.extension == "7z")
+            .try_for_each(|fixture| extract_fixture(ArchiveHandleKind::SevenzRust2, fixture))
+    }
+}
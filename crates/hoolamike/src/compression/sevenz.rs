@@ -1,7 +1,7 @@
 use {
     super::{ProcessArchive, *},
     crate::{
-        compression::case_insensitive_lookup::CaseInsensitiveArchiveListing,
+        compression::{case_insensitive_lookup::CaseInsensitiveArchiveListing, listing_cache::ListedEntry},
         install_modlist::directives::IteratorTryFlatMapExt,
         path::{Path, PathBuf},
         progress_bars_v2::count_progress_style,
@@ -11,18 +11,35 @@ use {
     sevenz_rust2::{BlockDecoder, Password},
     std::{
         borrow::Cow,
-        collections::BTreeSet,
+        collections::{BTreeMap, BTreeSet},
         fs::File,
         io::{BufWriter, Read},
         ops::Not,
         str::FromStr,
+        sync::atomic::{AtomicU64, Ordering},
     },
     tracing_indicatif::span_ext::IndicatifSpanExt,
 };
 
+/// the memory needed to decode a solid block grows with the block, so blocks unpacking to more than this are handed over to
+/// the 7z binary, which keeps its buffers bounded - big archives would otherwise get the installer killed on machines with
+/// little memory (steam deck)
+pub const DEFAULT_BLOCK_MEMORY_LIMIT: u64 = 512 * 1024 * 1024;
+
+static BLOCK_MEMORY_LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_BLOCK_MEMORY_LIMIT);
+
+/// applies to archives opened from now on
+pub fn configure_block_memory_limit(limit: u64) {
+    BLOCK_MEMORY_LIMIT.store(limit, Ordering::Relaxed);
+}
+
 pub struct SevenZipArchive {
     file: File,
     archive: ::sevenz_rust2::Archive,
+    /// blocks unpacking to more bytes than this are extracted with the 7z binary
+    block_memory_limit: u64,
+    /// needed for the 7z binary, without it every block is decoded in process
+    path: Option<std::path::PathBuf>,
 }
 
 impl SevenZipArchive {
@@ -30,11 +47,114 @@ impl SevenZipArchive {
         ::sevenz_rust2::Archive::read(&mut file, no_password())
             .context("reading archive contents")
             .and_then(|archive| {
-                file.rewind()
-                    .context("rewinding file")
-                    .map(|_| Self { file, archive })
+                file.rewind().context("rewinding file").map(|_| Self {
+                    file,
+                    archive,
+                    block_memory_limit: BLOCK_MEMORY_LIMIT.load(Ordering::Relaxed),
+                    path: None,
+                })
             })
     }
+
+    /// lets blocks over the memory limit be extracted with the 7z binary
+    pub fn with_path(self, path: &std::path::Path) -> Self {
+        Self {
+            path: Some(path.to_owned()),
+            ..self
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_block_memory_limit(self, block_memory_limit: u64) -> Self {
+        Self { block_memory_limit, ..self }
+    }
+}
+
+/// block and size of every entry of the archive
+fn block_indexed_sizes(archive: &::sevenz_rust2::Archive) -> impl Iterator<Item = (Option<usize>, u64)> + '_ {
+    archive
+        .files
+        .iter()
+        .zip(archive.stream_map.file_block_index.iter())
+        .map(|(entry, block_index)| (*block_index, entry.size()))
+}
+
+/// bytes every block unpacks to
+fn unpacked_block_sizes(entries: impl IntoIterator<Item = (Option<usize>, u64)>) -> BTreeMap<usize, u64> {
+    entries
+        .into_iter()
+        .filter_map(|(block_index, size)| block_index.map(|block_index| (block_index, size)))
+        .fold(BTreeMap::new(), |mut sizes, (block_index, size)| {
+            *sizes.entry(block_index).or_default() += size;
+            sizes
+        })
+}
+
+/// files of the archive at `path` along with their sizes and blocks, reads the headers only
+pub fn list_entries(path: &std::path::Path) -> Result<Vec<ListedEntry>> {
+    File::open(path)
+        .with_context(|| format!("opening [{}]", path.display()))
+        .and_then(|mut file| ::sevenz_rust2::Archive::read(&mut file, no_password()).context("reading archive contents"))
+        .map(|archive| {
+            archive
+                .files
+                .iter()
+                .zip(block_indexed_sizes(&archive))
+                .filter(|(entry, _)| entry.is_directory.not())
+                .map(|(entry, (block_index, size))| ListedEntry {
+                    path: entry.name.clone(),
+                    size: Some(size),
+                    block_index,
+                })
+                .collect()
+        })
+}
+
+/// whether any block of a listed archive would be extracted by the 7z binary
+pub fn exceeds_block_memory_limit(entries: &[ListedEntry]) -> bool {
+    let limit = BLOCK_MEMORY_LIMIT.load(Ordering::Relaxed);
+    entries
+        .iter()
+        .map(|entry| (entry.block_index, entry.size.unwrap_or(0)))
+        .pipe(unpacked_block_sizes)
+        .values()
+        .any(|unpacked_size| *unpacked_size > limit)
+}
+
+/// the archive to hand over to the 7z binary when the block is too big to be decoded in process
+fn oversized_block<'path>(block_idx: usize, unpacked_size: u64, limit: u64, path: Option<&'path std::path::Path>) -> Option<&'path std::path::Path> {
+    match unpacked_size > limit {
+        false => None,
+        true => path.or_else(|| {
+            warn!(
+                "block [{block_idx}] unpacks to [{unpacked_size}] bytes (over the limit of [{limit}]), but the archive path is unknown - decoding it in \
+                 process"
+            );
+            None
+        }),
+    }
+}
+
+/// members of a single block, extracted by the 7z binary
+fn extract_with_7z(
+    path: &std::path::Path,
+    block_idx: usize,
+    unpacked_size: u64,
+    lookup: &BTreeSet<PathBuf>,
+) -> Result<Vec<(PathBuf, super::ArchiveFileHandle)>> {
+    tracing::info!(
+        "block [{block_idx}] of [{}] unpacks to [{unpacked_size}] bytes, extracting [{}] entries with 7z",
+        path.display(),
+        lookup.len()
+    );
+    super::wrapped_7zip::WRAPPED_7ZIP
+        .with(|wrapped| wrapped.open_file(path))
+        .and_then(|mut archive| ProcessArchive::get_many_handles(&mut archive, &lookup.iter().collect_vec()))
+        .and_then(|extracted| match extracted.len() == lookup.len() {
+            true => Ok(extracted),
+            false => Err(anyhow::anyhow!("expected [{}] entries, 7z extracted [{}]", lookup.len(), extracted.len())),
+        })
+        .with_context(|| format!("extracting block [{block_idx}] with 7z"))
 }
 
 thread_local! {
@@ -96,6 +216,8 @@ impl ProcessArchive for SevenZipArchive {
                     sevenz_rust2::Error::Io(std::io::Error::other(e), error)
                 }
 
+                let unpacked_block_sizes = unpacked_block_sizes(block_indexed_sizes(&self.archive));
+
                 extract_list
                     .into_iter()
                     .pipe(|extract_list| {
@@ -107,6 +229,12 @@ impl ProcessArchive for SevenZipArchive {
                             .collect_vec()
                             .into_iter()
                             .map(|(block_idx, mut lookup)| {
+                                // blocks are handled strictly in order, entries stream straight into their files
+                                let unpacked_size = unpacked_block_sizes.get(&block_idx).copied().unwrap_or(0);
+                                if let Some(path) = oversized_block(block_idx, unpacked_size, self.block_memory_limit, self.path.as_deref()) {
+                                    return extract_with_7z(path, block_idx, unpacked_size, &lookup)
+                                        .tap_ok(|extracted| extracting_files.pb_inc(extracted.len() as _));
+                                }
                                 let mut output_data = Vec::with_capacity(lookup.len());
                                 let block = BlockDecoder::new(1, block_idx, &self.archive, no_password(), &mut self.file);
 
@@ -340,4 +468,48 @@ mod tests {
                 .map(|_| ())
         })
     }
+
+    #[test_log::test]
+    fn test_blocks_over_the_memory_limit_are_extracted_with_7z() -> Result<()> {
+        static ARCHIVE: &[u8] = include_bytes!("./example-files/data.7z");
+
+        fn contents(extracted: Vec<(PathBuf, super::super::ArchiveFileHandle)>) -> Result<BTreeMap<String, Vec<u8>>> {
+            extracted
+                .into_iter()
+                .map(|(path, mut handle)| {
+                    let mut contents = vec![];
+                    handle
+                        .read_to_end(&mut contents)
+                        .context("reading extracted file")
+                        .map(|_| (path.to_string().to_lowercase(), contents))
+                })
+                .collect()
+        }
+
+        in_tempfile(ARCHIVE, |mut file, path| {
+            let mut in_process = SevenZipArchive::new(file.try_clone()?)?;
+            let paths = in_process.list_paths()?;
+            let paths = paths.iter().collect_vec();
+            let decoded = in_process.get_many_handles(&paths)?;
+            assert!(
+                decoded
+                    .iter()
+                    .all(|(_, handle)| matches!(handle, super::super::ArchiveFileHandle::Zip(_)))
+            );
+            let decoded = contents(decoded)?;
+            file.rewind()?;
+
+            let extracted = SevenZipArchive::new(file)?
+                .with_path(&path.as_original_std_path())
+                .with_block_memory_limit(0)
+                .get_many_handles(&paths)?;
+            assert!(
+                extracted
+                    .iter()
+                    .all(|(_, handle)| matches!(handle, super::super::ArchiveFileHandle::Wrapped7Zip(_)))
+            );
+            assert_eq!(contents(extracted)?, decoded);
+            Ok(())
+        })
+    }
 }
@@ -1,10 +1,30 @@
-use {::wrapped_7zip::Wrapped7Zip, itertools::Itertools, std::num::NonZeroUsize};
+use {
+    crate::progress_bars_v2::count_progress_style,
+    ::wrapped_7zip::Wrapped7Zip,
+    itertools::Itertools,
+    std::num::NonZeroUsize,
+    tracing_indicatif::span_ext::IndicatifSpanExt,
+};
 
 thread_local! {
-    pub static WRAPPED_7ZIP: Arc<Wrapped7Zip> = Arc::new(Wrapped7Zip::find_bin(*crate::consts::TEMP_FILE_DIR).expect("no 7z found, fix your dependencies"));
+    pub static WRAPPED_7ZIP: Arc<Wrapped7Zip> = crate::temp_directory::run_directory()
+        .and_then(Wrapped7Zip::find_bin)
+        .map(Arc::new)
+        .expect("no 7z found, fix your dependencies");
 }
 
 use super::*;
+
+/// external extractions of big archives take a while, 7z reports how far it got in percents - they're forwarded to `span`
+pub fn percentage_progress(span: &tracing::Span) -> impl FnMut(u8) + '_ {
+    span.pb_set_style(&count_progress_style());
+    span.pb_set_length(100);
+    let mut done = 0;
+    move |percentage| {
+        span.pb_inc(percentage.saturating_sub(done) as _);
+        done = done.max(percentage);
+    }
+}
 impl ProcessArchive for ::wrapped_7zip::ArchiveHandle {
     fn list_paths(&mut self) -> Result<Vec<PathBuf>> {
         self.list_files()
@@ -17,6 +37,8 @@ impl ProcessArchive for ::wrapped_7zip::ArchiveHandle {
             .context("listing paths of 7zip archive")
     }
     fn get_many_handles(&mut self, paths: &[&Path]) -> Result<Vec<(PathBuf, super::ArchiveFileHandle)>> {
+        let extracting = info_span!("extracting_with_7z", entries=%paths.len());
+        let on_progress = percentage_progress(&extracting);
         paths
             .iter()
             .map(|p| p.as_original_std_path())
@@ -26,7 +48,16 @@ impl ProcessArchive for ::wrapped_7zip::ArchiveHandle {
                     .iter()
                     .map(|p| p.as_path())
                     .collect_vec()
-                    .pipe_deref(|paths| ::wrapped_7zip::ArchiveHandle::get_many_handles(self, paths, Some(NonZeroUsize::new(1).expect("expected non-zero"))))
+                    .pipe_deref(|paths| {
+                        extracting.in_scope(|| {
+                            ::wrapped_7zip::ArchiveHandle::get_many_handles_with_progress(
+                                self,
+                                paths,
+                                Some(NonZeroUsize::new(1).expect("expected non-zero")),
+                                on_progress,
+                            )
+                        })
+                    })
             })
             .and_then(|output| {
                 output
@@ -1,7 +1,8 @@
 use {
-    crate::{modlist_json::GameName, post_install_fixup::common::Resolution},
+    crate::{exit_codes::ClassifyExt, modlist_json::GameName, post_install_fixup::common::Resolution},
     anyhow::{Context, Result},
     indexmap::IndexMap,
+    schemars::JsonSchema,
     serde::{Deserialize, Serialize},
     std::{
         iter::{empty, once},
@@ -11,22 +12,46 @@ use {
     tracing::{debug, info, warn},
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub mod schema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct NexusConfig {
+    /// personal API key, from https://next.nexusmods.com/settings/api-keys
     pub api_key: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, derivative::Derivative)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
 #[derivative(Default)]
 #[serde(deny_unknown_fields)]
 pub struct DownloadersConfig {
+    /// archives required by the modlist are downloaded to (and looked up in) this directory
     #[derivative(Default(value = "PathBuf::from(\"downloads\")"))]
     pub downloads_directory: PathBuf,
     pub nexus: NexusConfig,
+    /// every request goes through this proxy (`http://host:port`), when it's missing the
+    /// HTTPS_PROXY/HTTP_PROXY environment variables are honored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// hosts (like `mirror.example.org`) whose certificates are not verified, for self-hosted mirrors with broken certificate
+    /// chains. downloaded archives are still checked against the modlist hashes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub insecure_hosts: Vec<String>,
+    /// directories other tools downloaded archives to (like the downloads folder of a windows Wabbajack install), only
+    /// read from - archives missing from downloads_directory are looked up there and linked (or copied) over when they check out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_search_directories: Vec<PathBuf>,
+    /// order archives are downloaded in - `largest-first` starts the few biggest ones right away and fills the other slots
+    /// smallest first, `smallest-first` and `as-listed` (the order of the modlist) are there too
+    #[serde(default)]
+    pub schedule: crate::install_modlist::downloads::DownloadSchedule,
+    /// how archives already in downloads_directory are checked - `cached` hashes them again only when their size or
+    /// modification time changed, `quick` trusts the ones hashed before as long as the size matches, `full` always hashes
+    #[serde(default)]
+    pub verification: crate::install_modlist::download_cache::Verification,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, derivative::Derivative)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
 #[serde(deny_unknown_fields)]
 pub struct GameConfig {
     pub root_directory: PathBuf,
@@ -39,14 +64,78 @@ fn join_default_path(segments: impl IntoIterator<Item = &'static str>) -> PathBu
         .fold(PathBuf::new(), |acc, next| acc.join(next))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, derivative::Derivative)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
 #[derivative(Default)]
 #[serde(deny_unknown_fields)]
 pub struct InstallationConfig {
+    /// modlist (.wabbajack) file to install
     #[derivative(Default(value = "join_default_path([\"path\",\"to\",\"file.wabbajack\" ])"))]
     pub wabbajack_file_path: PathBuf,
     #[derivative(Default(value = "PathBuf::from(\"installed\")"))]
     pub installation_path: PathBuf,
+    /// copies the .wabbajack file next to the downloads before installing, useful when it lives on a removable drive
+    #[serde(default)]
+    pub copy_wabbajack_locally: bool,
+}
+
+/// url schemes people paste as `installation_path` hoping for a remote install
+const REMOTE_SCHEMES: &[&str] = &["ssh", "sftp"];
+
+/// installs are written through the local filesystem only, left alone `ssh://user@host/path` would quietly become a local
+/// directory called `ssh:`
+pub fn ensure_local_installation_path(installation_path: &Path) -> Result<()> {
+    installation_path
+        .to_str()
+        .and_then(|path| url::Url::parse(path).ok())
+        .filter(|url| REMOTE_SCHEMES.contains(&url.scheme()))
+        .map_or(Ok(()), |url| {
+            anyhow::bail!(
+                "installation_path [{url}] points at a remote machine, remote installation directories are not supported - mount it locally (NFS, sshfs) and \
+                 point installation_path at the mount instead"
+            )
+        })
+}
+
+/// paths given on the command line, they take precedence over the config (which is left as it is). relative ones are
+/// taken relative to the current directory, not to the config
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct PathOverrides {
+    /// installs to this directory instead of installation.installation_path
+    #[arg(long)]
+    pub installation_path: Option<PathBuf>,
+    /// downloads archives to (and looks them up in) this directory instead of downloaders.downloads_directory
+    #[arg(long)]
+    pub downloads_path: Option<PathBuf>,
+}
+
+impl PathOverrides {
+    fn resolve(flag: &str, path: &Path) -> Result<PathBuf> {
+        std::path::absolute(path)
+            .with_context(|| format!("resolving --{flag} [{}]", path.display()))
+            .tap_ok(|path| info!("--{flag} overrides the config: [{}]", path.display()))
+    }
+
+    /// config with the overridden paths put in, done before entering the project root so relative paths keep pointing
+    /// where they did when the command was typed
+    pub fn apply(self, config: HoolamikeConfig) -> Result<HoolamikeConfig> {
+        let Self {
+            installation_path,
+            downloads_path,
+        } = self;
+        Ok(config)
+            .and_then(|config| match installation_path {
+                Some(path) => ensure_local_installation_path(&path)
+                    .and_then(|_| Self::resolve("installation-path", &path))
+                    .map(|path| config.tap_mut(|config| config.installation.installation_path = path)),
+                None => Ok(config),
+            })
+            .and_then(|config| match downloads_path {
+                Some(path) => Self::resolve("downloads-path", &path).map(|path| config.tap_mut(|config| config.downloaders.downloads_directory = path)),
+                None => Ok(config),
+            })
+            .context("applying path overrides")
+            .classify(crate::exit_codes::Failure::Config)
+    }
 }
 
 pub type GamesConfig = IndexMap<GameName, GameConfig>;
@@ -56,32 +145,148 @@ fn default_games_config() -> GamesConfig {
 }
 
 #[serde_with::serde_as]
-#[derive(Debug, Clone, Serialize, Deserialize, derivative::Derivative)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
 #[derivative(Default)]
 #[serde(deny_unknown_fields)]
 pub struct FixupConfig {
     #[derivative(Default(value = "Resolution {x: 1280, y: 800}"))]
     #[serde_as(as = "serde_with::DisplayFromStr")]
     pub game_resolution: Resolution,
+    /// points ModOrganizer.ini (game path, download/base directories) at the configured locations, translated to wine paths
+    #[derivative(Default(value = "true"))]
+    #[serde(default = "default_true")]
+    pub fix_mod_organizer_paths: bool,
+    /// borderless window, applied to the game's prefs ini
+    #[derivative(Default(value = "true"))]
+    #[serde(default = "default_true")]
+    pub borderless: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// extra ini settings, keyed by file name (e.g. 'Skyrim.ini'), entries are 'Section.key: value'.
+    /// missing files are created, existing ones are edited in place (a .bak is written once)
+    #[serde(default)]
+    pub ini_overrides: IndexMap<String, IndexMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct ExtrasConfig {
     pub tale_of_two_wastelands: Option<crate::extensions::tale_of_two_wastelands_installer::ExtensionConfig>,
     pub texconv_wine: Option<crate::extensions::texconv_wine::ExtensionConfig>,
+    pub prefix_bootstrap: Option<crate::extensions::prefix_bootstrap::ExtensionConfig>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, derivative::Derivative)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
 #[derivative(Default)]
 #[serde(deny_unknown_fields)]
 pub struct HoolamikeConfig {
     pub downloaders: DownloadersConfig,
     pub installation: InstallationConfig,
+    /// keyed by the game name used by wabbajack (e.g. 'SkyrimSpecialEdition', 'FalloutNewVegas')
     #[derivative(Default(value = "default_games_config()"))]
     pub games: GamesConfig,
     pub fixup: Option<FixupConfig>,
     pub extras: Option<ExtrasConfig>,
+    /// worker pools used while handling directives, missing values are picked based on the hardware
+    #[serde(default)]
+    pub concurrency: crate::install_modlist::directives::concurrency::ConcurrencyConfig,
+    /// named sets of debug flags, selected with 'hoolamike install --preset <name>'
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub debug_presets: crate::debug_presets::DebugPresets,
+    #[serde(default, skip_serializing_if = "AdvancedConfig::is_default")]
+    pub advanced: AdvancedConfig,
+}
+
+/// rendered (commented out) at the end of the default config
+pub const ADVANCED_DOCS: &str = "\
+# tuning knobs nobody should need to touch, uncomment the ones you do need. keys this version doesn't know are ignored with a warning
+# advanced:
+#   # where temporary files go, defaults to '.hoolamike-tmp' inside installation_path
+#   temp_directory: /path/to/big/disk/tmp
+#   # solid 7z blocks unpacking to more than this many MiB are extracted by the 7z binary instead, lower it when running out of memory
+#   sevenz_block_memory_limit_mib: 512
+#   # file handles kept open at once while handling directives, defaults to 40 per cpu - lower it on 'too many open files'
+#   max_open_files: 640
+#   # outputs of an earlier run checked against their hashes at the same time when resuming, defaults to the number of cpus
+#   resume_check_workers: 16
+#   # when resuming, textures converted by an earlier run are taken as done if their size and DDS header match
+#   resume_trusts_converted_textures: true
+#   # textures texconv could not convert (or all of them, without extras.texconv_wine) are recompressed in process
+#   texture_recompression_fallback: true
+";
+
+/// all of it is optional, omitting the section (or any of its keys) keeps the defaults. unlike the rest of the config, keys
+/// it doesn't know are only warned about - knobs come and go between versions, and a config shouldn't stop working because of that
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
+#[derivative(Default)]
+pub struct AdvancedConfig {
+    /// where temporary files (extracted archives, recompressed textures, wine prefixes) go, every run uses a directory of its own
+    /// in there. defaults to '.hoolamike-tmp' inside installation_path, so that they land on the same (usually big) filesystem
+    pub temp_directory: Option<PathBuf>,
+    /// solid 7z blocks unpacking to more than this many MiB are extracted by the 7z binary instead of being decoded in process,
+    /// which keeps memory use bounded. defaults to 512, lower it if the installer gets killed for running out of memory
+    pub sevenz_block_memory_limit_mib: Option<u64>,
+    /// file handles kept open at once while handling directives (archives being read, entries extracted into temp files).
+    /// defaults to 40 per cpu, lower it when the installation fails with 'too many open files' and the limit can't be raised
+    pub max_open_files: Option<usize>,
+    /// outputs of an earlier run checked against their hashes at the same time when resuming, defaults to the number of cpus
+    pub resume_check_workers: Option<usize>,
+    /// when resuming, textures converted by an earlier run are taken as done if their size and DDS header match what the
+    /// directive asks for (their hash never matches the one wabbajack expects). false converts all of them again
+    #[derivative(Default(value = "true"))]
+    #[serde(default = "default_true")]
+    pub resume_trusts_converted_textures: bool,
+    /// textures texconv could not convert (or all of them, without extras.texconv_wine) are recompressed in process with
+    /// slower encoders. false fails them instead
+    #[derivative(Default(value = "true"))]
+    #[serde(default = "default_true")]
+    pub texture_recompression_fallback: bool,
+}
+
+impl AdvancedConfig {
+    fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    fn known_keys() -> Vec<String> {
+        schemars::schema_for!(AdvancedConfig)
+            .pipe_ref(serde_json::to_value)
+            .ok()
+            .and_then(|schema| {
+                schema
+                    .get("properties")
+                    .and_then(serde_json::Value::as_object)
+                    .map(|properties| properties.keys().cloned().collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// keys of the `advanced` section of a config (as plain yaml) this version doesn't know
+    pub fn unknown_keys(config: &serde_yaml::Value) -> Vec<String> {
+        let known = Self::known_keys();
+        config
+            .get("advanced")
+            .and_then(serde_yaml::Value::as_mapping)
+            .into_iter()
+            .flat_map(|advanced| advanced.keys())
+            .map(|key| match key.as_str() {
+                Some(key) => key.to_string(),
+                None => format!("{key:?}"),
+            })
+            .filter(|key| !known.contains(key))
+            .collect()
+    }
+}
+
+/// `0` wouldn't do anything, so it's most likely a mistake
+fn at_least_one(key: &str, value: Option<u64>) -> Option<String> {
+    value
+        .filter(|value| *value == 0)
+        .map(|_| format!("'{key}' must be at least 1 (or left empty for the default)"))
 }
 
 pub static CONFIG_FILE_NAME: &str = "hoolamike.yaml";
@@ -102,6 +307,14 @@ impl HoolamikeConfig {
         Self::default()
             .pipe_ref(serde_yaml::to_string)
             .context("serialization failed")
+            .map(|config| {
+                config.replacen(
+                    "\nconcurrency:\n",
+                    &format!("\n{}concurrency:\n", crate::install_modlist::directives::concurrency::CONCURRENCY_DOCS),
+                    1,
+                )
+            })
+            .map(|config| format!("{config}{ADVANCED_DOCS}"))
             .map(|config| {
                 format!(
                     "\n# default {CONFIG_FILE_NAME} file, generated using CLI interface with {} {} on {} \n# edit it according to your needs:\n{config}",
@@ -111,6 +324,44 @@ impl HoolamikeConfig {
                 )
             })
     }
+    /// values out of their ranges, installation only warns about them (zero workers are bumped to one)
+    pub fn problems(&self) -> Vec<String> {
+        let Self { concurrency, advanced, .. } = self;
+        let usize_to_u64 = |value: Option<usize>| value.map(|value| value as u64);
+        [
+            ("concurrency.io_workers", usize_to_u64(concurrency.io_workers)),
+            ("concurrency.cpu_workers", usize_to_u64(concurrency.cpu_workers)),
+            ("concurrency.extraction_workers", usize_to_u64(concurrency.extraction_workers)),
+            ("concurrency.hashing_workers", usize_to_u64(concurrency.hashing_workers)),
+            ("concurrency.fs_workers", usize_to_u64(concurrency.fs_workers)),
+            ("concurrency.max_blocking_threads", usize_to_u64(concurrency.max_blocking_threads)),
+            ("advanced.sevenz_block_memory_limit_mib", advanced.sevenz_block_memory_limit_mib),
+            ("advanced.max_open_files", usize_to_u64(advanced.max_open_files)),
+            ("advanced.resume_check_workers", usize_to_u64(advanced.resume_check_workers)),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| at_least_one(key, value))
+        .collect()
+    }
+
+    /// parses the config, keys of the `advanced` section this version doesn't know are warned about rather than rejected
+    pub fn parse(config: &str) -> Result<Self> {
+        serde_yaml::from_str::<Self>(config)
+            .map_err(schema::with_suggestion)
+            .tap_ok(|_| {
+                serde_yaml::from_str::<serde_yaml::Value>(config)
+                    .map(|config| AdvancedConfig::unknown_keys(&config))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .for_each(
+                        |key| match schema::did_you_mean(&key, AdvancedConfig::known_keys().iter().map(String::as_str)) {
+                            Some(known) => warn!("unknown key 'advanced.{key}' is ignored, did you mean '{known}'?"),
+                            None => warn!("unknown key 'advanced.{key}' is ignored"),
+                        },
+                    )
+            })
+    }
+
     pub fn read(path: &Path) -> Result<(PathBuf, Self)> {
         path.exists()
             .then(|| path.to_owned())
@@ -119,13 +370,138 @@ impl HoolamikeConfig {
             .and_then(|config_path| {
                 std::fs::read_to_string(&config_path)
                     .context("reading file")
-                    .and_then(|config| serde_yaml::from_str::<Self>(&config).context("parsing config file"))
+                    .and_then(|config| Self::parse(&config).context("parsing config file"))
+                    .tap_ok(|config| {
+                        config
+                            .problems()
+                            .into_iter()
+                            .for_each(|problem| warn!("{problem}"))
+                    })
                     .map(|config| (config_path, config))
             })
             .with_context(|| format!("getting [{CONFIG_FILE_NAME}]"))
+            .classify(crate::exit_codes::Failure::Config)
             .tap_err(|e| warn!("{e:?}"))
             .tap_ok(|config| {
                 debug!("{config:?}");
             })
     }
 }
+
+/// the mapping under given key, created when it's missing (or left empty)
+pub fn yaml_section<'a>(config: &'a mut serde_yaml::Mapping, key: &str) -> Result<&'a mut serde_yaml::Mapping> {
+    config
+        .entry(key.into())
+        .or_insert(serde_yaml::Value::Null)
+        .pipe(|section| {
+            if section.is_null() {
+                *section = serde_yaml::Mapping::new().into();
+            }
+            section.as_mapping_mut()
+        })
+        .with_context(|| format!("'{key}' is not a mapping"))
+}
+
+/// edits the config as plain yaml so that values hoolamike doesn't touch stay exactly as the user wrote them
+pub fn edit_config(config: &str, edit: impl FnOnce(&mut serde_yaml::Mapping) -> Result<()>) -> Result<String> {
+    serde_yaml::from_str::<serde_yaml::Value>(config)
+        .context("parsing config")
+        .and_then(|mut config| {
+            config
+                .as_mapping_mut()
+                .context("config is not a mapping")
+                .and_then(edit)
+                .map(|_| config)
+        })
+        .and_then(|config| {
+            serde_yaml::from_value::<HoolamikeConfig>(config.clone())
+                .context("config is not valid after editing")
+                .and_then(|_| serde_yaml::to_string(&config).context("serializing config"))
+        })
+}
+
+/// comments don't survive [edit_config], so the original is kept next to the config (e.g. 'hoolamike.yaml.bak')
+pub fn edit_config_file(config_path: &Path, edit: impl FnOnce(&str) -> Result<String>) -> Result<()> {
+    std::fs::read_to_string(config_path)
+        .with_context(|| format!("reading [{}]", config_path.display()))
+        .and_then(|original| {
+            edit(&original).and_then(|updated| {
+                config_path
+                    .as_os_str()
+                    .to_owned()
+                    .tap_mut(|backup| backup.push(".bak"))
+                    .pipe(PathBuf::from)
+                    .pipe(|backup| {
+                        std::fs::write(&backup, &original)
+                            .with_context(|| format!("backing up the config to [{}]", backup.display()))
+                            .tap_ok(|_| info!("previous config was backed up to [{}]", backup.display()))
+                    })
+                    .and_then(|_| crate::utils::write_atomically(config_path, updated))
+            })
+        })
+        .with_context(|| format!("editing [{}]", config_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_partial_advanced_section_keeps_the_defaults() -> Result<()> {
+        let default = HoolamikeConfig::write_default()?;
+        assert_eq!(
+            HoolamikeConfig::parse(&default)?.advanced,
+            AdvancedConfig::default(),
+            "the section is commented out"
+        );
+
+        let advanced = format!("{default}advanced:\n  max_open_files: 64\n  resume_trusts_converted_textures: false\n")
+            .pipe_deref(HoolamikeConfig::parse)?
+            .advanced;
+        assert_eq!(
+            advanced,
+            AdvancedConfig {
+                max_open_files: Some(64),
+                resume_trusts_converted_textures: false,
+                ..Default::default()
+            }
+        );
+        assert!(advanced.texture_recompression_fallback);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_unknown_advanced_keys_are_only_warned_about() -> Result<()> {
+        let config = format!(
+            "{}advanced:\n  max_open_file: 64\n  temp_directory: /mnt/big/tmp\n",
+            HoolamikeConfig::write_default()?
+        );
+        assert_eq!(HoolamikeConfig::parse(&config)?.advanced.temp_directory, Some(PathBuf::from("/mnt/big/tmp")));
+        assert_eq!(AdvancedConfig::unknown_keys(&serde_yaml::from_str(&config)?), vec!["max_open_file".to_string()]);
+        // the rest of the config stays strict
+        assert!(HoolamikeConfig::parse(&config.replace("downloads_directory", "downloads_directoy")).is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_out_of_range_values_are_problems() -> Result<()> {
+        let config = format!(
+            "{}advanced:\n  resume_check_workers: 0\n",
+            HoolamikeConfig::write_default()?.replace("cpu_workers: null", "cpu_workers: 0")
+        );
+        let problems = HoolamikeConfig::parse(&config)?.problems();
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.contains("concurrency.cpu_workers"))
+        );
+        assert!(
+            problems
+                .iter()
+                .any(|problem| problem.contains("advanced.resume_check_workers"))
+        );
+        assert!(HoolamikeConfig::default().problems().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! JSON Schema of [HoolamikeConfig], so that editors with YAML language server can autocomplete and validate the config.
+//! Add `# yaml-language-server: $schema=<path to hoolamike.schema.json>` at the top of `hoolamike.yaml` to use it.
+
+use {
+    super::HoolamikeConfig,
+    anyhow::{Context, Result},
+    serde_json::Value,
+    std::collections::BTreeSet,
+    tap::prelude::*,
+};
+
+pub const SCHEMA_FILE_NAME: &str = "hoolamike.schema.json";
+
+pub fn generate() -> Result<String> {
+    schemars::schema_for!(HoolamikeConfig)
+        .pipe_ref(serde_json::to_string_pretty)
+        .context("serializing config schema")
+}
+
+/// every property name appearing anywhere in the schema, including the extras sub-schemas
+fn property_names(schema: &Value) -> BTreeSet<String> {
+    match schema {
+        Value::Object(object) => object
+            .get("properties")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flat_map(|properties| properties.keys().cloned())
+            .chain(object.values().flat_map(property_names))
+            .collect(),
+        Value::Array(items) => items.iter().flat_map(property_names).collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+fn known_properties() -> Result<BTreeSet<String>> {
+    schemars::schema_for!(HoolamikeConfig)
+        .pipe_ref(serde_json::to_value)
+        .context("serializing config schema")
+        .map(|schema| property_names(&schema))
+}
+
+/// closest known property, as long as it's close enough to be a typo rather than a different key altogether
+pub(super) fn did_you_mean<'a>(unknown: &str, known: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (unknown.chars().count() / 3).clamp(1, 3);
+    known
+        .into_iter()
+        .map(|known| (strsim::damerau_levenshtein(unknown, known), known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// serde only lists the fields expected at the current level, this points at the one that was most likely meant
+pub fn with_suggestion(error: serde_yaml::Error) -> anyhow::Error {
+    let suggestion = error
+        .to_string()
+        .split_once("unknown field `")
+        .and_then(|(_, rest)| rest.split_once('`'))
+        .map(|(unknown, _)| unknown.to_string())
+        .and_then(|unknown| {
+            known_properties().ok().and_then(|known| {
+                did_you_mean(&unknown, known.iter().map(String::as_str)).map(|known| format!("unknown field `{unknown}`, did you mean `{known}`?"))
+            })
+        });
+    match suggestion {
+        Some(suggestion) => anyhow::Error::new(error).context(suggestion),
+        None => anyhow::Error::new(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::path::Path};
+
+    #[test_log::test]
+    fn test_schema_snapshot_is_up_to_date() -> Result<()> {
+        let snapshot_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../..")
+            .join(SCHEMA_FILE_NAME);
+        let generated = generate()?;
+        if std::env::var_os("HOOLAMIKE_UPDATE_SCHEMA").is_some() {
+            std::fs::write(&snapshot_path, format!("{generated}\n")).context("updating schema snapshot")?;
+        }
+        let snapshot = std::fs::read_to_string(&snapshot_path).context("reading schema snapshot")?;
+        assert!(
+            generated.trim_end() == snapshot.trim_end(),
+            "{SCHEMA_FILE_NAME} is out of date, regenerate it with 'HOOLAMIKE_UPDATE_SCHEMA=1 cargo test' or 'hoolamike config schema > {SCHEMA_FILE_NAME}'"
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_schema_covers_extras() -> Result<()> {
+        let known = known_properties()?;
+        [
+            "downloads_directory",
+            "wabbajack_file_path",
+            "path_to_ttw_mpi_file",
+            "texconv_path",
+            "components",
+            "skip_kind",
+        ]
+        .iter()
+        .for_each(|property| assert!(known.contains(*property), "[{property}] is missing from the schema"));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_did_you_mean() {
+        let known = ["downloads_directory", "nexus", "installation_path"];
+        assert_eq!(did_you_mean("downloads_directoy", known), Some("downloads_directory"));
+        assert_eq!(did_you_mean("nexsu", known), Some("nexus"));
+        assert_eq!(did_you_mean("something_else_entirely", known), None);
+    }
+
+    #[test_log::test]
+    fn test_unknown_key_gets_a_suggestion() {
+        let config = HoolamikeConfig::default()
+            .pipe_ref(serde_yaml::to_string)
+            .unwrap()
+            .replace("downloads_directory", "downloads_directoy");
+        let error = serde_yaml::from_str::<HoolamikeConfig>(&config)
+            .map_err(with_suggestion)
+            .err()
+            .expect("typo must be rejected");
+        assert!(format!("{error:?}").contains("did you mean `downloads_directory`?"), "{error:?}");
+    }
+}
@@ -0,0 +1,154 @@
+//! named sets of [DebugHelpers] stored in the config, so that iterating on a broken modlist
+//! doesn't require retyping the same flags over and over
+
+use {
+    crate::{DebugHelpers, modlist_json::DirectiveKind},
+    anyhow::{Context, Result},
+    indexmap::IndexMap,
+    itertools::Itertools,
+    schemars::JsonSchema,
+    serde::{Deserialize, Serialize},
+    tap::prelude::*,
+};
+
+/// passing this as preset name prints the available presets instead of installing
+pub const LIST_PRESETS: &str = "list";
+
+/// every field is optional - missing ones are taken from the command line
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DebugPreset {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_verify_and_downloads: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_from_directive: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_kind: Option<Vec<DirectiveKind>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<Vec<String>>,
+}
+
+pub type DebugPresets = IndexMap<String, DebugPreset>;
+
+impl DebugHelpers {
+    /// flags given explicitly on the command line win over the ones from the preset
+    pub fn with_preset(self, presets: &DebugPresets) -> Result<Self> {
+        let Some(name) = self.preset.as_deref() else {
+            return Ok(self);
+        };
+        presets
+            .get(name)
+            .with_context(|| format!("no debug preset named [{name}], available presets: [{}]", presets.keys().join(", ")))
+            .map(
+                |DebugPreset {
+                     skip_verify_and_downloads,
+                     start_from_directive,
+                     skip_kind,
+                     contains,
+                 }| Self {
+                    skip_verify_and_downloads: self.skip_verify_and_downloads || skip_verify_and_downloads.unwrap_or_default(),
+                    start_from_directive: self
+                        .start_from_directive
+                        .clone()
+                        .or_else(|| start_from_directive.clone()),
+                    skip_kind: match self.skip_kind.is_empty() {
+                        true => skip_kind.clone().unwrap_or_default(),
+                        false => self.skip_kind.clone(),
+                    },
+                    contains: match self.contains.is_empty() {
+                        true => contains.clone().unwrap_or_default(),
+                        false => self.contains.clone(),
+                    },
+                    preset: self.preset.clone(),
+                },
+            )
+            .tap_ok(|expanded| tracing::info!(preset=%name, ?expanded, "expanded debug preset"))
+    }
+}
+
+pub fn list_presets(presets: &DebugPresets) -> Result<String> {
+    match presets.is_empty() {
+        true => Ok("no debug presets defined, add them to the config under 'debug_presets'".to_string()),
+        false => serde_yaml::to_string(presets).context("serializing debug presets"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presets() -> DebugPresets {
+        serde_yaml::from_str(
+            r#"
+texture-debug:
+  skip_verify_and_downloads: true
+  skip_kind: [FromArchive, InlineFile]
+  contains: [".dds"]
+resume:
+  start_from_directive: "abc"
+"#,
+        )
+        .unwrap()
+    }
+
+    fn cli(preset: &str) -> DebugHelpers {
+        DebugHelpers {
+            preset: Some(preset.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test_log::test]
+    fn test_preset_expansion() -> Result<()> {
+        let expanded = cli("texture-debug").with_preset(&presets())?;
+        assert!(expanded.skip_verify_and_downloads);
+        assert_eq!(expanded.skip_kind, [DirectiveKind::FromArchive, DirectiveKind::InlineFile]);
+        assert_eq!(expanded.contains, [".dds"]);
+        assert_eq!(expanded.start_from_directive, None);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_cli_flags_override_preset() -> Result<()> {
+        let expanded = DebugHelpers {
+            contains: vec!["Skyrim.esm".to_string()],
+            start_from_directive: Some("xyz".to_string()),
+            ..cli("texture-debug")
+        }
+        .with_preset(&presets())?;
+        assert!(expanded.skip_verify_and_downloads);
+        assert_eq!(expanded.skip_kind, [DirectiveKind::FromArchive, DirectiveKind::InlineFile]);
+        assert_eq!(expanded.contains, ["Skyrim.esm"]);
+        assert_eq!(expanded.start_from_directive.as_deref(), Some("xyz"));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_unknown_preset() {
+        let error = cli("texture-degub")
+            .with_preset(&presets())
+            .err()
+            .expect("unknown preset must fail");
+        assert!(format!("{error:?}").contains("texture-debug, resume"));
+    }
+
+    #[test_log::test]
+    fn test_no_preset_keeps_flags() -> Result<()> {
+        let expanded = DebugHelpers {
+            skip_verify_and_downloads: true,
+            ..Default::default()
+        }
+        .with_preset(&presets())?;
+        assert!(expanded.skip_verify_and_downloads);
+        assert!(expanded.skip_kind.is_empty());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_list_presets() -> Result<()> {
+        let listed = list_presets(&presets())?;
+        assert!(listed.contains("texture-debug:"));
+        assert!(listed.contains("start_from_directive: abc"));
+        Ok(())
+    }
+}
@@ -1,15 +1,22 @@
 use {
     crate::{
-        downloaders::wabbajack_cdn::WabbajackCDNDownloader,
-        install_modlist::downloads::stream_file_validate,
+        downloaders::wabbajack_cdn::{WabbajackCDNDownloader, fetch_part},
+        install_modlist::downloads::HTTP_CLIENT,
         modlist_json::{HumanUrl, WabbajackCDNDownloaderState},
         utils::PathFileNameOrEmpty,
     },
     anyhow::{Context, Result},
-    case_insensitive_path::PathExistsUtf8Ext,
     clap::Args,
     futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt},
-    std::{future::ready, num::NonZeroUsize, path::PathBuf, sync::Arc},
+    std::{
+        future::ready,
+        num::NonZeroUsize,
+        path::PathBuf,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    },
     tap::{Pipe, TapFallible},
     tracing::info,
 };
@@ -24,7 +31,13 @@ pub struct CommandArgs {
 
 impl CommandArgs {
     pub async fn download(self) -> Result<PathBuf> {
+        self.download_with_progress(|_, _| {}).await
+    }
+
+    /// `on_part` is called with the number of finished parts and the number of all parts
+    pub async fn download_with_progress(self, on_part: impl Fn(usize, usize) + Clone + Send + 'static) -> Result<PathBuf> {
         let Self { url, to, download_concurrency } = self;
+        let finished_parts = Arc::new(AtomicUsize::new(0));
         let _ = std::fs::File::options()
             .create(true)
             .write(true)
@@ -40,14 +53,16 @@ impl CommandArgs {
 
         WabbajackCDNDownloader::prepare_download(WabbajackCDNDownloaderState { url: url.clone() })
             .map(|r| r.context("fetching the source urls"))
-            .and_then(|urls| {
-                let chunk_count = urls.len();
-                urls.pipe(futures::stream::iter)
+            .and_then(|parts| {
+                let chunk_count = parts.len();
+                on_part(0, chunk_count);
+                parts
+                    .pipe(futures::stream::iter)
                     .enumerate()
                     .map({
-                        cloned![to, temp_directory];
-                        move |(idx, url)| {
-                            cloned![to, temp_directory];
+                        cloned![to, temp_directory, on_part, finished_parts];
+                        move |(idx, part)| {
+                            cloned![to, temp_directory, on_part, finished_parts];
                             async move {
                                 to.map_file_stem(|s| format!("{s}--{idx}"))
                                     .context("bad output filename")
@@ -58,17 +73,24 @@ impl CommandArgs {
                                             .pipe(|name| temp_directory.path().join(name))
                                     })
                                     .pipe(ready)
-                                    .map_ok(|output_path| (url, output_path, idx))
-                                    .and_then(|(url, output_path, idx)| {
-                                        output_path
-                                            .utf8_platform_path()
-                                            .pipe(ready)
-                                            .and_then(|output_path| stream_file_validate(url, output_path, None))
-                                            .map(move |r| r.with_context(|| format!("downloading part {idx}")))
-                                            .map_ok(move |output| {
-                                                info!("downloaded chunk {idx}/{chunk_count}");
-                                                (idx, output)
-                                            })
+                                    .map_ok(|output_path| (part, output_path, idx))
+                                    .and_then(|(part, output_path, idx)| {
+                                        // a part which stays corrupted fails the whole download right away, the ones in flight are dropped
+                                        async move {
+                                            fetch_part(&HTTP_CLIENT, &part)
+                                                .and_then(|contents| {
+                                                    tokio::fs::write(&output_path, contents)
+                                                        .map(|r| r.with_context(|| format!("writing [{}]", output_path.display())))
+                                                })
+                                                .await
+                                                .map(|_| output_path)
+                                        }
+                                        .map(move |r| r.with_context(|| format!("downloading part {idx}")))
+                                        .map_ok(move |output| {
+                                            info!("downloaded chunk {idx}/{chunk_count}");
+                                            on_part(finished_parts.fetch_add(1, Ordering::SeqCst) + 1, chunk_count);
+                                            (idx, output)
+                                        })
                                     })
                                     .await
                             }
@@ -93,7 +115,7 @@ impl CommandArgs {
                                 .and_then(async |mut output_file| {
                                     for (idx, source) in files {
                                         tokio::fs::File::open(&source)
-                                            .map(|r| r.with_context(|| format!("opening chunk file at {}", source)))
+                                            .map(|r| r.with_context(|| format!("opening chunk file at {}", source.display())))
                                             .and_then(async |mut source| {
                                                 tokio::io::copy(&mut source, &mut output_file)
                                                     .map(|r| r.with_context(|| format!("merging chunk [{idx}]")))
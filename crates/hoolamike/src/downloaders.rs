@@ -1,7 +1,8 @@
 use {
-    crate::modlist_json::{ArchiveDescriptor, HumanUrl},
-    case_insensitive_path::ExistingPathBuf,
+    crate::modlist_json::{ArchiveDescriptor, HttpHeader, HumanUrl},
+    case_insensitive_path::{CaseInsensitivePathBuf, ExistingPathBuf},
     typed_path::Utf8PlatformPathBuf,
+    wabbajack_cdn::CdnPart,
 };
 
 pub mod gamefile_source_downloader;
@@ -19,13 +20,17 @@ pub struct WithArchiveDescriptor<T> {
     pub descriptor: ArchiveDescriptor,
 }
 
-pub type MergeDownloadTask = WithArchiveDescriptor<(Vec<HumanUrl>, Utf8PlatformPathBuf)>;
-pub type DownloadTask = WithArchiveDescriptor<(HumanUrl, Utf8PlatformPathBuf)>;
+pub type MergeDownloadTask = WithArchiveDescriptor<(Vec<CdnPart>, Utf8PlatformPathBuf)>;
+/// headers go along with the request
+pub type DownloadTask = WithArchiveDescriptor<(HumanUrl, Utf8PlatformPathBuf, Vec<HttpHeader>)>;
 pub type CopyFileTask = WithArchiveDescriptor<(ExistingPathBuf, Utf8PlatformPathBuf)>;
+/// an entry of a local archive (one of the game's own) - the hash is checked while it's extracted
+pub type ExtractEntryTask = WithArchiveDescriptor<(ExistingPathBuf, CaseInsensitivePathBuf, Utf8PlatformPathBuf)>;
 
 #[derive(Debug, Clone, derive_more::From)]
 pub enum SyncTask {
     MergeDownload(MergeDownloadTask),
     Download(DownloadTask),
     Copy(CopyFileTask),
+    Extract(ExtractEntryTask),
 }
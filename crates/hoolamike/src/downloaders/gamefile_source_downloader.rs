@@ -1,17 +1,96 @@
+//! game files are copied out of the game directory - or extracted, when the modlist points into one of the game's own
+//! archives (`Data\Fallout - Textures.bsa\textures\foo.dds`)
+
 use {
     crate::{
+        compression::{ArchiveHandle, ProcessArchive},
         config_file::{GameConfig, GamesConfig},
-        install_modlist::download_cache::validate_hash_wabbajack,
+        game_version::{GameFileHashMismatch, mismatch_hint},
+        install_modlist::download_cache::{to_base_64_from_u64, validate_hash_wabbajack},
         modlist_json::{GameFileSourceState, GameName},
+        read_wrappers::ReadExt,
     },
     anyhow::{Context, Result},
-    case_insensitive_path::{ExistingPathBuf, PathExistsUtf8Ext},
+    case_insensitive_path::{CaseInsensitivePathBuf, ExistingPathBuf, PathExistsUtf8Ext},
     futures::TryFutureExt,
     indexmap::IndexMap,
-    std::future::ready,
+    itertools::Itertools,
+    std::{future::ready, str::FromStr},
     tap::prelude::*,
+    typed_path::Utf8PlatformPathBuf,
 };
 
+const GAME_ARCHIVE_EXTENSIONS: &[&str] = &["bsa", "ba2"];
+
+/// where the bytes of a game file come from
+#[derive(Debug, Clone)]
+pub enum GameFile {
+    /// checked against the hash already
+    Plain(ExistingPathBuf),
+    /// the hash is checked while the entry is extracted
+    ArchiveEntry { archive: ExistingPathBuf, entry: CaseInsensitivePathBuf },
+}
+
+/// the archive and the path within it, when a component before the last one is a bethesda archive
+pub fn split_archive_entry(game_file: &CaseInsensitivePathBuf) -> Result<Option<(CaseInsensitivePathBuf, CaseInsensitivePathBuf)>> {
+    let components = game_file
+        .as_original_path()
+        .as_str()
+        .split(['\\', '/'])
+        .filter(|component| !component.is_empty())
+        .collect_vec();
+    components
+        .iter()
+        .take(components.len().saturating_sub(1))
+        .position(|component| {
+            component.rsplit_once('.').is_some_and(|(_, extension)| {
+                GAME_ARCHIVE_EXTENSIONS
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(extension))
+            })
+        })
+        .map(|archive| {
+            let (archive, entry) = components.split_at(archive + 1);
+            CaseInsensitivePathBuf::from_str(&archive.join("\\"))
+                .and_then(|archive| CaseInsensitivePathBuf::from_str(&entry.join("\\")).map(|entry| (archive, entry)))
+        })
+        .transpose()
+        .with_context(|| format!("splitting [{game_file}] into an archive and an entry"))
+}
+
+/// writes `entry` of a game archive to `to`, nothing is left behind unless it comes out with `expected_hash`
+pub async fn extract_archive_entry(
+    archive: ExistingPathBuf,
+    entry: CaseInsensitivePathBuf,
+    to: Utf8PlatformPathBuf,
+    expected_hash: String,
+) -> Result<ExistingPathBuf> {
+    let destination = std::path::PathBuf::from(to.as_str());
+    let context = format!("extracting [{entry}] out of [{archive}] to [{}]", destination.display());
+    crate::blocking_pool::pools()
+        .fs
+        .run(move || {
+            ArchiveHandle::with_guessed(&archive, archive.as_path().extension(), |mut archive| {
+                archive.get_handle(&entry).and_then(|handle| {
+                    crate::atomic_write::write_atomically(&destination, |file| {
+                        let mut reader = handle.and_hash();
+                        std::io::copy(&mut reader, file)
+                            .context("writing extracted entry")
+                            .map(|_| to_base_64_from_u64(reader.hash()))
+                            .and_then(|hash| {
+                                hash.eq(&expected_hash)
+                                    .then_some(())
+                                    .with_context(|| format!("hash mismatch, expected [{expected_hash}], found [{hash}]"))
+                            })
+                    })
+                })
+            })
+        })
+        .await
+        .and_then(|_| to.exists_utf8())
+        .context(context)
+}
+
 pub struct GameFileSourceDownloader {
     game_name: GameName,
     source_directory: ExistingPathBuf,
@@ -26,28 +105,47 @@ impl GameFileSourceDownloader {
     pub async fn prepare_copy(
         &self,
         GameFileSourceState {
-            game_version: _,
+            game_version,
             hash,
             game_file,
             game,
         }: GameFileSourceState,
-    ) -> Result<ExistingPathBuf> {
+    ) -> Result<GameFile> {
+        let existing = |path: CaseInsensitivePathBuf| {
+            self.source_directory
+                .clone()
+                .case_insensitive()
+                .join_case_insensitive(path)
+                .pipe(ready)
+                .and_then(async |path| path.try_exists_async().await)
+        };
         self.game_name
             .eq(&game)
             .then_some(())
             .with_context(|| format!("expected downloader for [{game}], but this is a downloader for [{}]", self.game_name))
-            .map(|_| game_file)
+            .and_then(|_| split_archive_entry(&game_file))
             .pipe(ready)
-            .and_then(|game_file| {
-                self.source_directory
-                    .clone()
-                    .case_insensitive()
-                    .join_case_insensitive(game_file)
-                    .pipe(ready)
-                    .and_then(async |game_file| game_file.try_exists_async().await)
+            .and_then(|archive_entry| async {
+                match archive_entry {
+                    Some((archive, entry)) => existing(archive)
+                        .await
+                        .map(|archive| GameFile::ArchiveEntry { archive, entry }),
+                    None => existing(game_file)
+                        .and_then(|source| {
+                            validate_hash_wabbajack(source, hash).map_err(|reason| {
+                                reason.context(GameFileHashMismatch {
+                                    game: game.clone(),
+                                    game_version: game_version.clone(),
+                                    root_directory: self.source_directory.as_os_path().to_owned(),
+                                })
+                            })
+                        })
+                        .await
+                        .map(GameFile::Plain),
+                }
             })
-            .and_then(|source| validate_hash_wabbajack(source, hash))
             .await
+            .with_context(|| mismatch_hint(&game, &game_version, self.source_directory.as_os_path()))
     }
 }
 
@@ -64,3 +162,126 @@ pub fn get_game_file_source_synchronizers(config: GamesConfig) -> Result<GameFil
         .collect::<Result<_>>()
         .context("instantiating game downloaders, check config")
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        ba2::tes4::{Archive, ArchiveFlags, ArchiveKey, ArchiveOptions, ArchiveTypes, Directory, DirectoryKey, File, Version},
+    };
+
+    fn hash(contents: &[u8]) -> String {
+        xxhash_rust::xxh64::xxh64(contents, 0).pipe(to_base_64_from_u64)
+    }
+
+    /// skyrim style bsa holding `textures\sky\moon.dds`
+    fn write_bsa(path: &std::path::Path, contents: &[u8]) -> Result<()> {
+        Archive::new()
+            .tap_mut(|archive| {
+                archive.insert(
+                    ArchiveKey::from("textures\\sky"),
+                    Directory::default().tap_mut(|directory| {
+                        directory.insert(DirectoryKey::from("moon.dds"), File::from_decompressed(contents));
+                    }),
+                );
+            })
+            .pipe(|archive| {
+                std::fs::File::create(path)
+                    .context("creating bsa")
+                    .and_then(|mut file| {
+                        archive
+                            .write(
+                                &mut file,
+                                &ArchiveOptions::builder()
+                                    .version(Version::v105)
+                                    .flags(ArchiveFlags::DIRECTORY_STRINGS | ArchiveFlags::FILE_STRINGS)
+                                    .types(ArchiveTypes::TEXTURES)
+                                    .build(),
+                            )
+                            .map_err(Into::into)
+                    })
+            })
+    }
+
+    fn state(game_file: &str, contents: &[u8]) -> Result<GameFileSourceState> {
+        CaseInsensitivePathBuf::from_str(game_file).map(|game_file| GameFileSourceState {
+            game_version: "1.6.1170.0".into(),
+            hash: hash(contents),
+            game_file,
+            game: GameName::new("Skyrim".into()),
+        })
+    }
+
+    #[test_log::test]
+    fn test_split_archive_entry() -> Result<()> {
+        let split = |game_file: &str| -> Result<Option<(String, String)>> {
+            CaseInsensitivePathBuf::from_str(game_file)
+                .and_then(|game_file| split_archive_entry(&game_file))
+                .map(|split| split.map(|(archive, entry)| (archive.as_original_path().as_str().to_string(), entry.as_original_path().as_str().to_string())))
+        };
+        assert_eq!(split(r"Data\Skyrim.esm")?, None);
+        assert_eq!(split(r"Data\Skyrim - Textures0.bsa")?, None, "the archive itself is a plain file");
+        assert_eq!(
+            split(r"Data\Fallout - Textures.BA2\textures\foo.dds")?,
+            Some((r"Data\Fallout - Textures.BA2".to_string(), r"textures\foo.dds".to_string()))
+        );
+        assert_eq!(
+            split("Data/Skyrim - Textures0.bsa/textures/sky/moon.dds")?,
+            Some((r"Data\Skyrim - Textures0.bsa".to_string(), r"textures\sky\moon.dds".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_plain_and_archived_game_files() -> Result<()> {
+        let (game, downloads) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        std::fs::create_dir_all(game.path().join("Data"))?;
+        std::fs::write(game.path().join("Data/Skyrim.esm"), b"plugin")?;
+        write_bsa(&game.path().join("Data/Skyrim - Textures0.bsa"), b"moon")?;
+        let downloader = GameFileSourceDownloader::new(
+            GameName::new("Skyrim".into()),
+            GameConfig {
+                root_directory: game.path().to_owned(),
+            },
+        )?;
+
+        match downloader
+            .prepare_copy(state(r"Data\Skyrim.esm", b"plugin")?)
+            .await?
+        {
+            GameFile::Plain(source) => assert_eq!(std::fs::read(source.as_os_path())?, b"plugin"),
+            other => panic!("expected a plain file, got {other:?}"),
+        }
+        assert!(
+            downloader
+                .prepare_copy(state(r"Data\Skyrim.esm", b"modified")?)
+                .await
+                .is_err()
+        );
+
+        let (archive, entry) = match downloader
+            .prepare_copy(state(r"Data\skyrim - textures0.bsa\Textures\Sky\Moon.dds", b"moon")?)
+            .await?
+        {
+            GameFile::ArchiveEntry { archive, entry } => (archive, entry),
+            other => panic!("expected an archive entry, got {other:?}"),
+        };
+        let extracted = extract_archive_entry(
+            archive.clone(),
+            entry.clone(),
+            downloads.path().join("Moon.dds").utf8_platform_path()?,
+            hash(b"moon"),
+        )
+        .await?;
+        assert_eq!(std::fs::read(extracted.as_os_path())?, b"moon");
+
+        let wrong = downloads.path().join("Sun.dds");
+        assert!(
+            extract_archive_entry(archive, entry, wrong.utf8_platform_path()?, hash(b"sun"))
+                .await
+                .is_err()
+        );
+        assert!(!wrong.exists(), "an entry with a different hash is not left behind");
+        Ok(())
+    }
+}
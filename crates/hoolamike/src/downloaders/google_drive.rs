@@ -3,7 +3,7 @@ use {
     crate::{install_modlist::downloads::HTTP_CLIENT, modlist_json::HumanUrl},
     anyhow::{Context, Result},
     futures::TryFutureExt,
-    std::{future::ready, str::FromStr},
+    std::str::FromStr,
     tap::prelude::*,
 };
 
@@ -96,9 +96,9 @@ impl GoogleDriveDownloader {
                     .text()
                     .map_context("extracting text")
                     .and_then(|text| {
-                        tokio::task::spawn_blocking(move || response_parsing::get_url_from_gdrive_confirmation(&text))
-                            .map_context("thread crashed")
-                            .and_then(ready)
+                        crate::blocking_pool::pools()
+                            .fs
+                            .run(move || response_parsing::get_url_from_gdrive_confirmation(&text))
                     })
                     .await
             }
@@ -3,7 +3,6 @@ use {
     crate::modlist_json::HumanUrl,
     anyhow::{Context, Result},
     futures::TryFutureExt,
-    std::future::ready,
     tap::prelude::*,
     tracing::instrument,
 };
@@ -89,11 +88,9 @@ impl MediaFireDownloader {
             // })
             .and_then(|res| res.text().map_context("extracting text"))
             .and_then(|text| {
-                tokio::task::spawn_blocking(move || {
-                    response_parsing::get_url_from_mediafire_confirmation(&text).tap_ok(|url| tracing::debug!(%url, "parsed mediafire url"))
-                })
-                .map_context("thread crashed")
-                .and_then(ready)
+                crate::blocking_pool::pools()
+                    .fs
+                    .run(move || response_parsing::get_url_from_mediafire_confirmation(&text).tap_ok(|url| tracing::debug!(%url, "parsed mediafire url")))
             })
             .await
             .with_context(|| format!("preparing MediaFire download for [{url}]"))
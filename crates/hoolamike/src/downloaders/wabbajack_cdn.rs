@@ -1,24 +1,34 @@
 use {
     super::helpers::FutureAnyhowExt,
-    crate::modlist_json::{HumanUrl, WabbajackCDNDownloaderState},
+    crate::{
+        install_modlist::download_cache::to_base_64_from_u64,
+        modlist_json::{HumanUrl, WabbajackCDNDownloaderState},
+    },
     anyhow::{Context, Result},
     flate2::read::GzDecoder,
-    futures::TryFutureExt,
+    futures::{StreamExt, TryFutureExt},
     itertools::Itertools,
     reqwest::Client,
     serde::{Deserialize, Serialize},
-    std::{future::ready, io::Read},
+    std::{hash::Hasher, io::Read},
     tap::prelude::*,
+    tracing::warn,
     url::Url,
 };
 
 pub struct WabbajackCDNDownloader {}
 
+/// a part is re-fetched on hash mismatch, a part which keeps mismatching fails the whole archive
+pub const MAX_PART_ATTEMPTS: usize = 3;
+
 const MAGIC_FILENAME: &str = "definition.json.gz";
 
 #[cfg(test)]
 mod test_responses;
 
+#[cfg(test)]
+pub(crate) mod mock_cdn;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct Part {
@@ -97,8 +107,58 @@ fn parse_wabbajack_cdn_file_response(contents: &str) -> Result<WabbajackCdnFile>
         .context("invalid wabbajack cdn response")
 }
 
+/// single part of a file on the CDN, with the hash the definition file declares for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CdnPart {
+    pub index: usize,
+    pub url: HumanUrl,
+    pub hash: String,
+    pub size: u64,
+}
+
+/// downloads a single part, hashing it on the fly
+async fn fetch_part_once(client: &Client, CdnPart { url, size, .. }: &CdnPart) -> Result<(Vec<u8>, String)> {
+    let mut byte_stream = client
+        .get(url.to_string())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("making request to {url}"))?
+        .bytes_stream();
+    let mut hasher = xxhash_rust::xxh64::Xxh64::new(0);
+    let mut contents = Vec::with_capacity(*size as usize);
+    while let Some(chunk) = byte_stream.next().await {
+        let chunk = chunk.with_context(|| format!("reading from {url}"))?;
+        hasher.update(&chunk);
+        contents.extend_from_slice(&chunk);
+    }
+    Ok((contents, to_base_64_from_u64(hasher.finish())))
+}
+
+/// downloads a part and checks it against its declared hash, re-fetching it up to [MAX_PART_ATTEMPTS] times
+pub async fn fetch_part(client: &Client, part: &CdnPart) -> Result<Vec<u8>> {
+    let mut attempt = 1;
+    loop {
+        let (contents, hash) = fetch_part_once(client, part).await?;
+        match (hash == part.hash, attempt < MAX_PART_ATTEMPTS) {
+            (true, _) => return Ok(contents),
+            (false, true) => warn!(
+                "part [{}] ({}) is corrupted (expected hash [{}], got [{hash}]), fetching it again ({attempt}/{MAX_PART_ATTEMPTS})",
+                part.index, part.url, part.hash
+            ),
+            (false, false) => anyhow::bail!(
+                "part [{}] ({}) is still corrupted after [{MAX_PART_ATTEMPTS}] attempts (expected hash [{}], got [{hash}])",
+                part.index,
+                part.url,
+                part.hash
+            ),
+        }
+        attempt += 1;
+    }
+}
+
 impl WabbajackCDNDownloader {
-    pub async fn prepare_download(WabbajackCDNDownloaderState { url }: WabbajackCDNDownloaderState) -> Result<Vec<HumanUrl>> {
+    pub async fn prepare_download(WabbajackCDNDownloaderState { url }: WabbajackCDNDownloaderState) -> Result<Vec<CdnPart>> {
         let url = url
             .clone()
             .conv::<url::Url>()
@@ -112,7 +172,7 @@ impl WabbajackCDNDownloader {
             .map_with_context(|| format!("fetching from [{deduced_url}]"))
             .and_then(|response| response.bytes().map_context("reading bytes"))
             .and_then(|bytes| {
-                tokio::task::spawn_blocking(move || {
+                crate::blocking_pool::pools().fs.run(move || {
                     GzDecoder::new(std::io::Cursor::new(&bytes)).pipe_ref_mut(|gzip| {
                         String::new()
                             .pipe(|mut output| {
@@ -127,8 +187,6 @@ impl WabbajackCDNDownloader {
                             .and_then(|contents| parse_wabbajack_cdn_file_response(&contents))
                     })
                 })
-                .map_context("thread crashed")
-                .and_then(ready)
             })
             .map_ok({
                 let url = url.clone();
@@ -143,11 +201,14 @@ impl WabbajackCDNDownloader {
                       }| {
                     parts
                         .into_iter()
-                        .map(move |Part { index, .. }| {
-                            url.clone().tap_mut(|url| {
+                        .map(move |Part { index, hash, size, offset: _ }| CdnPart {
+                            index,
+                            url: url.clone().tap_mut(|url| {
                                 url.as_mut()
                                     .set_path(&format!("{munged_name}/parts/{index}"))
-                            })
+                            }),
+                            hash,
+                            size: size as u64,
                         })
                         .collect_vec()
                 }
@@ -156,3 +217,38 @@ impl WabbajackCDNDownloader {
             .with_context(|| format!("fetching stuff from deduced url: [{deduced_url}] based on [{url}]"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        mock_cdn::{MockCdn, PartBehavior},
+    };
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_corrupted_part_is_fetched_again() -> Result<()> {
+        let cdn = MockCdn::start(1024, &[PartBehavior::Intact, PartBehavior::CorruptedTimes(2)]).await?;
+        let client = Client::new();
+        assert_eq!(fetch_part(&client, &cdn.parts[0]).await?, vec![0; 1024]);
+        assert_eq!(fetch_part(&client, &cdn.parts[1]).await?, vec![1; 1024]);
+        assert_eq!(cdn.requests(0), 1);
+        assert_eq!(cdn.requests(1), 3);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_persistently_corrupted_part_gives_up() -> Result<()> {
+        let cdn = MockCdn::start(1024, &[PartBehavior::CorruptedAlways]).await?;
+        let reason = fetch_part(&Client::new(), &cdn.parts[0])
+            .await
+            .expect_err("corrupted part was accepted");
+        assert!(
+            reason
+                .to_string()
+                .contains("still corrupted after [3] attempts"),
+            "{reason:?}"
+        );
+        assert_eq!(cdn.requests(0), MAX_PART_ATTEMPTS);
+        Ok(())
+    }
+}
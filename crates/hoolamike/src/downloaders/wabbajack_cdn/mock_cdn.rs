@@ -0,0 +1,99 @@
+//! local stand-in for the CDN serving the parts of a single file, some of them corrupted or never finishing
+
+use {
+    super::*,
+    axum::{
+        Router,
+        extract::{Path, State},
+        routing::get,
+    },
+    std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartBehavior {
+    Intact,
+    /// the first `n` responses have a flipped byte
+    CorruptedTimes(usize),
+    CorruptedAlways,
+    /// the response never arrives
+    Hangs,
+}
+
+struct Served {
+    contents: Vec<u8>,
+    behavior: PartBehavior,
+    requests: AtomicUsize,
+}
+
+pub struct MockCdn {
+    pub parts: Vec<CdnPart>,
+    served: Arc<Vec<Served>>,
+}
+
+impl MockCdn {
+    /// every part is `part_size` bytes of its own index
+    pub async fn start(part_size: usize, behaviors: &[PartBehavior]) -> Result<Self> {
+        let served = behaviors
+            .iter()
+            .enumerate()
+            .map(|(index, behavior)| Served {
+                contents: vec![index as u8; part_size],
+                behavior: *behavior,
+                requests: AtomicUsize::new(0),
+            })
+            .collect_vec()
+            .pipe(Arc::new);
+        let listener = tokio::net::TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .await
+            .context("binding the mock cdn")?;
+        let address = listener.local_addr().context("no local address")?;
+        Router::new()
+            .route("/file/parts/{index}", get(serve_part))
+            .with_state(served.clone())
+            .pipe(|router| tokio::task::spawn(async move { axum::serve(listener, router).await }));
+        served
+            .iter()
+            .enumerate()
+            .map(|(index, Served { contents, .. })| {
+                format!("http://{address}/file/parts/{index}")
+                    .parse::<HumanUrl>()
+                    .context("bad mock url")
+                    .map(|url| CdnPart {
+                        index,
+                        url,
+                        hash: xxhash_rust::xxh64::xxh64(contents, 0).pipe(to_base_64_from_u64),
+                        size: contents.len() as u64,
+                    })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|parts| Self { parts, served })
+    }
+
+    pub fn requests(&self, index: usize) -> usize {
+        self.served[index].requests.load(Ordering::SeqCst)
+    }
+}
+
+async fn serve_part(State(served): State<Arc<Vec<Served>>>, Path(index): Path<usize>) -> Vec<u8> {
+    let Served { contents, behavior, requests } = &served[index];
+    let request = requests.fetch_add(1, Ordering::SeqCst) + 1;
+    let corrupted = contents
+        .clone()
+        .tap_mut(|contents| contents[0] = contents[0].wrapping_add(1));
+    match behavior {
+        PartBehavior::Intact => contents.clone(),
+        PartBehavior::CorruptedTimes(times) => match request <= *times {
+            true => corrupted,
+            false => contents.clone(),
+        },
+        PartBehavior::CorruptedAlways => corrupted,
+        PartBehavior::Hangs => std::future::pending().await,
+    }
+}
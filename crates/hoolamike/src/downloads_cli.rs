@@ -0,0 +1,334 @@
+//! housekeeping of the downloads directory - shared by a few modlists it keeps collecting archives none of them needs
+//! anymore. `prune` lists them (biggest first) along with quarantined downloads, and deletes them when asked to
+
+use {
+    crate::{
+        config_file::{HoolamikeConfig, PathOverrides},
+        install_modlist::{
+            archive_meta::{META_EXTENSION, meta_path},
+            download_cache::{HASH_SIDECAR_EXTENSION, hash_file_wabbajack, hash_suffixed_name, read_sidecar, sidecar_path, to_base_64_from_u64},
+            quarantine::Quarantine,
+        },
+        modlist_json::ArchiveDescriptor,
+        wabbajack_file::{WabbajackFile, modlist_cache::ModlistCache},
+    },
+    anyhow::{Context, Result},
+    case_insensitive_path::PathExistsUtf8Ext,
+    indicatif::HumanBytes,
+    itertools::Itertools,
+    rayon::iter::{IntoParallelIterator, ParallelIterator},
+    std::{
+        io::Write,
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tracing::{info, warn},
+};
+
+#[derive(clap::Args, Clone)]
+pub struct DownloadsCli {
+    #[command(flatten)]
+    pub paths: PathOverrides,
+    #[command(subcommand)]
+    command: DownloadsCommand,
+}
+
+#[derive(clap::Subcommand, Clone)]
+pub enum DownloadsCommand {
+    /// lists files in downloads_directory the modlist does not use, stale versions of archives it does use and downloads which
+    /// were quarantined for coming out with a wrong hash
+    Prune(PruneCli),
+}
+
+#[derive(clap::Args, Clone)]
+pub struct PruneCli {
+    /// another modlist (.wabbajack file) sharing the downloads directory, its archives are kept as well. can be repeated
+    #[arg(long)]
+    keep_for: Vec<PathBuf>,
+    /// deletes the listed files, after asking
+    #[arg(long)]
+    delete: bool,
+    /// does not ask before deleting
+    #[arg(long, requires = "delete")]
+    yes: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PruneCandidate {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// named like no archive of the modlists
+    pub unreferenced: Vec<PruneCandidate>,
+    /// named like an archive, but with a different hash (an older version most of the time)
+    pub stale: Vec<PruneCandidate>,
+    /// downloads which came out with a wrong hash, kept aside in `.quarantine`
+    pub quarantined: Vec<PruneCandidate>,
+}
+
+/// hash records and `.meta` files go along with their archives
+fn is_sidecar(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == HASH_SIDECAR_EXTENSION || extension == META_EXTENSION)
+}
+
+/// the recorded hash is trusted like it is during installation, files which were never verified are hashed
+fn is_version_of(path: &Path, size: u64, descriptor: &ArchiveDescriptor) -> bool {
+    size == descriptor.size
+        && match read_sidecar(path) {
+            Some(recorded) => recorded == descriptor.hash,
+            None => path
+                .exists_utf8()
+                .and_then(|path| hash_file_wabbajack(&path))
+                .map(to_base_64_from_u64)
+                .is_ok_and(|hash| hash == descriptor.hash),
+        }
+}
+
+impl PruneReport {
+    pub fn collect(downloads_directory: &Path, archives: &[ArchiveDescriptor]) -> Result<Self> {
+        // archives are looked up case-insensitively, under their plain and their hash suffixed name
+        let referenced = archives
+            .iter()
+            .flat_map(|descriptor| {
+                std::iter::once(descriptor.name.clone())
+                    .chain(hash_suffixed_name(&descriptor.name, &descriptor.hash).ok())
+                    .map(move |name| (name.to_lowercase(), descriptor))
+            })
+            .into_group_map();
+        std::fs::read_dir(downloads_directory)
+            .with_context(|| format!("reading [{}]", downloads_directory.display()))?
+            .map(|entry| {
+                entry
+                    .and_then(|entry| entry.metadata().map(|metadata| (entry.path(), metadata)))
+                    .with_context(|| format!("listing [{}]", downloads_directory.display()))
+            })
+            .filter_ok(|(path, metadata)| metadata.is_file() && !is_sidecar(path) && !crate::atomic_write::is_partial(path))
+            .map_ok(|(path, metadata)| (path, metadata.len()))
+            .collect::<Result<Vec<_>>>()?
+            .into_par_iter()
+            .filter_map(|(path, size)| {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                match referenced.get(&name) {
+                    None => Some((false, PruneCandidate { path, size })),
+                    Some(descriptors) => (!descriptors
+                        .iter()
+                        .any(|descriptor| is_version_of(&path, size, descriptor)))
+                    .then_some((true, PruneCandidate { path, size })),
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .sorted_by(|(_, a), (_, b)| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)))
+            .fold(Self::default(), |report, (stale, candidate)| {
+                report.tap_mut(|report| match stale {
+                    true => report.stale.push(candidate),
+                    false => report.unreferenced.push(candidate),
+                })
+            })
+            .pipe(|report| {
+                Quarantine::in_downloads(downloads_directory)
+                    .files()
+                    .map(|files| {
+                        files
+                            .into_iter()
+                            .map(|(path, size)| PruneCandidate { path, size })
+                            .sorted_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)))
+                            .collect()
+                    })
+                    .map(|quarantined| Self { quarantined, ..report })
+            })
+    }
+
+    fn candidates(&self) -> impl Iterator<Item = &PruneCandidate> {
+        self.unreferenced
+            .iter()
+            .chain(&self.stale)
+            .chain(&self.quarantined)
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.candidates().map(|candidate| candidate.size).sum()
+    }
+
+    pub fn print(&self) -> String {
+        let section = |title: &str, candidates: &[PruneCandidate]| {
+            format!(
+                "[{}] {title} ({}):\n{}",
+                candidates.len(),
+                HumanBytes(candidates.iter().map(|candidate| candidate.size).sum()),
+                candidates
+                    .iter()
+                    .map(|PruneCandidate { path, size }| format!("  {:>12}  {}", HumanBytes(*size).to_string(), path.display()))
+                    .join("\n")
+            )
+        };
+        [
+            section("unreferenced files", &self.unreferenced),
+            section("stale versions", &self.stale),
+            section("quarantined downloads", &self.quarantined),
+        ]
+        .join("\n\n")
+    }
+
+    /// removes the files along with their hash sidecars and `.meta` files, returns how many were removed
+    pub fn delete(&self) -> Result<usize> {
+        self.candidates()
+            .map(|PruneCandidate { path, size: _ }| {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("removing [{}]", path.display()))
+                    .map(|_| {
+                        [sidecar_path(path), meta_path(path)]
+                            .into_iter()
+                            .filter(|sidecar| sidecar.exists())
+                            .for_each(|sidecar| {
+                                std::fs::remove_file(&sidecar).unwrap_or_else(|reason| warn!(?reason, "could not remove [{}]", sidecar.display()))
+                            })
+                    })
+            })
+            .try_fold(0, |removed, result| result.map(|_| removed + 1))
+    }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    crate::non_interactive::ensure_interactive("confirming the deletion (pass --yes to skip it)")?;
+    print!("{question} [y/N] ");
+    std::io::stdout().flush().context("flushing stdout")?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("reading the answer")
+        .map(|_| matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn archives_of(modlist: &Path, cache: &ModlistCache) -> Result<Vec<ArchiveDescriptor>> {
+    modlist
+        .exists_utf8()
+        .and_then(|path| WabbajackFile::load_modlist_json(&path, Some(cache)))
+        .map(|wabbajack| {
+            wabbajack
+                .modlist
+                .archives
+                .into_iter()
+                .map(|archive| archive.descriptor)
+                .collect()
+        })
+        .with_context(|| format!("reading archives of [{}]", modlist.display()))
+}
+
+pub fn run_prune(PruneCli { keep_for, delete, yes }: PruneCli, config: HoolamikeConfig) -> Result<()> {
+    let cache = ModlistCache::in_project_root(&std::env::current_dir().context("reading current directory")?);
+    let archives = std::iter::once(&config.installation.wabbajack_file_path)
+        .chain(&keep_for)
+        .map(|modlist| archives_of(modlist, &cache))
+        .collect::<Result<Vec<_>>>()?
+        .concat();
+    let report = PruneReport::collect(&config.downloaders.downloads_directory, &archives)?;
+    println!("{}", report.print());
+    let count = report.candidates().count();
+    match (count, delete) {
+        (0, _) => info!("nothing to prune"),
+        (_, false) => info!("run with --delete to remove them ([{}] in total)", HumanBytes(report.total_size())),
+        (_, true) => match yes || confirm(&format!("delete [{count}] files ({})?", HumanBytes(report.total_size())))? {
+            true => report
+                .delete()
+                .map(|removed| info!("removed [{removed}] files"))?,
+            false => info!("nothing was deleted"),
+        },
+    }
+    Ok(())
+}
+
+pub fn run(DownloadsCli { paths: _, command }: DownloadsCli, config: HoolamikeConfig) -> Result<()> {
+    match command {
+        DownloadsCommand::Prune(prune) => run_prune(prune, config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::install_modlist::download_cache::write_sidecar, serde_json::json};
+
+    fn archive(name: &str, contents: &[u8]) -> ArchiveDescriptor {
+        serde_json::from_value(json!({
+            "Hash": xxhash_rust::xxh64::xxh64(contents, 0).pipe(to_base_64_from_u64),
+            "Meta": "",
+            "Name": name,
+            "Size": contents.len(),
+        }))
+        .expect("bad descriptor fixture")
+    }
+
+    #[test_log::test]
+    fn test_prune_report() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let write = |name: &str, contents: &[u8]| std::fs::write(directory.path().join(name), contents);
+        let current = archive("Current.7z", b"current version");
+        let recorded = archive("Recorded.zip", b"verified before");
+        let suffixed = archive("Current.7z", b"another version kept aside");
+        write("current.7z", b"current version")?;
+        write("Recorded.zip", b"verified before")?;
+        write_sidecar(&directory.path().join("Recorded.zip"), &recorded.hash)?;
+        write(&hash_suffixed_name(&suffixed.name, &suffixed.hash)?, b"another version kept aside")?;
+        write("Stale.7z", b"an older version")?;
+        write("Conflicting.7z", b"sized like it")?;
+        write_sidecar(&directory.path().join("Conflicting.7z"), "AAAAAAAAAAA=")?;
+        write("Forgotten.rar", b"from a modlist long gone")?;
+        write("Forgotten.rar.meta", b"[General]\ndirectURL=https://example.org/forgotten.rar\n")?;
+        write("In Progress.7z.hoolamike-partial", b"half")?;
+        std::fs::create_dir(directory.path().join(".hoolamike-state"))?;
+        let quarantine = Quarantine::in_downloads(directory.path());
+        write("Broken.7z", b"<html>503</html>")?;
+        quarantine.quarantine(&directory.path().join("Broken.7z"), &archive("Broken.7z", b"the real archive"), "AAAAAAAAAAA=")?;
+
+        let report = PruneReport::collect(
+            directory.path(),
+            &[
+                current,
+                recorded,
+                suffixed,
+                archive("Stale.7z", b"the newest version"),
+                archive("Conflicting.7z", b"sized like it"),
+            ],
+        )?;
+        let names = |candidates: &[PruneCandidate]| {
+            candidates
+                .iter()
+                .map(|candidate| {
+                    candidate
+                        .path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect_vec()
+        };
+        assert_eq!(names(&report.unreferenced), ["Forgotten.rar"]);
+        // biggest first
+        assert_eq!(names(&report.stale), ["Stale.7z", "Conflicting.7z"]);
+        assert_eq!(report.quarantined.len(), 1);
+        assert!(names(&report.quarantined)[0].starts_with("Broken.7z."));
+        assert!(report.print().contains("[1] quarantined downloads"));
+
+        assert_eq!(report.delete()?, 4);
+        assert!(quarantine.files()?.is_empty());
+        assert!(!directory.path().join("Conflicting.7z").exists());
+        assert!(!directory.path().join("Conflicting.7z.xxh64").exists());
+        assert!(!directory.path().join("Forgotten.rar.meta").exists());
+        assert!(directory.path().join("Recorded.zip.xxh64").exists());
+        assert!(
+            directory
+                .path()
+                .join("In Progress.7z.hoolamike-partial")
+                .exists()
+        );
+        Ok(())
+    }
+}
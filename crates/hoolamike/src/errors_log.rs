@@ -0,0 +1,251 @@
+//! the console only gets a summary of a failed run, the complete error chains (with backtraces when `RUST_BACKTRACE` is set)
+//! go to `errors-<run>.log` - next to the state hoolamike keeps for the installation, or to the first writable fallback
+//! when the run failed before that was known
+
+use {
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+        sync::OnceLock,
+    },
+    tap::prelude::*,
+};
+
+/// directives listed under every distinct cause on the console, the errors log has all of them
+const EXAMPLES_PER_CAUSE: usize = 3;
+
+static LOG_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+static RUN_SUMMARY: OnceLock<String> = OnceLock::new();
+
+/// called once the installation state directory is known, earlier failures end up in the fallbacks
+pub fn set_log_directory(directory: PathBuf) {
+    LOG_DIRECTORY.get_or_init(|| directory);
+}
+
+/// numbers of the installation, written along with the errors when it fails
+pub fn set_run_summary(summary: String) {
+    RUN_SUMMARY.get_or_init(|| summary);
+}
+
+/// every error of a run which doesn't stop at the first one - the console shows the summary, the log gets them all
+#[derive(Debug)]
+pub struct AggregatedErrors {
+    pub summary: String,
+    pub errors: Vec<anyhow::Error>,
+}
+
+impl fmt::Display for AggregatedErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary)
+    }
+}
+
+impl std::error::Error for AggregatedErrors {}
+
+/// errors ending in the same innermost message - a missing tool fails thousands of textures, but it's a single problem
+#[derive(Debug)]
+pub struct Cause<'a> {
+    pub message: String,
+    pub errors: Vec<&'a anyhow::Error>,
+}
+
+/// most frequent first, causes hit equally often in the order they first happened
+pub fn group_by_cause(errors: &[anyhow::Error]) -> Vec<Cause<'_>> {
+    errors
+        .iter()
+        .fold(Vec::<Cause>::new(), |causes, error| {
+            let message = error.root_cause().to_string();
+            causes.tap_mut(|causes| match causes.iter_mut().find(|cause| cause.message == message) {
+                Some(cause) => cause.errors.push(error),
+                None => causes.push(Cause { message, errors: vec![error] }),
+            })
+        })
+        .tap_mut(|causes| causes.sort_by_key(|cause| std::cmp::Reverse(cause.errors.len())))
+}
+
+/// the outermost single line context naming something (`[...]`), directive contexts are whole pretty printed directives
+fn example(error: &anyhow::Error) -> String {
+    error
+        .chain()
+        .take(error.chain().count().saturating_sub(1).max(1))
+        .map(|cause| cause.to_string())
+        .find(|message| !message.contains('\n') && message.contains('['))
+        .unwrap_or_else(|| {
+            error
+                .to_string()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+}
+
+pub fn installation_failed(errors: &[anyhow::Error]) -> String {
+    format!(
+        "could not finish installation due to [{}] errors ([{}] distinct causes)",
+        errors.len(),
+        group_by_cause(errors).len()
+    )
+}
+
+/// what the console gets of a failed run - a cause hit once is shown whole, the others once with a count and a few examples
+pub fn grouped(errors: &[anyhow::Error]) -> String {
+    group_by_cause(errors)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, Cause { message, errors })| match errors.as_slice() {
+            [error] => format!("{idx}. {error:?}", idx = idx + 1),
+            errors => errors
+                .iter()
+                .take(EXAMPLES_PER_CAUSE)
+                .map(|error| format!("\n    - {}", example(error)))
+                .chain((errors.len() > EXAMPLES_PER_CAUSE).then(|| format!("\n    - and [{}] more", errors.len() - EXAMPLES_PER_CAUSE)))
+                .join("")
+                .pipe(|examples| format!("{idx}. [{count}x] {message}{examples}", idx = idx + 1, count = errors.len())),
+        })
+        .join("\n\n")
+}
+
+fn is_writable(directory: &Path) -> bool {
+    std::fs::create_dir_all(directory).is_ok() && tempfile::tempfile_in(directory).is_ok()
+}
+
+/// log directory, then the directory holding the config, then the temp directory
+fn pick_directory(log_directory: Option<&Path>, config_path: &Path, temp_directory: &Path, is_writable: impl Fn(&Path) -> bool) -> PathBuf {
+    log_directory
+        .map(Path::to_owned)
+        .into_iter()
+        .chain([crate::project_root::project_root_for(config_path), temp_directory.to_owned()])
+        .find(|directory| is_writable(directory))
+        .unwrap_or_else(|| temp_directory.to_owned())
+}
+
+fn details(error: &anyhow::Error) -> String {
+    format!(
+        "hoolamike {}\ncommand: {}\n\n{error:?}{}{}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::args().join(" "),
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<AggregatedErrors>())
+            .map(|AggregatedErrors { errors, .. }| {
+                errors
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, reason)| format!("\n\n--- [{}/{}] ---\n{reason:?}", idx + 1, errors.len()))
+                    .join("")
+            })
+            .unwrap_or_default(),
+        RUN_SUMMARY
+            .get()
+            .map(|summary| format!("\n\n--- summary of the run ---\n{summary}"))
+            .unwrap_or_default()
+    )
+}
+
+fn write_errors_log_in(directory: &Path, error: &anyhow::Error) -> Result<PathBuf> {
+    directory
+        .join(format!("errors-{}.log", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+        .pipe(|path| {
+            std::fs::write(&path, details(error))
+                .with_context(|| format!("writing [{}]", path.display()))
+                .and_then(|_| std::path::absolute(&path).context("making the path absolute"))
+        })
+}
+
+/// returns the absolute path of the written log
+pub fn write_errors_log(error: &anyhow::Error, config_path: &Path) -> Result<PathBuf> {
+    pick_directory(LOG_DIRECTORY.get().map(PathBuf::as_path), config_path, &std::env::temp_dir(), is_writable)
+        .pipe(|directory| write_errors_log_in(&directory, error))
+        .context("writing errors log")
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::anyhow};
+
+    #[test_log::test]
+    fn test_fallback_order() {
+        let (log, config, temp) = (Path::new("/state/.hoolamike-state"), Path::new("/project/hoolamike.yaml"), Path::new("/tmp"));
+        let writable = |writable: &'static [&'static str]| move |directory: &Path| writable.iter().any(|w| Path::new(w) == directory);
+        assert_eq!(
+            pick_directory(Some(log), config, temp, writable(&["/state/.hoolamike-state", "/project", "/tmp"])),
+            log
+        );
+        // failed before the state directory was known
+        assert_eq!(
+            pick_directory(None, config, temp, writable(&["/state/.hoolamike-state", "/project", "/tmp"])),
+            Path::new("/project")
+        );
+        assert_eq!(pick_directory(Some(log), config, temp, writable(&["/project", "/tmp"])), Path::new("/project"));
+        assert_eq!(pick_directory(Some(log), config, temp, writable(&["/tmp"])), temp);
+        // nothing is writable, the temp directory is still the best bet
+        assert_eq!(pick_directory(Some(log), config, temp, writable(&[])), temp);
+    }
+
+    #[test_log::test]
+    fn test_every_aggregated_error_is_written() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let error = anyhow::Error::new(AggregatedErrors {
+            summary: "could not finish installation due to [2] errors".into(),
+            errors: vec![
+                anyhow!("connection reset").context("downloading [https://example.com/a.7z]"),
+                anyhow!("hash mismatch").context("verifying [b.7z]"),
+            ],
+        })
+        .context("error occurred");
+        let path = write_errors_log_in(directory.path(), &error)?;
+        assert!(path.is_absolute());
+        assert!(
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("errors-") && name.ends_with(".log"))
+        );
+        let written = std::fs::read_to_string(&path)?;
+        [
+            "could not finish installation due to [2] errors",
+            "--- [1/2] ---\ndownloading [https://example.com/a.7z]",
+            "connection reset",
+            "--- [2/2] ---\nverifying [b.7z]",
+            "hash mismatch",
+        ]
+        .iter()
+        .for_each(|expected| assert!(written.contains(expected), "[{expected}] missing from:\n{written}"));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_errors_are_grouped_by_root_cause() {
+        let errors = (0..5)
+            .map(|idx| {
+                anyhow!("texconv+wine not set up")
+                    .context(format!("writing to [textures/{idx}.dds]"))
+                    .context(format!("handling directive [TransformedTexture {{\n    to: \"textures/{idx}.dds\",\n}}]"))
+            })
+            .chain([anyhow!("hash mismatch").context("verifying [b.7z]")])
+            .collect_vec();
+        let causes = group_by_cause(&errors);
+        assert_eq!(
+            causes
+                .iter()
+                .map(|cause| (cause.message.as_str(), cause.errors.len()))
+                .collect_vec(),
+            vec![("texconv+wine not set up", 5), ("hash mismatch", 1)]
+        );
+        assert_eq!(
+            installation_failed(&errors),
+            "could not finish installation due to [6] errors ([2] distinct causes)"
+        );
+        let grouped = grouped(&errors);
+        assert!(
+            grouped.starts_with(
+                "1. [5x] texconv+wine not set up\n    - writing to [textures/0.dds]\n    - writing to [textures/1.dds]\n    - writing to \
+                 [textures/2.dds]\n    - and [2] more\n\n2. verifying [b.7z]"
+            ),
+            "{grouped}"
+        );
+        assert!(!grouped.contains("textures/3.dds"), "{grouped}");
+    }
+}
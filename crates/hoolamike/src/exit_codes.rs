@@ -0,0 +1,164 @@
+//! exit codes scripts wrapping hoolamike (Jackify, systemd units) can rely on. errors are classified where it's known what
+//! failed, the classification doesn't show up in the error itself
+
+use {crate::errors_log::AggregatedErrors, std::fmt};
+
+pub const SUCCESS: u8 = 0;
+/// anything not classified below (2 is taken by clap, for a bad command line)
+pub const GENERIC_FAILURE: u8 = 1;
+/// what shells report for a process killed by SIGINT, cancelled installs exit with it too
+pub const INTERRUPTED: u8 = 130;
+
+pub const HELP: &str = "\
+Exit codes:
+  0  success
+  1  any other failure
+  2  invalid command line
+  3  the config could not be read, or points at something which can't be used
+  4  archives could not be downloaded
+  5  directives could not be handled
+  6  manual intervention is needed (manual downloads, nexus archives waiting for a click, prompts refused by --non-interactive)
+130  the installation was cancelled (ctrl-c, or from the gui/tui), running the same command again resumes it
+when a run fails in several ways, the code comes from the first of: 130, 3, 5, 4, 6";
+
+/// ordered from the least to the most serious, a run failing in several ways exits with the most serious one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Failure {
+    ManualIntervention,
+    Downloads,
+    Directives,
+    Config,
+    /// whatever else failed was stopped on purpose
+    Cancelled,
+}
+
+impl Failure {
+    pub const fn code(self) -> u8 {
+        match self {
+            Failure::Config => 3,
+            Failure::Downloads => 4,
+            Failure::Directives => 5,
+            Failure::ManualIntervention => 6,
+            Failure::Cancelled => INTERRUPTED,
+        }
+    }
+
+    /// errors which are classified already keep their classification, it's the most specific one
+    pub fn mark(self, error: anyhow::Error) -> anyhow::Error {
+        match error.chain().any(|cause| cause.is::<Classified>()) {
+            true => error,
+            false => anyhow::Error::new(Classified { failure: self, error }),
+        }
+    }
+}
+
+/// transparent wrapper, it displays (and reports the causes of) the error it wraps
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Classified {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+#[extension_traits::extension(pub(crate) trait ClassifyExt)]
+impl<T> anyhow::Result<T> {
+    fn classify(self, failure: Failure) -> anyhow::Result<T> {
+        self.map_err(|error| failure.mark(error))
+    }
+}
+
+fn failure_of(error: &anyhow::Error) -> Option<Failure> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<Classified>())
+        .map(|Classified { failure, .. }| *failure)
+        .or_else(|| {
+            error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<AggregatedErrors>())
+                .and_then(|AggregatedErrors { errors, .. }| errors.iter().filter_map(failure_of).max())
+        })
+}
+
+pub fn exit_code_of(error: &anyhow::Error) -> u8 {
+    failure_of(error).map_or(GENERIC_FAILURE, Failure::code)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::anyhow};
+
+    #[test_log::test]
+    fn test_classification_is_invisible() {
+        let error = Failure::Downloads
+            .mark(anyhow!("connection reset").context("downloading [a.7z]"))
+            .context("error occurred");
+        assert_eq!(exit_code_of(&error), 4);
+        assert_eq!(
+            error
+                .chain()
+                .map(|cause| cause.to_string())
+                .collect::<Vec<_>>(),
+            ["error occurred", "downloading [a.7z]", "connection reset"]
+        );
+        assert_eq!(exit_code_of(&anyhow!("something else")), GENERIC_FAILURE);
+    }
+
+    #[test_log::test]
+    fn test_the_most_specific_classification_wins() {
+        let manual = Failure::ManualIntervention.mark(anyhow!("Manual action is required"));
+        assert_eq!(exit_code_of(&Failure::Downloads.mark(manual)), 6);
+    }
+
+    #[test_log::test]
+    fn test_the_most_serious_failure_of_a_run_wins() {
+        let run = |errors: Vec<anyhow::Error>| {
+            anyhow::Error::new(AggregatedErrors {
+                summary: format!("could not finish installation due to [{}] errors", errors.len()),
+                errors,
+            })
+            .context("error occurred")
+        };
+        assert_eq!(
+            exit_code_of(&run(vec![
+                Failure::ManualIntervention.mark(anyhow!("click")),
+                Failure::ManualIntervention.mark(anyhow!("click again")),
+            ])),
+            6
+        );
+        assert_eq!(
+            exit_code_of(&run(vec![
+                Failure::ManualIntervention.mark(anyhow!("click")),
+                anyhow!("unclassified"),
+                Failure::Downloads.mark(anyhow!("connection reset")),
+            ])),
+            4
+        );
+        assert_eq!(
+            exit_code_of(&run(vec![
+                Failure::Directives.mark(anyhow!("bad patch")),
+                Failure::Config.mark(anyhow!("no game"))
+            ])),
+            3
+        );
+        assert_eq!(exit_code_of(&run(vec![anyhow!("unclassified")])), GENERIC_FAILURE);
+        assert_eq!(
+            exit_code_of(&run(vec![
+                Failure::Config.mark(anyhow!("no game")),
+                Failure::Cancelled.mark(anyhow!("installation was cancelled"))
+            ])),
+            INTERRUPTED
+        );
+    }
+}
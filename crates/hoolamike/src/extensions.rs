@@ -1,15 +1,24 @@
 pub mod fallout_new_vegas_4gb_patch;
+pub mod prefix_bootstrap;
 pub mod tale_of_two_wastelands_installer;
 pub mod texconv_wine {
     use {
+        schemars::JsonSchema,
         serde::{Deserialize, Serialize},
         std::path::PathBuf,
     };
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
+    #[schemars(rename = "TexconvWineConfig")]
     pub struct ExtensionConfig {
+        /// not used on windows, texconv runs directly there
+        #[serde(default = "default_wine_path")]
         pub wine_path: PathBuf,
         pub texconv_path: PathBuf,
     }
+
+    fn default_wine_path() -> PathBuf {
+        PathBuf::from("wine")
+    }
 }
@@ -0,0 +1,480 @@
+//! installs the windows runtime dependencies tools launched through MO2 (xEdit, pandora, etc.) need into the wine/proton prefix.
+//! Wabbajack doesn't have to care about those on windows, on linux a missing one usually shows up as a crash after installation.
+
+use {
+    crate::{config_file::HoolamikeConfig, progress_bars_v2::count_progress_style},
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    schemars::JsonSchema,
+    serde::{Deserialize, Serialize},
+    std::{
+        path::{Path, PathBuf},
+        process::Command,
+    },
+    tabled::{
+        Tabled,
+        settings::{Color, Style, object::Columns},
+    },
+    tap::prelude::*,
+    tracing::{info, info_span, instrument},
+    tracing_indicatif::span_ext::IndicatifSpanExt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum Component {
+    #[serde(rename = "vcrun2022")]
+    Vcrun2022,
+    #[serde(rename = "dotnet48")]
+    Dotnet48,
+    #[serde(rename = "corefonts")]
+    Corefonts,
+    #[serde(rename = "d3dcompiler_47")]
+    D3dcompiler47,
+}
+
+/// how to install a component and how to tell it's already there
+#[derive(Debug, Clone, Copy)]
+pub struct ComponentSpec {
+    pub winetricks_verb: &'static str,
+    /// (installer file name, silent install arguments), looked up in [ExtensionConfig::installers_directory]
+    pub bundled_installer: Option<(&'static str, &'static [&'static str])>,
+    /// relative to the prefix, all of them have to exist
+    pub installed_files: &'static [&'static str],
+}
+
+impl Component {
+    pub const ALL: &[Self] = &[Self::Vcrun2022, Self::Dotnet48, Self::Corefonts, Self::D3dcompiler47];
+
+    pub fn spec(self) -> ComponentSpec {
+        match self {
+            Component::Vcrun2022 => ComponentSpec {
+                winetricks_verb: "vcrun2022",
+                bundled_installer: Some(("VC_redist.x64.exe", &["/install", "/quiet", "/norestart"])),
+                installed_files: &["drive_c/windows/system32/vcruntime140_1.dll", "drive_c/windows/system32/msvcp140.dll"],
+            },
+            Component::Dotnet48 => ComponentSpec {
+                winetricks_verb: "dotnet48",
+                bundled_installer: Some(("ndp48-x86-x64-allos-enu.exe", &["/q", "/norestart"])),
+                installed_files: &["drive_c/windows/Microsoft.NET/Framework64/v4.0.30319/mscorlib.dll"],
+            },
+            Component::Corefonts => ComponentSpec {
+                winetricks_verb: "corefonts",
+                bundled_installer: None,
+                installed_files: &["drive_c/windows/Fonts/arial.ttf", "drive_c/windows/Fonts/times.ttf"],
+            },
+            Component::D3dcompiler47 => ComponentSpec {
+                winetricks_verb: "d3dcompiler_47",
+                bundled_installer: None,
+                installed_files: &["drive_c/windows/system32/d3dcompiler_47.dll"],
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.spec().winetricks_verb
+    }
+
+    pub fn is_installed(self, prefix: &Path) -> bool {
+        self.spec()
+            .installed_files
+            .iter()
+            .all(|file| prefix.join(file).exists())
+    }
+}
+
+impl std::fmt::Display for Component {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+const AUTO_PREFIX: &str = "auto";
+const AUTO_PREFIX_DIRECTORY: &str = "prefix";
+const MARKERS_DIRECTORY: &str = ".hoolamike-bootstrap";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(from = "PathBuf", into = "PathBuf")]
+pub enum PrefixTarget {
+    /// creates one under the installation directory
+    #[default]
+    Auto,
+    Path(PathBuf),
+}
+
+impl From<PathBuf> for PrefixTarget {
+    fn from(path: PathBuf) -> Self {
+        match path.as_os_str() == AUTO_PREFIX {
+            true => Self::Auto,
+            false => Self::Path(path),
+        }
+    }
+}
+
+impl From<PrefixTarget> for PathBuf {
+    fn from(target: PrefixTarget) -> Self {
+        match target {
+            PrefixTarget::Auto => PathBuf::from(AUTO_PREFIX),
+            PrefixTarget::Path(path) => path,
+        }
+    }
+}
+
+impl PrefixTarget {
+    pub fn resolve(&self, installation_path: &Path) -> PathBuf {
+        match self {
+            PrefixTarget::Auto => installation_path.join(AUTO_PREFIX_DIRECTORY),
+            PrefixTarget::Path(path) => path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, derivative::Derivative)]
+#[derivative(Default)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "PrefixBootstrapConfig")]
+pub struct ExtensionConfig {
+    /// prefix that will eventually run MO2, `auto` creates one under the installation directory
+    #[serde(default)]
+    #[schemars(with = "PathBuf")]
+    pub prefix: PrefixTarget,
+    #[derivative(Default(value = "default_wine_path()"))]
+    #[serde(default = "default_wine_path")]
+    pub wine_path: PathBuf,
+    /// directory with silent installers (VC_redist.x64.exe, ndp48-x86-x64-allos-enu.exe), used when winetricks is not installed
+    #[serde(default)]
+    pub installers_directory: Option<PathBuf>,
+    #[derivative(Default(value = "Component::ALL.to_vec()"))]
+    pub components: Vec<Component>,
+}
+
+fn default_wine_path() -> PathBuf {
+    PathBuf::from("wine")
+}
+
+#[derive(clap::Args, Clone)]
+pub struct CliConfig {
+    /// only checks which components are present in the prefix, nothing gets installed
+    #[arg(long)]
+    doctor: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub prefix: PathBuf,
+}
+
+/// everything that touches the outside world, so that the install logic can be tested without wine
+pub trait Runner {
+    fn which(&self, program: &str) -> Option<PathBuf>;
+    fn run(&self, command: &BootstrapCommand) -> Result<()>;
+}
+
+pub struct SystemRunner;
+
+impl Runner for SystemRunner {
+    fn which(&self, program: &str) -> Option<PathBuf> {
+        which::which(program).ok()
+    }
+
+    #[instrument(skip(self))]
+    fn run(&self, BootstrapCommand { program, args, prefix }: &BootstrapCommand) -> Result<()> {
+        Command::new(program)
+            .args(args)
+            .env("WINEPREFIX", prefix)
+            .env("WINEDEBUG", "-all")
+            .output()
+            .with_context(|| format!("spawning [{}]", program.display()))
+            .and_then(|output| match output.status.success() {
+                true => Ok(()),
+                false => Err(anyhow::anyhow!(
+                    "status: {}\n\nstdout:\n{}\n\nstderr:\n{}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                )),
+            })
+            .with_context(|| format!("running [{}] with args {args:?}", program.display()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallMethod {
+    Winetricks(PathBuf),
+    Bundled { installer: PathBuf, args: &'static [&'static str] },
+}
+
+/// winetricks knows how to install everything, bundled installers are the fallback for machines without it
+pub fn install_method(component: Component, winetricks: Option<&Path>, installers_directory: Option<&Path>) -> Result<InstallMethod> {
+    match winetricks {
+        Some(winetricks) => Ok(InstallMethod::Winetricks(winetricks.to_owned())),
+        None => component
+            .spec()
+            .bundled_installer
+            .with_context(|| format!("[{component}] can only be installed using winetricks, which was not found in PATH"))
+            .and_then(|(installer, args)| {
+                installers_directory
+                    .map(|directory| directory.join(installer))
+                    .filter(|installer| installer.exists())
+                    .map(|installer| InstallMethod::Bundled { installer, args })
+                    .with_context(|| format!("winetricks was not found in PATH and [{installer}] is missing from installers_directory"))
+            }),
+    }
+}
+
+impl InstallMethod {
+    pub fn command(&self, component: Component, wine_path: &Path, prefix: &Path) -> BootstrapCommand {
+        match self {
+            InstallMethod::Winetricks(winetricks) => BootstrapCommand {
+                program: winetricks.clone(),
+                args: vec!["-q".to_string(), component.name().to_string()],
+                prefix: prefix.to_owned(),
+            },
+            InstallMethod::Bundled { installer, args } => BootstrapCommand {
+                program: wine_path.to_owned(),
+                args: std::iter::once(installer.display().to_string())
+                    .chain(args.iter().map(|arg| arg.to_string()))
+                    .collect(),
+                prefix: prefix.to_owned(),
+            },
+        }
+    }
+}
+
+pub fn marker_path(prefix: &Path, component: Component) -> PathBuf {
+    prefix
+        .join(MARKERS_DIRECTORY)
+        .join(format!("{component}.done"))
+}
+
+fn initialize_prefix(runner: &dyn Runner, wine_path: &Path, prefix: &Path) -> Result<()> {
+    std::fs::create_dir_all(prefix).with_context(|| format!("creating prefix directory at [{}]", prefix.display()))?;
+    match prefix.join("system.reg").exists() {
+        true => Ok(()),
+        false => runner
+            .run(&BootstrapCommand {
+                program: wine_path.to_owned(),
+                args: vec!["wineboot".to_string(), "--init".to_string()],
+                prefix: prefix.to_owned(),
+            })
+            .context("initializing wine prefix"),
+    }
+}
+
+/// installs every component that doesn't have a marker yet, returns the ones that got installed
+#[instrument(skip(runner, config))]
+pub fn bootstrap_prefix(runner: &dyn Runner, config: &ExtensionConfig, prefix: &Path) -> Result<Vec<Component>> {
+    initialize_prefix(runner, &config.wine_path, prefix)?;
+    let winetricks = runner.which("winetricks");
+    let components = config.components.iter().copied().unique().collect_vec();
+    let bootstrapping = info_span!("bootstrapping_prefix").tap_mut(|pb| {
+        pb.pb_set_style(&count_progress_style());
+        pb.pb_set_length(components.len() as _);
+    });
+    let _bootstrapping = bootstrapping.enter();
+    components
+        .into_iter()
+        .filter_map(|component| {
+            info_span!("component", %component)
+                .in_scope(|| match marker_path(prefix, component).exists() {
+                    true => {
+                        info!("[{component}] already installed, skipping");
+                        Ok(None)
+                    }
+                    false => install_method(component, winetricks.as_deref(), config.installers_directory.as_deref())
+                        .map(|method| method.command(component, &config.wine_path, prefix))
+                        .and_then(|command| runner.run(&command))
+                        .and_then(|_| {
+                            let marker = marker_path(prefix, component);
+                            marker
+                                .parent()
+                                .map(std::fs::create_dir_all)
+                                .transpose()
+                                .and_then(|_| std::fs::write(&marker, ""))
+                                .with_context(|| format!("writing marker at [{}]", marker.display()))
+                        })
+                        .map(|_| Some(component))
+                        .tap_ok(|_| info!("[{component}] installed"))
+                        .with_context(|| format!("installing [{component}]")),
+                })
+                .tap(|_| bootstrapping.pb_inc(1))
+                .transpose()
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Tabled)]
+pub struct ComponentStatus {
+    pub component: Component,
+    pub marker: bool,
+    pub present: bool,
+}
+
+pub fn doctor(config: &ExtensionConfig, prefix: &Path) -> Vec<ComponentStatus> {
+    config
+        .components
+        .iter()
+        .copied()
+        .unique()
+        .map(|component| ComponentStatus {
+            component,
+            marker: marker_path(prefix, component).exists(),
+            present: component.is_installed(prefix),
+        })
+        .collect()
+}
+
+fn print_doctor_report(statuses: &[ComponentStatus]) -> Result<()> {
+    println!(
+        "{}",
+        tabled::Table::new(statuses)
+            .with(Style::modern())
+            .modify(Columns::single(0), Color::FG_GREEN)
+    );
+    statuses
+        .iter()
+        .filter(|status| !status.present)
+        .map(|status| status.component)
+        .collect_vec()
+        .pipe(|missing| match missing.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("missing components: [{}]", missing.iter().join(", "))),
+        })
+}
+
+pub fn run(CliConfig { doctor: doctor_only }: CliConfig, config: HoolamikeConfig) -> Result<()> {
+    let extension = config
+        .extras
+        .as_ref()
+        .and_then(|extras| extras.prefix_bootstrap.as_ref())
+        .context("no extras.prefix_bootstrap section in config")?;
+    let prefix = extension
+        .prefix
+        .resolve(&config.installation.installation_path);
+    info!("using prefix at [{}]", prefix.display());
+    match doctor_only {
+        true => Ok(()),
+        false => bootstrap_prefix(&SystemRunner, extension, &prefix).map(|installed| info!("installed [{}] components", installed.len())),
+    }
+    .and_then(|_| print_doctor_report(&doctor(extension, &prefix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::cell::RefCell};
+
+    #[derive(Default)]
+    struct MockRunner {
+        winetricks: Option<PathBuf>,
+        ran: RefCell<Vec<BootstrapCommand>>,
+    }
+
+    impl Runner for MockRunner {
+        fn which(&self, program: &str) -> Option<PathBuf> {
+            self.winetricks.clone().filter(|_| program == "winetricks")
+        }
+
+        fn run(&self, command: &BootstrapCommand) -> Result<()> {
+            self.ran.borrow_mut().push(command.clone());
+            Ok(())
+        }
+    }
+
+    #[test_log::test]
+    fn test_registry_is_complete() {
+        Component::ALL.iter().for_each(|component| {
+            let spec = component.spec();
+            assert!(!spec.installed_files.is_empty(), "[{component}] can't be verified");
+            assert_eq!(
+                serde_yaml::to_string(component).unwrap().trim(),
+                spec.winetricks_verb,
+                "config name of [{component}] should match its winetricks verb"
+            );
+        });
+    }
+
+    #[test_log::test]
+    fn test_install_method_selection() -> Result<()> {
+        let installers = tempfile::tempdir()?;
+        std::fs::write(installers.path().join("VC_redist.x64.exe"), "")?;
+        let winetricks = Path::new("/usr/bin/winetricks");
+
+        assert_eq!(
+            install_method(Component::Vcrun2022, Some(winetricks), Some(installers.path()))?,
+            InstallMethod::Winetricks(winetricks.to_owned())
+        );
+        assert!(matches!(
+            install_method(Component::Vcrun2022, None, Some(installers.path()))?,
+            InstallMethod::Bundled { installer, .. } if installer == installers.path().join("VC_redist.x64.exe")
+        ));
+        // not downloaded
+        assert!(install_method(Component::Dotnet48, None, Some(installers.path())).is_err());
+        // no bundled installer at all
+        assert!(install_method(Component::Corefonts, None, Some(installers.path())).is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_markers_make_bootstrap_idempotent() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        let config = ExtensionConfig {
+            components: vec![Component::Corefonts, Component::D3dcompiler47, Component::Corefonts],
+            ..Default::default()
+        };
+        let runner = MockRunner {
+            winetricks: Some(PathBuf::from("winetricks")),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            bootstrap_prefix(&runner, &config, prefix.path())?,
+            [Component::Corefonts, Component::D3dcompiler47]
+        );
+        assert_eq!(
+            runner
+                .ran
+                .borrow()
+                .iter()
+                .map(|command| command.args.join(" "))
+                .collect_vec(),
+            ["wineboot --init", "-q corefonts", "-q d3dcompiler_47"]
+        );
+        assert!(marker_path(prefix.path(), Component::Corefonts).exists());
+
+        // pretend wineboot created the prefix
+        std::fs::write(prefix.path().join("system.reg"), "")?;
+        runner.ran.borrow_mut().clear();
+        assert!(bootstrap_prefix(&runner, &config, prefix.path())?.is_empty());
+        assert!(runner.ran.borrow().is_empty());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_doctor_checks_files_not_markers() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        let config = ExtensionConfig {
+            components: vec![Component::D3dcompiler47],
+            ..Default::default()
+        };
+        std::fs::create_dir_all(prefix.path().join(MARKERS_DIRECTORY))?;
+        std::fs::write(marker_path(prefix.path(), Component::D3dcompiler47), "")?;
+        assert_eq!(
+            doctor(&config, prefix.path()),
+            [ComponentStatus {
+                component: Component::D3dcompiler47,
+                marker: true,
+                present: false,
+            }]
+        );
+        std::fs::create_dir_all(prefix.path().join("drive_c/windows/system32"))?;
+        std::fs::write(
+            prefix
+                .path()
+                .join("drive_c/windows/system32/d3dcompiler_47.dll"),
+            "",
+        )?;
+        assert!(doctor(&config, prefix.path())[0].present);
+        Ok(())
+    }
+}
@@ -4,11 +4,11 @@ use {
         config_file::HoolamikeConfig,
         extensions::tale_of_two_wastelands_installer::manifest_file::location::FolderLocation,
         modlist_json::GameName,
-        progress_bars_v2::{IndicatifWrapIoExt, count_progress_style},
+        progress_bars_v2::{IndicatifWrapIoExt, count_progress_style, rate_progress_style},
         utils::{ExistingPathRead, PathReadWrite, ReadableCatchUnwindExt, scoped_temp_file},
     },
     anyhow::{Context, Result},
-    case_insensitive_path::{CaseInsensitivePathBuf, PathExistsUtf8Ext},
+    case_insensitive_path::{CaseInsensitivePathBuf, ExistingPath, PathExistsUtf8Ext},
     handle_asset::AssetContext,
     itertools::Itertools,
     manifest_file::{
@@ -20,13 +20,14 @@ use {
     },
     num::ToPrimitive,
     rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    schemars::JsonSchema,
     serde::{Deserialize, Serialize},
     std::{
         borrow::Cow,
         collections::BTreeMap,
         convert::identity,
         io::{BufReader, Read},
-        path::PathBuf,
+        path::{Path, PathBuf},
         str::FromStr,
         sync::Arc,
     },
@@ -38,6 +39,28 @@ use {
 
 pub mod manifest_file;
 pub mod templating {
+    use {
+        anyhow::{Context, Result},
+        typed_path::Utf8TypedPath,
+    };
+
+    /// templates are written with windows separators in mind (`%DESTINATION%\Data`), so after substitution
+    /// the remainder has to be converted to native ones - otherwise a file named `TTW\Data` gets created on linux
+    pub fn normalize_separators(resolved: &str) -> Result<String> {
+        #[cfg(unix)]
+        let resolved = resolved.replace(r#"\"#, "/");
+        match Utf8TypedPath::derive(&resolved) {
+            Utf8TypedPath::Unix(path) => path
+                .with_platform_encoding_checked()
+                .context("converting to platform encoding"),
+            Utf8TypedPath::Windows(path) => path
+                .with_platform_encoding_checked()
+                .context("converting to platform encoding"),
+        }
+        .map(|encoded| encoded.normalize().to_string())
+        .with_context(|| format!("normalizing separators of [{resolved}]"))
+    }
+
     /// returns (left, variable_name, right)
     pub fn find_template_marker(input: &str) -> Option<(&str, &str, &str)> {
         input.split_once('%').and_then(|(left, right)| {
@@ -48,10 +71,13 @@ pub mod templating {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(rename = "TaleOfTwoWastelandsConfig")]
 pub struct ExtensionConfig {
+    /// the installer (.mpi) file
     pub path_to_ttw_mpi_file: PathBuf,
+    /// installer variables (DESTINATION, etc.), FO3ROOT and FNVROOT are filled in from the games section
     pub variables: BTreeMap<String, String>,
 }
 
@@ -60,9 +86,13 @@ pub struct CliConfig {
     /// will only run assets containing this chunk of text, useful for debugging
     #[arg(long)]
     contains: Vec<String>,
+    /// ignore the checkpoint left by previous (failed) run and install everything from scratch
+    #[arg(long)]
+    ttw_fresh: bool,
 }
 
 const MANIFEST_PATH: &str = "_package/index.json";
+const DESTINATION_VARIABLE: &str = "%DESTINATION%";
 
 type LocationsLookup = BTreeMap<LocationIndex, Location>;
 
@@ -168,6 +198,20 @@ impl VariablesContext {
     }
 }
 
+impl VariablesContext {
+    /// like [VariablesContext::resolve_variable], but for values which are paths. post commands are whole command lines
+    /// rather than paths, they go through [VariablesContext::resolve_variable] - their path arguments are normalized as
+    /// they're parsed ([post_commands])
+    fn resolve_path(&self, maybe_with_variable: &str) -> Result<String> {
+        match self::templating::find_template_marker(maybe_with_variable) {
+            Some(_) => self
+                .resolve_variable(maybe_with_variable)
+                .and_then(|resolved| self::templating::normalize_separators(&resolved)),
+            None => Ok(maybe_with_variable.to_string()),
+        }
+    }
+}
+
 impl MaybeFullLocation {
     fn lookup_from_both_source_and_target(self, source: &FullLocation) -> FullLocation {
         match self.path {
@@ -303,8 +347,38 @@ impl FullLocation {
     }
 }
 
+pub fn read_manifest(path_to_ttw_mpi_file: &ExistingPath) -> Result<manifest_file::Manifest> {
+    crate::compression::bethesda_archive::BethesdaArchive::open(path_to_ttw_mpi_file)
+        .and_then(|mut archive| {
+            archive
+                .get_handle(&CaseInsensitivePathBuf::from_str(MANIFEST_PATH).expect("bad encoding of manifest path"))
+                .context("extracting the manifest out of MPI file")
+        })
+        .map(BufReader::new)
+        .and_then(|reader| {
+            String::new()
+                .pipe(|mut out| {
+                    info_span!("extracting_manifest")
+                        .wrap_read(0, reader)
+                        .read_to_string(&mut out)
+                        .map(|_| out)
+                        .context("extracting")
+                })
+                .and_then(|manifest| serde_json::from_str::<manifest_file::Manifest>(&manifest).context("parsing"))
+                .context("parsing extracted manifest file")
+        })
+        .with_context(|| format!("extracting manifest out of [{path_to_ttw_mpi_file:?}]"))
+}
+
+/// transcoding only reads its source and writes its own output file, so unlike esm patching or archive building
+/// these don't depend on the order of other operations
+fn is_independent_media<'a>(location: Option<&Location>, mut assets: impl Iterator<Item = &'a manifest_file::asset::Asset>) -> bool {
+    use manifest_file::asset::Asset;
+    matches!(location, Some(Location::Folder(_))) && assets.all(|asset| matches!(asset, Asset::OggEnc2(_) | Asset::AudioEnc(_) | Asset::XwmaFuz(_)))
+}
+
 #[instrument(skip_all)]
-pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeConfig) -> Result<()> {
+pub fn install(CliConfig { contains, ttw_fresh }: CliConfig, hoolamike_config: HoolamikeConfig) -> Result<()> {
     let ExtensionConfig {
         path_to_ttw_mpi_file,
         variables: ttw_config_variables,
@@ -327,6 +401,20 @@ pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeCon
 
     let path_to_ttw_mpi_file = path_to_ttw_mpi_file.exists_utf8()?;
 
+    let manifest = read_manifest(&path_to_ttw_mpi_file)?;
+    validation::Requirements::from_manifest(&manifest)
+        .check(ttw_config_variables, &hoolamike_config.games)
+        .pipe(|problems| match problems.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!(
+                "installer cannot be run with current configuration:\n{}",
+                problems
+                    .iter()
+                    .map(|problem| format!(" - {problem}"))
+                    .join("\n")
+            )),
+        })
+        .context("validating tale of two wastelands configuration")?;
     let manifest_file::Manifest {
         package,
         variables,
@@ -336,26 +424,7 @@ pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeCon
         file_attrs,
         post_commands,
         assets,
-    } = crate::compression::bethesda_archive::BethesdaArchive::open(&path_to_ttw_mpi_file)
-        .and_then(|mut archive| {
-            archive
-                .get_handle(&CaseInsensitivePathBuf::from_str(MANIFEST_PATH).expect("bad encoding of manifest path"))
-                .context("extracting the manifest out of MPI file")
-        })
-        .map(BufReader::new)
-        .and_then(|reader| {
-            String::new()
-                .pipe(|mut out| {
-                    info_span!("extracting_manifest")
-                        .wrap_read(0, reader)
-                        .read_to_string(&mut out)
-                        .map(|_| out)
-                        .context("extracting")
-                })
-                .and_then(|manifest| serde_json::from_str::<manifest_file::Manifest>(&manifest).context("parsing"))
-                .context("parsing extracted manifest file")
-        })
-        .with_context(|| format!("extracting manifest out of [{path_to_ttw_mpi_file:?}]"))?;
+    } = manifest;
     info!(package=%serde_json::to_string_pretty(&package).unwrap_or_else(|e| format!("[{e:#?}]")), "got manifest file");
 
     let preheated_mpi_file = PreheatedArchive::from_archive_concurrent(&path_to_ttw_mpi_file, 64)
@@ -390,13 +459,14 @@ pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeCon
                 .map(LocationIndex)
                 .and_then(|idx| {
                     variables_context
-                        .resolve_variable(location.value_mut())
-                        .map(|resolved| (idx, location.tap_mut(|location| *location.value_mut() = resolved.to_string())))
+                        .resolve_path(location.value_mut())
+                        .map(|resolved| (idx, location.tap_mut(|location| *location.value_mut() = resolved)))
                 })
         })
         .collect::<Result<BTreeMap<LocationIndex, Location>>>()
         .context("collecting locations")?;
 
+    // command lines, not paths - see [VariablesContext::resolve_path]
     let post_commands = post_commands
         .into_iter()
         .map(|p| {
@@ -411,18 +481,26 @@ pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeCon
         .into_iter()
         .map(|p| {
             variables_context
-                .resolve_variable(&p.value)
-                .map(|updated| p.tap_mut(|p| p.value = updated.to_string()))
+                .resolve_path(&p.value)
+                .map(|updated| p.tap_mut(|p| p.value = updated))
         })
         .collect::<Result<Vec<_>>>()
-        .context("collecting post commands")?;
+        .context("collecting file attributes")?;
+
+    let checkpoint = variables_context
+        .resolve_path(DESTINATION_VARIABLE)
+        .context("resolving destination for the checkpoint")
+        .and_then(|destination| checkpoint::CheckpointFile::open(Path::new(&destination), &package.version, ttw_fresh))
+        .context("opening checkpoint file")?;
 
     let contains = Arc::new(contains);
+    // index in the manifest identifies the operation in the checkpoint
+    let assets = assets.into_iter().enumerate().collect_vec();
     let assets = match contains.is_empty() {
         true => assets,
         false => assets
             .into_par_iter()
-            .filter(|a| format!("{a:?}").pipe(|text| contains.iter().all(|phrase| text.contains(phrase))))
+            .filter(|(_, a)| format!("{a:?}").pipe(|text| contains.iter().all(|phrase| text.contains(phrase))))
             .collect::<Vec<_>>(),
     };
     let asset_count = assets.len() as u64;
@@ -431,136 +509,179 @@ pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeCon
         pb.pb_set_length(asset_count);
     });
     let locations = Arc::new(locations);
+    let checkpoint = parking_lot::Mutex::new(checkpoint);
+
+    let handle_location = |(location, assets): (LocationIndex, Vec<(usize, manifest_file::asset::Asset)>), rate: Option<&tracing::Span>| -> Result<u64> {
+        let asset_chunk_len = assets.len() as u64;
+        let location_debug = locations
+            .get(&location)
+            .map(|l| format!("{} ({location:#?})", l.name()))
+            .unwrap_or_else(|| format!("UNKNOWN ({location:?})"));
+        let (indices, assets): (Vec<_>, Vec<_>) = assets.into_iter().unzip();
+        if checkpoint.lock().all_completed(&indices) {
+            info!(location=%location_debug, "all [{asset_chunk_len}] assets were completed in previous run, skipping");
+            return Ok(asset_chunk_len);
+        }
+        let outputs = checkpoint::chunk_outputs(locations.get(&location), &assets)?;
+        let handling_assets_for_location = info_span!("handling_assets_for_location", location=%location_debug).tap(|pb| {
+            pb.pb_set_style(&count_progress_style());
+            pb.pb_set_length(asset_chunk_len);
+        });
+        let repacking_context = RepackingContext::new(locations.clone());
+        let preheated_sources = assets
+            .iter()
+            .map(|asset| asset.target())
+            .map(|source| {
+                locations
+                    .get(&source)
+                    .with_context(|| format!("source not found: [{source:?}]"))
+                    .map(|location| match location {
+                        Location::Folder(_) => None,
+                        Location::ReadArchive(archive) => Some((source, archive.inner.clone())),
+                        Location::WriteArchive(_) => None,
+                    })
+            })
+            .collect::<Result<Vec<_>>>()
+            .context("not all locations could be found")
+            .and_then(|locations| {
+                locations
+                    .into_iter()
+                    .flatten()
+                    .map(|(source, ReadArchiveLocation { name: _, value })| {
+                        CaseInsensitivePathBuf::from_str(&value)
+                            .and_then(|archive_path| archive_path.try_exists())
+                            .and_then(|archive_path| PreheatedArchive::from_archive_concurrent(&archive_path, 128).map(|preheated| (source, preheated)))
+                    })
+                    .collect::<Result<BTreeMap<_, _>>>()
+                    .context("preheating failed")
+            })?;
+        let asset_context = handle_asset::AssetContext {
+            preheated_mpi_file: preheated_mpi_file.clone(),
+            repacking_context: repacking_context.clone(),
+            preheated: Arc::new(preheated_sources),
+        };
+
+        handling_assets_for_location
+            .clone()
+            .in_scope(move || {
+                assets
+                    .into_par_iter()
+                    .inspect({
+                        let rate = rate.cloned();
+                        move |_| {
+                            handling_assets_for_location.pb_inc(1);
+                            if let Some(rate) = rate.as_ref() {
+                                rate.pb_inc(1)
+                            }
+                        }
+                    })
+                    .map({
+                        let asset_context = asset_context.clone();
+                        move |asset| {
+                            info_span!("handling_asset", kind=?manifest_file::asset::AssetRawKind::from(&asset), asset=%asset.name()).in_scope(|| {
+                                tracing::trace!("starting");
+                                asset_context
+                                    .clone()
+                                    .pipe(|c| {
+                                        std::panic::catch_unwind(|| c.handle_asset(asset.clone()))
+                                            .for_anyhow()
+                                            .and_then(identity)
+                                    })
+                                    .with_context(|| format!("handling [{asset:#?}]"))
+                                    .inspect(|_| info!("[OK]"))
+                            })
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()
+                    .context("executing asset operations")
+                    .map(move |lazy_archive| {
+                        lazy_archive
+                            .into_iter()
+                            .flatten()
+                            .collect_vec()
+                            .into_iter()
+                            .peekable()
+                            .pipe(|mut archive| {
+                                archive
+                                    .peek()
+                                    .map(|chunk| chunk.target.clone())
+                                    .map(|first_target| {
+                                        LazyArchive::new(&first_target)
+                                            .pipe(|lazy_archive| archive.fold(lazy_archive, |a, entry| a.tap_mut(|a| a.insert(entry.key, entry.buffer))))
+                                    })
+                            })
+                    })
+                    .and_then(|archives| {
+                        let building_archives = info_span!("building_archive");
+                        building_archives.clone().in_scope(|| {
+                            archives
+                                .into_iter()
+                                .inspect(|_| building_archives.pb_inc(1))
+                                .try_for_each(|descriptor| {
+                                    build_bsa::build_bsa(descriptor, |archive, options, output_path| {
+                                        output_path
+                                            .normalize()
+                                            .as_path()
+                                            .open_file_write()
+                                            .and_then(|(output_path, output)| {
+                                                archive
+                                                    .write(&mut tracing::Span::current().wrap_write(0, output), &options)
+                                                    .with_context(|| format!("writing built bsa file to {output_path:?}"))
+                                                    .tap_ok(|_| info!(?output_path, "[OK]"))
+                                            })
+                                    })
+                                })
+                        })
+                    })
+            })
+            .and_then(|_| {
+                checkpoint
+                    .lock()
+                    .record_each(indices.iter().copied().zip(outputs))
+                    .context("recording checkpoint")
+            })
+            .map(|_| asset_chunk_len)
+    };
+
+    let (media, ordered): (Vec<_>, Vec<_>) = assets
+        .into_iter()
+        .sorted_unstable_by_key(|(_, a)| a.target())
+        .chunk_by(|(_, a)| a.target())
+        .into_iter()
+        .map(|(location, assets)| (location, assets.into_iter().collect_vec()))
+        .collect_vec()
+        .into_iter()
+        .partition(|(location, assets)| is_independent_media(locations.get(location), assets.iter().map(|(_, asset)| asset)));
 
     handling_assets
         .clone()
         .in_scope(|| {
-            assets
-                .into_iter()
-                .sorted_unstable_by_key(|a| a.target())
-                .chunk_by(|a| a.target())
-                .into_iter()
-                .map(|(location, assets)| (location, assets.into_iter().collect_vec()))
-                .collect_vec()
-                .pipe(|by_location| {
-                    by_location
-                        .into_iter()
-                        .map(move |(location, assets)| {
-                            let asset_chunk_len = assets.len() as u64;
-                            let location_debug = locations
-                                .get(&location)
-                                .map(|l| format!("{} ({location:#?})", l.name()))
-                                .unwrap_or_else(|| format!("UNKNOWN ({location:?})"));
-                            let handling_assets_for_location = info_span!("handling_assets_for_location", location=%location_debug).tap(|pb| {
-                                pb.pb_set_style(&count_progress_style());
-                                pb.pb_set_length(asset_chunk_len);
-                            });
-                            let repacking_context = RepackingContext::new(locations.clone());
-                            let preheated_sources = assets
-                                .iter()
-                                .map(|asset| asset.target())
-                                .map(|source| {
-                                    locations
-                                        .get(&source)
-                                        .with_context(|| format!("source not found: [{source:?}]"))
-                                        .map(|location| match location {
-                                            Location::Folder(_) => None,
-                                            Location::ReadArchive(archive) => Some((source, archive.inner.clone())),
-                                            Location::WriteArchive(_) => None,
-                                        })
-                                })
-                                .collect::<Result<Vec<_>>>()
-                                .context("not all locations could be found")
-                                .and_then(|locations| {
-                                    locations
-                                        .into_iter()
-                                        .flatten()
-                                        .map(|(source, ReadArchiveLocation { name: _, value })| {
-                                            CaseInsensitivePathBuf::from_str(&value)
-                                                .and_then(|archive_path| archive_path.try_exists())
-                                                .and_then(|archive_path| {
-                                                    PreheatedArchive::from_archive_concurrent(&archive_path, 128).map(|preheated| (source, preheated))
-                                                })
-                                        })
-                                        .collect::<Result<BTreeMap<_, _>>>()
-                                        .context("preheating failed")
-                                })?;
-                            let asset_context = handle_asset::AssetContext {
-                                preheated_mpi_file: preheated_mpi_file.clone(),
-                                repacking_context: repacking_context.clone(),
-                                preheated: Arc::new(preheated_sources),
-                            };
-
-                            handling_assets_for_location
-                                .clone()
-                                .in_scope(move || {
-                                    assets
-                                        .into_par_iter()
-                                        .inspect(move |_| handling_assets_for_location.pb_inc(1))
-                                        .map({
-                                            let asset_context = asset_context.clone();
-                                            move |asset| {
-                                                info_span!("handling_asset", kind=?manifest_file::asset::AssetRawKind::from(&asset), asset=%asset.name())
-                                                    .in_scope(|| {
-                                                        tracing::trace!("starting");
-                                                        asset_context
-                                                            .clone()
-                                                            .pipe(|c| {
-                                                                std::panic::catch_unwind(|| c.handle_asset(asset.clone()))
-                                                                    .for_anyhow()
-                                                                    .and_then(identity)
-                                                            })
-                                                            .with_context(|| format!("handling [{asset:#?}]"))
-                                                            .inspect(|_| info!("[OK]"))
-                                                    })
-                                            }
-                                        })
-                                        .collect::<Result<Vec<_>>>()
-                                        .context("executing asset operations")
-                                        .map(move |lazy_archive| {
-                                            lazy_archive
-                                                .into_iter()
-                                                .flatten()
-                                                .collect_vec()
-                                                .into_iter()
-                                                .peekable()
-                                                .pipe(|mut archive| {
-                                                    archive
-                                                        .peek()
-                                                        .map(|chunk| chunk.target.clone())
-                                                        .map(|first_target| {
-                                                            LazyArchive::new(&first_target).pipe(|lazy_archive| {
-                                                                archive.fold(lazy_archive, |a, entry| a.tap_mut(|a| a.insert(entry.key, entry.buffer)))
-                                                            })
-                                                        })
-                                                })
-                                        })
-                                        .and_then(|archives| {
-                                            let building_archives = info_span!("building_archive");
-                                            building_archives.clone().in_scope(|| {
-                                                archives
-                                                    .into_iter()
-                                                    .inspect(|_| building_archives.pb_inc(1))
-                                                    .try_for_each(|descriptor| {
-                                                        build_bsa::build_bsa(descriptor, |archive, options, output_path| {
-                                                            output_path
-                                                                .normalize()
-                                                                .as_path()
-                                                                .open_file_write()
-                                                                .and_then(|(output_path, output)| {
-                                                                    archive
-                                                                        .write(&mut tracing::Span::current().wrap_write(0, output), &options)
-                                                                        .with_context(|| format!("writing built bsa file to {output_path:?}"))
-                                                                        .tap_ok(|_| info!(?output_path, "[OK]"))
-                                                                })
-                                                        })
-                                                    })
-                                            })
-                                        })
-                                })
-                                .map(|_| asset_chunk_len)
+            let transcoding_media = info_span!("transcoding_media", locations=%media.len()).tap(|pb| {
+                pb.pb_set_style(&rate_progress_style());
+                pb.pb_set_length(media.iter().map(|(_, assets)| assets.len() as u64).sum());
+            });
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(num_cpus::get())
+                .thread_name(|idx| format!("ttw-media-{idx}"))
+                .build()
+                .context("building media transcoding pool")
+                .and_then(|pool| {
+                    transcoding_media.clone().in_scope(|| {
+                        pool.install(|| {
+                            media
+                                .into_par_iter()
+                                .map(|location| handle_location(location, Some(&transcoding_media)))
+                                // groups finish out of order, totals are only ever incremented
+                                .try_for_each(|count| count.map(|count| handling_assets.pb_inc(count)))
                         })
-                        .try_for_each(|e| e.map(|count| handling_assets.pb_inc(count)))
+                    })
+                })
+                .context("transcoding media")
+                .and_then(|_| {
+                    ordered
+                        .into_iter()
+                        .map(|location| handle_location(location, None))
+                        .try_for_each(|count| count.map(|count| handling_assets.pb_inc(count)))
                 })
         })
         .and_then(|_| self::post_commands::handle_post_commands(post_commands).context("handling post_commands"))
@@ -589,6 +710,33 @@ pub fn install(CliConfig { contains }: CliConfig, hoolamike_config: HoolamikeCon
 }
 
 pub mod build_bsa;
+pub mod checkpoint;
 pub mod file_attrs;
 pub mod handle_asset;
 pub mod post_commands;
+pub mod validation;
+
+#[cfg(test)]
+mod tests {
+    use super::templating::normalize_separators;
+
+    fn substitute(template: &str, value: &str) -> anyhow::Result<String> {
+        normalize_separators(&template.replace("%VAR%", value))
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_normalize_separators() -> anyhow::Result<()> {
+        assert_eq!(
+            substitute(r"%VAR%\LonesomeRoad.esm", "/home/deck/Games/TTW")?,
+            "/home/deck/Games/TTW/LonesomeRoad.esm"
+        );
+        assert_eq!(substitute(r"%VAR%\sub\file", "/home/deck/Games/TTW")?, "/home/deck/Games/TTW/sub/file");
+        assert_eq!(substitute("%VAR%/already/unix", "/home/deck/Games/TTW")?, "/home/deck/Games/TTW/already/unix");
+        // value itself ends with a slash
+        assert_eq!(substitute(r"%VAR%\sub\file", "/home/deck/Games/TTW/")?, "/home/deck/Games/TTW/sub/file");
+        assert_eq!(substitute("%VAR%/already/unix", "/home/deck/Games/TTW/")?, "/home/deck/Games/TTW/already/unix");
+        assert_eq!(substitute("%VAR%", "/home/deck/Games/TTW/")?, "/home/deck/Games/TTW");
+        Ok(())
+    }
+}
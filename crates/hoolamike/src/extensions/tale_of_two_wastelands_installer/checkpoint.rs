@@ -0,0 +1,283 @@
+//! remembers which MPI operations already finished, so that a failure late in the installation
+//! doesn't force redoing hours of audio encoding
+
+use {
+    super::manifest_file::{
+        asset::Asset,
+        kind_guard::WithKindGuard,
+        location::{FolderLocation, Location},
+    },
+    crate::{install_modlist::download_cache::to_base_64_from_u64, read_wrappers::ReadExt},
+    anyhow::{Context, Result},
+    case_insensitive_path::CaseInsensitivePathBuf,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+    tap::prelude::*,
+    tracing::{debug, info, warn},
+};
+
+pub const CHECKPOINT_FILE_NAME: &str = ".hoolamike-ttw-checkpoint.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletedOperation {
+    /// [None] for operations that don't produce a file (like creating a folder)
+    pub output: Option<PathBuf>,
+    pub size: u64,
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Checkpoint {
+    /// resolved DESTINATION variable, checkpoint recorded for a different one is not valid
+    pub destination: String,
+    pub package_version: String,
+    /// keyed by index of the asset in the manifest
+    pub completed: BTreeMap<usize, CompletedOperation>,
+}
+
+impl CompletedOperation {
+    /// an output which isn't a file is recorded as missing, the operation is redone on the next run
+    pub fn new(output: Option<&Path>) -> Result<Self> {
+        match output {
+            Some(output) if !output.is_file() => {
+                debug!(?output, "output of a completed operation is not a file, it won't count as completed");
+                Ok(Self {
+                    output: Some(output.to_owned()),
+                    size: 0,
+                    checksum: String::new(),
+                })
+            }
+            Some(output) => std::fs::File::open(output)
+                .map(|file| file.and_hash())
+                .and_then(|mut reader| std::io::copy(&mut reader, &mut std::io::sink()).map(|size| (size, reader.hash())))
+                .with_context(|| format!("computing checksum of [{}]", output.display()))
+                .map(|(size, hash)| Self {
+                    output: Some(output.to_owned()),
+                    size,
+                    checksum: to_base_64_from_u64(hash),
+                }),
+            None => Ok(Self {
+                output: None,
+                size: 0,
+                checksum: String::new(),
+            }),
+        }
+    }
+
+    /// cheap check - only compares sizes, hashing multi-gigabyte archives on every run would defeat the purpose
+    pub fn still_valid(&self) -> bool {
+        match self.output.as_ref() {
+            Some(output) => std::fs::metadata(output).is_ok_and(|metadata| metadata.is_file() && metadata.len() == self.size),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckpointFile {
+    path: PathBuf,
+    checkpoint: Checkpoint,
+}
+
+impl CheckpointFile {
+    /// starts from scratch when `fresh` is set, the file is missing, or it was recorded for a different destination/package
+    pub fn open(destination: &Path, package_version: &str, fresh: bool) -> Result<Self> {
+        let path = destination.join(CHECKPOINT_FILE_NAME);
+        let empty = Checkpoint {
+            destination: destination.display().to_string(),
+            package_version: package_version.to_string(),
+            completed: Default::default(),
+        };
+        match fresh || !path.exists() {
+            true => Ok(empty),
+            false => std::fs::read_to_string(&path)
+                .with_context(|| format!("reading [{}]", path.display()))
+                .and_then(|checkpoint| serde_json::from_str::<Checkpoint>(&checkpoint).context("parsing checkpoint"))
+                .map(
+                    |checkpoint| match checkpoint.destination == empty.destination && checkpoint.package_version == empty.package_version {
+                        true => checkpoint.tap(|checkpoint| info!("resuming from checkpoint with [{}] completed operations", checkpoint.completed.len())),
+                        false => {
+                            warn!(
+                                recorded_destination=%checkpoint.destination,
+                                recorded_version=%checkpoint.package_version,
+                                "checkpoint was recorded for a different installation, starting from scratch"
+                            );
+                            empty
+                        }
+                    },
+                ),
+        }
+        .map(|checkpoint| Self { path, checkpoint })
+    }
+
+    /// every operation was recorded and its output still looks the same
+    pub fn all_completed(&self, indices: &[usize]) -> bool {
+        indices.iter().all(|index| {
+            self.checkpoint
+                .completed
+                .get(index)
+                .is_some_and(CompletedOperation::still_valid)
+        })
+    }
+
+    pub fn record(&mut self, indices: &[usize], output: Option<&Path>) -> Result<()> {
+        self.record_each(
+            indices
+                .iter()
+                .map(|index| (*index, output.map(Path::to_owned))),
+        )
+    }
+
+    /// like [Self::record], with an output per operation - outputs shared by many of them (an archive) are hashed once
+    pub fn record_each(&mut self, outputs: impl IntoIterator<Item = (usize, Option<PathBuf>)>) -> Result<()> {
+        let mut hashed = BTreeMap::<Option<PathBuf>, CompletedOperation>::new();
+        outputs
+            .into_iter()
+            .try_for_each(|(index, output)| {
+                match hashed.get(&output) {
+                    Some(completed) => Ok(completed.clone()),
+                    None => CompletedOperation::new(output.as_deref()).tap_ok(|completed| {
+                        hashed.insert(output, completed.clone());
+                    }),
+                }
+                .map(|completed| {
+                    self.checkpoint.completed.insert(index, completed);
+                })
+            })
+            .and_then(|_| self.save())
+    }
+
+    fn save(&self) -> Result<()> {
+        self.path
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .context("creating destination directory")
+            .and_then(|_| serde_json::to_string(&self.checkpoint).context("serializing checkpoint"))
+            .and_then(|checkpoint| {
+                let temp = self.path.with_extension("json.tmp");
+                std::fs::write(&temp, checkpoint)
+                    .and_then(|_| std::fs::rename(&temp, &self.path))
+                    .with_context(|| format!("writing [{}]", self.path.display()))
+            })
+    }
+}
+
+/// files left behind by `assets`, all of which target `location`: the archive they're packed into, or a file per asset for folders
+pub fn chunk_outputs(location: Option<&Location>, assets: &[Asset]) -> Result<Vec<Option<PathBuf>>> {
+    let to_path = |path: CaseInsensitivePathBuf| PathBuf::from(path.normalize().as_path().as_str());
+    match location {
+        Some(Location::Folder(WithKindGuard {
+            inner: FolderLocation {
+                value, create_folder: false, ..
+            },
+            ..
+        })) => assets
+            .iter()
+            .map(|asset| {
+                asset
+                    .target_path()
+                    .map(|file| {
+                        CaseInsensitivePathBuf::from_str(value)
+                            .and_then(|folder| folder.join(file.0.as_path().as_str()))
+                            .map(to_path)
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>>>(),
+        location => location
+            .and_then(Location::output_archive)
+            .map(|archive| CaseInsensitivePathBuf::from_str(archive).map(to_path))
+            .transpose()
+            .map(|archive| vec![archive; assets.len()]),
+    }
+    .context("resolving outputs of the operations")
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    #[test_log::test]
+    fn test_checkpoint_resumes_and_invalidates() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let destination = directory.path().join("ttw");
+        let output = directory.path().join("TaleOfTwoWastelands - Sounds.bsa");
+        std::fs::write(&output, b"archive")?;
+
+        let mut checkpoint = CheckpointFile::open(&destination, "3.3.3", false)?;
+        assert!(!checkpoint.all_completed(&[0, 1]));
+        checkpoint.record(&[0, 1], Some(&output))?;
+        checkpoint.record(&[2], None)?;
+
+        let reopened = CheckpointFile::open(&destination, "3.3.3", false)?;
+        assert!(reopened.all_completed(&[0, 1, 2]));
+        assert!(!reopened.all_completed(&[0, 3]));
+
+        // output got truncated after the checkpoint was recorded
+        std::fs::write(&output, b"arch")?;
+        assert!(!reopened.all_completed(&[0]));
+        assert!(reopened.all_completed(&[2]));
+
+        assert!(!CheckpointFile::open(&destination, "3.3.3", true)?.all_completed(&[2]));
+        assert!(!CheckpointFile::open(&destination, "3.3.4", false)?.all_completed(&[2]));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_changed_destination_invalidates_checkpoint() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let destination = directory.path().join("ttw");
+        CheckpointFile::open(&destination, "3.3.3", false)?.record(&[0], None)?;
+        // same checkpoint file, but DESTINATION resolves differently now
+        let moved = directory.path().join("ttw-moved");
+        std::fs::rename(&destination, &moved)?;
+        assert!(!CheckpointFile::open(&moved, "3.3.3", false)?.all_completed(&[0]));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_folder_location_records_a_file_per_asset() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let destination = directory.path().join("ttw");
+        let folder = destination.join("Data");
+        std::fs::create_dir_all(folder.join("meshes"))?;
+        let location = serde_json::from_value::<Location>(json!({
+            "Name": "Data",
+            "Value": folder.display().to_string(),
+            "CreateFolder": false,
+            "Type": 0,
+        }))?;
+        let assets = [r"meshes\a.nif", r"meshes\b.nif"]
+            .map(|path| serde_json::from_value::<Asset>(json!([0, 0, "", 0, 0, 1, path])))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        std::fs::write(folder.join("meshes").join("a.nif"), b"mesh a")?;
+        std::fs::write(folder.join("meshes").join("b.nif"), b"mesh b")?;
+
+        let outputs = chunk_outputs(Some(&location), &assets)?;
+        assert!(
+            outputs
+                .iter()
+                .all(|output| output.as_ref().is_some_and(|output| output.is_file()))
+        );
+        let mut checkpoint = CheckpointFile::open(&destination, "3.3.3", false)?;
+        // the folder itself used to be hashed here
+        checkpoint.record_each([0, 1].into_iter().zip(outputs))?;
+        assert!(CheckpointFile::open(&destination, "3.3.3", false)?.all_completed(&[0, 1]));
+
+        std::fs::remove_file(folder.join("meshes").join("b.nif"))?;
+        let reopened = CheckpointFile::open(&destination, "3.3.3", false)?;
+        assert!(!reopened.all_completed(&[0, 1]), "a missing file redoes the chunk");
+
+        // a directory never counts as an output
+        let mut checkpoint = reopened;
+        checkpoint.record(&[2], Some(&folder))?;
+        assert!(!checkpoint.all_completed(&[2]));
+        Ok(())
+    }
+}
@@ -145,6 +145,17 @@ impl Asset {
             Asset::XwmaFuz(_) => unimplemented!("Asset::XwmaFuz(_)"),
         }
     }
+    /// path within the target location, the source path is reused when the manifest doesn't name another one
+    pub fn target_path(&self) -> Option<&FileName> {
+        match self {
+            Asset::Copy(CopyAsset { source, target, .. })
+            | Asset::New(NewAsset { source, target, .. })
+            | Asset::Patch(PatchAsset { source, target, .. })
+            | Asset::OggEnc2(OggEnc2Asset { source, target, .. })
+            | Asset::AudioEnc(AudioEncAsset { source, target, .. }) => Some(target.path.as_ref().unwrap_or(&source.path)),
+            Asset::XwmaFuz(_) => None,
+        }
+    }
     pub fn name(&self) -> String {
         match self {
             Asset::Copy(copy_asset) => copy_asset
@@ -41,6 +41,13 @@ pub enum Location {
 }
 
 impl Location {
+    /// archive the assets targeting this location are packed into, folder locations get a file per asset instead
+    pub fn output_archive(&self) -> Option<&str> {
+        match self {
+            Location::Folder(_) | Location::ReadArchive(_) => None,
+            Location::WriteArchive(l) => Some(l.inner.value.as_str()),
+        }
+    }
     pub fn name(&self) -> &str {
         match self {
             Location::Folder(l) => l.inner.name.as_str(),
@@ -48,6 +55,13 @@ impl Location {
             Location::WriteArchive(l) => l.inner.name.as_str(),
         }
     }
+    pub fn value(&self) -> &str {
+        match self {
+            Location::Folder(l) => l.inner.value.as_str(),
+            Location::ReadArchive(l) => l.inner.value.as_str(),
+            Location::WriteArchive(l) => l.inner.value.as_str(),
+        }
+    }
     pub fn value_mut(&mut self) -> &mut String {
         match self {
             Location::Folder(WithKindGuard {
@@ -174,4 +174,27 @@ mod tests {
         );
         Ok(())
     }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_resolved_command_keeps_its_text() -> Result<()> {
+        // variables are substituted into the command line as they are, the path argument gets its separators here
+        ["/home/deck/Games/TTW", "/home/deck/Games/TTW/"]
+            .into_iter()
+            .try_for_each(|destination| {
+                "cmd.exe /C ren \"%DESTINATION%\\New Fallout - Sound.bsa\" \"Fallout - Sound.bsa\""
+                    .replace("%DESTINATION%", destination)
+                    .pipe_deref(ParsedPostCommand::parse)
+                    .map(|parsed| {
+                        assert_eq!(
+                            parsed,
+                            ParsedPostCommand::Rename(
+                                PathBuf::from("/home/deck/Games/TTW/New Fallout - Sound.bsa"),
+                                String::from("Fallout - Sound.bsa")
+                            ),
+                            "{destination}"
+                        )
+                    })
+            })
+    }
 }
@@ -0,0 +1,249 @@
+//! checks done before anything is touched - unsupported installer versions and missing variables
+//! otherwise surface as opaque errors deep inside some operation
+
+use {
+    super::{
+        manifest_file::{
+            Manifest,
+            kind_guard::WithKindGuard,
+            location::{FolderLocation, Location},
+        },
+        templating::find_template_marker,
+    },
+    crate::{config_file::GamesConfig, modlist_json::GameName},
+    itertools::Itertools,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        path::Path,
+    },
+};
+
+/// inclusive
+pub const MINIMUM_SUPPORTED_VERSION: &[u32] = &[3, 3];
+/// exclusive
+pub const FIRST_UNSUPPORTED_VERSION: &[u32] = &[3, 4];
+
+/// variables hoolamike fills in from the games section of the config
+const GAME_VARIABLES: &[(&str, &str)] = &[("FO3ROOT", "Fallout3"), ("FNVROOT", "FalloutNewVegas")];
+
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum Problem {
+    #[display("installer version [{found}] is not supported (supported: {})", supported_range())]
+    UnsupportedVersion { found: String },
+    #[display("'{name}' is used by the installer but it does not define it")]
+    UnknownVariable { name: String },
+    #[display("'{name}' is required, add it to extras.tale_of_two_wastelands.variables")]
+    MissingVariable { name: String },
+    #[display("'{game}' is required to fill in '{name}', add it to the games section")]
+    MissingGame { name: String, game: String },
+    #[display("'{name}' points at [{value}], which is not an existing directory")]
+    MissingDirectory { name: String, value: String },
+}
+
+fn supported_range() -> String {
+    format!(
+        ">= {}, < {}",
+        MINIMUM_SUPPORTED_VERSION.iter().join("."),
+        FIRST_UNSUPPORTED_VERSION.iter().join(".")
+    )
+}
+
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version
+        .trim()
+        .split('.')
+        .map(|segment| segment.parse::<u32>().ok())
+        .collect()
+}
+
+/// every `%VARIABLE%` in the value
+fn referenced_variables(mut value: &str) -> Vec<&str> {
+    std::iter::from_fn(|| {
+        find_template_marker(value).map(|(_, name, right)| {
+            value = right;
+            name
+        })
+    })
+    .collect()
+}
+
+/// everything about the installer that needs to be checked against the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirements {
+    pub version: String,
+    /// defined by the installer, along with their default values
+    pub defined: BTreeMap<String, Option<String>>,
+    pub referenced: BTreeSet<String>,
+    /// variables at the root of locations which are read from, these must be existing directories
+    pub source_directories: BTreeSet<String>,
+}
+
+impl Requirements {
+    pub fn from_manifest(manifest: &Manifest) -> Self {
+        let defined = manifest
+            .variables
+            .clone()
+            .release()
+            .into_iter()
+            .map(|variable| (variable.name().to_string(), variable.value().map(str::to_string)))
+            .collect::<BTreeMap<_, _>>();
+        let locations = manifest.locations.clone().release();
+        let referenced = locations
+            .iter()
+            .map(|location| location.value().to_string())
+            .chain(manifest.file_attrs.iter().map(|attr| attr.value.clone()))
+            .chain(
+                manifest
+                    .post_commands
+                    .iter()
+                    .map(|command| command.value.clone()),
+            )
+            .chain(defined.values().flatten().cloned())
+            .collect_vec()
+            .iter()
+            .flat_map(|value| referenced_variables(value))
+            .map(str::to_string)
+            .collect();
+        let source_directories = locations
+            .iter()
+            .filter(|location| match location {
+                Location::Folder(WithKindGuard {
+                    inner: FolderLocation { create_folder, .. },
+                    ..
+                }) => !create_folder,
+                Location::ReadArchive(_) => true,
+                Location::WriteArchive(_) => false,
+            })
+            .map(Location::value)
+            .filter_map(|value| find_template_marker(value).and_then(|(left, name, _)| left.is_empty().then(|| name.to_string())))
+            .collect();
+        Self {
+            version: manifest.package.version.clone(),
+            defined,
+            referenced,
+            source_directories,
+        }
+    }
+
+    /// value the variable will resolve to (mirrors `VariablesContext::resolve_variable`)
+    fn resolve(&self, name: &str, variables: &BTreeMap<String, String>, games: &GamesConfig) -> Result<String, Problem> {
+        match GAME_VARIABLES
+            .iter()
+            .find(|(variable, _)| *variable == name)
+        {
+            Some((_, game)) => games
+                .get(&GameName::new(game.to_string()))
+                .map(|game| game.root_directory.display().to_string())
+                .ok_or_else(|| Problem::MissingGame {
+                    name: name.to_string(),
+                    game: game.to_string(),
+                }),
+            None => match self.defined.get(name) {
+                None => Err(Problem::UnknownVariable { name: name.to_string() }),
+                Some(default) => variables
+                    .get(name)
+                    .cloned()
+                    .or_else(|| default.clone().filter(|default| !default.is_empty()))
+                    .ok_or_else(|| Problem::MissingVariable { name: name.to_string() }),
+            },
+        }
+    }
+
+    /// empty when the installation can proceed
+    pub fn check(&self, variables: &BTreeMap<String, String>, games: &GamesConfig) -> Vec<Problem> {
+        let version = parse_version(&self.version)
+            .filter(|version| version.as_slice() >= MINIMUM_SUPPORTED_VERSION && version.as_slice() < FIRST_UNSUPPORTED_VERSION)
+            .is_none()
+            .then(|| Problem::UnsupportedVersion { found: self.version.clone() });
+        let variables = self
+            .referenced
+            .iter()
+            .filter_map(|name| match self.resolve(name, variables, games) {
+                Err(problem) => Some(problem),
+                Ok(value) if self.source_directories.contains(name) && !value.contains('%') && !Path::new(&value).is_dir() => {
+                    Some(Problem::MissingDirectory { name: name.clone(), value })
+                }
+                Ok(_) => None,
+            });
+        version.into_iter().chain(variables).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::config_file::GameConfig};
+
+    fn requirements(version: &str) -> Requirements {
+        Requirements {
+            version: version.to_string(),
+            defined: [("DESTINATION".to_string(), None), ("FO3DATA".to_string(), Some("%FO3ROOT%\\Data".to_string()))]
+                .into_iter()
+                .collect(),
+            referenced: ["DESTINATION", "FO3ROOT", "FNVROOT", "FO3DATA"]
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+            source_directories: ["FO3ROOT", "FNVROOT"]
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn games(fallout_3: &Path, new_vegas: &Path) -> GamesConfig {
+        [("Fallout3", fallout_3), ("FalloutNewVegas", new_vegas)]
+            .into_iter()
+            .map(|(game, root)| {
+                (
+                    GameName::new(game.to_string()),
+                    GameConfig {
+                        root_directory: root.to_owned(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_referenced_variables() {
+        assert_eq!(referenced_variables(r"%DESTINATION%\Data\%NAME%.esm"), ["DESTINATION", "NAME"]);
+        assert!(referenced_variables("no variables here").is_empty());
+    }
+
+    #[test_log::test]
+    fn test_valid_configuration() -> anyhow::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let variables = [("DESTINATION".to_string(), "ttw".to_string())]
+            .into_iter()
+            .collect();
+        assert!(
+            requirements("3.3.3")
+                .check(&variables, &games(directory.path(), directory.path()))
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_every_problem_is_reported() -> anyhow::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let missing = directory.path().join("not-installed");
+        let mut requirements = requirements("3.4");
+        requirements.referenced.insert("TYPO".to_string());
+        assert_eq!(
+            requirements.check(&Default::default(), &games(directory.path(), &missing)),
+            [
+                Problem::UnsupportedVersion { found: "3.4".to_string() },
+                Problem::MissingVariable {
+                    name: "DESTINATION".to_string()
+                },
+                Problem::MissingDirectory {
+                    name: "FNVROOT".to_string(),
+                    value: missing.display().to_string(),
+                },
+                Problem::UnknownVariable { name: "TYPO".to_string() },
+            ]
+        );
+        Ok(())
+    }
+}
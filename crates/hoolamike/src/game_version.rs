@@ -0,0 +1,465 @@
+//! every game file sourced from the game directory records the version of the game it was taken from.
+//! Hash mismatches of those files almost always come down to a different game version (e.g. Skyrim AE vs SE),
+//! so the versions are compared upfront and named in the errors.
+
+use {
+    crate::{
+        config_file::GamesConfig,
+        modlist_json::{Archive, GameFileSourceState, GameName, State},
+    },
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+};
+
+/// executable carrying the version of the game, relative to the game root
+const GAME_EXECUTABLES: &[(&str, &str)] = &[
+    ("Morrowind", "Morrowind.exe"),
+    ("Oblivion", "Oblivion.exe"),
+    ("Fallout3", "Fallout3.exe"),
+    ("FalloutNewVegas", "FalloutNV.exe"),
+    ("Skyrim", "TESV.exe"),
+    ("Enderal", "TESV.exe"),
+    ("SkyrimSpecialEdition", "SkyrimSE.exe"),
+    ("EnderalSpecialEdition", "SkyrimSE.exe"),
+    ("SkyrimVR", "SkyrimVR.exe"),
+    ("Fallout4", "Fallout4.exe"),
+    ("Fallout4VR", "Fallout4VR.exe"),
+    ("Starfield", "Starfield.exe"),
+];
+
+pub type RequiredVersions = BTreeMap<GameName, BTreeSet<String>>;
+
+/// distinct game versions the game file sources of the modlist were taken from
+pub fn required_versions<'a>(archives: impl IntoIterator<Item = &'a Archive>) -> RequiredVersions {
+    archives
+        .into_iter()
+        .filter_map(|archive| match &archive.state {
+            State::GameFileSource(GameFileSourceState { game_version, game, .. }) => Some((game, game_version.trim())),
+            _ => None,
+        })
+        .filter(|(_, version)| !version.is_empty())
+        .fold(RequiredVersions::new(), |acc, (game, version)| {
+            acc.tap_mut(|acc| {
+                acc.entry(game.clone())
+                    .or_default()
+                    .insert(version.to_string());
+            })
+        })
+}
+
+pub fn format_required_versions(required: &RequiredVersions) -> String {
+    required
+        .iter()
+        .map(|(game, versions)| format!("{game}: {}", versions.iter().join(", ")))
+        .join("\n")
+}
+
+const VS_FIXEDFILEINFO_SIGNATURE: u32 = 0xFEEF04BD;
+const VS_FIXEDFILEINFO_STRUCT_VERSION: u32 = 0x0001_0000;
+
+/// file version from the VS_FIXEDFILEINFO of the executable's version resource, formatted the way wabbajack does it (`1.6.1170.0`)
+pub fn read_executable_version(executable: &[u8]) -> Option<String> {
+    let read_u32 = |offset: usize| {
+        executable
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    (0..executable.len().saturating_sub(16))
+        .step_by(4)
+        .find(|offset| read_u32(*offset) == Some(VS_FIXEDFILEINFO_SIGNATURE) && read_u32(offset + 4) == Some(VS_FIXEDFILEINFO_STRUCT_VERSION))
+        .and_then(|offset| read_u32(offset + 8).zip(read_u32(offset + 12)))
+        .map(|(most_significant, least_significant)| {
+            format!(
+                "{}.{}.{}.{}",
+                most_significant >> 16,
+                most_significant & 0xffff,
+                least_significant >> 16,
+                least_significant & 0xffff
+            )
+        })
+}
+
+pub fn game_executable(game: &GameName) -> Option<&'static str> {
+    GAME_EXECUTABLES
+        .iter()
+        .find(|(name, _)| game.to_string().eq_ignore_ascii_case(name))
+        .map(|(_, executable)| *executable)
+}
+
+/// [None] for games hoolamike doesn't know the executable of
+pub fn installed_version(game: &GameName, root_directory: &Path) -> Result<Option<String>> {
+    game_executable(game)
+        .map(|executable| {
+            let path = root_directory.join(executable);
+            std::fs::read(&path)
+                .with_context(|| format!("reading [{}]", path.display()))
+                .and_then(|executable| read_executable_version(&executable).with_context(|| format!("[{}] has no version information", path.display())))
+        })
+        .transpose()
+        .with_context(|| format!("detecting installed version of [{game}]"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstalledVersion {
+    Detected(String),
+    /// hoolamike doesn't know where to look for the version of this game
+    Unknown,
+    NotConfigured,
+    Unreadable(String),
+}
+
+impl std::fmt::Display for InstalledVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstalledVersion::Detected(version) => write!(f, "{version}"),
+            InstalledVersion::Unknown => write!(f, "unknown"),
+            InstalledVersion::NotConfigured => write!(f, "game is not configured"),
+            InstalledVersion::Unreadable(reason) => write!(f, "unreadable ({reason})"),
+        }
+    }
+}
+
+impl InstalledVersion {
+    pub fn at(game: &GameName, root_directory: &Path) -> Self {
+        match installed_version(game, root_directory) {
+            Ok(Some(version)) => Self::Detected(version),
+            Ok(None) => Self::Unknown,
+            Err(reason) => Self::Unreadable(format!("{reason:#}")),
+        }
+    }
+
+    pub fn probe(game: &GameName, games: &GamesConfig) -> Self {
+        match games.get(game) {
+            None => Self::NotConfigured,
+            Some(config) => Self::at(game, &config.root_directory),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionCheck {
+    pub game: GameName,
+    pub required: BTreeSet<String>,
+    pub installed: InstalledVersion,
+}
+
+impl VersionCheck {
+    /// only a detected version can be told apart from the required ones
+    pub fn is_mismatch(&self) -> bool {
+        match &self.installed {
+            InstalledVersion::Detected(installed) => !self.required.contains(installed),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for VersionCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] modlist expects version [{}], installed version is [{}]",
+            self.game,
+            self.required.iter().join(", "),
+            self.installed
+        )
+    }
+}
+
+pub fn check_versions(required: &RequiredVersions, games: &GamesConfig) -> Vec<VersionCheck> {
+    required
+        .iter()
+        .map(|(game, required)| VersionCheck {
+            game: game.clone(),
+            required: required.clone(),
+            installed: InstalledVersion::probe(game, games),
+        })
+        .collect()
+}
+
+/// logs the compatibility report, a mismatch is not an error by itself - not every game file has to differ between versions
+pub fn report_versions(required: &RequiredVersions, games: &GamesConfig) {
+    check_versions(required, games)
+        .into_iter()
+        .for_each(|check| match check.is_mismatch() {
+            true => tracing::warn!("{check}, game files sourced from the game directory will most likely fail hash validation"),
+            false => tracing::info!("{check}"),
+        })
+}
+
+/// explanation attached to hash mismatches of game files
+pub fn mismatch_hint(game: &GameName, expected_version: &str, root_directory: &Path) -> String {
+    VersionCheck {
+        game: game.clone(),
+        required: [expected_version.to_string()].into(),
+        installed: InstalledVersion::at(game, root_directory),
+    }
+    .to_string()
+}
+
+/// context of a game file which is there, but hashes differently than the one the modlist was made with
+#[derive(Debug, Clone, derive_more::Display)]
+#[display("[{game}] game file does not match the one from version [{game_version}]")]
+pub struct GameFileHashMismatch {
+    pub game: GameName,
+    pub game_version: String,
+    pub root_directory: PathBuf,
+}
+
+/// more game files of a game failing than this is a different game version, not a few files modified by hand
+const CONSOLIDATED_MISMATCHES_THRESHOLD: usize = 5;
+
+/// `1.10.984.0` > `1.10.163.0`, numerically
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map(|part| part.trim().parse().unwrap_or_default())
+        .collect()
+}
+
+/// replaces the per-file [GameFileHashMismatch] errors of a game with a single one naming both versions, when there are
+/// enough of them and the installed version is confirmed to differ. the individual errors only make it into debug logs then
+pub fn consolidate_version_mismatches<T>(prepared: Vec<Result<T>>) -> Vec<Result<T>> {
+    let consolidated = prepared
+        .iter()
+        .filter_map(|prepared| prepared.as_ref().err())
+        .filter_map(|reason| reason.downcast_ref::<GameFileHashMismatch>())
+        .into_group_map_by(|mismatch| mismatch.game.clone())
+        .into_iter()
+        .filter(|(_, mismatches)| mismatches.len() > CONSOLIDATED_MISMATCHES_THRESHOLD)
+        .map(|(game, mismatches)| {
+            let root_directory = mismatches[0].root_directory.clone();
+            let check = VersionCheck {
+                installed: InstalledVersion::at(&game, &root_directory),
+                required: mismatches
+                    .iter()
+                    .map(|mismatch| mismatch.game_version.clone())
+                    .collect(),
+                game: game.clone(),
+            };
+            (game, (check, root_directory, mismatches.len()))
+        })
+        .filter(|(_, (check, _, _))| check.is_mismatch())
+        .collect::<BTreeMap<_, _>>();
+    prepared
+        .into_iter()
+        .filter(|prepared| match prepared {
+            Err(reason) => match reason
+                .downcast_ref::<GameFileHashMismatch>()
+                .filter(|mismatch| consolidated.contains_key(&mismatch.game))
+            {
+                Some(_) => {
+                    tracing::debug!("{reason:?}");
+                    false
+                }
+                None => true,
+            },
+            Ok(_) => true,
+        })
+        .chain(
+            consolidated
+                .into_iter()
+                .map(|(game, (check, root_directory, count))| {
+                    let expected = check.required.iter().join(", ");
+                    let advice = match (
+                        &check.installed,
+                        check
+                            .required
+                            .iter()
+                            .map(|version| version_key(version))
+                            .max(),
+                    ) {
+                        (InstalledVersion::Detected(installed), Some(newest_required)) if version_key(installed) < newest_required => "updating",
+                        _ => "downgrading",
+                    };
+                    Err(crate::exit_codes::Failure::Config.mark(anyhow::anyhow!(
+                        "[{count}] game files failed hash validation: modlist expects {game} {expected} but found {installed}, consider {advice} the game at \
+                         [{root}] (games.{game}.root_directory in the config)",
+                        installed = check.installed,
+                        root = root_directory.display(),
+                    )))
+                }),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::config_file::GameConfig, serde_json::json};
+
+    fn game_file(game: &str, version: &str) -> Archive {
+        serde_json::from_value(json!({
+            "Hash": "AAAAAAAAAAA=",
+            "Meta": "",
+            "Name": "SkyrimSE.exe",
+            "Size": 0,
+            "State": {
+                "$type": "GameFileSourceDownloader, Wabbajack.Lib",
+                "GameVersion": version,
+                "Hash": "AAAAAAAAAAA=",
+                "GameFile": "SkyrimSE.exe",
+                "Game": game,
+            },
+        }))
+        .expect("valid archive")
+    }
+
+    /// just enough of a PE file for the version to be found in it
+    fn executable(version: [u16; 4]) -> Vec<u8> {
+        let [a, b, c, d] = version.map(u32::from);
+        b"MZ"
+            .iter()
+            .copied()
+            .chain(std::iter::repeat_n(0, 102))
+            .chain(
+                b"V\0S\0_\0V\0E\0R\0S\0I\0O\0N\0_\0I\0N\0F\0O\0\0\0\0\0\0\0"
+                    .iter()
+                    .copied(),
+            )
+            .chain(
+                [VS_FIXEDFILEINFO_SIGNATURE, VS_FIXEDFILEINFO_STRUCT_VERSION, (a << 16) | b, (c << 16) | d, 0, 0]
+                    .into_iter()
+                    .flat_map(u32::to_le_bytes),
+            )
+            .chain(std::iter::repeat_n(0, 64))
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_required_versions_are_aggregated_per_game() {
+        let archives = [
+            game_file("SkyrimSpecialEdition", "1.6.1170.0"),
+            game_file("SkyrimSpecialEdition", "1.6.1170.0"),
+            game_file("SkyrimSpecialEdition", "1.5.97.0"),
+            game_file("Fallout4", "1.10.163.0"),
+            game_file("Fallout4", ""),
+        ]
+        .into_iter()
+        .chain([serde_json::from_value(json!({
+            "Hash": "AAAAAAAAAAA=",
+            "Meta": "",
+            "Name": "file.7z",
+            "Size": 0,
+            "State": {"$type": "HttpDownloader, Wabbajack.Lib", "Url": "https://example.com/file.7z"},
+        }))
+        .expect("valid archive")])
+        .collect_vec();
+        let required = required_versions(&archives);
+        assert_eq!(
+            format_required_versions(&required),
+            "Fallout4: 1.10.163.0\nSkyrimSpecialEdition: 1.5.97.0, 1.6.1170.0"
+        );
+    }
+
+    #[test_log::test]
+    fn test_executable_version_probe() {
+        assert_eq!(read_executable_version(&executable([1, 6, 1170, 0])).as_deref(), Some("1.6.1170.0"));
+        assert_eq!(read_executable_version(b"MZ not really an executable"), None);
+    }
+
+    #[test_log::test]
+    fn test_version_check_against_installed_game() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        std::fs::write(directory.path().join("SkyrimSE.exe"), executable([1, 5, 97, 0]))?;
+        let games: GamesConfig = [
+            (GameName::new("SkyrimSpecialEdition".to_string()), directory.path()),
+            (GameName::new("Fallout4".to_string()), directory.path()),
+            (GameName::new("SomethingElse".to_string()), directory.path()),
+        ]
+        .into_iter()
+        .map(|(game, root)| {
+            (
+                game,
+                GameConfig {
+                    root_directory: root.to_owned(),
+                },
+            )
+        })
+        .collect();
+        let required = [
+            game_file("SkyrimSpecialEdition", "1.6.1170.0"),
+            game_file("Fallout4", "1.10.163.0"),
+            game_file("SomethingElse", "1.0.0.0"),
+            game_file("Starfield", "1.0.0.0"),
+        ]
+        .pipe_ref(required_versions);
+        let checks = check_versions(&required, &games);
+        assert_eq!(
+            checks
+                .iter()
+                .map(|check| (check.game.to_string(), check.is_mismatch()))
+                .collect_vec(),
+            [
+                ("Fallout4".to_string(), false),
+                ("SkyrimSpecialEdition".to_string(), true),
+                ("SomethingElse".to_string(), false),
+                ("Starfield".to_string(), false),
+            ]
+        );
+        assert!(matches!(checks[0].installed, InstalledVersion::Unreadable(_)));
+        assert_eq!(checks[1].installed, InstalledVersion::Detected("1.5.97.0".to_string()));
+        assert_eq!(checks[2].installed, InstalledVersion::Unknown);
+        assert_eq!(checks[3].installed, InstalledVersion::NotConfigured);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_mismatch_hint() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        std::fs::write(directory.path().join("SkyrimSE.exe"), executable([1, 6, 640, 0]))?;
+        assert_eq!(
+            mismatch_hint(&GameName::new("SkyrimSpecialEdition".to_string()), "1.5.97.0", directory.path()),
+            "[SkyrimSpecialEdition] modlist expects version [1.5.97.0], installed version is [1.6.640.0]"
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_version_mismatches_are_consolidated() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        std::fs::write(directory.path().join("Fallout4.exe"), executable([1, 10, 984, 0]))?;
+        let mismatch = |game: &str, count: usize| {
+            (0..count).map(move |idx| {
+                Err::<(), _>(anyhow::anyhow!("hash mismatch of file [{idx}]").context(GameFileHashMismatch {
+                    game: GameName::new(game.to_string()),
+                    game_version: "1.10.163.0".to_string(),
+                    root_directory: directory.path().to_owned(),
+                }))
+                .context("copying game file")
+            })
+        };
+        let messages = |prepared: &[Result<()>]| {
+            prepared
+                .iter()
+                .filter_map(|prepared| prepared.as_ref().err())
+                .map(|reason| reason.to_string())
+                .collect_vec()
+        };
+
+        let prepared = consolidate_version_mismatches(
+            mismatch("Fallout4", 300)
+                .chain([Ok(()), Err(anyhow::anyhow!("mega is not supported"))])
+                .collect_vec(),
+        );
+        assert_eq!(prepared.len(), 3);
+        let errors = messages(&prepared);
+        assert_eq!(errors[0], "mega is not supported");
+        assert!(
+            errors[1].starts_with(
+                "[300] game files failed hash validation: modlist expects Fallout4 1.10.163.0 but found 1.10.984.0, consider downgrading the game at"
+            ),
+            "{}",
+            errors[1]
+        );
+        assert!(errors[1].ends_with("(games.Fallout4.root_directory in the config)"));
+
+        // a few modified files, or a game the installed version of can't be told, are reported one by one
+        assert_eq!(consolidate_version_mismatches(mismatch("Fallout4", 3).collect_vec()).len(), 3);
+        assert_eq!(consolidate_version_mismatches(mismatch("Starfield", 20).collect_vec()).len(), 20);
+        Ok(())
+    }
+}
@@ -3,36 +3,40 @@ use {
         Cli,
         compression::{ProcessArchive, zip::ZipArchive},
         config_file::{CONFIG_FILE_NAME, HoolamikeConfig},
+        extensions::tale_of_two_wastelands_installer::validation::Requirements,
         modlist_json::{GameFileSourceState, GameName},
+        nexus_login,
         path::CaseInsensitivePathBuf,
-        utils::ResultZipExt,
-        wabbajack_file::WabbajackFile,
+        post_install_fixup,
+        project_root::{MaybeRelativeTo, enter_project_root, project_root_for},
+        wabbajack_file::{
+            WabbajackFile,
+            modlist_cache::{CacheKey, ModlistCache},
+        },
     },
     anyhow::{Context, Result, anyhow},
     case_insensitive_path::{ExistingPathBuf, PathExistsUtf8Ext},
     futures::{FutureExt, TryFutureExt},
     iced::{Task, Theme, widget::image::Handle as ImageHandle},
-    image::{DynamicImage, GenericImage, GenericImageView},
-    itertools::Itertools,
+    image::RgbaImage,
     serde::Serialize,
     std::{
         collections::BTreeSet,
-        future::ready,
+        convert::identity,
+        future::{Future, ready},
         io::{BufRead, Read, Seek},
         path::{Path, PathBuf},
         str::FromStr,
+        time::Instant,
     },
     tap::prelude::*,
-    tracing::{error, info},
+    tracing::{error, info, warn},
 };
 
 mod helpers {
     use {
         extension_traits::extension,
         iced::{Font, font::Weight},
-        normalize_path::NormalizePath,
-        std::path::{Path, PathBuf},
-        tap::Pipe,
     };
 
     #[extension(pub trait BoldText)]
@@ -44,39 +48,39 @@ mod helpers {
             })
         }
     }
-
-    #[extension(pub trait MaybeRelativeTo)]
-    impl<P: AsRef<Path>> P {
-        fn maybe_relative_to<Parent: AsRef<Path>>(&self, parent: Parent) -> PathBuf {
-            match self
-                .as_ref()
-                .normalize()
-                .strip_prefix(parent.as_ref().normalize())
-            {
-                Ok(relative) => relative.to_owned(),
-                Err(_) => self.as_ref().normalize().to_owned(),
-            }
-        }
-        fn maybe_relative_to_exists<Parent: AsRef<Path>>(&self, parent: Parent) -> PathBuf {
-            match (self.as_ref().canonicalize(), parent.as_ref().canonicalize()) {
-                (Ok(this), Ok(parent)) => this
-                    .maybe_relative_to(parent)
-                    .pipe(|p| if p.exists() { p } else { this }),
-                _ => self.as_ref().to_owned(),
-            }
-        }
-    }
 }
 
 const TITLE: &str = concat!(clap::crate_name!(), " ", clap::crate_version!());
 
+mod displays;
 mod embedded_terminal;
+mod file_drop;
+mod gallery;
+mod image_cache;
+mod install;
+mod modlist_info;
+mod validation;
 
 #[derive(Clone, Debug)]
 enum FinalMessage {
     Save,
     SaveAndRun,
 }
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Tab {
+    #[default]
+    Config,
+    Gallery,
+}
+
+/// what to do when the config file changed on disk while there are unsaved changes in the gui
+#[derive(Clone, Copy, Debug)]
+enum ConfigConflictResolution {
+    KeepGui,
+    TakeDisk,
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 enum Message {
@@ -87,6 +91,30 @@ enum Message {
     ToggleTTW(bool),
     ToggleTexconv(bool),
     ToggleFixup(bool),
+    /// emitted by the config file watcher, carries the moment the change was observed
+    ConfigFileChanged(Instant),
+    /// emitted after the debounce period, only the most recent change is acted upon
+    ReloadConfigFromDisk(Instant),
+    ResolveConfigConflict(ConfigConflictResolution),
+    NexusLogin,
+    /// api key and the account name
+    NexusLoggedIn(Result<(String, String)>),
+    /// emitted once typing in the api key field stops, the key is only checked when it's still the one in the config
+    CheckApiKey(String),
+    /// api key and the account name, or why nexus did not accept it
+    ApiKeyChecked(String, std::result::Result<String, String>),
+    /// raw contents of the resolution field, applied to the config once it parses
+    EditResolution(String),
+    PickResolution(displays::ResolutionChoice),
+    Install(install::InstallMessage),
+    SelectTab(Tab),
+    Gallery(gallery::GalleryMessage),
+    FileDropped(PathBuf),
+    /// switch to the config file dropped onto the window (or don't)
+    ConfirmDroppedConfig(bool),
+    /// carries the moment the banner was shown, a newer banner stays up
+    DismissBanner(Instant),
+    ModlistInfo(modlist_info::ModlistInfoInput),
 }
 
 type AppMessage = Option<Message>;
@@ -106,30 +134,90 @@ struct State {
     #[serde(skip_serializing)]
     loaded_image: Option<ImageHandle>,
     project_root: PathBuf,
+    has_unsaved_changes: bool,
+    #[serde(skip_serializing)]
+    last_config_file_change: Option<Instant>,
+    pending_external_config: Option<HoolamikeConfig>,
+    /// parsed out of the selected .MPI file, only reloaded when the selection changes
+    #[serde(skip_serializing)]
+    ttw_requirements: Option<(PathBuf, std::result::Result<Requirements, String>)>,
+    /// started by SAVE AND RUN, kept around after it finishes so the outcome stays visible
+    #[serde(skip_serializing)]
+    install: Option<install::InstallRun>,
+    /// resolution typed in the gui which does not parse (yet)
+    #[serde(skip_serializing)]
+    resolution_input: Option<String>,
+    /// offered in the resolution picker
+    #[serde(skip_serializing)]
+    detected_resolutions: Vec<post_install_fixup::common::Resolution>,
+    /// the resolution is typed in instead of picked
+    #[serde(skip_serializing)]
+    custom_resolution: bool,
+    #[serde(skip_serializing)]
+    validation: validation::Validation,
+    #[serde(skip_serializing)]
+    tab: Tab,
+    #[serde(skip_serializing)]
+    gallery: gallery::Gallery,
+    #[serde(skip_serializing)]
+    banner: Option<file_drop::Banner>,
+    /// config file dropped onto the window, waiting for confirmation
+    #[serde(skip_serializing)]
+    dropped_config: Option<(PathBuf, HoolamikeConfig)>,
+    /// the NSFW warning of the loaded modlist was acknowledged, asked again for every modlist loaded
+    #[serde(skip_serializing)]
+    nsfw_acknowledged: bool,
+    /// what nexus said about the api key, checked after a pause in typing since iced has no blur event
+    #[serde(skip_serializing)]
+    api_key_check: Option<(String, std::result::Result<String, String>)>,
 }
 
-fn read_image<R: BufRead + Seek>(bytes: R) -> Result<ImageHandle> {
+/// pause in typing after which the api key is sent to nexus
+const API_KEY_CHECK_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// gallery thumbnails are scaled down to fit in this box, keeping the aspect ratio
+const THUMBNAIL_SIZE: (u32, u32) = (192, 108);
+
+#[derive(Debug, Clone, Copy)]
+enum ImageKind {
+    /// the modlist image drawn behind the whole window
+    Background,
+    Thumbnail,
+}
+
+fn image_handle(image: RgbaImage) -> ImageHandle {
+    ImageHandle::from_rgba(image.width(), image.height(), image.into_raw())
+}
+
+fn read_image<R: BufRead + Seek>(bytes: R, kind: ImageKind) -> Result<RgbaImage> {
     image::ImageReader::new(bytes)
         .with_guessed_format()
         .context("bad image format")
         .and_then(|image| image.decode().context("decoding image"))
-        .map(|image| image.to_rgba8())
-        // lowering the contrast because it's a background image
-        .map(DynamicImage::from)
-        .tap_ok_mut(|image| {
-            image
-                .pixels()
-                .collect_vec()
-                .into_iter()
-                .for_each(|(x, y, pixel)| image.put_pixel(x, y, pixel.tap_mut(|p| p.0[3] /= 10)))
+        .map(|image| match kind {
+            ImageKind::Background => image.to_rgba8().tap_mut(|image| {
+                // lowering the contrast because it's a background image, straight on the rgba bytes - a 4k image is 8M pixels
+                image
+                    .iter_mut()
+                    .skip(3)
+                    .step_by(4)
+                    .for_each(|alpha| *alpha /= 10)
+            }),
+            ImageKind::Thumbnail => image
+                .thumbnail(THUMBNAIL_SIZE.0, THUMBNAIL_SIZE.1)
+                .to_rgba8(),
         })
-        .map(|i| i.to_rgba8())
-        .map(|image| ImageHandle::from_rgba(image.width(), image.height(), image.into_raw()))
 }
 
-async fn download_image(url: url::Url) -> Result<ImageHandle> {
+async fn download_image(url: url::Url, kind: ImageKind) -> Result<ImageHandle> {
+    fetch_image(url, kind).map_ok(image_handle).await
+}
+
+async fn fetch_image(url: url::Url, kind: ImageKind) -> Result<RgbaImage> {
     const MAX_IMAGE_SIZE: u64 = 20 * 1024 * 1024;
-    reqwest::get(url.to_string())
+    crate::install_modlist::downloads::HTTP_CLIENT
+        .get(url.to_string())
+        .send()
         .map(|r| r.context("performing request"))
         .and_then(|request| {
             request
@@ -143,15 +231,32 @@ async fn download_image(url: url::Url) -> Result<ImageHandle> {
                 .pipe(ready)
         })
         .and_then(|request| request.bytes().map(|r| r.context("fetching bytes")))
-        .and_then(|bytes| read_image(std::io::Cursor::new(bytes)).pipe(ready))
+        .and_then(|bytes| read_image(std::io::Cursor::new(bytes), kind).pipe(ready))
         .await
         .with_context(|| format!("fetching image at [{url}]"))
 }
 
-fn load_image_from_zip(wabbajack_file: ExistingPathBuf, path: CaseInsensitivePathBuf) -> Result<ImageHandle> {
+/// `value` quoted for a posix shell
+fn shell_quoted(value: &Path) -> String {
+    format!("'{}'", value.display().to_string().replace('\'', "'\\''"))
+}
+
+/// the command line installing what's configured, paths are passed explicitly so later edits of the config don't change
+/// what it does
+fn install_command(project_root: &Path, current_exe: &Path, config: &HoolamikeConfig) -> String {
+    format!(
+        "cd {project_root} && {current_exe} install --installation-path {installation_path} --downloads-path {downloads_path}",
+        project_root = shell_quoted(project_root),
+        current_exe = shell_quoted(current_exe),
+        installation_path = shell_quoted(&config.installation.installation_path),
+        downloads_path = shell_quoted(&config.downloaders.downloads_directory),
+    )
+}
+
+fn load_image_from_zip(wabbajack_file: &ExistingPathBuf, path: &CaseInsensitivePathBuf) -> Result<RgbaImage> {
     ZipArchive::new(&wabbajack_file)
         .with_context(|| format!("reading wabbajack file contents at [{wabbajack_file:?}]"))
-        .and_then(|mut archive| archive.get_handle(&path))
+        .and_then(|mut archive| archive.get_handle(path))
         .and_then(|mut handle| {
             Vec::new().pipe(|mut buf| {
                 handle
@@ -161,16 +266,65 @@ fn load_image_from_zip(wabbajack_file: ExistingPathBuf, path: CaseInsensitivePat
             })
         })
         .map(std::io::Cursor::new)
-        .and_then(read_image)
+        .and_then(|image| read_image(image, ImageKind::Background))
+}
+
+/// the modlist image (an url, or a path inside of the .wabbajack file) drawn behind the window. comes out of the
+/// [image_cache::ImageCache] when it was cached for this very .wabbajack file, otherwise it's fetched and cached - on a thread of
+/// its own either way, reading it out of a .wabbajack file on a network share takes seconds
+fn load_background_image(wabbajack_file: ExistingPathBuf, image: String, cache: image_cache::ImageCache) -> impl Future<Output = Result<ImageHandle>> {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let fetched = |key: &CacheKey| {
+            match image
+                .parse::<url::Url>()
+                .with_context(|| format!("bad image url: {image}"))
+            {
+                Ok(url) => crate::tokio_runtime_single().and_then(|runtime| runtime.block_on(fetch_image(url, ImageKind::Background))),
+                Err(reason) => {
+                    tracing::debug!("not a url?: {reason:?}");
+                    CaseInsensitivePathBuf::from_str(&image).and_then(|image| load_image_from_zip(&wabbajack_file, &image))
+                }
+            }
+            .tap_ok(|image| {
+                if let Err(reason) = cache.store(key, image) {
+                    warn!("could not cache the modlist image: {reason:?}");
+                }
+            })
+        };
+        CacheKey::of(wabbajack_file.as_ref())
+            .and_then(|key| match cache.load(&key) {
+                Some(cached) => Ok(cached),
+                None => fetched(&key),
+            })
+            .map(image_handle)
+            .pipe(|result| tx.send(result).ok())
+    });
+    rx.map(|result| result.context("image thread crashed").and_then(identity))
 }
 
 mod ttw {
     use {
-        crate::{config_file::ExtrasConfig, extensions::tale_of_two_wastelands_installer::ExtensionConfig},
-        std::{collections::BTreeMap, path::PathBuf},
+        crate::{
+            config_file::ExtrasConfig,
+            extensions::tale_of_two_wastelands_installer::{ExtensionConfig, read_manifest, validation::Requirements},
+        },
+        anyhow::Result,
+        case_insensitive_path::PathExistsUtf8Ext,
+        std::{
+            collections::BTreeMap,
+            path::{Path, PathBuf},
+        },
         tap::prelude::*,
     };
 
+    pub fn load_requirements(path_to_ttw_mpi_file: &Path) -> Result<Requirements> {
+        path_to_ttw_mpi_file
+            .exists_utf8()
+            .and_then(|path| read_manifest(&path))
+            .map(|manifest| Requirements::from_manifest(&manifest))
+    }
+
     pub fn default_extension_config() -> ExtensionConfig {
         ExtensionConfig {
             path_to_ttw_mpi_file: PathBuf::from("FIXME"),
@@ -218,68 +372,322 @@ mod texconv {
     }
 }
 
+mod config_watcher {
+    use {
+        super::{AppMessage, Message},
+        futures::{FutureExt, StreamExt, stream::BoxStream},
+        notify::Watcher,
+        std::{
+            future::Future,
+            ops::Not,
+            path::{Path, PathBuf},
+            time::{Duration, Instant},
+        },
+        tap::prelude::*,
+        tracing::warn,
+    };
+
+    /// editors tend to write the file in multiple steps (truncate, write, rename), we only want to reload once
+    pub const CONFIG_RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+    /// executor-agnostic sleep, gui tasks are not guaranteed to run on tokio
+    pub fn delay(duration: Duration) -> impl Future<Output = ()> {
+        let (tx, rx) = futures::channel::oneshot::channel();
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+            tx.send(()).ok();
+        });
+        rx.map(|_| ())
+    }
+
+    fn is_config_file(config_path: &Path, event: &notify::Event) -> bool {
+        event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == config_path.file_name())
+    }
+
+    /// watches the parent directory (editors often replace the file by renaming) and reports changes to the config file
+    pub fn watch(config_path: &PathBuf) -> BoxStream<'static, AppMessage> {
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let config_path = config_path.clone();
+        let watched_directory = config_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(Path::to_owned)
+            .unwrap_or_else(|| PathBuf::from("."));
+        notify::RecommendedWatcher::new(
+            {
+                let config_path = config_path.clone();
+                move |res: notify::Result<notify::Event>| match res {
+                    Ok(event) => {
+                        if event.kind.is_access().not() && is_config_file(&config_path, &event) {
+                            tx.unbounded_send(Some(Message::ConfigFileChanged(Instant::now())))
+                                .ok();
+                        }
+                    }
+                    Err(reason) => warn!(?reason, "watching config file failed"),
+                }
+            },
+            notify::Config::default(),
+        )
+        .and_then(|mut watcher| {
+            watcher
+                .watch(&watched_directory, notify::RecursiveMode::NonRecursive)
+                .map(|_| watcher)
+        })
+        .tap_err(|reason| {
+            warn!(
+                ?reason,
+                "could not watch [{}], external config edits will not be picked up",
+                config_path.display()
+            )
+        })
+        .pipe(|watcher| match watcher {
+            // the watcher has to outlive the stream
+            Ok(watcher) => rx
+                .map(move |message| {
+                    let _watcher = &watcher;
+                    message
+                })
+                .boxed(),
+            Err(_) => futures::stream::empty().boxed(),
+        })
+    }
+}
+
 mod view;
 
 impl State {
+    fn from_config(config_path: PathBuf, config: HoolamikeConfig, project_root: PathBuf, error: Option<anyhow::Error>) -> Self {
+        const DEFAULT_THEME: Theme = Theme::SolarizedDark;
+        Self {
+            output_command: None,
+            theme: DEFAULT_THEME,
+            loaded_modlist_json: None,
+            error,
+            config,
+            loaded_image: None,
+            required_games: Default::default(),
+            project_root,
+            config_path,
+            has_unsaved_changes: false,
+            last_config_file_change: None,
+            pending_external_config: None,
+            ttw_requirements: None,
+            install: None,
+            resolution_input: None,
+            detected_resolutions: displays::detect(),
+            custom_resolution: false,
+            validation: Default::default(),
+            tab: Tab::default(),
+            gallery: Default::default(),
+            banner: None,
+            dropped_config: None,
+            nsfw_acknowledged: false,
+            api_key_check: None,
+        }
+        .tap_mut(Self::refresh_ttw_requirements)
+        .tap_mut(Self::revalidate)
+    }
+
+    /// why SAVE AND RUN can't start the installation yet
+    fn run_blocked(&self) -> Option<String> {
+        self.validation.blocking().or_else(|| {
+            self.loaded_modlist_json
+                .as_ref()
+                .filter(|loaded| loaded.modlist.is_nsfw && !self.nsfw_acknowledged)
+                .map(|_| "the modlist is NSFW, acknowledge the warning first".to_string())
+        })
+    }
+
+    fn revalidate(&mut self) {
+        self.validation = validation::Validation::check(&self.config, &self.project_root, &self.required_games, self.resolution_input.as_deref());
+    }
+
+    fn refresh_ttw_requirements(&mut self) {
+        let selected = self
+            .config
+            .extras
+            .as_ref()
+            .and_then(|extras| extras.tale_of_two_wastelands.as_ref())
+            .map(|ttw| self.project_root.join(&ttw.path_to_ttw_mpi_file))
+            .filter(|selected| selected.is_file());
+        match selected {
+            None => self.ttw_requirements = None,
+            Some(selected) => {
+                if self.ttw_requirements.as_ref().map(|(path, _)| path) != Some(&selected) {
+                    info!("reading installer manifest out of [{}]", selected.display());
+                    self.ttw_requirements = ttw::load_requirements(&selected)
+                        .map_err(|reason| format!("{reason:?}"))
+                        .pipe(|requirements| Some((selected, requirements)));
+                }
+            }
+        }
+    }
+
+    fn subscription(&self) -> iced::Subscription<AppMessage> {
+        iced::Subscription::batch([
+            iced::Subscription::run_with(self.config_path.clone(), config_watcher::watch),
+            file_drop::listen(),
+        ])
+    }
+
+    fn reload_config_from_disk(&mut self) {
+        match HoolamikeConfig::read(&self.config_path) {
+            Ok((_, config)) => match self.has_unsaved_changes {
+                false => {
+                    info!("config file changed on disk, reloading");
+                    self.config = config;
+                    self.pending_external_config = None;
+                }
+                true => {
+                    warn!("config file changed on disk, but there are unsaved changes in the gui");
+                    self.pending_external_config = Some(config);
+                }
+            },
+            Err(error) => self.error = Some(error.context("reloading config after external change")),
+        }
+    }
+
+    fn show_banner(&mut self, banner: file_drop::Banner) -> Task<AppMessage> {
+        let shown_at = banner.shown_at;
+        self.banner = Some(banner);
+        Task::perform(config_watcher::delay(file_drop::BANNER_DURATION), move |_| {
+            Some(Message::DismissBanner(shown_at))
+        })
+    }
+
+    fn is_installing(&self) -> bool {
+        self.install
+            .as_ref()
+            .is_some_and(|install| matches!(install.status, install::InstallStatus::Running | install::InstallStatus::Cancelling))
+    }
+
+    fn file_dropped(&mut self, path: PathBuf) -> Task<AppMessage> {
+        match file_drop::classify(path) {
+            file_drop::Dropped::Modlist(path) => match self.select_wabbajack_file(path.maybe_relative_to_exists(&self.project_root)) {
+                Ok(task) => Task::batch([task, self.show_banner(file_drop::Banner::info(format!("loaded [{}]", path.display())))]),
+                Err(reason) => self.show_banner(file_drop::Banner::error(format!("could not load [{}]: {reason:#}", path.display()))),
+            },
+            file_drop::Dropped::Config(path) => match self.is_installing() {
+                true => self.show_banner(file_drop::Banner::error(format!(
+                    "not switching to [{}] while the installation is running",
+                    path.display()
+                ))),
+                false => match HoolamikeConfig::read(&path) {
+                    Ok((path, config)) => {
+                        self.dropped_config = Some((path, config));
+                        Task::none()
+                    }
+                    Err(reason) => self.show_banner(file_drop::Banner::error(format!("could not read [{}]: {reason:#}", path.display()))),
+                },
+            },
+            file_drop::Dropped::Unsupported(path) => self.show_banner(file_drop::Banner::error(format!(
+                "[{}] is neither a .wabbajack file nor {CONFIG_FILE_NAME}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// starts over with the dropped config, its project root becomes the working directory
+    fn switch_to_config(&mut self, config_path: PathBuf, config: HoolamikeConfig) -> Task<AppMessage> {
+        let previous = std::mem::replace(self, Self::from_config(config_path.clone(), config, project_root_for(&config_path), None));
+        self.theme = previous.theme;
+        self.gallery = previous.gallery;
+        self.install = previous.install;
+        let wabbajack_file_path = self.config.installation.wabbajack_file_path.clone();
+        Task::batch([
+            Task::perform(
+                {
+                    let config_path = config_path.clone();
+                    async move { enter_project_root(&config_path) }
+                },
+                move |entered| match entered {
+                    // relative paths in the config only resolve from within its project root
+                    Ok(_) => Some(Message::SelectWabbajackFile(wabbajack_file_path)),
+                    Err(reason) => Some(Message::TryUpdateConfig(Err(reason))),
+                },
+            ),
+            self.show_banner(file_drop::Banner::info(format!("switched to [{}]", config_path.display()))),
+        ])
+    }
+
+    /// asks nexus about the api key once typing stops, keys which don't even look like one are left to [validation]
+    fn schedule_api_key_check(&self) -> Option<Task<AppMessage>> {
+        self.config
+            .downloaders
+            .nexus
+            .api_key
+            .clone()
+            .filter(|api_key| crate::downloaders::nexus::check_api_key(api_key).is_ok())
+            .map(|api_key| config_watcher::delay(API_KEY_CHECK_DELAY).pipe(|delay| Task::perform(delay, move |_| Some(Message::CheckApiKey(api_key)))))
+    }
+
+    /// loads the modlist out of the .wabbajack file and makes it the one being installed
+    fn select_wabbajack_file(&mut self, path_buf: PathBuf) -> Result<Task<AppMessage>> {
+        path_buf
+            .exists_utf8()
+            .and_then(|path_buf| WabbajackFile::load_modlist_json(&path_buf, Some(&ModlistCache::in_project_root(&self.project_root))))
+            .map(|file| {
+                let image_url = file.modlist.image.clone();
+                self.required_games = file
+                    .modlist
+                    .archives
+                    .iter()
+                    .filter_map(|a| match &a.state {
+                        crate::modlist_json::State::GameFileSource(GameFileSourceState { game, .. }) => Some(game),
+                        _ => None,
+                    })
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                info!("loaded {}", file.modlist.about());
+                self.loaded_modlist_json = Some(file);
+                self.nsfw_acknowledged = false;
+                if self.config.installation.wabbajack_file_path != path_buf {
+                    self.config.installation.wabbajack_file_path = path_buf.clone();
+                    self.has_unsaved_changes = true;
+                }
+
+                Task::perform(
+                    path_buf
+                        .exists_utf8()
+                        .map(|wabbajack_file| load_background_image(wabbajack_file, image_url, image_cache::ImageCache::in_project_root(&self.project_root)))
+                        .pipe(ready)
+                        .and_then(identity),
+                    |image| Some(Message::ImageLoaded(image)),
+                )
+            })
+    }
+
     fn update(&mut self, message: AppMessage) -> iced::Task<AppMessage> {
+        // progress of a running install and gallery updates don't touch the config, and there's lots of them
+        let revalidate = !matches!(message, None | Some(Message::Install(_) | Message::Gallery(_) | Message::ModlistInfo(_)));
         message
             .and_then(|message| match message {
                 Message::TryUpdateConfig(hoolamike_config) => match hoolamike_config {
                     Ok(config) => {
+                        let api_key_changed = config.downloaders.nexus.api_key != self.config.downloaders.nexus.api_key;
                         self.config = config;
-                        None
+                        self.has_unsaved_changes = true;
+                        api_key_changed
+                            .then(|| self.schedule_api_key_check())
+                            .flatten()
                     }
                     Err(error) => {
                         self.error = Some(error);
                         None
                     }
                 },
-                Message::SelectWabbajackFile(path_buf) => path_buf
-                    .exists_utf8()
-                    .and_then(|path_buf| WabbajackFile::load_modlist_json(&path_buf))
-                    .pipe(|res| match res {
-                        Ok(file) => {
-                            let image_url = file.modlist.image.clone();
-                            self.required_games = file
-                                .modlist
-                                .archives
-                                .iter()
-                                .filter_map(|a| match &a.state {
-                                    crate::modlist_json::State::GameFileSource(GameFileSourceState { game, .. }) => Some(game),
-                                    _ => None,
-                                })
-                                .collect::<BTreeSet<_>>()
-                                .into_iter()
-                                .cloned()
-                                .collect::<BTreeSet<_>>();
-                            self.loaded_modlist_json = Some(file);
-                            self.config.installation.wabbajack_file_path = path_buf.clone();
-
-                            Task::perform(
-                                match image_url
-                                    .parse::<url::Url>()
-                                    .with_context(|| format!("bad image url: {image_url}"))
-                                {
-                                    Ok(url) => download_image(url).boxed(),
-                                    Err(reason) => {
-                                        tracing::debug!("not a url?: {reason:?}");
-                                        path_buf
-                                            .exists_utf8()
-                                            .zip(image_url.pipe_deref(CaseInsensitivePathBuf::from_str))
-                                            .and_then(|(path_buf, image_url)| load_image_from_zip(path_buf, image_url))
-                                            .pipe(ready)
-                                            .boxed()
-                                    }
-                                },
-                                |image| Some(Message::ImageLoaded(image)),
-                            )
-                            .pipe(Some)
-                        }
-                        Err(reason) => {
-                            self.error = Some(reason);
-                            None
-                        }
-                    }),
+                Message::SelectWabbajackFile(path_buf) => match self.select_wabbajack_file(path_buf) {
+                    Ok(task) => Some(task),
+                    Err(reason) => {
+                        self.error = Some(reason);
+                        None
+                    }
+                },
                 Message::ImageLoaded(handle) => match handle {
                     Ok(handle) => {
                         self.loaded_image = Some(handle);
@@ -291,6 +699,7 @@ impl State {
                     }
                 },
                 Message::ToggleTexconv(to) => {
+                    self.has_unsaved_changes = true;
                     match to {
                         true => {
                             self.config
@@ -308,12 +717,15 @@ impl State {
                     None
                 }
                 Message::ToggleFixup(to) => {
+                    self.has_unsaved_changes = true;
                     match to {
                         true => {
                             self.config.fixup.get_or_insert_with(fixup::default_fixup);
                         }
                         false => {
                             self.config.fixup.take();
+                            self.resolution_input = None;
+                            self.custom_resolution = false;
                         }
                     }
 
@@ -321,6 +733,7 @@ impl State {
                 }
 
                 Message::ToggleTTW(to) => {
+                    self.has_unsaved_changes = true;
                     match to {
                         true => {
                             self.config
@@ -338,6 +751,136 @@ impl State {
 
                     None
                 }
+                Message::ConfigFileChanged(at) => {
+                    self.last_config_file_change = Some(at);
+                    config_watcher::delay(config_watcher::CONFIG_RELOAD_DEBOUNCE)
+                        .pipe(|delay| Task::perform(delay, move |_| Some(Message::ReloadConfigFromDisk(at))))
+                        .pipe(Some)
+                }
+                Message::ReloadConfigFromDisk(at) => {
+                    // a newer change was observed in the meantime, it will trigger its own reload
+                    if self.last_config_file_change == Some(at) {
+                        self.last_config_file_change = None;
+                        self.reload_config_from_disk();
+                    }
+                    None
+                }
+                Message::ResolveConfigConflict(resolution) => {
+                    match (resolution, self.pending_external_config.take()) {
+                        (ConfigConflictResolution::TakeDisk, Some(config)) => {
+                            self.config = config;
+                            self.has_unsaved_changes = false;
+                            self.resolution_input = None;
+                        }
+                        (ConfigConflictResolution::KeepGui, _) | (_, None) => {}
+                    }
+                    None
+                }
+                Message::NexusLogin => Task::perform(nexus_login::login_in_background(nexus_login::DEFAULT_BROWSER.to_string()), |result| {
+                    Some(Message::NexusLoggedIn(result))
+                })
+                .pipe(Some),
+                Message::NexusLoggedIn(result) => {
+                    match result {
+                        Ok((api_key, name)) => {
+                            info!("logged in to nexus as [{name}], save the config to keep the api key");
+                            self.api_key_check = Some((api_key.clone(), Ok(name)));
+                            self.config.downloaders.nexus.api_key = Some(api_key);
+                            self.has_unsaved_changes = true;
+                        }
+                        Err(error) => self.error = Some(error.context("logging in through nexus")),
+                    }
+                    None
+                }
+                Message::CheckApiKey(api_key) => (self.config.downloaders.nexus.api_key.as_ref() == Some(&api_key)
+                    && self
+                        .api_key_check
+                        .as_ref()
+                        .is_none_or(|(checked, _)| checked != &api_key))
+                .then(|| {
+                    Task::perform(nexus_login::validate_in_background(api_key.clone()), move |checked| {
+                        Some(Message::ApiKeyChecked(api_key, checked.map_err(|reason| format!("{reason:#}"))))
+                    })
+                }),
+                Message::ApiKeyChecked(api_key, checked) => {
+                    if let Err(reason) = &checked {
+                        warn!("nexus did not accept the api key: {reason}");
+                    }
+                    self.api_key_check = Some((api_key, checked));
+                    None
+                }
+                Message::EditResolution(input) => {
+                    self.has_unsaved_changes = true;
+                    match input.parse::<post_install_fixup::common::Resolution>() {
+                        Ok(resolution) => {
+                            self.config
+                                .fixup
+                                .get_or_insert_with(fixup::default_fixup)
+                                .game_resolution = resolution;
+                            self.resolution_input = None;
+                        }
+                        Err(_) => self.resolution_input = Some(input),
+                    }
+                    None
+                }
+                Message::PickResolution(choice) => {
+                    match choice {
+                        displays::ResolutionChoice::Listed { resolution, .. } => {
+                            self.has_unsaved_changes = true;
+                            self.config
+                                .fixup
+                                .get_or_insert_with(fixup::default_fixup)
+                                .game_resolution = resolution;
+                            self.resolution_input = None;
+                            self.custom_resolution = false;
+                        }
+                        displays::ResolutionChoice::Custom => self.custom_resolution = true,
+                    }
+                    None
+                }
+                Message::SelectTab(tab) => {
+                    self.tab = tab;
+                    match tab {
+                        Tab::Config => None,
+                        Tab::Gallery => self
+                            .gallery
+                            .update(gallery::GalleryMessage::Open, &self.project_root)
+                            .pipe(Some),
+                    }
+                }
+                Message::Gallery(message) => self.gallery.update(message, &self.project_root).pipe(Some),
+                Message::FileDropped(path) => self.file_dropped(path).pipe(Some),
+                Message::ConfirmDroppedConfig(confirmed) => match (confirmed, self.dropped_config.take()) {
+                    (true, Some((config_path, config))) => self.switch_to_config(config_path, config).pipe(Some),
+                    (false, _) | (_, None) => None,
+                },
+                Message::DismissBanner(shown_at) => {
+                    if self
+                        .banner
+                        .as_ref()
+                        .is_some_and(|banner| banner.shown_at == shown_at)
+                    {
+                        self.banner = None;
+                    }
+                    None
+                }
+                Message::ModlistInfo(input) => {
+                    match input {
+                        modlist_info::ModlistInfoInput::OpenLink(url) => modlist_info::open_link(&url),
+                        modlist_info::ModlistInfoInput::AcknowledgeNsfw(acknowledged) => self.nsfw_acknowledged = acknowledged,
+                    }
+                    None
+                }
+                Message::Install(message) => {
+                    if let Some(error) = self
+                        .install
+                        .as_mut()
+                        .and_then(|install| install.update(message, &self.config_path))
+                    {
+                        self.error = Some(error);
+                    }
+                    None
+                }
                 Message::Final(m) => {
                     let write_config = |config: &HoolamikeConfig, config_path: &Path| {
                         config
@@ -347,6 +890,10 @@ impl State {
                                     .with_context(|| format!("writing to [{}]", self.config_path.display()))
                                     .tap_ok(|_| info!("saved to {config_path:?}\n{contents}"))
                             })
+                            .and_then(|_| match config.downloaders.nexus.api_key.is_some() {
+                                true => nexus_login::restrict_permissions(config_path),
+                                false => Ok(()),
+                            })
                             .context("writing config")
                             .tap_err(|e| error!("{e:?}"))
                     };
@@ -354,6 +901,8 @@ impl State {
                         FinalMessage::Save => match write_config(&self.config, &self.config_path) {
                             Ok(()) => {
                                 self.error.take();
+                                self.has_unsaved_changes = false;
+                                self.pending_external_config = None;
                                 None
                             }
                             Err(error) => {
@@ -361,15 +910,22 @@ impl State {
                                 None
                             }
                         },
-                        FinalMessage::SaveAndRun => match write_config(&self.config, &self.config_path) {
-                            Ok(()) => {
+                        FinalMessage::SaveAndRun => match self
+                            .run_blocked()
+                            .map_or(Ok(()), |blocking| Err(anyhow!("{blocking}")))
+                            .context("configuration is not ready for installation")
+                            .and_then(|_| write_config(&self.config, &self.config_path))
+                            .and_then(|_| std::env::current_exe().context("could not determine the path of the current executable"))
+                        {
+                            Ok(current_exe) => {
                                 self.error.take();
-                                self.output_command = Some(format!(
-                                    "cd {project_root} && {current_exe} install",
-                                    project_root = self.project_root.display(),
-                                    current_exe = std::env::current_exe().unwrap().display()
-                                ));
-                                None
+                                self.has_unsaved_changes = false;
+                                self.pending_external_config = None;
+                                self.output_command = Some(install_command(&self.project_root, &current_exe, &self.config));
+                                install::InstallRun::start(self.config.clone()).pipe(|(install, task)| {
+                                    self.install = Some(install);
+                                    Some(task)
+                                })
                             }
                             Err(error) => {
                                 self.error = Some(error);
@@ -380,6 +936,12 @@ impl State {
                 }
             })
             .unwrap_or_default()
+            .tap(|_| self.refresh_ttw_requirements())
+            .tap(|_| {
+                if revalidate {
+                    self.revalidate()
+                }
+            })
     }
 
     fn new(
@@ -387,35 +949,31 @@ impl State {
             hoolamike_config,
             command: _,
             logging_mode: _,
+            progress_format: _,
+            no_log_file: _,
+            non_interactive: _,
             nxm_link_handler_port: _,
             nxm_link: _,
         }: Cli,
     ) -> (Self, Task<AppMessage>) {
-        const DEFAULT_THEME: Theme = Theme::SolarizedDark;
         HoolamikeConfig::read(&hoolamike_config)
             .context("could not read config, default will be generated")
             .map(|(config_path, config)| {
-                Self {
-                    output_command: None,
-                    theme: DEFAULT_THEME,
-                    loaded_modlist_json: None,
-                    error: None,
-                    config,
-                    loaded_image: None,
-                    required_games: Default::default(),
-                    project_root: config_path
-                        .parent()
-                        .expect("if this ever happens I'm installing windows")
-                        .canonicalize()
-                        .expect("checked above")
-                        .to_owned(),
-                    config_path,
-                }
-                .pipe(|state| Task::done(Some(Message::SelectWabbajackFile(state.config.installation.wabbajack_file_path.clone()))).pipe(|task| (state, task)))
+                // so that thumbnails come through the same proxy downloads do
+                crate::install_modlist::downloads::configure_proxy(config.downloaders.proxy.as_deref()).unwrap_or_else(|reason| warn!("{reason:?}"));
+                Self::from_config(config_path.clone(), config, project_root_for(&config_path), None).pipe(|state| {
+                    Task::done(Some(Message::SelectWabbajackFile(state.config.installation.wabbajack_file_path.clone()))).pipe(|task| (state, task))
+                })
             })
             .unwrap_or_else(|error| {
                 rfd::FileDialog::new()
-                    .set_directory(std::env::current_exe().unwrap().parent().unwrap())
+                    .set_directory(
+                        std::env::current_exe()
+                            .ok()
+                            .and_then(|exe| exe.parent().map(Path::to_owned))
+                            .or_else(|| std::env::current_dir().ok())
+                            .unwrap_or_else(|| PathBuf::from(".")),
+                    )
                     .set_file_name(CONFIG_FILE_NAME)
                     .add_filter("Hoolamike config", &[CONFIG_FILE_NAME.split_once(".").unwrap().1])
                     .set_title("IGNORE OVERWRITE WARNING, Root installation location (parent for hoolamike.yaml)")
@@ -426,20 +984,12 @@ impl State {
                             .map(|(_, c)| c)
                             .tap_err(|e| error!("bad config at [{}]\n{e:?}", hoolamike_config.display()));
                         let is_err = config.is_err();
-                        Self {
-                            output_command: None,
-                            theme: DEFAULT_THEME,
-                            loaded_modlist_json: None,
-                            error: Some(error),
-                            project_root: hoolamike_config
-                                .parent()
-                                .expect("if this ever happens I'm installing macos")
-                                .to_owned(),
-                            config: config.unwrap_or_default(),
-                            config_path: hoolamike_config,
-                            loaded_image: None,
-                            required_games: Default::default(),
-                        }
+                        Self::from_config(
+                            hoolamike_config.clone(),
+                            config.unwrap_or_default(),
+                            project_root_for(&hoolamike_config),
+                            Some(error),
+                        )
                         .pipe(|state| {
                             match is_err {
                                 false => Task::done(Some(Message::SelectWabbajackFile(state.config.installation.wabbajack_file_path.clone()))),
@@ -449,7 +999,12 @@ impl State {
                         })
                     })
             })
-            .tap(|(s, _)| std::env::set_current_dir(&s.project_root).expect("failed to set current working directory"))
+            .tap_mut(|(s, _)| {
+                if let Err(reason) = enter_project_root(&s.config_path) {
+                    error!(?reason, "could not enter the project root");
+                    s.error = Some(reason);
+                }
+            })
     }
 }
 
@@ -458,6 +1013,7 @@ const APP_SIZE: (f32, f32) = (900., 640.);
 pub fn run(cli: Cli) -> Result<()> {
     iced::application(move || State::new(cli.clone()), State::update, State::view)
         .theme(|s| s.theme.clone())
+        .subscription(State::subscription)
         .title(TITLE)
         .window_size(APP_SIZE)
         // .resizable(false)
@@ -465,3 +1021,211 @@ pub fn run(cli: Cli) -> Result<()> {
         .map_err(|e| anyhow!("{e:?}"))
         .context("running gui")
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::time::Duration};
+
+    fn write_config(path: &Path, config: &HoolamikeConfig) -> Result<()> {
+        config
+            .write_with_gui_message()
+            .and_then(|contents| std::fs::write(path, contents).context("writing test config"))
+    }
+
+    fn with_installation_path(installation_path: &str) -> HoolamikeConfig {
+        HoolamikeConfig::default().tap_mut(|c| c.installation.installation_path = PathBuf::from(installation_path))
+    }
+
+    fn state_for(directory: &Path, config: HoolamikeConfig) -> Result<State> {
+        let config_path = directory.join(CONFIG_FILE_NAME);
+        write_config(&config_path, &config).map(|_| State::from_config(config_path, config, directory.to_owned(), None))
+    }
+
+    #[test_log::test]
+    fn test_external_change_is_debounced() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let mut state = state_for(directory.path(), with_installation_path("before"))?;
+        write_config(&state.config_path, &with_installation_path("after"))?;
+
+        let first = Instant::now();
+        let second = first + Duration::from_millis(100);
+        let _ = state.update(Some(Message::ConfigFileChanged(first)));
+        let _ = state.update(Some(Message::ConfigFileChanged(second)));
+
+        let _ = state.update(Some(Message::ReloadConfigFromDisk(first)));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("before"));
+
+        let _ = state.update(Some(Message::ReloadConfigFromDisk(second)));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("after"));
+        assert!(state.pending_external_config.is_none());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_external_change_with_unsaved_changes_prompts() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let mut state = state_for(directory.path(), with_installation_path("before"))?;
+        let _ = state.update(Some(Message::TryUpdateConfig(Ok(with_installation_path("gui")))));
+        assert!(state.has_unsaved_changes);
+        write_config(&state.config_path, &with_installation_path("disk"))?;
+
+        let at = Instant::now();
+        let _ = state.update(Some(Message::ConfigFileChanged(at)));
+        let _ = state.update(Some(Message::ReloadConfigFromDisk(at)));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("gui"));
+        assert!(state.pending_external_config.is_some());
+
+        let _ = state.update(Some(Message::ResolveConfigConflict(ConfigConflictResolution::KeepGui)));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("gui"));
+        assert!(state.pending_external_config.is_none());
+        assert!(state.has_unsaved_changes);
+
+        let at = Instant::now();
+        let _ = state.update(Some(Message::ConfigFileChanged(at)));
+        let _ = state.update(Some(Message::ReloadConfigFromDisk(at)));
+        let _ = state.update(Some(Message::ResolveConfigConflict(ConfigConflictResolution::TakeDisk)));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("disk"));
+        assert!(!state.has_unsaved_changes);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_dropped_config_waits_for_confirmation() -> Result<()> {
+        let (directory, elsewhere) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let mut state = state_for(directory.path(), with_installation_path("before"))?;
+        let dropped = elsewhere.path().join(CONFIG_FILE_NAME);
+        write_config(&dropped, &with_installation_path("dropped"))?;
+
+        let _ = state.update(Some(Message::FileDropped(dropped.clone())));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("before"));
+        let _ = state.update(Some(Message::ConfirmDroppedConfig(false)));
+        assert!(state.dropped_config.is_none());
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("before"));
+
+        let _ = state.update(Some(Message::FileDropped(dropped.clone())));
+        let _ = state.update(Some(Message::ConfirmDroppedConfig(true)));
+        assert_eq!(state.config.installation.installation_path, PathBuf::from("dropped"));
+        assert_eq!(state.config_path, dropped);
+        assert_eq!(state.project_root, elsewhere.path());
+        assert!(state.banner.as_ref().is_some_and(|banner| !banner.is_error));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_bad_drops_show_an_error_banner() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let mut state = state_for(directory.path(), with_installation_path("before"))?;
+        let before = state.config.installation.wabbajack_file_path.clone();
+        let broken = directory.path().join("broken.wabbajack");
+        let unsupported = directory.path().join("readme.txt");
+        [&broken, &unsupported]
+            .into_iter()
+            .try_for_each(|path| std::fs::write(path, b"not a zip"))?;
+
+        [broken, unsupported].into_iter().for_each(|dropped| {
+            let _ = state.update(Some(Message::FileDropped(dropped.clone())));
+            assert!(
+                state
+                    .banner
+                    .as_ref()
+                    .is_some_and(|banner| banner.is_error && banner.message.contains(&*dropped.to_string_lossy())),
+                "{:?}",
+                state.banner
+            );
+            assert_eq!(state.config.installation.wabbajack_file_path, before);
+        });
+
+        let shown_at = state.banner.as_ref().map(|banner| banner.shown_at).unwrap();
+        let _ = state.update(Some(Message::DismissBanner(shown_at - Duration::from_secs(1))));
+        assert!(state.banner.is_some(), "an older timer leaves the banner up");
+        let _ = state.update(Some(Message::DismissBanner(shown_at)));
+        assert!(state.banner.is_none());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_picked_resolution_is_written_to_the_fixup() -> Result<()> {
+        use post_install_fixup::common::Resolution;
+        let directory = tempfile::tempdir()?;
+        let mut state = state_for(directory.path(), HoolamikeConfig::default())?;
+        let picked = Resolution { x: 2560, y: 1440 };
+        let _ = state.update(Some(Message::PickResolution(displays::ResolutionChoice::Listed {
+            resolution: picked,
+            detected: true,
+        })));
+        assert_eq!(
+            state
+                .config
+                .fixup
+                .as_ref()
+                .map(|fixup| fixup.game_resolution),
+            Some(picked)
+        );
+
+        let _ = state.update(Some(Message::PickResolution(displays::ResolutionChoice::Custom)));
+        assert!(state.custom_resolution);
+        assert_eq!(
+            state
+                .config
+                .fixup
+                .as_ref()
+                .map(|fixup| fixup.game_resolution),
+            Some(picked),
+            "switching to typing it in keeps the value"
+        );
+        let _ = state.update(Some(Message::EditResolution("1280x".into())));
+        assert_eq!(state.resolution_input.as_deref(), Some("1280x"));
+        let _ = state.update(Some(Message::EditResolution("1280x720".into())));
+        assert_eq!(
+            state
+                .config
+                .fixup
+                .as_ref()
+                .map(|fixup| fixup.game_resolution),
+            Some(Resolution { x: 1280, y: 720 })
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_nsfw_modlist_has_to_be_acknowledged() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let mut state = state_for(directory.path(), with_installation_path("installed"))?;
+        std::fs::write(directory.path().join("nsfw.wabbajack"), b"")?;
+        state.loaded_modlist_json = Some(WabbajackFile {
+            wabbajack_file_path: ExistingPathBuf::new(&directory.path().join("nsfw.wabbajack"))?,
+            wabbajack_entries: vec![],
+            modlist: serde_json::from_value(serde_json::json!({
+                "Archives": [],
+                "Directives": [],
+                "GameType": "SkyrimSpecialEdition",
+                "IsNSFW": true,
+                "Name": "Spicy",
+                "Version": "1.0.0",
+                "WabbajackVersion": "3.7.0.0",
+            }))?,
+        });
+        // nothing wrong with the config itself
+        state.validation = Default::default();
+        assert!(
+            state
+                .run_blocked()
+                .is_some_and(|blocked| blocked.contains("NSFW"))
+        );
+
+        let _ = state.update(Some(Message::ModlistInfo(modlist_info::ModlistInfoInput::AcknowledgeNsfw(true))));
+        assert_eq!(state.run_blocked(), None);
+        let _ = state.update(Some(Message::ModlistInfo(modlist_info::ModlistInfoInput::AcknowledgeNsfw(false))));
+        assert!(state.run_blocked().is_some());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_install_command_embeds_quoted_paths() {
+        let config = with_installation_path("/games/it's modded").tap_mut(|c| c.downloaders.downloads_directory = PathBuf::from("downloads"));
+        assert_eq!(
+            install_command(Path::new("/home/user/lists"), Path::new("/usr/bin/hoolamike"), &config),
+            "cd '/home/user/lists' && '/usr/bin/hoolamike' install --installation-path '/games/it'\\''s modded' --downloads-path 'downloads'"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! resolutions of the connected displays, read out of the kernel's DRM connectors so that it works the same under X11,
+//! wayland and gamescope without asking any of them
+
+use {
+    crate::post_install_fixup::common::Resolution,
+    itertools::Itertools,
+    std::{cmp::Reverse, path::Path},
+    tracing::debug,
+};
+
+const DRM_ROOT: &str = "/sys/class/drm";
+
+/// every mode of every connected display, largest first
+pub fn detect() -> Vec<Resolution> {
+    modes_in(Path::new(DRM_ROOT))
+}
+
+fn modes_in(drm_root: &Path) -> Vec<Resolution> {
+    std::fs::read_dir(drm_root)
+        .map_err(|reason| debug!(?reason, "could not list display connectors in [{}]", drm_root.display()))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|connector| is_connected(connector))
+        .filter_map(|connector| std::fs::read_to_string(connector.join("modes")).ok())
+        .flat_map(|modes| modes.lines().filter_map(parse_mode).collect_vec())
+        .unique()
+        .sorted_by_key(|Resolution { x, y }| Reverse((u32::from(*x) * u32::from(*y), *x)))
+        .collect()
+}
+
+fn is_connected(connector: &Path) -> bool {
+    std::fs::read_to_string(connector.join("status")).is_ok_and(|status| status.trim() == "connected")
+}
+
+/// modes look like `1920x1080`, interlaced ones are suffixed with `i`
+fn parse_mode(mode: &str) -> Option<Resolution> {
+    mode.trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .parse()
+        .ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolutionChoice {
+    Listed { resolution: Resolution, detected: bool },
+    Custom,
+}
+
+impl std::fmt::Display for ResolutionChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolutionChoice::Listed { resolution, detected: true } => write!(f, "{resolution}"),
+            ResolutionChoice::Listed { resolution, detected: false } => write!(f, "{resolution} (not detected)"),
+            ResolutionChoice::Custom => write!(f, "custom..."),
+        }
+    }
+}
+
+/// detected resolutions, with the current one kept in even when no display reports it
+pub fn choices(detected: &[Resolution], current: Resolution) -> Vec<ResolutionChoice> {
+    detected
+        .iter()
+        .map(|resolution| ResolutionChoice::Listed {
+            resolution: *resolution,
+            detected: true,
+        })
+        .chain((!detected.contains(&current)).then_some(ResolutionChoice::Listed {
+            resolution: current,
+            detected: false,
+        }))
+        .chain(std::iter::once(ResolutionChoice::Custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::Result};
+
+    fn connector(drm_root: &Path, name: &str, status: &str, modes: &[&str]) -> Result<()> {
+        let connector = drm_root.join(name);
+        std::fs::create_dir_all(&connector)?;
+        std::fs::write(connector.join("status"), format!("{status}\n"))?;
+        std::fs::write(connector.join("modes"), modes.iter().map(|mode| format!("{mode}\n")).join(""))?;
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_modes_of_connected_displays() -> Result<()> {
+        let drm_root = tempfile::tempdir()?;
+        connector(drm_root.path(), "card0-eDP-1", "connected", &["1280x800", "800x600"])?;
+        connector(
+            drm_root.path(),
+            "card0-HDMI-A-1",
+            "connected",
+            &["2560x1440", "1920x1080", "1920x1080i", "1280x800"],
+        )?;
+        connector(drm_root.path(), "card0-DP-1", "disconnected", &["3840x2160"])?;
+        // the card itself has no status
+        std::fs::create_dir(drm_root.path().join("card0"))?;
+        assert_eq!(
+            modes_in(drm_root.path())
+                .iter()
+                .map(ToString::to_string)
+                .collect_vec(),
+            ["2560x1440", "1920x1080", "1280x800", "800x600"]
+        );
+        assert!(modes_in(&drm_root.path().join("missing")).is_empty());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_current_resolution_is_always_offered() {
+        let detected = [Resolution { x: 1920, y: 1080 }, Resolution { x: 1280, y: 800 }];
+        assert_eq!(
+            choices(&detected, Resolution { x: 1280, y: 800 }),
+            [
+                ResolutionChoice::Listed {
+                    resolution: detected[0],
+                    detected: true
+                },
+                ResolutionChoice::Listed {
+                    resolution: detected[1],
+                    detected: true
+                },
+                ResolutionChoice::Custom,
+            ]
+        );
+        let odd = Resolution { x: 1234, y: 567 };
+        assert_eq!(
+            choices(&detected, odd)
+                .iter()
+                .map(ToString::to_string)
+                .collect_vec(),
+            ["1920x1080", "1280x800", "1234x567 (not detected)", "custom..."]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! files dropped onto the window - a .wabbajack file is selected right away, a config file is switched to once confirmed
+
+use {
+    super::{AppMessage, Message},
+    crate::config_file::CONFIG_FILE_NAME,
+    std::{
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+};
+
+/// how long the banner confirming a drop stays up
+pub const BANNER_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dropped {
+    Modlist(PathBuf),
+    Config(PathBuf),
+    Unsupported(PathBuf),
+}
+
+pub fn classify(path: PathBuf) -> Dropped {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match (path.is_file(), extension.as_deref()) {
+        (true, Some("wabbajack")) => Dropped::Modlist(path),
+        (true, _) if path.file_name() == Path::new(CONFIG_FILE_NAME).file_name() => Dropped::Config(path),
+        _ => Dropped::Unsupported(path),
+    }
+}
+
+pub fn listen() -> iced::Subscription<AppMessage> {
+    iced::event::listen_with(|event, _status, _window| match event {
+        iced::Event::Window(iced::window::Event::FileDropped(path)) => Some(Some(Message::FileDropped(path))),
+        _ => None,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Banner {
+    pub message: String,
+    pub is_error: bool,
+    /// identifies the banner, a newer one is not dismissed by the timer of the previous one
+    pub shown_at: Instant,
+}
+
+impl Banner {
+    pub fn info(message: String) -> Self {
+        Self {
+            message,
+            is_error: false,
+            shown_at: Instant::now(),
+        }
+    }
+
+    pub fn error(message: String) -> Self {
+        Self {
+            is_error: true,
+            ..Self::info(message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::Result};
+
+    #[test_log::test]
+    fn test_classify() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let [modlist, config, other] = ["Some Modlist.WABBAJACK", CONFIG_FILE_NAME, "notes.txt"].map(|name| directory.path().join(name));
+        [&modlist, &config, &other]
+            .into_iter()
+            .try_for_each(|path| std::fs::write(path, b""))?;
+        assert_eq!(classify(modlist.clone()), Dropped::Modlist(modlist));
+        assert_eq!(classify(config.clone()), Dropped::Config(config));
+        assert_eq!(classify(other.clone()), Dropped::Unsupported(other));
+        // a directory named like a modlist is still not one
+        let directory_modlist = directory.path().join("folder.wabbajack");
+        std::fs::create_dir(&directory_modlist)?;
+        assert_eq!(classify(directory_modlist.clone()), Dropped::Unsupported(directory_modlist));
+        Ok(())
+    }
+}
@@ -0,0 +1,457 @@
+//! second tab of the gui: the official modlist gallery. the copy cached by the last fetch is shown right away and refreshed
+//! in the background, picking a modlist downloads its .wabbajack file into the project root and selects it
+
+use {
+    super::{AppMessage, ImageKind, Message, Tab, download_image, helpers::BoldText},
+    crate::{
+        modlist_gallery::{self, CachedGallery, GalleryEntry, GalleryRow},
+        project_root::MaybeRelativeTo,
+    },
+    anyhow::{Context, Result},
+    chrono::{DateTime, Utc},
+    futures::FutureExt,
+    iced::{
+        Element,
+        Length,
+        alignment::Vertical,
+        task::Handle,
+        widget::{Column, Row, button, checkbox, container, image::Handle as ImageHandle, pick_list, progress_bar, scrollable, text},
+    },
+    itertools::Itertools,
+    std::{
+        collections::BTreeMap,
+        convert::identity,
+        future::Future,
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tracing::{debug, info},
+};
+
+/// the gallery lists a few hundred modlists, thumbnails are only fetched for the top of the current listing
+const MAX_THUMBNAILS: usize = 64;
+const ALL_GAMES: &str = "all games";
+
+/// everything the view can emit, buttons need their messages to be cloneable
+#[derive(Debug, Clone)]
+pub enum GalleryInput {
+    Game(String),
+    ShowNsfw(bool),
+    /// machine url of the entry
+    Download(String),
+    Refresh,
+}
+
+#[derive(Debug)]
+pub enum GalleryMessage {
+    /// the tab was opened
+    Open,
+    CacheRead(Result<CachedGallery>),
+    Fetched(Result<Vec<GalleryEntry>>),
+    Thumbnail(String, Result<ImageHandle>),
+    Input(GalleryInput),
+    DownloadProgress {
+        finished: usize,
+        total: usize,
+    },
+    Downloaded(Result<PathBuf>),
+}
+
+struct Download {
+    machine_url: String,
+    finished: usize,
+    total: usize,
+    _progress_task: Handle,
+}
+
+#[derive(Default)]
+pub struct Gallery {
+    entries: Vec<GalleryEntry>,
+    /// when the shown entries were fetched, [None] until either the cache or the fetch delivers them
+    fetched_at: Option<DateTime<Utc>>,
+    opened: bool,
+    refreshing: bool,
+    status: Option<String>,
+    /// [None] while the thumbnail is being downloaded (or when it could not be)
+    thumbnails: BTreeMap<String, Option<ImageHandle>>,
+    game: Option<String>,
+    show_nsfw: bool,
+    download: Option<Download>,
+}
+
+fn gallery(message: GalleryMessage) -> AppMessage {
+    Some(Message::Gallery(message))
+}
+
+/// the gallery goes through the same http client the cli uses, which needs a tokio runtime of its own
+fn in_background<T, F, Fut>(task: F) -> impl Future<Output = Result<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T>>,
+{
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        crate::tokio_runtime_multi(2)
+            .and_then(|runtime| runtime.block_on(task()))
+            .pipe(|result| tx.send(result).ok())
+    });
+    rx.map(|result| result.context("gallery thread crashed").and_then(identity))
+}
+
+impl Gallery {
+    fn visible(&self) -> Vec<&GalleryEntry> {
+        modlist_gallery::filter(&self.entries, self.game.as_deref(), None)
+            .into_iter()
+            .filter(|entry| self.show_nsfw || !entry.nsfw)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> iced::Task<AppMessage> {
+        self.refreshing = true;
+        iced::Task::perform(
+            in_background(|| async {
+                modlist_gallery::fetch_gallery()
+                    .await
+                    .tap_ok(|entries| modlist_gallery::write_cache(&modlist_gallery::cache_path(), entries))
+            }),
+            |fetched| gallery(GalleryMessage::Fetched(fetched)),
+        )
+    }
+
+    fn show(&mut self, entries: Vec<GalleryEntry>, fetched_at: DateTime<Utc>) -> iced::Task<AppMessage> {
+        self.entries = entries;
+        self.fetched_at = Some(fetched_at);
+        self.request_thumbnails()
+    }
+
+    fn request_thumbnails(&mut self) -> iced::Task<AppMessage> {
+        let wanted = self
+            .visible()
+            .into_iter()
+            .take(MAX_THUMBNAILS)
+            .filter(|entry| !self.thumbnails.contains_key(&entry.machine_url()))
+            .filter_map(|entry| {
+                entry
+                    .links
+                    .image
+                    .parse::<url::Url>()
+                    .ok()
+                    .map(|url| (entry.machine_url(), url))
+            })
+            .collect_vec();
+        wanted
+            .into_iter()
+            .map(|(machine_url, url)| {
+                self.thumbnails.insert(machine_url.clone(), None);
+                iced::Task::perform(download_image(url, ImageKind::Thumbnail), move |thumbnail| {
+                    gallery(GalleryMessage::Thumbnail(machine_url.clone(), thumbnail))
+                })
+            })
+            .pipe(iced::Task::batch)
+    }
+
+    fn start_download(&mut self, machine_url: String, project_root: &Path) -> iced::Task<AppMessage> {
+        match (
+            &self.download,
+            self.entries
+                .iter()
+                .find(|entry| entry.machine_url() == machine_url),
+        ) {
+            (Some(_), _) | (_, None) => iced::Task::none(),
+            (None, Some(entry)) => {
+                let entry = entry.clone();
+                let to = project_root.join(entry.file_name());
+                let (tx, rx) = futures::channel::mpsc::unbounded();
+                let (progress_task, handle) =
+                    iced::Task::run(rx, |(finished, total)| gallery(GalleryMessage::DownloadProgress { finished, total })).abortable();
+                self.status = None;
+                self.download = Some(Download {
+                    machine_url,
+                    finished: 0,
+                    total: 0,
+                    _progress_task: handle.abort_on_drop(),
+                });
+                iced::Task::batch([
+                    progress_task,
+                    iced::Task::perform(
+                        in_background(move || async move {
+                            modlist_gallery::download_entry(&entry, to, move |finished, total| {
+                                tx.unbounded_send((finished, total)).ok();
+                            })
+                            .await
+                        }),
+                        |downloaded| gallery(GalleryMessage::Downloaded(downloaded)),
+                    ),
+                ])
+            }
+        }
+    }
+
+    pub fn update(&mut self, message: GalleryMessage, project_root: &Path) -> iced::Task<AppMessage> {
+        match message {
+            GalleryMessage::Open => match self.opened {
+                true => iced::Task::none(),
+                false => {
+                    self.opened = true;
+                    let cache = modlist_gallery::cache_path();
+                    iced::Task::batch([
+                        iced::Task::perform(async move { modlist_gallery::read_cache(&cache) }, |cached| {
+                            gallery(GalleryMessage::CacheRead(cached))
+                        }),
+                        self.refresh(),
+                    ])
+                }
+            },
+            GalleryMessage::CacheRead(cached) => match (cached, self.fetched_at) {
+                // the fetch was faster
+                (Ok(_), Some(_)) => iced::Task::none(),
+                (Ok(CachedGallery { fetched_at, entries }), None) => self.show(entries, fetched_at),
+                (Err(reason), _) => {
+                    debug!("no cached gallery: {reason:?}");
+                    iced::Task::none()
+                }
+            },
+            GalleryMessage::Fetched(fetched) => {
+                self.refreshing = false;
+                match fetched {
+                    Ok(entries) => {
+                        self.status = None;
+                        self.show(entries, Utc::now())
+                    }
+                    Err(reason) => {
+                        self.status = Some(match self.fetched_at {
+                            Some(fetched_at) => format!("could not refresh the gallery, showing the copy from [{fetched_at}]: {reason:#}"),
+                            None => format!("could not fetch the gallery: {reason:?}"),
+                        });
+                        iced::Task::none()
+                    }
+                }
+            }
+            GalleryMessage::Thumbnail(machine_url, thumbnail) => {
+                match thumbnail {
+                    Ok(thumbnail) => {
+                        self.thumbnails.insert(machine_url, Some(thumbnail));
+                    }
+                    Err(reason) => debug!("no thumbnail for [{machine_url}]: {reason:?}"),
+                }
+                iced::Task::none()
+            }
+            GalleryMessage::Input(input) => match input {
+                GalleryInput::Game(game) => {
+                    self.game = (game != ALL_GAMES).then_some(game);
+                    self.request_thumbnails()
+                }
+                GalleryInput::ShowNsfw(show_nsfw) => {
+                    self.show_nsfw = show_nsfw;
+                    self.request_thumbnails()
+                }
+                GalleryInput::Refresh => match self.refreshing {
+                    true => iced::Task::none(),
+                    false => self.refresh(),
+                },
+                GalleryInput::Download(machine_url) => self.start_download(machine_url, project_root),
+            },
+            GalleryMessage::DownloadProgress { finished, total } => {
+                if let Some(download) = self.download.as_mut() {
+                    download.finished = finished;
+                    download.total = total;
+                }
+                iced::Task::none()
+            }
+            GalleryMessage::Downloaded(downloaded) => {
+                self.download = None;
+                match downloaded {
+                    Ok(path) => {
+                        info!("selecting the downloaded modlist at [{}]", path.display());
+                        self.status = Some(format!("downloaded [{}]", path.display()));
+                        iced::Task::batch([
+                            iced::Task::done(Some(Message::SelectTab(Tab::Config))),
+                            iced::Task::done(Some(Message::SelectWabbajackFile(path.maybe_relative_to_exists(project_root)))),
+                        ])
+                    }
+                    Err(reason) => {
+                        self.status = Some(format!("{reason:?}"));
+                        iced::Task::none()
+                    }
+                }
+            }
+        }
+    }
+
+    fn entry_view<'a>(&'a self, entry: &'a GalleryEntry) -> Element<'a, GalleryInput> {
+        let GalleryRow {
+            download_size, installed_size, ..
+        } = GalleryRow::from(entry);
+        let machine_url = entry.machine_url();
+        Row::with_children([
+            match self.thumbnails.get(&machine_url) {
+                Some(Some(thumbnail)) => iced::widget::image(thumbnail.clone()).conv::<Element<_>>(),
+                _ => text("").into(),
+            }
+            .pipe(container)
+            .width(Length::Fixed(super::THUMBNAIL_SIZE.0 as f32))
+            .into(),
+            Column::with_children([
+                text(entry.title.clone()).bold().into(),
+                text(format!(
+                    "{game} | by {author}{version}",
+                    game = entry.game,
+                    author = entry.author,
+                    version = entry
+                        .version
+                        .as_ref()
+                        .map(|version| format!(" | v{version}"))
+                        .unwrap_or_default()
+                ))
+                .into(),
+                text(format!("download: {download_size}, installed: {installed_size}")).into(),
+            ])
+            .spacing(5)
+            .width(Length::Fill)
+            .into(),
+            button("DOWNLOAD")
+                .on_press_maybe(
+                    self.download
+                        .is_none()
+                        .then(|| GalleryInput::Download(machine_url)),
+                )
+                .into(),
+        ])
+        .align_y(Vertical::Center)
+        .spacing(20)
+        .into()
+    }
+
+    pub fn view(&self) -> Element<'_, GalleryInput> {
+        let games = std::iter::once(ALL_GAMES.to_string())
+            .chain(
+                self.entries
+                    .iter()
+                    .map(|entry| entry.game.clone())
+                    .sorted()
+                    .dedup(),
+            )
+            .collect_vec();
+        let visible = self.visible();
+        Column::new()
+            .push(
+                Row::with_children([
+                    pick_list(games, Some(self.game.clone().unwrap_or_else(|| ALL_GAMES.to_string())), GalleryInput::Game).into(),
+                    checkbox("show NSFW", self.show_nsfw)
+                        .on_toggle(GalleryInput::ShowNsfw)
+                        .into(),
+                    match (self.refreshing, self.fetched_at) {
+                        (true, _) => "refreshing...".to_string(),
+                        (false, Some(fetched_at)) => format!("[{}] modlists, fetched at [{fetched_at}]", visible.len()),
+                        (false, None) => String::new(),
+                    }
+                    .pipe(text)
+                    .width(Length::Fill)
+                    .into(),
+                    button("REFRESH")
+                        .on_press_maybe((!self.refreshing).then_some(GalleryInput::Refresh))
+                        .into(),
+                ])
+                .align_y(Vertical::Center)
+                .spacing(20),
+            )
+            .extend(self.status.as_ref().map(|status| {
+                text(status.clone())
+                    .color(iced::Color::from_rgb(1., 0.5, 0.))
+                    .into()
+            }))
+            .extend(self.download.as_ref().map(|download| {
+                Row::with_children([
+                    text(format!("downloading [{}]", download.machine_url)).into(),
+                    progress_bar(0. ..=download.total.max(1) as f32, download.finished as f32)
+                        .girth(12)
+                        .into(),
+                ])
+                .align_y(Vertical::Center)
+                .spacing(20)
+                .into()
+            }))
+            .push(
+                visible
+                    .into_iter()
+                    .map(|entry| self.entry_view(entry))
+                    .pipe(Column::with_children)
+                    .spacing(15)
+                    .pipe(scrollable)
+                    .height(Length::Fill),
+            )
+            .spacing(15)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn entry(machine_url: &str, game: &str, nsfw: bool) -> GalleryEntry {
+        serde_json::from_value::<GalleryEntry>(json!({
+            "title": machine_url,
+            "game": game,
+            "nsfw": nsfw,
+            "links": {
+                "download": format!("https://authored-files.wabbajack.org/{machine_url}.wabbajack_abc"),
+                "machineURL": machine_url,
+            },
+        }))
+        .expect("valid gallery entry")
+        .tap_mut(|entry| entry.repository = "wj-featured".to_string())
+    }
+
+    fn titles(gallery: &Gallery) -> Vec<&str> {
+        gallery
+            .visible()
+            .into_iter()
+            .map(|entry| entry.title.as_str())
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_filters() {
+        let mut gallery = Gallery::default();
+        let _ = gallery.show(
+            vec![
+                entry("tuxborn", "skyrimspecialedition", false),
+                entry("lewd", "skyrimspecialedition", true),
+                entry("magnum-opus", "fallout4", false),
+            ],
+            Utc::now(),
+        );
+        assert_eq!(titles(&gallery), ["magnum-opus", "tuxborn"]);
+        let _ = gallery.update(GalleryMessage::Input(GalleryInput::ShowNsfw(true)), Path::new("."));
+        let _ = gallery.update(GalleryMessage::Input(GalleryInput::Game("skyrimspecialedition".into())), Path::new("."));
+        assert_eq!(titles(&gallery), ["lewd", "tuxborn"]);
+        let _ = gallery.update(GalleryMessage::Input(GalleryInput::Game(ALL_GAMES.into())), Path::new("."));
+        assert_eq!(titles(&gallery).len(), 3);
+    }
+
+    #[test_log::test]
+    fn test_failed_refresh_keeps_the_cached_copy() {
+        let mut gallery = Gallery::default();
+        let cached_at = Utc::now();
+        let _ = gallery.update(
+            GalleryMessage::CacheRead(Ok(CachedGallery {
+                fetched_at: cached_at,
+                entries: vec![entry("tuxborn", "skyrimspecialedition", false)],
+            })),
+            Path::new("."),
+        );
+        let _ = gallery.update(GalleryMessage::Fetched(Err(anyhow::anyhow!("offline"))), Path::new("."));
+        assert_eq!(titles(&gallery), ["tuxborn"]);
+        assert_eq!(gallery.fetched_at, Some(cached_at));
+        assert!(
+            gallery
+                .status
+                .as_deref()
+                .is_some_and(|status| status.starts_with("could not refresh the gallery")),
+            "{:?}",
+            gallery.status
+        );
+    }
+}
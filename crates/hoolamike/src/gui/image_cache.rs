@@ -0,0 +1,92 @@
+//! the modlist image behind the window is kept in the project root, decoded and dimmed already. reading it out of the
+//! .wabbajack file goes through the whole zip (seconds on a network share) and downloading it takes a request - the cached
+//! one is used for as long as it was made from the very same .wabbajack file
+
+use {
+    crate::wabbajack_file::modlist_cache::{CACHE_DIRECTORY, CacheKey},
+    anyhow::{Context, Result},
+    image::RgbaImage,
+    std::{
+        io::Write,
+        path::{Path, PathBuf},
+    },
+    tracing::debug,
+};
+
+/// inside of [CACHE_DIRECTORY]
+pub const IMAGE_FILE_NAME: &str = "modlist-image.png";
+/// [CacheKey] of the .wabbajack file the image came from
+const KEY_FILE_NAME: &str = "modlist-image.json";
+
+#[derive(Debug, Clone)]
+pub struct ImageCache {
+    directory: PathBuf,
+}
+
+impl ImageCache {
+    pub fn in_project_root(project_root: &Path) -> Self {
+        Self {
+            directory: project_root.join(CACHE_DIRECTORY),
+        }
+    }
+
+    /// [None] when there's no image cached for exactly this .wabbajack file (or it can't be read)
+    pub fn load(&self, key: &CacheKey) -> Option<RgbaImage> {
+        std::fs::read(self.directory.join(KEY_FILE_NAME))
+            .context("reading key")
+            .and_then(|cached| serde_json::from_slice::<CacheKey>(&cached).context("parsing key"))
+            .and_then(|cached| match &cached == key {
+                true => image::open(self.directory.join(IMAGE_FILE_NAME))
+                    .context("decoding image")
+                    .map(|image| Some(image.to_rgba8())),
+                false => Ok(None),
+            })
+            .unwrap_or_else(|reason| {
+                debug!("no cached modlist image: {reason:?}");
+                None
+            })
+    }
+
+    pub fn store(&self, key: &CacheKey, image: &RgbaImage) -> Result<()> {
+        let key_path = self.directory.join(KEY_FILE_NAME);
+        // the old key must not vouch for the new image, even if writing the new key fails
+        match key_path.exists() {
+            true => std::fs::remove_file(&key_path).context("removing old key"),
+            false => Ok(()),
+        }
+        .and_then(|_| {
+            crate::atomic_write::write_atomically(&self.directory.join(IMAGE_FILE_NAME), |file| {
+                let mut writer = std::io::BufWriter::new(file);
+                image
+                    .write_with_encoder(image::codecs::png::PngEncoder::new(&mut writer))
+                    .context("encoding image")
+                    .and_then(|_| writer.flush().context("writing image"))
+            })
+        })
+        .and_then(|_| crate::atomic_write::write_atomically(&key_path, |file| serde_json::to_writer(file, key).context("serializing key")))
+        .with_context(|| format!("caching modlist image in [{}]", self.directory.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_image_is_cached_per_wabbajack_file() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let cache = ImageCache::in_project_root(directory.path());
+        let wabbajack_file = directory.path().join("Modlist.wabbajack");
+        std::fs::write(&wabbajack_file, b"first version")?;
+        let key = CacheKey::of(&wabbajack_file)?;
+        assert_eq!(cache.load(&key), None);
+
+        let image = RgbaImage::from_fn(4, 3, |x, y| image::Rgba([x as u8, y as u8, 7, 25]));
+        cache.store(&key, &image)?;
+        assert_eq!(cache.load(&key), Some(image));
+
+        std::fs::write(&wabbajack_file, b"second version of the modlist")?;
+        assert_eq!(cache.load(&CacheKey::of(&wabbajack_file)?), None, "cached for another file");
+        Ok(())
+    }
+}
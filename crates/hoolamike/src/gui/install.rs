@@ -0,0 +1,314 @@
+//! runs the installation inside the gui. the install itself is the same one `hoolamike install` runs, its progress is
+//! mirrored out of the tracing spans by [crate::progress_bars_v2::bridge]
+
+use {
+    super::{AppMessage, Message},
+    crate::{
+        DebugHelpers,
+        config_file::HoolamikeConfig,
+        error::TotalResult,
+        errors_log::{AggregatedErrors, grouped, installation_failed, write_errors_log},
+        install_modlist::{cancellation, install_modlist},
+        progress_bars_v2::bridge,
+    },
+    futures::{FutureExt, StreamExt},
+    hoola_progress::{Aggregation, ProgressMap, ProgressMessage, ProgressSpan, SpanPath},
+    iced::{
+        Element,
+        Length,
+        Padding,
+        alignment::Vertical,
+        task::Handle,
+        widget::{Column, Row, button, container, progress_bar, text},
+    },
+    itertools::Itertools,
+    std::path::Path,
+    tap::prelude::*,
+    tracing::info,
+};
+
+/// progress messages are handed to the gui in batches, byte counters alone produce thousands of them per second
+const PROGRESS_BATCH: usize = 4096;
+/// deeper levels of the tree are summarized by their parents
+const MAX_DEPTH: usize = 4;
+const MAX_ROWS: usize = 48;
+
+#[derive(Debug)]
+pub enum InstallMessage {
+    Progress(Vec<ProgressMessage>),
+    Cancel,
+    /// number of installed files
+    Finished(TotalResult<usize>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallStatus {
+    Running,
+    Cancelling,
+    Finished(String),
+}
+
+pub struct InstallRun {
+    pub progress: ProgressMap,
+    pub status: InstallStatus,
+    /// the progress stream outlives the install (some spans are never closed), it's aborted when the run is dropped
+    _progress_task: Handle,
+}
+
+impl std::fmt::Debug for InstallRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstallRun")
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
+}
+
+fn run_in_background(config: HoolamikeConfig) -> impl std::future::Future<Output = TotalResult<usize>> {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        install_modlist(config, DebugHelpers::default(), false, false, false, false)
+            .map(|installed| installed.len())
+            .pipe(|result| {
+                bridge::detach();
+                tx.send(result).ok()
+            })
+    });
+    rx.map(|result| result.unwrap_or_else(|_| Err(vec![anyhow::anyhow!("installation thread crashed")])))
+}
+
+impl InstallRun {
+    pub fn start(config: HoolamikeConfig) -> (Self, iced::Task<AppMessage>) {
+        cancellation::reset();
+        let (mut progress, messages, root) = ProgressMap::new();
+        // the phases show the bytes downloaded/written under them, a count of finished archives says little about the time left
+        progress.set_aggregation(Aggregation::Bytes);
+        bridge::attach(root);
+        let (progress_task, handle) = messages
+            .ready_chunks(PROGRESS_BATCH)
+            .pipe(|messages| iced::Task::run(messages, |batch| Some(Message::Install(InstallMessage::Progress(batch)))))
+            .abortable();
+        (
+            Self {
+                progress,
+                status: InstallStatus::Running,
+                _progress_task: handle.abort_on_drop(),
+            },
+            iced::Task::batch([
+                progress_task,
+                iced::Task::perform(run_in_background(config), |result| Some(Message::Install(InstallMessage::Finished(result)))),
+            ]),
+        )
+    }
+
+    /// returns the error for the error pane, if the install failed
+    pub fn update(&mut self, message: InstallMessage, config_path: &Path) -> Option<anyhow::Error> {
+        match message {
+            InstallMessage::Progress(batch) => {
+                batch
+                    .into_iter()
+                    .for_each(|message| self.progress.handle(message));
+                None
+            }
+            InstallMessage::Cancel => {
+                if self.status == InstallStatus::Running {
+                    cancellation::cancel();
+                    self.status = InstallStatus::Cancelling;
+                }
+                None
+            }
+            InstallMessage::Finished(result) => match result {
+                Ok(installed) => {
+                    self.status = InstallStatus::Finished(format!("successfully installed [{installed}] files"));
+                    info!("successfully installed [{installed}] files");
+                    None
+                }
+                Err(errors) => {
+                    let summary = match cancellation::is_cancelled() {
+                        true => "installation was cancelled".to_string(),
+                        false => installation_failed(&errors),
+                    };
+                    let listed = grouped(&errors);
+                    let log = AggregatedErrors {
+                        summary: summary.clone(),
+                        errors,
+                    }
+                    .pipe(anyhow::Error::new)
+                    .pipe_ref(|error| write_errors_log(error, config_path))
+                    .map(|path| format!(" (full details in [{}])", path.display()))
+                    .unwrap_or_default();
+                    self.status = InstallStatus::Finished(summary.clone());
+                    Some(anyhow::anyhow!("{listed}").context(format!("{summary}{log}")))
+                }
+            },
+        }
+    }
+
+    fn amount(&self, path: &SpanPath) -> String {
+        crate::progress_bars_v2::amount(&self.progress, path)
+    }
+
+    /// the root of the tree only collects the phases
+    fn visible(&self) -> impl Iterator<Item = (&SpanPath, &ProgressSpan)> {
+        self.progress
+            .progress
+            .iter()
+            .filter(|(path, _)| !path.is_empty() && path.len() <= MAX_DEPTH)
+    }
+
+    /// the only interaction is the cancel button
+    pub fn view(&self) -> Element<'_, ()> {
+        let visible = self.visible().collect_vec();
+        Column::new()
+            .push(
+                Row::with_children([
+                    match &self.status {
+                        InstallStatus::Running => "installing...",
+                        InstallStatus::Cancelling => "cancelling, waiting for the running tasks to stop...",
+                        InstallStatus::Finished(summary) => summary.as_str(),
+                    }
+                    .pipe(text)
+                    .width(Length::Fill)
+                    .into(),
+                    button("CANCEL")
+                        .on_press_maybe((self.status == InstallStatus::Running).then_some(()))
+                        .into(),
+                ])
+                .align_y(Vertical::Center)
+                .spacing(20),
+            )
+            .extend(visible.iter().take(MAX_ROWS).map(|(path, span)| {
+                let (current, total) = self
+                    .progress
+                    .displayed(path)
+                    .map_or((span.state.current, span.state.total), |(_, state)| (state.current, state.total));
+                Row::with_children([
+                    text(span.name.to_string())
+                        .width(Length::FillPortion(3))
+                        .into(),
+                    progress_bar(0. ..=total.max(1) as f32, current as f32)
+                        .girth(12)
+                        .length(Length::FillPortion(2))
+                        .into(),
+                    text(self.amount(path)).width(Length::FillPortion(2)).into(),
+                ])
+                .align_y(Vertical::Center)
+                .spacing(10)
+                .pipe(container)
+                .padding(Padding {
+                    left: 16. * path.len().saturating_sub(1) as f32,
+                    ..Default::default()
+                })
+                .into()
+            }))
+            .extend(
+                visible
+                    .len()
+                    .checked_sub(MAX_ROWS)
+                    .filter(|more| *more > 0)
+                    .map(|more| text(format!("...and [{more}] more")).into()),
+            )
+            .spacing(5)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::anyhow, hoola_progress::ProgressKind};
+
+    fn run() -> InstallRun {
+        InstallRun {
+            progress: ProgressMap::new().0,
+            status: InstallStatus::Running,
+            _progress_task: iced::Task::<()>::none().abortable().1,
+        }
+    }
+
+    #[test_log::test]
+    fn test_errors_end_up_in_the_error_pane() -> anyhow::Result<()> {
+        let directory = tempfile::tempdir()?;
+        let config_path = directory.path().join(crate::config_file::CONFIG_FILE_NAME);
+        let mut install = run();
+        let error = install
+            .update(
+                InstallMessage::Finished(Err(vec![anyhow!("connection reset"), anyhow!("hash mismatch")])),
+                &config_path,
+            )
+            .expect("no error reported");
+        let shown = format!("{error:?}");
+        assert!(
+            shown.starts_with("could not finish installation due to [2] errors ([2] distinct causes) (full details in ["),
+            "{shown}"
+        );
+        assert!(shown.contains("connection reset") && shown.contains("hash mismatch"), "{shown}");
+        assert_eq!(
+            install.status,
+            InstallStatus::Finished("could not finish installation due to [2] errors ([2] distinct causes)".into())
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_progress_is_applied() {
+        let (_, messages, root) = ProgressMap::new();
+        let mut install = run();
+        let _phase = root.child("sync_downloads");
+        drop(root);
+        let mut messages = messages;
+        messages.close();
+        let batch = futures::executor::block_on(messages.by_ref().collect::<Vec<_>>());
+        assert!(
+            install
+                .update(InstallMessage::Progress(batch), Path::new("unused"))
+                .is_none()
+        );
+        assert_eq!(
+            install
+                .visible()
+                .map(|(_, span)| span.name.to_string())
+                .collect_vec(),
+            ["sync_downloads"]
+        );
+    }
+
+    #[test_log::test]
+    fn test_rate_and_eta_are_shown() {
+        let (_, mut messages, root) = ProgressMap::new();
+        let mut install = run();
+        install.progress.set_aggregation(Aggregation::Bytes);
+        let phase = root.child("sync_downloads");
+        let download = phase.span_raw(ProgressSpan {
+            name: "a.7z".into(),
+            state: hoola_progress::progress_span::ProgressState { total: 1000, current: 0 },
+            kind: ProgressKind::Bytes,
+            samples: Default::default(),
+        });
+        hoola_progress::Progress::send(
+            &download,
+            hoola_progress::Update::Update(hoola_progress::progress_span::ProgressDelta { total: 0, current: 250 }),
+        );
+        download.flush();
+        messages.close();
+        let started = std::time::Instant::now();
+        futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .zip([0, 0, 500])
+            .for_each(|(message, after)| {
+                install
+                    .progress
+                    .handle_at(message, started + std::time::Duration::from_millis(after))
+            });
+        let amounts = install
+            .visible()
+            .map(|(path, _)| install.amount(path))
+            .collect_vec();
+        // 250 bytes in half a second, 750 left
+        assert_eq!(amounts.len(), 2);
+        amounts.iter().for_each(|amount| {
+            assert!(amount.contains("500 B/s ETA"), "{amount}");
+        });
+        // the phase shows the bytes of the download under it
+        assert!(amounts[0].starts_with("250 B/1000 B "), "{}", amounts[0]);
+        drop((download, phase));
+    }
+}
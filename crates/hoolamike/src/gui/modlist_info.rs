@@ -0,0 +1,99 @@
+//! what the author of the loaded modlist wants people to see first - the readme and website (opened in the browser), the
+//! description, and the NSFW warning which has to be acknowledged before the installation can start
+
+use {
+    super::helpers::BoldText,
+    crate::{
+        modlist_json::about::{About, NSFW_WARNING, as_link},
+        nexus_login::DEFAULT_BROWSER,
+    },
+    anyhow::Context,
+    iced::{
+        Color,
+        Element,
+        Length,
+        alignment::Vertical,
+        widget::{Column, Row, button, checkbox, container, text},
+    },
+    tap::prelude::*,
+    tracing::warn,
+};
+
+#[derive(Debug, Clone)]
+pub enum ModlistInfoInput {
+    OpenLink(String),
+    AcknowledgeNsfw(bool),
+}
+
+pub fn open_link(url: &str) {
+    std::process::Command::new(DEFAULT_BROWSER)
+        .arg(url)
+        .spawn()
+        .with_context(|| format!("opening [{url}] with ({DEFAULT_BROWSER})"))
+        .tap_err(|reason| warn!("{reason:?}"))
+        .ok();
+}
+
+/// links are buttons, anything else is plain text
+fn link<'a>(label: &str, value: &str) -> Element<'a, ModlistInfoInput> {
+    match as_link(value) {
+        Some(url) => Row::with_children([
+            text(format!("{label}:")).conv::<Element<_>>(),
+            button(text(url.to_string()))
+                .style(button::text)
+                .on_press(ModlistInfoInput::OpenLink(url.to_string()))
+                .into(),
+        ])
+        .align_y(Vertical::Center)
+        .spacing(10)
+        .into(),
+        None => text(format!("{label}: {value}")).into(),
+    }
+}
+
+pub fn view<'a>(about: About<'_>, nsfw_acknowledged: bool) -> Element<'a, ModlistInfoInput> {
+    let About {
+        name,
+        version,
+        author,
+        description,
+        website,
+        readme,
+        is_nsfw,
+    } = about;
+    Column::with_children(
+        [
+            Some(
+                text(format!("\"{name}\" by {author} (v{version})"))
+                    .bold()
+                    .into(),
+            ),
+            is_nsfw.then(|| {
+                container(
+                    Row::with_children([
+                        text(format!("NSFW: {NSFW_WARNING}"))
+                            .bold()
+                            .color(Color::from_rgb(1., 0.3, 0.3))
+                            .width(Length::Fill)
+                            .conv::<Element<_>>(),
+                        checkbox("I understand", nsfw_acknowledged)
+                            .on_toggle(ModlistInfoInput::AcknowledgeNsfw)
+                            .into(),
+                    ])
+                    .align_y(Vertical::Center)
+                    .spacing(20),
+                )
+                .padding(10)
+                .style(container::rounded_box)
+                .into()
+            }),
+            description.map(|description| text(description.to_string()).into()),
+            readme.map(|readme| link("readme (read it before installing)", readme)),
+            website.map(|website| link("website", website)),
+        ]
+        .into_iter()
+        .flatten(),
+    )
+    .spacing(10)
+    .into()
+}
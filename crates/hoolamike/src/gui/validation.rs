@@ -0,0 +1,255 @@
+//! checks ran against the config while it's being edited, so that problems show up next to the field instead of halfway
+//! through the install
+
+use {
+    crate::{
+        config_file::{GameConfig, HoolamikeConfig, ensure_local_installation_path},
+        downloaders::nexus::check_api_key,
+        modlist_json::GameName,
+        post_install_fixup::common::Resolution,
+    },
+    itertools::Itertools,
+    std::{collections::BTreeSet, iter::empty, ops::Not, path::Path},
+    tap::prelude::*,
+};
+
+/// placeholder used by the default config for paths the user has to fill in
+const PLACEHOLDER: &str = "FIXME";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    WabbajackFile,
+    InstallationPath,
+    DownloadsDirectory,
+    NexusApiKey,
+    Game(GameName),
+    GameResolution,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub field: Field,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub problems: Vec<Problem>,
+}
+
+fn is_placeholder(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str() == PLACEHOLDER)
+}
+
+/// the directory does not have to exist yet (install creates it), but the closest existing parent has to be writable
+fn check_writable_directory(path: &Path) -> Option<String> {
+    match path.is_file() {
+        true => Some(format!("[{}] is a file, not a directory", path.display())),
+        false => path
+            .ancestors()
+            .find(|ancestor| ancestor.is_dir())
+            .map(|existing| match tempfile::tempfile_in(existing) {
+                Ok(_) => None,
+                Err(reason) => Some(format!("[{}] is not writable: {reason}", existing.display())),
+            })
+            .unwrap_or_else(|| Some(format!("no part of [{}] exists", path.display()))),
+    }
+}
+
+impl Validation {
+    /// `resolution_input` is the text typed into the resolution field, when it could not be applied to the config
+    pub fn check(config: &HoolamikeConfig, project_root: &Path, required_games: &BTreeSet<GameName>, resolution_input: Option<&str>) -> Self {
+        let resolve = |path: &Path| project_root.join(path);
+        let placeholder = |field: Field, path: &Path| {
+            is_placeholder(path).then(|| Problem {
+                field,
+                message: format!("[{}] still has to be set up", path.display()),
+            })
+        };
+        let wabbajack_file = placeholder(Field::WabbajackFile, &config.installation.wabbajack_file_path).or_else(|| {
+            resolve(&config.installation.wabbajack_file_path)
+                .is_file()
+                .not()
+                .then(|| Problem {
+                    field: Field::WabbajackFile,
+                    message: format!("[{}] does not exist", config.installation.wabbajack_file_path.display()),
+                })
+        });
+        let remote_installation = ensure_local_installation_path(&config.installation.installation_path)
+            .err()
+            .map(|reason| Problem {
+                field: Field::InstallationPath,
+                message: format!("{reason}"),
+            });
+        let directories = [
+            (Field::InstallationPath, &config.installation.installation_path),
+            (Field::DownloadsDirectory, &config.downloaders.downloads_directory),
+        ]
+        .into_iter()
+        .filter(|(field, _)| !(field == &Field::InstallationPath && remote_installation.is_some()))
+        .filter_map(|(field, path)| {
+            placeholder(field.clone(), path).or_else(|| check_writable_directory(&resolve(path)).map(|message| Problem { field, message }))
+        })
+        .collect_vec();
+        let api_key = config
+            .downloaders
+            .nexus
+            .api_key
+            .as_deref()
+            .and_then(|api_key| check_api_key(api_key).err())
+            .map(|reason| Problem {
+                field: Field::NexusApiKey,
+                message: reason.to_string(),
+            });
+        let games = config
+            .games
+            .iter()
+            .filter_map(|(game_name, GameConfig { root_directory })| {
+                placeholder(Field::Game(game_name.clone()), root_directory).or_else(|| {
+                    resolve(root_directory).is_dir().not().then(|| Problem {
+                        field: Field::Game(game_name.clone()),
+                        message: format!("[{}] is not a directory", root_directory.display()),
+                    })
+                })
+            })
+            .chain(
+                required_games
+                    .iter()
+                    .filter(|game_name| !config.games.contains_key(*game_name))
+                    .map(|game_name| Problem {
+                        field: Field::Game(game_name.clone()),
+                        message: format!("modlist requires {game_name}, its directory is not set up"),
+                    }),
+            )
+            .collect_vec();
+        let resolution = resolution_input.and_then(|input| {
+            input.parse::<Resolution>().err().map(|reason| Problem {
+                field: Field::GameResolution,
+                message: format!("[{input}] is not a valid resolution (expected e.g. '1280x800'): {reason:#}"),
+            })
+        });
+        empty()
+            .chain(wabbajack_file)
+            .chain(remote_installation)
+            .chain(directories)
+            .chain(api_key)
+            .chain(games)
+            .chain(resolution)
+            .collect_vec()
+            .pipe(|problems| Self { problems })
+    }
+
+    pub fn for_field(&self, field: &Field) -> Vec<&str> {
+        self.problems
+            .iter()
+            .filter(|problem| &problem.field == field)
+            .map(|problem| problem.message.as_str())
+            .collect()
+    }
+
+    /// every problem found makes the install fail sooner or later
+    pub fn blocking(&self) -> Option<String> {
+        match self.problems.is_empty() {
+            true => None,
+            false => self
+                .problems
+                .iter()
+                .map(|Problem { message, .. }| format!("- {message}"))
+                .join("\n")
+                .pipe(|listed| format!("fix these first:\n{listed}"))
+                .pipe(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::Result};
+
+    fn config_in(directory: &Path) -> Result<HoolamikeConfig> {
+        std::fs::write(directory.join("modlist.wabbajack"), b"")?;
+        std::fs::create_dir(directory.join("game"))?;
+        HoolamikeConfig::default()
+            .tap_mut(|config| {
+                config.installation.wabbajack_file_path = "modlist.wabbajack".into();
+                config.installation.installation_path = "installed".into();
+                config.downloaders.downloads_directory = "downloads".into();
+                config
+                    .games
+                    .insert(GameName::new("Fallout4".into()), GameConfig { root_directory: "game".into() });
+            })
+            .pipe(Ok)
+    }
+
+    #[test_log::test]
+    fn test_sane_config_has_no_problems() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let config = config_in(directory.path())?;
+        let validation = Validation::check(&config, directory.path(), &BTreeSet::new(), None);
+        assert_eq!(validation.problems, []);
+        assert_eq!(validation.blocking(), None);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_problems_are_attached_to_their_fields() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let config = config_in(directory.path())?.tap_mut(|config| {
+            config.installation.wabbajack_file_path = "missing.wabbajack".into();
+            config.downloaders.downloads_directory = "FIXME/downloads".into();
+            config.downloaders.nexus.api_key = Some("pasted with a space".into());
+        });
+        let required = [GameName::new("SkyrimSpecialEdition".into())]
+            .into_iter()
+            .collect();
+        let validation = Validation::check(&config, directory.path(), &required, Some("1280-800"));
+        assert_eq!(
+            validation
+                .problems
+                .iter()
+                .map(|Problem { field, .. }| field.clone())
+                .collect_vec(),
+            [
+                Field::WabbajackFile,
+                Field::DownloadsDirectory,
+                Field::NexusApiKey,
+                Field::Game(GameName::new("SkyrimSpecialEdition".into())),
+                Field::GameResolution,
+            ]
+        );
+        assert_eq!(validation.for_field(&Field::InstallationPath), Vec::<&str>::new());
+        assert!(
+            validation
+                .blocking()
+                .is_some_and(|blocking| blocking.contains("missing.wabbajack")),
+            "{validation:?}"
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_remote_installation_path_is_refused() -> Result<()> {
+        ["ssh://me@nas/srv/modlist", "sftp://nas:2222/srv/modlist"]
+            .into_iter()
+            .try_for_each(|remote| -> Result<()> {
+                let directory = tempfile::tempdir()?;
+                let config = config_in(directory.path())?.tap_mut(|config| config.installation.installation_path = remote.into());
+                let validation = Validation::check(&config, directory.path(), &BTreeSet::new(), None);
+                assert_eq!(
+                    validation
+                        .for_field(&Field::InstallationPath)
+                        .into_iter()
+                        .map(|message| message.contains("not supported"))
+                        .collect_vec(),
+                    [true],
+                    "{remote}: {validation:?}"
+                );
+                Ok(())
+            })?;
+        // plain paths (even windows-looking ones, which parse as urls) stay local
+        ["installed", "/srv/modlist", "C:\\modlist"]
+            .into_iter()
+            .try_for_each(|local| ensure_local_installation_path(Path::new(local)))
+    }
+}
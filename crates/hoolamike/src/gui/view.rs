@@ -1,18 +1,23 @@
 use {
     crate::{
-        config_file::{DownloadersConfig, FixupConfig, GameConfig, HoolamikeConfig, InstallationConfig, NexusConfig},
+        config_file::{CONFIG_FILE_NAME, DownloadersConfig, FixupConfig, GameConfig, HoolamikeConfig, InstallationConfig, NexusConfig},
         gui::{
             AppMessage,
+            ConfigConflictResolution,
             FinalMessage,
             Message,
             TITLE,
-            fixup,
-            helpers::{BoldText, MaybeRelativeTo},
+            Tab,
+            displays::{self, ResolutionChoice},
+            gallery::GalleryMessage,
+            helpers::BoldText,
+            install::InstallMessage,
+            modlist_info,
             texconv,
             ttw,
+            validation::Field,
         },
-        modlist_json::Modlist,
-        post_install_fixup::common::Resolution,
+        project_root::MaybeRelativeTo,
     },
     anyhow::Context,
     clipboard_rs::Clipboard,
@@ -24,7 +29,7 @@ use {
         Padding,
         alignment::{Horizontal, Vertical},
         border,
-        widget::{Column, Row, Stack, button, center_x, checkbox, container, scrollable, text, text_input, tooltip},
+        widget::{Column, Row, Stack, button, center_x, checkbox, container, pick_list, scrollable, text, text_input, tooltip},
     },
     itertools::Itertools,
     normalize_path::NormalizePath,
@@ -42,6 +47,7 @@ pub const FONT_SIZE: f32 = 10.;
 
 impl super::State {
     pub fn view(&self) -> Element<'_, AppMessage> {
+        let run_blocked = self.run_blocked();
         self.pipe(
             |Self {
                  output_command,
@@ -53,6 +59,21 @@ impl super::State {
                  loaded_image,
                  required_games,
                  project_root,
+                 has_unsaved_changes: _,
+                 last_config_file_change: _,
+                 pending_external_config,
+                 ttw_requirements,
+                 install,
+                 resolution_input,
+                 detected_resolutions,
+                 custom_resolution,
+                 validation,
+                 tab,
+                 gallery,
+                 banner,
+                 dropped_config,
+                 nsfw_acknowledged,
+                 api_key_check,
              }| {
                 let config_editor = config.pipe(
                     |HoolamikeConfig {
@@ -60,15 +81,24 @@ impl super::State {
                              DownloadersConfig {
                                  downloads_directory,
                                  nexus: NexusConfig { api_key },
+                                 proxy: _,
+                                 insecure_hosts: _,
+                                 extra_search_directories: _,
+                                 schedule: _,
+                                 verification: _,
                              },
                          installation:
                              InstallationConfig {
                                  wabbajack_file_path,
                                  installation_path,
+                                 copy_wabbajack_locally: _,
                              },
                          games,
                          fixup,
                          extras,
+                         concurrency: _,
+                         debug_presets: _,
+                         advanced: _,
                      }| {
                         let config = config.clone();
                         enum PromptMode {
@@ -167,17 +197,71 @@ impl super::State {
                             )
                         }
 
-                        fn text_input_entry_password<'a>(tooltip_content: &str, placeholder: &str, name: &str, current: &str) -> Element<'a, String> {
+                        #[derive(Clone)]
+                        enum ApiKeyInput {
+                            Edit(String),
+                            LoginViaNexus,
+                        }
+
+                        /// `checked` is the account name, or why nexus did not accept the key
+                        fn api_key_entry<'a>(
+                            tooltip_content: &str,
+                            placeholder: &str,
+                            name: &str,
+                            current: &str,
+                            checked: Option<&Result<String, String>>,
+                        ) -> Element<'a, ApiKeyInput> {
                             table_entry_alignment(
                                 tooltip_content.into(),
                                 name.to_string(),
                                 text_input(placeholder, current)
                                     .secure(true)
-                                    .on_input(identity),
-                                text(""),
+                                    .on_input(ApiKeyInput::Edit),
+                                Row::with_children(
+                                    checked
+                                        .map(|checked| match checked {
+                                            Ok(account) => text(format!("\u{25CF} {account}")).color(Color::from_rgb(0.3, 0.9, 0.3)),
+                                            Err(_) => text("\u{25CF} rejected").color(Color::from_rgb(1., 0.3, 0.3)),
+                                        })
+                                        .map(Element::from)
+                                        .into_iter()
+                                        .chain(once(
+                                            button(text("Login via Nexus"))
+                                                .on_press(ApiKeyInput::LoginViaNexus)
+                                                .into(),
+                                        )),
+                                )
+                                .spacing(10)
+                                .align_y(Vertical::Center),
                             )
                         }
 
+                        /// surrounds the entry with a warning border and lists the problems found with its value
+                        fn flagged<'a, M: 'a>(entry: Element<'a, M>, problems: &[&str]) -> Element<'a, M> {
+                            match problems.is_empty() {
+                                true => entry,
+                                false => Column::with_children(once(entry).chain(problems.iter().map(|problem| {
+                                    text(problem.to_string())
+                                        .color(Color::from_rgb(1., 0.5, 0.))
+                                        .into()
+                                })))
+                                .spacing(5)
+                                .pipe(container)
+                                .style(|theme| {
+                                    iced::widget::container::Style::default().border(border::color(theme.extended_palette().warning.strong.color).width(4))
+                                })
+                                .align_y(Vertical::Center)
+                                .padding(20)
+                                .into(),
+                            }
+                        }
+
+                        // only the answer about the key currently in the field
+                        let api_key_checked = api_key_check
+                            .as_ref()
+                            .filter(|(checked, _)| Some(checked) == api_key.as_ref())
+                            .map(|(_, checked)| checked);
+
                         // false positive
                         #[allow(clippy::needless_borrows_for_generic_args)]
                         {
@@ -201,7 +285,8 @@ impl super::State {
                                                 p.map(|p| p.maybe_relative_to_exists(&project_root))
                                                     .map(Message::SelectWabbajackFile)
                                             }
-                                        }),
+                                        })
+                                        .pipe(|entry| flagged(entry, &validation.for_field(&Field::WabbajackFile))),
                                         path_entry(
                                             "Installation path - this is where .wabbajack files will be extracted. Default is fine.",
                                             "installation path",
@@ -218,7 +303,8 @@ impl super::State {
                                                 })
                                             }
                                         })
-                                        .map(non_fallible),
+                                        .map(non_fallible)
+                                        .pipe(|entry| flagged(entry, &validation.for_field(&Field::InstallationPath))),
                                     ])
                                     // DOWNLOADS
                                     .chain([
@@ -239,27 +325,46 @@ impl super::State {
                                                 })
                                             }
                                         })
-                                        .map(non_fallible),
-                                        text_input_entry_password(
-                                            "Your Nexus api key for premium downloads.  You can also use nxm handler for non-premium accounts - read \
-                                             `hoolamike handle-nxm --help` for details",
+                                        .map(non_fallible)
+                                        .pipe(|entry| flagged(entry, &validation.for_field(&Field::DownloadsDirectory))),
+                                        api_key_entry(
+                                            "Your Nexus api key for premium downloads (\"Login via Nexus\" fetches it for you).  You can also use nxm handler \
+                                             for non-premium accounts - read `hoolamike handle-nxm --help` for details",
                                             "<optional>",
                                             "nexus api key",
                                             &api_key.clone().unwrap_or_default(),
+                                            api_key_checked,
                                         )
                                         .map({
                                             cloned![config];
-                                            move |api_key| {
-                                                config.clone().tap_mut(|config| {
-                                                    config.downloaders.nexus.api_key = match api_key.is_empty() {
-                                                        true => None,
-                                                        false => Some(api_key),
-                                                    }
-                                                })
+                                            move |input| match input {
+                                                ApiKeyInput::Edit(api_key) => config
+                                                    .clone()
+                                                    .tap_mut(|config| {
+                                                        config.downloaders.nexus.api_key = match api_key.is_empty() {
+                                                            true => None,
+                                                            false => Some(api_key),
+                                                        }
+                                                    })
+                                                    .pipe(Some)
+                                                    .pipe(non_fallible),
+                                                ApiKeyInput::LoginViaNexus => Some(Message::NexusLogin),
                                             }
                                         })
-                                        .map(Some)
-                                        .map(non_fallible),
+                                        .pipe(|entry| {
+                                            flagged(
+                                                entry,
+                                                &validation
+                                                    .for_field(&Field::NexusApiKey)
+                                                    .tap_mut(|problems| {
+                                                        problems.extend(
+                                                            api_key_checked
+                                                                .and_then(|checked| checked.as_ref().err())
+                                                                .map(String::as_str),
+                                                        )
+                                                    }),
+                                            )
+                                        }),
                                     ])
                                     .chain(
                                         games
@@ -275,13 +380,20 @@ impl super::State {
                                                     cloned![config];
                                                     move |p| {
                                                         p.map(|p| {
-                                                            config
-                                                                .clone()
-                                                                .tap_mut(|c| c.games[game_name].root_directory = p)
+                                                            // config might have been reloaded in the meantime, the game entry could be gone
+                                                            config.clone().pipe(|mut config| {
+                                                                config
+                                                                    .games
+                                                                    .get_mut(game_name)
+                                                                    .with_context(|| format!("game [{game_name}] is no longer present in the config"))
+                                                                    .map(|game| game.root_directory = p)
+                                                                    .map(|_| config)
+                                                            })
                                                         })
+                                                        .map(Message::TryUpdateConfig)
                                                     }
                                                 })
-                                                .map(non_fallible)
+                                                .pipe(|entry| flagged(entry, &validation.for_field(&Field::Game(game_name.clone()))))
                                             }),
                                     )
                                     // GAME DIRECTORIES
@@ -309,16 +421,7 @@ impl super::State {
                                                     }
                                                 })
                                                 .map(non_fallible)
-                                                .pipe(|entry| {
-                                                    container(entry)
-                                                        .style(|theme| {
-                                                            iced::widget::container::Style::default()
-                                                                .border(border::color(theme.extended_palette().warning.strong.color).width(4))
-                                                        })
-                                                        .align_y(Vertical::Center)
-                                                        .padding(20)
-                                                        .conv::<Element<_>>()
-                                                })
+                                                .pipe(|entry| flagged(entry, &validation.for_field(&Field::Game(game_name.clone()))))
                                             }),
                                     )
                                     .chain(
@@ -334,34 +437,45 @@ impl super::State {
                                             .chain(
                                                 fixup
                                                     .as_ref()
-                                                    .map(|FixupConfig { game_resolution }| {
-                                                        text_input_entry(
-                                                            "Game resolution which will be automatically applied for Bethesda games. Format is '1280x800'",
-                                                            "game resolution",
-                                                            "game resolution",
-                                                            game_resolution.to_string().as_str(),
-                                                        )
-                                                        .map({
-                                                            cloned![config];
-                                                            move |resolution| {
-                                                                resolution
-                                                                    .parse::<Resolution>()
-                                                                    .context("bad resolution value")
-                                                                    .map({
-                                                                        cloned![config];
-                                                                        move |resolution: Resolution| {
-                                                                            config.clone().tap_mut(|c| {
-                                                                                c.fixup
-                                                                                    .get_or_insert_with(fixup::default_fixup)
-                                                                                    .game_resolution = resolution;
-                                                                            })
-                                                                        }
-                                                                    })
-                                                                    .pipe(Message::TryUpdateConfig)
-                                                                    .pipe(Some)
-                                                            }
-                                                        })
-                                                        .pipe(once)
+                                                    .map(|FixupConfig { game_resolution, .. }| {
+                                                        let choices = displays::choices(detected_resolutions, *game_resolution);
+                                                        let custom = *custom_resolution || resolution_input.is_some();
+                                                        let selected = choices
+                                                            .iter()
+                                                            .copied()
+                                                            .filter(|_| !custom)
+                                                            .find(|choice| {
+                                                                matches!(choice, ResolutionChoice::Listed { resolution, .. } if resolution == game_resolution)
+                                                            })
+                                                            .unwrap_or(ResolutionChoice::Custom);
+                                                        empty()
+                                                            .chain(
+                                                                table_entry_alignment(
+                                                                    "Game resolution which will be automatically applied for Bethesda games, pick one of the \
+                                                                     connected displays or type it in"
+                                                                        .to_string(),
+                                                                    "game resolution".to_string(),
+                                                                    pick_list(choices, Some(selected), identity),
+                                                                    text(""),
+                                                                )
+                                                                .map(|choice| Some(Message::PickResolution(choice)))
+                                                                .pipe(once),
+                                                            )
+                                                            .chain(custom.then(|| {
+                                                                text_input_entry(
+                                                                    "Game resolution which will be automatically applied for Bethesda games. Format is \
+                                                                     '1280x800'",
+                                                                    "game resolution",
+                                                                    "custom resolution",
+                                                                    resolution_input
+                                                                        .clone()
+                                                                        .unwrap_or_else(|| game_resolution.to_string())
+                                                                        .as_str(),
+                                                                )
+                                                                .map(|input| Some(Message::EditResolution(input)))
+                                                                .pipe(|entry| flagged(entry, &validation.for_field(&Field::GameResolution)))
+                                                            }))
+                                                            .collect_vec()
                                                     })
                                                     .into_iter()
                                                     .flatten(),
@@ -548,6 +662,21 @@ impl super::State {
                                                                                     })
                                                                                     .map(non_fallible)
                                                                                 }))
+                                                                                .chain(
+                                                                                    match ttw_requirements {
+                                                                                        Some((_, Ok(requirements))) => requirements
+                                                                                            .check(&variables, &config.games)
+                                                                                            .iter()
+                                                                                            .map(ToString::to_string)
+                                                                                            .collect_vec(),
+                                                                                        Some((_, Err(reason))) => {
+                                                                                            vec![format!("could not read the installer: {reason}")]
+                                                                                        }
+                                                                                        None => vec![],
+                                                                                    }
+                                                                                    .into_iter()
+                                                                                    .map(|problem| text(problem).color(Color::from_rgb(1., 0.5, 0.)).into()),
+                                                                                )
                                                                                 .collect_vec()
                                                                         },
                                                                     )
@@ -586,14 +715,31 @@ impl super::State {
                                         .padding(20)
                                         .conv::<Element<_>>()
                                         .map::<AppMessage>(|_| None)
-                                        .pipe(once),
+                                        .pipe(once)
+                                        .chain(install.as_ref().map(|install| {
+                                            install
+                                                .view()
+                                                .map(|()| Some(Message::Install(InstallMessage::Cancel)))
+                                        }))
+                                        .collect_vec()
+                                        .into_iter(),
                                         None => Row::with_children([
                                             button("SAVE")
                                                 .on_press_with(|| FinalMessage::Save)
                                                 .conv::<Element<_>>(),
                                             button("SAVE AND RUN")
-                                                .on_press_with(|| FinalMessage::SaveAndRun)
-                                                .into(),
+                                                .on_press_maybe(run_blocked.is_none().then_some(FinalMessage::SaveAndRun))
+                                                .pipe(|run| match run_blocked.clone() {
+                                                    Some(blocking) => tooltip(
+                                                        run,
+                                                        container(text(blocking))
+                                                            .padding(10)
+                                                            .style(container::rounded_box),
+                                                        tooltip::Position::Top,
+                                                    )
+                                                    .into(),
+                                                    None => run.into(),
+                                                }),
                                         ])
                                         .spacing(20)
                                         .padding(20)
@@ -605,7 +751,9 @@ impl super::State {
                                         })
                                         .conv::<Element<_>>()
                                         .map(|m| Some(Message::Final(m)))
-                                        .pipe(once),
+                                        .pipe(once)
+                                        .collect_vec()
+                                        .into_iter(),
                                     }),
                             )
                             .align_x(Horizontal::Center)
@@ -615,27 +763,91 @@ impl super::State {
                     },
                 );
                 let main_content = Column::with_children([
+                    Row::with_children([(Tab::Config, "CONFIGURATION"), (Tab::Gallery, "MODLIST GALLERY")].map(|(target, title)| {
+                        button(title)
+                            .on_press_maybe((*tab != target).then_some(target))
+                            .into()
+                    }))
+                    .spacing(20)
+                    .conv::<Element<_>>()
+                    .map(|tab| Some(Message::SelectTab(tab))),
+                    pending_external_config
+                        .as_ref()
+                        .map(|_| {
+                            Row::with_children([
+                                text(format!("{CONFIG_FILE_NAME} was changed on disk, but you have unsaved changes"))
+                                    .bold()
+                                    .width(Length::Fill)
+                                    .conv::<Element<_>>(),
+                                button("KEEP MINE")
+                                    .on_press_with(|| ConfigConflictResolution::KeepGui)
+                                    .into(),
+                                button("LOAD FROM DISK")
+                                    .on_press_with(|| ConfigConflictResolution::TakeDisk)
+                                    .into(),
+                            ])
+                            .align_y(Vertical::Center)
+                            .spacing(20)
+                            .conv::<Element<_>>()
+                            .map(|resolution| Some(Message::ResolveConfigConflict(resolution)))
+                        })
+                        .into(),
+                    dropped_config
+                        .as_ref()
+                        .map(|(dropped, _)| {
+                            Row::with_children([
+                                text(format!("switch to [{}]? unsaved changes will be lost", dropped.display()))
+                                    .bold()
+                                    .width(Length::Fill)
+                                    .conv::<Element<_>>(),
+                                button("CANCEL").on_press(false).into(),
+                                button("SWITCH").on_press(true).into(),
+                            ])
+                            .align_y(Vertical::Center)
+                            .spacing(20)
+                            .conv::<Element<_>>()
+                            .map(|confirmed| Some(Message::ConfirmDroppedConfig(confirmed)))
+                        })
+                        .into(),
+                    banner
+                        .as_ref()
+                        .map(|banner| {
+                            text(&banner.message)
+                                .bold()
+                                .pipe(|message| match banner.is_error {
+                                    true => message.color(Color::from_rgb(1., 0.5, 0.)),
+                                    false => message,
+                                })
+                                .conv::<Element<_>>()
+                        })
+                        .into(),
                     loaded_modlist_json
                         .as_ref()
                         .map(|f| &f.modlist)
-                        .map(
-                            |Modlist {
-                                 name,
-                                 version,
-                                 image: _,
-                                 game_type,
-                                 author,
-                                 ..
-                             }| {
-                                text(format!("[{game_type}]: \"{name}\" by {author} (v{version})"))
+                        .map(|modlist| {
+                            Column::with_children([
+                                text(format!("[{}]", modlist.game_type))
                                     .bold()
-                                    .conv::<Element<_>>()
-                            },
-                        )
+                                    .conv::<Element<_>>(),
+                                modlist_info::view(modlist.about(), *nsfw_acknowledged),
+                            ])
+                            .spacing(10)
+                            .conv::<Element<_>>()
+                            .map(|input| Some(Message::ModlistInfo(input)))
+                        })
                         .into(),
-                    scrollable(config_editor)
+                    match tab {
+                        Tab::Config => scrollable(config_editor)
+                            .height(Length::FillPortion(3))
+                            .conv::<Element<_, _, _>>(),
+                        Tab::Gallery => container(
+                            gallery
+                                .view()
+                                .map(|input| Some(Message::Gallery(GalleryMessage::Input(input)))),
+                        )
                         .height(Length::FillPortion(3))
-                        .conv::<Element<_, _, _>>(),
+                        .into(),
+                    },
                     scrollable(center_x(
                         text(error.as_ref().map(|e| format!("{e:?}")).unwrap_or_default()).color(Color::from_rgb(1., 0.5, 0.)),
                     ))
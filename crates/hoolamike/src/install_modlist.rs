@@ -2,41 +2,67 @@ use {
     crate::{
         DebugHelpers,
         config_file::{HoolamikeConfig, InstallationConfig},
-        consts::TEMP_FILE_DIR,
         downloaders::WithArchiveDescriptor,
         error::TotalResult,
+        exit_codes::{ClassifyExt, Failure},
         extensions::texconv_wine,
-        modlist_json::{Archive, HumanUrl, Modlist},
+        modlist_data::ArchiveDependents,
+        modlist_json::{Archive, Modlist},
         path::ExistingPath,
         progress_bars_v2::io_progress_style,
         tokio_runtime_multi,
         utils::PathReadWrite,
-        wabbajack_file::WabbajackFile,
+        wabbajack_file::{WabbajackFile, modlist_cache::ModlistCache},
     },
     anyhow::Context,
     case_insensitive_path::PathExistsUtf8Ext,
-    directives::{DirectivesHandler, DirectivesHandlerConfig, concurrency, transformed_texture::TexconvWineState},
-    download_cache::validate_hash_sha512,
-    downloads::{Synchronizers, stream_file_validate},
+    directives::{
+        DirectivesHandler,
+        DirectivesHandlerConfig,
+        concurrency,
+        transformed_texture::{TexconvWineState, dds_recompression_texconv_wine::TexconvHost},
+    },
+    downloads::{FailedArchive, Synchronizers},
+    execution_plan::{DirectiveSelector, ExecutionPlan, resume_from},
     futures::{FutureExt, TryFutureExt},
     itertools::Itertools,
     rayon::iter::{IntoParallelIterator, ParallelIterator},
+    run_stats::RunStats,
     std::{future::ready, path::Path, sync::Arc},
     tap::{Pipe, Tap, TapFallible},
-    tokio_stream::StreamExt,
-    tracing::{info, info_span, instrument},
+    tracing::{info, instrument, warn},
     tracing_indicatif::span_ext::IndicatifSpanExt,
 };
 
+/// hoolamike's own files kept next to the downloads
+const LOCAL_STATE_DIRECTORY: &str = ".hoolamike-state";
+
+pub mod archive_meta;
+pub mod cancellation;
+pub mod case_collisions;
+pub mod deterministic;
 pub mod directives;
 pub mod download_cache;
+pub mod download_overrides;
 pub mod downloads;
+pub mod execution_plan;
+pub mod foreign_downloads;
+pub mod quarantine;
+pub mod run_stats;
 
+#[cfg(unix)]
 #[instrument(fields(at=%at))]
 fn setup_texconv_wine(
     at: &ExistingPath,
     texconv_wine::ExtensionConfig { wine_path, texconv_path }: texconv_wine::ExtensionConfig,
 ) -> anyhow::Result<TexconvWineState> {
+    use {
+        crate::modlist_json::HumanUrl,
+        download_cache::validate_hash_sha512,
+        downloads::{HTTP_CLIENT, stream_file_validate},
+        tokio_stream::StreamExt,
+        tracing::info_span,
+    };
     #[rustfmt::skip]
     const TEXCONV_DEPS: &[(&str, &str, Option<&str>, &[&str])] = &[
         (
@@ -64,7 +90,7 @@ fn setup_texconv_wine(
                     at.join_new(name)
                         .with_context(|| format!("adding '{name}' to '{at}'"))
                         .pipe(ready)
-                        .and_then(|at| stream_file_validate(url, at, None))
+                        .and_then(|at| stream_file_validate(&HTTP_CLIENT, url, at, None, &[]))
                         .and_then(async |file| match expected_hash {
                             Some(expected_hash) => validate_hash_sha512(file.clone(), expected_hash).await,
                             None => Ok(file),
@@ -80,22 +106,44 @@ fn setup_texconv_wine(
             let canonicalize = |path: &Path| std::fs::canonicalize(path).with_context(|| format!("could not canonicalize [{path:?}]"));
             anyhow::Ok(TexconvWineState {
                 texconv_path: texconv_path.pipe_deref(canonicalize)?,
-                wine_prefix_state: wine_wrapper::wine_context::WineContext {
+                host: wine_wrapper::wine_context::WineContext {
                     wine_path,
                     show_gui: false,
                     prefix_dir: tempfile::Builder::new()
                         .prefix("pfx-")
-                        .tempdir_in(*TEMP_FILE_DIR)
+                        .tempdir_in(crate::temp_directory::run_directory()?)
                         .context("creating temp directory for prefix")
                         .map(Arc::new)?,
                 }
                 .initialize_with_installs(&downloaded)
                 .context("could not initialize wine context for texconv")
+                .map(TexconvHost::Wine)
                 .map(Arc::new)?,
             })
         })
 }
 
+/// texconv runs directly, the runtimes it needs are there on any windows which runs the games
+#[cfg(not(unix))]
+#[instrument(fields(at=%at))]
+fn setup_texconv_wine(
+    at: &ExistingPath,
+    texconv_wine::ExtensionConfig { wine_path: _, texconv_path }: texconv_wine::ExtensionConfig,
+) -> anyhow::Result<TexconvWineState> {
+    std::path::absolute(&texconv_path)
+        .with_context(|| format!("could not make [{}] absolute", texconv_path.display()))
+        .and_then(|texconv_path| {
+            texconv_path
+                .exists()
+                .then_some(texconv_path.clone())
+                .with_context(|| format!("texconv not found at [{}]", texconv_path.display()))
+        })
+        .map(|texconv_path| TexconvWineState {
+            texconv_path,
+            host: Arc::new(TexconvHost::Native),
+        })
+}
+
 #[allow(clippy::needless_as_bytes)]
 #[instrument(skip_all)]
 pub fn install_modlist(
@@ -104,22 +152,62 @@ pub fn install_modlist(
         installation: InstallationConfig {
             wabbajack_file_path,
             installation_path,
+            copy_wabbajack_locally,
         },
         games,
         fixup: _,
         extras,
+        concurrency: directive_concurrency,
+        debug_presets: _,
+        advanced,
     }: HoolamikeConfig,
     DebugHelpers {
         skip_verify_and_downloads,
         start_from_directive,
         skip_kind,
         contains,
+        preset: _,
     }: DebugHelpers,
+    strict_case: bool,
+    strict_version: bool,
+    deterministic: bool,
+    keep_going: bool,
 ) -> TotalResult<()> {
-    let installation_path = installation_path
-        .utf8_platform_path()
+    let run_stats = RunStats::start();
+    run_stats.phase("preparing");
+    let installation_path = crate::config_file::ensure_local_installation_path(&installation_path)
+        .and_then(|_| installation_path.utf8_platform_path())
         .and_then(|installation_path| installation_path.create_dir())
         .context("initializing installation path")
+        .classify(Failure::Config)
+        .map_err(|e| vec![e])?;
+    crate::temp_directory::configure(advanced.temp_directory.clone().unwrap_or_else(|| {
+        installation_path
+            .as_os_path()
+            .join(crate::temp_directory::DEFAULT_DIRECTORY_NAME)
+    }));
+    downloads::configure_proxy(downloaders.proxy.as_deref())
+        .classify(Failure::Config)
+        .map_err(|e| vec![e])?;
+    crate::compression::sevenz::configure_block_memory_limit(
+        advanced
+            .sevenz_block_memory_limit_mib
+            .map(|limit| limit * 1024 * 1024)
+            .unwrap_or(crate::compression::sevenz::DEFAULT_BLOCK_MEMORY_LIMIT),
+    );
+    directives::nested_archive_manager::configure_max_open_files(advanced.max_open_files);
+    let directive_concurrency = match deterministic {
+        true => {
+            warn!("--deterministic is set, directives are handled one at a time - expect the installation to take much longer");
+            deterministic::enable();
+            deterministic::concurrency(directive_concurrency)
+        }
+        false => directive_concurrency,
+    };
+    crate::compression::self_test::startup_check(&downloaders.downloads_directory.join(LOCAL_STATE_DIRECTORY));
+    crate::errors_log::set_log_directory(downloaders.downloads_directory.join(LOCAL_STATE_DIRECTORY));
+    let blocking_pools = crate::blocking_pool::configure(&directive_concurrency)
+        .context("setting up blocking pools")
         .map_err(|e| vec![e])?;
 
     let texconv_wine_state = extras
@@ -129,10 +217,17 @@ pub fn install_modlist(
         .map(|texconv_config| setup_texconv_wine(&installation_path, texconv_config))
         .transpose()
         .context("texconv config was specified, but it could not be set up")
+        .classify(Failure::Config)
         .map_err(|e| vec![e])?;
 
-    let synchronizers = Synchronizers::new(downloaders.clone(), games.clone())
+    // installation runs from the project root, the overrides live next to the config
+    let download_overrides = download_overrides::DownloadOverrides::load(Path::new(download_overrides::FILE_NAME))
+        .classify(Failure::Config)
+        .map(Arc::new)
+        .map_err(|e| vec![e])?;
+    let synchronizers = Synchronizers::new(downloaders.clone(), games.clone(), download_overrides.clone(), run_stats.clone())
         .context("setting up downloaders")
+        .classify(Failure::Config)
         .map_err(|e| vec![e])?;
     let (
         wabbajack_file_handle,
@@ -141,153 +236,250 @@ pub fn install_modlist(
             wabbajack_entries: _,
             modlist,
         },
-    ) = wabbajack_file_path
-        .exists_utf8()
-        .and_then(|wabbajack_file_path| WabbajackFile::load_wabbajack_file(&wabbajack_file_path))
-        .context("loading modlist file")
-        .tap_ok(|(_, wabbajack)| {
-            // PROGRESS
-            wabbajack
-                .modlist
-                .archives
-                .iter()
-                .map(|archive| archive.descriptor.size)
-                .chain(
-                    wabbajack
-                        .modlist
-                        .directives
-                        .iter()
-                        .map(|directive| directive.size()),
-                )
-                .sum::<u64>()
-                .pipe(|total_size| {
-                    tracing::Span::current().pipe_ref(|pb| {
-                        pb.pb_set_style(&io_progress_style());
-                        pb.pb_set_length(total_size);
-                    });
-                })
-        })
-        .map_err(|e| vec![e])?;
+    ) = match copy_wabbajack_locally {
+        true => WabbajackFile::copy_locally(&wabbajack_file_path, &downloaders.downloads_directory.join(LOCAL_STATE_DIRECTORY)),
+        false => Ok(wabbajack_file_path),
+    }
+    .and_then(|wabbajack_file_path| wabbajack_file_path.exists_utf8())
+    .and_then(|wabbajack_file_path| {
+        // installation runs from the project root
+        let cache = std::env::current_dir().ok().map(|project_root| {
+            crate::compression::listing_cache::configure(&project_root);
+            ModlistCache::in_project_root(&project_root)
+        });
+        WabbajackFile::load_wabbajack_file(&wabbajack_file_path, cache.as_ref())
+    })
+    .context("loading modlist file")
+    .tap_ok(|(_, wabbajack)| info!("installing {}", wabbajack.modlist.about()))
+    .tap_ok(|(_, wabbajack)| {
+        // PROGRESS
+        wabbajack
+            .modlist
+            .archives
+            .iter()
+            .map(|archive| archive.descriptor.size)
+            .chain(
+                wabbajack
+                    .modlist
+                    .directives
+                    .iter()
+                    .map(|directive| directive.size()),
+            )
+            .sum::<u64>()
+            .pipe(|total_size| {
+                tracing::Span::current().pipe_ref(|pb| {
+                    pb.pb_set_style(&io_progress_style());
+                    pb.pb_set_length(total_size);
+                });
+            })
+    })
+    .map_err(|e| vec![e])?;
 
-    modlist.pipe(Ok).and_then(
-        move |Modlist {
-                  archives,
-                  author: _,
-                  description: _,
-                  directives,
-                  game_type,
-                  image: _,
-                  is_nsfw: _,
-                  name: _,
-                  readme: _,
-                  version: _,
-                  wabbajack_version: _,
-                  website: _,
-              }| {
-            // let archives: Vec<_> = archives
-            //     .into_iter()
-            //     .filter(|archive| {
-            //         serde_json::to_string(&archive)
-            //             .tap_err(|e| tracing::error!("{e:#?}"))
-            //             .map(|directive| contains.iter().all(|contains| directive.contains(contains)))
-            //             .unwrap_or(false)
-            //     })
-            //     .collect();
-            match skip_verify_and_downloads {
-                true => archives
-                    .into_iter()
-                    .map(|Archive { descriptor, state: _ }| {
-                        synchronizers
-                            .cache
-                            .download_output_path(descriptor.name.as_str())
-                            .and_then(|inner| inner.exists_utf8())
-                            .map(|inner| WithArchiveDescriptor { inner, descriptor })
-                    })
-                    .collect::<anyhow::Result<Vec<_>>>()
-                    .map_err(|e| vec![e])
-                    .pipe(ready)
-                    .boxed_local(),
-                false => synchronizers
-                    .clone()
-                    .sync_downloads(archives.pipe(|archives| {
-                        archives
-                            .into_iter()
-                            .filter(|a| {
-                                contains.is_empty()
-                                    || serde_json::to_string(a)
-                                        .unwrap()
-                                        .pipe_deref(|a| contains.iter().any(|needle| a.contains(needle)))
+    let stats = run_stats.clone();
+    modlist
+        .pipe(Ok)
+        .and_then(
+            move |Modlist {
+                      archives,
+                      author: _,
+                      description: _,
+                      directives,
+                      game_type,
+                      image: _,
+                      is_nsfw: _,
+                      name: _,
+                      readme: _,
+                      version: _,
+                      wabbajack_version,
+                      website: _,
+                  }| {
+                // let archives: Vec<_> = archives
+                //     .into_iter()
+                //     .filter(|archive| {
+                //         serde_json::to_string(&archive)
+                //             .tap_err(|e| tracing::error!("{e:#?}"))
+                //             .map(|directive| contains.iter().all(|contains| directive.contains(contains)))
+                //             .unwrap_or(false)
+                //     })
+                //     .collect();
+                crate::modlist_json::format_version::check(&wabbajack_version, strict_version).map_err(|e| vec![e])?;
+                crate::game_version::required_versions(&archives).pipe_ref(|required| crate::game_version::report_versions(required, &games));
+                // the biggest archive has to fit when it's extracted
+                archives
+                    .iter()
+                    .map(|archive| archive.descriptor.size)
+                    .max()
+                    .map_or(Ok(()), crate::temp_directory::ensure_space)
+                    .map_err(|e| vec![e])?;
+                let mut directives = directives;
+                case_collisions::unify_destinations(&mut directives)
+                    .pipe(|collisions| case_collisions::report(&collisions, strict_case))
+                    .map_err(|e| vec![e])?;
+                let dependents = ArchiveDependents::new(&directives);
+                let resume = start_from_directive
+                    .as_deref()
+                    .map(|selector| {
+                        let plan = ExecutionPlan::new(&directives);
+                        selector
+                            .parse::<DirectiveSelector>()
+                            .and_then(|selector| plan.locate(&selector))
+                            .map(|start| resume_from(&directives, start))
+                            .tap_ok(|resume| info!("{}", resume.summary(&plan, archives.len())))
+                            .tap_ok(|_| {
+                                if skip_verify_and_downloads {
+                                    warn!(
+                                        "--skip-verify-and-downloads is set, archives required by the remaining directives are only expected to be downloaded \
+                                         already"
+                                    )
+                                }
                             })
-                            .collect_vec()
-                    }))
-                    .boxed_local(),
-            }
-            .pipe(|tasks| {
-                tokio_runtime_multi(concurrency())
-                    .map_err(|e| vec![e])
-                    .and_then(|r| r.block_on(tasks))
-            })
-            .and_then(|summary| {
-                // TODO: don't validate, just use the information that file was succesfully downloaded
-                summary
-                    .into_par_iter()
-                    .map(|summary| summary.try_map_t(|p| p.exists_utf8()))
-                    .collect::<Vec<_>>()
-                    .pipe(|v| v.into_iter().collect::<anyhow::Result<Vec<_>>>())
-                    .context("doing one last existance check")
-                    .map_err(|e| vec![e])
-            })
-            .and_then({
-                move |summary| {
-                    tracing::Span::current().pb_inc(summary.iter().map(|d| d.descriptor.size).sum());
-                    games
-                        .get(&game_type)
-                        .with_context(|| format!("[{game_type}] not found in {:?}", games.keys().collect::<Vec<_>>()))
-                        .and_then(|game_config| {
-                            DirectivesHandler::new(
-                                DirectivesHandlerConfig {
-                                    wabbajack_file: wabbajack_file_handle,
-                                    output_directory: installation_path,
-                                    game_directory: game_config.root_directory.clone(),
-                                    downloads_directory: downloaders.downloads_directory.clone(),
-                                    texconv_wine_state,
-                                },
-                                summary,
-                            )
+                    })
+                    .transpose()
+                    .context("resolving --start-from-directive")
+                    .map_err(|e| vec![e])?;
+                // archives only the skipped directives read from are neither verified nor downloaded
+                let archives = match resume.as_ref() {
+                    Some(resume) => archives
+                        .into_iter()
+                        .filter(|archive| resume.required_archives.contains(&archive.descriptor.hash))
+                        .collect_vec(),
+                    None => archives,
+                };
+                stats.phase("downloads");
+                match skip_verify_and_downloads {
+                    true => archives
+                        .into_iter()
+                        .map(|Archive { descriptor, state: _ }| {
+                            synchronizers
+                                .cache
+                                .output_path_for(&descriptor)
+                                .and_then(|inner| inner.exists_utf8())
+                                .map(|inner| WithArchiveDescriptor { inner, descriptor })
                         })
+                        .collect::<anyhow::Result<Vec<_>>>()
                         .map_err(|e| vec![e])
+                        .pipe(ready)
+                        .boxed_local(),
+                    false => synchronizers
+                        .clone()
+                        .sync_downloads(archives.pipe(|archives| {
+                            archives
+                                .into_iter()
+                                .filter(|a| {
+                                    contains.is_empty()
+                                        || serde_json::to_string(a)
+                                            .unwrap()
+                                            .pipe_deref(|a| contains.iter().any(|needle| a.contains(needle)))
+                                })
+                                .collect_vec()
+                        }))
+                        .boxed_local(),
                 }
-            })
-            .map(Arc::new)
-            .and_then(move |directives_handler| {
-                directives_handler
-                    .handle_directives(directives.tap_mut(|directives| {
-                        *directives = directives
-                            .pipe(std::mem::take)
-                            .drain(..)
-                            .skip_while(|d| {
-                                start_from_directive
-                                    .as_ref()
-                                    .map(|start_from_directive| &d.directive_hash() != start_from_directive)
-                                    .unwrap_or(false)
-                            })
-                            .filter(|directive| !skip_kind.contains(&directive.directive_kind()))
-                            .filter(|directive| {
-                                serde_json::to_string(&directive)
-                                    .tap_err(|e| tracing::error!("{e:#?}"))
-                                    .map(|directive| contains.iter().all(|contains| directive.contains(contains)))
-                                    .unwrap_or(false)
+                .pipe(|tasks| {
+                    tokio_runtime_multi(concurrency())
+                        .map_err(|e| vec![e])
+                        .and_then(|r| {
+                            // downloads in flight stop at their next chunk, keeping what they wrote in their partial files
+                            r.block_on(cancellation::until_cancelled_gracefully(tasks))
+                                .filter(|_| !cancellation::is_cancelled())
+                                .unwrap_or_else(|| Err(vec![cancellation::cancelled_error()]))
+                        })
+                })
+                .and_then(|summary| {
+                    // TODO: don't validate, just use the information that file was succesfully downloaded
+                    summary
+                        .into_par_iter()
+                        .map(|summary| summary.try_map_t(|p| p.exists_utf8()))
+                        .collect::<Vec<_>>()
+                        .pipe(|v| v.into_iter().collect::<anyhow::Result<Vec<_>>>())
+                        .context("doing one last existance check")
+                        .map_err(|e| vec![e])
+                })
+                .map_err(|errors| {
+                    errors
+                        .into_iter()
+                        .map(|error| {
+                            let impact = error
+                                .downcast_ref::<FailedArchive>()
+                                .map(|failed| dependents.impact(&failed.hash));
+                            match impact {
+                                Some(impact) if impact.files > 0 => error.context(format!("this failed archive affects {impact}")),
+                                _ => error,
+                            }
+                        })
+                        .map(|error| Failure::Downloads.mark(error))
+                        .collect_vec()
+                })
+                .and_then({
+                    move |summary| {
+                        stats.phase("directives");
+                        tracing::Span::current().pb_inc(summary.iter().map(|d| d.descriptor.size).sum());
+                        games
+                            .get(&game_type)
+                            .with_context(|| format!("[{game_type}] not found in {:?}", games.keys().collect::<Vec<_>>()))
+                            .classify(Failure::Config)
+                            .and_then(|game_config| {
+                                DirectivesHandler::new(
+                                    DirectivesHandlerConfig {
+                                        wabbajack_file: wabbajack_file_handle,
+                                        output_directory: installation_path,
+                                        game_directory: game_config.root_directory.clone(),
+                                        downloads_directory: downloaders.downloads_directory.clone(),
+                                        texconv_wine_state,
+                                        concurrency: directive_concurrency,
+                                        stats,
+                                        advanced,
+                                        keep_going,
+                                    },
+                                    summary,
+                                )
                             })
-                            .collect_vec();
-                    }))
-                    .map(|sizes| {
-                        sizes
-                            .into_iter()
-                            .for_each(|size| tracing::Span::current().pb_inc(size))
-                    })
-                    .map(|_| vec![()])
-                    .map_err(|err| vec![err])
-            })
-        },
-    )
+                            .map_err(|e| vec![e])
+                    }
+                })
+                .map(Arc::new)
+                .and_then(move |directives_handler| {
+                    directives_handler
+                        .handle_directives(directives.tap_mut(|directives| {
+                            *directives = directives
+                                .pipe(std::mem::take)
+                                .drain(..)
+                                .enumerate()
+                                .filter(|(position, _)| {
+                                    resume
+                                        .as_ref()
+                                        .is_none_or(|resume| resume.run.contains(position))
+                                })
+                                .map(|(_, directive)| directive)
+                                .filter(|directive| !skip_kind.contains(&directive.directive_kind()))
+                                .filter(|directive| {
+                                    serde_json::to_string(&directive)
+                                        .tap_err(|e| tracing::error!("{e:#?}"))
+                                        .map(|directive| contains.iter().all(|contains| directive.contains(contains)))
+                                        .unwrap_or(false)
+                                })
+                                .collect_vec();
+                            if deterministic {
+                                deterministic::sort(directives);
+                            }
+                        }))
+                        .map(|sizes| {
+                            sizes
+                                .into_iter()
+                                .for_each(|size| tracing::Span::current().pb_inc(size))
+                        })
+                        .map(|_| vec![()])
+                        .map_err(|err| match err.downcast::<directives::keep_going::DirectiveFailures>() {
+                            Ok(directives::keep_going::DirectiveFailures(errors)) => errors
+                                .into_iter()
+                                .map(|err| Failure::Directives.mark(err))
+                                .collect_vec(),
+                            Err(err) => vec![Failure::Directives.mark(err)],
+                        })
+                })
+            },
+        )
+        .tap(|_| blocking_pools.report())
+        .tap(|_| download_overrides.report())
+        .tap(|_| run_stats.report())
 }
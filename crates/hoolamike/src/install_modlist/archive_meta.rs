@@ -0,0 +1,157 @@
+//! `.meta` files Wabbajack and MO2 keep next to downloaded archives (`Some Mod.7z` -> `Some Mod.7z.meta`) - ini telling
+//! where the archive came from. written after every download, so that the downloads directory can be handed over to them,
+//! and read when looking for renamed archives in the extra search directories
+
+use {
+    crate::{
+        modlist_json::{GoogleDriveState, HttpState, ManualState, MediaFireState, MegaState, NexusState, State, WabbajackCDNDownloaderState},
+        post_install_fixup::ini::IniDocument,
+    },
+    anyhow::{Context, Result},
+    std::path::{Path, PathBuf},
+    tap::prelude::*,
+};
+
+pub const META_EXTENSION: &str = "meta";
+const SECTION: &str = "General";
+
+/// the source of an archive, as far as `.meta` files tell
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ArchiveMeta {
+    Nexus {
+        game_name: String,
+        mod_id: usize,
+        file_id: usize,
+    },
+    /// `directURL`
+    Url(String),
+    /// `manualURL`, a page someone has to click through
+    Manual(String),
+}
+
+/// `Archive.7z` -> `Archive.7z.meta`
+pub fn meta_path(archive: &Path) -> PathBuf {
+    archive
+        .as_os_str()
+        .to_owned()
+        .tap_mut(|path| {
+            path.push(".");
+            path.push(META_EXTENSION);
+        })
+        .into()
+}
+
+impl ArchiveMeta {
+    /// nothing to write for game files, they are not downloaded
+    pub fn from_state(state: &State) -> Option<Self> {
+        match state {
+            State::Nexus(NexusState {
+                game_name, mod_id, file_id, ..
+            }) => Some(Self::Nexus {
+                game_name: game_name.to_string(),
+                mod_id: *mod_id,
+                file_id: *file_id,
+            }),
+            State::Http(HttpState { url, headers: _ })
+            | State::WabbajackCDN(WabbajackCDNDownloaderState { url })
+            | State::MediaFire(MediaFireState { url })
+            | State::Mega(MegaState { url }) => Some(Self::Url(url.to_string())),
+            State::GoogleDrive(GoogleDriveState { id }) => Some(Self::Url(format!("https://drive.google.com/uc?id={id}&export=download"))),
+            State::Manual(ManualState { prompt: _, url }) => Some(Self::Manual(url.to_string())),
+            State::GameFileSource(_) => None,
+        }
+    }
+
+    pub fn parse(contents: &str) -> Option<Self> {
+        let ini = IniDocument::parse(contents);
+        let get = |key: &str| ini.get(SECTION, key).filter(|value| !value.is_empty());
+        match (get("gameName"), get("modID"), get("fileID"), get("directURL"), get("manualURL")) {
+            (Some(game_name), Some(mod_id), Some(file_id), _, _) => mod_id
+                .parse()
+                .ok()
+                .zip(file_id.parse().ok())
+                .map(|(mod_id, file_id)| Self::Nexus {
+                    game_name: game_name.to_string(),
+                    mod_id,
+                    file_id,
+                }),
+            (_, _, _, Some(url), _) => Some(Self::Url(url.to_string())),
+            (_, _, _, _, Some(url)) => Some(Self::Manual(url.to_string())),
+            _ => None,
+        }
+    }
+
+    /// game names are compared case-insensitively, Wabbajack and MO2 don't agree on the spelling
+    pub fn matches(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Nexus { game_name, mod_id, file_id },
+                Self::Nexus {
+                    game_name: other_game_name,
+                    mod_id: other_mod_id,
+                    file_id: other_file_id,
+                },
+            ) => game_name.eq_ignore_ascii_case(other_game_name) && mod_id == other_mod_id && file_id == other_file_id,
+            (this, other) => this == other,
+        }
+    }
+
+    pub fn to_ini(&self) -> String {
+        match self {
+            Self::Nexus { game_name, mod_id, file_id } => format!("[{SECTION}]\ngameName={game_name}\nmodID={mod_id}\nfileID={file_id}\n"),
+            Self::Url(url) => format!("[{SECTION}]\ndirectURL={url}\n"),
+            Self::Manual(url) => format!("[{SECTION}]\nmanualURL={url}\n"),
+        }
+    }
+
+    /// writes the `.meta` file of `archive`, unless one describing the same source is there already (MO2 adds keys of its
+    /// own, those are kept). returns whether anything was written
+    pub fn write_next_to(&self, archive: &Path) -> Result<bool> {
+        let path = meta_path(archive);
+        match std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|existing| Self::parse(&existing))
+            .is_some_and(|existing| existing.matches(self))
+        {
+            true => Ok(false),
+            false => std::fs::write(&path, self.to_ini())
+                .with_context(|| format!("writing [{}]", path.display()))
+                .map(|_| true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_meta_roundtrip() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let archive = directory.path().join("Some Mod-1234-1-0.7z");
+        assert_eq!(meta_path(&archive), directory.path().join("Some Mod-1234-1-0.7z.meta"));
+        let nexus = ArchiveMeta::Nexus {
+            game_name: "SkyrimSpecialEdition".into(),
+            mod_id: 1234,
+            file_id: 5678,
+        };
+        assert!(nexus.write_next_to(&archive)?);
+        assert_eq!(ArchiveMeta::parse(&std::fs::read_to_string(meta_path(&archive))?), Some(nexus.clone()));
+        assert!(!nexus.write_next_to(&archive)?, "identical meta was written again");
+
+        // the one mo2 keeps, with keys of its own
+        let mo2 = "[General]\r\ngameName=skyrimspecialedition\r\nmodID=1234\r\nfileID=5678\r\ninstalled=true\r\n";
+        std::fs::write(meta_path(&archive), mo2)?;
+        assert!(!nexus.write_next_to(&archive)?);
+        assert_eq!(std::fs::read_to_string(meta_path(&archive))?, mo2);
+
+        let url = ArchiveMeta::Url("https://mirror.example.org/mod.7z".into());
+        assert!(url.write_next_to(&archive)?);
+        assert_eq!(
+            std::fs::read_to_string(meta_path(&archive))?,
+            "[General]\ndirectURL=https://mirror.example.org/mod.7z\n"
+        );
+        assert_eq!(ArchiveMeta::parse("[General]\ninstalled=true\n"), None);
+        Ok(())
+    }
+}
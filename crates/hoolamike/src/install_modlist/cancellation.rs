@@ -0,0 +1,185 @@
+//! installs can be cancelled - from the gui, or with ctrl-c in the cli. downloads stop between chunks (keeping what they've
+//! written in their partial files, see [crate::atomic_write]), directives stop before the next one is handled. whatever is
+//! still running after a grace period is dropped with its runtime. a second ctrl-c exits right away
+
+use {
+    anyhow::{Context, Result, anyhow},
+    std::{
+        future::Future,
+        sync::{
+            Arc,
+            LazyLock,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::Duration,
+    },
+    tracing::warn,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// how long the running work gets to wind down on its own once the installation is cancelled
+pub const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+pub const RESUME_HINT: &str = "installation is being cancelled, the work in flight is finishing (press ctrl-c again to exit right away). run the same command \
+                               again to resume - finished downloads and directives are skipped, interrupted downloads continue from their partial files";
+
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(cancelled_error()),
+            false => Ok(()),
+        }
+    }
+
+    /// [None] when the token got cancelled before `task` finished
+    pub async fn until_cancelled<T>(&self, task: impl Future<Output = T>) -> Option<T> {
+        tokio::select! {
+            output = task => Some(output),
+            _ = async {
+                while !self.is_cancelled() {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            } => None,
+        }
+    }
+
+    /// like [Self::until_cancelled], but once cancelled `task` gets `grace` to wind down on its own before it's dropped
+    pub async fn until_cancelled_gracefully<T>(&self, task: impl Future<Output = T>, grace: Duration) -> Option<T> {
+        let mut task = std::pin::pin!(task);
+        match self.until_cancelled(task.as_mut()).await {
+            Some(output) => Some(output),
+            None => tokio::time::timeout(grace, task)
+                .await
+                .map_err(|_| warn!("running tasks did not stop within [{grace:?}], dropping them"))
+                .ok(),
+        }
+    }
+}
+
+/// token of the installation, the gui cancel button and ctrl-c both drive it
+static INSTALLATION: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::default);
+
+pub fn token() -> CancellationToken {
+    INSTALLATION.clone()
+}
+
+pub fn cancel() {
+    warn!("cancelling the installation");
+    INSTALLATION.cancel();
+}
+
+/// called before an installation starts
+pub fn reset() {
+    INSTALLATION.reset();
+}
+
+pub fn is_cancelled() -> bool {
+    INSTALLATION.is_cancelled()
+}
+
+/// classified as [crate::exit_codes::Failure::Cancelled]
+pub fn cancelled_error() -> anyhow::Error {
+    crate::exit_codes::Failure::Cancelled.mark(anyhow!("installation was cancelled"))
+}
+
+pub fn check() -> Result<()> {
+    INSTALLATION.check()
+}
+
+/// [None] when the installation got cancelled before `task` finished
+pub async fn until_cancelled<T>(task: impl Future<Output = T>) -> Option<T> {
+    INSTALLATION.until_cancelled(task).await
+}
+
+/// [None] when the installation got cancelled and `task` did not wind down within [GRACE_PERIOD]
+pub async fn until_cancelled_gracefully<T>(task: impl Future<Output = T>) -> Option<T> {
+    INSTALLATION
+        .until_cancelled_gracefully(task, GRACE_PERIOD)
+        .await
+}
+
+/// the first ctrl-c cancels the installation, the second one exits right away
+pub fn cancel_on_ctrl_c() -> Result<()> {
+    std::thread::Builder::new()
+        .name("ctrl-c".into())
+        .spawn(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|reason| warn!("could not listen for ctrl-c: {reason}"))
+                .ok()
+                .map(|runtime| {
+                    runtime.block_on(async {
+                        while tokio::signal::ctrl_c().await.is_ok() {
+                            match is_cancelled() {
+                                false => {
+                                    cancel();
+                                    eprintln!("\n{RESUME_HINT}\n");
+                                }
+                                true => {
+                                    eprintln!("\ninterrupted, exiting right away");
+                                    std::process::exit(crate::exit_codes::INTERRUPTED.into())
+                                }
+                            }
+                        }
+                    })
+                })
+        })
+        .context("spawning ctrl-c handler")
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test(tokio::test)]
+    async fn test_pending_task_is_dropped() {
+        reset();
+        let finished = until_cancelled(std::future::ready(1)).await;
+        assert_eq!(finished, Some(1));
+        let cancelled = until_cancelled(std::future::pending::<()>());
+        cancel();
+        assert_eq!(cancelled.await, None);
+        assert!(check().is_err());
+        reset();
+        assert!(check().is_ok());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_cancelled_task_gets_to_wind_down() {
+        let token = CancellationToken::default();
+        let winding_down = token.until_cancelled_gracefully(
+            {
+                let token = token.clone();
+                async move {
+                    while !token.is_cancelled() {
+                        tokio::time::sleep(POLL_INTERVAL).await;
+                    }
+                    "stopped on its own"
+                }
+            },
+            Duration::from_secs(5),
+        );
+        token.cancel();
+        assert_eq!(winding_down.await, Some("stopped on its own"));
+        let stuck = token.until_cancelled_gracefully(std::future::pending::<()>(), Duration::from_millis(200));
+        assert_eq!(stuck.await, None);
+    }
+}
@@ -0,0 +1,218 @@
+//! some modlists write both `Textures\foo.dds` and `textures\Foo.dds` - the game (through wine) sees a single file, but on a
+//! case-sensitive filesystem those are two, and which one wine picks is anyone's guess. destinations colliding like that are
+//! unified onto the spelling of the first directive writing there, so that what ends up on disk is what windows would have
+
+use {
+    super::execution_plan::{destination, destination_mut},
+    crate::modlist_json::Directive,
+    anyhow::Result,
+    case_insensitive_path::CaseInsensitivePathBuf,
+    itertools::Itertools,
+    std::collections::BTreeMap,
+    tracing::warn,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    /// spelling of the first directive, which every colliding directive writes to now
+    pub destination: String,
+    /// `<kind>:<index>` of the first directive
+    pub winner: String,
+    /// `<kind>:<index>` and the original spelling of every directive moved onto [Self::destination]
+    pub renamed: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for Collision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] ({}) wins over {}",
+            self.destination,
+            self.winner,
+            self.renamed
+                .iter()
+                .map(|(directive, spelling)| format!("[{spelling}] ({directive})"))
+                .join(", ")
+        )
+    }
+}
+
+/// `Textures\foo.dds` and `Textures/foo.dds` are the same file on every filesystem
+fn spelling(path: &CaseInsensitivePathBuf) -> String {
+    path.as_original_path().as_str().replace('\\', "/")
+}
+
+/// the same `<kind>:<index>` `--start-from-directive` accepts
+fn labels(directives: &[Directive]) -> Vec<String> {
+    let mut seen = BTreeMap::new();
+    directives
+        .iter()
+        .map(|directive| {
+            let kind = directive.directive_kind();
+            let count = seen.entry(kind).or_insert(0usize);
+            *count += 1;
+            format!("{kind}:{}", *count - 1)
+        })
+        .collect()
+}
+
+fn set_destination(directive: &mut Directive, to: CaseInsensitivePathBuf) {
+    // handlers of unknown directives read the raw json, it has to agree with the parsed fields
+    if let Directive::Unknown(unknown) = directive {
+        unknown
+            .raw
+            .get_mut("To")
+            .into_iter()
+            .for_each(|raw_to| *raw_to = serde_json::Value::String(to.as_original_path().as_str().to_string()));
+    }
+    *destination_mut(directive) = to;
+}
+
+/// rewrites the destinations of directives colliding case-insensitively with an earlier one to its spelling
+pub fn unify_destinations(directives: &mut [Directive]) -> Vec<Collision> {
+    let labels = labels(directives);
+    let mut first_writers = BTreeMap::<CaseInsensitivePathBuf, usize>::new();
+    let mut collisions = BTreeMap::<usize, Collision>::new();
+    (0..directives.len()).for_each(|position| {
+        let to = destination(&directives[position]);
+        match first_writers.get(to).copied() {
+            None => {
+                first_writers.insert(to.clone(), position);
+            }
+            Some(winner) => {
+                let (winner_to, original) = (destination(&directives[winner]).clone(), spelling(to));
+                if spelling(&winner_to) != original {
+                    collisions
+                        .entry(winner)
+                        .or_insert_with(|| Collision {
+                            destination: winner_to.as_original_path().as_str().to_string(),
+                            winner: labels[winner].clone(),
+                            renamed: vec![],
+                        })
+                        .renamed
+                        .push((labels[position].clone(), to.as_original_path().as_str().to_string()));
+                    set_destination(&mut directives[position], winner_to);
+                }
+            }
+        }
+    });
+    collisions.into_values().collect()
+}
+
+/// warns about every collision, or with `strict_case` fails listing all of them
+pub fn report(collisions: &[Collision], strict_case: bool) -> Result<()> {
+    match (collisions.is_empty(), strict_case) {
+        (true, _) => Ok(()),
+        (false, true) => Err(anyhow::anyhow!(
+            "[{}] destinations differ from others only in case (--strict-case is set):\n{}",
+            collisions.len(),
+            collisions.iter().join("\n")
+        )),
+        (false, false) => {
+            collisions
+                .iter()
+                .for_each(|collision| warn!("destinations differ only in case, writing them to the first one: {collision}"));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::modlist_json::directive::fixtures::{directive, from_archive, inline_file},
+        serde_json::json,
+    };
+
+    fn unknown(to: &str) -> Directive {
+        directive(json!({
+            "$type": "MergedPatch",
+            "Hash": "AAAAAAAAAAA=",
+            "Size": 1,
+            "To": to,
+        }))
+    }
+
+    fn spellings(directives: &[Directive]) -> Vec<String> {
+        directives
+            .iter()
+            .map(|directive| {
+                destination(directive)
+                    .as_original_path()
+                    .as_str()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_collisions_across_kinds_take_the_first_spelling() {
+        let mut directives = vec![
+            from_archive(r"Mods\A\Textures\foo.dds"),
+            inline_file(r"Mods\A\meshes\bar.nif"),
+            inline_file(r"mods\a\textures\Foo.dds"),
+            unknown(r"MODS\A\TEXTURES\FOO.DDS"),
+            from_archive(r"Mods\A\Meshes\Bar.nif"),
+        ];
+        let collisions = unify_destinations(&mut directives);
+        assert_eq!(
+            collisions,
+            [
+                Collision {
+                    destination: r"Mods\A\Textures\foo.dds".to_string(),
+                    winner: "FromArchive:0".to_string(),
+                    renamed: vec![
+                        ("InlineFile:1".to_string(), r"mods\a\textures\Foo.dds".to_string()),
+                        ("Unknown:0".to_string(), r"MODS\A\TEXTURES\FOO.DDS".to_string()),
+                    ],
+                },
+                Collision {
+                    destination: r"Mods\A\meshes\bar.nif".to_string(),
+                    winner: "InlineFile:0".to_string(),
+                    renamed: vec![("FromArchive:1".to_string(), r"Mods\A\Meshes\Bar.nif".to_string())],
+                },
+            ]
+        );
+        assert_eq!(
+            spellings(&directives),
+            [
+                r"Mods\A\Textures\foo.dds",
+                r"Mods\A\meshes\bar.nif",
+                r"Mods\A\Textures\foo.dds",
+                r"Mods\A\Textures\foo.dds",
+                r"Mods\A\meshes\bar.nif",
+            ]
+        );
+        match &directives[3] {
+            Directive::Unknown(unknown) => assert_eq!(unknown.raw["To"], r"Mods\A\Textures\foo.dds"),
+            other => panic!("expected an unknown directive, got {other:?}"),
+        }
+    }
+
+    #[test_log::test]
+    fn test_same_spelling_is_not_a_collision() {
+        let mut directives = vec![from_archive(r"Mods\A\foo.esp"), inline_file("Mods/A/foo.esp"), from_archive(r"Mods\B\foo.esp")];
+        assert!(unify_destinations(&mut directives).is_empty());
+        assert_eq!(spellings(&directives), [r"Mods\A\foo.esp", "Mods/A/foo.esp", r"Mods\B\foo.esp"]);
+    }
+
+    #[test_log::test]
+    fn test_strict_case_fails_listing_every_collision() {
+        let mut directives = vec![
+            from_archive(r"Textures\foo.dds"),
+            inline_file(r"textures\Foo.dds"),
+            inline_file(r"Meshes\bar.nif"),
+            unknown(r"meshes\bar.nif"),
+        ];
+        let collisions = unify_destinations(&mut directives);
+        assert!(report(&collisions, false).is_ok());
+        let error = report(&collisions, true)
+            .expect_err("collisions are not allowed")
+            .to_string();
+        assert!(error.starts_with("[2] destinations"), "{error}");
+        assert!(error.contains(r"[textures\Foo.dds] (InlineFile:0)"), "{error}");
+        assert!(error.contains(r"[meshes\bar.nif] (Unknown:0)"), "{error}");
+        assert!(report(&[], true).is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+//! `--deterministic` makes two runs of the same modlist log the same way: directives are sorted by kind and destination and
+//! handled one at a time, and temporary files get the same names. downloads stay parallel, they finish before any directive
+//! starts. it's slow - meant for debugging and for comparing logs between machines, not for installing
+
+use {
+    super::{directives::concurrency::ConcurrencyConfig, execution_plan::destination},
+    crate::modlist_json::Directive,
+    std::sync::atomic::{AtomicBool, Ordering},
+};
+
+/// temporary file names are drawn from a thread local generator, every directive worker starts it from here
+const SEED: u64 = 0x686f6f6c616d696b;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    seed_thread();
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// called by every directive worker when it starts, does nothing unless enabled
+pub fn seed_thread() {
+    if is_enabled() {
+        fastrand::seed(SEED);
+    }
+}
+
+/// ties (same kind and destination) keep the order of the modlist
+pub fn sort(directives: &mut [Directive]) {
+    directives.sort_by(|a, b| (a.directive_kind(), destination(a)).cmp(&(b.directive_kind(), destination(b))))
+}
+
+/// a single worker of every kind directives are handled by, what downloads use is left as configured
+pub fn concurrency(concurrency: ConcurrencyConfig) -> ConcurrencyConfig {
+    ConcurrencyConfig {
+        io_workers: Some(1),
+        cpu_workers: Some(1),
+        extraction_workers: Some(1),
+        ..concurrency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::modlist_json::{
+            DirectiveKind,
+            directive::fixtures::{from_archive, inline_file},
+        },
+    };
+
+    #[test_log::test]
+    fn test_sorted_by_kind_and_destination() {
+        let mut directives = vec![inline_file(r"mods\b.esp"), from_archive(r"textures\z.dds"), inline_file(r"Mods\A.esp")];
+        sort(&mut directives);
+        assert_eq!(
+            directives
+                .iter()
+                .map(|directive| (directive.directive_kind(), destination(directive).as_original_path().as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (DirectiveKind::FromArchive, r"textures\z.dds"),
+                (DirectiveKind::InlineFile, r"Mods\A.esp"),
+                (DirectiveKind::InlineFile, r"mods\b.esp"),
+            ]
+        );
+    }
+}
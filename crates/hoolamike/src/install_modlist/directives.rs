@@ -13,6 +13,7 @@ use {
                 RemappedInlineFileDirective,
                 TransformedTextureDirective,
                 create_bsa_directive::{CreateBSADirective, CreateBSADirectiveKind},
+                unknown_directive::UnknownDirective,
             },
         },
         progress_bars_v2::count_progress_style,
@@ -42,12 +43,16 @@ use {
 
 pub type DownloadSummary = Arc<BTreeMap<String, WithArchiveDescriptor<CaseInsensitivePathBuf>>>;
 
+pub mod concurrency;
 pub mod create_bsa;
 pub mod from_archive;
 pub mod inline_file;
+pub mod keep_going;
+pub mod output_directories;
 pub mod patched_from_archive;
 pub mod remapped_inline_file;
 pub mod transformed_texture;
+pub mod unknown_directive;
 
 use crate::modlist_json::Directive;
 
@@ -56,7 +61,6 @@ use crate::modlist_json::Directive;
 pub mod wabbajack_file_handle;
 
 pub struct DirectivesHandler {
-    #[allow(dead_code)]
     pub config: DirectivesHandlerConfig,
     pub create_bsa: create_bsa::CreateBSAHandler,
     pub from_archive: from_archive::FromArchiveHandler,
@@ -65,6 +69,7 @@ pub struct DirectivesHandler {
     pub remapped_inline_file: remapped_inline_file::RemappedInlineFileHandler,
     pub transformed_texture: transformed_texture::TransformedTextureHandler,
     pub download_summary: DownloadSummary,
+    pub pools: concurrency::DirectivePools,
 }
 
 #[derive(Debug, Clone)]
@@ -74,6 +79,11 @@ pub struct DirectivesHandlerConfig {
     pub game_directory: PathBuf,
     pub downloads_directory: PathBuf,
     pub texconv_wine_state: Option<TexconvWineState>,
+    pub concurrency: concurrency::ConcurrencyConfig,
+    pub stats: Arc<super::run_stats::RunStats>,
+    pub advanced: crate::config_file::AdvancedConfig,
+    /// failed directives are recorded instead of stopping the installation
+    pub keep_going: bool,
 }
 
 pub mod nested_archive_manager;
@@ -181,6 +191,13 @@ impl ArchivePathDirective {
             ArchivePathDirective::TransformedTexture(d) => d.size,
         }
     }
+    fn destination(&self) -> &CaseInsensitivePathBuf {
+        match self {
+            ArchivePathDirective::FromArchive(d) => &d.to,
+            ArchivePathDirective::PatchedFromArchive(d) => &d.to,
+            ArchivePathDirective::TransformedTexture(d) => &d.to,
+        }
+    }
     fn archive_path(&self) -> &ArchiveHashPath {
         match self {
             ArchivePathDirective::FromArchive(f) => &f.archive_hash_path,
@@ -207,14 +224,17 @@ pub fn boxed_iter<'a, T: 'a>(iter: impl Iterator<Item = T> + 'a) -> Box<dyn Iter
     Box::new(iter)
 }
 
+/// the next kind of directives is only handled when the installation was not cancelled in the meantime
 #[extension_traits::extension(trait ResultVecAndThenChain)]
-impl<E, T> std::result::Result<Vec<T>, E>
+impl<T> Result<Vec<T>>
 where
     Self: Sized,
 {
-    fn and_then_chain(self, and_then_chain: impl FnOnce() -> std::result::Result<Vec<T>, E>) -> Self {
+    fn and_then_chain(self, and_then_chain: impl FnOnce() -> Result<Vec<T>>) -> Self {
         match self {
-            Ok(head) => and_then_chain().map(|tail| head.tap_mut(|head| head.extend(tail))),
+            Ok(head) => super::cancellation::check()
+                .and_then(|_| and_then_chain())
+                .map(|tail| head.tap_mut(|head| head.extend(tail))),
             Err(e) => Err(e),
         }
     }
@@ -251,6 +271,10 @@ impl DirectivesHandler {
             game_directory,
             downloads_directory,
             texconv_wine_state,
+            concurrency,
+            stats: _,
+            advanced,
+            keep_going: _,
         } = config.clone();
         let output_directory = output_directory
             .create_dir()
@@ -266,6 +290,7 @@ impl DirectivesHandler {
             .map(|s| (s.descriptor.hash.clone(), s.map_t(|s| s.case_insensitive())))
             .collect::<BTreeMap<_, _>>()
             .pipe(Arc::new);
+        let pools = concurrency::DirectivePools::for_target(&concurrency, &output_directory).context("setting up directive worker pools")?;
 
         Self {
             config,
@@ -297,8 +322,10 @@ impl DirectivesHandler {
                 output_directory: output_directory.clone(),
                 download_summary: download_summary.clone(),
                 texconv_wine_state,
+                recompression_fallback: advanced.texture_recompression_fallback,
             },
             download_summary,
+            pools,
         }
         .pipe(Ok)
     }
@@ -322,20 +349,32 @@ impl DirectivesHandler {
                 Directive::PatchedFromArchive(directive) => directive.size,
                 Directive::RemappedInlineFile(directive) => directive.size,
                 Directive::TransformedTexture(directive) => directive.size,
+                Directive::Unknown(directive) => directive.size,
             }
         }
+        let output_directory = self.from_archive.output_directory.as_os_path();
+        output_directories::create(output_directory, &directives)
+            .and_then(|_| output_directories::destination_directories(&directives))
+            .map(|directories| crate::atomic_write::discard_partials(output_directory, &directories, &self.config.downloads_directory))
+            .context("preparing output directories")?;
         let manager = self.clone();
+        let failures = Arc::new(keep_going::Failures::new(self.config.keep_going));
 
         #[allow(clippy::large_enum_variant)]
         enum DirectiveStatus {
-            Completed(u64),
+            Completed(DirectiveKind, u64),
             NeedsRebuild { reason: anyhow::Error, directive: Directive },
         }
 
         let check_completed = {
             let output_directory = self.from_archive.output_directory.clone();
+            let trusts_converted_textures = self.config.advanced.resume_trusts_converted_textures;
             move |directive: Directive| {
-                let _kind = DirectiveKind::from(&directive);
+                let kind = DirectiveKind::from(&directive);
+                let texture = match &directive {
+                    Directive::TransformedTexture(texture) if trusts_converted_textures => Some(texture.clone()),
+                    _ => None,
+                };
                 match &directive {
                     Directive::CreateBSA(create_bsa) => match create_bsa {
                         CreateBSADirective::Bsa(CreateBSADirectiveKind { hash, size, to, .. }) => (hash.clone(), *size, to.clone()),
@@ -346,6 +385,7 @@ impl DirectivesHandler {
                     Directive::PatchedFromArchive(PatchedFromArchiveDirective { hash, size, to, .. }) => (hash.clone(), *size, to.clone()),
                     Directive::RemappedInlineFile(RemappedInlineFileDirective { hash, size, to, .. }) => (hash.clone(), *size, to.clone()),
                     Directive::TransformedTexture(TransformedTextureDirective { hash, size, to, .. }) => (hash.clone(), *size, to.clone()),
+                    Directive::Unknown(UnknownDirective { hash, size, to, .. }) => (hash.clone(), *size, to.clone()),
                 }
                 .pipe(|(hash, size, to)| {
                     (
@@ -359,9 +399,18 @@ impl DirectivesHandler {
                 .pipe(move |(hash, size, to)| {
                     to.pipe(ready)
                         .and_then(async |to| to.try_exists_async().await)
-                        .and_then(move |to| validate_hash_with_overrides(to, hash, size))
+                        .and_then(move |to| {
+                            validate_hash_with_overrides(to.clone(), hash, size).map(move |res| {
+                                res.or_else(|reason| match texture {
+                                    Some(texture) => transformed_texture::already_converted(to.as_os_path(), &texture)
+                                        .map(|_| to)
+                                        .with_context(|| format!("tried because:\n{reason:?}")),
+                                    None => Err(reason),
+                                })
+                            })
+                        })
                         .map(move |res| match res {
-                            Ok(_) => DirectiveStatus::Completed(size),
+                            Ok(_) => DirectiveStatus::Completed(kind, size),
                             Err(reason) => DirectiveStatus::NeedsRebuild { reason, directive },
                         })
                         .instrument(handle_directives.clone())
@@ -373,20 +422,42 @@ impl DirectivesHandler {
                 pb.pb_set_style(&count_progress_style());
                 pb.pb_set_length(directives.len() as _);
             });
+            // finished in any order, but handled in the order of the directives
             directives
+                .into_iter()
+                .enumerate()
                 .pipe(futures::stream::iter)
-                .map(check_completed)
-                .buffer_unordered(num_cpus::get())
+                .map(move |(position, directive)| check_completed(directive).map(move |status| (position, status)))
+                .buffer_unordered(
+                    self.config
+                        .advanced
+                        .resume_check_workers
+                        .unwrap_or_else(num_cpus::get)
+                        .max(1),
+                )
                 .inspect({
                     cloned![validating_hashes];
                     move |_| validating_hashes.pb_inc(1)
                 })
                 .collect::<Vec<_>>()
+                .map(|statuses| {
+                    statuses
+                        .into_iter()
+                        .sorted_by_key(|(position, _)| *position)
+                        .map(|(_, status)| status)
+                        .collect_vec()
+                })
                 .instrument(validating_hashes)
-                .pipe(|tasks| tokio_runtime_multi(concurrency()).map(|runtime| runtime.block_on(tasks)))
+                .pipe(|tasks| {
+                    tokio_runtime_multi(concurrency()).and_then(|runtime| {
+                        runtime
+                            .block_on(super::cancellation::until_cancelled(tasks))
+                            .ok_or_else(super::cancellation::cancelled_error)
+                    })
+                })
         }
         .map(|directives| {
-            (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()).pipe(
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()).pipe(
                 |(
                     mut create_bsa,
                     mut from_archive,
@@ -394,12 +465,13 @@ impl DirectivesHandler {
                     mut patched_from_archive,
                     mut remapped_inline_file,
                     mut transformed_texture,
+                    mut unknown,
                     mut completed,
                 )| {
                     directives
                         .into_iter()
                         .for_each(|directive| match directive {
-                            DirectiveStatus::Completed(size) => completed.push(size),
+                            DirectiveStatus::Completed(kind, size) => completed.push((kind, size)),
                             DirectiveStatus::NeedsRebuild { reason, directive } => {
                                 tracing::debug!(
                                     "recomputing directive\ndirective:{directive}:\nreason:{reason:?}",
@@ -415,6 +487,7 @@ impl DirectivesHandler {
                                     Directive::PatchedFromArchive(patched_from_archive_directive) => patched_from_archive.push(patched_from_archive_directive),
                                     Directive::RemappedInlineFile(remapped_inline_file_directive) => remapped_inline_file.push(remapped_inline_file_directive),
                                     Directive::TransformedTexture(transformed_texture_directive) => transformed_texture.push(transformed_texture_directive),
+                                    Directive::Unknown(unknown_directive) => unknown.push(unknown_directive),
                                 }
                             }
                         })
@@ -426,6 +499,7 @@ impl DirectivesHandler {
                                 patched_from_archive,
                                 remapped_inline_file,
                                 transformed_texture,
+                                unknown,
                                 completed,
                             )
                         })
@@ -433,37 +507,56 @@ impl DirectivesHandler {
             )
         })
         .and_then(
-            |(create_bsa, from_archive, inline_file, patched_from_archive, remapped_inline_file, transformed_texture, completed)| {
+            |(create_bsa, from_archive, inline_file, patched_from_archive, remapped_inline_file, transformed_texture, unknown, completed)| {
+                self.config.stats.record_directives(
+                    [
+                        (DirectiveKind::CreateBSA, create_bsa.len()),
+                        (DirectiveKind::FromArchive, from_archive.len()),
+                        (DirectiveKind::InlineFile, inline_file.len()),
+                        (DirectiveKind::PatchedFromArchive, patched_from_archive.len()),
+                        (DirectiveKind::RemappedInlineFile, remapped_inline_file.len()),
+                        (DirectiveKind::TransformedTexture, transformed_texture.len()),
+                        (DirectiveKind::Unknown, unknown.len()),
+                    ],
+                    completed.iter().map(|(kind, _)| *kind),
+                );
                 Ok(vec![])
                     .and_then_chain(|| {
                         completed
                             .into_iter()
+                            .map(|(_, size)| size)
                             .inspect(|size| handle_directives.pb_inc(*size))
                             .collect_vec()
                             .pipe(Ok)
                     })
                     .and_then_chain(|| {
                         info_span!("inline_file").in_scope(|| {
-                            inline_file
-                                .into_par_iter()
-                                .map({
-                                    cloned![manager];
-                                    move |directive| {
-                                        manager
-                                            .clone()
-                                            .inline_file
-                                            .clone()
-                                            .handle(directive.clone())
-                                            .with_context(|| format!("handling directive [{directive:#?}]"))
-                                    }
-                                })
-                                .inspect(|size| {
-                                    if let Ok(size) = size {
-                                        handle_directives.pb_inc(*size)
-                                    }
-                                })
-                                .collect::<Result<Vec<_>>>()
-                                .context("handling inline file directives")
+                            self.pools.io.install(|| {
+                                inline_file
+                                    .into_par_iter()
+                                    .map({
+                                        cloned![manager, failures];
+                                        move |directive| {
+                                            super::cancellation::check()
+                                                .and_then(|_| {
+                                                    manager
+                                                        .clone()
+                                                        .inline_file
+                                                        .clone()
+                                                        .handle(directive.clone())
+                                                        .with_context(|| format!("handling directive [{directive:#?}]"))
+                                                })
+                                                .pipe(|handled| failures.absorb(handled))
+                                        }
+                                    })
+                                    .inspect(|size| {
+                                        if let Ok(size) = size {
+                                            handle_directives.pb_inc(*size)
+                                        }
+                                    })
+                                    .collect::<Result<Vec<_>>>()
+                                    .context("handling inline file directives")
+                            })
                         })
                     })
                     .and_then_chain(|| {
@@ -487,77 +580,137 @@ impl DirectivesHandler {
                                     crate::utils::chunk_while(directives, |d: &[ArchivePathDirective]| {
                                         d.iter().map(|d| d.directive_size()).sum::<u64>() > DIRECTIVE_CHUNK_SIZE
                                     })
-                                    .into_par_iter()
-                                    .flat_map({
-                                        cloned![manager, download_summary];
-                                        move |directives| {
-                                            info_span!("handling nested archive directives chunk", chunk_size=%directives.len()).in_scope(|| {
-                                                nested_archive_directives::handle_nested_archive_directives(
-                                                    manager.clone(),
-                                                    download_summary.clone(),
-                                                    directives,
-                                                )
-                                                .collect_vec()
-                                            })
-                                        }
-                                    })
-                                    .inspect(|size| {
-                                        if let Ok(size) = size {
-                                            handle_directives.pb_inc(*size)
-                                        }
+                                    .pipe(|chunks| {
+                                        self.pools.extraction.install(|| {
+                                            chunks
+                                                .into_par_iter()
+                                                .flat_map({
+                                                    cloned![manager, download_summary];
+                                                    move |directives| {
+                                                        // a chunk which did not start yet is skipped once the installation is cancelled
+                                                        if let Err(cancelled) = super::cancellation::check() {
+                                                            return vec![Err(cancelled)];
+                                                        }
+                                                        info_span!("handling nested archive directives chunk", chunk_size=%directives.len()).in_scope(|| {
+                                                            nested_archive_directives::handle_nested_archive_directives(
+                                                                manager.clone(),
+                                                                download_summary.clone(),
+                                                                directives,
+                                                            )
+                                                            .collect_vec()
+                                                        })
+                                                    }
+                                                })
+                                                .map({
+                                                    cloned![failures];
+                                                    move |handled| failures.absorb(handled)
+                                                })
+                                                .inspect(|size| {
+                                                    if let Ok(size) = size {
+                                                        handle_directives.pb_inc(*size)
+                                                    }
+                                                })
+                                                .collect::<Vec<_>>()
+                                        })
                                     })
                                 })
                             })
+                            .into_iter()
                             .collect::<Result<Vec<_>>>()
                             .context("handling nested archive directives")
                     })
                     .and_then_chain(|| {
-                        remapped_inline_file
-                            .into_par_iter()
-                            .map({
-                                cloned![manager];
-                                move |remapped_inline_file| {
-                                    manager
-                                        .remapped_inline_file
-                                        .clone()
-                                        .handle(remapped_inline_file.clone())
-                                        .with_context(|| format!("handling {remapped_inline_file:#?}"))
-                                }
-                            })
-                            .inspect(|size| {
-                                if let Ok(size) = size {
-                                    handle_directives.pb_inc(*size)
-                                }
-                            })
-                            .collect::<Result<Vec<_>>>()
-                            .context("handling remapped inline files")
+                        self.pools.io.install(|| {
+                            remapped_inline_file
+                                .into_par_iter()
+                                .map({
+                                    cloned![manager, failures];
+                                    move |remapped_inline_file| {
+                                        super::cancellation::check()
+                                            .and_then(|_| {
+                                                manager
+                                                    .remapped_inline_file
+                                                    .clone()
+                                                    .handle(remapped_inline_file.clone())
+                                                    .with_context(|| format!("handling {remapped_inline_file:#?}"))
+                                            })
+                                            .pipe(|handled| failures.absorb(handled))
+                                    }
+                                })
+                                .inspect(|size| {
+                                    if let Ok(size) = size {
+                                        handle_directives.pb_inc(*size)
+                                    }
+                                })
+                                .collect::<Result<Vec<_>>>()
+                                .context("handling remapped inline files")
+                        })
                     })
                     .and_then_chain(|| {
-                        create_bsa
-                            .into_iter()
-                            .map({
-                                cloned![manager];
-                                move |create_bsa| {
-                                    let debug = format!("{create_bsa:#?}")
-                                        .chars()
-                                        .take(256)
-                                        .collect::<String>();
-                                    manager
-                                        .create_bsa
-                                        .clone()
-                                        .handle(create_bsa)
-                                        .with_context(|| format!("handling directive: [{debug}]"))
-                                }
-                            })
-                            .inspect(|size| {
-                                if let Ok(size) = size {
-                                    handle_directives.pb_inc(*size)
-                                }
+                        // before the archives are built, the outputs may well be staged for them
+                        info_span!("unknown_directive").in_scope(|| {
+                            self.pools.io.install(|| {
+                                unknown
+                                    .into_par_iter()
+                                    .map(|directive| {
+                                        super::cancellation::check()
+                                            .and_then(|_| unknown_directive::handle(directive))
+                                            .pipe(|handled| failures.absorb(handled))
+                                    })
+                                    .inspect(|size| {
+                                        if let Ok(size) = size {
+                                            handle_directives.pb_inc(*size)
+                                        }
+                                    })
+                                    .collect::<Result<Vec<_>>>()
+                                    .context("handling directives of unknown kinds")
                             })
-                            .collect::<Result<Vec<_>>>()
-                            .context("handling bsa creation")
+                        })
+                    })
+                    .and_then_chain(|| {
+                        // every archive ingests its entries in parallel, so archives themselves are built one by one
+                        self.pools.cpu.install(|| {
+                            create_bsa
+                                .into_iter()
+                                .map({
+                                    cloned![manager, failures];
+                                    move |create_bsa| {
+                                        let debug = format!("{create_bsa:#?}")
+                                            .chars()
+                                            .take(256)
+                                            .collect::<String>();
+                                        super::cancellation::check()
+                                            .and_then(|_| match failures.any() {
+                                                // an archive missing some of its files is skipped rather than built without them
+                                                true => manager
+                                                    .create_bsa
+                                                    .missing_members(&create_bsa)
+                                                    .and_then(|missing| match missing.as_slice() {
+                                                        [] => Ok(()),
+                                                        missing => Err(anyhow::anyhow!(
+                                                            "skipped, [{}] of the files it packs failed earlier, e.g. [{}]",
+                                                            missing.len(),
+                                                            missing.iter().take(3).join("], [")
+                                                        )),
+                                                    }),
+                                                false => Ok(()),
+                                            })
+                                            .and_then(|_| manager.create_bsa.clone().handle(create_bsa))
+                                            .with_context(|| format!("handling directive: [{debug}]"))
+                                            .pipe(|handled| failures.absorb(handled))
+                                    }
+                                })
+                                .inspect(|size| {
+                                    if let Ok(size) = size {
+                                        handle_directives.pb_inc(*size)
+                                    }
+                                })
+                                .collect::<Result<Vec<_>>>()
+                                .context("handling bsa creation")
+                        })
                     })
             },
         )
+        .pipe(|handled| failures.finish(handled))
     }
 }
@@ -0,0 +1,193 @@
+//! directives mix cpu heavy work (patching, texture recompression, building archives) with io heavy work (extraction, copying).
+//! running both on a single pool either thrashes spinning disks or leaves the cpus idle, so each kind gets a pool of its own.
+
+use {
+    anyhow::{Context, Result},
+    rayon::{ThreadPool, ThreadPoolBuilder},
+    schemars::JsonSchema,
+    serde::{Deserialize, Serialize},
+    std::path::Path,
+    tap::prelude::*,
+};
+
+/// rendered above the 'concurrency' section of the default config
+pub const CONCURRENCY_DOCS: &str = "\
+# worker pools used while handling directives, leave a value empty (null) to pick it based on the number of cpus
+# and on whether the installation path lives on a spinning disk:
+#   io_workers: files copied into the installation at the same time (inline files, plain archive entries)
+#   cpu_workers: patches, texture recompression and BSA/BA2 building running at the same time
+#   extraction_workers: downloaded archives extracted at the same time
+#   hashing_workers: archives hashed at the same time
+#   fs_workers: small blocking jobs (decompressing and parsing downloaded metadata) running at the same time
+#   max_blocking_threads: cap for the async runtime's own blocking threads (file io done by downloads)
+";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// files copied into the installation at the same time (inline files, plain archive entries)
+    pub io_workers: Option<usize>,
+    /// patches, texture recompression and BSA/BA2 building running at the same time
+    pub cpu_workers: Option<usize>,
+    /// downloaded archives extracted at the same time
+    pub extraction_workers: Option<usize>,
+    /// archives hashed at the same time
+    pub hashing_workers: Option<usize>,
+    /// small blocking jobs (decompressing and parsing downloaded metadata) running at the same time
+    pub fs_workers: Option<usize>,
+    /// cap for the async runtime's own blocking threads (file io done by downloads)
+    pub max_blocking_threads: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Workers {
+    pub io: usize,
+    pub cpu: usize,
+    pub extraction: usize,
+}
+
+impl ConcurrencyConfig {
+    /// seeking is what kills spinning disks, so they get very few concurrent readers/writers
+    pub fn resolve(&self, cpus: usize, rotational: bool) -> Workers {
+        let cpus = cpus.max(1);
+        Workers {
+            io: self
+                .io_workers
+                .unwrap_or(if rotational { 2 } else { cpus.clamp(4, 16) }),
+            cpu: self.cpu_workers.unwrap_or(cpus),
+            extraction: self
+                .extraction_workers
+                .unwrap_or(if rotational { 1 } else { (cpus / 2).max(1) }),
+        }
+        .pipe(|Workers { io, cpu, extraction }| Workers {
+            io: io.max(1),
+            cpu: cpu.max(1),
+            extraction: extraction.max(1),
+        })
+    }
+}
+
+const SYSFS_ROOT: &str = "/sys";
+
+/// (major, minor) of the device holding the path - the path itself might not be created yet, so the closest existing ancestor is used
+#[cfg(target_os = "linux")]
+fn device_of(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    path.ancestors()
+        .find_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.dev())
+        .map(|dev| {
+            (
+                ((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0x0000_0fff),
+                ((dev >> 12) & 0xffff_ff00) | (dev & 0x0000_00ff),
+            )
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn device_of(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// partitions don't have a queue of their own, the flag lives on the disk they belong to
+fn is_device_rotational(sysfs: &Path, (major, minor): (u64, u64)) -> Option<bool> {
+    sysfs
+        .join("dev/block")
+        .join(format!("{major}:{minor}"))
+        .canonicalize()
+        .ok()
+        .and_then(|device| {
+            device
+                .ancestors()
+                .take(2)
+                .find_map(|device| std::fs::read_to_string(device.join("queue/rotational")).ok())
+        })
+        .and_then(|rotational| match rotational.trim() {
+            "1" => Some(true),
+            "0" => Some(false),
+            _ => None,
+        })
+}
+
+/// [None] when it can't be told (not linux, network/virtual filesystems, etc.)
+pub fn is_rotational(path: &Path) -> Option<bool> {
+    device_of(path).and_then(|device| is_device_rotational(Path::new(SYSFS_ROOT), device))
+}
+
+#[derive(Debug)]
+pub struct DirectivePools {
+    pub io: ThreadPool,
+    pub cpu: ThreadPool,
+    pub extraction: ThreadPool,
+}
+
+impl DirectivePools {
+    pub fn new(Workers { io, cpu, extraction }: Workers) -> Result<Self> {
+        let pool = |name: &'static str, threads: usize| {
+            ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(move |idx| format!("{name}-{idx}"))
+                .start_handler(|_| crate::install_modlist::deterministic::seed_thread())
+                .build()
+                .with_context(|| format!("building [{name}] pool with [{threads}] threads"))
+        };
+        Ok(Self {
+            io: pool("io", io)?,
+            cpu: pool("cpu", cpu)?,
+            extraction: pool("extraction", extraction)?,
+        })
+    }
+
+    pub fn for_target(config: &ConcurrencyConfig, output_directory: &Path) -> Result<Self> {
+        let rotational = is_rotational(output_directory);
+        config
+            .resolve(num_cpus::get(), rotational.unwrap_or(false))
+            .tap(|workers| tracing::info!(?workers, ?rotational, "directive worker pools for [{}]", output_directory.display()))
+            .pipe(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn fake_sysfs(rotational: &str) -> Result<tempfile::TempDir> {
+        let sysfs = tempfile::tempdir()?;
+        let disk = sysfs.path().join("devices/pci0000:00/ata1/block/sda");
+        std::fs::create_dir_all(disk.join("queue"))?;
+        std::fs::create_dir_all(disk.join("sda1"))?;
+        std::fs::write(disk.join("queue/rotational"), rotational)?;
+        std::fs::create_dir_all(sysfs.path().join("dev/block"))?;
+        std::os::unix::fs::symlink(&disk, sysfs.path().join("dev/block/8:0"))?;
+        std::os::unix::fs::symlink(disk.join("sda1"), sysfs.path().join("dev/block/8:1"))?;
+        Ok(sysfs)
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_rotational_flag_is_read_from_the_disk_of_a_partition() -> Result<()> {
+        let spinning = fake_sysfs("1\n")?;
+        assert_eq!(is_device_rotational(spinning.path(), (8, 0)), Some(true));
+        assert_eq!(is_device_rotational(spinning.path(), (8, 1)), Some(true));
+        assert_eq!(is_device_rotational(spinning.path(), (8, 2)), None);
+        let solid = fake_sysfs("0\n")?;
+        assert_eq!(is_device_rotational(solid.path(), (8, 1)), Some(false));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_defaults_depend_on_the_disk_and_explicit_values_win() {
+        let auto = ConcurrencyConfig::default();
+        assert_eq!(auto.resolve(8, true), Workers { io: 2, cpu: 8, extraction: 1 });
+        assert_eq!(auto.resolve(8, false), Workers { io: 8, cpu: 8, extraction: 4 });
+        assert_eq!(auto.resolve(0, false), Workers { io: 4, cpu: 1, extraction: 1 });
+        let explicit = ConcurrencyConfig {
+            io_workers: Some(3),
+            cpu_workers: Some(0),
+            extraction_workers: None,
+            ..Default::default()
+        };
+        assert_eq!(explicit.resolve(8, true), Workers { io: 3, cpu: 1, extraction: 1 });
+    }
+}
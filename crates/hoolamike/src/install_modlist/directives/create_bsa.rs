@@ -2,11 +2,14 @@ use {
     super::*,
     crate::{
         modlist_json::directive::create_bsa_directive::CreateBSADirective,
-        progress_bars_v2::{IndicatifWrapIoExt, count_progress_style},
+        progress_bars_v2::{IndicatifWrapIoExt, count_progress_style, io_progress_style},
         utils::{ExistingPathRead, PathReadWrite},
     },
     case_insensitive_path::ExistingPathBuf,
     remapped_inline_file::wabbajack_consts::BSA_CREATION_DIR,
+    spooled::{DEFAULT_IN_MEMORY_BUDGET, SpoolBudget},
+    tracing::info_span,
+    tracing_indicatif::span_ext::IndicatifSpanExt,
 };
 
 #[derive(Clone, Debug)]
@@ -15,8 +18,51 @@ pub struct CreateBSAHandler {
 }
 
 pub mod fallout_4;
+pub mod spooled;
 pub mod tes_4;
 
+/// ingests a single member (of `size` bytes in the source) under its own span, the byte counts add up in the parent
+fn ingest_member<T>(member: &str, size: u64, ingest: impl FnOnce() -> Result<T>) -> Result<T> {
+    let span = info_span!("ingesting_member", member);
+    span.in_scope(|| {
+        span.pb_set_style(&io_progress_style());
+        span.pb_set_length(size);
+        let bytes = crate::progress_bars_v2::bridge::bytes(&span, size);
+        ingest().tap_ok(|_| {
+            span.pb_inc(size);
+            crate::progress_bars_v2::bridge::inc(&bytes, size as usize);
+        })
+    })
+}
+
+/// the ba2 writer lays out its tables in the same pass as the data, so the index is finalized when the archive is put together
+fn finalizing_index<T>(members: usize, finalize: impl FnOnce() -> T) -> T {
+    info_span!("finalizing_index", members).in_scope(finalize)
+}
+
+/// progress of writing a built archive - its size is expected to be the sum of the member sizes (the tables add a little on
+/// top of that)
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveProgress {
+    pub members_size: u64,
+}
+
+impl ArchiveProgress {
+    pub fn from_member_sizes(sizes: impl IntoIterator<Item = u64>) -> Self {
+        Self {
+            members_size: sizes.into_iter().sum(),
+        }
+    }
+
+    pub fn writing<W: std::io::Write, T>(self, output: W, write: impl FnOnce(&mut dyn std::io::Write) -> Result<T>) -> Result<T> {
+        info_span!("writing_archive", members_size = self.members_size).in_scope(|| {
+            tracing::Span::current()
+                .wrap_write(self.members_size, output)
+                .pipe(|mut output| write(&mut output))
+        })
+    }
+}
+
 #[allow(unused_variables)]
 fn try_optimize_memory_mapping(memmap: &memmap2::Mmap) {
     #[cfg(unix)]
@@ -28,6 +74,32 @@ fn try_optimize_memory_mapping(memmap: &memmap2::Mmap) {
 }
 
 impl CreateBSAHandler {
+    /// packed files which are not staged, e.g. because the directives producing them failed
+    pub fn missing_members(&self, create_bsa_directive: &CreateBSADirective) -> Result<Vec<CaseInsensitivePathBuf>> {
+        let temp_id = match create_bsa_directive {
+            CreateBSADirective::Bsa(bsa) => &bsa.temp_id,
+            CreateBSADirective::Ba2(ba2) => &ba2.temp_id,
+        };
+        self.output_directory
+            .case_insensitive()
+            .join(BSA_CREATION_DIR.with(|p| p.as_str().to_string()))
+            .and_then(|bsa_creation_dir| bsa_creation_dir.join(temp_id))
+            .and_then(|staging| {
+                create_bsa_directive
+                    .member_paths()
+                    .into_iter()
+                    .map(|member| {
+                        staging
+                            .join_case_insensitive(member.clone())
+                            .map(|path| path.exists().ok().flatten().is_none())
+                            .map(|missing| (member, missing))
+                    })
+                    .filter_map_ok(|(member, missing)| missing.then(|| member.clone()))
+                    .collect()
+            })
+            .context("looking for the staged files of the archive")
+    }
+
     #[tracing::instrument(skip(create_bsa_directive), level = "INFO")]
     pub fn handle(self, create_bsa_directive: CreateBSADirective) -> Result<u64> {
         let Self { output_directory } = self;
@@ -37,29 +109,45 @@ impl CreateBSAHandler {
             output_directory
                 .join_create(&BSA_CREATION_DIR.with(|p| p.as_str().to_string()))
                 .context("creating bsa creation dir")
-                .and_then(|bsa_creation_dir| match create_bsa_directive {
-                    CreateBSADirective::Ba2(ba2) => self::fallout_4::create_archive(&bsa_creation_dir, ba2, |archive, options, output_path| {
-                        output_directory
-                            .case_insensitive()
-                            .join_case_insensitive(output_path)
-                            .and_then(|p| p.as_path().open_file_write())
-                            .context("opening file for writing")
-                            .and_then(|(output_path, output)| {
-                                archive
-                                    .write(&mut tracing::Span::current().wrap_write(size, output), &options)
-                                    .with_context(|| format!("writing ba2 (fallout 4 / starfield) file to {output_path:?}"))
-                            })
-                    }),
-                    CreateBSADirective::Bsa(bsa) => self::tes_4::create_archive(&bsa_creation_dir, bsa, |archive, options, output_path| {
+                .map(|bsa_creation_dir| {
+                    let spool = SpoolBudget::new(DEFAULT_IN_MEMORY_BUDGET, bsa_creation_dir.as_os_path());
+                    (bsa_creation_dir, spool)
+                })
+                .and_then(|(bsa_creation_dir, spool)| match create_bsa_directive {
+                    CreateBSADirective::Ba2(ba2) => {
+                        self::fallout_4::create_archive(&bsa_creation_dir, ba2, &spool, |archive, options, output_path, progress| {
+                            output_directory
+                                .case_insensitive()
+                                .join_case_insensitive(output_path)
+                                .context("building output path")
+                                .and_then(|output_path| {
+                                    output_path.as_path().write_atomically(|output| {
+                                        progress
+                                            .writing(output, |mut output| {
+                                                archive
+                                                    .write(&mut output, &options)
+                                                    .map_err(anyhow::Error::from)
+                                            })
+                                            .with_context(|| format!("writing ba2 (fallout 4 / starfield) file to {output_path:?}"))
+                                    })
+                                })
+                        })
+                    }
+                    CreateBSADirective::Bsa(bsa) => self::tes_4::create_archive(&bsa_creation_dir, bsa, &spool, |archive, options, output_path, progress| {
                         output_directory
                             .case_insensitive()
                             .join_case_insensitive(output_path)
-                            .and_then(|output| output.as_path().open_file_write())
-                            .context("opening file for writing")
-                            .and_then(|(output_path, output)| {
-                                archive
-                                    .write(&mut tracing::Span::current().wrap_write(size, output), &options)
-                                    .with_context(|| format!("writing bsa file (skyrim and before) to {output_path:?}"))
+                            .context("building output path")
+                            .and_then(|output_path| {
+                                output_path.as_path().write_atomically(|output| {
+                                    progress
+                                        .writing(output, |mut output| {
+                                            archive
+                                                .write(&mut output, &options)
+                                                .map_err(anyhow::Error::from)
+                                        })
+                                        .with_context(|| format!("writing bsa file (skyrim and before) to {output_path:?}"))
+                                })
                             })
                     }),
                 })
@@ -67,3 +155,19 @@ impl CreateBSAHandler {
         .map(|_| size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    #[test_log::test]
+    fn test_writing_passes_the_archive_through() -> Result<()> {
+        let progress = ArchiveProgress::from_member_sizes([100, 50]);
+        assert_eq!(progress.members_size, 150);
+        let mut written = vec![];
+        ingest_member("a.nif", 100, || Ok(()))?;
+        progress.writing(&mut written, |output| output.write_all(&[7; 160]).context("writing"))?;
+        assert_eq!(written, [7; 160], "the tables make the archive a little bigger than its members");
+        Ok(())
+    }
+}
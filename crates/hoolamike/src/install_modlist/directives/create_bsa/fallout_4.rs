@@ -1,5 +1,12 @@
 use {
-    super::{count_progress_style, try_optimize_memory_mapping},
+    super::{
+        ArchiveProgress,
+        count_progress_style,
+        finalizing_index,
+        ingest_member,
+        spooled::{SpoolBudget, Spooled},
+        try_optimize_memory_mapping,
+    },
     crate::{
         modlist_json::{
             BA2DX10EntryChunk,
@@ -19,7 +26,10 @@ use {
             Archive,
             ArchiveKey,
             ArchiveOptions,
+            Chunk,
             ChunkCompressionOptions,
+            ChunkDX10,
+            ChunkExtra,
             CompressionFormat,
             CompressionLevel,
             File,
@@ -30,7 +40,7 @@ use {
         },
     },
     case_insensitive_path::ExistingPath,
-    rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    rayon::iter::{IntoParallelIterator, ParallelIterator},
     std::path::Path,
     tap::prelude::*,
     tracing::{info_span, instrument},
@@ -38,6 +48,8 @@ use {
     typed_path::Utf8WindowsPath,
 };
 
+pub mod dx10;
+
 #[derive(derive_more::From)]
 enum LazyArchiveKind {
     File(LazyArchiveFile<BA2FileEntry>),
@@ -45,6 +57,13 @@ enum LazyArchiveKind {
 }
 
 impl LazyArchiveKind {
+    fn len(&self) -> u64 {
+        match self {
+            LazyArchiveKind::File(i) => i.as_bytes().len() as u64,
+            LazyArchiveKind::DX10(i) => i.as_bytes().len() as u64,
+        }
+    }
+
     fn as_archive_file(&self) -> Result<File<'_>> {
         match self {
             LazyArchiveKind::File(i) => i.as_archive_file(),
@@ -91,35 +110,44 @@ impl LazyArchiveFile<BA2FileEntry> {
 }
 
 impl LazyArchiveFile<BA2DX10Entry> {
+    /// chunks are laid out exactly as recorded in the directive, each one compressed (or not) on its own
     fn as_archive_file(&self) -> Result<File<'_>> {
-        File::read(
-            Borrowed(self.as_bytes()),
-            &FileReadOptions::builder()
-                .format(Format::DX10)
-                .compression_result(CompressionResult::Decompressed)
-                .build(),
-        )
-        .context("reading file using memory mapping")
-        .context("building bsa archive file")
-        .and_then(|mut file| {
-            let res = file
-                .iter_mut()
-                .zip(&self.directive.chunks)
-                .try_for_each(|(chunk, BA2DX10EntryChunk { compressed, .. })| {
-                    if *compressed {
-                        *chunk = chunk
-                            .compress(
-                                &ChunkCompressionOptions::builder()
-                                    .compression_format(CompressionFormat::Zip)
-                                    .compression_level(CompressionLevel::FO4)
-                                    .build(),
-                            )
-                            .context("compressing chunk")?
-                    }
-                    Ok(())
-                });
-            res.map(move |_| file)
-        })
+        let compression = ChunkCompressionOptions::builder()
+            .compression_format(CompressionFormat::Zip)
+            .compression_level(CompressionLevel::FO4)
+            .build();
+        dx10::dds_pixel_data(self.as_bytes())
+            .and_then(|data| dx10::chunk_ranges(&self.directive, data.len()).map(|ranges| (data, ranges)))
+            .and_then(|(data, ranges)| {
+                ranges
+                    .into_iter()
+                    .zip(&self.directive.chunks)
+                    .map(
+                        |(
+                            range,
+                            BA2DX10EntryChunk {
+                                compressed,
+                                start_mip,
+                                end_mip,
+                                ..
+                            },
+                        )| {
+                            Chunk::from_decompressed(&data[range])
+                                .tap_mut(|chunk| {
+                                    chunk.extra = ChunkExtra::DX10(ChunkDX10 {
+                                        mips: (*start_mip as u16)..=(*end_mip as u16),
+                                    })
+                                })
+                                .pipe(|chunk| match compressed {
+                                    true => chunk.compress(&compression).context("compressing chunk"),
+                                    false => Ok(chunk),
+                                })
+                        },
+                    )
+                    .collect::<Result<Vec<_>>>()
+            })
+            .map(|chunks| File::from_iter(chunks).tap_mut(|file| file.header = FileHeader::DX10(dx10::header(&self.directive))))
+            .context("building bsa archive file")
     }
 }
 
@@ -135,8 +163,81 @@ pub(super) fn create_key<'a>(for_path: &Utf8WindowsPath) -> ArchiveKey<'a> {
         .conv::<ArchiveKey>()
 }
 
-#[instrument(skip(handle_archive, file_states))]
-pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePathBuf) -> Result<()>>(
+struct IngestedChunk {
+    bytes: Spooled,
+    /// [None] for chunks stored uncompressed
+    decompressed_len: Option<usize>,
+    extra: ChunkExtra,
+}
+
+/// archive file with its (already compressed) chunks moved out of the source file
+struct IngestedFile {
+    header: FileHeader,
+    chunks: Vec<IngestedChunk>,
+}
+
+impl IngestedFile {
+    fn spool(file: &File<'_>, spool: &SpoolBudget) -> Result<Self> {
+        file.iter()
+            .map(|chunk| {
+                spool.spool(chunk.as_bytes()).map(|bytes| IngestedChunk {
+                    bytes,
+                    decompressed_len: chunk.decompressed_len(),
+                    extra: chunk.extra.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|chunks| Self {
+                header: file.header.clone(),
+                chunks,
+            })
+    }
+
+    fn len(&self) -> u64 {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.bytes.len() as u64)
+            .sum()
+    }
+
+    fn as_archive_file(&self) -> File<'_> {
+        self.chunks
+            .iter()
+            .map(
+                |IngestedChunk {
+                     bytes,
+                     decompressed_len,
+                     extra,
+                 }| {
+                    match decompressed_len {
+                        Some(decompressed_len) => Chunk::from_compressed(&bytes[..], *decompressed_len),
+                        None => Chunk::from_decompressed(&bytes[..]),
+                    }
+                    .tap_mut(|chunk| chunk.extra = extra.clone())
+                },
+            )
+            .pipe(File::from_iter)
+            .tap_mut(|file| file.header = self.header.clone())
+    }
+}
+
+/// name table hashes are what the game uses to look files up - a mismatch means the file won't be found in game
+fn check_name_hashes(key: &ArchiveKey<'_>, name_hash: u32, dir_hash: u32) {
+    let hash = key.hash();
+    if hash.file != name_hash || hash.directory != dir_hash {
+        tracing::warn!(
+            key=%key.name(),
+            expected_name_hash=%name_hash,
+            expected_dir_hash=%dir_hash,
+            name_hash=%hash.file,
+            dir_hash=%hash.directory,
+            "archive key hashes differ from the ones recorded in the modlist"
+        );
+    }
+}
+
+#[instrument(skip(handle_archive, file_states, spool))]
+pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePathBuf, ArchiveProgress) -> Result<()>>(
     temp_bsa_dir: &ExistingPath,
     Ba2 {
         hash: _,
@@ -156,6 +257,7 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                 ..
             },
     }: Ba2,
+    spool: &SpoolBudget,
     handle_archive: F,
 ) -> Result<()> {
     let version: ArchiveVersion = match version {
@@ -184,12 +286,21 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                 .and_then(|path| path.try_exists().and_then(|path| path.open_file_read()))
                 .and_then(|(_path, file)| LazyArchiveFile::new(&file, ba2_file_entry.clone()).map(LazyArchiveKind::from))
                 .and_then(|file| {
-                    ba2_file_entry.pipe(|BA2FileEntry { path, .. }| {
-                        path.as_original_path()
-                            .into_windows_encoding_checked()
-                            .map(|path| create_key(path.as_path()))
-                            .map(|key| (key, file))
-                    })
+                    ba2_file_entry.pipe(
+                        |BA2FileEntry {
+                             path,
+                             name_hash,
+                             dir_hash,
+                             index,
+                             ..
+                         }| {
+                            path.as_original_path()
+                                .into_windows_encoding_checked()
+                                .map(|path| create_key(path.as_path()))
+                                .tap_ok(|key| check_name_hashes(key, name_hash, dir_hash))
+                                .map(|key| (index, key, file))
+                        },
+                    )
                 }),
             FileState::BA2DX10Entry(ba2_dx10_entry) => temp_id_dir
                 .join_case_insensitive(ba2_dx10_entry.path.clone())
@@ -204,12 +315,21 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                         .map(LazyArchiveKind::from)
                 })
                 .and_then(|file| {
-                    ba2_dx10_entry.pipe(|BA2DX10Entry { path, .. }| {
-                        path.as_original_path()
-                            .into_windows_encoding_checked()
-                            .map(|path| create_key(path.as_path()))
-                            .map(|key| (key, file))
-                    })
+                    ba2_dx10_entry.pipe(
+                        |BA2DX10Entry {
+                             path,
+                             name_hash,
+                             dir_hash,
+                             index,
+                             ..
+                         }| {
+                            path.as_original_path()
+                                .into_windows_encoding_checked()
+                                .map(|path| create_key(path.as_path()))
+                                .tap_ok(|key| check_name_hashes(key, name_hash, dir_hash))
+                                .map(|key| (index, key, file))
+                        },
+                    )
                 }),
         })
         .inspect(|_| reading_bsa_entries.pb_inc(1))
@@ -219,39 +339,305 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                 pb.pb_set_style(&count_progress_style());
                 pb.pb_set_length(entries.len() as _);
             });
-            entries.pipe_ref(|entries| {
-                entries
-                    .par_iter()
-                    .map(|(key, file)| {
-                        file.as_archive_file().map(|file| {
-                            building_archive.pb_inc(1);
-                            (key, file)
-                        })
+            // compressed entries are spooled, so that only the budgeted amount of them is held in memory
+            entries
+                .into_par_iter()
+                .map(|(index, key, file)| {
+                    ingest_member(&key.name().to_string(), file.len(), || {
+                        file.as_archive_file()
+                            .and_then(|file| IngestedFile::spool(&file, spool))
                     })
-                    .collect::<Result<Vec<_>>>()
-                    .and_then(|entries| {
-                        entries
-                            .first()
-                            .map(|(_, file)| match file.header {
-                                FileHeader::GNRL => Format::GNRL,
-                                FileHeader::DX10(_) => Format::DX10,
-                                FileHeader::GNMF(_) => Format::GNMF,
-                            })
-                            .unwrap_or_default()
-                            .pipe(|format| ArchiveOptions::builder().format(format))
-                            .pipe(|options| {
-                                entries
-                                    .into_iter()
-                                    .fold(Archive::new(), |acc, (key, file)| {
-                                        acc.tap_mut(|acc| {
-                                            acc.insert(key.clone(), file);
-                                        })
-                                    })
-                                    .pipe(|archive| (archive, options.version(version).strings(has_name_table).build()))
-                                    .pipe(|(archive, options)| handle_archive(&archive, options, to))
+                    .with_context(|| format!("ingesting [{}]", key.name()))
+                    .map(|file| {
+                        building_archive.pb_inc(1);
+                        (index, key, file)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .map(|entries| entries.tap_mut(|entries| entries.sort_by_key(|(index, _, _)| *index)))
+        .and_then(|entries| {
+            entries
+                .first()
+                .map(|(_, _, file)| match file.header {
+                    FileHeader::GNRL => Format::GNRL,
+                    FileHeader::DX10(_) => Format::DX10,
+                    FileHeader::GNMF(_) => Format::GNMF,
+                })
+                .unwrap_or_default()
+                .pipe(|format| ArchiveOptions::builder().format(format))
+                .pipe(|options| {
+                    let progress = ArchiveProgress::from_member_sizes(entries.iter().map(|(_, _, file)| file.len()));
+                    // entries are inserted in the order of the directive, the writer appends them in that order and fills in the offsets
+                    finalizing_index(entries.len(), || {
+                        entries.iter().fold(Archive::new(), |acc, (_, key, file)| {
+                            acc.tap_mut(|acc| {
+                                acc.insert(key.clone(), file.as_archive_file());
                             })
+                        })
                     })
-                    .context("creating BA2 (fallout4/starfield) archive")
+                    .pipe(|archive| (archive, options.version(version).strings(has_name_table).build()))
+                    .pipe(|(archive, options)| handle_archive(&archive, options, to, progress))
+                })
+        })
+        .context("creating BA2 (fallout4/starfield) archive")
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::install_modlist::directives::create_bsa::spooled::DEFAULT_IN_MEMORY_BUDGET,
+        ba2::{Reader, fo4::DX10Header},
+        case_insensitive_path::ExistingPathBuf,
+        serde_json::json,
+    };
+
+    const WIDTH: u16 = 64;
+    const MIPS: u8 = 7;
+    /// DXGI_FORMAT_BC1_UNORM
+    const BC1: u8 = 71;
+    const TEXTURE: &str = r"textures\hoolamike\test.dds";
+
+    fn pixel_data() -> Vec<u8> {
+        (0..MIPS as u64)
+            .map(|mip| dx10::mip_size(BC1, WIDTH, WIDTH, mip).unwrap())
+            .sum::<u64>()
+            .pipe(|size| {
+                (0..size)
+                    .map(|byte| (byte % 251) as u8 ^ (byte / 64) as u8)
+                    .collect()
             })
+    }
+
+    /// DDS with a DX10 extended header, only the fields read by [dx10::dds_pixel_data] are filled in
+    fn dds(pixel_data: &[u8]) -> Vec<u8> {
+        vec![0; 148].tap_mut(|dds| {
+            dds[..4].copy_from_slice(b"DDS ");
+            dds[4..8].copy_from_slice(&124_u32.to_le_bytes());
+            dds[84..88].copy_from_slice(b"DX10");
+            dds[128..132].copy_from_slice(&u32::from(BC1).to_le_bytes());
+            dds.extend_from_slice(pixel_data);
         })
+    }
+
+    fn chunk(start_mip: u64, end_mip: u64, compressed: bool) -> serde_json::Value {
+        let full_size = (start_mip..=end_mip)
+            .map(|mip| dx10::mip_size(BC1, WIDTH, WIDTH, mip).unwrap())
+            .sum::<u64>();
+        json!({"Align": 0, "Compressed": compressed, "StartMip": start_mip, "EndMip": end_mip, "FullSz": full_size})
+    }
+
+    fn directive(chunks: Vec<serde_json::Value>) -> Ba2 {
+        let (name_hash, dir_hash) = create_key(Utf8WindowsPath::new(TEXTURE))
+            .hash()
+            .pipe(|hash| (hash.file, hash.directory));
+        serde_json::from_value(json!({
+            "Hash": "AAAAAAAAAAA=",
+            "Size": 0,
+            "To": r"Data\hoolamike - Textures.ba2",
+            "TempID": "temp-id",
+            "FileStates": [{
+                "$type": "BA2DX10Entry",
+                "DirHash": dir_hash,
+                "ChunkHdrLen": 24,
+                "Chunks": chunks,
+                "NumMips": MIPS,
+                "PixelFormat": BC1,
+                "TileMode": 8,
+                "Unk8": 0,
+                "Extension": ".dds",
+                "Height": WIDTH,
+                "Width": WIDTH,
+                "IsCubeMap": 0,
+                "Index": 0,
+                "NameHash": name_hash,
+                "Path": TEXTURE,
+            }],
+            "State": {"$type": "BA2State, Compression.BSA", "HasNameTable": true, "HeaderMagic": "BTDX", "Type": 1, "Version": 1},
+        }))
+        .expect("valid directive")
+    }
+
+    /// builds the archive from the directive and reads it back with the native reader
+    fn build(directive: Ba2) -> Result<Vec<u8>> {
+        let directory = tempfile::tempdir()?;
+        let texture = directory.path().join("temp-id/textures/hoolamike/test.dds");
+        std::fs::create_dir_all(texture.parent().unwrap())?;
+        std::fs::write(&texture, dds(&pixel_data()))?;
+        let mut output = vec![];
+        let spool = SpoolBudget::new(DEFAULT_IN_MEMORY_BUDGET, directory.path());
+        ExistingPathBuf::new(directory.path()).and_then(|temp_bsa_dir| {
+            create_archive(&temp_bsa_dir, directive, &spool, |archive, options, _, _| {
+                archive
+                    .write(&mut output, &options)
+                    .context("writing archive")
+            })
+        })?;
+        Ok(output)
+    }
+
+    #[test_log::test]
+    fn test_dx10_archive_round_trip() -> Result<()> {
+        let output = build(directive(vec![chunk(0, 0, true), chunk(1, MIPS as u64 - 1, true)]))?;
+        let (archive, options) = Archive::read(Borrowed(&output)).context("reading archive back")?;
+        assert_eq!(options.format(), Format::DX10);
+        assert!(options.strings());
+        let file = archive
+            .get(&create_key(Utf8WindowsPath::new(TEXTURE)))
+            .context("texture missing from the archive")?;
+        match &file.header {
+            FileHeader::DX10(DX10Header {
+                height,
+                width,
+                mip_count,
+                format,
+                ..
+            }) => assert_eq!((*height, *width, *mip_count, *format), (WIDTH, WIDTH, MIPS, BC1)),
+            other => anyhow::bail!("expected DX10 header, got {other:?}"),
+        }
+        let chunks = file.iter().collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|chunk| chunk.is_compressed()));
+        assert!(matches!(&chunks[1].extra, ChunkExtra::DX10(ChunkDX10 { mips }) if *mips == (1..=6)));
+        chunks
+            .iter()
+            .map(|chunk| {
+                chunk
+                    .decompress(&Default::default())
+                    .map(|chunk| chunk.as_bytes().to_vec())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|chunks| assert!(chunks.concat() == pixel_data(), "pixel data differs after round trip"))
+            .context("decompressing chunks")
+    }
+
+    #[test_log::test]
+    fn test_uncompressed_chunks_are_kept_as_is() -> Result<()> {
+        let output = build(directive(vec![chunk(0, MIPS as u64 - 1, false)]))?;
+        let (archive, _) = Archive::read(Borrowed(&output))?;
+        let file = archive
+            .get(&create_key(Utf8WindowsPath::new(TEXTURE)))
+            .context("texture missing from the archive")?;
+        let chunks = file.iter().collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 1);
+        assert!(!chunks[0].is_compressed());
+        assert!(chunks[0].as_bytes() == pixel_data());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_mismatched_chunk_layout_is_rejected() {
+        let wrong_size = chunk(0, 0, true).tap_mut(|chunk| chunk["FullSz"] = json!(100));
+        let skipped_mip = chunk(2, MIPS as u64 - 1, true);
+        [vec![wrong_size, chunk(1, MIPS as u64 - 1, true)], vec![chunk(0, 0, true), skipped_mip]]
+            .into_iter()
+            .for_each(|chunks| assert!(build(directive(chunks)).is_err()));
+    }
+
+    #[test_log::test]
+    fn test_mip_sizes_are_padded_to_blocks() {
+        assert_eq!(dx10::mip_size(BC1, WIDTH, WIDTH, 0), Some(2048));
+        // 2x2 and 1x1 mips still take up a whole 4x4 block
+        assert_eq!(dx10::mip_size(BC1, WIDTH, WIDTH, 5), Some(8));
+        assert_eq!(dx10::mip_size(BC1, WIDTH, WIDTH, 6), Some(8));
+        // BC7
+        assert_eq!(dx10::mip_size(98, 8, 4, 0), Some(32));
+        // R8G8B8A8
+        assert_eq!(dx10::mip_size(28, 3, 3, 0), Some(36));
+        assert_eq!(dx10::mip_size(0, 3, 3, 0), None);
+    }
+
+    fn general_directive(files: &[(usize, &str, bool)]) -> Ba2 {
+        serde_json::from_value(json!({
+            "Hash": "AAAAAAAAAAA=",
+            "Size": 0,
+            "To": r"Data\hoolamike - Main.ba2",
+            "TempID": "temp-id",
+            "FileStates": files.iter().map(|(index, path, compressed)| json!({
+                "$type": "BA2File",
+                "Align": 0,
+                "Compressed": compressed,
+                "DirHash": 0,
+                "Extension": ".nif",
+                "Flags": 0,
+                "Index": index,
+                "NameHash": 0,
+                "Path": path,
+            })).collect::<Vec<_>>(),
+            "State": {"$type": "BA2State, Compression.BSA", "HasNameTable": true, "HeaderMagic": "BTDX", "Type": 0, "Version": 1},
+        }))
+        .expect("valid directive")
+    }
+
+    fn contents(idx: usize) -> Vec<u8> {
+        (0..(idx * 997) % 5000 + 100)
+            .map(|byte| (byte % (idx + 7)) as u8)
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_parallel_ingestion_is_deterministic() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        // listed out of order, the index decides where they end up
+        let files = (0..40)
+            .map(|idx| ((idx * 17) % 40, format!(r"meshes\hoolamike\mesh-{idx:02}.nif"), idx % 3 != 0))
+            .collect::<Vec<_>>();
+        files.iter().try_for_each(|(index, path, _)| {
+            let path = directory
+                .path()
+                .join("temp-id")
+                .join(path.replace('\\', "/"));
+            std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| std::fs::write(path, contents(*index)))
+        })?;
+        let temp_bsa_dir = ExistingPathBuf::new(directory.path())?;
+        let build = |threads: usize, budget: u64| -> Result<Vec<u8>> {
+            let spool = SpoolBudget::new(budget, directory.path());
+            let mut output = vec![];
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()?
+                .install(|| {
+                    files
+                        .iter()
+                        .map(|(index, path, compressed)| (*index, path.as_str(), *compressed))
+                        .collect::<Vec<_>>()
+                        .pipe(|files| general_directive(&files))
+                        .pipe(|directive| {
+                            create_archive(&temp_bsa_dir, directive, &spool, |archive, options, _, _| {
+                                archive
+                                    .write(&mut output, &options)
+                                    .context("writing archive")
+                            })
+                        })
+                })
+                .map(|_| output)
+        };
+        // single thread with everything in memory vs many threads with everything spilled to disk
+        let sequential = build(1, DEFAULT_IN_MEMORY_BUDGET)?;
+        let parallel = build(8, 0)?;
+        let mixed = build(4, 20_000)?;
+        assert!(sequential == parallel, "spilled, parallel build differs from the sequential one");
+        assert!(sequential == mixed, "partially spilled build differs from the sequential one");
+
+        let (archive, options) = Archive::read(Borrowed(&sequential))?;
+        assert_eq!(options.format(), Format::GNRL);
+        files.iter().try_for_each(|(index, path, compressed)| {
+            let file = archive
+                .get(&create_key(Utf8WindowsPath::new(path)))
+                .with_context(|| format!("[{path}] missing from the archive"))?;
+            let chunks = file.iter().collect::<Vec<_>>();
+            assert_eq!(chunks.len(), 1);
+            assert_eq!(chunks[0].is_compressed(), *compressed);
+            match chunks[0].is_compressed() {
+                true => chunks[0]
+                    .decompress(&Default::default())
+                    .map(|chunk| chunk.as_bytes().to_vec())
+                    .context("decompressing")?,
+                false => chunks[0].as_bytes().to_vec(),
+            }
+            .pipe(|bytes| assert!(bytes == contents(*index), "[{path}] differs after round trip"));
+            Ok::<_, anyhow::Error>(())
+        })
+    }
 }
@@ -0,0 +1,141 @@
+//! DX10 (texture) entries are not stored as plain DDS files - the header is replaced with [DX10Header] and the pixel data
+//! is split into chunks of mip levels, each compressed separately. Wabbajack records the exact chunk layout of the
+//! original archive, so it is rebuilt from the directive instead of letting the writer pick its own.
+
+use {
+    crate::modlist_json::{BA2DX10EntryChunk, directive::create_bsa_directive::ba2::BA2DX10Entry},
+    anyhow::{Context, Result},
+    ba2::fo4::DX10Header,
+    itertools::Itertools,
+    std::ops::Range,
+    tap::prelude::*,
+};
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+/// magic + DDS_HEADER
+const DDS_HEADER_SIZE: usize = 4 + 124;
+const DDS_HEADER_DXT10_SIZE: usize = 20;
+const FOURCC_OFFSET: usize = 84;
+const FOURCC_DX10: &[u8] = b"DX10";
+
+/// pixel data following the DDS header(s)
+pub fn dds_pixel_data(dds: &[u8]) -> Result<&[u8]> {
+    anyhow::ensure!(dds.len() >= DDS_HEADER_SIZE && dds.starts_with(DDS_MAGIC), "not a DDS file");
+    match &dds[FOURCC_OFFSET..FOURCC_OFFSET + 4] == FOURCC_DX10 {
+        true => dds
+            .get(DDS_HEADER_SIZE + DDS_HEADER_DXT10_SIZE..)
+            .context("DDS file is too short to hold its DX10 header"),
+        false => Ok(&dds[DDS_HEADER_SIZE..]),
+    }
+}
+
+/// (block dimension in pixels, bytes per block) of a DXGI format, for the formats found in FO4 archives
+fn block_layout(pixel_format: u8) -> Option<(u64, u64)> {
+    match pixel_format {
+        // BC1, BC4
+        70..=72 | 79..=81 => Some((4, 8)),
+        // BC2, BC3, BC5, BC6H, BC7
+        73..=78 | 82..=84 | 94..=99 => Some((4, 16)),
+        // R32G32B32A32
+        1..=4 => Some((1, 16)),
+        // R16G16B16A16
+        9..=14 => Some((1, 8)),
+        // R10G10B10A2, R8G8B8A8, R16G16, R32, B8G8R8A8, B8G8R8X8
+        23..=25 | 27..=32 | 33..=38 | 39..=43 | 87 | 88 | 90..=93 => Some((1, 4)),
+        // R8G8, R16, B5G6R5, B5G5R5A1
+        48..=52 | 53..=59 | 85 | 86 => Some((1, 2)),
+        // R8, A8
+        60..=65 => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// size of a single mip level - block compressed formats are padded to whole 4x4 blocks
+pub fn mip_size(pixel_format: u8, width: u16, height: u16, mip: u64) -> Option<u64> {
+    let dimension = |size: u16| (u64::from(size) >> mip).max(1);
+    block_layout(pixel_format).map(|(block, bytes)| dimension(width).div_ceil(block) * dimension(height).div_ceil(block) * bytes)
+}
+
+pub fn header(
+    &BA2DX10Entry {
+        height,
+        width,
+        num_mips,
+        pixel_format,
+        is_cube_map,
+        tile_mode,
+        ..
+    }: &BA2DX10Entry,
+) -> DX10Header {
+    DX10Header {
+        height,
+        width,
+        mip_count: num_mips,
+        format: pixel_format,
+        flags: is_cube_map,
+        tile_mode,
+    }
+}
+
+/// byte ranges of the pixel data covered by each chunk of the directive
+pub fn chunk_ranges(entry: &BA2DX10Entry, data_len: usize) -> Result<Vec<Range<usize>>> {
+    anyhow::ensure!(!entry.chunks.is_empty(), "directive holds no chunks");
+    entry
+        .chunks
+        .iter()
+        .tuple_windows()
+        .try_for_each(|(previous, next)| {
+            anyhow::ensure!(
+                next.start_mip == previous.end_mip + 1,
+                "chunks must cover consecutive mips, but [{}..={}] is followed by [{}..={}]",
+                previous.start_mip,
+                previous.end_mip,
+                next.start_mip,
+                next.end_mip
+            );
+            Ok(())
+        })?;
+    entry.chunks.iter().try_for_each(
+        |&BA2DX10EntryChunk {
+             start_mip, end_mip, full_sz, ..
+         }| {
+            anyhow::ensure!(
+                start_mip <= end_mip && end_mip < u64::from(entry.num_mips),
+                "chunk [{start_mip}..={end_mip}] is out of bounds for a texture with [{}] mips",
+                entry.num_mips
+            );
+            // cube maps store every face with all of its mips, so mip levels don't map onto a contiguous range
+            let expected = (start_mip..=end_mip)
+                .map(|mip| mip_size(entry.pixel_format, entry.width, entry.height, mip))
+                .sum::<Option<u64>>()
+                .filter(|_| entry.is_cube_map == 0);
+            anyhow::ensure!(
+                expected.is_none_or(|expected| expected == full_sz),
+                "chunk [{start_mip}..={end_mip}] should hold [{}] bytes for a {}x{} texture of format [{}], but directive says [{full_sz}]",
+                expected.unwrap_or_default(),
+                entry.width,
+                entry.height,
+                entry.pixel_format
+            );
+            Ok(())
+        },
+    )?;
+    entry
+        .chunks
+        .iter()
+        .scan(0, |start, chunk| {
+            let range = *start..(*start + chunk.full_sz as usize);
+            *start = range.end;
+            Some(range)
+        })
+        .collect_vec()
+        .pipe(|ranges| {
+            let covered = ranges.last().map(|range| range.end).unwrap_or_default();
+            anyhow::ensure!(
+                covered == data_len,
+                "chunks cover [{covered}] bytes, but the texture holds [{data_len}] bytes of pixel data"
+            );
+            Ok(ranges)
+        })
+        .with_context(|| format!("laying out chunks of [{:?}]", entry.path))
+}
@@ -0,0 +1,115 @@
+//! compressed entries are produced in parallel, but the archive can only be written once all of them are ready.
+//! Holding all of them in memory doesn't work for archives with thousands of files, so once the budget is used up
+//! the rest is spilled to temporary files (memory mapped, so the writer can borrow them just like in-memory buffers).
+
+use {
+    anyhow::{Context, Result},
+    std::{
+        io::Write,
+        ops::Deref,
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicU64, Ordering},
+    },
+    tap::prelude::*,
+};
+
+/// compressed data of a single archive being built that is allowed to stay in memory
+pub const DEFAULT_IN_MEMORY_BUDGET: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct SpoolBudget {
+    limit: u64,
+    used: AtomicU64,
+    spill_directory: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum Spooled {
+    Memory(Vec<u8>),
+    /// the file is unnamed, so it's gone as soon as the mapping is dropped (or the process dies)
+    Spilled(memmap2::Mmap),
+}
+
+impl Deref for Spooled {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Spooled::Memory(bytes) => bytes,
+            Spooled::Spilled(mmap) => mmap,
+        }
+    }
+}
+
+impl SpoolBudget {
+    pub fn new(limit: u64, spill_directory: &Path) -> Self {
+        Self {
+            limit,
+            used: AtomicU64::new(0),
+            spill_directory: spill_directory.to_owned(),
+        }
+    }
+
+    /// bytes currently kept in memory
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+
+    fn try_reserve(&self, size: u64) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(size)
+                    .filter(|reserved| *reserved <= self.limit)
+            })
+            .is_ok()
+    }
+
+    pub fn spool(&self, bytes: &[u8]) -> Result<Spooled> {
+        match self.try_reserve(bytes.len() as u64) {
+            true => Ok(Spooled::Memory(bytes.to_vec())),
+            false => self.spill(bytes),
+        }
+    }
+
+    fn spill(&self, bytes: &[u8]) -> Result<Spooled> {
+        // zero sized files can't be memory mapped
+        if bytes.is_empty() {
+            return Ok(Spooled::Memory(vec![]));
+        }
+        tempfile::tempfile_in(&self.spill_directory)
+            .context("creating spill file")
+            .and_then(|mut file| {
+                file.write_all(bytes)
+                    .and_then(|_| file.flush())
+                    .context("writing spill file")
+                    .map(|_| file)
+            })
+            .and_then(|file| unsafe { memmap2::Mmap::map(&file) }.context("mapping spill file"))
+            .tap_ok(super::try_optimize_memory_mapping)
+            .map(Spooled::Spilled)
+            .with_context(|| format!("spilling [{}] bytes to [{}]", bytes.len(), self.spill_directory.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_spills_once_budget_is_used_up() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let budget = SpoolBudget::new(10, directory.path());
+        let first = budget.spool(b"0123456")?;
+        let second = budget.spool(b"0123456")?;
+        let third = budget.spool(b"012")?;
+        assert!(matches!(first, Spooled::Memory(_)));
+        assert!(matches!(second, Spooled::Spilled(_)));
+        assert!(matches!(third, Spooled::Memory(_)));
+        assert_eq!(budget.used(), 10);
+        [first, second, third]
+            .iter()
+            .zip([&b"0123456"[..], b"0123456", b"012"])
+            .for_each(|(spooled, expected)| assert_eq!(&spooled[..], expected));
+        Ok(())
+    }
+}
@@ -1,5 +1,11 @@
 use {
-    super::count_progress_style,
+    super::{
+        ArchiveProgress,
+        count_progress_style,
+        finalizing_index,
+        ingest_member,
+        spooled::{SpoolBudget, Spooled},
+    },
     crate::{
         modlist_json::{
             directive::create_bsa_directive::bsa::{self, Bsa, DirectiveStateData, FileStateData},
@@ -15,7 +21,7 @@ use {
         tes4::{Archive, ArchiveFlags, ArchiveKey, ArchiveOptions, ArchiveTypes, Directory, DirectoryKey, File, FileReadOptions, Version},
     },
     case_insensitive_path::{CaseInsensitivePathBuf, ExistingPath, Utf8TypedPathToPlatformExt},
-    rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    rayon::iter::{IntoParallelIterator, ParallelIterator},
     tap::prelude::*,
     tracing::{debug, info_span, instrument},
     tracing_indicatif::span_ext::IndicatifSpanExt,
@@ -67,6 +73,33 @@ impl LazyArchiveFile<FileStateData> {
     }
 }
 
+/// archive file with its (already compressed) contents moved out of the source file
+struct IngestedFile {
+    bytes: Spooled,
+    /// [None] for files stored uncompressed
+    decompressed_len: Option<usize>,
+}
+
+impl IngestedFile {
+    fn spool(file: &File<'_>, spool: &SpoolBudget) -> Result<Self> {
+        spool.spool(file.as_bytes()).map(|bytes| Self {
+            bytes,
+            decompressed_len: file.decompressed_len(),
+        })
+    }
+
+    fn len(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    fn as_archive_file(&self) -> File<'_> {
+        match self.decompressed_len {
+            Some(decompressed_len) => File::from_compressed(&self.bytes[..], decompressed_len),
+            None => File::from_decompressed(&self.bytes[..]),
+        }
+    }
+}
+
 #[instrument]
 pub fn create_key<'a>(path: &Utf8WindowsPath) -> Result<(ArchiveKey<'a>, DirectoryKey<'a>)> {
     path.file_name()
@@ -94,8 +127,8 @@ pub fn create_key<'a>(path: &Utf8WindowsPath) -> Result<(ArchiveKey<'a>, Directo
         .with_context(|| format!("reading archive key and directory key for `{path}`"))
 }
 
-#[instrument(skip(handle_archive, file_states))]
-pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePathBuf) -> Result<()>>(
+#[instrument(skip(handle_archive, file_states, spool))]
+pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePathBuf, ArchiveProgress) -> Result<()>>(
     temp_bsa_dir: &ExistingPath,
     Bsa {
         hash: _,
@@ -115,6 +148,7 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                 ..
             },
     }: Bsa,
+    spool: &SpoolBudget,
     handle_archive: F,
 ) -> Result<()> {
     let version = match version {
@@ -160,7 +194,7 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                             .as_original_path()
                             .into_windows_encoding_checked()
                             .and_then(|path| create_key(path.as_path()))
-                            .map(|key| (key, file))
+                            .map(|key| (file_state_data.index, key, file))
                     })
             })
         })
@@ -171,47 +205,58 @@ pub fn create_archive<F: FnOnce(&Archive<'_>, ArchiveOptions, CaseInsensitivePat
                 pb.pb_set_style(&count_progress_style());
                 pb.pb_set_length(entries.len() as _);
             });
-            entries.pipe_ref(|entries| {
-                entries
-                    .par_iter()
-                    .map(|(key, file)| {
-                        file.as_archive_file(version, None).map(|file| {
-                            building_archive.pb_inc(1);
-                            (key, file)
-                        })
+            // compressed entries are spooled, so that only the budgeted amount of them is held in memory
+            entries
+                .into_par_iter()
+                .map(|(index, key, file)| {
+                    ingest_member(file.directive.path.as_original_path().as_str(), file.as_bytes().len() as u64, || {
+                        file.as_archive_file(version, None)
+                            .and_then(|file| IngestedFile::spool(&file, spool))
                     })
-                    .collect::<Result<Vec<_>>>()
-                    .and_then(|entries| {
-                        entries
-                            .into_iter()
-                            .fold(Archive::new(), |acc, ((archive_key, directory_key), file)| {
-                                acc.tap_mut(|acc| match acc.get_mut(archive_key) {
-                                    Some(directory) => {
+                    .with_context(|| format!("ingesting [{:?}]", file.directive.path))
+                    .map(|file| {
+                        building_archive.pb_inc(1);
+                        (index, key, file)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .map(|entries| entries.tap_mut(|entries| entries.sort_by_key(|(index, _, _)| *index)))
+        .and_then(|entries| {
+            let progress = ArchiveProgress::from_member_sizes(entries.iter().map(|(_, _, file)| file.len()));
+            // entries are inserted in the order of the directive, the writer appends them in that order and fills in the offsets
+            finalizing_index(entries.len(), || {
+                entries
+                    .iter()
+                    .fold(Archive::new(), |acc, (_, (archive_key, directory_key), file)| {
+                        let file = file.as_archive_file();
+                        acc.tap_mut(|acc| match acc.get_mut(archive_key) {
+                            Some(directory) => {
+                                directory.insert(directory_key.clone(), file);
+                            }
+                            None => {
+                                acc.insert(
+                                    archive_key.clone(),
+                                    Directory::default().tap_mut(|directory| {
                                         directory.insert(directory_key.clone(), file);
-                                    }
-                                    None => {
-                                        acc.insert(
-                                            archive_key.clone(),
-                                            Directory::default().tap_mut(|directory| {
-                                                directory.insert(directory_key.clone(), file);
-                                            }),
-                                        );
-                                    }
-                                })
-                            })
-                            .pipe(|archive| {
-                                handle_archive(
-                                    &archive,
-                                    ArchiveOptions::builder()
-                                        .version(version)
-                                        .flags(archive_flags)
-                                        .types(archive_types)
-                                        .build(),
-                                    to,
-                                )
-                            })
+                                    }),
+                                );
+                            }
+                        })
                     })
-                    .context("creating BSA (skyrim and before) archive")
+            })
+            .pipe(|archive| {
+                handle_archive(
+                    &archive,
+                    ArchiveOptions::builder()
+                        .version(version)
+                        .flags(archive_flags)
+                        .types(archive_types)
+                        .build(),
+                    to,
+                    progress,
+                )
             })
         })
+        .context("creating BSA (skyrim and before) archive")
 }
@@ -1,12 +1,19 @@
 use {
     super::*,
     crate::{
-        install_modlist::download_cache::to_u64_from_base_64,
+        compression::{
+            ArchiveFileHandle,
+            ArchiveHandle,
+            ProcessArchive,
+            wrapped_7zip::{WRAPPED_7ZIP, percentage_progress},
+        },
+        install_modlist::download_cache::{to_base_64_from_u64, to_u64_from_base_64},
         modlist_json::directive::FromArchiveDirective,
         progress_bars_v2::IndicatifWrapIoExt,
         read_wrappers::ReadExt,
         utils::ExistingPathRead,
     },
+    case_insensitive_path::ExistingPath,
     preheat_archive_hash_paths::PreheatedArchiveHashPaths,
     std::{
         io::{Read, Write},
@@ -15,6 +22,109 @@ use {
     tracing::info_span,
 };
 
+fn check_hash(expected_hash: Option<u64>, hash: u64) -> Result<u64> {
+    match expected_hash {
+        Some(expected) if expected != hash => Err(anyhow::anyhow!(
+            "hash mismatch: expected [{}], found [{}]",
+            to_base_64_from_u64(expected),
+            to_base_64_from_u64(hash)
+        )),
+        _ => Ok(hash),
+    }
+}
+
+/// writes an entry the archive handed out into `output_path` through a temp file next to it, so the destination is either
+/// complete or untouched. backends which extract into temp files anyway get theirs moved into place instead of copied.
+/// returns the hash computed along the way
+fn write_entry(handle: ArchiveFileHandle, expected_size: u64, expected_hash: Option<u64>, output_path: &Path) -> Result<u64> {
+    match handle.into_extracted() {
+        Ok(extracted) => extracted
+            .reopen()
+            .context("reopening extracted entry")
+            .and_then(|file| {
+                let mut reader = tracing::Span::current()
+                    .wrap_read(expected_size, file)
+                    .and_validate_size(expected_size)
+                    .and_hash();
+                std::io::copy(&mut reader, &mut std::io::sink())
+                    .context("hashing extracted entry")
+                    .map(|_| reader.hash())
+            })
+            .and_then(|hash| check_hash(expected_hash, hash))
+            .and_then(|hash| crate::temp_directory::promote(extracted, output_path).map(|_| hash)),
+        Err(handle) => crate::atomic_write::temp_file_next_to(output_path).and_then(|mut temp_file| {
+            let mut reader = tracing::Span::current()
+                .wrap_read(expected_size, handle)
+                .and_validate_size(expected_size)
+                .and_hash();
+            std::io::copy(&mut reader, &mut temp_file)
+                .context("streaming entry into destination")
+                .and_then(|_| temp_file.flush().context("flushing write"))
+                .map(|_| reader.hash())
+                .and_then(|hash| check_hash(expected_hash, hash))
+                .and_then(|hash| {
+                    temp_file
+                        .persist(output_path)
+                        .with_context(|| format!("renaming temp file to [{}]", output_path.display()))
+                        .map(|_| hash)
+                })
+        }),
+    }
+}
+
+/// extracts entries of a single archive into their destinations - `(entry, expected size, expected hash, destination)`,
+/// see [write_entry]. the archive is opened (and its index read) once for all of them. there's a result for every entry,
+/// the hash computed along the way
+#[tracing::instrument(skip(archive, entries), fields(archive=%archive, entries=%entries.len()))]
+pub fn extract_entries_to_files(
+    archive: &ExistingPath,
+    archive_extension: Option<&str>,
+    entries: Vec<(CaseInsensitivePathBuf, u64, Option<u64>, std::path::PathBuf)>,
+) -> Vec<Result<u64>> {
+    let paths = entries.iter().map(|(entry, ..)| entry).collect_vec();
+    let extracted = ArchiveHandle::with_guessed(archive, archive_extension, |mut handle| handle.get_many_handles(&paths))
+        .map(|handles| handles.into_iter().collect::<BTreeMap<_, _>>())
+        .with_context(|| format!("extracting [{}] entries of [{archive}]", paths.len()));
+    match extracted {
+        Err(reason) => {
+            let reason = format!("{reason:?}");
+            entries
+                .iter()
+                .map(|_| Err(anyhow::anyhow!("{reason}")))
+                .collect()
+        }
+        Ok(mut extracted) => entries
+            .into_iter()
+            .map(|(entry, expected_size, expected_hash, output_path)| {
+                extracted
+                    .remove(&entry)
+                    .with_context(|| format!("[{entry}] was not extracted"))
+                    .and_then(|handle| write_entry(handle, expected_size, expected_hash, &output_path))
+            })
+            .collect(),
+    }
+}
+
+/// checks a file which landed in its destination without passing through a hashing reader, removing it when it's wrong
+fn verify_in_place(expected_size: u64, expected_hash: Option<u64>, output_path: &Path) -> Result<u64> {
+    std::fs::File::open(output_path)
+        .with_context(|| format!("opening [{}]", output_path.display()))
+        .and_then(|file| {
+            let mut reader = tracing::Span::current()
+                .wrap_read(expected_size, file)
+                .and_validate_size(expected_size)
+                .and_hash();
+            std::io::copy(&mut reader, &mut std::io::sink())
+                .context("hashing extracted entry")
+                .map(|_| reader.hash())
+        })
+        .and_then(|hash| check_hash(expected_hash, hash))
+        .tap_err(|_| {
+            std::fs::remove_file(output_path).ok();
+        })
+        .with_context(|| format!("verifying [{}]", output_path.display()))
+}
+
 #[derive(Clone, derivative::Derivative)]
 #[derivative(Debug)]
 pub struct FromArchiveHandler {
@@ -93,13 +203,142 @@ impl FromArchiveHandler {
             .exists()
             .and_then(|source_file| source_file.open_file_read())
             .and_then(|(source_path, mut final_source)| {
-                output_path
-                    .open_file_write()
-                    .and_then(|(_, mut output_file)| {
-                        perform_copy(&mut final_source, &mut output_file, output_path.clone().into_string().pipe(PathBuf::from))
-                            .with_context(|| format!("when extracting from [{source_path:?}] ({:?}) to [{}]", archive_hash_path, output_path))
-                    })
+                output_path.write_atomically(|output_file| {
+                    perform_copy(&mut final_source, output_file, output_path.clone().into_string().pipe(PathBuf::from))
+                        .with_context(|| format!("when extracting from [{source_path:?}] ({:?}) to [{}]", archive_hash_path, output_path))
+                })
             })
             .map(|_| size)
     }
+
+    /// entries of a top-level archive read out of it by a backend of its own - bethesda archives, and zip and rar whose temp
+    /// files are moved into the destinations instead of being copied there. the archive is opened (and its index read) once
+    /// for all of them, see [extract_entries_to_files]. there's a result for every directive
+    #[tracing::instrument(skip(self, directives), fields(directives=%directives.len()))]
+    pub fn handle_batched(
+        self,
+        archive: &CaseInsensitivePathBuf,
+        directives: Vec<(FromArchiveDirective, NonEmpty<CaseInsensitivePathBuf>)>,
+    ) -> Vec<Result<u64>> {
+        let planned = directives
+            .into_iter()
+            .map(|(directive, source)| {
+                let [entry] = source.tail.as_slice() else {
+                    anyhow::bail!("only entries of top-level archives can be extracted in batches, got [{source:?}]");
+                };
+                let output_path = self
+                    .output_directory
+                    .as_path()
+                    .join_checked(directive.to.as_path())
+                    .with_context(|| format!("joining {} to output directory", directive.to))
+                    .map(|output_path| std::path::PathBuf::from(output_path.as_str()))?;
+                let expected_hash = match is_whitelisted_by_path(&output_path) {
+                    true => None,
+                    false => directive.hash.clone().pipe(to_u64_from_base_64).map(Some)?,
+                };
+                Ok((directive, entry.clone(), expected_hash, output_path))
+            })
+            .collect_vec();
+        let entries = planned
+            .iter()
+            .filter_map(|planned| planned.as_ref().ok())
+            .map(|(directive, entry, expected_hash, output_path)| (entry.clone(), directive.size, *expected_hash, output_path.clone()))
+            .collect_vec();
+        let extracted = match archive.try_exists() {
+            Ok(path) => extract_entries_to_files(&path, archive.extension(), entries),
+            Err(reason) => {
+                let reason = format!("{reason:?}");
+                entries
+                    .iter()
+                    .map(|_| Err(anyhow::anyhow!("{reason}")))
+                    .collect()
+            }
+        };
+        let mut extracted = extracted.into_iter();
+        planned
+            .into_iter()
+            .map(|planned| {
+                planned.and_then(|(FromArchiveDirective { size, to, .. }, ..)| {
+                    extracted
+                        .next()
+                        .context("fewer results than there were entries")
+                        .and_then(|extracted| extracted)
+                        .with_context(|| format!("when extracting [{to}] from [{archive}]"))
+                        .map(|_| size)
+                })
+            })
+            .collect()
+    }
+
+    /// entries of a top-level archive which the 7z binary extracts straight into their destinations, in a single invocation.
+    /// there's a result for every directive
+    #[tracing::instrument(skip(self, directives), fields(directives=%directives.len()))]
+    pub fn handle_extracted_to(
+        self,
+        archive: &CaseInsensitivePathBuf,
+        directives: Vec<(FromArchiveDirective, NonEmpty<CaseInsensitivePathBuf>)>,
+    ) -> Vec<Result<u64>> {
+        let planned = directives
+            .into_iter()
+            .map(|(directive, source)| {
+                let [entry] = source.tail.as_slice() else {
+                    anyhow::bail!("only entries of top-level archives can be extracted by 7z, got [{source:?}]");
+                };
+                let output_path = self
+                    .output_directory
+                    .as_path()
+                    .join_checked(directive.to.as_path())
+                    .with_context(|| format!("joining {} to output directory", directive.to))?;
+                Ok((directive, entry.as_original_std_path(), std::path::PathBuf::from(output_path.as_str())))
+            })
+            .collect_vec();
+        let mappings = planned
+            .iter()
+            .filter_map(|planned| planned.as_ref().ok())
+            .map(|(_, entry, output_path)| (entry.as_path(), output_path.as_path()))
+            .collect_vec();
+        let extracting = info_span!("extracting_with_7z", entries=%mappings.len());
+        let extracted = WRAPPED_7ZIP
+            .with(|wrapped| wrapped.open_file(&archive.as_original_std_path()))
+            .and_then(|handle| {
+                extracting.in_scope(|| {
+                    handle.extract_to(
+                        &mappings,
+                        Some(std::num::NonZeroUsize::new(1).expect("1 is non-zero")),
+                        percentage_progress(&extracting),
+                    )
+                })
+            })
+            .with_context(|| format!("extracting [{}] entries of [{archive}] with 7z", mappings.len()));
+        match extracted {
+            Err(reason) => {
+                let reason = format!("{reason:?}");
+                planned
+                    .into_iter()
+                    .map(|planned| planned.and_then(|_| Err(anyhow::anyhow!("{reason}"))))
+                    .collect()
+            }
+            Ok(extracted) => {
+                let mut extracted = extracted.into_iter();
+                planned
+                    .into_iter()
+                    .map(|planned| {
+                        planned.and_then(|(FromArchiveDirective { hash, size, to, .. }, _, output_path)| {
+                            let expected_hash = match is_whitelisted_by_path(&output_path) {
+                                true => None,
+                                false => hash.pipe(to_u64_from_base_64).map(Some)?,
+                            };
+                            extracted
+                                .next()
+                                .context("7z returned fewer results than there were entries")
+                                .and_then(|extracted| extracted)
+                                .and_then(|_| verify_in_place(size, expected_hash, &output_path))
+                                .with_context(|| format!("when extracting [{to}] from [{archive}]"))
+                                .map(|_| size)
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use {
     super::*,
-    crate::{modlist_json::directive::InlineFileDirective, progress_bars_v2::IndicatifWrapIoExt, utils::ExistingPathRead},
+    crate::{modlist_json::directive::InlineFileDirective, progress_bars_v2::IndicatifWrapIoExt},
     std::io::Write,
     wabbajack_file_handle::WabbajackFileHandle,
 };
@@ -28,30 +28,25 @@ impl InlineFileHandler {
             .join_case_insensitive(to)
             .context("building output path")?;
         let wabbajack_file = self.wabbajack_file.clone();
-        let (_, output_file) = output_path
-            .as_path()
-            .open_file_write()
-            .context("creating output file")?;
 
         let archive = wabbajack_file;
-        archive
-            .get_source_data(source_data_id)
-            .and_then(|source_data| {
-                source_data
-                    .open_file_read()
-                    .map(|(_, file)| (source_data, file))
-            })
-            .and_then(|(_guard, mut file)| {
-                let mut writer = std::io::BufWriter::new(output_file);
-                std::io::copy(
-                    &mut tracing::Span::current().wrap_read(size, &mut file),
-                    // WARN: stuff that's inside modlist.wabbajack/modlist(.json) is incorrect
-                    // .and_validate_size(size)
-                    // .and_validate_hash(hash.pipe(to_u64_from_base_64).expect("come on")),
-                    &mut writer,
-                )
-                .context("copying file from archive")
-                .and_then(|_| writer.flush().context("flushing"))
+        output_path
+            .as_path()
+            .write_atomically(|output_file| {
+                archive
+                    .open_source_data(source_data_id)
+                    .and_then(|(_guard, mut file)| {
+                        let mut writer = std::io::BufWriter::new(output_file);
+                        std::io::copy(
+                            &mut tracing::Span::current().wrap_read(size, &mut file),
+                            // WARN: stuff that's inside modlist.wabbajack/modlist(.json) is incorrect
+                            // .and_validate_size(size)
+                            // .and_validate_hash(hash.pipe(to_u64_from_base_64).expect("come on")),
+                            &mut writer,
+                        )
+                        .context("copying file from archive")
+                        .and_then(|_| writer.flush().context("flushing"))
+                    })
             })
             .map(|_| ())
             .map(|_| size)
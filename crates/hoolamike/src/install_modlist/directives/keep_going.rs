@@ -0,0 +1,80 @@
+//! with `--keep-going` a failed directive doesn't stop the installation: its error is recorded, the other directives still
+//! run, and archives packing a file which did not get staged are skipped. the run fails in the end all the same - and since a
+//! failed directive never leaves its output behind, the next plain `install` finds exactly those outputs missing and redoes them
+
+use {anyhow::Result, parking_lot::Mutex, std::fmt};
+
+/// every directive failure recorded with `--keep-going`
+#[derive(Debug)]
+pub struct DirectiveFailures(pub Vec<anyhow::Error>);
+
+impl fmt::Display for DirectiveFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] directives failed", self.0.len())
+    }
+}
+
+impl std::error::Error for DirectiveFailures {}
+
+#[derive(Debug)]
+pub struct Failures {
+    keep_going: bool,
+    errors: Mutex<Vec<anyhow::Error>>,
+}
+
+impl Failures {
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            errors: Default::default(),
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        !self.errors.lock().is_empty()
+    }
+
+    /// a failed directive counts as handled (with nothing written) when going on, cancellation stops the run either way
+    pub fn absorb(&self, handled: Result<u64>) -> Result<u64> {
+        match handled {
+            Err(reason) if self.keep_going && !crate::install_modlist::cancellation::is_cancelled() => {
+                tracing::error!("{reason:?}\n\n--keep-going is set, moving on to the other directives");
+                self.errors.lock().push(reason);
+                Ok(0)
+            }
+            handled => handled,
+        }
+    }
+
+    /// fails with [DirectiveFailures] when anything was absorbed
+    pub fn finish<T>(&self, handled: Result<T>) -> Result<T> {
+        handled.and_then(|handled| match std::mem::take(&mut *self.errors.lock()) {
+            errors if errors.is_empty() => Ok(handled),
+            errors => Err(DirectiveFailures(errors).into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::anyhow};
+
+    #[test_log::test]
+    fn test_failures_are_absorbed_only_when_going_on() {
+        let stopping = Failures::new(false);
+        assert!(stopping.absorb(Err(anyhow!("broken texture"))).is_err());
+        assert!(!stopping.any());
+
+        let going_on = Failures::new(true);
+        assert_eq!(going_on.absorb(Ok(4)).ok(), Some(4));
+        assert_eq!(going_on.absorb(Err(anyhow!("broken texture"))).ok(), Some(0));
+        assert!(going_on.any());
+        let failures = going_on
+            .finish(Ok(()))
+            .expect_err("failures were recorded")
+            .downcast::<DirectiveFailures>()
+            .expect("not a DirectiveFailures");
+        assert_eq!(failures.0.len(), 1);
+        assert!(going_on.finish(Ok(())).is_ok(), "taken by the first finish");
+    }
+}
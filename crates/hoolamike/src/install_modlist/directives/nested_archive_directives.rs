@@ -1,24 +1,92 @@
 use {
-    super::{
-        ArchivePathDirective,
-        DirectivesHandler,
-        DownloadSummary,
-        IteratorTryFlatMapExt,
-        ResolvePathExt,
-        preheat_archive_hash_paths::PreheatedArchiveHashPaths,
-    },
+    super::{ArchivePathDirective, DirectivesHandler, DownloadSummary, ResolvePathExt, preheat_archive_hash_paths::PreheatedArchiveHashPaths},
+    crate::compression::ArchiveHandleKind,
     anyhow::{Context, Result},
-    std::{iter::once, sync::Arc},
+    case_insensitive_path::CaseInsensitivePathBuf,
+    itertools::{Either, Itertools},
+    nonempty::NonEmpty,
+    rayon::prelude::*,
+    std::{collections::BTreeMap, sync::Arc},
     tap::prelude::*,
     tracing::{info_span, instrument},
 };
 
+/// how the source file of a directive gets to the output directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractionPath {
+    /// extracted into a temp file during preheat, which every directive needing it shares
+    Preheated,
+    /// streamed straight out of the downloaded archive into the destination, bethesda archives only (see [crate::compression]).
+    /// the archive is opened once per chunk of the entries planned this way
+    Streamed,
+    /// extracted by the backend into a temp file of its own, which is moved into the destination rather than copied there.
+    /// the archive is opened once per chunk of the entries planned this way
+    Promoted,
+    /// extracted by the 7z binary straight into the destination, along with the other entries of the archive planned this way
+    ExtractedTo,
+}
+
+/// how many entries of an archive are read out of it at once, before the first one is written to its destination
+const BATCH_CHUNK_SIZE: usize = 64;
+
+/// backend the archive at `path` (described by `source_hash`) is going to be extracted with
+fn archive_backend(source_hash: &str, path: &CaseInsensitivePathBuf) -> Option<ArchiveHandleKind> {
+    ArchiveHandleKind::for_archive(source_hash, &path.as_original_std_path(), path.extension())
+}
+
+/// a directive can skip the preheat only when no other directive in the chunk needs the same temp file
+/// (either directly, or as a parent of a nested archive) and the archive backend doesn't need a temp file of its own anyway -
+/// the 7z binary extracts into the destinations, the streaming backends read entries straight out of the archive, and the
+/// temp files zip and rar entries are extracted into are renamed into place
+fn plan_extraction_paths(
+    directives: &[(&ArchivePathDirective, &NonEmpty<CaseInsensitivePathBuf>)],
+    backend: impl Fn(&str, &CaseInsensitivePathBuf) -> Option<ArchiveHandleKind>,
+) -> Vec<ExtractionPath> {
+    let required_by = directives
+        .iter()
+        .flat_map(|(_, path)| (2..=path.len()).map(|len| path.iter().take(len).cloned().collect_vec()))
+        .counts();
+    let mut backends = BTreeMap::new();
+    directives
+        .iter()
+        .map(|(directive, path)| match directive {
+            ArchivePathDirective::FromArchive(_) if path.len() == 2 && required_by.get(&path.iter().cloned().collect_vec()) == Some(&1) => {
+                match *backends
+                    .entry(path.head.clone())
+                    .or_insert_with(|| backend(&directive.archive_path().source_hash, &path.head))
+                {
+                    Some(ArchiveHandleKind::Wrapped7Zip) => ExtractionPath::ExtractedTo,
+                    Some(kind) if kind.streams_entries() => ExtractionPath::Streamed,
+                    Some(kind) if kind.extracts_to_temp_files() => ExtractionPath::Promoted,
+                    _ => ExtractionPath::Preheated,
+                }
+            }
+            _ => ExtractionPath::Preheated,
+        })
+        .collect()
+}
+
+/// when preparing the sources of a chunk fails, every directive of it failed - each one gets an error of its own, so that
+/// `--keep-going` records (and the summary counts) all of them
+fn failed_chunk(reason: anyhow::Error, destinations: Vec<CaseInsensitivePathBuf>) -> Vec<Result<u64>> {
+    let count = destinations.len();
+    let reason = format!("{reason:?}");
+    destinations
+        .into_iter()
+        .map(|to| Err(anyhow::anyhow!("{reason}")).with_context(|| format!("preparing the source of [{to}] (in a chunk of [{count}] directives)")))
+        .collect()
+}
+
 #[instrument(skip_all)]
 pub(crate) fn handle_nested_archive_directives(
     manager: Arc<DirectivesHandler>,
     download_summary: DownloadSummary,
     directives: Vec<ArchivePathDirective>,
 ) -> impl Iterator<Item = Result<u64>> {
+    let destinations = directives
+        .iter()
+        .map(|directive| directive.destination().clone())
+        .collect_vec();
     let preheat_task = {
         let preheat_directives = info_span!("preheat_directives");
         directives
@@ -26,39 +94,260 @@ pub(crate) fn handle_nested_archive_directives(
             .map(|d| d.archive_path())
             .map(|path| download_summary.resolve_archive_path(path))
             .collect::<Result<Vec<_>>>()
-            .and_then(|paths| preheat_directives.in_scope(|| PreheatedArchiveHashPaths::preheat_archive_hash_paths(paths)))
+            .map(|paths| {
+                let plan = plan_extraction_paths(&directives.iter().zip(paths.iter()).collect_vec(), archive_backend);
+                directives.into_iter().zip(paths).zip(plan).collect_vec()
+            })
+            .and_then(|planned| {
+                planned
+                    .iter()
+                    .filter(|(_, extraction)| *extraction == ExtractionPath::Preheated)
+                    .map(|((_, path), _)| path.clone())
+                    .collect_vec()
+                    .tap(|preheated| tracing::debug!(preheated=%preheated.len(), skipped=%(planned.len() - preheated.len())))
+                    .pipe(|paths| preheat_directives.in_scope(|| PreheatedArchiveHashPaths::preheat_archive_hash_paths(paths)))
+                    .map(|preheated| (planned, Arc::new(preheated)))
+            })
     };
     let _handle_directives = info_span!("handle_directives").entered();
 
-    let directives: Arc<[_]> = Arc::from(directives);
-
-    preheat_task
-        .map(Arc::new)
-        .pipe(once)
-        .try_flat_map(move |preheated| {
-            let directives = directives.as_ref().to_vec();
-            directives.into_iter().map({
-                cloned![manager];
-                move |directive| match directive {
-                    ArchivePathDirective::TransformedTexture(transformed_texture) => manager
-                        .clone()
-                        .transformed_texture
-                        .clone()
-                        .handle(transformed_texture.clone(), preheated.clone())
-                        .with_context(|| format!("handling directive: {transformed_texture:#?}")),
-                    ArchivePathDirective::FromArchive(from_archive) => manager
-                        .clone()
-                        .from_archive
-                        .clone()
-                        .handle(from_archive.clone(), preheated.clone())
-                        .with_context(|| format!("handling directive: {from_archive:#?}")),
-                    ArchivePathDirective::PatchedFromArchive(patched_from_archive_directive) => manager
-                        .clone()
-                        .patched_from_archive
-                        .clone()
-                        .handle(patched_from_archive_directive.clone(), preheated.clone())
-                        .with_context(|| format!("handling directive: {patched_from_archive_directive:#?}")),
-                }
-            })
+    let (planned, preheated) = match preheat_task {
+        Ok(prepared) => prepared,
+        Err(reason) => return Either::Left(failed_chunk(reason, destinations).into_iter()),
+    };
+    let (cpu_bound, io_bound): (Vec<_>, Vec<_>) = planned.into_iter().partition(|((directive, _), _)| {
+        matches!(
+            directive,
+            ArchivePathDirective::PatchedFromArchive(_) | ArchivePathDirective::TransformedTexture(_)
+        )
+    });
+    let (textures, cpu_bound): (Vec<_>, Vec<_>) = cpu_bound
+        .into_iter()
+        .partition_map(|planned| match planned {
+            ((ArchivePathDirective::TransformedTexture(texture), _), _) => Either::Left(texture),
+            other => Either::Right(other),
+        });
+    // textures go through texconv in batches, starting a wine process for each one of them is what takes the longest
+    let textures = info_span!("transformed_textures", count=%textures.len()).in_scope(|| {
+        manager.pools.cpu.install(|| {
+            manager
+                .transformed_texture
+                .clone()
+                .handle_batched(textures, preheated.clone())
         })
+    });
+    let handle = {
+        cloned![manager];
+        move |((directive, source), extraction): ((ArchivePathDirective, NonEmpty<CaseInsensitivePathBuf>), ExtractionPath)| match directive {
+            ArchivePathDirective::TransformedTexture(transformed_texture) => manager
+                .clone()
+                .transformed_texture
+                .clone()
+                .handle(transformed_texture.clone(), preheated.clone())
+                .with_context(|| format!("handling directive: {transformed_texture:#?}")),
+            ArchivePathDirective::FromArchive(from_archive) => match extraction {
+                ExtractionPath::Preheated => manager
+                    .clone()
+                    .from_archive
+                    .clone()
+                    .handle(from_archive.clone(), preheated.clone()),
+                // entries which don't need the preheat are extracted together below, one by one when they end up here
+                ExtractionPath::Streamed | ExtractionPath::Promoted | ExtractionPath::ExtractedTo => manager
+                    .clone()
+                    .from_archive
+                    .clone()
+                    .handle_batched(&source.head.clone(), vec![(from_archive.clone(), source)])
+                    .into_iter()
+                    .next()
+                    .context("no result for the directive")
+                    .and_then(|handled| handled),
+            }
+            .with_context(|| format!("handling directive: {from_archive:#?}")),
+            ArchivePathDirective::PatchedFromArchive(patched_from_archive_directive) => manager
+                .patched_from_archive
+                .clone()
+                .handle(patched_from_archive_directive.clone(), preheated.clone())
+                .with_context(|| format!("handling directive: {patched_from_archive_directive:#?}")),
+        }
+    };
+    // sources of every directive are extracted by now, so they don't need to wait on each other
+    let cpu_bound = info_span!("cpu_bound", count=%cpu_bound.len()).in_scope(|| {
+        manager
+            .pools
+            .cpu
+            .install(|| cpu_bound.into_par_iter().map(&handle).collect::<Vec<_>>())
+    });
+    let (extracted_to, io_bound): (Vec<_>, Vec<_>) = io_bound.into_iter().partition_map(|planned| match planned {
+        ((ArchivePathDirective::FromArchive(from_archive), source), ExtractionPath::ExtractedTo) => Either::Left((from_archive, source)),
+        other => Either::Right(other),
+    });
+    // a single 7z invocation per archive, its entries land in their destinations without a detour through temp files
+    let extracted_to = info_span!("extracted_to", count=%extracted_to.len()).in_scope(|| {
+        extracted_to
+            .into_iter()
+            .into_group_map_by(|(_, source)| source.head.clone())
+            .pipe(|by_archive| {
+                manager.pools.io.install(|| {
+                    by_archive
+                        .into_par_iter()
+                        .flat_map_iter(|(archive, directives)| {
+                            manager
+                                .from_archive
+                                .clone()
+                                .handle_extracted_to(&archive, directives)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+    });
+    let (batched, io_bound): (Vec<_>, Vec<_>) = io_bound.into_iter().partition_map(|planned| match planned {
+        ((ArchivePathDirective::FromArchive(from_archive), source), ExtractionPath::Streamed | ExtractionPath::Promoted) => {
+            Either::Left((from_archive, source))
+        }
+        other => Either::Right(other),
+    });
+    // an archive is opened (and its index read) once per chunk rather than once per entry. every entry of a chunk is read out
+    // of the archive before the first one is written, so chunks are of bounded size
+    let batched = info_span!("batched", count=%batched.len()).in_scope(|| {
+        batched
+            .into_iter()
+            .into_group_map_by(|(_, source)| source.head.clone())
+            .into_iter()
+            .flat_map(|(archive, directives)| {
+                directives
+                    .into_iter()
+                    .chunks(BATCH_CHUNK_SIZE)
+                    .into_iter()
+                    .map(|chunk| (archive.clone(), chunk.collect_vec()))
+                    .collect_vec()
+            })
+            .collect_vec()
+            .pipe(|chunks| {
+                manager.pools.io.install(|| {
+                    chunks
+                        .into_par_iter()
+                        .flat_map_iter(|(archive, directives)| {
+                            manager
+                                .from_archive
+                                .clone()
+                                .handle_batched(&archive, directives)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+    });
+    let io_bound = info_span!("io_bound", count=%io_bound.len()).in_scope(|| {
+        manager
+            .pools
+            .io
+            .install(|| io_bound.into_par_iter().map(&handle).collect::<Vec<_>>())
+    });
+    textures
+        .into_iter()
+        .chain(cpu_bound)
+        .chain(extracted_to)
+        .chain(batched)
+        .chain(io_bound)
+        .pipe(Either::Right)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::modlist_json::directive::{ArchiveHashPath, FromArchiveDirective},
+        std::str::FromStr,
+    };
+
+    fn path(segments: &[&str]) -> NonEmpty<CaseInsensitivePathBuf> {
+        segments
+            .iter()
+            .map(|segment| CaseInsensitivePathBuf::from_str(segment).unwrap())
+            .collect_vec()
+            .pipe(NonEmpty::from_vec)
+            .unwrap()
+    }
+
+    fn from_archive() -> ArchivePathDirective {
+        FromArchiveDirective {
+            hash: Default::default(),
+            size: 0,
+            to: CaseInsensitivePathBuf::from_str("out").unwrap(),
+            archive_hash_path: ArchiveHashPath {
+                source_hash: Default::default(),
+                path: vec![],
+            },
+        }
+        .into()
+    }
+
+    #[test_log::test]
+    fn test_failed_chunk_fails_every_directive() {
+        let destinations = ["mods/a/a.esp", "mods/a/textures/a.dds", "mods/b/b.esp"]
+            .map(|to| CaseInsensitivePathBuf::from_str(to).unwrap())
+            .to_vec();
+        let failed = failed_chunk(anyhow::anyhow!("archive is corrupted"), destinations);
+        assert_eq!(failed.len(), 3);
+        let reasons = failed
+            .into_iter()
+            .map(|failed| format!("{:?}", failed.expect_err("every directive fails")))
+            .collect_vec();
+        assert!(
+            reasons[1].contains("preparing the source of [mods/a/textures/a.dds] (in a chunk of [3] directives)"),
+            "{}",
+            reasons[1]
+        );
+        assert!(
+            reasons
+                .iter()
+                .all(|reason| reason.contains("archive is corrupted"))
+        );
+    }
+
+    #[test_log::test]
+    fn test_plan_extraction_paths() {
+        let directive = from_archive();
+        let paths = [
+            // only user of this entry
+            path(&["/downloads/a.bsa", "textures/a.dds"]),
+            // both directives need the same temp file
+            path(&["/downloads/a.bsa", "meshes/shared.nif"]),
+            path(&["/downloads/a.bsa", "meshes/shared.nif"]),
+            // parent of a nested archive
+            path(&["/downloads/b.ba2", "nested.bsa"]),
+            path(&["/downloads/b.ba2", "nested.bsa", "inner.esp"]),
+            // backends extract into temp files anyway, which are moved into place
+            path(&["/downloads/c.zip", "plugin.esp"]),
+            path(&["/downloads/e.rar", "readme.txt"]),
+            // unless another directive needs the same one
+            path(&["/downloads/c.zip", "shared.esp"]),
+            path(&["/downloads/c.zip", "shared.esp"]),
+            // entries of nested zip archives still need the parent preheated
+            path(&["/downloads/b.ba2", "nested.zip", "inner.esp"]),
+            // the 7z binary extracts into the destinations
+            path(&["/downloads/d.7z", "plugin.esp"]),
+            path(&["/downloads/d.7z", "textures/d.dds"]),
+        ];
+        let backend = |_: &str, path: &CaseInsensitivePathBuf| match path.extension() {
+            Some("7z") => Some(ArchiveHandleKind::Wrapped7Zip),
+            other => ArchiveHandleKind::preferred_for_extension(other),
+        };
+        assert_eq!(
+            plan_extraction_paths(&paths.iter().map(|path| (&directive, path)).collect_vec(), backend),
+            [
+                ExtractionPath::Streamed,
+                ExtractionPath::Preheated,
+                ExtractionPath::Preheated,
+                ExtractionPath::Preheated,
+                ExtractionPath::Preheated,
+                ExtractionPath::Promoted,
+                ExtractionPath::Promoted,
+                ExtractionPath::Preheated,
+                ExtractionPath::Preheated,
+                ExtractionPath::Preheated,
+                ExtractionPath::ExtractedTo,
+                ExtractionPath::ExtractedTo,
+            ]
+        );
+    }
 }
@@ -4,13 +4,27 @@ use {
     anyhow::Result,
     futures::TryFutureExt,
     once_cell::sync::Lazy,
-    std::{future::ready, sync::Arc},
+    std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
     tokio::sync::{OwnedSemaphorePermit, Semaphore},
     tracing::{Instrument, info_span, instrument},
 };
 
+/// 0 until configured
+static MAX_OPEN_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// `advanced.max_open_files`, has to happen before the first file is opened
+pub fn configure_max_open_files(max_open_files: Option<usize>) {
+    MAX_OPEN_FILES.store(max_open_files.unwrap_or_default(), Ordering::Relaxed);
+}
+
 pub fn max_open_files() -> usize {
-    concurrency() * 40
+    match MAX_OPEN_FILES.load(Ordering::Relaxed) {
+        0 => concurrency() * 40,
+        configured => configured,
+    }
 }
 
 #[allow(dead_code)]
@@ -47,12 +61,11 @@ where
     where
         F: FnOnce() -> Result<T> + Clone + Send + 'static,
     {
-        let span = tracing::Span::current();
         Self::new(semaphore, move || {
-            tokio::task::spawn_blocking(move || span.in_scope(new))
+            crate::blocking_pool::pools()
+                .extraction
+                .run(new)
                 .instrument(tracing::Span::current())
-                .map_context("thread crashed")
-                .and_then(ready)
         })
         .await
     }
@@ -0,0 +1,108 @@
+//! directories the directives write to are created up front - once each, parents first - instead of with a create_dir_all
+//! for every written file. destinations pointing outside of the installation directory are refused before anything is
+//! written: `..` segments which stay inside are resolved when the modlist is parsed, the ones climbing above the
+//! installation directory are kept (see [CaseInsensitivePathBuf]'s parsing) and refused here, along with rooted and
+//! drive-prefixed paths. paths put together at runtime (BSA staging, texconv output) get their directories created by
+//! [crate::atomic_write] when the first attempt finds them missing
+
+use {
+    crate::{install_modlist::execution_plan::destination, modlist_json::Directive},
+    anyhow::{Context, Result},
+    case_insensitive_path::CaseInsensitivePathBuf,
+    std::{
+        collections::BTreeSet,
+        path::{Component, Path, PathBuf},
+    },
+    tap::prelude::*,
+    tracing::debug,
+};
+
+/// directory `to` ends up in, relative to the installation directory
+fn relative_directory(to: &CaseInsensitivePathBuf) -> Result<PathBuf> {
+    Path::new(to.as_path().as_str())
+        .components()
+        .try_fold(PathBuf::new(), |relative, component| match component {
+            Component::Normal(segment) => Ok(relative.join(segment)),
+            Component::CurDir => Ok(relative),
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => Err(anyhow::anyhow!(
+                "refusing to write [{}] - it points outside of the installation directory. the modlist is either broken or trying to write files it has no \
+                 business touching",
+                to.as_original_path()
+            )),
+        })
+        .map(|relative| relative.parent().map(Path::to_owned).unwrap_or_default())
+}
+
+/// unique directories `directives` write to, relative to the installation directory, parents first
+pub fn destination_directories(directives: &[Directive]) -> Result<BTreeSet<PathBuf>> {
+    directives
+        .iter()
+        .map(|directive| relative_directory(destination(directive)))
+        .filter(|directory| !matches!(directory, Ok(directory) if directory.as_os_str().is_empty()))
+        .collect()
+}
+
+/// creates every directory `directives` write to in a single sorted pass, returns how many there are
+pub fn create(output_directory: &Path, directives: &[Directive]) -> Result<usize> {
+    destination_directories(directives).and_then(|directories| {
+        directories
+            .iter()
+            .map(|directory| output_directory.join(directory))
+            .try_for_each(|directory| std::fs::create_dir_all(&directory).with_context(|| format!("creating [{}]", directory.display())))
+            .map(|_| directories.len())
+            .tap_ok(|created| debug!(%created, "output directories are in place"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::modlist_json::directive::fixtures::from_archive};
+
+    #[test_log::test]
+    fn test_directories_are_created_once_each() -> Result<()> {
+        let directives = [
+            "mods\\Some Mod\\meshes\\a.nif",
+            "mods\\Some Mod\\meshes\\b.nif",
+            "mods\\Some Mod\\Some Mod.esp",
+            "profiles\\Default\\modlist.txt",
+            "ModOrganizer.ini",
+        ]
+        .map(from_archive);
+        assert_eq!(
+            destination_directories(&directives)?,
+            ["mods/Some Mod", "mods/Some Mod/meshes", "profiles/Default"]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect::<BTreeSet<_>>()
+        );
+
+        let installation = tempfile::tempdir()?;
+        assert_eq!(create(installation.path(), &directives)?, 3);
+        assert!(installation.path().join("mods/Some Mod/meshes").is_dir());
+        assert!(installation.path().join("profiles/Default").is_dir());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_destinations_outside_of_the_installation_are_refused() -> Result<()> {
+        let installation = tempfile::tempdir()?;
+        for escaping in [
+            "\\etc\\cron.d\\evil",
+            "C:\\Windows\\System32\\evil.dll",
+            "mods\\..\\..\\..\\home\\user\\.bashrc",
+            "..\\outside.esp",
+        ] {
+            let directives = [from_archive("mods\\fine.esp"), from_archive(escaping)];
+            let refused = create(installation.path(), &directives).expect_err(escaping);
+            assert!(format!("{refused:?}").contains("outside of the installation directory"), "{refused:?}");
+        }
+        assert!(!installation.path().join("mods").exists(), "nothing is created when any destination is refused");
+
+        // `..` which stays inside is fine
+        assert_eq!(
+            destination_directories(&[from_archive("mods\\Some Mod\\..\\Other Mod\\other.esp")])?,
+            BTreeSet::from([PathBuf::from("mods/Other Mod")])
+        );
+        Ok(())
+    }
+}
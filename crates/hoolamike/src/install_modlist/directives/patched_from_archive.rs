@@ -1,11 +1,9 @@
 use {
     super::*,
     crate::{
-        compression::forward_only_seek::ForwardOnlySeek,
         install_modlist::download_cache::to_u64_from_base_64,
         modlist_json::directive::PatchedFromArchiveDirective,
         progress_bars_v2::IndicatifWrapIoExt,
-        read_wrappers::ReadExt,
         utils::ExistingPathRead,
     },
     preheat_archive_hash_paths::PreheatedArchiveHashPaths,
@@ -56,43 +54,30 @@ impl PatchedFromArchiveHandler {
             D: Read,
             T: Write,
         {
-            // this applies delta on the fly
-            let from = crate::octadiff_reader::ApplyDetla::new_from_readers(source, ForwardOnlySeek::new(delta))
-                .context("invalid delta")?
-                .context("delta is empty")?;
-            let mut writer = &mut std::io::BufWriter::new(target);
-            std::io::copy(
-                &mut tracing::Span::current()
-                    .wrap_read(expected_size, from)
-                    .and_validate_size(expected_size)
-                    .and_validate_hash(to_u64_from_base_64(expected_hash)?),
-                &mut writer,
+            let expected_hash = to_u64_from_base_64(expected_hash)?;
+            // size and hash are computed while the patched file is being written
+            crate::octadiff_reader::apply(
+                std::io::BufReader::new(delta),
+                std::io::BufReader::new(source),
+                tracing::Span::current().wrap_write(expected_size, std::io::BufWriter::new(target)),
             )
-            .context("copying file from archive")
-            .and_then(|_| writer.flush().context("flushing"))
+            .and_then(|applied| applied.verify(expected_size, expected_hash))
+            .context("applying delta")
             .map(|_| ())
         }
         let (_guard, delta_file) = wabbajack_file
-            .get_source_data(patch_id)
-            .and_then(|source_data| {
-                source_data
-                    .open_file_read()
-                    .map(|(_, file)| (source_data, file))
-            })
+            .open_source_data(patch_id)
             .with_context(|| format!("patch {patch_id:?} does not exist"))?;
 
         source_file
             .exists()
             .and_then(|source_file| source_file.open_file_read())
             .and_then(|(final_source_path, mut final_source)| {
-                output_path
-                    .as_path()
-                    .open_file_write()
-                    .and_then(|(output_path, mut output_file)| {
-                        perform_copy(&mut final_source, delta_file, &mut output_file, size, hash)
-                            .with_context(|| format!("when extracting from [{final_source_path:?}] to [{output_path:?}]"))
-                            .with_context(|| format!("when handling [{archive_hash_path:?}] copy"))
-                    })
+                output_path.as_path().write_atomically(|output_file| {
+                    perform_copy(&mut final_source, delta_file, output_file, size, hash)
+                        .with_context(|| format!("when extracting from [{final_source_path:?}] to [{output_path:?}]"))
+                        .with_context(|| format!("when handling [{archive_hash_path:?}] copy"))
+                })
             })
             .map(|_| size)
     }
@@ -3,11 +3,10 @@ use {
     crate::{
         modlist_json::directive::RemappedInlineFileDirective,
         progress_bars_v2::IndicatifWrapIoExt,
-        utils::{ExistingPathRead, PathReadWrite, StreamLenExt},
+        utils::{PathReadWrite, StreamLenExt},
     },
     std::io::Read,
     tracing::instrument,
-    typed_path::Utf8PlatformPath,
     wabbajack_file_handle::WabbajackFileHandle,
 };
 
@@ -16,17 +15,17 @@ pub mod wabbajack_consts {
 
     use typed_path::Utf8TypedPath;
 
-    pub(crate) static GAME_PATH_MAGIC_BACK: &str = "{--||GAME_PATH_MAGIC_BACK||--}";
-    pub(crate) static GAME_PATH_MAGIC_DOUBLE_BACK: &str = "{--||GAME_PATH_MAGIC_DOUBLE_BACK||--}";
-    pub(crate) static GAME_PATH_MAGIC_FORWARD: &str = "{--||GAME_PATH_MAGIC_FORWARD||--}";
+    pub(crate) const GAME_PATH_MAGIC_BACK: &str = "{--||GAME_PATH_MAGIC_BACK||--}";
+    pub(crate) const GAME_PATH_MAGIC_DOUBLE_BACK: &str = "{--||GAME_PATH_MAGIC_DOUBLE_BACK||--}";
+    pub(crate) const GAME_PATH_MAGIC_FORWARD: &str = "{--||GAME_PATH_MAGIC_FORWARD||--}";
 
-    pub(crate) static MO2_PATH_MAGIC_BACK: &str = "{--||MO2_PATH_MAGIC_BACK||--}";
-    pub(crate) static MO2_PATH_MAGIC_DOUBLE_BACK: &str = "{--||MO2_PATH_MAGIC_DOUBLE_BACK||--}";
-    pub(crate) static MO2_PATH_MAGIC_FORWARD: &str = "{--||MO2_PATH_MAGIC_FORWARD||--}";
+    pub(crate) const MO2_PATH_MAGIC_BACK: &str = "{--||MO2_PATH_MAGIC_BACK||--}";
+    pub(crate) const MO2_PATH_MAGIC_DOUBLE_BACK: &str = "{--||MO2_PATH_MAGIC_DOUBLE_BACK||--}";
+    pub(crate) const MO2_PATH_MAGIC_FORWARD: &str = "{--||MO2_PATH_MAGIC_FORWARD||--}";
 
-    pub(crate) static DOWNLOAD_PATH_MAGIC_BACK: &str = "{--||DOWNLOAD_PATH_MAGIC_BACK||--}";
-    pub(crate) static DOWNLOAD_PATH_MAGIC_DOUBLE_BACK: &str = "{--||DOWNLOAD_PATH_MAGIC_DOUBLE_BACK||--}";
-    pub(crate) static DOWNLOAD_PATH_MAGIC_FORWARD: &str = "{--||DOWNLOAD_PATH_MAGIC_FORWARD||--}";
+    pub(crate) const DOWNLOAD_PATH_MAGIC_BACK: &str = "{--||DOWNLOAD_PATH_MAGIC_BACK||--}";
+    pub(crate) const DOWNLOAD_PATH_MAGIC_DOUBLE_BACK: &str = "{--||DOWNLOAD_PATH_MAGIC_DOUBLE_BACK||--}";
+    pub(crate) const DOWNLOAD_PATH_MAGIC_FORWARD: &str = "{--||DOWNLOAD_PATH_MAGIC_FORWARD||--}";
     thread_local! {
         pub(crate)  static SETTINGS_INI: Utf8TypedPath<'static> = Utf8TypedPath::unix("settings.ini");
         pub(crate)  static MO2_MOD_FOLDER_NAME:  Utf8TypedPath<'static> = Utf8TypedPath::unix("mods");
@@ -49,61 +48,116 @@ pub struct RemappingContext {
     pub downloads_directory: ExistingPathBuf,
 }
 
-#[extension_traits::extension(trait PathCrossPlatformJoineryExt)]
-impl Utf8PlatformPath {
-    fn join_with_delimiter(&self, delimiter: &str) -> String {
-        self.iter().join(delimiter)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Separator {
+    Back,
+    DoubleBack,
+    Forward,
+}
+
+impl Separator {
+    /// `windows_path` spelled with these separators
+    fn spell(self, windows_path: &str) -> String {
+        match self {
+            Self::Back => windows_path.to_string(),
+            Self::DoubleBack => windows_path.replace('\\', r"\\"),
+            Self::Forward => windows_path.replace('\\', "/"),
+        }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Root {
+    Game,
+    Mo2,
+    Downloads,
+}
+
+/// every magic string wabbajack puts in place of the paths of the author's machine
+const MAGIC: [(&str, Root, Separator); 9] = [
+    (wabbajack_consts::GAME_PATH_MAGIC_BACK, Root::Game, Separator::Back),
+    (wabbajack_consts::GAME_PATH_MAGIC_DOUBLE_BACK, Root::Game, Separator::DoubleBack),
+    (wabbajack_consts::GAME_PATH_MAGIC_FORWARD, Root::Game, Separator::Forward),
+    (wabbajack_consts::MO2_PATH_MAGIC_BACK, Root::Mo2, Separator::Back),
+    (wabbajack_consts::MO2_PATH_MAGIC_DOUBLE_BACK, Root::Mo2, Separator::DoubleBack),
+    (wabbajack_consts::MO2_PATH_MAGIC_FORWARD, Root::Mo2, Separator::Forward),
+    (wabbajack_consts::DOWNLOAD_PATH_MAGIC_BACK, Root::Downloads, Separator::Back),
+    (wabbajack_consts::DOWNLOAD_PATH_MAGIC_DOUBLE_BACK, Root::Downloads, Separator::DoubleBack),
+    (wabbajack_consts::DOWNLOAD_PATH_MAGIC_FORWARD, Root::Downloads, Separator::Forward),
+];
+
+/// percent-encoding keeping what urls and paths leave as it is (`/` and the `:` of the drive letter included)
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            true => (byte as char).to_string(),
+            false => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// the path as the windows tools reading remapped files see it (MO2 running under wine most of the time) - absolute, with
+/// wine's `Z:` drive standing for the unix root
+#[cfg(unix)]
+fn windows_path(path: &Path) -> String {
+    crate::post_install_fixup::mod_organizer::wine_path(path)
+        .unwrap_or_else(|_| format!("Z:{}", path.to_string_lossy()))
+        .trim_end_matches('/')
+        .replace('/', "\\")
+        .pipe(|path| match path.ends_with(':') {
+            true => format!("{path}\\"),
+            false => path,
+        })
+}
+
+/// native paths are windows paths already, only the verbatim prefix canonicalization adds is left out
+#[cfg(not(unix))]
+fn windows_path(path: &Path) -> String {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_owned())
+        .to_string_lossy()
+        .trim_start_matches(r"\\?\")
+        .to_string()
+}
+
+/// replaces the magic strings (plain and url-encoded) with the windows paths of the roots, spelled the way the matched
+/// magic string asks for
+fn remap(data: &str, root: impl Fn(Root) -> String) -> String {
+    MAGIC
+        .iter()
+        .flat_map(|(magic, magic_root, separator)| {
+            let path = separator.spell(&root(*magic_root));
+            let encoded = url_encode(magic);
+            // hex digits of the escapes can be either case
+            let encoded_lowercase = encoded
+                .replace("%7B", "%7b")
+                .replace("%7C", "%7c")
+                .replace("%7D", "%7d");
+            [
+                (magic.to_string(), path.clone()),
+                (encoded, url_encode(&path)),
+                (encoded_lowercase, url_encode(&path)),
+            ]
+        })
+        .fold(data.to_string(), |data, (magic, path)| data.replace(&magic, &path))
+}
+
 impl RemappingContext {
     pub fn remap_file_contents(&self, data: &str) -> String {
-        self.pipe(
-            |Self {
-                 game_folder,
-                 output_directory: install_directory,
-                 downloads_directory,
-             }| {
-                fn trim_relative_path_start(path: &str) -> String {
-                    path.trim_start_matches(r#".\\"#)
-                        .trim_start_matches(r#".\"#)
-                        .trim_start_matches(r#"./"#)
-                        .to_string()
-                }
-                let game_folder = |delimiter| {
-                    game_folder
-                        .as_path()
-                        .join_with_delimiter(delimiter)
-                        .pipe_as_ref(trim_relative_path_start)
-                };
-                let install_directory = |delimiter| {
-                    install_directory
-                        .as_path()
-                        .join_with_delimiter(delimiter)
-                        .pipe_as_ref(trim_relative_path_start)
-                };
-                let downloads_directory = |delimiter| {
-                    downloads_directory
-                        .as_path()
-                        .join_with_delimiter(delimiter)
-                        .pipe_as_ref(trim_relative_path_start)
-                };
-
-                const BACK: &str = r#"\"#;
-                const DOUBLE_BACK: &str = r#"\\"#;
-                const FORWARD: &str = r#"/"#;
-                data.replace(wabbajack_consts::GAME_PATH_MAGIC_BACK, game_folder(BACK).as_str())
-                    .replace(wabbajack_consts::GAME_PATH_MAGIC_DOUBLE_BACK, game_folder(DOUBLE_BACK).as_str())
-                    .replace(wabbajack_consts::GAME_PATH_MAGIC_FORWARD, game_folder(FORWARD).as_str())
-                    .replace(wabbajack_consts::MO2_PATH_MAGIC_BACK, install_directory(BACK).as_str())
-                    .replace(wabbajack_consts::MO2_PATH_MAGIC_DOUBLE_BACK, install_directory(DOUBLE_BACK).as_str())
-                    .replace(wabbajack_consts::MO2_PATH_MAGIC_FORWARD, install_directory(FORWARD).as_str())
-                    .replace(wabbajack_consts::DOWNLOAD_PATH_MAGIC_BACK, downloads_directory(BACK).as_str())
-                    .replace(wabbajack_consts::DOWNLOAD_PATH_MAGIC_DOUBLE_BACK, downloads_directory(DOUBLE_BACK).as_str())
-                    .replace(wabbajack_consts::DOWNLOAD_PATH_MAGIC_FORWARD, downloads_directory(FORWARD).as_str())
-                    .tap(|new| tracing::trace!("remapped:\n{data}-->\n{new}"))
-            },
-        )
+        let Self {
+            game_folder,
+            output_directory,
+            downloads_directory,
+        } = self;
+        remap(data, |root| {
+            match root {
+                Root::Game => game_folder,
+                Root::Mo2 => output_directory,
+                Root::Downloads => downloads_directory,
+            }
+            .pipe(|path| windows_path(path.as_ref()))
+        })
+        .tap(|new| tracing::trace!("remapped:\n{data}-->\n{new}"))
     }
 }
 
@@ -129,12 +183,7 @@ impl RemappedInlineFileHandler {
             wabbajack_file,
         } = self;
         wabbajack_file
-            .get_source_data(source_data_id)
-            .and_then(|source_data| {
-                source_data
-                    .open_file_read()
-                    .map(|(_, file)| (source_data, file))
-            })
+            .open_source_data(source_data_id)
             .context("reading the file for remapping")
             .and_then(|(_guard, mut handle)| {
                 String::new().pipe(|mut out| {
@@ -151,10 +200,87 @@ impl RemappedInlineFileHandler {
                     .output_directory
                     .case_insensitive()
                     .join_case_insensitive(to.clone())
-                    .and_then(|to| to.as_path().open_file_write())
-                    .and_then(|(_, mut file)| {
-                        std::io::copy(&mut tracing::Span::current().wrap_read(size, std::io::Cursor::new(output)), &mut file).context("writing remapped file")
+                    .and_then(|to| {
+                        to.as_path().write_atomically(|file| {
+                            std::io::copy(&mut tracing::Span::current().wrap_read(size, std::io::Cursor::new(output)), file).context("writing remapped file")
+                        })
                     })
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = r"Z:\home\deck\.steam\steam\steamapps\common\Skyrim Special Edition";
+    const MO2: &str = r"Z:\home\deck\Modlists\Nordic Souls";
+    const DOWNLOADS: &str = r"Z:\home\deck\Modlists\downloads";
+
+    fn remap_test(data: &str) -> String {
+        remap(data, |root| {
+            match root {
+                Root::Game => GAME,
+                Root::Mo2 => MO2,
+                Root::Downloads => DOWNLOADS,
+            }
+            .to_string()
+        })
+    }
+
+    #[test_log::test]
+    fn test_remapping_table() {
+        [
+            (
+                "ModOrganizer.ini, game path",
+                r"gamePath=@ByteArray({--||GAME_PATH_MAGIC_DOUBLE_BACK||--})",
+                r"gamePath=@ByteArray(Z:\\home\\deck\\.steam\\steam\\steamapps\\common\\Skyrim Special Edition)",
+            ),
+            (
+                "ModOrganizer.ini, downloads",
+                r"download_directory={--||DOWNLOAD_PATH_MAGIC_FORWARD||--}",
+                r"download_directory=Z:/home/deck/Modlists/downloads",
+            ),
+            (
+                "ModOrganizer.ini, tool executable",
+                "1\\binary={--||MO2_PATH_MAGIC_FORWARD||--}/tools/SSEEdit/SSEEdit64.exe\n1\\workingDirectory={--||MO2_PATH_MAGIC_FORWARD||--}/tools/SSEEdit",
+                "1\\binary=Z:/home/deck/Modlists/Nordic Souls/tools/SSEEdit/SSEEdit64.exe\n1\\workingDirectory=Z:/home/deck/Modlists/Nordic \
+                 Souls/tools/SSEEdit",
+            ),
+            (
+                "ModOrganizer.ini, tool arguments",
+                r#"2\arguments="-o:\"{--||MO2_PATH_MAGIC_BACK||--}\mods\DynDOLOD Output\"""#,
+                r#"2\arguments="-o:\"Z:\home\deck\Modlists\Nordic Souls\mods\DynDOLOD Output\"""#,
+            ),
+            (
+                "SSEEdit.ini, data path",
+                r"DataPath={--||GAME_PATH_MAGIC_BACK||--}\Data",
+                r"DataPath=Z:\home\deck\.steam\steam\steamapps\common\Skyrim Special Edition\Data",
+            ),
+            (
+                "BodySlide config.xml",
+                r#"<GameDataPath>{--||GAME_PATH_MAGIC_BACK||--}\Data\</GameDataPath>"#,
+                r#"<GameDataPath>Z:\home\deck\.steam\steam\steamapps\common\Skyrim Special Edition\Data\</GameDataPath>"#,
+            ),
+            (
+                "url-encoded, both cases",
+                "file:///%7B--%7C%7CMO2_PATH_MAGIC_FORWARD%7C%7C--%7D/profiles and %7b--%7c%7cDOWNLOAD_PATH_MAGIC_FORWARD%7c%7c--%7d",
+                "file:///Z:/home/deck/Modlists/Nordic%20Souls/profiles and Z:/home/deck/Modlists/downloads",
+            ),
+            ("untouched", r"C:\Games\Skyrim\Data", r"C:\Games\Skyrim\Data"),
+        ]
+        .into_iter()
+        .for_each(|(name, data, expected)| assert_eq!(remap_test(data), expected, "{name}"));
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_paths_are_spelled_for_wine() {
+        assert_eq!(
+            windows_path(Path::new("/home/deck/Modlists/Nordic Souls/")),
+            r"Z:\home\deck\Modlists\Nordic Souls"
+        );
+        assert_eq!(windows_path(Path::new("/")), r"Z:\");
+        assert!(windows_path(Path::new("relative/install")).starts_with("Z:\\"));
+    }
+}
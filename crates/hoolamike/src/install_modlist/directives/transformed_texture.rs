@@ -5,17 +5,17 @@ use {
         progress_bars_v2::IndicatifWrapIoExt,
         utils::ExistingPathRead,
     },
+    dds_recompression_texconv_wine::{BatchSettings, TexconvHost},
     preheat_archive_hash_paths::PreheatedArchiveHashPaths,
     std::io::{Read, Write},
     tracing::warn,
     typed_path::{Utf8PlatformPathBuf},
-    wine_wrapper::wine_context::{Initialized, WineContext},
 };
 
 #[derive(Debug, Clone)]
 pub struct TexconvWineState {
     pub texconv_path: PathBuf,
-    pub wine_prefix_state: Arc<Initialized<WineContext>>,
+    pub host: Arc<TexconvHost>,
 }
 
 #[derive(Clone, derivative::Derivative)]
@@ -25,6 +25,8 @@ pub struct TransformedTextureHandler {
     #[derivative(Debug = "ignore")]
     pub download_summary: DownloadSummary,
     pub texconv_wine_state: Option<TexconvWineState>,
+    /// see `advanced.texture_recompression_fallback`
+    pub recompression_fallback: bool,
 }
 
 #[allow(dead_code)]
@@ -39,15 +41,226 @@ impl std::io::Result<u64> {
     }
 }
 
+mod dds_header;
 // #[cfg(feature = "dds_recompression")]
 mod dds_recompression;
 mod dds_recompression_directx_tex;
-mod dds_recompression_texconv_wine;
+pub mod dds_recompression_texconv_wine;
 
 #[cfg(feature = "intel_tex")]
 mod dds_recompression_intel_tex;
 
+/// a single texture isn't worth staging a batch for
+const MIN_BATCH_SIZE: usize = 2;
+/// batches of the same settings still spread across the cpu pool
+const MAX_BATCH_SIZE: usize = 256;
+
+fn batch_settings(
+    TransformedTextureDirective {
+        image_state: ImageState {
+            format,
+            height,
+            mip_levels,
+            width,
+            ..
+        },
+        ..
+    }: &TransformedTextureDirective,
+) -> BatchSettings {
+    BatchSettings {
+        format: *format,
+        width: *width,
+        height: *height,
+        mip_levels: *mip_levels,
+    }
+}
+
+/// batches of at most [MAX_BATCH_SIZE] textures sharing their [BatchSettings]
+fn plan_batches(directives: Vec<TransformedTextureDirective>) -> Vec<(BatchSettings, Vec<TransformedTextureDirective>)> {
+    directives
+        .into_iter()
+        .fold(BTreeMap::<_, Vec<_>>::new(), |mut groups, directive| {
+            groups
+                .entry(batch_settings(&directive))
+                .or_default()
+                .push(directive);
+            groups
+        })
+        .into_iter()
+        .flat_map(|(settings, group)| {
+            group
+                .chunks(MAX_BATCH_SIZE)
+                .map(|batch| (settings, batch.to_vec()))
+                .collect_vec()
+        })
+        .collect()
+}
+
+/// textures converted by an earlier run never match the hash wabbajack expects (it's the hash of its own conversion), so an
+/// output which has the expected size and whose DDS header says it's exactly what the directive asks for is taken as done
+pub fn already_converted(
+    output_path: &Path,
+    TransformedTextureDirective {
+        size,
+        image_state: ImageState {
+            format,
+            height,
+            mip_levels,
+            width,
+            ..
+        },
+        ..
+    }: &TransformedTextureDirective,
+) -> Result<()> {
+    std::fs::File::open(output_path)
+        .context("opening existing output")
+        .and_then(|mut file| {
+            file.metadata()
+                .context("reading metadata")
+                .and_then(|metadata| {
+                    metadata
+                        .len()
+                        .eq(size)
+                        .then_some(())
+                        .with_context(|| format!("expected [{size} bytes], found [{} bytes]", metadata.len()))
+                })
+                .and_then(|_| dds_header::DdsHeader::read(&mut file))
+        })
+        .and_then(|header| {
+            let expected = dds_header::DdsHeader {
+                width: *width,
+                height: *height,
+                mip_levels: *mip_levels,
+                dxgi_format: Some(*format as u32),
+            };
+            header
+                .eq(&expected)
+                .then_some(())
+                .with_context(|| format!("expected {expected:?}, found {header:?}"))
+        })
+        .with_context(|| format!("checking whether [{}] is converted already", output_path.display()))
+}
+
 impl TransformedTextureHandler {
+    fn output_path(&self, to: &CaseInsensitivePathBuf) -> Result<Utf8PlatformPathBuf> {
+        self.output_directory
+            .clone()
+            .case_insensitive()
+            .join_case_insensitive(to.clone())
+            .context("validating output path")
+            .map(|output_path| output_path.as_path().to_owned())
+    }
+
+    /// with texconv set up, every batch of textures sharing their settings is converted by a single texconv process instead of
+    /// one per texture. textures a batch didn't produce correctly are retried one by one, so that a bad one doesn't fail the rest
+    #[instrument(skip_all, fields(directives=%directives.len()))]
+    pub fn handle_batched(self, directives: Vec<TransformedTextureDirective>, preheated: Arc<PreheatedArchiveHashPaths>) -> Vec<Result<u64>> {
+        let handle_one = |directive: TransformedTextureDirective| {
+            self.clone()
+                .handle(directive.clone(), preheated.clone())
+                .with_context(|| format!("handling directive: {directive:#?}"))
+        };
+        match self.texconv_wine_state.as_ref() {
+            None => directives.into_par_iter().map(handle_one).collect(),
+            Some(texconv_wine_state) => plan_batches(directives)
+                .into_par_iter()
+                .flat_map_iter(|(settings, batch)| match batch.len() < MIN_BATCH_SIZE {
+                    true => batch.into_iter().map(&handle_one).collect_vec(),
+                    false => self
+                        .convert_batch(settings, batch, texconv_wine_state, &preheated)
+                        .into_iter()
+                        .map(|(directive, converted)| {
+                            converted.or_else(|reason| {
+                                warn!(
+                                    "texture [{}] did not come out of its batch right, converting it on its own:\n{reason:?}",
+                                    directive.to
+                                );
+                                handle_one(directive)
+                            })
+                        })
+                        .collect_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    /// runs texconv once for the whole batch, then checks and moves every output into place on its own
+    fn convert_batch(
+        &self,
+        settings: BatchSettings,
+        batch: Vec<TransformedTextureDirective>,
+        TexconvWineState { texconv_path, host }: &TexconvWineState,
+        preheated: &PreheatedArchiveHashPaths,
+    ) -> Vec<(TransformedTextureDirective, Result<u64>)> {
+        let outputs = batch
+            .iter()
+            .map(|directive| {
+                self.download_summary
+                    .resolve_archive_path(&directive.archive_hash_path)
+                    .and_then(|path| preheated.get_archive(path))
+                    .and_then(|source| source.exists())
+                    .and_then(|source| {
+                        directive
+                            .to
+                            .extension()
+                            .map(|extension| (source, extension.to_string()))
+                            .with_context(|| format!("no extension on [{}]", directive.to))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()
+            .and_then(|sources| {
+                sources
+                    .iter()
+                    .map(|(source, extension)| (source.as_os_path(), extension.as_str()))
+                    .collect_vec()
+                    .pipe(|sources| dds_recompression_texconv_wine::resize_dds_batch(&sources, settings, texconv_path, host))
+            });
+        // verification is a bonus, the size check below is what the directive demands
+        let described = outputs
+            .as_ref()
+            .ok()
+            .zip(dds_recompression_texconv_wine::texdiag_next_to(texconv_path))
+            .and_then(|(outputs, texdiag)| {
+                outputs
+                    .describe(batch.len(), &texdiag, host)
+                    .tap_err(|reason| warn!("could not verify the batch outputs with texdiag, relying on their sizes:\n{reason:?}"))
+                    .ok()
+            });
+        batch
+            .into_iter()
+            .enumerate()
+            .map(|(position, directive)| {
+                let converted = match outputs.as_ref() {
+                    Ok(outputs) => described
+                        .as_ref()
+                        .and_then(|described| described.get(&position))
+                        .map_or(Ok(()), |info| settings.check(info))
+                        .context("verifying the output with texdiag")
+                        .and_then(|()| self.place_batch_output(&outputs.output(position), &directive)),
+                    Err(reason) => Err(anyhow::anyhow!("converting the batch failed:\n{reason:?}")),
+                };
+                (directive, converted)
+            })
+            .collect()
+    }
+
+    /// the output has to have the expected size, just like one converted on its own
+    fn place_batch_output(&self, output: &Path, TransformedTextureDirective { size, to, .. }: &TransformedTextureDirective) -> Result<u64> {
+        std::fs::metadata(output)
+            .with_context(|| format!("texconv did not write [{}]", output.display()))
+            .and_then(|metadata| {
+                metadata
+                    .len()
+                    .eq(size)
+                    .then_some(*size)
+                    .with_context(|| format!("expected output size to be [{size} bytes], but got [{} bytes]", metadata.len()))
+            })
+            .and_then(|size| {
+                self.output_path(to)
+                    .and_then(|destination| crate::temp_directory::move_into_place(output, Path::new(destination.as_str())).map(|_| size))
+            })
+    }
+
     #[instrument(skip(self, preheated))]
     pub fn handle(
         self,
@@ -69,14 +282,7 @@ impl TransformedTextureHandler {
     ) -> Result<u64> {
         let handle = tracing::Span::current();
         // let _image_dds_format = supported_image_format(format).context("checking for format support")?;
-        let output_path = self
-            .output_directory
-            .clone()
-            .case_insensitive()
-            .join_case_insensitive(to_path.clone())
-            .context("validating output path")?
-            .as_path()
-            .to_owned();
+        let output_path = self.output_path(&to_path)?;
         let source_file = self
             .download_summary
             .resolve_archive_path(&archive_hash_path)
@@ -95,52 +301,51 @@ impl TransformedTextureHandler {
                                     self.texconv_wine_state
                                         .as_ref()
                                         .context("texconv+wine not set up, gonna try slow methods")
-                                        .and_then(
-                                            |TexconvWineState {
-                                                 texconv_path,
-                                                 wine_prefix_state,
-                                             }| {
-                                                dds_recompression_texconv_wine::resize_dds(
-                                                    &mut reader,
-                                                    width,
-                                                    height,
-                                                    format,
-                                                    mip_levels,
-                                                    &mut writer,
-                                                    texconv_path,
-                                                    wine_prefix_state.as_ref(),
-                                                    to_path
-                                                        .clone()
-                                                        .extension()
-                                                        .with_context(|| format!("no extension on [{to_path}]"))?,
-                                                )
-                                                .with_context(|| format!("tried because:\n{reason:?}"))
-                                            },
-                                        )
-                                })
-                                .pipe(|r| {
-                                    #[cfg(feature = "intel_tex")]
-                                    {
-                                        r.or_else(|e| {
-                                            dds_recompression_intel_tex::resize_dds(&mut reader, width, height, format, mip_levels, &mut writer)
-                                                .context("resizing using intel_tex")
-                                                .map(|_| size)
-                                                .with_context(|| format!("tried because:\n{e:?}"))
+                                        .and_then(|TexconvWineState { texconv_path, host }| {
+                                            dds_recompression_texconv_wine::resize_dds(
+                                                &mut reader,
+                                                width,
+                                                height,
+                                                format,
+                                                mip_levels,
+                                                &mut writer,
+                                                texconv_path,
+                                                host.as_ref(),
+                                                to_path
+                                                    .clone()
+                                                    .extension()
+                                                    .with_context(|| format!("no extension on [{to_path}]"))?,
+                                            )
+                                            .with_context(|| format!("tried because:\n{reason:?}"))
                                         })
-                                    }
-                                    #[cfg(not(feature = "intel_tex"))]
-                                    {
-                                        r
-                                    }
                                 })
-                                .or_else(|e| {
-                                    // error!(
-                                    //     "other texture recompression methods (fast) failed,\
-                                    //     falling back to microsoft directxtex (slow)\nreason:\n{e:?}\n",
-                                    // );
-                                    dds_recompression_directx_tex::resize_dds(&mut reader, width, height, format, mip_levels, &mut writer)
-                                        .context("resizing using directx_tex")
-                                        .with_context(|| format!("tried because:\n{e:?}"))
+                                .or_else(|reason| match self.recompression_fallback {
+                                    false => Err(reason).context("recompressing in process is turned off (advanced.texture_recompression_fallback)"),
+                                    true => Err(reason)
+                                        .pipe(|r| {
+                                            #[cfg(feature = "intel_tex")]
+                                            {
+                                                r.or_else(|e| {
+                                                    dds_recompression_intel_tex::resize_dds(&mut reader, width, height, format, mip_levels, &mut writer)
+                                                        .context("resizing using intel_tex")
+                                                        .map(|_| size)
+                                                        .with_context(|| format!("tried because:\n{e:?}"))
+                                                })
+                                            }
+                                            #[cfg(not(feature = "intel_tex"))]
+                                            {
+                                                r
+                                            }
+                                        })
+                                        .or_else(|e| {
+                                            // error!(
+                                            //     "other texture recompression methods (fast) failed,\
+                                            //     falling back to microsoft directxtex (slow)\nreason:\n{e:?}\n",
+                                            // );
+                                            dds_recompression_directx_tex::resize_dds(&mut reader, width, height, format, mip_levels, &mut writer)
+                                                .context("resizing using directx_tex")
+                                                .with_context(|| format!("tried because:\n{e:?}"))
+                                        }),
                                 })
                                 .and_then(|wrote| {
                                     wrote
@@ -160,9 +365,8 @@ impl TransformedTextureHandler {
                     .exists()
                     .and_then(|source_file| source_file.open_file_read())
                     .and_then(|(source_path, mut final_source)| {
-                        output_path.open_file_write()
-                        .and_then(|(_, mut output_file)| {
-                            perform_copy(&mut final_source, &mut output_file, output_path.clone())
+                        output_path.write_atomically(|output_file| {
+                            perform_copy(&mut final_source, output_file, output_path.clone())
                                 // .or_else(|reason| {
                                 //     let _span =
                                 //         tracing::error_span!("could not resize texture, copying the original", reason = %format!("{reason:?}")).entered();
@@ -187,3 +391,67 @@ impl TransformedTextureHandler {
             .map(|_| size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::modlist_json::image_format::DXGIFormat, serde_json::json};
+
+    fn texture(to: &str, format: &str, size: u32) -> TransformedTextureDirective {
+        serde_json::from_value(json!({
+            "Hash": "AAAAAAAAAAA=",
+            "Size": 1,
+            "To": to,
+            "ArchiveHashPath": ["c291cmNlAAA=", "texture.dds"],
+            "ImageState": {
+                "Format": format,
+                "Height": size,
+                "MipLevels": 1,
+                "PerceptualHash": "AAAA",
+                "Width": size,
+            },
+        }))
+        .expect("valid directive")
+    }
+
+    #[test_log::test]
+    fn test_batches_share_their_settings() {
+        let directives = (0..MAX_BATCH_SIZE + 1)
+            .map(|idx| texture(&format!("textures/{idx}.dds"), "BC7_UNORM", 512))
+            .chain([
+                texture("textures/small.dds", "BC7_UNORM", 256),
+                texture("textures/other-format.dds", "BC1_UNORM", 512),
+            ])
+            .collect_vec();
+        let batches = plan_batches(directives)
+            .into_iter()
+            .map(|(settings, batch)| (settings.format, settings.width, batch.len()))
+            .collect_vec();
+        assert_eq!(
+            batches,
+            [
+                (DXGIFormat::BC1_UNORM, 512, 1),
+                (DXGIFormat::BC7_UNORM, 256, 1),
+                (DXGIFormat::BC7_UNORM, 512, MAX_BATCH_SIZE),
+                (DXGIFormat::BC7_UNORM, 512, 1),
+            ]
+        );
+    }
+
+    #[test_log::test]
+    fn test_texdiag_info_is_checked_against_the_settings() {
+        let settings = batch_settings(&texture("textures/a.dds", "BC7_UNORM", 512));
+        let info = |format: &str, width, mip_levels| texconv_wrapper::TexdiagInfo {
+            file: "Z:\\tmp\\outputs\\0.dds".to_string(),
+            width,
+            height: 512,
+            depth: 1,
+            mip_levels,
+            array_size: 1,
+            format: format.to_string(),
+        };
+        assert!(settings.check(&info("BC7_UNORM", 512, 1)).is_ok());
+        assert!(settings.check(&info("BC7_UNORM_SRGB", 512, 1)).is_err());
+        assert!(settings.check(&info("BC7_UNORM", 256, 1)).is_err());
+        assert!(settings.check(&info("BC7_UNORM", 512, 10)).is_err());
+    }
+}
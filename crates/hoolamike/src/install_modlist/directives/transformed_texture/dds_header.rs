@@ -0,0 +1,184 @@
+//! just enough of a DDS header to tell what's inside without decoding the texture - the 124 byte `DDS_HEADER` after the
+//! magic, followed by the 20 byte `DDS_HEADER_DXT10` when the pixel format says `DX10`
+
+use {
+    crate::modlist_json::image_format::DXGIFormat,
+    anyhow::{Context, Result},
+    std::io::Read,
+};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 4 + 124;
+const DX10_HEADER_SIZE: usize = 20;
+/// `dwMipMapCount` is only valid with this flag
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DdsHeader {
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    /// value of the [DXGIFormat], [None] for legacy pixel formats with no DXGI counterpart
+    pub dxgi_format: Option<u32>,
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// the formats texconv and wabbajack write without the DX10 extension
+fn legacy_format(flags: u32, four_cc: &[u8], bit_count: u32, masks: [u32; 4]) -> Option<DXGIFormat> {
+    match (flags & DDPF_FOURCC != 0, flags & DDPF_RGB != 0) {
+        (true, _) => match four_cc {
+            b"DXT1" => Some(DXGIFormat::BC1_UNORM),
+            b"DXT2" | b"DXT3" => Some(DXGIFormat::BC2_UNORM),
+            b"DXT4" | b"DXT5" => Some(DXGIFormat::BC3_UNORM),
+            b"ATI1" | b"BC4U" => Some(DXGIFormat::BC4_UNORM),
+            b"BC4S" => Some(DXGIFormat::BC4_SNORM),
+            b"ATI2" | b"BC5U" => Some(DXGIFormat::BC5_UNORM),
+            b"BC5S" => Some(DXGIFormat::BC5_SNORM),
+            _ => None,
+        },
+        (false, true) => match (bit_count, masks, flags & DDPF_ALPHAPIXELS != 0) {
+            (32, [0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000], true) => Some(DXGIFormat::B8G8R8A8_UNORM),
+            (32, [0x000000ff, 0x0000ff00, 0x00ff0000, 0xff000000], true) => Some(DXGIFormat::R8G8B8A8_UNORM),
+            (32, [0x00ff0000, 0x0000ff00, 0x000000ff, _], false) => Some(DXGIFormat::B8G8R8X8_UNORM),
+            _ => None,
+        },
+        (false, false) => None,
+    }
+}
+
+impl DdsHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        anyhow::ensure!(bytes.len() >= HEADER_SIZE, "[{} bytes] is too short for a DDS header", bytes.len());
+        anyhow::ensure!(bytes.starts_with(MAGIC), "not a DDS file");
+        anyhow::ensure!(u32_at(bytes, 4) == 124, "unexpected header size [{}]", u32_at(bytes, 4));
+        let (flags, pixel_format_flags, four_cc) = (u32_at(bytes, 8), u32_at(bytes, 80), &bytes[84..88]);
+        let dxgi_format = match pixel_format_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+            true => bytes
+                .get(HEADER_SIZE..HEADER_SIZE + DX10_HEADER_SIZE)
+                .context("DX10 header is missing")
+                .map(|dx10| Some(u32_at(dx10, 0)))?,
+            false => legacy_format(
+                pixel_format_flags,
+                four_cc,
+                u32_at(bytes, 88),
+                [u32_at(bytes, 92), u32_at(bytes, 96), u32_at(bytes, 100), u32_at(bytes, 104)],
+            )
+            .map(|format| format as u32),
+        };
+        Ok(Self {
+            height: u32_at(bytes, 12),
+            width: u32_at(bytes, 16),
+            mip_levels: match flags & DDSD_MIPMAPCOUNT != 0 {
+                true => u32_at(bytes, 28).max(1),
+                false => 1,
+            },
+            dxgi_format,
+        })
+    }
+
+    /// reads the headers only, the texture data is never touched
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + DX10_HEADER_SIZE);
+        reader
+            .take((HEADER_SIZE + DX10_HEADER_SIZE) as u64)
+            .read_to_end(&mut bytes)
+            .context("reading DDS header")
+            .and_then(|_| Self::parse(&bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(flags: u32, (width, height, mips): (u32, u32, u32), pixel_format: (u32, &[u8; 4], u32, [u32; 4]), dx10_format: Option<u32>) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(MAGIC);
+        let mut put = |offset: usize, value: u32| bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        let (pixel_format_flags, four_cc, bit_count, masks) = pixel_format;
+        put(4, 124);
+        put(8, flags);
+        put(12, height);
+        put(16, width);
+        put(28, mips);
+        put(76, 32);
+        put(80, pixel_format_flags);
+        put(88, bit_count);
+        masks
+            .iter()
+            .enumerate()
+            .for_each(|(idx, mask)| put(92 + idx * 4, *mask));
+        bytes[84..88].copy_from_slice(four_cc);
+        bytes.extend(
+            dx10_format
+                .into_iter()
+                .flat_map(|format| [format, 3, 0, 1, 0].into_iter().flat_map(u32::to_le_bytes)),
+        );
+        // texture data
+        bytes.extend([0xAB; 64]);
+        bytes
+    }
+
+    #[test_log::test]
+    fn test_legacy_headers() -> Result<()> {
+        assert_eq!(
+            DdsHeader::read(&mut header(DDSD_MIPMAPCOUNT, (1024, 512, 11), (DDPF_FOURCC, b"DXT5", 0, [0; 4]), None).as_slice())?,
+            DdsHeader {
+                width: 1024,
+                height: 512,
+                mip_levels: 11,
+                dxgi_format: Some(DXGIFormat::BC3_UNORM as u32),
+            }
+        );
+        // no mipmap count flag - whatever is in the field doesn't count
+        assert_eq!(
+            DdsHeader::parse(&header(
+                0,
+                (256, 256, 9),
+                (DDPF_RGB | DDPF_ALPHAPIXELS, &[0; 4], 32, [0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000]),
+                None
+            ))?,
+            DdsHeader {
+                width: 256,
+                height: 256,
+                mip_levels: 1,
+                dxgi_format: Some(DXGIFormat::B8G8R8A8_UNORM as u32),
+            }
+        );
+        assert_eq!(
+            DdsHeader::parse(&header(DDSD_MIPMAPCOUNT, (4, 4, 1), (DDPF_FOURCC, b"YUY2", 0, [0; 4]), None))?.dxgi_format,
+            None
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_dx10_headers() -> Result<()> {
+        assert_eq!(
+            DdsHeader::parse(&header(
+                DDSD_MIPMAPCOUNT,
+                (2048, 2048, 12),
+                (DDPF_FOURCC, b"DX10", 0, [0; 4]),
+                Some(DXGIFormat::BC7_UNORM as u32)
+            ))?,
+            DdsHeader {
+                width: 2048,
+                height: 2048,
+                mip_levels: 12,
+                dxgi_format: Some(DXGIFormat::BC7_UNORM as u32),
+            }
+        );
+        assert!(
+            DdsHeader::parse(&header(DDSD_MIPMAPCOUNT, (2048, 2048, 12), (DDPF_FOURCC, b"DX10", 0, [0; 4]), None)[..HEADER_SIZE]).is_err(),
+            "DX10 extension is cut off"
+        );
+        assert!(DdsHeader::parse(b"PNG not a dds at all").is_err());
+        Ok(())
+    }
+}
@@ -2,14 +2,19 @@ use {
     crate::modlist_json::image_format::DXGIFormat,
     anyhow::{Context, Result},
     ddsfile::{AlphaMode, D3D10ResourceDimension, Dds, DxgiFormat},
-    image::{GenericImageView, ImageBuffer, Pixel},
-    intel_tex::{bc1, bc3, bc6h, bc7},
+    image::{GenericImageView, RgbaImage, imageops::FilterType},
+    intel_tex::{bc1, bc3, bc4, bc5, bc6h, bc7},
+    itertools::Itertools,
+    mip_chain::MipFilter,
     std::io::{BufReader, Read, Write},
     tap::{Pipe, Tap},
     tracing::{info, warn},
 };
 
+mod mip_chain;
+
 #[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum OutputFormat {
     BC1_TYPELESS,
     BC1_UNORM,
@@ -17,12 +22,37 @@ enum OutputFormat {
     BC3_TYPELESS,
     BC3_UNORM,
     BC3_UNORM_SRGB,
+    BC4_TYPELESS,
+    BC4_UNORM,
+    BC5_TYPELESS,
+    BC5_UNORM,
     BC6H_TYPELESS,
     BC6H_UF16,
     BC6H_SF16,
     BC7_TYPELESS,
     BC7_UNORM,
     BC7_UNORM_SRGB,
+    R8G8B8A8_TYPELESS,
+    R8G8B8A8_UNORM,
+    R8G8B8A8_UNORM_SRGB,
+    B8G8R8A8_TYPELESS,
+    B8G8R8A8_UNORM,
+    B8G8R8A8_UNORM_SRGB,
+}
+
+/// how the pixels of a format are put together, the srgb and typeless variants only differ in the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Bc1,
+    Bc3,
+    /// red channel only
+    Bc4,
+    /// red and green channels, normal maps
+    Bc5,
+    Bc6h,
+    Bc7,
+    Rgba8,
+    Bgra8,
 }
 
 impl OutputFormat {
@@ -34,15 +64,66 @@ impl OutputFormat {
             DXGIFormat::BC3_TYPELESS => Some(Self::BC3_TYPELESS),
             DXGIFormat::BC3_UNORM => Some(Self::BC3_UNORM),
             DXGIFormat::BC3_UNORM_SRGB => Some(Self::BC3_UNORM_SRGB),
+            DXGIFormat::BC4_TYPELESS => Some(Self::BC4_TYPELESS),
+            DXGIFormat::BC4_UNORM => Some(Self::BC4_UNORM),
+            DXGIFormat::BC5_TYPELESS => Some(Self::BC5_TYPELESS),
+            DXGIFormat::BC5_UNORM => Some(Self::BC5_UNORM),
             DXGIFormat::BC6H_TYPELESS => Some(Self::BC6H_TYPELESS),
             DXGIFormat::BC6H_UF16 => Some(Self::BC6H_UF16),
             DXGIFormat::BC6H_SF16 => Some(Self::BC6H_SF16),
             DXGIFormat::BC7_TYPELESS => Some(Self::BC7_TYPELESS),
             DXGIFormat::BC7_UNORM => Some(Self::BC7_UNORM),
             DXGIFormat::BC7_UNORM_SRGB => Some(Self::BC7_UNORM_SRGB),
+            DXGIFormat::R8G8B8A8_TYPELESS => Some(Self::R8G8B8A8_TYPELESS),
+            DXGIFormat::R8G8B8A8_UNORM => Some(Self::R8G8B8A8_UNORM),
+            DXGIFormat::R8G8B8A8_UNORM_SRGB => Some(Self::R8G8B8A8_UNORM_SRGB),
+            DXGIFormat::B8G8R8A8_TYPELESS => Some(Self::B8G8R8A8_TYPELESS),
+            DXGIFormat::B8G8R8A8_UNORM => Some(Self::B8G8R8A8_UNORM),
+            DXGIFormat::B8G8R8A8_UNORM_SRGB => Some(Self::B8G8R8A8_UNORM_SRGB),
             _ => None,
         }
     }
+
+    /// goes into the DX10 header as it is - that's where the game learns a texture is srgb
+    fn dxgi_format(self) -> DxgiFormat {
+        match self {
+            Self::BC1_TYPELESS => DxgiFormat::BC1_Typeless,
+            Self::BC1_UNORM => DxgiFormat::BC1_UNorm,
+            Self::BC1_UNORM_SRGB => DxgiFormat::BC1_UNorm_sRGB,
+            Self::BC3_TYPELESS => DxgiFormat::BC3_Typeless,
+            Self::BC3_UNORM => DxgiFormat::BC3_UNorm,
+            Self::BC3_UNORM_SRGB => DxgiFormat::BC3_UNorm_sRGB,
+            Self::BC4_TYPELESS => DxgiFormat::BC4_Typeless,
+            Self::BC4_UNORM => DxgiFormat::BC4_UNorm,
+            Self::BC5_TYPELESS => DxgiFormat::BC5_Typeless,
+            Self::BC5_UNORM => DxgiFormat::BC5_UNorm,
+            Self::BC6H_TYPELESS => DxgiFormat::BC6H_Typeless,
+            Self::BC6H_UF16 => DxgiFormat::BC6H_UF16,
+            Self::BC6H_SF16 => DxgiFormat::BC6H_SF16,
+            Self::BC7_TYPELESS => DxgiFormat::BC7_Typeless,
+            Self::BC7_UNORM => DxgiFormat::BC7_UNorm,
+            Self::BC7_UNORM_SRGB => DxgiFormat::BC7_UNorm_sRGB,
+            Self::R8G8B8A8_TYPELESS => DxgiFormat::R8G8B8A8_Typeless,
+            Self::R8G8B8A8_UNORM => DxgiFormat::R8G8B8A8_UNorm,
+            Self::R8G8B8A8_UNORM_SRGB => DxgiFormat::R8G8B8A8_UNorm_sRGB,
+            Self::B8G8R8A8_TYPELESS => DxgiFormat::B8G8R8A8_Typeless,
+            Self::B8G8R8A8_UNORM => DxgiFormat::B8G8R8A8_UNorm,
+            Self::B8G8R8A8_UNORM_SRGB => DxgiFormat::B8G8R8A8_UNorm_sRGB,
+        }
+    }
+
+    fn encoding(self) -> Encoding {
+        match self {
+            Self::BC1_TYPELESS | Self::BC1_UNORM | Self::BC1_UNORM_SRGB => Encoding::Bc1,
+            Self::BC3_TYPELESS | Self::BC3_UNORM | Self::BC3_UNORM_SRGB => Encoding::Bc3,
+            Self::BC4_TYPELESS | Self::BC4_UNORM => Encoding::Bc4,
+            Self::BC5_TYPELESS | Self::BC5_UNORM => Encoding::Bc5,
+            Self::BC6H_TYPELESS | Self::BC6H_UF16 | Self::BC6H_SF16 => Encoding::Bc6h,
+            Self::BC7_TYPELESS | Self::BC7_UNORM | Self::BC7_UNORM_SRGB => Encoding::Bc7,
+            Self::R8G8B8A8_TYPELESS | Self::R8G8B8A8_UNORM | Self::R8G8B8A8_UNORM_SRGB => Encoding::Rgba8,
+            Self::B8G8R8A8_TYPELESS | Self::B8G8R8A8_UNORM | Self::B8G8R8A8_UNORM_SRGB => Encoding::Bgra8,
+        }
+    }
 }
 
 macro_rules! spanned {
@@ -51,6 +132,138 @@ macro_rules! spanned {
     };
 }
 
+/// block compressors only take whole 4x4 blocks, the last row and column are repeated to fill them (mips smaller than a block
+/// still take a whole one)
+fn padded_to_blocks(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width.next_multiple_of(4), height.next_multiple_of(4), |x, y| {
+        *image.get_pixel(x.min(width - 1), y.min(height - 1))
+    })
+}
+
+/// the rgba compressors all take the same surface
+fn compress_rgba(image: &RgbaImage, compress: impl FnOnce(&intel_tex::RgbaSurface) -> Vec<u8>) -> Vec<u8> {
+    let padded = padded_to_blocks(image);
+    compress(&intel_tex::RgbaSurface {
+        width: padded.width(),
+        height: padded.height(),
+        stride: padded.width() * 4,
+        data: padded.as_raw(),
+    })
+}
+
+impl Encoding {
+    /// bytes a level takes, the block compressed ones round up to whole 4x4 blocks
+    fn level_size(self, (width, height): (u32, u32)) -> usize {
+        let blocks = (width.div_ceil(4) * height.div_ceil(4)) as usize;
+        match self {
+            Encoding::Bc1 | Encoding::Bc4 => blocks * 8,
+            Encoding::Bc3 | Encoding::Bc5 | Encoding::Bc6h | Encoding::Bc7 => blocks * 16,
+            Encoding::Rgba8 | Encoding::Bgra8 => (width * height * 4) as usize,
+        }
+    }
+
+    /// rows are tightly packed (pitch is `width * 4` for the uncompressed ones), which is the layout ddsfile sizes the data for
+    fn encode(self, image: &RgbaImage, is_opaque: bool) -> Vec<u8> {
+        match self {
+            Encoding::Rgba8 => image.as_raw().clone(),
+            Encoding::Bgra8 => image
+                .pixels()
+                .flat_map(|&image::Rgba([r, g, b, a])| [b, g, r, a])
+                .collect(),
+            Encoding::Bc1 => compress_rgba(image, |surface| spanned!(bc1::compress_blocks(surface))),
+            Encoding::Bc3 => compress_rgba(image, |surface| spanned!(bc3::compress_blocks(surface))),
+            Encoding::Bc4 => padded_to_blocks(image).pipe(|padded| {
+                let red = padded.pixels().map(|pixel| pixel.0[0]).collect_vec();
+                spanned!(bc4::compress_blocks(&intel_tex::RSurface {
+                    width: padded.width(),
+                    height: padded.height(),
+                    stride: padded.width(),
+                    data: &red,
+                }))
+            }),
+            Encoding::Bc5 => padded_to_blocks(image).pipe(|padded| {
+                let red_green = padded
+                    .pixels()
+                    .flat_map(|pixel| [pixel.0[0], pixel.0[1]])
+                    .collect_vec();
+                spanned!(bc5::compress_blocks(&intel_tex::RgSurface {
+                    width: padded.width(),
+                    height: padded.height(),
+                    stride: padded.width() * 2,
+                    data: &red_green,
+                }))
+            }),
+            Encoding::Bc6h => compress_rgba(image, |surface| spanned!(bc6h::compress_blocks(&bc6h::very_fast_settings(), surface))),
+            Encoding::Bc7 => compress_rgba(image, |surface| {
+                spanned!(bc7::compress_blocks(
+                    &match is_opaque {
+                        true => bc7::opaque_ultra_fast_settings(),
+                        false => bc7::alpha_ultra_fast_settings(),
+                    },
+                    surface,
+                ))
+            }),
+        }
+    }
+}
+
+/// `image` resized to `width`x`height` and encoded in `target_format`, with `mipmaps` levels (0 is the full chain) filtered down
+/// from the resized one
+fn encode_dds(
+    image: &RgbaImage,
+    (width, height): (u32, u32),
+    target_format: DXGIFormat,
+    mipmaps: u32,
+    alpha_mode: AlphaMode,
+    mip_filter: MipFilter,
+) -> Result<Dds> {
+    let output_format = OutputFormat::match_output_format(target_format).with_context(|| format!("{target_format:?} is not supported by intel tex"))?;
+    let encoding = output_format.encoding();
+    let is_opaque = matches!(alpha_mode, AlphaMode::Opaque, MipFilter::Box);
+    let levels = mip_chain::level_dimensions((width, height), mipmaps);
+    let (offsets, total) = mip_chain::level_offsets(&levels, |level| encoding.level_size(level));
+    let encoded = spanned!(image::imageops::resize(image, width, height, FilterType::Triangle))
+        .pipe(|base| spanned!(mip_chain::generate(base, &levels, mip_filter)))
+        .iter()
+        .zip_eq(offsets)
+        .try_fold(vec![0; total], |mut encoded, (mip, offset)| {
+            let level = encoding.encode(mip, is_opaque);
+            let expected = encoding.level_size(mip.dimensions());
+            anyhow::ensure!(
+                level.len() == expected,
+                "{}x{} level encoded to [{}] bytes, but it takes [{expected}]",
+                mip.width(),
+                mip.height(),
+                level.len()
+            );
+            encoded[offset..offset + expected].copy_from_slice(&level);
+            Ok(encoded)
+        })?;
+    Dds::new_dxgi(ddsfile::NewDxgiParams {
+        height,
+        width,
+        depth: None,
+        format: output_format.dxgi_format(),
+        mipmap_levels: Some(levels.len() as u32),
+        array_layers: None,
+        caps2: None,
+        is_cubemap: false,
+        resource_dimension: D3D10ResourceDimension::Texture2D,
+        alpha_mode,
+    })
+    .context("creating dds file")
+    .and_then(|dds| match dds.data.len() == encoded.len() {
+        true => Ok(dds.tap_mut(|dds| dds.data = encoded)),
+        false => Err(anyhow::anyhow!(
+            "encoded [{}] bytes, but a {output_format:?} texture of {width}x{height} with [{}] mipmaps takes [{}]",
+            encoded.len(),
+            levels.len(),
+            dds.data.len()
+        )),
+    })
+}
+
 #[tracing::instrument(skip(input, output))]
 pub fn resize_dds<R, W>(input: &mut R, target_width: u32, target_height: u32, target_format: DXGIFormat, target_mipmaps: u32, output: &mut W) -> Result<()>
 where
@@ -59,7 +272,7 @@ where
 {
     OutputFormat::match_output_format(target_format)
         .with_context(|| format!("{target_format:?} is not supported by intel tex"))
-        .and_then(|output_format| {
+        .and_then(|_| {
             warn!("trying experimental intel texture recompression library! if it fails it will fall back to slower microsoft directxtex");
             spanned!(Dds::read(input))
                 .context("reading dds file")
@@ -68,157 +281,162 @@ where
                         .context("reading image data")
                         .and_then(|image| spanned!(image.decode().context("bad image")))
                         .and_then(|image| {
-                            image.dimensions().pipe(|(width, height)| {
-                                ImageBuffer::new(width, height)
-                                    .tap_mut(|rgba_img| {
-                                        (0..width)
-                                            .flat_map(|x| (0..height).map(move |y| (x, y)))
-                                            .map(|(x, y)| (x, y, image.get_pixel(x, y).to_rgba()))
-                                            .for_each(|(x, y, pixel)| {
-                                                rgba_img.put_pixel(x, y, pixel);
-                                            })
-                                    })
-                                    .pipe(|rgba_img| {
-                                        intel_tex::divide_up_by_multiple(width * height, 16)
-                                            .tap(|block_count| info!("block count: {block_count}"))
-                                            .pipe(|_| {
-                                                let mip_count = dds_file.header.mip_map_count;
-                                                let array_layers = dds_file
-                                                    .header10
-                                                    .as_ref()
-                                                    .map(|a| a.array_size)
-                                                    .unwrap_or(1);
-                                                let caps2 = dds_file.header.caps2;
-                                                let is_cubemap = false;
-                                                let resource_dimension = dds_file
-                                                    .header10
-                                                    .as_ref()
-                                                    .map(|h| h.resource_dimension)
-                                                    .unwrap_or(D3D10ResourceDimension::Texture2D);
-                                                let alpha_mode = dds_file
-                                                    .header10
-                                                    .as_ref()
-                                                    .map(|h| h.alpha_mode)
-                                                    .unwrap_or(AlphaMode::Opaque);
-                                                let depth = dds_file.header.depth.unwrap_or(1);
-
-                                                let is_opaque = match alpha_mode {
-                                                    AlphaMode::Opaque => true,
-                                                    AlphaMode::Unknown => false,
-                                                    AlphaMode::Straight => false,
-                                                    AlphaMode::PreMultiplied => false,
-                                                    AlphaMode::Custom => false,
-                                                };
-                                                Dds::new_dxgi(ddsfile::NewDxgiParams {
-                                                    height,
-                                                    width,
-                                                    depth: Some(depth),
-                                                    format: DxgiFormat::BC7_UNorm,
-                                                    mipmap_levels: mip_count,
-                                                    array_layers: Some(array_layers),
-                                                    caps2: Some(caps2),
-                                                    is_cubemap,
-                                                    resource_dimension,
-                                                    alpha_mode,
-                                                })
-                                                .context("creating dds file")
-                                                .and_then(|mut dds| {
-                                                    intel_tex::RgbaSurface {
-                                                        width,
-                                                        height,
-                                                        stride: width * 4,
-                                                        data: &rgba_img,
-                                                    }
-                                                    .pipe(|surface| {
-                                                        dds.get_mut_data(0 /* layer */)
-                                                            .context("layers")
-                                                            .map(|output_layer| match output_format {
-                                                                OutputFormat::BC7_TYPELESS => {
-                                                                    spanned!(bc7::compress_blocks_into(
-                                                                        &match is_opaque {
-                                                                            true => bc7::opaque_ultra_fast_settings(),
-                                                                            false => bc7::alpha_ultra_fast_settings(),
-                                                                        },
-                                                                        &surface,
-                                                                        output_layer,
-                                                                    ));
-                                                                }
-                                                                OutputFormat::BC1_TYPELESS => {
-                                                                    spanned!(bc1::compress_blocks_into(&surface, output_layer));
-                                                                }
-                                                                OutputFormat::BC1_UNORM => {
-                                                                    spanned!(bc1::compress_blocks_into(&surface, output_layer));
-                                                                }
-                                                                OutputFormat::BC1_UNORM_SRGB => {
-                                                                    spanned!(bc1::compress_blocks_into(&surface, output_layer));
-                                                                }
-                                                                OutputFormat::BC3_TYPELESS => {
-                                                                    spanned!(bc3::compress_blocks_into(&surface, output_layer));
-                                                                }
-                                                                OutputFormat::BC3_UNORM => {
-                                                                    spanned!(bc3::compress_blocks_into(&surface, output_layer));
-                                                                }
-                                                                OutputFormat::BC3_UNORM_SRGB => {
-                                                                    spanned!(bc3::compress_blocks_into(&surface, output_layer));
-                                                                }
-                                                                OutputFormat::BC6H_TYPELESS => {
-                                                                    spanned!(bc6h::compress_blocks_into(
-                                                                        &match is_opaque {
-                                                                            true => bc6h::very_fast_settings(),
-                                                                            false => bc6h::very_fast_settings(),
-                                                                        },
-                                                                        &surface,
-                                                                        output_layer,
-                                                                    ));
-                                                                }
-                                                                OutputFormat::BC6H_UF16 => {
-                                                                    spanned!(bc6h::compress_blocks_into(
-                                                                        &match is_opaque {
-                                                                            true => bc6h::very_fast_settings(),
-                                                                            false => bc6h::very_fast_settings(),
-                                                                        },
-                                                                        &surface,
-                                                                        output_layer,
-                                                                    ));
-                                                                }
-                                                                OutputFormat::BC6H_SF16 => {
-                                                                    spanned!(bc6h::compress_blocks_into(
-                                                                        &match is_opaque {
-                                                                            true => bc6h::very_fast_settings(),
-                                                                            false => bc6h::very_fast_settings(),
-                                                                        },
-                                                                        &surface,
-                                                                        output_layer,
-                                                                    ));
-                                                                }
-                                                                OutputFormat::BC7_UNORM => {
-                                                                    spanned!(bc7::compress_blocks_into(
-                                                                        &match is_opaque {
-                                                                            true => bc7::opaque_ultra_fast_settings(),
-                                                                            false => bc7::alpha_ultra_fast_settings(),
-                                                                        },
-                                                                        &surface,
-                                                                        output_layer,
-                                                                    ));
-                                                                }
-                                                                OutputFormat::BC7_UNORM_SRGB => {
-                                                                    spanned!(bc7::compress_blocks_into(
-                                                                        &match is_opaque {
-                                                                            true => bc7::opaque_ultra_fast_settings(),
-                                                                            false => bc7::alpha_ultra_fast_settings(),
-                                                                        },
-                                                                        &surface,
-                                                                        output_layer,
-                                                                    ));
-                                                                }
-                                                            })
-                                                    })
-                                                })
-                                            })
-                                    })
-                            })
+                            image
+                                .dimensions()
+                                .tap(|(width, height)| info!("source is {width}x{height}"));
+                            let alpha_mode = dds_file
+                                .header10
+                                .as_ref()
+                                .map(|h| h.alpha_mode)
+                                .unwrap_or(AlphaMode::Opaque, MipFilter::Box);
+                            encode_dds(
+                                &image.to_rgba8(),
+                                (target_width, target_height),
+                                target_format,
+                                target_mipmaps,
+                                alpha_mode,
+                                MipFilter::from_env(),
+                            )
                         })
-                        .and_then(|_| dds_file.write(output).context("writing dds file"))
                 })
+                .and_then(|dds| dds.write(output).context("writing dds file"))
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{super::dds_header::DdsHeader, *},
+        image::Rgba,
+    };
+
+    /// `(x, y)` of a 16x8 gradient, one distinct value per channel
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(16, 8, |x, y| Rgba([(x * 16) as u8, (y * 32) as u8, 128, 255]))
+    }
+
+    /// BC4 block - two endpoints and 3 bit indices into the palette spanned between them
+    fn decode_bc4_block(block: &[u8]) -> [u8; 16] {
+        let (a, b) = (block[0] as u32, block[1] as u32);
+        let palette = match a > b {
+            true => (0..8)
+                .map(|idx| match idx {
+                    0 => a,
+                    1 => b,
+                    idx => ((8 - idx) * a + (idx - 1) * b) / 7,
+                })
+                .collect_vec(),
+            false => (0..8)
+                .map(|idx| match idx {
+                    0 => a,
+                    1 => b,
+                    6 => 0,
+                    7 => 255,
+                    idx => ((6 - idx) * a + (idx - 1) * b) / 5,
+                })
+                .collect_vec(),
+        };
+        let indices = block[2..8]
+            .iter()
+            .rev()
+            .fold(0u64, |bits, byte| (bits << 8) | *byte as u64);
+        std::array::from_fn(|texel| palette[((indices >> (texel * 3)) & 0b111) as usize] as u8)
+    }
+
+    /// one channel per 8 bytes of every block, `channels` of them (1 for BC4, 2 for BC5)
+    fn decode_bc45(data: &[u8], (width, height): (u32, u32), channels: usize) -> Vec<Vec<u8>> {
+        let blocks_wide = width.div_ceil(4) as usize;
+        (0..channels)
+            .map(|channel| {
+                (0..height as usize)
+                    .flat_map(|y| (0..width as usize).map(move |x| (x, y)))
+                    .map(|(x, y)| {
+                        let block = ((y / 4) * blocks_wide + x / 4) * 8 * channels + channel * 8;
+                        decode_bc4_block(&data[block..block + 8])[(y % 4) * 4 + x % 4]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_close(decoded: &[u8], expected: impl Iterator<Item = u8>, tolerance: u8) {
+        decoded
+            .iter()
+            .zip_eq(expected)
+            .enumerate()
+            .for_each(|(idx, (decoded, expected))| assert!(decoded.abs_diff(expected) <= tolerance, "texel [{idx}]: {decoded} vs {expected}"));
+    }
+
+    fn written(dds: &Dds) -> Result<Vec<u8>> {
+        Vec::new().pipe(|mut bytes| dds.write(&mut bytes).context("writing").map(|_| bytes))
+    }
+
+    #[test_log::test]
+    fn test_bc4_and_bc5_round_trip() -> Result<()> {
+        let image = gradient();
+        let bc4 = encode_dds(&image, (16, 8), DXGIFormat::BC4_UNORM, 1, AlphaMode::Opaque, MipFilter::Box)?;
+        let [red] = decode_bc45(&bc4.data, (16, 8), 1)
+            .try_into()
+            .expect("one channel");
+        assert_close(&red, image.pixels().map(|pixel| pixel.0[0]), 8);
+
+        let bc5 = encode_dds(&image, (16, 8), DXGIFormat::BC5_UNORM, 1, AlphaMode::Opaque, MipFilter::Box)?;
+        let [red, green] = decode_bc45(&bc5.data, (16, 8), 2)
+            .try_into()
+            .expect("two channels");
+        assert_close(&red, image.pixels().map(|pixel| pixel.0[0]), 8);
+        assert_close(&green, image.pixels().map(|pixel| pixel.0[1]), 8);
+        assert_eq!(DdsHeader::parse(&written(&bc5)?)?.dxgi_format, Some(DXGIFormat::BC5_UNORM as u32));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_uncompressed_round_trip() -> Result<()> {
+        let image = gradient();
+        let rgba = encode_dds(&image, (16, 8), DXGIFormat::R8G8B8A8_UNORM_SRGB, 1, AlphaMode::Straight, MipFilter::Box)?;
+        assert_eq!(rgba.get_pitch(), Some(16 * 4));
+        assert_eq!(&rgba.data, image.as_raw());
+        assert_eq!(
+            DdsHeader::parse(&written(&rgba)?)?.dxgi_format,
+            Some(DXGIFormat::R8G8B8A8_UNORM_SRGB as u32),
+            "srgb makes it into the header"
+        );
+
+        let bgra = encode_dds(&image, (16, 8), DXGIFormat::B8G8R8A8_UNORM, 1, AlphaMode::Straight, MipFilter::Box)?;
+        assert_eq!(
+            bgra.data
+                .chunks_exact(4)
+                .map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+                .collect_vec(),
+            image.pixels().map(|pixel| pixel.0).collect_vec()
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_mipmaps_and_odd_sizes() -> Result<()> {
+        // 16x8 -> 8x4 -> 4x2 -> 2x1, the last two still take a whole block
+        let bc5 = encode_dds(&gradient(), (16, 8), DXGIFormat::BC5_UNORM, 4, AlphaMode::Opaque, MipFilter::Box)?;
+        assert_eq!(bc5.data.len(), (8 + 2 + 1 + 1) * 16);
+        let rgba = encode_dds(&gradient(), (6, 3), DXGIFormat::R8G8B8A8_UNORM, 2, AlphaMode::Opaque, MipFilter::Box)?;
+        assert_eq!(rgba.data.len(), (6 * 3 + 3) * 4);
+        assert!(encode_dds(&gradient(), (16, 8), DXGIFormat::R16G16_FLOAT, 1, AlphaMode::Opaque, MipFilter::Box).is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_full_chain_of_non_power_of_two() -> Result<()> {
+        // 1000x500 down to 1x1 - ten levels, BC1 takes 8 bytes a block
+        let dds = encode_dds(&gradient(), (1000, 500), DXGIFormat::BC1_UNORM, 0, AlphaMode::Opaque, MipFilter::Box)?;
+        assert_eq!(dds.get_num_mipmap_levels(), 10);
+        assert_eq!(dds.data.len(), 669200 / 2);
+        let level = mip_chain::level_offsets(&mip_chain::level_dimensions((1000, 500), 0), |level| Encoding::Bc1.level_size(level))
+            .0
+            .pipe(|offsets| offsets[3]..offsets[4]);
+        // 125x62 is the fourth level, compressed on its own it lands at the same place
+        let fourth = RgbaImage::from_pixel(125, 62, image::Rgba([10, 200, 30, 255]));
+        let flat = encode_dds(&fourth, (1000, 500), DXGIFormat::BC1_UNORM, 0, AlphaMode::Opaque, MipFilter::Box)?;
+        assert_eq!(&flat.data[level], Encoding::Bc1.encode(&fourth, true).as_slice());
+        Ok(())
+    }
+}
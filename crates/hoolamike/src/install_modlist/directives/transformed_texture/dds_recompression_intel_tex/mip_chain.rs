@@ -0,0 +1,208 @@
+//! mip chains of the native recompressor. like in directxtex every level is filtered down from the one above it (not from
+//! the source), and its dimensions are halved and rounded down, never below 1 - 1000x500 goes 500x250, 250x125, 125x62 and so
+//! on down to 1x1
+
+use {
+    image::RgbaImage,
+    itertools::Itertools,
+    std::f32::consts::PI,
+    tap::{Pipe, Tap},
+};
+
+/// set it to `kaiser` for a sharper (windowed sinc) filter, box is what directxtex uses by default
+pub const MIP_FILTER_ENV: &str = "HOOLAMIKE_MIP_FILTER";
+
+/// half-width (in texels of the smaller level) and shape of the kaiser window, same as nvtt
+const KAISER_WIDTH: f32 = 3.;
+const KAISER_ALPHA: f32 = 4.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipFilter {
+    #[default]
+    Box,
+    Kaiser,
+}
+
+impl MipFilter {
+    pub fn from_env() -> Self {
+        match std::env::var(MIP_FILTER_ENV).is_ok_and(|filter| filter.eq_ignore_ascii_case("kaiser")) {
+            true => Self::Kaiser,
+            false => Self::Box,
+        }
+    }
+}
+
+/// levels of a full chain, down to 1x1
+pub fn full_chain_length((width, height): (u32, u32)) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// dimensions of every level, `mip_levels` of 0 is the full chain (same as for texconv), more levels than the full chain has are
+/// capped
+pub fn level_dimensions((width, height): (u32, u32), mip_levels: u32) -> Vec<(u32, u32)> {
+    let levels = match full_chain_length((width, height)) {
+        full_chain if mip_levels == 0 => full_chain,
+        full_chain => mip_levels.min(full_chain),
+    };
+    (0..levels)
+        .map(|level| ((width >> level).max(1), (height >> level).max(1)))
+        .collect()
+}
+
+/// where every level starts in the dds payload (they are stored one after another, largest first), and the size of all of them
+pub fn level_offsets(levels: &[(u32, u32)], level_size: impl Fn((u32, u32)) -> usize) -> (Vec<usize>, usize) {
+    levels.iter().fold((vec![], 0), |(offsets, total), level| {
+        (offsets.tap_mut(|offsets| offsets.push(total)), total + level_size(*level))
+    })
+}
+
+fn sinc(x: f32) -> f32 {
+    match x.abs() < 1e-6 {
+        true => 1.,
+        false => (PI * x).sin() / (PI * x),
+    }
+}
+
+/// modified bessel function of the first kind, the series converges quickly for the alphas kaiser windows use
+fn bessel_i0(x: f32) -> f32 {
+    (1..64)
+        .scan(1f32, |term, k| {
+            *term *= (x / (2. * k as f32)).powi(2);
+            Some(*term)
+        })
+        .take_while(|term| *term > 1e-8)
+        .sum::<f32>()
+        + 1.
+}
+
+fn kaiser(x: f32) -> f32 {
+    match x.abs() < KAISER_WIDTH {
+        true => sinc(x) * bessel_i0(KAISER_ALPHA * (1. - (x / KAISER_WIDTH).powi(2)).sqrt()) / bessel_i0(KAISER_ALPHA),
+        false => 0.,
+    }
+}
+
+/// source texels (clamped at the edges) and their normalized weights, for every texel of a `source` long row shrunk to `target`
+fn weights(filter: MipFilter, source: u32, target: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = source as f32 / target as f32;
+    (0..target)
+        .map(|texel| {
+            let (start, end) = (texel as f32 * scale, (texel + 1) as f32 * scale);
+            let taps = match filter {
+                // how much of every source texel is covered, odd sizes share the middle texel between two target ones
+                MipFilter::Box => (start.floor() as i64..end.ceil() as i64)
+                    .map(|source_texel| (source_texel, (end.min(source_texel as f32 + 1.) - start.max(source_texel as f32)).max(0.)))
+                    .collect_vec(),
+                MipFilter::Kaiser => {
+                    let (center, stretch) = ((start + end) / 2., scale.max(1.));
+                    ((center - KAISER_WIDTH * stretch).floor() as i64..=(center + KAISER_WIDTH * stretch).ceil() as i64)
+                        .map(|source_texel| (source_texel, kaiser((source_texel as f32 + 0.5 - center) / stretch)))
+                        .collect_vec()
+                }
+            };
+            let total = taps.iter().map(|(_, weight)| weight).sum::<f32>();
+            taps.into_iter()
+                .map(|(source_texel, weight)| (source_texel.clamp(0, source as i64 - 1) as usize, weight / total))
+                .collect()
+        })
+        .collect()
+}
+
+/// `image` filtered down to `width`x`height`, one axis at a time
+fn downsample(image: &RgbaImage, (width, height): (u32, u32), filter: MipFilter) -> RgbaImage {
+    let (source_width, source_height) = image.dimensions();
+    let (columns, rows) = (weights(filter, source_width, width), weights(filter, source_height, height));
+    let columns = &columns;
+    // still `source_height` rows tall
+    let horizontal = (0..source_height)
+        .flat_map(|y| {
+            columns.iter().map(move |taps| {
+                taps.iter().fold([0f32; 4], |texel, (x, weight)| {
+                    let source = image.get_pixel(*x as u32, y).0;
+                    std::array::from_fn(|channel| texel[channel] + source[channel] as f32 * weight)
+                })
+            })
+        })
+        .collect_vec();
+    RgbaImage::from_fn(width, height, |x, y| {
+        rows[y as usize]
+            .iter()
+            .fold([0f32; 4], |texel, (source_y, weight)| {
+                let source = horizontal[source_y * width as usize + x as usize];
+                std::array::from_fn(|channel| texel[channel] + source[channel] * weight)
+            })
+            .map(|channel| channel.round().clamp(0., 255.) as u8)
+            .pipe(image::Rgba)
+    })
+}
+
+/// every level of the chain, the first of `levels` is the size of `base`
+pub fn generate(base: RgbaImage, levels: &[(u32, u32)], filter: MipFilter) -> Vec<RgbaImage> {
+    levels.iter().skip(1).fold(vec![base], |chain, level| {
+        let next = downsample(chain.last().expect("chain starts with the base level"), *level, filter);
+        chain.tap_mut(|chain| chain.push(next))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, image::Rgba};
+
+    #[test_log::test]
+    fn test_levels_and_offsets_of_non_power_of_two() {
+        let levels = level_dimensions((1000, 500), 0);
+        assert_eq!(
+            levels,
+            vec![
+                (1000, 500),
+                (500, 250),
+                (250, 125),
+                (125, 62),
+                (62, 31),
+                (31, 15),
+                (15, 7),
+                (7, 3),
+                (3, 1),
+                (1, 1)
+            ]
+        );
+        // BC7 - 16 bytes per 4x4 block, small levels still take a whole one
+        let (offsets, total) = level_offsets(&levels, |(width, height)| (width.div_ceil(4) * height.div_ceil(4)) as usize * 16);
+        assert_eq!(offsets, vec![0, 500000, 626000, 658256, 666448, 668496, 669008, 669136, 669168, 669184]);
+        assert_eq!(total, 669200);
+
+        assert_eq!(level_dimensions((1000, 500), 4).len(), 4);
+        assert_eq!(level_dimensions((1000, 500), 20).len(), 10, "capped at 1x1");
+        assert_eq!(level_dimensions((1, 1), 0), vec![(1, 1)]);
+    }
+
+    #[test_log::test]
+    fn test_box_filter_averages() {
+        let image = RgbaImage::from_fn(3, 3, |x, y| Rgba([(x + y * 3) as u8 * 10, 0, 255, 255]));
+        let chain = generate(image, &level_dimensions((3, 3), 0), MipFilter::Box);
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[1].get_pixel(0, 0), &Rgba([40, 0, 255, 255]));
+
+        // 5 wide goes to 2, the middle column is split between them
+        let image = RgbaImage::from_fn(5, 1, |x, _| Rgba([[0, 0, 100, 200, 200][x as usize], 0, 0, 0]));
+        let chain = generate(image, &[(5, 1), (2, 1)], MipFilter::Box);
+        assert_eq!(chain[1].get_pixel(0, 0).0[0], 20);
+        assert_eq!(chain[1].get_pixel(1, 0).0[0], 180);
+    }
+
+    #[test_log::test]
+    fn test_flat_colors_stay_flat() {
+        let color = Rgba([12, 34, 56, 78]);
+        [MipFilter::Box, MipFilter::Kaiser]
+            .into_iter()
+            .for_each(|filter| {
+                generate(RgbaImage::from_pixel(12, 5, color), &level_dimensions((12, 5), 0), filter)
+                    .iter()
+                    .zip_eq(level_dimensions((12, 5), 0))
+                    .for_each(|(level, dimensions)| {
+                        assert_eq!(level.dimensions(), dimensions);
+                        assert!(level.pixels().all(|pixel| pixel == &color), "{filter:?} at {dimensions:?}");
+                    })
+            });
+    }
+}
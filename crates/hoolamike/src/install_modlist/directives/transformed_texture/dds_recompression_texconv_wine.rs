@@ -1,18 +1,18 @@
 // Import the Texconv builder and related enums
 use {
-    crate::{compression::SeekWithTempFileExt, consts::TEMP_FILE_DIR, modlist_json::image_format::DXGIFormat},
-    ::texconv_wrapper::{BcFlag, FileType, ImageFilter, Texconv},
-    ::wine_wrapper::wine_context::{Initialized, WineContext},
+    crate::{compression::SeekWithTempFileExt, modlist_json::image_format::DXGIFormat},
+    ::texconv_wrapper::{BcFlag, DiagCommand, FileType, ImageFilter, Texconv, Texdiag, TexdiagInfo},
     anyhow::{Context, Result},
     itertools::Itertools,
     std::{
+        collections::BTreeMap,
         io::{Read, Write},
         num::NonZeroU32,
-        path::Path,
+        path::{Path, PathBuf},
+        process::Command,
     },
     tap::{Pipe, TapFallible},
     tracing::info,
-    wine_wrapper::wine_context::CommandWrapInWineExt,
 };
 
 mod dxgi_format_mapping;
@@ -23,6 +23,55 @@ macro_rules! spanned {
     };
 }
 
+/// where texconv and texdiag run - inside a wine prefix on linux, directly on windows
+#[derive(Debug)]
+pub enum TexconvHost {
+    #[cfg(unix)]
+    Wine(::wine_wrapper::wine_context::Initialized<::wine_wrapper::wine_context::WineContext>),
+    #[cfg(not(unix))]
+    Native,
+}
+
+impl TexconvHost {
+    /// `path` the way the tools see it
+    pub fn path(&self, path: &Path) -> Result<String> {
+        match self {
+            #[cfg(unix)]
+            Self::Wine(context) => context.host_to_pfx_path(path).map(|path| path.to_string()),
+            #[cfg(not(unix))]
+            Self::Native => path
+                .to_str()
+                .map(str::to_string)
+                .with_context(|| format!("[{}] is not valid unicode", path.display())),
+        }
+    }
+
+    /// runs the command to completion, returns what it printed
+    pub fn run(&self, mut command: Command) -> Result<String> {
+        match self {
+            #[cfg(unix)]
+            Self::Wine(context) => {
+                use ::wine_wrapper::wine_context::CommandWrapInWineExt;
+                command
+                    .wrap_in_wine(context)
+                    .and_then(|command| spanned!(command.output_blocking()))
+            }
+            #[cfg(not(unix))]
+            Self::Native => spanned!(command.output())
+                .with_context(|| format!("spawning [{command:?}]"))
+                .and_then(|output| match output.status.success() {
+                    true => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+                    false => Err(anyhow::anyhow!(
+                        "status: {}\n\nstdout:\n{}\n\nstderr:\n{}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stdout),
+                        String::from_utf8_lossy(&output.stderr)
+                    )),
+                }),
+        }
+    }
+}
+
 /// The number of bytes written to the output stream.
 #[allow(clippy::too_many_arguments)]
 #[tracing::instrument(skip(input, output))]
@@ -34,13 +83,14 @@ pub fn resize_dds<R, W>(
     target_mipmaps: u32,
     output: &mut W,
     texconv_binary: &Path,
-    wine_context: &Initialized<WineContext>,
+    host: &TexconvHost,
     extension: &str,
 ) -> Result<u64>
 where
     R: Read,
     W: Write,
 {
+    let temp_directory = crate::temp_directory::run_directory()?;
     // Map the DXGIFormat to a texconv-compatible format string
     dxgi_format_mapping::map_dxgi_format(target_format)
         .context("mapping DXGI format to texconv format")
@@ -52,19 +102,15 @@ where
                     tempfile::Builder::new()
                         .prefix("dds-output-")
                         .suffix(&format!(".{extension}"))
-                        .tempdir_in(*TEMP_FILE_DIR)
+                        .tempdir_in(temp_directory)
                         .context("creating output dir")
                         .map(|output_dir| (format_str, input, output_dir))
                 })
         })
         .and_then(|(format_str, input_file, output_dir)| {
-            Texconv::builder(wine_context.host_to_pfx_path(texconv_binary)?.to_string())
-                .input_file(wine_context.host_to_pfx_path(&input_file)?.to_string())
-                .output_dir(
-                    wine_context
-                        .host_to_pfx_path(output_dir.path())?
-                        .to_string(),
-                )
+            Texconv::builder(host.path(texconv_binary)?)
+                .input_file(host.path(&input_file)?)
+                .output_dir(host.path(output_dir.path())?)
                 .file_type(FileType::Dds)
                 .format(format_str)
                 .width(target_width)
@@ -80,11 +126,11 @@ where
                 .no_logo(true)
                 .single_proc(true)
                 .build()
-                .command()
-                .wrap_in_wine(wine_context)
-                .and_then(|command| spanned!(command.output_blocking()))
+                .try_command()
+                .context("validating texconv arguments")
+                .and_then(|command| host.run(command))
                 .map(|output| info!("{output}"))
-                .context("spawning wine command")
+                .context("running texconv")
                 .and_then(|()| {
                     std::fs::read_dir(output_dir.path())
                         .context("reading output dir")
@@ -104,8 +150,8 @@ where
                                 .and_then(|mut result| std::io::copy(&mut result, output).context("copying output into output buffer"))
                         })
                 })
-                .context("trying to resize texture using texconv + wine")
-                .tap_ok(|size| info!("texconv wine success: {size}"))
+                .context("trying to resize texture using texconv")
+                .tap_ok(|size| info!("texconv success: {size}"))
                 .pipe(|reason| match reason {
                     Ok(v) => Ok(v),
                     Err(reason) => {
@@ -136,3 +182,154 @@ where
                 })
         })
 }
+
+/// settings texconv applies to every file of a single run, textures sharing them can be converted together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BatchSettings {
+    pub format: DXGIFormat,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+}
+
+impl BatchSettings {
+    /// what texdiag reports for an output has to match what the batch was asked for
+    pub fn check(&self, info: &TexdiagInfo) -> Result<()> {
+        let expected = (self.width, self.height, format!("{:?}", self.format));
+        let found = (info.width, info.height, info.format.clone());
+        (expected == found)
+            .then_some(())
+            .with_context(|| format!("expected [{expected:?}] (width, height, format), but texdiag reports [{found:?}]"))
+            .and_then(|()| {
+                // 0 asks for the whole mip chain, whatever its length
+                (self.mip_levels == 0 || self.mip_levels == info.mip_levels)
+                    .then_some(())
+                    .with_context(|| format!("expected [{}] mip levels, but texdiag reports [{}]", self.mip_levels, info.mip_levels))
+            })
+    }
+}
+
+/// texdiag ships with texconv, it's used when it's found next to it
+pub fn texdiag_next_to(texconv_binary: &Path) -> Option<PathBuf> {
+    texconv_binary
+        .parent()
+        .map(|directory| directory.join("texdiag.exe"))
+        .filter(|texdiag| texdiag.exists())
+}
+
+/// outputs of a batch, they're gone once it's dropped
+pub struct BatchOutputs {
+    directory: tempfile::TempDir,
+    outputs: PathBuf,
+}
+
+impl BatchOutputs {
+    /// inputs are staged as `<position>.<extension>`, so that no two outputs share a name
+    pub fn output(&self, position: usize) -> PathBuf {
+        self.outputs.join(format!("{position}.dds"))
+    }
+
+    /// `texdiag info` of every output the batch wrote (out of `count`), by position
+    #[tracing::instrument(skip(self, host))]
+    pub fn describe(&self, count: usize, texdiag: &Path, host: &TexconvHost) -> Result<BTreeMap<usize, TexdiagInfo>> {
+        let file_list = self.directory.path().join("texdiag-file-list.txt");
+        (0..count)
+            .map(|position| self.output(position))
+            .filter(|output| output.exists())
+            .map(|output| host.path(&output))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|outputs| std::fs::write(&file_list, outputs.join("\n")).context("writing file list"))
+            .and_then(|()| {
+                Texdiag::builder(host.path(texdiag)?, DiagCommand::Info)
+                    .file_list(host.path(&file_list)?)
+                    .permissive(true)
+                    .no_logo(true)
+                    .build()
+                    .try_command()
+                    .context("validating texdiag arguments")
+            })
+            .and_then(|command| host.run(command))
+            .context("running texdiag on the batch outputs")
+            .and_then(|output| TexdiagInfo::parse(&output).context("reading texdiag output"))
+            .map(|infos| {
+                infos
+                    .into_iter()
+                    .filter_map(|info| {
+                        // texdiag names them by their windows path
+                        info.file
+                            .rsplit(['\\', '/'])
+                            .next()
+                            .and_then(|name| name.strip_suffix(".dds"))
+                            .and_then(|position| position.parse::<usize>().ok())
+                            .map(|position| (position, info))
+                    })
+                    .collect()
+            })
+    }
+}
+
+/// converts every source (and the extension it's staged with) in a single wine process. the run succeeding doesn't mean every
+/// output was written - those have to be checked one by one
+#[tracing::instrument(skip(sources, host), fields(sources=%sources.len()))]
+pub fn resize_dds_batch(
+    sources: &[(&Path, &str)],
+    BatchSettings {
+        format,
+        width,
+        height,
+        mip_levels,
+    }: BatchSettings,
+    texconv_binary: &Path,
+    host: &TexconvHost,
+) -> Result<BatchOutputs> {
+    let format_str = dxgi_format_mapping::map_dxgi_format(format).context("mapping DXGI format to texconv format")?;
+    let directory = tempfile::Builder::new()
+        .prefix("dds-batch-")
+        .tempdir_in(crate::temp_directory::run_directory()?)
+        .context("creating batch dir")?;
+    let (inputs, outputs, file_list) = (
+        directory.path().join("inputs"),
+        directory.path().join("outputs"),
+        directory.path().join("file-list.txt"),
+    );
+    // arguments are known before anything gets staged
+    let command = Texconv::builder(host.path(texconv_binary)?)
+        .file_list(host.path(&file_list)?)
+        .output_dir(host.path(&outputs)?)
+        .overwrite(true)
+        .file_type(FileType::Dds)
+        .format(format_str)
+        .width(width)
+        .height(height)
+        .maybe_mip_levels(NonZeroU32::new(mip_levels))
+        .image_filter(ImageFilter::Triangle)
+        .permissive(true)
+        .maybe_bc_flag(match format {
+            DXGIFormat::BC7_TYPELESS | DXGIFormat::BC7_UNORM | DXGIFormat::BC7_UNORM_SRGB => BcFlag::Quick.pipe(Some),
+            _ => None,
+        })
+        .no_logo(true)
+        .single_proc(true)
+        .build()
+        .try_command()
+        .context("validating texconv arguments")?;
+    [&inputs, &outputs]
+        .into_iter()
+        .try_for_each(|dir| std::fs::create_dir_all(dir).with_context(|| format!("creating [{}]", dir.display())))?;
+    sources
+        .iter()
+        .enumerate()
+        .map(|(position, (source, extension))| {
+            let staged = inputs.join(format!("{position}.{extension}"));
+            std::fs::copy(source, &staged)
+                .with_context(|| format!("staging [{}]", source.display()))
+                .and_then(|_| host.path(&staged))
+        })
+        .collect::<Result<Vec<_>>>()
+        .and_then(|staged| std::fs::write(&file_list, staged.join("\n")).context("writing file list"))?;
+
+    host.run(command)
+        .map(|output| info!("{output}"))
+        .context("running texconv on the batch")
+        .map(|()| BatchOutputs { directory, outputs })
+}
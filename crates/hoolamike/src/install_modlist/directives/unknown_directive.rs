@@ -0,0 +1,33 @@
+//! directives of kinds this version doesn't know about. hoolamike is a binary, there is nothing outside of it that could
+//! register handlers for them - so unless their output is already in place, they fail naming the kind
+
+use {super::*, crate::modlist_json::directive::unknown_directive::UnknownDirective};
+
+/// the modlist was most likely made with a newer wabbajack, the error says which kind and which file it would have produced
+#[tracing::instrument(skip_all, fields(type_tag=%directive.type_tag))]
+pub fn handle(directive: UnknownDirective) -> Result<u64> {
+    let UnknownDirective { type_tag, to, .. } = directive;
+    anyhow::bail!("no handler for [{type_tag}] directives (producing [{to}]), this modlist was probably made with a newer wabbajack")
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::modlist_json::Directive};
+
+    #[test_log::test]
+    fn test_unknown_kind_fails_listing_the_tag() -> Result<()> {
+        let directive = match serde_json::from_value(serde_json::json!({
+            "$type": "MergedPatch",
+            "Hash": "AAAAAAAAAAA=",
+            "Size": 1,
+            "To": "merged.esp",
+        }))? {
+            Directive::Unknown(unknown) => unknown,
+            other => anyhow::bail!("expected an unknown directive, got {other:?}"),
+        };
+        let error = format!("{:?}", handle(directive).expect_err("nobody handles MergedPatch"));
+        assert!(error.contains("[MergedPatch]"), "{error}");
+        assert!(error.contains("merged.esp"), "{error}");
+        Ok(())
+    }
+}
@@ -1,82 +1,330 @@
+//! entries of the modlist file itself (inline files, patches) are extracted lazily, when a directive asks for one. only an
+//! index of them is built up front, so a modlist with tens of thousands of them starts executing directives right away and
+//! holds a single temp file open per extraction in flight
+
 use {
-    super::IteratorTryFlatMapExt,
-    crate::{
-        compression::{ArchiveFileHandle, ProcessArchive, zip::ZipArchive},
-        utils::ExistingPathRead,
-    },
+    crate::utils::{ExistingPathRead, PathFileNameOrEmpty},
     anyhow::{Context, Result},
     case_insensitive_path::{CaseInsensitivePathBuf, ExistingPath, ExistingPathBuf},
-    itertools::Itertools,
     parking_lot::Mutex,
-    rayon::{iter::ParallelIterator, slice::ParallelSlice},
-    std::{collections::BTreeMap, ops::Div, str::FromStr, sync::Arc},
+    std::{
+        collections::BTreeMap,
+        fs::File,
+        io::ErrorKind,
+        path::Path,
+        str::FromStr,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+    },
     tap::prelude::*,
-    tempfile::TempPath,
-    tracing::instrument,
+    tempfile::{NamedTempFile, TempPath},
+    tracing::{debug, error, instrument},
 };
 
+type ZipReader = ::zip::ZipArchive<File>;
+
+/// `EIO` - what reads from a yanked usb stick fail with
+const EIO: i32 = 5;
+
+/// identifies the modlist file, a re-inserted drive (or a replaced file) won't match anymore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileIdentity {
+    device: u64,
+    inode: u64,
+}
+
+impl FileIdentity {
+    pub fn of(path: &Path) -> std::io::Result<Self> {
+        std::fs::metadata(path).map(|metadata| {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                Self {
+                    device: metadata.dev(),
+                    inode: metadata.ino(),
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                Self {
+                    device: 0,
+                    inode: metadata.len(),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ModlistFileLost {
+    pub path: String,
+}
+
+impl std::fmt::Display for ModlistFileLost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the modlist file at [{}] is no longer accessible - was the drive removed?", self.path)
+    }
+}
+
+impl std::error::Error for ModlistFileLost {}
+
+/// io errors which mean the storage itself is gone rather than a single entry being broken
+fn is_storage_failure(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|error| error.downcast_ref::<std::io::Error>())
+        .any(|error| error.kind() == ErrorKind::NotFound || error.raw_os_error() == Some(EIO))
+}
+
+/// re-checks the modlist file after a failed read - when it's gone, the original error is replaced with a single clear one
+fn classify_failure(path: &Path, identity: FileIdentity, error: anyhow::Error) -> anyhow::Error {
+    match is_storage_failure(&error) && FileIdentity::of(path).ok() != Some(identity) {
+        true => ModlistFileLost {
+            path: path.display().to_string(),
+        }
+        .pipe(anyhow::Error::new)
+        .tap(|lost| error!("{lost}")),
+        false => error,
+    }
+}
+
 #[derive(Clone, derivative::Derivative)]
 #[derivative(Debug)]
 pub struct WabbajackFileHandle {
     wabbajack_file_path: Arc<ExistingPathBuf>,
+    identity: FileIdentity,
+    /// once the file is lost, every remaining directive needing it fails right away with the same error
+    lost: Arc<AtomicBool>,
+    /// entry name -> its index within the zip
+    #[derivative(Debug = "ignore")]
+    entries: Arc<BTreeMap<CaseInsensitivePathBuf, usize>>,
+    /// readers which are not in use at the moment - the central directory is read once per reader, not once per entry, and
+    /// there are never more of them than extractions running at the same time
     #[derivative(Debug = "ignore")]
-    preloaded: Arc<Mutex<BTreeMap<CaseInsensitivePathBuf, TempPath>>>,
+    readers: Arc<Mutex<Vec<ZipReader>>>,
+}
+
+fn open_reader(path: &ExistingPath) -> Result<ZipReader> {
+    path.open_file_read()
+        .and_then(|(_, file)| ZipReader::new(file).context("opening file as zip"))
+        .with_context(|| format!("opening modlist file at [{path}]"))
+}
+
+/// files of the modlist file by their (case insensitive) name
+fn index_entries(reader: &mut ZipReader) -> Result<BTreeMap<CaseInsensitivePathBuf, usize>> {
+    (0..reader.len())
+        .filter_map(|idx| {
+            reader
+                .by_index_raw(idx)
+                .with_context(|| format!("reading file idx [{idx}]"))
+                .map(|entry| entry.is_file().then(|| entry.name().to_string()))
+                .and_then(|name| {
+                    name.map(|name| CaseInsensitivePathBuf::from_str(&name).map(|name| (name, idx)))
+                        .transpose()
+                })
+                .transpose()
+        })
+        .collect()
+}
+
+fn extract_entry(reader: &mut ZipReader, idx: usize, name: &CaseInsensitivePathBuf) -> Result<TempPath> {
+    reader
+        .by_index(idx)
+        .with_context(|| format!("opening ({name})"))
+        .and_then(|mut entry| {
+            let expected_size = entry.size();
+            name.as_path()
+                .named_tempfile_with_context()
+                .and_then(|mut output| {
+                    std::io::copy(&mut entry, &mut output)
+                        .context("extracting into temp file")
+                        .and_then(|wrote| {
+                            wrote
+                                .eq(&expected_size)
+                                .then_some(output)
+                                .with_context(|| format!("expected [{expected_size}], found [{wrote}]"))
+                        })
+                })
+        })
+        .map(NamedTempFile::into_temp_path)
 }
 
 impl WabbajackFileHandle {
+    fn lost_error(&self) -> anyhow::Error {
+        ModlistFileLost {
+            path: self.wabbajack_file_path.to_string(),
+        }
+        .pipe(anyhow::Error::new)
+    }
+
+    fn classify_failure(&self, error: anyhow::Error) -> anyhow::Error {
+        classify_failure(Path::new(self.wabbajack_file_path.as_path().as_str()), self.identity, error).tap(|error| {
+            if error.is::<ModlistFileLost>() {
+                self.lost.store(true, Ordering::SeqCst);
+            }
+        })
+    }
+
+    /// [WabbajackFileHandle::get_source_data] along with an open handle to it
+    pub fn open_source_data(&self, source_data_id: uuid::Uuid) -> Result<(TempPath, File)> {
+        self.get_source_data(source_data_id)
+            .and_then(|source_data| {
+                source_data
+                    .open_file_read()
+                    .map(|(_, file)| (source_data, file))
+                    .map_err(|error| self.classify_failure(error))
+            })
+    }
+
+    /// extracts the entry with a reader from the pool, another one is opened when all of them are taken
+    fn extract(&self, idx: usize, name: &CaseInsensitivePathBuf) -> Result<TempPath> {
+        let reader = self.readers.lock().pop();
+        reader
+            .map(Ok)
+            .unwrap_or_else(|| open_reader(&self.wabbajack_file_path).tap_ok(|_| debug!("opened another reader of the modlist file")))
+            .and_then(|mut reader| extract_entry(&mut reader, idx, name).tap_ok(|_| self.readers.lock().push(reader)))
+    }
+
     #[instrument]
     pub fn get_source_data(&self, source_data_id: uuid::Uuid) -> Result<TempPath> {
-        let mut preloaded = self.preloaded.lock();
-        preloaded
-            .remove(&CaseInsensitivePathBuf::from_str(&source_data_id.as_hyphenated().to_string()).context("uuid to be a valid utf8 segment")?)
-            .with_context(|| format!("no [{source_data_id:?}] inside wabbajack archive ({:#?})", preloaded.keys().collect_vec()))
+        if self.lost.load(Ordering::SeqCst) {
+            return Err(self.lost_error());
+        }
+        let name = CaseInsensitivePathBuf::from_str(&source_data_id.as_hyphenated().to_string()).context("uuid to be a valid utf8 segment")?;
+        self.entries
+            .get(&name)
+            .with_context(|| format!("no [{source_data_id:?}] inside wabbajack archive ([{}] entries)", self.entries.len()))
+            .and_then(|idx| {
+                self.extract(*idx, &name)
+                    .map_err(|error| self.classify_failure(error))
+            })
     }
+
+    /// only indexes the entries, see [WabbajackFileHandle::get_source_data]
     pub(crate) fn from_archive(archive_path: &ExistingPath) -> Result<Self> {
-        archive_path
-            .open_file_read()
-            .and_then(|(at_path, _file)| ZipArchive::new(&at_path).with_context(|| format!("opening archive at path [{at_path:#?}]")))
-            .and_then(|mut archive| {
-                archive
-                    .list_paths()
+        let identity = FileIdentity::of(Path::new(archive_path.as_path().as_str())).context("reading modlist file metadata")?;
+        open_reader(archive_path)
+            .and_then(|mut reader| {
+                index_entries(&mut reader)
                     .context("reading archive contents")
-                    .and_then(|paths| {
-                        drop(archive);
-                        let chunk_size = paths.len().div(num_cpus::get()).clamp(1, 64);
-                        paths
-                            .iter()
-                            .collect_vec()
-                            .par_chunks(chunk_size)
-                            .map(|chunk| {
-                                ZipArchive::new(archive_path)
-                                    .with_context(|| format!("opening archive at path [{archive_path:#?}]"))
-                                    .and_then(|mut archive| {
-                                        archive.get_many_handles(chunk).map(|handles| {
-                                            handles.into_iter().map(|(path, handle)| {
-                                                (match handle {
-                                                    ArchiveFileHandle::Zip(named_temp_file) => named_temp_file.into_temp_path(),
-                                                    _ => panic!("come on"),
-                                                })
-                                                .pipe(|temp_path| (path, temp_path))
-                                            })
-                                        })
-                                    })
-                            })
-                            .collect_vec_list()
-                            .into_iter()
-                            .flat_map(|chunk| {
-                                chunk
-                                    .into_iter()
-                                    .try_flat_map(|chunk| chunk.into_iter().map(Ok))
-                            })
-                            .collect::<Result<BTreeMap<_, _>>>()
-                            .context("getting all wabbajack archive file handles")
-                    })
+                    .map(|entries| (entries, reader))
             })
-            .map(Mutex::new)
-            .map(Arc::new)
-            .map(|preloaded| Self {
-                preloaded,
+            .map_err(|error| classify_failure(Path::new(archive_path.as_path().as_str()), identity, error))
+            .map(|(entries, reader)| Self {
+                entries: Arc::new(entries),
+                readers: Arc::new(Mutex::new(vec![reader])),
+                identity,
+                lost: Default::default(),
                 wabbajack_file_path: Arc::new(archive_path.into_owned()),
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, case_insensitive_path::PathExistsUtf8Ext};
+
+    fn not_found() -> anyhow::Error {
+        std::io::Error::from(ErrorKind::NotFound)
+            .pipe(anyhow::Error::new)
+            .context("reading inline file")
+    }
+
+    #[test_log::test]
+    fn test_failure_classification() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let modlist = directory.path().join("modlist.wabbajack");
+        std::fs::write(&modlist, b"modlist")?;
+        let identity = FileIdentity::of(&modlist)?;
+
+        // file is still there, the error is about something else
+        assert!(!classify_failure(&modlist, identity, not_found()).is::<ModlistFileLost>());
+        std::fs::remove_file(&modlist)?;
+        // not an io error, can't be caused by the drive going away
+        assert!(!classify_failure(&modlist, identity, anyhow::anyhow!("bad entry")).is::<ModlistFileLost>());
+        assert!(classify_failure(&modlist, identity, not_found()).is::<ModlistFileLost>());
+        assert!(
+            classify_failure(&modlist, identity, std::io::Error::from_raw_os_error(EIO).pipe(anyhow::Error::new))
+                .to_string()
+                .contains("was the drive removed?")
+        );
+        Ok(())
+    }
+
+    /// modlist file with given entries, stored like wabbajack stores them
+    fn modlist_file(at: &Path, entries: &[(String, Vec<u8>)]) -> Result<ExistingPathBuf> {
+        use std::io::Write;
+        let options = ::zip::write::SimpleFileOptions::default().compression_method(::zip::CompressionMethod::Deflated);
+        std::fs::File::create(at)
+            .context("creating archive")
+            .map(::zip::ZipWriter::new)
+            .and_then(|mut writer| {
+                entries
+                    .iter()
+                    .try_for_each(|(name, contents)| {
+                        writer
+                            .start_file(name.as_str(), options)
+                            .context("starting entry")
+                            .and_then(|_| writer.write_all(contents).context("writing entry"))
+                    })
+                    .and_then(|_| writer.finish().context("finishing archive"))
+            })
+            .and_then(|_| at.exists_utf8())
+    }
+
+    #[test_log::test]
+    fn test_entries_are_extracted_on_demand() -> Result<()> {
+        use rayon::prelude::*;
+        let directory = tempfile::tempdir()?;
+        let ids = (0..256).map(|_| uuid::Uuid::new_v4()).collect::<Vec<_>>();
+        let modlist = ids
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| match idx {
+                // wabbajack doesn't care about the case of the names
+                0 => (id.as_hyphenated().to_string().to_uppercase(), idx.to_string().into_bytes()),
+                _ => (id.as_hyphenated().to_string(), idx.to_string().into_bytes()),
+            })
+            .chain([("modlist".to_string(), b"{}".to_vec())])
+            .collect::<Vec<_>>()
+            .pipe(|entries| modlist_file(&directory.path().join("modlist.wabbajack"), &entries))?;
+        let handle = WabbajackFileHandle::from_archive(&modlist)?;
+        assert_eq!(handle.entries.len(), ids.len() + 1);
+        assert_eq!(handle.readers.lock().len(), 1, "nothing is extracted up front");
+
+        ids.par_iter().enumerate().try_for_each(|(idx, id)| {
+            handle
+                .get_source_data(*id)
+                .and_then(|source_data| std::fs::read_to_string(&source_data).context("reading extracted entry"))
+                .map(|contents| assert_eq!(contents, idx.to_string()))
+        })?;
+        assert!(handle.readers.lock().len() <= rayon::current_num_threads());
+        // entries can be asked for more than once
+        assert_eq!(std::fs::read(handle.get_source_data(ids[1])?)?, b"1");
+        assert!(handle.get_source_data(uuid::Uuid::new_v4()).is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_lost_file_aborts_remaining_reads() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let entry = uuid::Uuid::new_v4();
+        let modlist = directory.path().join("modlist.wabbajack");
+        let handle = modlist_file(&modlist, &[(entry.as_hyphenated().to_string(), b"inline".to_vec())])
+            .and_then(|modlist| WabbajackFileHandle::from_archive(&modlist))?;
+
+        std::fs::remove_file(&modlist)?;
+        assert!(handle.classify_failure(not_found()).is::<ModlistFileLost>());
+        // the entry is still indexed, but the whole group is aborted now
+        assert!(
+            handle
+                .open_source_data(entry)
+                .err()
+                .expect("must be aborted")
+                .is::<ModlistFileLost>()
+        );
+        Ok(())
+    }
+}
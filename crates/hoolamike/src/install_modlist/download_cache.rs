@@ -1,34 +1,86 @@
 use {
     crate::{
         downloaders::{WithArchiveDescriptor, helpers::FutureAnyhowExt},
+        exit_codes::Failure,
+        install_modlist::{
+            foreign_downloads::{self, ForeignDownloads},
+            quarantine::{self, Quarantine},
+        },
         modlist_json::ArchiveDescriptor,
         progress_bars_v2::io_progress_style,
         utils::PathReadWrite,
     },
     anyhow::{Context, Result},
-    case_insensitive_path::{ExistingPath, ExistingPathBuf, IntoUtf8CaseInsensitivePath},
+    case_insensitive_path::{ExistingPath, ExistingPathBuf, IntoUtf8CaseInsensitivePath, PathExistsUtf8Ext},
     futures::{FutureExt, TryFutureExt},
     hex::{FromHex, ToHex},
+    schemars::JsonSchema,
+    serde::{Deserialize, Serialize},
     sha2::{Sha512, digest::Digest},
-    std::{future::ready, hash::Hasher, sync::Arc},
+    std::{fmt, future::ready, hash::Hasher, io::Read, sync::Arc, time::UNIX_EPOCH},
     tap::prelude::*,
-    tokio::io::AsyncReadExt,
     tracing_indicatif::span_ext::IndicatifSpanExt,
     typed_path::Utf8PlatformPathBuf,
 };
 
+/// how much of an archive which is already in the downloads directory gets checked before it's used - `full` hashes it
+/// on every run, `cached` only when its size or modification time changed since it was last hashed, `quick` trusts
+/// archives hashed at least once for as long as their size matches (new ones are always hashed)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verification {
+    Full,
+    #[default]
+    Cached,
+    Quick,
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Full => "full",
+            Self::Cached => "cached",
+            Self::Quick => "quick",
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DownloadCache {
     pub root_directory: ExistingPathBuf,
+    /// `downloaders.extra_search_directories`, consulted when an archive is missing from [Self::root_directory]
+    foreign: Arc<ForeignDownloads>,
+    verification: Verification,
+    quarantine: Arc<Quarantine>,
 }
 impl DownloadCache {
     pub fn new(root_directory: Utf8PlatformPathBuf) -> Result<Self> {
         root_directory
             .create_dir()
             .context("creating download directory")
-            .map(|root_directory| Self { root_directory })
+            .map(|root_directory| Self {
+                quarantine: Arc::new(Quarantine::in_downloads(std::path::Path::new(root_directory.as_path()))),
+                root_directory,
+                foreign: Default::default(),
+                verification: Default::default(),
+            })
             .with_context(|| format!("creating download cache handler at [{root_directory}]"))
     }
+
+    pub fn with_extra_search_directories(self, directories: Vec<std::path::PathBuf>) -> Self {
+        Self {
+            foreign: Arc::new(ForeignDownloads::new(directories)),
+            ..self
+        }
+    }
+
+    pub fn with_verification(self, verification: Verification) -> Self {
+        Self { verification, ..self }
+    }
+
+    pub fn quarantine(&self) -> &Quarantine {
+        &self.quarantine
+    }
 }
 
 async fn read_file_size(path: &ExistingPathBuf) -> Result<u64> {
@@ -38,12 +90,9 @@ async fn read_file_size(path: &ExistingPathBuf) -> Result<u64> {
         .await
 }
 
-#[tracing::instrument(fields(path=%path))]
-async fn calculate_hash_wabbajack(path: &ExistingPath) -> Result<u64> {
-    let size = tokio::fs::metadata(&path)
-        .await
-        .context("no such file")?
-        .len();
+/// hashes on the calling thread, [calculate_hash_wabbajack] runs it on the hashing pool
+pub fn hash_file_wabbajack(path: &ExistingPath) -> Result<u64> {
+    let size = std::fs::metadata(path).context("no such file")?.len();
 
     let file_name = path
         .as_path()
@@ -55,14 +104,11 @@ async fn calculate_hash_wabbajack(path: &ExistingPath) -> Result<u64> {
         pb.pb_set_message(file_name);
     });
 
-    let mut file = tokio::fs::File::open(&path)
-        .map_with_context(|| format!("opening file [{}]", path))
-        .await?
-        .pipe(tokio::io::BufReader::new);
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening file [{}]", path))?;
     let mut buffer = vec![0; crate::BUFFER_SIZE];
     let mut hasher = xxhash_rust::xxh64::Xxh64::new(0);
     loop {
-        match file.read(&mut buffer).await? {
+        match file.read(&mut buffer)? {
             0 => break,
             read => {
                 hasher.update(&buffer[..read]);
@@ -74,11 +120,16 @@ async fn calculate_hash_wabbajack(path: &ExistingPath) -> Result<u64> {
 }
 
 #[tracing::instrument(fields(path=%path))]
-async fn calculate_hash_sha512(path: &ExistingPath) -> Result<[u8; 64]> {
-    let size = tokio::fs::metadata(&path)
+pub async fn calculate_hash_wabbajack(path: &ExistingPath) -> Result<u64> {
+    let path = path.to_owned();
+    crate::blocking_pool::pools()
+        .hashing
+        .run(move || hash_file_wabbajack(&path))
         .await
-        .context("no such file")?
-        .len();
+}
+
+fn hash_file_sha512(path: &ExistingPath) -> Result<[u8; 64]> {
+    let size = std::fs::metadata(path).context("no such file")?.len();
 
     let file_name = path
         .as_path()
@@ -91,15 +142,12 @@ async fn calculate_hash_sha512(path: &ExistingPath) -> Result<[u8; 64]> {
         pb.pb_set_message(&file_name);
     });
 
-    let mut file = tokio::fs::File::open(&path)
-        .map_with_context(|| format!("opening file [{}]", path))
-        .await?
-        .pipe(tokio::io::BufReader::new);
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening file [{}]", path))?;
 
     let mut buffer = vec![0; crate::BUFFER_SIZE];
     let mut hasher = Sha512::new();
     loop {
-        match file.read(&mut buffer).await? {
+        match file.read(&mut buffer)? {
             0 => break,
             read => {
                 hasher.update(&buffer[..read]);
@@ -110,6 +158,15 @@ async fn calculate_hash_sha512(path: &ExistingPath) -> Result<[u8; 64]> {
     Ok(hasher.finalize().into())
 }
 
+#[tracing::instrument(fields(path=%path))]
+async fn calculate_hash_sha512(path: &ExistingPath) -> Result<[u8; 64]> {
+    let path = path.to_owned();
+    crate::blocking_pool::pools()
+        .hashing
+        .run(move || hash_file_sha512(&path))
+        .await
+}
+
 fn to_base_64(input: &[u8]) -> String {
     use base64::prelude::*;
     BASE64_STANDARD.encode(input)
@@ -187,13 +244,138 @@ pub async fn validate_file_size(path: ExistingPathBuf, expected_size: u64) -> Re
     })
 }
 
+/// extension of the file recording the wabbajack hash of a cached archive, written after it was successfully validated
+pub const HASH_SIDECAR_EXTENSION: &str = "xxh64";
+
+/// `Archive.7z` -> `Archive.7z.xxh64`
+pub fn sidecar_path(path: &std::path::Path) -> std::path::PathBuf {
+    path.as_os_str()
+        .to_owned()
+        .tap_mut(|path| {
+            path.push(".");
+            path.push(HASH_SIDECAR_EXTENSION);
+        })
+        .into()
+}
+
+/// size and modification time of the archive when it was hashed, the second line of the sidecar (older sidecars have the
+/// hash only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    size: u64,
+    modified_seconds: u64,
+    modified_nanos: u32,
+}
+
+impl FileStamp {
+    pub fn of(path: &std::path::Path) -> Option<Self> {
+        std::fs::metadata(path).ok().and_then(|metadata| {
+            metadata
+                .modified()
+                .ok()
+                .map(|modified| modified.duration_since(UNIX_EPOCH).unwrap_or_default())
+                .map(|modified| Self {
+                    size: metadata.len(),
+                    modified_seconds: modified.as_secs(),
+                    modified_nanos: modified.subsec_nanos(),
+                })
+        })
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace().map(str::parse::<u64>);
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(Ok(size)), Some(Ok(modified_seconds)), Some(Ok(modified_nanos)), None) => Some(Self {
+                size,
+                modified_seconds,
+                modified_nanos: modified_nanos.try_into().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FileStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.size, self.modified_seconds, self.modified_nanos)
+    }
+}
+
+/// the recorded hash, the first line of the sidecar
+pub fn read_sidecar(path: &std::path::Path) -> Option<String> {
+    std::fs::read_to_string(sidecar_path(path))
+        .ok()
+        .and_then(|sidecar| sidecar.lines().next().map(|hash| hash.trim().to_string()))
+        .filter(|hash| !hash.is_empty())
+}
+
+pub fn read_sidecar_stamp(path: &std::path::Path) -> Option<FileStamp> {
+    std::fs::read_to_string(sidecar_path(path))
+        .ok()
+        .and_then(|sidecar| sidecar.lines().nth(1).and_then(FileStamp::parse))
+}
+
+/// records the hash together with the current [FileStamp] of the archive
+pub fn write_sidecar(path: &std::path::Path, hash: &str) -> Result<()> {
+    let contents = match FileStamp::of(path) {
+        Some(stamp) => format!("{hash}\n{stamp}\n"),
+        None => hash.to_string(),
+    };
+    sidecar_path(path).pipe(|sidecar| std::fs::write(&sidecar, contents).with_context(|| format!("writing hash sidecar [{}]", sidecar.display())))
+}
+
+/// name under which a different version of an archive is kept when the plain name is already taken by another hash,
+/// `Some Mod-1.0.7z` -> `Some Mod-1.0.<hex hash>.7z` (extension stays last so that the archive type can still be guessed)
+pub fn hash_suffixed_name(name: &str, hash: &str) -> Result<String> {
+    let sanitized = name.replace(['/', '\\', ':'], "_");
+    to_u64_from_base_64(hash.to_string()).map(|hash| match sanitized.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}.{hash:016x}.{extension}"),
+        _ => format!("{sanitized}.{hash:016x}"),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CachedEntry {
+    Missing,
+    /// file is there, but it was never validated (or was downloaded by an older version)
+    Unverified,
+    /// recorded hash is the one the modlist expects
+    Matches,
+    /// recorded hash is different - most likely another version of the archive published under the same name
+    Conflicts {
+        recorded: String,
+    },
+}
+
+impl CachedEntry {
+    pub fn inspect(path: &std::path::Path, expected_hash: &str) -> Self {
+        match path.exists() {
+            false => Self::Missing,
+            true => match read_sidecar(path) {
+                None => Self::Unverified,
+                Some(recorded) if recorded == expected_hash => Self::Matches,
+                Some(recorded) => Self::Conflicts { recorded },
+            },
+        }
+    }
+}
+
 impl DownloadCache {
     pub fn download_output_path(&self, file_name: &str) -> Result<Utf8PlatformPathBuf> {
         self.root_directory.join_new(file_name)
     }
-    pub async fn verify(self: Arc<Self>, descriptor: ArchiveDescriptor) -> Result<WithArchiveDescriptor<ExistingPathBuf>> {
-        let ArchiveDescriptor { hash, meta: _, name, size } = descriptor.clone();
-        self.download_output_path(&name)
+
+    /// where the archive should be downloaded to - the plain name, unless a different version already occupies it
+    pub fn output_path_for(&self, descriptor: &ArchiveDescriptor) -> Result<Utf8PlatformPathBuf> {
+        self.download_output_path(&descriptor.name)
+            .and_then(|primary| match CachedEntry::inspect(std::path::Path::new(primary.as_str()), &descriptor.hash) {
+                CachedEntry::Conflicts { .. } => hash_suffixed_name(&descriptor.name, &descriptor.hash).and_then(|name| self.download_output_path(&name)),
+                _ => Ok(primary),
+            })
+    }
+
+    async fn find_cached(&self, file_name: &str) -> Result<Option<ExistingPathBuf>> {
+        self.download_output_path(file_name)
             .pipe(ready)
             .and_then(async |expected_path| {
                 expected_path
@@ -202,21 +384,262 @@ impl DownloadCache {
                     .and_then(async |expected_path| expected_path.exists_async().await)
                     .await
             })
-            .and_then(|exists| match exists {
-                Some(existing_path) => validate_file_size(existing_path.clone(), size)
-                    .and_then(|found_path| validate_hash_wabbajack(found_path, hash))
-                    .map_ok(Some)
-                    .boxed(),
-                None => None.pipe(Ok).pipe(ready).boxed(),
-            })
             .await
-            .and_then(|validated_path| {
-                validated_path
-                    .context("does not exist")
-                    .map(|inner| WithArchiveDescriptor {
-                        inner,
-                        descriptor: descriptor.clone(),
-                    })
+    }
+
+    /// hashes the file unless the recorded hash can be trusted under `verification`, the hash is recorded afterwards
+    async fn verify_cached(path: ExistingPathBuf, hash: String, size: u64, verification: Verification) -> Result<ExistingPathBuf> {
+        let os_path = std::path::Path::new(path.as_path());
+        match (CachedEntry::inspect(os_path, &hash), verification) {
+            (CachedEntry::Conflicts { recorded }, _) => Err(anyhow::anyhow!("recorded hash [{recorded}] does not match expected [{hash}]")),
+            (CachedEntry::Matches, Verification::Quick) => validate_file_size(path, size).await,
+            (CachedEntry::Matches, Verification::Cached) if read_sidecar_stamp(os_path).is_some_and(|recorded| FileStamp::of(os_path) == Some(recorded)) => {
+                validate_file_size(path, size).await
+            }
+            (CachedEntry::Matches | CachedEntry::Missing | CachedEntry::Unverified, _) => validate_file_size(path, size)
+                .and_then(|found_path| validate_hash_wabbajack(found_path, hash.clone()))
+                .await
+                .and_then(|validated| write_sidecar(std::path::Path::new(validated.as_path()), &hash).map(|_| validated)),
+        }
+    }
+
+    /// the first file from the extra search directories which turns out to be this archive, linked (or copied) into the
+    /// downloads directory and recorded as verified there
+    async fn adopt_foreign(&self, descriptor: &ArchiveDescriptor) -> Result<Option<ExistingPathBuf>> {
+        let ArchiveDescriptor { hash, meta, name, size } = descriptor.clone();
+        let candidates = self.foreign.candidates(&name, &hash, &meta);
+        for candidate in candidates {
+            let verified = candidate
+                .exists_utf8()
+                .pipe(ready)
+                .and_then(|candidate| validate_file_size(candidate, size))
+                .and_then(|candidate| validate_hash_wabbajack(candidate, hash.clone()))
+                .await;
+            match verified {
+                Ok(found) => {
+                    let output_path = self.output_path_for(descriptor)?;
+                    tracing::info!(%name, from=%found, "reusing an archive from the extra search directories");
+                    return crate::blocking_pool::pools()
+                        .fs
+                        .run({
+                            let hash = hash.clone();
+                            move || {
+                                let output_path = std::path::Path::new(output_path.as_str());
+                                foreign_downloads::link_or_copy(std::path::Path::new(found.as_path()), output_path)
+                                    .and_then(|_| write_sidecar(output_path, &hash))
+                                    .and_then(|_| output_path.exists_utf8())
+                            }
+                        })
+                        .await
+                        .map(Some);
+                }
+                Err(reason) => tracing::debug!(?reason, ?candidate, "not the archive [{name}]"),
+            }
+        }
+        Ok(None)
+    }
+
+    /// hashes an archive which was just downloaded. one which doesn't match is quarantined rather than left where the next run
+    /// would find it, fail to verify it and download it into the same path again
+    pub async fn verify_download(
+        self: Arc<Self>,
+        WithArchiveDescriptor { inner, descriptor }: WithArchiveDescriptor<ExistingPathBuf>,
+    ) -> Result<WithArchiveDescriptor<ExistingPathBuf>> {
+        let found = calculate_hash_wabbajack(&inner)
+            .await
+            .map(to_base_64_from_u64)?;
+        let archive = std::path::Path::new(inner.as_path()).to_owned();
+        crate::blocking_pool::pools()
+            .fs
+            .run(move || match found == descriptor.hash {
+                true => write_sidecar(&archive, &found)
+                    .and_then(|_| self.quarantine.forget(&descriptor))
+                    .map(|_| WithArchiveDescriptor { inner, descriptor }),
+                false => {
+                    self.quarantine
+                        .quarantine(&archive, &descriptor, &found)
+                        .and_then(|quarantined| {
+                            let mismatch = anyhow::anyhow!(
+                                "hash mismatch, expected [{}], found [{found}] - the download was moved to [{}]",
+                                descriptor.hash,
+                                self.quarantine
+                                    .directory()
+                                    .join(&quarantined.file_name)
+                                    .display()
+                            );
+                            match self.quarantine.repeated_failure(&descriptor)? {
+                                Some(repeated) => Err(Failure::ManualIntervention
+                                    .mark(mismatch.context(quarantine::repeated_failure_hint(&repeated, self.quarantine.directory())))),
+                                None => Err(mismatch.context("it will be downloaded again on the next run")),
+                            }
+                        })
+                }
             })
+            .await
+    }
+
+    pub async fn verify(self: Arc<Self>, descriptor: ArchiveDescriptor) -> Result<WithArchiveDescriptor<ExistingPathBuf>> {
+        let ArchiveDescriptor { hash, meta: _, name, size } = descriptor.clone();
+        let validated = match self.find_cached(&name).await? {
+            Some(existing) => match CachedEntry::inspect(std::path::Path::new(existing.as_path()), &hash) {
+                CachedEntry::Conflicts { recorded } => {
+                    tracing::warn!(
+                        %name,
+                        %recorded,
+                        expected=%hash,
+                        "cached archive was recorded with a different hash, this is most likely a different version - keeping both"
+                    );
+                    match hash_suffixed_name(&name, &hash)
+                        .pipe(ready)
+                        .and_then(async |suffixed| self.find_cached(&suffixed).await)
+                        .await?
+                    {
+                        Some(suffixed) => Self::verify_cached(suffixed, hash, size, self.verification)
+                            .await
+                            .map(Some),
+                        None => Ok(None),
+                    }
+                }
+                _ => Self::verify_cached(existing, hash, size, self.verification)
+                    .await
+                    .map(Some),
+            },
+            None => Ok(None),
+        }?;
+        let validated = match validated {
+            Some(validated) => Some(validated),
+            None => self.adopt_foreign(&descriptor).await?,
+        };
+        validated
+            .context("does not exist")
+            .map(|inner| WithArchiveDescriptor { inner, descriptor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(name: &str, contents: &[u8]) -> ArchiveDescriptor {
+        ArchiveDescriptor {
+            hash: xxhash_rust::xxh64::xxh64(contents, 0).pipe(to_base_64_from_u64),
+            meta: String::new(),
+            name: name.to_string(),
+            size: contents.len() as u64,
+        }
+    }
+
+    #[test_log::test]
+    fn test_sidecar_roundtrip() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let archive = directory.path().join("Some Mod-1.0.7z");
+        assert_eq!(sidecar_path(&archive), directory.path().join("Some Mod-1.0.7z.xxh64"));
+        assert_eq!(read_sidecar(&archive), None);
+        write_sidecar(&archive, "AAAAAAAAAAA=")?;
+        assert_eq!(read_sidecar(&archive).as_deref(), Some("AAAAAAAAAAA="));
+        assert_eq!(read_sidecar_stamp(&archive), None, "archive itself is not there");
+
+        std::fs::write(&archive, b"archive")?;
+        write_sidecar(&archive, "AAAAAAAAAAA=")?;
+        assert_eq!(read_sidecar(&archive).as_deref(), Some("AAAAAAAAAAA="));
+        assert_eq!(read_sidecar_stamp(&archive), FileStamp::of(&archive));
+        assert_eq!(read_sidecar_stamp(&archive).map(|stamp| stamp.size), Some(7));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_hash_suffixed_name() -> Result<()> {
+        let hash = to_base_64_from_u64(0xdeadbeef);
+        assert_eq!(hash_suffixed_name("Some Mod-1.0.7z", &hash)?, "Some Mod-1.0.00000000deadbeef.7z");
+        assert_eq!(hash_suffixed_name("no_extension", &hash)?, "no_extension.00000000deadbeef");
+        assert_eq!(hash_suffixed_name("sub/dir:file.zip", &hash)?, "sub_dir_file.00000000deadbeef.zip");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_conflict_detection() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let archive = directory.path().join("archive.7z");
+        let expected = descriptor("archive.7z", b"new version");
+        assert_eq!(CachedEntry::inspect(&archive, &expected.hash), CachedEntry::Missing);
+        std::fs::write(&archive, b"old version")?;
+        assert_eq!(CachedEntry::inspect(&archive, &expected.hash), CachedEntry::Unverified);
+        let old = descriptor("archive.7z", b"old version");
+        write_sidecar(&archive, &old.hash)?;
+        assert_eq!(CachedEntry::inspect(&archive, &old.hash), CachedEntry::Matches);
+        assert_eq!(CachedEntry::inspect(&archive, &expected.hash), CachedEntry::Conflicts { recorded: old.hash });
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_both_versions_are_retained() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let cache = directory
+            .path()
+            .to_str()
+            .context("utf8")?
+            .pipe(Utf8PlatformPathBuf::from)
+            .pipe(DownloadCache::new)?
+            .pipe(Arc::new);
+        let old = descriptor("archive.7z", b"old version");
+        let new = descriptor("archive.7z", b"new version");
+
+        // first modlist downloads and validates its version, which records the hash
+        let old_path = cache.output_path_for(&old)?;
+        std::fs::write(old_path.as_str(), b"old version")?;
+        cache.clone().verify(old.clone()).await?;
+        assert_eq!(read_sidecar(std::path::Path::new(old_path.as_str())), Some(old.hash.clone()));
+
+        // second modlist expects something else under the same name, it must not overwrite the first one
+        let new_path = cache.output_path_for(&new)?;
+        assert_ne!(new_path, old_path);
+        assert!(cache.clone().verify(new.clone()).await.is_err());
+        std::fs::write(new_path.as_str(), b"new version")?;
+
+        cache.clone().verify(old.clone()).await?;
+        cache.clone().verify(new.clone()).await?;
+        assert_eq!(std::fs::read(old_path.as_str())?, b"old version");
+        assert_eq!(std::fs::read(new_path.as_str())?, b"new version");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_verification_modes() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let archive = directory.path().join("archive.7z");
+        let expected = descriptor("archive.7z", b"good archive");
+        let verify = |verification| {
+            let (archive, hash, size) = (archive.clone(), expected.hash.clone(), expected.size);
+            async move { DownloadCache::verify_cached(archive.exists_utf8()?, hash, size, verification).await }
+        };
+        let touch = |seconds| -> Result<()> {
+            std::fs::File::options()
+                .write(true)
+                .open(&archive)?
+                .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+                .map_err(Into::into)
+        };
+
+        std::fs::write(&archive, b"good archive")?;
+        touch(1000)?;
+        verify(Verification::Quick).await?;
+        assert_eq!(read_sidecar(&archive), Some(expected.hash.clone()), "new archives are always hashed");
+
+        // corrupted on disk without the size or the modification time changing - only a full verification notices
+        std::fs::write(&archive, b"bad archive!")?;
+        touch(1000)?;
+        verify(Verification::Quick).await?;
+        verify(Verification::Cached).await?;
+        assert!(verify(Verification::Full).await.is_err());
+
+        // the modification time changed, cached verification hashes it again
+        touch(2000)?;
+        verify(Verification::Quick).await?;
+        assert!(verify(Verification::Cached).await.is_err());
+
+        std::fs::write(&archive, b"good archive")?;
+        verify(Verification::Full).await?;
+        verify(Verification::Cached).await?;
+        Ok(())
     }
 }
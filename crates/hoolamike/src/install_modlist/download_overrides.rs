@@ -0,0 +1,160 @@
+//! `downloads_overrides.yaml` next to the config - replacement sources for archives whose links died, so that the
+//! .wabbajack file doesn't have to be edited. keys are archive names or hashes, values urls or local file paths:
+//!
+//! ```yaml
+//! SomeMod-1234-1-0.7z: https://mirror.example.org/SomeMod-1234-1-0.7z
+//! "zVw9vTlnpXA=": /home/me/archives/OtherMod.zip
+//! ```
+//!
+//! overridden archives are still checked against the size and hash from the modlist
+
+use {
+    crate::modlist_json::{ArchiveDescriptor, HumanUrl},
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    parking_lot::Mutex,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tracing::{info, warn},
+};
+
+pub const FILE_NAME: &str = "downloads_overrides.yaml";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OverrideSource {
+    Url(HumanUrl),
+    Path(PathBuf),
+}
+
+impl std::fmt::Display for OverrideSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverrideSource::Url(url) => write!(f, "{url}"),
+            OverrideSource::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl std::str::FromStr for OverrideSource {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        match source.starts_with("http://") || source.starts_with("https://") {
+            true => source
+                .parse::<HumanUrl>()
+                .with_context(|| format!("invalid url [{source}]"))
+                .map(Self::Url),
+            false => Ok(Self::Path(PathBuf::from(source))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DownloadOverrides {
+    overrides: BTreeMap<String, OverrideSource>,
+    /// `(archive name, source)` of every override used so far
+    applied: Mutex<Vec<(String, OverrideSource)>>,
+}
+
+impl DownloadOverrides {
+    pub fn parse(contents: &str) -> Result<Self> {
+        serde_yaml::from_str::<Option<BTreeMap<String, String>>>(contents)
+            .context("parsing overrides")?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(archive, source)| {
+                source
+                    .parse::<OverrideSource>()
+                    .with_context(|| format!("override for [{archive}]"))
+                    .map(|source| (archive, source))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+            .map(|overrides| Self {
+                overrides,
+                applied: Default::default(),
+            })
+    }
+
+    /// no file means no overrides
+    pub fn load(path: &Path) -> Result<Self> {
+        match path.exists() {
+            false => Ok(Self::default()),
+            true => std::fs::read_to_string(path)
+                .context("reading file")
+                .and_then(|contents| Self::parse(&contents))
+                .tap_ok(|overrides| info!("[{}] download overrides loaded", overrides.overrides.len()))
+                .with_context(|| format!("loading [{}]", path.display())),
+        }
+    }
+
+    /// the replacement source for this archive (looked up by name, then by hash), recorded as applied
+    pub fn apply(&self, descriptor: &ArchiveDescriptor) -> Option<OverrideSource> {
+        self.overrides
+            .get(&descriptor.name)
+            .or_else(|| self.overrides.get(&descriptor.hash))
+            .cloned()
+            .tap_some(|source| {
+                warn!(name=%descriptor.name, %source, "archive source is overridden by [{FILE_NAME}]");
+                self.applied
+                    .lock()
+                    .push((descriptor.name.clone(), source.clone()));
+            })
+    }
+
+    pub fn is_overridden(&self, descriptor: &ArchiveDescriptor) -> bool {
+        self.overrides.contains_key(&descriptor.name) || self.overrides.contains_key(&descriptor.hash)
+    }
+
+    /// reminds that the installation deviates from the modlist, if it does
+    pub fn report(&self) {
+        let applied = self.applied.lock();
+        if applied.is_empty() {
+            return;
+        }
+        warn!(
+            "[{}] archives came from [{FILE_NAME}] instead of the modlist, this installation deviates from it:\n{}",
+            applied.len(),
+            applied
+                .iter()
+                .sorted()
+                .map(|(name, source)| format!("  - {name} <- {source}"))
+                .join("\n")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn descriptor(name: &str, hash: &str) -> ArchiveDescriptor {
+        serde_json::from_value(json!({"Hash": hash, "Meta": "", "Name": name, "Size": 1})).expect("bad descriptor fixture")
+    }
+
+    #[test_log::test]
+    fn test_overrides_by_name_and_hash() -> Result<()> {
+        let overrides = DownloadOverrides::parse(
+            r#"
+SomeMod-1234-1-0.7z: https://mirror.example.org/SomeMod-1234-1-0.7z
+"zVw9vTlnpXA=": /home/me/archives/OtherMod.zip
+"#,
+        )?;
+        assert_eq!(
+            overrides.apply(&descriptor("SomeMod-1234-1-0.7z", "AAAAAAAAAAA=")),
+            Some(OverrideSource::Url("https://mirror.example.org/SomeMod-1234-1-0.7z".parse()?))
+        );
+        assert_eq!(
+            overrides.apply(&descriptor("renamed.zip", "zVw9vTlnpXA=")),
+            Some(OverrideSource::Path(PathBuf::from("/home/me/archives/OtherMod.zip")))
+        );
+        assert!(!overrides.is_overridden(&descriptor("Untouched.7z", "AAAAAAAAAAA=")));
+        assert_eq!(overrides.apply(&descriptor("Untouched.7z", "AAAAAAAAAAA=")), None);
+        assert_eq!(overrides.applied.lock().len(), 2);
+        assert!(DownloadOverrides::parse("")?.overrides.is_empty());
+        assert!(DownloadOverrides::parse("broken.7z: https://[not-a-host/").is_err());
+        Ok(())
+    }
+}
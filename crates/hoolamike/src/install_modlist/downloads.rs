@@ -1,51 +1,177 @@
 use {
     super::*,
     crate::{
+        atomic_write,
         config_file::{DownloadersConfig, GamesConfig},
         downloaders::{
             CopyFileTask,
             DownloadTask,
+            ExtractEntryTask,
             MergeDownloadTask,
             SyncTask,
             WithArchiveDescriptor,
-            gamefile_source_downloader::{GameFileSourceSynchronizers, get_game_file_source_synchronizers},
+            gamefile_source_downloader::{GameFile, GameFileSourceSynchronizers, extract_archive_entry, get_game_file_source_synchronizers},
             helpers::FutureAnyhowExt,
             mediafire::MediaFireDownloader,
-            nexus::{self, NexusDownloader},
-            wabbajack_cdn::WabbajackCDNDownloader,
+            nexus::{self, NexusDownloader, ValidatedUser},
+            wabbajack_cdn::{CdnPart, WabbajackCDNDownloader, fetch_part},
         },
         error::{MultiErrorCollectExt, TotalResult},
-        modlist_json::{Archive, GoogleDriveState, HttpState, HumanUrl, ManualState, MediaFireState, MegaState, State},
+        install_modlist::{
+            archive_meta::ArchiveMeta,
+            cancellation::{self, CancellationToken},
+            download_overrides::{DownloadOverrides, OverrideSource},
+            quarantine,
+            run_stats::RunStats,
+        },
+        modlist_json::{Archive, ArchiveDescriptor, GoogleDriveState, HttpHeader, HttpState, HumanUrl, ManualState, MediaFireState, MegaState, State},
         progress_bars_v2::IndicatifWrapIoExt,
     },
     anyhow::Result,
     case_insensitive_path::ExistingPathBuf,
     futures::{FutureExt, StreamExt, TryStreamExt},
+    schemars::JsonSchema,
+    serde::{Deserialize, Serialize},
     std::sync::Arc,
+    tokio::io::AsyncWriteExt,
     tracing::{Instrument, debug, instrument},
     typed_path::Utf8PlatformPathBuf,
 };
 
+pub const USER_AGENT: &str = concat!(clap::crate_name!(), "/", clap::crate_version!());
+
+struct ProxySettings {
+    proxy: Option<String>,
+    /// a client was built with `proxy`, the clients live for the whole process so it can't change anymore
+    in_use: bool,
+}
+
+static HTTP_PROXY: parking_lot::Mutex<ProxySettings> = parking_lot::Mutex::new(ProxySettings { proxy: None, in_use: false });
+
+/// routes every request through `proxy` (`downloaders.proxy` in the config), without it HTTPS_PROXY/HTTP_PROXY are honored.
+/// fails when a client was already built with a different proxy - the clients are built once, on first use
+pub fn configure_proxy(proxy: Option<&str>) -> Result<()> {
+    proxy
+        .map(|proxy| reqwest::Proxy::all(proxy).with_context(|| format!("invalid proxy [{proxy}]")))
+        .transpose()
+        .and_then(|_| {
+            let mut settings = HTTP_PROXY.lock();
+            match (settings.proxy.as_deref() == proxy, settings.in_use) {
+                (true, _) => Ok(()),
+                (false, false) => {
+                    settings.proxy = proxy.map(ToOwned::to_owned);
+                    Ok(())
+                }
+                (false, true) => Err(anyhow::anyhow!(
+                    "http clients are already using proxy {:?}, restart hoolamike for {proxy:?} to take effect",
+                    settings.proxy
+                )),
+            }
+        })
+}
+
+/// settings every client in hoolamike shares: user agent, proxy, connection pooling
+pub fn http_client_builder() -> reqwest::ClientBuilder {
+    reqwest::ClientBuilder::new()
+        .user_agent(USER_AGENT)
+        .pool_max_idle_per_host(16)
+        .pool_idle_timeout(std::time::Duration::from_secs(90))
+        .tcp_keepalive(std::time::Duration::from_secs(60))
+        .pipe(|builder| {
+            let mut settings = HTTP_PROXY.lock();
+            settings.in_use = true;
+            match settings
+                .proxy
+                .as_deref()
+                .and_then(|proxy| reqwest::Proxy::all(proxy).ok())
+            {
+                Some(proxy) => builder.proxy(proxy),
+                None => builder,
+            }
+        })
+}
+
+/// the one client downloads go through, so that connections get reused
 pub static HTTP_CLIENT: std::sync::LazyLock<reqwest::Client> = {
     std::sync::LazyLock::new(|| {
-        reqwest::ClientBuilder::new()
-            .user_agent(concat!(clap::crate_name!(), "", clap::crate_version!()))
+        http_client_builder()
             .build()
             .expect("could not construct http client")
     })
 };
 
+/// for hosts listed in `downloaders.insecure_hosts` only - certificates are not verified at all, archive hashes still are
+pub static INSECURE_HTTP_CLIENT: std::sync::LazyLock<reqwest::Client> = {
+    std::sync::LazyLock::new(|| {
+        http_client_builder()
+            .danger_accept_invalid_certs(true)
+            .redirect(insecure_redirect_policy())
+            .build()
+            .expect("could not construct insecure http client")
+    })
+};
+
+/// same as the default policy of reqwest
+const MAX_REDIRECTS: usize = 10;
+
+/// the insecure client is only ever handed urls of `insecure_hosts`, so it doesn't follow redirects leading anywhere else -
+/// certificates of those hosts would go unverified without the user asking for it
+fn insecure_redirect_policy() -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(|attempt| match attempt.previous().len() >= MAX_REDIRECTS {
+        true => attempt.error(format!("more than [{MAX_REDIRECTS}] redirects")),
+        false => match stays_on_host(attempt.previous(), attempt.url()) {
+            true => attempt.follow(),
+            false => attempt.error(format!(
+                "refusing to follow a redirect to [{}] with certificate verification disabled, only hosts listed in downloaders.insecure_hosts are downloaded \
+                 from that way",
+                attempt.url()
+            )),
+        },
+    })
+}
+
+/// whether `next` is on the host the chain of redirects started at
+fn stays_on_host(previous: &[url::Url], next: &url::Url) -> bool {
+    previous
+        .first()
+        .and_then(|first| first.host_str())
+        .zip(next.host_str())
+        .is_some_and(|(first, next)| first.eq_ignore_ascii_case(next))
+}
+
+/// whether `url` points at one of `insecure_hosts` (compared case-insensitively, without the port)
+fn is_insecure_host(insecure_hosts: &[String], url: &HumanUrl) -> bool {
+    AsRef::<url::Url>::as_ref(url)
+        .host_str()
+        .is_some_and(|host| {
+            insecure_hosts
+                .iter()
+                .any(|insecure| insecure.eq_ignore_ascii_case(host))
+        })
+}
+
 #[derive(Clone)]
 pub struct DownloadersInner {
     pub nexus: Option<Arc<NexusDownloader>>,
 }
 
 impl DownloadersInner {
-    pub fn new(DownloadersConfig { nexus, downloads_directory: _ }: DownloadersConfig) -> Result<Self> {
+    pub fn new(
+        client: &reqwest::Client,
+        DownloadersConfig {
+            nexus,
+            downloads_directory: _,
+            proxy: _,
+            insecure_hosts: _,
+            extra_search_directories: _,
+            schedule: _,
+            verification: _,
+        }: DownloadersConfig,
+    ) -> Result<Self> {
         Ok(Self {
             nexus: nexus
                 .api_key
-                .map(NexusDownloader::new)
+                .map(|api_key| NexusDownloader::with_client(client.clone(), api_key))
                 .transpose()?
                 .map(Arc::new),
         })
@@ -58,7 +184,149 @@ pub struct Synchronizers {
     pub config: Arc<DownloadersConfig>,
     inner: DownloadersInner,
     pub(crate) cache: Arc<download_cache::DownloadCache>,
+    client: reqwest::Client,
+    overrides: Arc<DownloadOverrides>,
     game_synchronizers: Arc<GameFileSourceSynchronizers>,
+    stats: Arc<RunStats>,
+}
+
+/// how nexus archives are fetched, decided once per run before any archive is looked at
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NexusAccess {
+    /// premium accounts generate download links through the api
+    Api,
+    /// everyone else has to click "slow download" on the website, every api download would fail
+    Website { reason: String },
+}
+
+impl NexusAccess {
+    /// a configured key nexus does not accept stops the download phase right away - otherwise every nexus archive would fail
+    /// with the same 401
+    async fn check(nexus: Option<Arc<NexusDownloader>>) -> Result<Self> {
+        match nexus {
+            None => Ok(Self::Website {
+                reason: "no nexus api key is configured".into(),
+            }),
+            Some(nexus) => match nexus.validate().await {
+                Ok(ValidatedUser { name, is_premium: true }) => {
+                    Ok(Self::Api.tap(|_| info!("nexus: logged in as [{name}] (premium), archives will be downloaded through the api")))
+                }
+                Ok(ValidatedUser { name, is_premium: false }) => Ok(Self::Website {
+                    reason: format!("[{name}] is not a premium account"),
+                }
+                .tap(|_| info!("nexus: logged in as [{name}] (not premium)"))),
+                Err(reason) => Err(Failure::Config.mark(reason.context(format!(
+                    "the nexus api key could not be validated, no archive was downloaded - {}",
+                    nexus::API_KEY_HELP
+                )))),
+            },
+        }
+        .tap_ok(|access| {
+            if let Self::Website { reason } = access {
+                warn!("nexus: {reason}, archives which are not downloaded yet will have to be fetched through the website")
+            }
+        })
+    }
+}
+
+/// stands in for a missing nexus archive while [NexusAccess::Website] is in effect, they are all reported together
+#[derive(Debug, derive_more::Display)]
+#[display("[{}] needs to be downloaded through the nexus website", _0.name)]
+struct AwaitingNxmClick(ArchiveDescriptor);
+
+impl std::error::Error for AwaitingNxmClick {}
+
+/// context of the errors about a single archive, [crate::modlist_data::ArchiveDependents] tells what else breaks because of it
+#[derive(Debug, Clone, derive_more::Display)]
+#[display("archive [{name}] failed")]
+pub struct FailedArchive {
+    pub name: String,
+    pub hash: String,
+}
+
+impl From<&ArchiveDescriptor> for FailedArchive {
+    fn from(descriptor: &ArchiveDescriptor) -> Self {
+        Self {
+            name: descriptor.name.clone(),
+            hash: descriptor.hash.clone(),
+        }
+    }
+}
+
+/// how many archive names make it into the report, `hoolamike handle-nxm` knows all of them anyway
+const AWAITING_NXM_CLICK_LISTED: usize = 10;
+
+/// replaces the per-archive [AwaitingNxmClick] errors with a single report
+fn report_awaiting_nxm_clicks<T>(prepared: Vec<Result<T>>, access: &NexusAccess) -> Vec<Result<T>> {
+    let (awaiting, prepared): (Vec<_>, Vec<_>) = prepared
+        .into_iter()
+        .partition(|prepared| matches!(prepared, Err(reason) if reason.is::<AwaitingNxmClick>()));
+    let reason = match access {
+        NexusAccess::Website { reason } => reason.as_str(),
+        NexusAccess::Api => "",
+    };
+    let names = awaiting
+        .iter()
+        .filter_map(|awaiting| awaiting.as_ref().err())
+        .filter_map(|reason| reason.downcast_ref::<AwaitingNxmClick>())
+        .map(|AwaitingNxmClick(descriptor)| descriptor.name.as_str())
+        .collect_vec();
+    prepared
+        .into_iter()
+        .chain((!names.is_empty()).then(|| {
+            Err(Failure::ManualIntervention.mark(anyhow::anyhow!(
+                "Manual action is required:\n\n[{count}] nexus archives can't be downloaded through the api ({reason}).\nrun `hoolamike handle-nxm` and click \
+                 \"slow download\" on each page it opens:\n{listed}{more}",
+                count = names.len(),
+                listed = names
+                    .iter()
+                    .take(AWAITING_NXM_CLICK_LISTED)
+                    .map(|name| format!("  - {name}"))
+                    .join("\n"),
+                more = names
+                    .len()
+                    .checked_sub(AWAITING_NXM_CLICK_LISTED)
+                    .filter(|more| *more > 0)
+                    .map(|more| format!("\n  ... and [{more}] more"))
+                    .unwrap_or_default(),
+            )))
+        }))
+        .collect()
+}
+
+/// order archives are handed to the download slots in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum DownloadSchedule {
+    #[default]
+    LargestFirst,
+    SmallestFirst,
+    AsListed,
+}
+
+/// how many of the biggest archives `largest-first` starts right away, they keep their slots for the whole run most of the time
+const LONG_POLE_SLOTS: usize = 3;
+
+impl DownloadSchedule {
+    /// downloads start in exactly this order. `largest-first` puts the `long_poles` biggest ones in front and the rest smallest
+    /// first - the long downloads overlap with everything else, while the small ones keep the other slots moving
+    pub fn order<T>(self, items: Vec<T>, size: impl Fn(&T) -> u64, long_poles: usize) -> Vec<T> {
+        match self {
+            Self::AsListed => items,
+            Self::SmallestFirst => items.tap_mut(|items| items.sort_by_key(&size)),
+            Self::LargestFirst => {
+                let mut largest = items
+                    .into_iter()
+                    .sorted_by_key(|item| std::cmp::Reverse(size(item)))
+                    .collect_vec();
+                let rest = largest.split_off(long_poles.min(largest.len()));
+                largest
+                    .into_iter()
+                    .chain(rest.into_iter().sorted_by_key(&size))
+                    .collect()
+            }
+        }
+    }
 }
 
 enum Either<L, R> {
@@ -73,93 +341,148 @@ async fn copy_local_file(from: ExistingPathBuf, to: Utf8PlatformPathBuf, expecte
         .await
         .context("looking up loading existing file")?;
 
-    let target_file = tokio::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&to)
+    // local copies are quick enough to start over
+    let target_file = atomic_write::open_partial_async(Path::new(to.as_str()), 0)
         .map_with_context(|| format!("opening [{}]", to))
         .await?;
 
-    let copied = tokio::io::copy(&mut source_file, &mut tracing::Span::current().wrap_async_write(expected_size, target_file))
+    let mut writer = tracing::Span::current().wrap_async_write(expected_size, target_file);
+    let copied = tokio::io::copy(&mut source_file, &mut writer)
         .await
         .context("copying")?;
 
     if copied != expected_size {
         anyhow::bail!("[{from:?} -> {to:?}] local copy finished, but received unexpected size (expected [{expected_size}] bytes, downloaded [{copied} bytes])")
     }
+    atomic_write::commit_async(writer.inner, Path::new(to.as_str())).await?;
     to.exists_utf8_async().await
 }
 
-#[instrument(skip(from), fields(chunks=%from.len()))]
-pub async fn stream_merge_file(from: Vec<HumanUrl>, to: Utf8PlatformPathBuf, expected_size: u64) -> Result<ExistingPathBuf> {
-    stream_merge_file_validate(from, to, Some(expected_size)).await
+#[instrument(skip(client, from), fields(chunks=%from.len()))]
+pub async fn stream_merge_file(client: reqwest::Client, from: Vec<CdnPart>, to: Utf8PlatformPathBuf, expected_size: u64) -> Result<ExistingPathBuf> {
+    stream_merge_file_validate(&client, from, to, Some(expected_size), &cancellation::token()).await
 }
 
-#[instrument(level = "DEBUG")]
-pub async fn stream_merge_file_validate(from: Vec<HumanUrl>, to: Utf8PlatformPathBuf, expected_size: Option<u64>) -> Result<ExistingPathBuf> {
-    let target_file = tokio::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&to)
+/// parts being fetched at the same time for a single archive
+const PART_CONCURRENCY: usize = 4;
+
+/// parts are fetched ahead and verified as they arrive, but written in order. the first part which can't be fetched intact
+/// fails the archive, the ones still in flight are dropped with it. parts written completely by an interrupted download are
+/// not fetched again - cancelling stops the download between parts, keeping the ones written
+#[instrument(level = "DEBUG", skip(client, cancellation))]
+pub async fn stream_merge_file_validate(
+    client: &reqwest::Client,
+    from: Vec<CdnPart>,
+    to: Utf8PlatformPathBuf,
+    expected_size: Option<u64>,
+    cancellation: &CancellationToken,
+) -> Result<ExistingPathBuf> {
+    let already_written = atomic_write::partial_len_async(Path::new(to.as_str())).await;
+    let written_parts = from
+        .iter()
+        .scan(0, |end, part| {
+            *end += part.size;
+            Some(*end)
+        })
+        .take_while(|end| *end <= already_written)
+        .count();
+    let downloaded_parts_size = from
+        .iter()
+        .take(written_parts)
+        .map(|part| part.size)
+        .sum::<u64>();
+    if written_parts > 0 {
+        info!("[{to}] resuming after [{written_parts}] parts downloaded earlier");
+    }
+    let target_file = atomic_write::open_partial_async(Path::new(to.as_str()), downloaded_parts_size)
         .map_with_context(|| format!("opening [{}]", to))
         .await?;
 
-    let mut writer = &mut tracing::Span::current().wrap_async_write(expected_size.unwrap_or(0), target_file);
-    let mut downloaded = 0;
-    for from_chunk in from.clone().into_iter() {
-        let mut byte_stream = HTTP_CLIENT
-            .get(from_chunk.to_string())
-            .send()
-            .await
-            .with_context(|| format!("making request to {from_chunk}"))?
-            .bytes_stream();
-        while let Some(chunk) = byte_stream.next().await {
-            match chunk {
-                Ok(chunk) => {
-                    downloaded += chunk.len() as u64;
-                    tokio::io::copy(&mut chunk.as_ref(), &mut writer)
-                        .await
-                        .with_context(|| format!("writing to fd {}", to))?;
-                }
-                Err(message) => Err(message)?,
-            }
+    let mut writer = tracing::Span::current().wrap_async_write(expected_size.unwrap_or(0), target_file);
+    let mut downloaded = downloaded_parts_size;
+    let mut parts = futures::stream::iter(from.iter().skip(written_parts))
+        .map(|part| fetch_part(client, part).map_ok(move |contents| (part, contents)))
+        .buffered(PART_CONCURRENCY);
+    while let Some((part, contents)) = match cancellation.until_cancelled(parts.try_next()).await {
+        Some(next) => next?,
+        None => {
+            atomic_write::keep_partial_async(writer.inner).await?;
+            return Err(cancellation::cancelled_error()).with_context(|| format!("[{to}] stopped after [{downloaded} bytes]"));
         }
-        info!("{from_chunk} finished");
+    } {
+        downloaded += contents.len() as u64;
+        tokio::io::copy(&mut contents.as_slice(), &mut writer)
+            .await
+            .with_context(|| format!("writing to fd {}", to))?;
+        info!("{} finished", part.url);
     }
     if let Some(expected_size) = expected_size
         && downloaded != expected_size
     {
-        anyhow::bail!("[{from:?}] download finished, but received unexpected size (expected [{expected_size}] bytes, downloaded [{downloaded} bytes])")
+        anyhow::bail!("[{to}] download finished, but received unexpected size (expected [{expected_size}] bytes, downloaded [{downloaded} bytes])")
     }
-
+    atomic_write::commit_async(writer.inner, Path::new(to.as_str())).await?;
     to.exists_utf8_async().await
 }
 
-#[instrument]
-pub async fn stream_file(from: HumanUrl, to: Utf8PlatformPathBuf, expected_size: u64) -> Result<ExistingPathBuf> {
-    stream_file_validate(from, to, Some(expected_size)).await
+#[instrument(skip(client))]
+pub async fn stream_file(
+    client: reqwest::Client,
+    from: HumanUrl,
+    to: Utf8PlatformPathBuf,
+    expected_size: u64,
+    headers: Vec<HttpHeader>,
+) -> Result<ExistingPathBuf> {
+    stream_file_validate(&client, from, to, Some(expected_size), &headers).await
 }
 
-#[instrument]
-pub async fn stream_file_validate(from: HumanUrl, to: Utf8PlatformPathBuf, expected_size: Option<u64>) -> Result<ExistingPathBuf> {
-    let target_file = tokio::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&to)
-        .map_with_context(|| format!("opening [{}]", to))
-        .await?;
-    let mut writer = &mut tracing::Span::current().wrap_async_write(expected_size.unwrap_or(0), tokio::io::BufWriter::new(target_file));
-    let mut byte_stream = HTTP_CLIENT
+/// `headers` are the ones the modlist asks for (a `Referer`, an auth token...)
+#[instrument(skip(client))]
+pub async fn stream_file_validate(
+    client: &reqwest::Client,
+    from: HumanUrl,
+    to: Utf8PlatformPathBuf,
+    expected_size: Option<u64>,
+    headers: &[HttpHeader],
+) -> Result<ExistingPathBuf> {
+    // an interrupted download is picked up with a range request, servers which ignore it send everything again
+    let resume_from = atomic_write::partial_len_async(Path::new(to.as_str()))
+        .await
+        .pipe(Some)
+        .filter(|written| *written > 0 && expected_size.is_some_and(|expected_size| *written < expected_size));
+    let response = client
         .get(from.to_string())
+        .pipe(|request| {
+            headers
+                .iter()
+                .fold(request, |request, HttpHeader { name, value }| request.header(name, value))
+        })
+        .pipe(|request| match resume_from {
+            Some(written) => request.header(reqwest::header::RANGE, format!("bytes={written}-")),
+            None => request,
+        })
         .send()
         .await
-        .with_context(|| format!("making request to {from}"))?
-        .bytes_stream();
-    let mut downloaded = 0;
-    while let Some(chunk) = byte_stream.next().await {
+        .with_context(|| format!("making request to {from}"))?;
+    let resumed = resume_from
+        .filter(|_| response.status() == reqwest::StatusCode::PARTIAL_CONTENT)
+        .inspect(|written| info!("[{from}] resuming after [{written}] bytes downloaded earlier"))
+        .unwrap_or(0);
+    let target_file = atomic_write::open_partial_async(Path::new(to.as_str()), resumed)
+        .map_with_context(|| format!("opening [{}]", to))
+        .await?;
+    let mut writer = tracing::Span::current().wrap_async_write(expected_size.unwrap_or(0), tokio::io::BufWriter::new(target_file));
+    let mut byte_stream = response.bytes_stream();
+    let mut downloaded = resumed;
+    let cancellation = cancellation::token();
+    while let Some(chunk) = match cancellation.until_cancelled(byte_stream.next()).await {
+        Some(chunk) => chunk,
+        None => {
+            writer.inner.flush().await.context("flushing download")?;
+            atomic_write::keep_partial_async(writer.inner.into_inner()).await?;
+            return Err(cancellation::cancelled_error()).with_context(|| format!("[{from}] stopped after [{downloaded} bytes]"));
+        }
+    } {
         match chunk {
             Ok(chunk) => {
                 downloaded += chunk.len() as u64;
@@ -176,25 +499,70 @@ pub async fn stream_file_validate(from: HumanUrl, to: Utf8PlatformPathBuf, expec
     {
         anyhow::bail!("[{from}] download finished, but received unexpected size (expected [{expected_size}] bytes, downloaded [{downloaded} bytes])")
     }
-
+    writer.inner.flush().await.context("flushing download")?;
+    atomic_write::commit_async(writer.inner.into_inner(), Path::new(to.as_str())).await?;
     to.exists_utf8_async().await
 }
 impl Synchronizers {
-    pub fn new(config: DownloadersConfig, games_config: GamesConfig) -> Result<Self> {
+    pub fn new(config: DownloadersConfig, games_config: GamesConfig, overrides: Arc<DownloadOverrides>, stats: Arc<RunStats>) -> Result<Self> {
+        let client = HTTP_CLIENT.clone();
         Ok(Self {
             config: Arc::new(config.clone()),
             cache: config
                 .downloads_directory
                 .utf8_platform_path()
                 .and_then(download_cache::DownloadCache::new)
+                .map(|cache| {
+                    cache
+                        .with_extra_search_directories(config.extra_search_directories.clone())
+                        .with_verification(config.verification)
+                })
                 .map(Arc::new)
                 .context("building downloads cache")?,
-            inner: DownloadersInner::new(config).context("building downloaders")?,
+            inner: DownloadersInner::new(&client, config).context("building downloaders")?,
+            client,
+            overrides,
             game_synchronizers: Arc::new(get_game_file_source_synchronizers(games_config).context("building game file source synchronizers")?),
+            stats: stats.tap(|stats| stats.record_verification(config.verification)),
         })
     }
 
+    /// the client downloading from `url`, the insecure one for hosts the user allowed to have broken certificates
+    fn client_for(&self, url: &HumanUrl) -> reqwest::Client {
+        match is_insecure_host(&self.config.insecure_hosts, url) {
+            true => {
+                tracing::warn!(%url, "!!! certificate verification is DISABLED (downloaders.insecure_hosts), only the archive hash protects this download !!!");
+                INSECURE_HTTP_CLIENT.clone()
+            }
+            false => self.client.clone(),
+        }
+    }
+
+    /// local files are copied, urls downloaded - the original source is not looked at
+    fn prepare_override_task(&self, descriptor: ArchiveDescriptor, source: OverrideSource) -> Result<SyncTask> {
+        let output_path = self.cache.output_path_for(&descriptor)?;
+        match source {
+            OverrideSource::Url(url) => DownloadTask {
+                inner: (url, output_path, vec![]),
+                descriptor,
+            }
+            .pipe(SyncTask::from)
+            .pipe(Ok),
+            OverrideSource::Path(path) => path.exists_utf8().map(|path| {
+                CopyFileTask {
+                    inner: (path, output_path),
+                    descriptor,
+                }
+                .pipe(SyncTask::from)
+            }),
+        }
+        .with_context(|| format!("applying override from [{}]", download_overrides::FILE_NAME))
+    }
+
     pub async fn prepare_sync_task(self, Archive { descriptor, state }: Archive) -> Result<SyncTask> {
+        if let Some(source) = self.overrides.apply(&descriptor) {
+            return self.prepare_override_task(descriptor, source);
+        }
         match state.clone() {
             State::Nexus(nexus_state) => self
                 .inner
@@ -206,9 +574,9 @@ impl Synchronizers {
                 .await
                 .and_then(|url| {
                     self.cache
-                        .download_output_path(&descriptor.name)
+                        .output_path_for(&descriptor)
                         .map(|name| DownloadTask {
-                            inner: (url, name),
+                            inner: (url, name, vec![]),
                             descriptor,
                         })
                 })
@@ -217,9 +585,9 @@ impl Synchronizers {
                 .await
                 .and_then(|url| {
                     self.cache
-                        .download_output_path(descriptor.name.as_str())
+                        .output_path_for(&descriptor)
                         .map(|name| DownloadTask {
-                            inner: (url, name),
+                            inner: (url, name, vec![]),
                             descriptor,
                         })
                 })
@@ -231,22 +599,29 @@ impl Synchronizers {
                 .pipe(ready)
                 .and_then(|synchronizer| synchronizer.prepare_copy(state))
                 .await
-                .and_then(|source_path| {
+                .and_then(|game_file| {
                     self.cache
-                        .download_output_path(descriptor.name.as_str())
-                        .map(|name| CopyFileTask {
-                            inner: (source_path, name),
-                            descriptor,
+                        .output_path_for(&descriptor)
+                        .map(|name| match game_file {
+                            GameFile::Plain(source_path) => CopyFileTask {
+                                inner: (source_path, name),
+                                descriptor,
+                            }
+                            .pipe(SyncTask::from),
+                            GameFile::ArchiveEntry { archive, entry } => ExtractEntryTask {
+                                inner: (archive, entry, name),
+                                descriptor,
+                            }
+                            .pipe(SyncTask::from),
                         })
-                })
-                .map(SyncTask::from),
+                }),
 
-            State::Http(HttpState { url, headers: _ }) => url
+            State::Http(HttpState { url, headers }) => url
                 .pipe(|url| {
                     self.cache
-                        .download_output_path(descriptor.name.as_str())
+                        .output_path_for(&descriptor)
                         .map(|name| DownloadTask {
-                            inner: (url, name),
+                            inner: (url, name, headers),
                             descriptor,
                         })
                 })
@@ -256,17 +631,19 @@ impl Synchronizers {
                 .context("fetching from wabbajack cdn")
                 .and_then(|source_urls| {
                     self.cache
-                        .download_output_path(descriptor.name.as_str())
+                        .output_path_for(&descriptor)
                         .map(|name| MergeDownloadTask {
                             inner: (source_urls, name),
                             descriptor,
                         })
                 })
                 .map(SyncTask::from),
-            State::Manual(ManualState { prompt, url }) => Err(anyhow::anyhow!("Manual action is required:\n\nURL: {url}\n{prompt}")),
-            State::Mega(MegaState { url }) => Err(anyhow::anyhow!(
+            State::Manual(ManualState { prompt, url }) => {
+                Err(Failure::ManualIntervention.mark(anyhow::anyhow!("Manual action is required:\n\nURL: {url}\n{prompt}")))
+            }
+            State::Mega(MegaState { url }) => Err(Failure::ManualIntervention.mark(anyhow::anyhow!(
                 "Manual action is required:\n\nURL: {url}\nMega is not supported (yet?), please download the file manually"
-            )),
+            ))),
             State::MediaFire(MediaFireState { url }) => {
                 // it cannot be done
                 MediaFireDownloader::download(url.clone())
@@ -274,78 +651,149 @@ impl Synchronizers {
                     .context("mediafire")
                     .and_then(|url| {
                         self.cache
-                            .download_output_path(descriptor.name.as_str())
+                            .output_path_for(&descriptor)
                             .map(|name| DownloadTask {
-                                inner: (url, name),
+                                inner: (url, name, vec![]),
                                 descriptor,
                             })
                     })
                     .map(SyncTask::from)
                     .with_context(|| format!("Manual action is required:\n\nURL: {url}\nGo to the website and download the file(s) manually"))
+                    .classify(Failure::ManualIntervention)
             }
         }
         .with_context(|| format!("when preparing download for\n{state:#?}"))
     }
 
+    /// the source served the same broken file too many times already, it's not asked for it again (unless it's overridden)
+    fn ensure_not_repeatedly_broken(&self, descriptor: &ArchiveDescriptor) -> Result<()> {
+        match self.overrides.is_overridden(descriptor) {
+            true => Ok(()),
+            false => self
+                .cache
+                .quarantine()
+                .repeated_failure(descriptor)
+                .and_then(|repeated| match repeated {
+                    Some(repeated) => Err(Failure::ManualIntervention.mark(anyhow::anyhow!(quarantine::repeated_failure_hint(
+                        &repeated,
+                        self.cache.quarantine().directory()
+                    )))),
+                    None => Ok(()),
+                }),
+        }
+    }
+
     #[instrument(skip_all, fields(archives=%archives.len()))]
     pub async fn sync_downloads(self, archives: Vec<Archive>) -> TotalResult<WithArchiveDescriptor<ExistingPathBuf>> {
         let base_concurrency = 7;
+        let archives = self
+            .config
+            .schedule
+            .order(archives, |archive| archive.descriptor.size, LONG_POLE_SLOTS);
         let sync_downloads = tracing::Span::current().tap(|pb| {
             pb.pb_set_length(archives.iter().map(|a| a.descriptor.size).sum());
             pb.pb_set_style(&io_progress_style());
         });
+        let nexus_access = match archives
+            .iter()
+            .any(|archive| matches!(archive.state, State::Nexus(_)))
+        {
+            true => NexusAccess::check(self.inner.nexus.clone())
+                .await
+                .map_err(|reason| vec![reason])?,
+            false => NexusAccess::Api,
+        };
+        // for wabbajack and mo2, should the downloads directory ever be handed over to them
+        let metas = archives
+            .iter()
+            .filter_map(|Archive { descriptor, state }| ArchiveMeta::from_state(state).map(|meta| (descriptor.hash.clone(), meta)))
+            .collect::<std::collections::HashMap<_, _>>()
+            .pipe(Arc::new);
+        let kinds = archives
+            .iter()
+            .map(|Archive { descriptor, state }| (descriptor.hash.clone(), state.kind()))
+            .collect::<std::collections::HashMap<_, _>>();
 
-        futures::stream::iter(archives)
-            .map(|Archive { descriptor, state }| async {
-                match self
-                    .cache
-                    .clone()
-                    .verify(descriptor.clone())
-                    .instrument(sync_downloads.clone())
-                    .pipe(tokio::task::spawn)
-                    .map_context("task crashed")
-                    .and_then(ready)
-                    .await
-                {
-                    Ok(verified) => Ok(Either::Left(verified.tap(|verified| {
-                        sync_downloads.pb_inc(verified.descriptor.size);
-                        tracing::debug!(?verified, "succesfully verified a file");
-                    }))),
-                    Err(message) => self
+        futures::stream::iter(archives.into_iter().enumerate())
+            .map(|(position, Archive { descriptor, state })| {
+                async {
+                    let failed = FailedArchive::from(&descriptor);
+                    match self
+                        .cache
                         .clone()
-                        .prepare_sync_task(Archive {
-                            descriptor: descriptor.tap(|descriptor| debug!(?descriptor, ?message, "could not verify a file, it will be downloaded")),
-                            state,
-                        })
+                        .verify(descriptor.clone())
+                        .instrument(sync_downloads.clone())
+                        .pipe(tokio::task::spawn)
+                        .map_context("task crashed")
+                        .and_then(ready)
                         .await
-                        .map(Either::Right),
+                    {
+                        Ok(verified) => Ok(Either::Left(verified.tap(|verified| {
+                            sync_downloads.pb_inc(verified.descriptor.size);
+                            tracing::debug!(?verified, "succesfully verified a file");
+                        }))),
+                        Err(message) => match (&state, &nexus_access) {
+                            (State::Nexus(_), NexusAccess::Website { .. }) => Err(anyhow::Error::new(AwaitingNxmClick(descriptor))),
+                            _ => match self.ensure_not_repeatedly_broken(&descriptor) {
+                                Ok(()) => self
+                                    .clone()
+                                    .prepare_sync_task(Archive {
+                                        descriptor: descriptor
+                                            .tap(|descriptor| debug!(?descriptor, ?message, "could not verify a file, it will be downloaded")),
+                                        state,
+                                    })
+                                    .await
+                                    .map(Either::Right),
+                                Err(reason) => Err(reason),
+                            }
+                            .context(failed),
+                        },
+                    }
                 }
+                .map(move |prepared| (position, prepared))
             })
             .buffer_unordered(num_cpus::get())
             .collect::<Vec<_>>()
             .await
+            // verification finishes in any order, the downloads have to start in the scheduled one
+            .pipe(|prepared| {
+                prepared
+                    .into_iter()
+                    .sorted_by_key(|(position, _)| *position)
+                    .map(|(_, prepared)| prepared)
+                    .collect_vec()
+            })
+            .pipe(|prepared| report_awaiting_nxm_clicks(prepared, &nexus_access))
+            .pipe(crate::game_version::consolidate_version_mismatches)
             .pipe(futures::stream::iter)
             .map_ok(|file| {
-                let name = match &file {
-                    Either::Left(left) => left.descriptor.name.clone(),
-                    Either::Right(right) => match right {
-                        SyncTask::MergeDownload(d) => d.descriptor.name.clone(),
-                        SyncTask::Download(d) => d.descriptor.name.clone(),
-                        SyncTask::Copy(d) => d.descriptor.name.clone(),
-                    },
-                };
+                let failed = match &file {
+                    Either::Left(left) => &left.descriptor,
+                    Either::Right(SyncTask::MergeDownload(d)) => &d.descriptor,
+                    Either::Right(SyncTask::Download(d)) => &d.descriptor,
+                    Either::Right(SyncTask::Copy(d)) => &d.descriptor,
+                    Either::Right(SyncTask::Extract(d)) => &d.descriptor,
+                }
+                .pipe(FailedArchive::from);
+                let name = failed.name.clone();
+                let fetched = matches!(file, Either::Right(_));
+                let downloaded = matches!(file, Either::Right(SyncTask::Download(_) | SyncTask::MergeDownload(_)));
+                let kind = kinds.get(&failed.hash).copied();
 
                 match file {
                     Either::Left(exists) => exists.pipe(Ok).pipe(ready).boxed(),
                     Either::Right(sync_task) => match sync_task {
                         SyncTask::MergeDownload(WithArchiveDescriptor { inner: (from, to), descriptor }) => {
-                            stream_merge_file(from.clone(), to.clone(), descriptor.size)
+                            stream_merge_file(self.client.clone(), from.clone(), to.clone(), descriptor.size)
                                 .map_ok(|inner| WithArchiveDescriptor { inner, descriptor })
                                 .map(move |res| res.with_context(|| format!("when downloading [{from:?} -> {to:?}]")))
                                 .instrument(sync_downloads.clone())
                                 .boxed()
                         }
-                        SyncTask::Download(WithArchiveDescriptor { inner: (from, to), descriptor }) => stream_file(from.clone(), to.clone(), descriptor.size)
+                        SyncTask::Download(WithArchiveDescriptor {
+                            inner: (from, to, headers),
+                            descriptor,
+                        }) => stream_file(self.client_for(&from), from.clone(), to.clone(), descriptor.size, headers)
                             .map_ok(|inner| WithArchiveDescriptor { inner, descriptor })
                             .map(move |res| res.with_context(|| format!("when downloading [{from} -> {to:?}]")))
                             .instrument(sync_downloads.clone())
@@ -355,22 +803,70 @@ impl Synchronizers {
                             .map(move |res| res.with_context(|| format!("when when copying [{from:?} -> {to:?}]")))
                             .instrument(sync_downloads.clone())
                             .boxed(),
+                        SyncTask::Extract(WithArchiveDescriptor {
+                            inner: (archive, entry, to),
+                            descriptor,
+                        }) => extract_archive_entry(archive, entry, to, descriptor.hash.clone())
+                            .map_ok(|inner| WithArchiveDescriptor { inner, descriptor })
+                            .instrument(sync_downloads.clone())
+                            .boxed(),
                     },
                 }
+                .and_then({
+                    let (cache, overrides) = (self.cache.clone(), self.overrides.clone());
+                    move |done| match (fetched && overrides.is_overridden(&done.descriptor), downloaded) {
+                        // the replacement has to be the very archive the modlist expects
+                        (true, _) => cache
+                            .verify(done.descriptor)
+                            .map(|verified| verified.context("overridden archive does not match the modlist"))
+                            .boxed(),
+                        (false, true) => cache.verify_download(done).boxed(),
+                        (false, false) => done.pipe(Ok).pipe(ready).boxed(),
+                    }
+                })
+                .and_then({
+                    let metas = metas.clone();
+                    move |done| match metas
+                        .get(&done.descriptor.hash)
+                        .filter(|_| fetched)
+                        .cloned()
+                    {
+                        Some(meta) => {
+                            let archive = Path::new(done.inner.as_path()).to_owned();
+                            crate::blocking_pool::pools()
+                                .fs
+                                .run(move || meta.write_next_to(&archive))
+                                .map(|written| {
+                                    written
+                                        .map(|_| ())
+                                        .unwrap_or_else(|reason| tracing::warn!(?reason, "could not write the .meta file of [{}]", done.descriptor.name));
+                                    Ok(done)
+                                })
+                                .boxed()
+                        }
+                        None => done.pipe(Ok).pipe(ready).boxed(),
+                    }
+                })
                 .inspect_err({
                     let name = name.clone();
                     move |message| tracing::debug!(?name, ?message)
                 })
                 .inspect_ok({
                     cloned![sync_downloads];
+                    let stats = self.stats.clone();
                     move |res| {
                         sync_downloads.pb_inc(res.descriptor.size);
+                        match (fetched, kind) {
+                            (true, Some(kind)) => stats.record_download(kind, res.descriptor.size),
+                            _ => stats.record_verified(res.descriptor.size),
+                        }
                         tracing::debug!(name, "[OK]");
                     }
                 })
                 .pipe(tokio::task::spawn)
                 .map_context("task crashed")
                 .and_then(ready)
+                .map(move |res| res.context(failed))
                 .boxed()
             })
             .try_buffer_unordered(base_concurrency * 2)
@@ -378,3 +874,190 @@ impl Synchronizers {
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::downloaders::wabbajack_cdn::{
+            MAX_PART_ATTEMPTS,
+            mock_cdn::{MockCdn, PartBehavior},
+        },
+        serde_json::json,
+        std::time::Duration,
+    };
+
+    fn descriptor(name: &str) -> ArchiveDescriptor {
+        serde_json::from_value(json!({"Hash": "AAAAAAAAAAA=", "Meta": "", "Name": name, "Size": 1})).expect("bad descriptor fixture")
+    }
+
+    #[test_log::test]
+    fn test_nexus_archives_are_reported_once() {
+        let access = NexusAccess::Website {
+            reason: "[someone] is not a premium account".into(),
+        };
+        let prepared = (0..12)
+            .map(|idx| Err(anyhow::Error::new(AwaitingNxmClick(descriptor(&format!("mod-{idx}.7z"))))))
+            .chain([Ok(()), Err(anyhow::anyhow!("mega is not supported"))])
+            .collect_vec();
+        let reported = report_awaiting_nxm_clicks(prepared, &access);
+        assert_eq!(reported.len(), 3);
+        let report = reported
+            .iter()
+            .filter_map(|reported| reported.as_ref().err())
+            .map(|reason| reason.to_string())
+            .find(|reason| reason.contains("handle-nxm"))
+            .expect("no report");
+        assert!(report.contains("[12] nexus archives"));
+        assert!(report.contains("[someone] is not a premium account"));
+        assert!(report.contains("  - mod-9.7z"));
+        assert!(!report.contains("  - mod-10.7z"));
+        assert!(report.ends_with("... and [2] more"));
+    }
+
+    #[test_log::test]
+    fn test_insecure_hosts_match_the_host_only() {
+        let insecure_hosts = ["Mirror.example.org".to_string()];
+        let url = |url: &str| url.parse::<HumanUrl>().expect("bad url fixture");
+        assert!(is_insecure_host(&insecure_hosts, &url("https://mirror.example.org:8443/files/mod.7z")));
+        assert!(!is_insecure_host(&insecure_hosts, &url("https://cdn.mirror.example.org/files/mod.7z")));
+        assert!(!is_insecure_host(&insecure_hosts, &url("https://example.org/mirror.example.org/mod.7z")));
+        assert!(!is_insecure_host(&[], &url("https://mirror.example.org/mod.7z")));
+    }
+
+    #[test_log::test]
+    fn test_insecure_redirects_stay_on_the_host() {
+        let url = |url: &str| url.parse::<url::Url>().expect("bad url fixture");
+        let started = [url("https://mirror.example.org/files/mod.7z")];
+        assert!(stays_on_host(&started, &url("https://MIRROR.example.org:8443/cdn/mod.7z")));
+        assert!(!stays_on_host(&started, &url("https://evil.example.com/mod.7z")));
+        assert!(!stays_on_host(&started, &url("https://cdn.mirror.example.org/mod.7z")));
+        assert!(!stays_on_host(&[], &url("https://mirror.example.org/mod.7z")));
+    }
+
+    #[test_log::test]
+    fn test_http_client_configuration() {
+        assert_eq!(USER_AGENT, format!("hoolamike/{}", env!("CARGO_PKG_VERSION")));
+        assert!(configure_proxy(Some("http://[not-a-host")).is_err());
+        assert!(configure_proxy(None).is_ok());
+    }
+
+    #[test_log::test]
+    fn test_proxy_change_after_first_use_fails() -> Result<()> {
+        // the statics are shared with the other tests, one that builds a client could run first
+        let previous = HTTP_PROXY.lock().proxy.clone();
+        let _client = http_client_builder().build()?;
+        assert!(configure_proxy(previous.as_deref()).is_ok(), "the same proxy again is fine");
+        let error = configure_proxy(Some("http://proxy.example.org:3128")).expect_err("a client already uses the previous proxy");
+        assert!(format!("{error:?}").contains("restart"), "{error:?}");
+        assert_eq!(HTTP_PROXY.lock().proxy, previous, "the running clients' proxy is kept");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_download_schedule() {
+        let sizes = vec![5u64, 60_000, 1, 300, 2, 40_000, 7, 10_000, 3];
+        let order = |schedule: DownloadSchedule| schedule.order(sizes.clone(), |size| *size, 3);
+        assert_eq!(order(DownloadSchedule::LargestFirst), [60_000, 40_000, 10_000, 1, 2, 3, 5, 7, 300]);
+        assert_eq!(order(DownloadSchedule::SmallestFirst), [1, 2, 3, 5, 7, 300, 10_000, 40_000, 60_000]);
+        assert_eq!(order(DownloadSchedule::AsListed), sizes);
+        assert_eq!(DownloadSchedule::LargestFirst.order(vec![2u64, 1], |size| *size, 3), [2, 1]);
+    }
+
+    #[test_log::test]
+    fn test_nothing_to_report() {
+        let reported = report_awaiting_nxm_clicks(vec![Ok(1), Err(anyhow::anyhow!("hash mismatch"))], &NexusAccess::Api);
+        assert_eq!(reported.len(), 2);
+    }
+
+    fn output_path(directory: &tempfile::TempDir) -> Result<Utf8PlatformPathBuf> {
+        directory
+            .path()
+            .join("merged.7z")
+            .to_str()
+            .context("utf8")
+            .map(Utf8PlatformPathBuf::from)
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_merge_survives_a_flaky_part() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let cdn = MockCdn::start(512, &[PartBehavior::Intact, PartBehavior::CorruptedTimes(1), PartBehavior::Intact]).await?;
+        let merged = stream_merge_file_validate(
+            &reqwest::Client::new(),
+            cdn.parts.clone(),
+            output_path(&directory)?,
+            Some(3 * 512),
+            &CancellationToken::default(),
+        )
+        .await?;
+        assert_eq!(std::fs::read(&merged)?, [vec![0; 512], vec![1; 512], vec![2; 512]].concat());
+        assert_eq!((0..3).map(|index| cdn.requests(index)).collect_vec(), [1, 2, 1]);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_merge_fails_early_on_a_corrupted_part() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        // the hanging part would keep the download alive forever if the corrupted one didn't abort it
+        let cdn = MockCdn::start(
+            512,
+            &[PartBehavior::Intact, PartBehavior::CorruptedAlways, PartBehavior::Hangs, PartBehavior::Intact],
+        )
+        .await?;
+        let reason = tokio::time::timeout(
+            Duration::from_secs(30),
+            stream_merge_file_validate(
+                &reqwest::Client::new(),
+                cdn.parts.clone(),
+                output_path(&directory)?,
+                Some(4 * 512),
+                &CancellationToken::default(),
+            ),
+        )
+        .await
+        .context("the corrupted part did not abort the download")?
+        .expect_err("corrupted part was accepted");
+        assert!(reason.to_string().contains("part [1]"), "{reason:?}");
+        assert_eq!(cdn.requests(1), MAX_PART_ATTEMPTS);
+        assert!(cdn.requests(3) <= 1);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_cancelled_download_keeps_its_written_parts() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let to = output_path(&directory)?;
+        let partial = atomic_write::partial_path(Path::new(to.as_str()));
+        let cancellation = CancellationToken::default();
+        // the hanging part keeps the download busy until it's cancelled
+        let cdn = MockCdn::start(512, &[PartBehavior::Intact, PartBehavior::Intact, PartBehavior::Hangs]).await?;
+        let download = tokio::spawn({
+            let (parts, to, cancellation) = (cdn.parts.clone(), to.clone(), cancellation.clone());
+            async move { stream_merge_file_validate(&reqwest::Client::new(), parts, to, Some(3 * 512), &cancellation).await }
+        });
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while atomic_write::partial_len_async(Path::new(to.as_str())).await < 2 * 512 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .context("the intact parts were never written")?;
+        cancellation.cancel();
+        let reason = tokio::time::timeout(Duration::from_secs(30), download)
+            .await
+            .context("cancelling did not stop the download")??
+            .expect_err("cancelled download finished");
+        assert!(format!("{reason:?}").contains("installation was cancelled"), "{reason:?}");
+        assert!(!Path::new(to.as_str()).exists(), "a cancelled download is not finished");
+        assert_eq!(std::fs::read(&partial)?, [vec![0; 512], vec![1; 512]].concat());
+
+        // resuming only asks for the part which never arrived
+        let cdn = MockCdn::start(512, &[PartBehavior::Intact, PartBehavior::Intact, PartBehavior::Intact]).await?;
+        let merged = stream_merge_file_validate(&reqwest::Client::new(), cdn.parts.clone(), to, Some(3 * 512), &CancellationToken::default()).await?;
+        assert_eq!(std::fs::read(&merged)?, [vec![0; 512], vec![1; 512], vec![2; 512]].concat());
+        assert_eq!((0..3).map(|index| cdn.requests(index)).collect_vec(), [0, 0, 1]);
+        assert!(!partial.exists());
+        Ok(())
+    }
+}
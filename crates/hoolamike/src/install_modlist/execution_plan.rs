@@ -0,0 +1,428 @@
+//! directives in the order the modlist lists them, and what it takes to resume the installation in the middle of them
+//! (`--start-from-directive`)
+
+use {
+    super::directives::remapped_inline_file::wabbajack_consts::BSA_CREATION_DIR,
+    crate::modlist_json::{Directive, DirectiveKind, directive::create_bsa_directive::CreateBSADirective},
+    anyhow::{Context, Result},
+    case_insensitive_path::CaseInsensitivePathBuf,
+    clap::ValueEnum,
+    itertools::Itertools,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        str::FromStr,
+    },
+    tap::prelude::*,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanEntry {
+    pub kind: DirectiveKind,
+    /// position among directives of the same kind
+    pub kind_index: usize,
+    /// [Directive::directive_hash]
+    pub hash: String,
+    /// hash of the file the directive produces
+    pub file_hash: String,
+}
+
+impl std::fmt::Display for PlanEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} ({})", self.kind, self.kind_index, self.hash)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan {
+    pub entries: Vec<PlanEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectiveSelector {
+    /// [Directive::directive_hash], as printed in logs
+    Hash(String),
+    /// n-th directive of given kind, counting from 0 (e.g. 'FromArchive:120')
+    KindIndex(DirectiveKind, usize),
+}
+
+fn parse_kind(kind: &str) -> Option<DirectiveKind> {
+    DirectiveKind::value_variants()
+        .iter()
+        .copied()
+        .find(|variant| {
+            variant.to_string().eq_ignore_ascii_case(kind)
+                || variant
+                    .to_possible_value()
+                    .is_some_and(|value| value.matches(kind, true))
+        })
+}
+
+impl FromStr for DirectiveSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        let selector = selector.trim();
+        anyhow::ensure!(!selector.is_empty(), "directive selector is empty");
+        // directive hashes are base64, so they never contain a colon
+        match selector.split_once(':') {
+            None => Ok(Self::Hash(selector.to_string())),
+            Some((kind, index)) => parse_kind(kind)
+                .with_context(|| {
+                    format!(
+                        "unknown directive kind [{kind}], expected one of: {}",
+                        DirectiveKind::value_variants().iter().join(", ")
+                    )
+                })
+                .and_then(|kind| {
+                    index
+                        .parse::<usize>()
+                        .with_context(|| format!("[{index}] is not a valid index"))
+                        .map(|index| Self::KindIndex(kind, index))
+                }),
+        }
+        .with_context(|| format!("parsing directive selector [{selector}], expected either a directive hash or '<kind>:<index>'"))
+    }
+}
+
+impl std::fmt::Display for DirectiveSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DirectiveSelector::Hash(hash) => write!(f, "{hash}"),
+            DirectiveSelector::KindIndex(kind, index) => write!(f, "{kind}:{index}"),
+        }
+    }
+}
+
+const MAX_NEAR_MATCHES: usize = 5;
+const MAX_HASH_DISTANCE: usize = 3;
+
+pub fn file_hash(directive: &Directive) -> &str {
+    match directive {
+        Directive::CreateBSA(CreateBSADirective::Bsa(d)) => &d.hash,
+        Directive::CreateBSA(CreateBSADirective::Ba2(d)) => &d.hash,
+        Directive::FromArchive(d) => &d.hash,
+        Directive::InlineFile(d) => &d.hash,
+        Directive::PatchedFromArchive(d) => &d.hash,
+        Directive::RemappedInlineFile(d) => &d.hash,
+        Directive::TransformedTexture(d) => &d.hash,
+        Directive::Unknown(d) => &d.hash,
+    }
+}
+
+pub fn destination(directive: &Directive) -> &CaseInsensitivePathBuf {
+    match directive {
+        Directive::CreateBSA(CreateBSADirective::Bsa(d)) => &d.to,
+        Directive::CreateBSA(CreateBSADirective::Ba2(d)) => &d.to,
+        Directive::FromArchive(d) => &d.to,
+        Directive::InlineFile(d) => &d.to,
+        Directive::PatchedFromArchive(d) => &d.to,
+        Directive::RemappedInlineFile(d) => &d.to,
+        Directive::TransformedTexture(d) => &d.to,
+        Directive::Unknown(d) => &d.to,
+    }
+}
+
+pub fn destination_mut(directive: &mut Directive) -> &mut CaseInsensitivePathBuf {
+    match directive {
+        Directive::CreateBSA(CreateBSADirective::Bsa(d)) => &mut d.to,
+        Directive::CreateBSA(CreateBSADirective::Ba2(d)) => &mut d.to,
+        Directive::FromArchive(d) => &mut d.to,
+        Directive::InlineFile(d) => &mut d.to,
+        Directive::PatchedFromArchive(d) => &mut d.to,
+        Directive::RemappedInlineFile(d) => &mut d.to,
+        Directive::TransformedTexture(d) => &mut d.to,
+        Directive::Unknown(d) => &mut d.to,
+    }
+}
+
+/// hash of the downloaded archive the directive reads from
+pub fn source_archive(directive: &Directive) -> Option<&str> {
+    match directive {
+        Directive::FromArchive(d) => Some(&d.archive_hash_path.source_hash),
+        Directive::PatchedFromArchive(d) => Some(&d.archive_hash_path.source_hash),
+        Directive::TransformedTexture(d) => Some(&d.archive_hash_path.source_hash),
+        Directive::CreateBSA(_) | Directive::InlineFile(_) | Directive::RemappedInlineFile(_) | Directive::Unknown(_) => None,
+    }
+}
+
+/// temp id of the BSA/BA2 archive this directive stages a file for
+pub fn staged_for(directive: &Directive) -> Option<String> {
+    let bsa_creation_dir = BSA_CREATION_DIR.with(|dir| dir.as_str().to_lowercase());
+    destination(directive)
+        .to_string()
+        .replace('\\', "/")
+        .to_lowercase()
+        .pipe(|to| {
+            to.split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(str::to_string)
+                .collect_vec()
+        })
+        .pipe(|segments| match segments.as_slice() {
+            [root, temp_id, _, ..] if *root == bsa_creation_dir => Some(temp_id.clone()),
+            _ => None,
+        })
+}
+
+pub fn temp_id(directive: &Directive) -> Option<String> {
+    match directive {
+        Directive::CreateBSA(CreateBSADirective::Bsa(d)) => Some(d.temp_id.to_lowercase()),
+        Directive::CreateBSA(CreateBSADirective::Ba2(d)) => Some(d.temp_id.to_lowercase()),
+        _ => None,
+    }
+}
+
+impl ExecutionPlan {
+    pub fn new(directives: &[Directive]) -> Self {
+        let mut seen = BTreeMap::<DirectiveKind, usize>::new();
+        directives
+            .iter()
+            .map(|directive| {
+                let kind = directive.directive_kind();
+                let kind_index = seen.entry(kind).or_default().pipe(|count| {
+                    let index = *count;
+                    *count += 1;
+                    index
+                });
+                PlanEntry {
+                    kind,
+                    kind_index,
+                    hash: directive.directive_hash(),
+                    file_hash: file_hash(directive).to_string(),
+                }
+            })
+            .collect_vec()
+            .pipe(|entries| Self { entries })
+    }
+
+    fn near_matches(&self, selector: &DirectiveSelector) -> Vec<&PlanEntry> {
+        match selector {
+            DirectiveSelector::Hash(hash) => self
+                .entries
+                .iter()
+                .filter_map(|entry| match entry.file_hash == *hash {
+                    // an easy mistake - the hash of the produced file is printed next to the directive in most errors
+                    true => Some((0, entry)),
+                    false => strsim::damerau_levenshtein(hash, &entry.hash).pipe(|distance| (distance <= MAX_HASH_DISTANCE).then_some((distance, entry))),
+                })
+                .sorted_by_key(|(distance, _)| *distance)
+                .map(|(_, entry)| entry)
+                .take(MAX_NEAR_MATCHES)
+                .collect(),
+            DirectiveSelector::KindIndex(kind, index) => self
+                .entries
+                .iter()
+                .filter(|entry| entry.kind == *kind)
+                .sorted_by_key(|entry| entry.kind_index.abs_diff(*index))
+                .take(MAX_NEAR_MATCHES)
+                .collect(),
+        }
+    }
+
+    /// position of the selected directive
+    pub fn locate(&self, selector: &DirectiveSelector) -> Result<usize> {
+        self.entries
+            .iter()
+            .position(|entry| match selector {
+                DirectiveSelector::Hash(hash) => entry.hash == *hash,
+                DirectiveSelector::KindIndex(kind, index) => entry.kind == *kind && entry.kind_index == *index,
+            })
+            .with_context(|| {
+                let count = |kind: DirectiveKind| {
+                    self.entries
+                        .iter()
+                        .filter(|entry| entry.kind == kind)
+                        .count()
+                };
+                let reason = match selector {
+                    DirectiveSelector::Hash(_) => "no directive has this hash".to_string(),
+                    DirectiveSelector::KindIndex(kind, _) => match count(*kind) {
+                        0 => format!("the modlist has no [{kind}] directives"),
+                        count => format!("the modlist has [{count}] [{kind}] directives ({kind}:0..={kind}:{})", count - 1),
+                    },
+                };
+                match self.near_matches(selector) {
+                    near if near.is_empty() => format!("directive [{selector}] not found: {reason}"),
+                    near => format!(
+                        "directive [{selector}] not found: {reason}, did you mean one of:\n{}",
+                        near.iter().map(|entry| format!("  - {entry}")).join("\n")
+                    ),
+                }
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resume {
+    /// position of the selected directive
+    pub start: usize,
+    /// positions of directives that are going to be handled
+    pub run: BTreeSet<usize>,
+    /// directives before the selected one, which are assumed to be done by a previous run
+    pub skipped: usize,
+    /// directives before the selected one that still have to run, because remaining BSA/BA2 archives are built from their output
+    pub pulled_back: usize,
+    /// hashes of downloaded archives the remaining directives read from
+    pub required_archives: BTreeSet<String>,
+}
+
+/// everything from `start` onwards, plus earlier directives staging files for archives which are not built yet
+pub fn resume_from(directives: &[Directive], start: usize) -> Resume {
+    let pending_archives = directives
+        .iter()
+        .skip(start)
+        .filter_map(temp_id)
+        .collect::<BTreeSet<_>>();
+    let pulled_back = directives
+        .iter()
+        .take(start)
+        .positions(|directive| staged_for(directive).is_some_and(|temp_id| pending_archives.contains(&temp_id)))
+        .collect::<BTreeSet<_>>();
+    let run = pulled_back
+        .iter()
+        .copied()
+        .chain(start..directives.len())
+        .collect::<BTreeSet<_>>();
+    Resume {
+        required_archives: run
+            .iter()
+            .filter_map(|position| source_archive(&directives[*position]))
+            .map(str::to_string)
+            .collect(),
+        start,
+        skipped: start - pulled_back.len(),
+        pulled_back: pulled_back.len(),
+        run,
+    }
+}
+
+impl Resume {
+    pub fn summary(&self, plan: &ExecutionPlan, total_archives: usize) -> String {
+        format!(
+            "starting from directive [{}]: [{}] earlier directives are skipped as done by a previous run, [{}] earlier directives are handled again because \
+             BSA/BA2 archives which are not built yet need their output, [{}] directives remain. [{}] of [{total_archives}] archives are required by them",
+            plan.entries[self.start],
+            self.skipped,
+            self.pulled_back,
+            self.run.len() - self.pulled_back,
+            self.required_archives.len(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::modlist_json::directive::fixtures::{directive, from_archive_in, inline_file},
+        serde_json::json,
+    };
+
+    fn create_bsa(temp_id: &str) -> Directive {
+        directive(json!({
+            "$type": "CreateBSA",
+            "Hash": "AAAAAAAAAAA=",
+            "Size": 0,
+            "To": format!(r"mods\main\{temp_id}.ba2"),
+            "TempID": temp_id,
+            "FileStates": [],
+            "State": {"$type": "BA2State, Compression.BSA", "HasNameTable": true, "HeaderMagic": "BTDX", "Type": 0, "Version": 1},
+        }))
+    }
+
+    /// two archives built from staged files, with the first one's inputs scattered before the resume point
+    fn fixture_plan() -> Vec<Directive> {
+        vec![
+            from_archive_in("archive-a", r"mods\main\plugin.esp"),
+            from_archive_in("archive-b", r"TEMP_BSA_FILES\first\meshes\a.nif"),
+            inline_file(r"mods\main\meta.ini"),
+            from_archive_in("archive-c", r"TEMP_BSA_FILES\second\meshes\b.nif"),
+            from_archive_in("archive-d", r"mods\main\textures\c.dds"),
+            from_archive_in("archive-e", r"TEMP_BSA_FILES\first\meshes\d.nif"),
+            create_bsa("first"),
+            inline_file(r"mods\main\other.ini"),
+        ]
+    }
+
+    #[test_log::test]
+    fn test_selectors_are_parsed() -> Result<()> {
+        assert_eq!(
+            "FromArchive:12".parse::<DirectiveSelector>()?,
+            DirectiveSelector::KindIndex(DirectiveKind::FromArchive, 12)
+        );
+        assert_eq!(
+            "from-archive:3".parse::<DirectiveSelector>()?,
+            DirectiveSelector::KindIndex(DirectiveKind::FromArchive, 3)
+        );
+        assert_eq!(
+            "createbsa:0".parse::<DirectiveSelector>()?,
+            DirectiveSelector::KindIndex(DirectiveKind::CreateBSA, 0)
+        );
+        assert_eq!(" abc+/= ".parse::<DirectiveSelector>()?, DirectiveSelector::Hash("abc+/=".into()));
+        assert!(format!("{:?}", "Nope:1".parse::<DirectiveSelector>().unwrap_err()).contains("InlineFile"));
+        assert!("FromArchive:x".parse::<DirectiveSelector>().is_err());
+        assert!("".parse::<DirectiveSelector>().is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_selectors_are_validated_against_the_plan() -> Result<()> {
+        let directives = fixture_plan();
+        let plan = ExecutionPlan::new(&directives);
+        assert_eq!(plan.locate(&DirectiveSelector::KindIndex(DirectiveKind::FromArchive, 3))?, 4);
+        assert_eq!(plan.locate(&DirectiveSelector::KindIndex(DirectiveKind::InlineFile, 1))?, 7);
+        assert_eq!(plan.locate(&DirectiveSelector::Hash(directives[5].directive_hash()))?, 5);
+
+        let out_of_range = format!(
+            "{:?}",
+            plan.locate(&DirectiveSelector::KindIndex(DirectiveKind::FromArchive, 9))
+                .unwrap_err()
+        );
+        assert!(out_of_range.contains("FromArchive:0..=FromArchive:4"), "{out_of_range}");
+        assert!(out_of_range.contains("FromArchive:4 ("), "{out_of_range}");
+
+        let missing_kind = format!(
+            "{:?}",
+            plan.locate(&DirectiveSelector::KindIndex(DirectiveKind::TransformedTexture, 0))
+                .unwrap_err()
+        );
+        assert!(missing_kind.contains("no [TransformedTexture] directives"), "{missing_kind}");
+
+        let typo = directives[3]
+            .directive_hash()
+            .pipe(|hash| format!("{}x", &hash[..hash.len() - 1]));
+        let typo = format!("{:?}", plan.locate(&DirectiveSelector::Hash(typo)).unwrap_err());
+        assert!(typo.contains(&format!("FromArchive:2 ({})", directives[3].directive_hash())), "{typo}");
+
+        let file_hash = format!(
+            "{:?}",
+            plan.locate(&DirectiveSelector::Hash(r"file-mods\main\meta.ini".into()))
+                .unwrap_err()
+        );
+        assert!(file_hash.contains("InlineFile:0 ("), "{file_hash}");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_resuming_keeps_inputs_of_archives_not_built_yet() {
+        let directives = fixture_plan();
+        let resume = resume_from(&directives, 4);
+        assert_eq!(resume.run, BTreeSet::from([1, 4, 5, 6, 7]));
+        assert_eq!(resume.skipped, 3);
+        assert_eq!(resume.pulled_back, 1);
+        assert_eq!(
+            resume.required_archives,
+            ["archive-b", "archive-d", "archive-e"]
+                .map(String::from)
+                .into()
+        );
+
+        let after_archive = resume_from(&directives, 7);
+        assert_eq!(after_archive.run, BTreeSet::from([7]));
+        assert!(after_archive.required_archives.is_empty());
+
+        let everything = resume_from(&directives, 0);
+        assert_eq!(everything.run.len(), directives.len());
+        assert_eq!(everything.skipped, 0);
+    }
+}
@@ -0,0 +1,181 @@
+//! archives downloaded by Wabbajack or MO2 somewhere else (the downloads folder of a windows install, most of the time) -
+//! looked up read-only when an archive is missing from downloads_directory, by its name, by the `<name>_<hash>` names
+//! Wabbajack gives duplicates, or by a `.meta` file next to it which matches the one from the modlist
+
+use {
+    super::archive_meta::{ArchiveMeta, META_EXTENSION},
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::OnceLock,
+    },
+    tap::prelude::*,
+    tracing::{debug, info},
+};
+
+#[derive(Debug, Default)]
+struct Index {
+    /// lowercase file name -> files
+    by_name: HashMap<String, Vec<PathBuf>>,
+    /// sources from `.meta` files, with the archives they sit next to
+    by_meta: Vec<(ArchiveMeta, PathBuf)>,
+}
+
+#[derive(Debug, Default)]
+pub struct ForeignDownloads {
+    directories: Vec<PathBuf>,
+    /// listing 300 GB worth of downloads is done once, and only when some archive is missing
+    index: OnceLock<Index>,
+}
+
+/// names Wabbajack keeps this archive under - `Some Mod.7z`, `Some Mod_<hex hash>.7z` and `Some Mod.7z_<hex hash>`
+pub fn wabbajack_names(name: &str, hash: &str) -> Vec<String> {
+    let hexes = crate::install_modlist::download_cache::to_u64_from_base_64(hash.to_string())
+        .map(|hash| vec![format!("{hash:016x}"), hex::encode(hash.to_le_bytes())])
+        .unwrap_or_default();
+    std::iter::once(name.to_string())
+        .chain(hexes.into_iter().flat_map(|hex| {
+            match name.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() => Some(format!("{stem}_{hex}.{extension}")),
+                _ => None,
+            }
+            .into_iter()
+            .chain(std::iter::once(format!("{name}_{hex}")))
+        }))
+        .unique()
+        .collect()
+}
+
+impl Index {
+    fn build(directories: &[PathBuf]) -> Self {
+        directories
+            .iter()
+            .flat_map(|directory| {
+                std::fs::read_dir(directory)
+                    .tap_err(|reason| tracing::warn!(?reason, "could not list [{}], skipping it", directory.display()))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_file())
+            })
+            .fold(Self::default(), |index, path| {
+                index.tap_mut(|index| {
+                    match path
+                        .extension()
+                        .is_some_and(|extension| extension == META_EXTENSION)
+                    {
+                        true => {
+                            if let Some(meta) = std::fs::read_to_string(&path)
+                                .ok()
+                                .and_then(|meta| ArchiveMeta::parse(&meta))
+                            {
+                                index.by_meta.push((meta, path.with_extension("")));
+                            }
+                        }
+                        false => {
+                            if let Some(name) = path
+                                .file_name()
+                                .map(|name| name.to_string_lossy().to_lowercase())
+                            {
+                                index.by_name.entry(name).or_default().push(path);
+                            }
+                        }
+                    }
+                })
+            })
+            .tap(|index| info!(archives = index.by_name.len(), metas = index.by_meta.len(), "indexed extra search directories"))
+    }
+}
+
+impl ForeignDownloads {
+    pub fn new(directories: Vec<PathBuf>) -> Self {
+        Self {
+            directories,
+            index: OnceLock::new(),
+        }
+    }
+
+    /// files which might be this archive, they still have to be checked against its size and hash. `meta` is the one from
+    /// the modlist
+    pub fn candidates(&self, name: &str, hash: &str, meta: &str) -> Vec<PathBuf> {
+        if self.directories.is_empty() {
+            return vec![];
+        }
+        let index = self.index.get_or_init(|| Index::build(&self.directories));
+        wabbajack_names(name, hash)
+            .into_iter()
+            .filter_map(|name| index.by_name.get(&name.to_lowercase()))
+            .flatten()
+            .chain(ArchiveMeta::parse(meta).into_iter().flat_map(|meta| {
+                index
+                    .by_meta
+                    .iter()
+                    .filter(move |(found, archive)| found.matches(&meta) && archive.exists())
+                    .map(|(_, archive)| archive)
+            }))
+            .cloned()
+            .unique()
+            .collect_vec()
+            .tap(|candidates| debug!(%name, ?candidates, "looked for the archive in extra search directories"))
+    }
+}
+
+/// a hardlink when both are on the same filesystem, a copy otherwise - the original is never touched
+pub fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+    std::fs::hard_link(from, to)
+        .or_else(|_| std::fs::copy(from, to).map(|_| ()))
+        .with_context(|| format!("linking [{}] to [{}]", from.display(), to.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::install_modlist::download_cache::to_base_64_from_u64};
+
+    #[test_log::test]
+    fn test_wabbajack_names() {
+        let hash = to_base_64_from_u64(0xdeadbeef);
+        assert_eq!(
+            wabbajack_names("Some Mod.7z", &hash),
+            [
+                "Some Mod.7z",
+                "Some Mod_00000000deadbeef.7z",
+                "Some Mod.7z_00000000deadbeef",
+                "Some Mod_efbeadde00000000.7z",
+                "Some Mod.7z_efbeadde00000000",
+            ]
+        );
+    }
+
+    #[test_log::test]
+    fn test_candidates_by_name_and_meta() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let hash = to_base_64_from_u64(0xdeadbeef);
+        let write = |name: &str, contents: &str| std::fs::write(directory.path().join(name), contents);
+        write("some mod_00000000deadbeef.7z", "renamed by wabbajack")?;
+        write("Renamed By Hand.zip", "found through its meta")?;
+        write(
+            "Renamed By Hand.zip.meta",
+            "[General]\r\n  gameName=skyrimspecialedition\r\nmodID=1234\r\nfileID=5678\r\ninstalled=true\r\n",
+        )?;
+        write("Other File.zip", "same mod, other file")?;
+        write("Other File.zip.meta", "[General]\ngameName=SkyrimSpecialEdition\nmodID=1234\nfileID=9999\n")?;
+        write("Unrelated.7z", "")?;
+        let foreign = ForeignDownloads::new(vec![directory.path().to_owned()]);
+        assert_eq!(
+            foreign.candidates("Some Mod.7z", &hash, "[General]\ngameName=SkyrimSpecialEdition\nmodID=1234\nfileID=5678\n"),
+            [
+                directory.path().join("some mod_00000000deadbeef.7z"),
+                directory.path().join("Renamed By Hand.zip"),
+            ]
+        );
+        assert!(foreign.candidates("Missing.7z", &hash, "").is_empty());
+        assert!(
+            ForeignDownloads::default()
+                .candidates("Some Mod.7z", &hash, "")
+                .is_empty()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+//! archives which finished downloading, but came out with a different hash (a corrupted transfer, or an html error page the
+//! server sent in place of the file) are moved to `downloads/.quarantine/<name>.<unix millis>` rather than being downloaded
+//! into the same path again on every run. every such failure is recorded in `.quarantine/index.json` together with the hash
+//! that came out - once the source serves the very same bad file [MAX_IDENTICAL_FAILURES] times in a row it's not tried again
+
+use {
+    crate::modlist_json::ArchiveDescriptor,
+    anyhow::{Context, Result},
+    chrono::{DateTime, Utc},
+    parking_lot::Mutex,
+    serde::{Deserialize, Serialize},
+    std::path::{Path, PathBuf},
+    tap::prelude::*,
+};
+
+pub const QUARANTINE_DIRECTORY: &str = ".quarantine";
+
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// identical bad hashes in a row after which the archive is not downloaded from its source anymore
+pub const MAX_IDENTICAL_FAILURES: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedDownload {
+    pub name: String,
+    pub expected_hash: String,
+    pub found_hash: String,
+    /// file name within the quarantine directory
+    pub file_name: String,
+    pub at: DateTime<Utc>,
+}
+
+/// oldest first
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuarantineIndex {
+    failures: Vec<QuarantinedDownload>,
+}
+
+#[derive(Debug)]
+pub struct Quarantine {
+    directory: PathBuf,
+    index_write: Mutex<()>,
+}
+
+impl Quarantine {
+    pub fn in_downloads(downloads_directory: &Path) -> Self {
+        Self {
+            directory: downloads_directory.join(QUARANTINE_DIRECTORY),
+            index_write: Mutex::new(()),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.directory.join(INDEX_FILE_NAME)
+    }
+
+    fn read_index(&self) -> Result<QuarantineIndex> {
+        let path = self.index_path();
+        match path.exists() {
+            false => Ok(QuarantineIndex::default()),
+            true => std::fs::read_to_string(&path)
+                .context("reading")
+                .and_then(|index| serde_json::from_str(&index).context("parsing"))
+                .with_context(|| format!("reading quarantine index at [{}]", path.display())),
+        }
+    }
+
+    fn update_index(&self, update: impl FnOnce(&mut QuarantineIndex)) -> Result<()> {
+        let _guard = self.index_write.lock();
+        self.read_index()
+            .map(|index| index.tap_mut(update))
+            .and_then(|index| {
+                crate::atomic_write::write_atomically(&self.index_path(), |file| {
+                    serde_json::to_writer_pretty(file, &index).context("serializing quarantine index")
+                })
+            })
+    }
+
+    /// moves the archive out of the downloads directory and records the failure
+    pub fn quarantine(&self, archive: &Path, descriptor: &ArchiveDescriptor, found_hash: &str) -> Result<QuarantinedDownload> {
+        let at = Utc::now();
+        let file_name = format!("{}.{}", descriptor.name.replace(['/', '\\', ':'], "_"), at.timestamp_millis());
+        let record = QuarantinedDownload {
+            name: descriptor.name.clone(),
+            expected_hash: descriptor.hash.clone(),
+            found_hash: found_hash.to_string(),
+            // a quarantined file is never replaced
+            file_name: (0..)
+                .map(|attempt| match attempt {
+                    0 => file_name.clone(),
+                    attempt => format!("{file_name}.{attempt}"),
+                })
+                .find(|file_name| !self.directory.join(file_name).exists())
+                .expect("some name is free"),
+            at,
+        };
+        let destination = self.directory.join(&record.file_name);
+        std::fs::create_dir_all(&self.directory)
+            .with_context(|| format!("creating [{}]", self.directory.display()))
+            .and_then(|_| std::fs::rename(archive, &destination).with_context(|| format!("moving [{}] to [{}]", archive.display(), destination.display())))
+            .and_then(|_| self.update_index(|index| index.failures.push(record.clone())))
+            .map(|_| record)
+    }
+
+    /// the last failure, when the last [MAX_IDENTICAL_FAILURES] downloads of the archive all came out with the same hash
+    pub fn repeated_failure(&self, descriptor: &ArchiveDescriptor) -> Result<Option<QuarantinedDownload>> {
+        self.read_index().map(|QuarantineIndex { failures }| {
+            let latest = failures
+                .into_iter()
+                .rev()
+                .filter(|failure| failure.expected_hash == descriptor.hash)
+                .take(MAX_IDENTICAL_FAILURES)
+                .collect::<Vec<_>>();
+            match latest.len() == MAX_IDENTICAL_FAILURES
+                && latest
+                    .iter()
+                    .all(|failure| failure.found_hash == latest[0].found_hash)
+            {
+                true => latest.into_iter().next(),
+                false => None,
+            }
+        })
+    }
+
+    /// the archive came out right after all, the failures don't count anymore (quarantined files stay until pruned)
+    pub fn forget(&self, descriptor: &ArchiveDescriptor) -> Result<()> {
+        match self.index_path().exists() {
+            false => Ok(()),
+            true => self.update_index(|index| {
+                index
+                    .failures
+                    .retain(|failure| failure.expected_hash != descriptor.hash)
+            }),
+        }
+    }
+
+    /// quarantined files along with their sizes
+    pub fn files(&self) -> Result<Vec<(PathBuf, u64)>> {
+        match self.directory.exists() {
+            false => Ok(vec![]),
+            true => std::fs::read_dir(&self.directory)
+                .with_context(|| format!("reading [{}]", self.directory.display()))?
+                .map(|entry| {
+                    entry
+                        .and_then(|entry| entry.metadata().map(|metadata| (entry.path(), metadata)))
+                        .with_context(|| format!("listing [{}]", self.directory.display()))
+                })
+                .filter(|entry| !matches!(entry, Ok((path, metadata)) if !metadata.is_file() || path.file_name().is_some_and(|name| name == INDEX_FILE_NAME)))
+                .map(|entry| entry.map(|(path, metadata)| (path, metadata.len())))
+                .collect(),
+        }
+    }
+}
+
+/// what's left to do once the source kept serving the same broken file
+pub fn repeated_failure_hint(
+    QuarantinedDownload {
+        name,
+        expected_hash,
+        found_hash,
+        ..
+    }: &QuarantinedDownload,
+    quarantine: &Path,
+) -> String {
+    format!(
+        "[{name}] was downloaded {MAX_IDENTICAL_FAILURES} times in a row and came out as the same wrong file (expected hash [{expected_hash}], found \
+         [{found_hash}]) - its source keeps serving a broken file, so it's not downloaded again. download it from a mirror or by hand and put it into the \
+         downloads directory, or point {} at it. the broken copies are kept in [{}], `hoolamike downloads prune` lists them",
+        crate::install_modlist::download_overrides::FILE_NAME,
+        quarantine.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::install_modlist::download_cache::to_base_64_from_u64, serde_json::json};
+
+    fn archive(name: &str, contents: &[u8]) -> ArchiveDescriptor {
+        serde_json::from_value(json!({
+            "Hash": xxhash_rust::xxh64::xxh64(contents, 0).pipe(to_base_64_from_u64),
+            "Meta": "",
+            "Name": name,
+            "Size": contents.len(),
+        }))
+        .expect("bad descriptor fixture")
+    }
+
+    #[test_log::test]
+    fn test_identical_failures_stop_retries() -> Result<()> {
+        let downloads = tempfile::tempdir()?;
+        let quarantine = Quarantine::in_downloads(downloads.path());
+        let expected = archive("Mod.7z", b"the real archive");
+        let download = |contents: &[u8]| -> Result<PathBuf> {
+            downloads
+                .path()
+                .join("Mod.7z")
+                .pipe(|path| std::fs::write(&path, contents).map(|_| path))
+                .map_err(Into::into)
+        };
+
+        let first = quarantine.quarantine(&download(b"<html>503</html>")?, &expected, "html")?;
+        assert!(!downloads.path().join("Mod.7z").exists());
+        assert!(quarantine.directory().join(&first.file_name).exists());
+        assert_eq!(quarantine.repeated_failure(&expected)?, None, "a single failure is retried");
+
+        // a different bad file is a different failure, most likely a flaky transfer
+        quarantine.quarantine(&download(b"truncated")?, &expected, "truncated")?;
+        assert_eq!(quarantine.repeated_failure(&expected)?, None);
+        let last = quarantine.quarantine(&download(b"truncated")?, &expected, "truncated")?;
+        assert_eq!(quarantine.repeated_failure(&expected)?, Some(last.clone()));
+        assert_eq!(quarantine.repeated_failure(&archive("Other.7z", b"other"))?, None);
+        assert!(repeated_failure_hint(&last, quarantine.directory()).contains(&expected.hash));
+
+        assert_eq!(quarantine.files()?.len(), 3);
+
+        quarantine.forget(&expected)?;
+        assert_eq!(quarantine.repeated_failure(&expected)?, None);
+        assert_eq!(quarantine.files()?.len(), 3, "files stay until pruned");
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+//! numbers of an installation run - what was downloaded from where, what was already there, how long each phase took and
+//! how big the temporary files got. printed as a table when the run is over (successful or not) and sent as the `summary`
+//! json event, so that issue reports come with something to go on
+
+use {
+    crate::{
+        install_modlist::download_cache::Verification,
+        modlist_json::{DirectiveKind, DownloadKind},
+    },
+    indicatif::{HumanBytes, HumanDuration},
+    itertools::Itertools,
+    parking_lot::Mutex,
+    serde::Serialize,
+    std::{
+        collections::BTreeMap,
+        sync::{
+            Arc,
+            Weak,
+            atomic::{AtomicU64, Ordering},
+        },
+        time::{Duration, Instant},
+    },
+    tap::prelude::*,
+    tracing::info,
+};
+
+/// the temp directory is measured this often, extracted archives stay around for much longer than that
+const TEMP_USAGE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Transferred {
+    pub archives: u64,
+    pub bytes: u64,
+}
+
+impl Transferred {
+    fn add(&mut self, bytes: u64) {
+        self.archives += 1;
+        self.bytes += bytes;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DirectiveCounts {
+    /// had to be (re)built
+    pub executed: u64,
+    /// output was there already, with the right hash
+    pub in_place: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseTime {
+    pub name: String,
+    pub seconds: f64,
+}
+
+/// what [RunStats] collected, as it's printed and serialized
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// fetched archives, game files not included
+    pub downloaded: BTreeMap<DownloadKind, Transferred>,
+    pub copied_from_game_files: Transferred,
+    /// archives which were in the downloads directory already (or in the extra search directories)
+    pub verified: Transferred,
+    /// `downloaders.verification` the archives above were checked with - `quick` skips hashing most of them
+    pub verification: Option<Verification>,
+    pub directives: BTreeMap<DirectiveKind, DirectiveCounts>,
+    pub phases: Vec<PhaseTime>,
+    pub peak_temp_usage_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct RunStats {
+    phases: Mutex<Vec<(String, Instant)>>,
+    downloaded: Mutex<BTreeMap<DownloadKind, Transferred>>,
+    verified: Mutex<Transferred>,
+    verification: Mutex<Option<Verification>>,
+    directives: Mutex<BTreeMap<DirectiveKind, DirectiveCounts>>,
+    peak_temp_usage: AtomicU64,
+}
+
+impl RunStats {
+    /// starts measuring the temp directory in the background, for as long as the stats are alive
+    pub fn start() -> Arc<Self> {
+        Arc::new(Self {
+            phases: Default::default(),
+            downloaded: Default::default(),
+            verified: Default::default(),
+            verification: Default::default(),
+            directives: Default::default(),
+            peak_temp_usage: AtomicU64::new(0),
+        })
+        .tap(|stats| {
+            let stats = Arc::downgrade(stats);
+            std::thread::spawn(move || Self::sample_temp_usage(stats));
+        })
+    }
+
+    fn sample_temp_usage(stats: Weak<Self>) {
+        while let Some(stats) = stats.upgrade() {
+            stats.record_temp_usage(crate::temp_directory::usage());
+            drop(stats);
+            std::thread::sleep(TEMP_USAGE_INTERVAL);
+        }
+    }
+
+    fn record_temp_usage(&self, bytes: u64) {
+        self.peak_temp_usage.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    /// the previous phase ends here, frontends following the json progress are told as well
+    pub fn phase(&self, name: &str) {
+        crate::progress_bars_v2::json_events::phase(name);
+        self.phases.lock().push((name.to_string(), Instant::now()));
+    }
+
+    pub fn record_download(&self, kind: DownloadKind, bytes: u64) {
+        self.downloaded.lock().entry(kind).or_default().add(bytes)
+    }
+
+    pub fn record_verified(&self, bytes: u64) {
+        self.verified.lock().add(bytes)
+    }
+
+    pub fn record_verification(&self, verification: Verification) {
+        *self.verification.lock() = Some(verification);
+    }
+
+    pub fn record_directives(&self, executed: impl IntoIterator<Item = (DirectiveKind, usize)>, in_place: impl IntoIterator<Item = DirectiveKind>) {
+        let mut directives = self.directives.lock();
+        executed
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .for_each(|(kind, count)| directives.entry(kind).or_default().executed += count as u64);
+        in_place
+            .into_iter()
+            .for_each(|kind| directives.entry(kind).or_default().in_place += 1);
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        let (mut downloaded, now) = (self.downloaded.lock().clone(), Instant::now());
+        RunSummary {
+            copied_from_game_files: downloaded
+                .remove(&DownloadKind::GameFileSource)
+                .unwrap_or_default(),
+            downloaded,
+            verified: *self.verified.lock(),
+            verification: *self.verification.lock(),
+            directives: self.directives.lock().clone(),
+            phases: self
+                .phases
+                .lock()
+                .iter()
+                .map(|(name, started)| (name.as_str(), *started))
+                .chain([("", now)])
+                .tuple_windows()
+                .map(|((name, started), (_, ended))| PhaseTime {
+                    name: name.to_string(),
+                    seconds: ended.duration_since(started).as_secs_f64(),
+                })
+                .collect(),
+            peak_temp_usage_bytes: self.peak_temp_usage.load(Ordering::Relaxed),
+        }
+    }
+
+    /// logs the table, sends it to json progress frontends and keeps it for the errors log
+    pub fn report(&self) {
+        let summary = self.summary();
+        let printed = summary.print();
+        info!("summary of this run:\n{printed}");
+        match serde_json::to_value(&summary) {
+            Ok(summary) => crate::progress_bars_v2::json_events::summary(summary),
+            Err(reason) => tracing::warn!(?reason, "could not serialize the summary of this run"),
+        }
+        crate::errors_log::set_run_summary(printed);
+    }
+}
+
+impl RunSummary {
+    pub fn print(&self) -> String {
+        let row =
+            |label: String, Transferred { archives, bytes }: Transferred| format!("  {label:<28}{archives:>8} archives  {:>12}", HumanBytes(bytes).to_string());
+        [
+            "downloads:".to_string(),
+            self.downloaded
+                .iter()
+                .map(|(kind, transferred)| row(format!("downloaded ({kind})"), *transferred))
+                .chain([
+                    row("copied from game files".into(), self.copied_from_game_files),
+                    row("already downloaded".into(), self.verified),
+                ])
+                .chain(
+                    self.verification
+                        .map(|verification| format!("  {:<28}{verification:>8}", "verification")),
+                )
+                .join("\n"),
+            "directives:".to_string(),
+            self.directives
+                .iter()
+                .map(|(kind, DirectiveCounts { executed, in_place })| format!("  {:<28}{executed:>8} executed  {in_place:>8} in place", kind.to_string()))
+                .join("\n"),
+            "phases:".to_string(),
+            self.phases
+                .iter()
+                .map(|PhaseTime { name, seconds }| format!("  {name:<28}{:>12}", HumanDuration(Duration::from_secs_f64(*seconds)).to_string()))
+                .join("\n"),
+            format!("peak temp directory usage: {}", HumanBytes(self.peak_temp_usage_bytes)),
+        ]
+        .into_iter()
+        .filter(|section| !section.is_empty())
+        .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_summary_of_a_run() -> anyhow::Result<()> {
+        let stats = RunStats::start();
+        stats.phase("downloads");
+        stats.record_download(DownloadKind::Nexus, 300);
+        stats.record_download(DownloadKind::Nexus, 200);
+        stats.record_download(DownloadKind::GameFileSource, 40);
+        stats.record_verified(1024);
+        stats.record_verification(Verification::Quick);
+        stats.record_temp_usage(7 * 1024);
+        stats.record_temp_usage(5 * 1024);
+        stats.phase("directives");
+        stats.record_directives(
+            [(DirectiveKind::FromArchive, 3), (DirectiveKind::CreateBSA, 0)],
+            [DirectiveKind::FromArchive, DirectiveKind::InlineFile],
+        );
+
+        let summary = stats.summary();
+        assert_eq!(
+            summary.downloaded,
+            BTreeMap::from([(DownloadKind::Nexus, Transferred { archives: 2, bytes: 500 })])
+        );
+        assert_eq!(summary.copied_from_game_files, Transferred { archives: 1, bytes: 40 });
+        assert_eq!(summary.verified, Transferred { archives: 1, bytes: 1024 });
+        assert_eq!(
+            summary.directives,
+            BTreeMap::from([
+                (DirectiveKind::FromArchive, DirectiveCounts { executed: 3, in_place: 1 }),
+                (DirectiveKind::InlineFile, DirectiveCounts { executed: 0, in_place: 1 }),
+            ])
+        );
+        assert_eq!(
+            summary
+                .phases
+                .iter()
+                .map(|phase| phase.name.as_str())
+                .collect_vec(),
+            ["downloads", "directives"]
+        );
+        assert!(summary.peak_temp_usage_bytes >= 7 * 1024);
+
+        let printed = summary.print();
+        assert!(printed.contains("downloaded (Nexus)"), "{printed}");
+        assert!(printed.contains("copied from game files"), "{printed}");
+        assert!(printed.contains("3 executed"), "{printed}");
+        assert!(printed.contains("verification                   quick"), "{printed}");
+
+        let serialized = serde_json::to_value(&summary)?;
+        assert_eq!(serialized["verification"], "quick");
+        assert_eq!(serialized["downloaded"]["Nexus"], serde_json::json!({"archives": 2, "bytes": 500}));
+        assert_eq!(serialized["directives"]["FromArchive"], serde_json::json!({"executed": 3, "in_place": 1}));
+        Ok(())
+    }
+}
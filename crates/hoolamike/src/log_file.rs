@@ -0,0 +1,130 @@
+//! every run writes its whole trace as json lines to `hoolamike.log` next to the config, no matter what `RUST_LOG` lets through
+//! to the console - a bug report needs more than the last screen of output. the logs of a few earlier runs are kept around as
+//! `hoolamike.1.log` (the previous run), `hoolamike.2.log` and so on
+
+use {
+    anyhow::{Context, Result},
+    std::{
+        path::{Path, PathBuf},
+        sync::{Mutex, OnceLock},
+    },
+    tap::prelude::*,
+    tracing_subscriber::{EnvFilter, Layer, registry::LookupSpan},
+};
+
+pub const LOG_FILE_STEM: &str = "hoolamike";
+/// the current run included
+pub const KEPT_RUNS: usize = 5;
+/// overrides the level of the log file, the console keeps following `RUST_LOG`
+pub const LEVEL_ENV: &str = "HOOLAMIKE_LOG_FILE";
+const DEFAULT_LEVEL: &str = "debug";
+
+static CURRENT: OnceLock<PathBuf> = OnceLock::new();
+
+/// log of `run` runs ago, `0` being the current one
+fn log_file_of_run(directory: &Path, run: usize) -> PathBuf {
+    match run {
+        0 => directory.join(format!("{LOG_FILE_STEM}.log")),
+        run => directory.join(format!("{LOG_FILE_STEM}.{run}.log")),
+    }
+}
+
+/// shifts every kept log one run back, dropping the oldest one
+fn rotate(directory: &Path, kept_runs: usize) -> Result<()> {
+    (1..kept_runs).rev().try_for_each(|run| {
+        let (older, newer) = (log_file_of_run(directory, run), log_file_of_run(directory, run - 1));
+        match newer.exists() {
+            true => std::fs::rename(&newer, &older).with_context(|| format!("moving [{}] to [{}]", newer.display(), older.display())),
+            false => Ok(()),
+        }
+    })
+}
+
+fn open_in(directory: &Path, kept_runs: usize) -> Result<(PathBuf, std::fs::File)> {
+    rotate(directory, kept_runs)
+        .map(|_| log_file_of_run(directory, 0))
+        .and_then(|path| {
+            std::fs::File::create(&path)
+                .with_context(|| format!("creating [{}]", path.display()))
+                .and_then(|file| {
+                    std::path::absolute(&path)
+                        .context("making the path absolute")
+                        .map(|path| (path, file))
+                })
+        })
+}
+
+/// log file of this run, if there is one
+pub fn current() -> Option<&'static Path> {
+    CURRENT.get().map(PathBuf::as_path)
+}
+
+/// starts the log file of this run in the directory holding the config
+pub fn layer<S>(config_path: &Path) -> Result<impl Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let filter = EnvFilter::try_from_env(LEVEL_ENV)
+        .or_else(|_| EnvFilter::try_new(DEFAULT_LEVEL))
+        .context("building log file filter")?;
+    crate::project_root::project_root_for(config_path)
+        .pipe(|directory| open_in(&directory, KEPT_RUNS))
+        .context("starting the log file")
+        .map(|(path, file)| {
+            CURRENT.get_or_init(|| path);
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(filter)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(directory: &Path) -> Vec<String> {
+        (0..KEPT_RUNS + 1)
+            .map(|run| log_file_of_run(directory, run))
+            .map(|path| std::fs::read_to_string(path).unwrap_or_default())
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_only_the_last_runs_are_kept() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        (0..KEPT_RUNS + 2).try_for_each(|run| {
+            open_in(directory.path(), KEPT_RUNS).and_then(|(_, file)| std::io::Write::write_all(&mut &file, format!("run {run}").as_bytes()).context("writing"))
+        })?;
+        assert_eq!(
+            runs(directory.path()),
+            ["run 6", "run 5", "run 4", "run 3", "run 2", ""]
+                .map(String::from)
+                .to_vec()
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_log_lines_are_json() -> Result<()> {
+        use tracing_subscriber::layer::SubscriberExt;
+        let directory = tempfile::tempdir()?;
+        let config_path = directory.path().join("hoolamike.yaml");
+        let subscriber = tracing_subscriber::registry().with(layer(&config_path)?);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("installing", modlist = "test").in_scope(|| tracing::debug!(archive = "foo.7z", "verified"));
+            tracing::trace!("too verbose for the file");
+        });
+        let written = std::fs::read_to_string(log_file_of_run(directory.path(), 0))?;
+        let lines = written
+            .lines()
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(lines.len(), 1, "{written}");
+        assert_eq!(lines[0]["fields"]["message"], "verified");
+        assert_eq!(lines[0]["fields"]["archive"], "foo.7z");
+        assert_eq!(lines[0]["span"]["modlist"], "test");
+        Ok(())
+    }
+}
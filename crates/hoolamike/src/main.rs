@@ -4,18 +4,19 @@ use {
     anyhow::{Context, Result},
     case_insensitive_path::PathExistsUtf8Ext,
     clap::{Args, Parser, Subcommand, ValueEnum},
-    modlist_data::ModlistSummary,
-    modlist_json::{DirectiveKind, HumanUrl},
+    modlist_data::{ArchiveReport, ModlistReport, ModlistSummary},
+    modlist_json::{DirectiveKind, HumanUrl, archive_meta::TagSelection},
     num::ToPrimitive,
     std::{ops::Div, path::PathBuf, str::FromStr},
-    tap::{Pipe, TapFallible},
+    tap::{Pipe, Tap, TapFallible},
     tracing::info,
+    wabbajack_file::modlist_cache::ModlistCache,
 };
 
 pub const BUFFER_SIZE: usize = 1024 * 64;
 
 #[derive(Parser, Clone)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = exit_codes::HELP)]
 struct Cli {
     /// the hoolamike config file is where you configure your installation - we're linux users, we can't afford windows
     /// which means we can't afford GUI-capable hardware anyway
@@ -32,6 +33,17 @@ struct Cli {
     /// generates a flamegraph, useful for performance testing (SLOW!)
     #[arg(long, value_enum, default_value_t = Default::default())]
     logging_mode: LoggingMode,
+    /// 'json' replaces the progress bars with newline-delimited json events on stdout (logs stay on stderr), for frontends wrapping hoolamike
+    #[arg(long, global = true, value_enum, default_value_t = Default::default())]
+    progress_format: ProgressFormat,
+    /// don't write the whole trace of this run (as json lines) to hoolamike.log next to the config.
+    /// its level is set with HOOLAMIKE_LOG_FILE (debug by default), RUST_LOG only affects the console
+    #[arg(long, global = true)]
+    no_log_file: bool,
+    /// never wait for someone to click through a browser or a window (nexus login, nxm links, the gui) - fail instead, with
+    /// exit code 6. for scripts and systemd units
+    #[arg(long, global = true, env = "HOOLAMIKE_NON_INTERACTIVE")]
+    non_interactive: bool,
     /// nxm handler default port, override this with an env var
     #[arg(long, env, default_value_t = crate::nxm_handler::single_instance_server::DEFAULT_PORT)]
     nxm_link_handler_port: u16,
@@ -39,17 +51,24 @@ struct Cli {
     nxm_link: Option<HumanUrl>,
 }
 
-#[derive(clap::Args, Default, Clone)]
+#[derive(clap::Args, Default, Clone, Debug)]
 pub struct DebugHelpers {
     /// skip verification (used mostly for developing the tool)
     #[arg(long)]
     skip_verify_and_downloads: bool,
+    /// resume the installation at given directive, either its hash or '<kind>:<index>' (e.g. 'FromArchive:120').
+    /// earlier directives are assumed to be done, except for the ones staging files for BSA/BA2 archives that are not built yet.
+    /// archives required by the remaining directives are still verified (and downloaded), unless verification is skipped as well
     #[arg(long)]
     start_from_directive: Option<String>,
     #[arg(long)]
     skip_kind: Vec<DirectiveKind>,
     #[arg(long)]
     contains: Vec<String>,
+    /// named set of the flags above, defined in the config under 'debug_presets' (explicit flags still win).
+    /// use '--preset list' to see the available ones
+    #[arg(long)]
+    preset: Option<String>,
 }
 
 #[derive(Subcommand, Clone)]
@@ -63,6 +82,20 @@ struct HoolamikeDebug {
     command: HoolamikeDebugCommand,
 }
 
+#[derive(Subcommand, Clone)]
+enum ConfigCommand {
+    /// prints JSON Schema of hoolamike.yaml - point your editor's YAML language server at it for autocompletion and validation
+    Schema,
+    /// reads hoolamike.yaml and reports what's wrong with it (unknown keys, values out of range) without installing anything
+    Check,
+}
+
+#[derive(Args, Clone)]
+struct ConfigCli {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
 #[derive(Subcommand, Clone)]
 enum Commands {
     /// Downlaods file from wabbajack CDN - much faster than link marked with "Slow Link (Debug Only)"
@@ -72,6 +105,8 @@ enum Commands {
     HandleNxm(nxm_handler::cli::HandleNxmCli),
     /// Emulates TTW installer (make sure to add installer variables to hoolamike.yaml)
     TaleOfTwoWastelands(crate::extensions::tale_of_two_wastelands_installer::CliConfig),
+    /// installs runtime dependencies (vcrun2022, dotnet48, fonts) into the prefix MO2 will run in (configure it in extras.prefix_bootstrap)
+    PrefixBootstrap(crate::extensions::prefix_bootstrap::CliConfig),
     /// applies 4GB patch to FalloutNV.exe (replaces FNVPatcher.exe/FNVPatcher.py etc )
     FalloutNewVegasPatcher {
         /// path to FalloutNV.exe
@@ -81,26 +116,81 @@ enum Commands {
     /// tests the modlist parser
     #[cfg(debug_assertions)]
     ValidateModlist {
-        /// path to modlist (.wabbajack) file
+        /// path to modlist (.wabbajack) file, or to the modlist json taken out of one
         path: PathBuf,
+        /// parse the modlist even when there's a cached copy of it
+        #[arg(long)]
+        no_cache: bool,
     },
+    /// lists modlists from the official gallery
+    BrowseModlists(modlist_gallery::BrowseCli),
+    /// downloads a modlist (.wabbajack) file from the official gallery and verifies its hash
+    FetchModlist(modlist_gallery::FetchCli),
+    /// logs in through the Nexus website and saves the api key in the config
+    NexusLogin(nexus_login::NexusLoginCli),
     /// prints information about the modlist
     ModlistInfo {
         /// path to modlist (.wabbajack) file
         path: PathBuf,
+        /// prints the summary as json instead, for tools wrapping hoolamike
+        #[arg(long)]
+        json: bool,
+        /// optional content tag (from the archive meta) to leave out, can be repeated - shows what the download shrinks to
+        #[arg(long)]
+        deselect_tag: Vec<String>,
+        /// name or hash of an archive - prints the directives built out of it instead, which is what breaks when it fails
+        #[arg(long)]
+        archive: Option<String>,
+    },
+    /// lists problems hoolamike is going to have with the modlist, exits with an error if any of them would break the installation
+    LintModlist {
+        /// path to modlist (.wabbajack) file
+        path: PathBuf,
     },
     Install {
         #[command(flatten)]
         debug: DebugHelpers,
+        #[command(flatten)]
+        paths: config_file::PathOverrides,
+        /// fail when the modlist writes to paths differing only in case, instead of writing all of them to the first one
+        #[arg(long)]
+        strict_case: bool,
+        /// fail when the modlist was made by a Wabbajack newer than the newest one hoolamike was tested with, instead of warning
+        #[arg(long)]
+        strict_version: bool,
+        /// handle directives one at a time, sorted by kind and destination, so that two runs log the same way (slow, meant for debugging)
+        #[arg(long)]
+        deterministic: bool,
+        /// record failed directives and carry on with the others (archives packing their files are skipped), the run still fails
+        /// in the end - a later plain install redoes exactly what failed
+        #[arg(long)]
+        keep_going: bool,
+        /// full screen view of the progress (totals and ETA of every phase, a tree of what's running) with the logs below it.
+        /// p pauses the installation, q quits, v changes how much is logged
+        #[arg(long)]
+        tui: bool,
     },
     /// prints prints default config. save it and modify to your liking
     PrintDefaultConfig,
+    Config(ConfigCli),
     /// runs post-install fixup - wouldn't be possible without extensive research done by Omni
     /// make sure to star his repo: https://github.com/Omni-guides/Wabbajack-Modlist-Linux
     PostInstallFixup,
+    /// packs the installation into hash-verified chunks which can be copied to another machine (rerun to resume)
+    Export(transfer::ExportCli),
+    /// puts an exported installation back together at installation_path and adapts it to this machine (rerun to resume)
+    Import(transfer::ImportCli),
     /// exposes the bare archive handling functionality used in hoolamike, useful for debugging
     Archive(self::archive_cli::ArchiveCliCommand),
+    /// writes single entries of an archive (of any kind, nested ones too) out and prints their sizes and hashes
+    Extract(self::archive_cli::ExtractCli),
     Audio(self::audio_cli::AudioCliCommand),
+    /// looks after downloads_directory, e.g. removes archives no modlist uses anymore
+    Downloads(downloads_cli::DownloadsCli),
+    /// runs short synthetic workloads (hashing, small file writes, 7z extraction) at a few worker counts and recommends 'concurrency' values
+    Bench(bench::BenchCli),
+    /// looks after the wine/proton prefix, e.g. reports how much space the logs of failed commands take in it
+    WinePrefix(wine_prefix_cli::WinePrefixCli),
 }
 
 pub(crate) mod read_wrappers;
@@ -110,13 +200,21 @@ pub(crate) mod utils;
 pub(crate) mod nxm_handler;
 
 pub(crate) mod archive_cli;
+pub(crate) mod atomic_write;
 pub(crate) mod audio_cli;
+pub(crate) mod bench;
+pub(crate) mod blocking_pool;
 pub(crate) mod compression;
 pub(crate) mod config_file;
+pub(crate) mod debug_presets;
 pub(crate) mod downloaders;
+pub(crate) mod downloads_cli;
 pub(crate) mod error;
+pub(crate) mod errors_log;
+pub(crate) mod exit_codes;
 pub(crate) mod helpers;
 pub(crate) mod install_modlist;
+pub(crate) mod log_file;
 // /// Surprisingly this is the most error-prone part of entire emulation
 // /// process - path need to be case-insensitive. Juggling between windows
 // /// and host encoding also brings a lot of headache. Hence it needs to be
@@ -132,26 +230,31 @@ pub(crate) mod install_modlist_v2 {
     }
 }
 pub(crate) mod modlist_data;
+pub(crate) mod modlist_gallery;
 pub(crate) mod modlist_json;
+pub(crate) mod modlist_lint;
+pub(crate) mod nexus_login;
+pub(crate) mod non_interactive;
 pub(crate) mod octadiff_reader;
 pub(crate) mod post_install_fixup;
 pub(crate) mod progress_bars_v2;
+pub(crate) mod project_root;
+pub(crate) mod temp_directory;
+pub(crate) mod transfer;
+pub(crate) mod tui;
 pub(crate) mod wabbajack_file;
+pub(crate) mod wine_prefix_cli;
 
 /// non-wabbajack extensions will go here
 pub(crate) mod extensions;
+pub(crate) mod game_version;
 
 pub(crate) mod download_wabbajack_cdn;
 pub(crate) mod gui;
 
-pub(crate) mod consts {
-    use {once_cell::sync::Lazy, std::path::Path, tap::prelude::*};
-    pub static TEMP_FILE_DIR: Lazy<&'static Path> =
-        Lazy::new(|| Path::new("HOOLAMIKE_TEMP_FILES").tap(|path| std::fs::create_dir_all(path).expect("could not create temporary dir storage")));
-}
-
 pub fn tokio_runtime_single() -> Result<tokio::runtime::Runtime> {
     tokio::runtime::Builder::new_current_thread()
+        .max_blocking_threads(blocking_pool::max_blocking_threads())
         .enable_all()
         .build()
         .context("cannot create runtime builder")
@@ -159,6 +262,7 @@ pub fn tokio_runtime_single() -> Result<tokio::runtime::Runtime> {
 pub fn tokio_runtime_multi(workers: usize) -> Result<tokio::runtime::Runtime> {
     tokio::runtime::Builder::new_multi_thread()
         .worker_threads(workers)
+        .max_blocking_threads(blocking_pool::max_blocking_threads())
         .enable_all()
         .build()
         .context("cannot create runtime builder")
@@ -172,12 +276,31 @@ pub enum LoggingMode {
     TracingConsole,
 }
 
+#[derive(Debug, ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressFormat {
+    #[default]
+    Human,
+    /// see [progress_bars_v2::json_events] for the schema
+    Json,
+}
+
 #[allow(unused_imports)]
-fn setup_logging(logging_mode: LoggingMode) -> Option<impl Drop> {
+fn setup_logging(
+    logging_mode: LoggingMode,
+    progress_format: ProgressFormat,
+    tui: bool,
+    log_file_next_to: Option<&std::path::Path>,
+) -> Option<Box<dyn std::any::Any>> {
     use {
         tracing_indicatif::IndicatifLayer,
         tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, prelude::*, util::SubscriberInitExt},
     };
+    // the console isn't set up yet
+    let log_file = log_file_next_to.and_then(|config_path| {
+        log_file::layer(config_path)
+            .tap_err(|reason| eprintln!("this run won't have a log file: {reason:?}"))
+            .ok()
+    });
     match logging_mode {
         LoggingMode::Flamegraph => {
             let fmt_layer = fmt::Layer::default();
@@ -185,11 +308,40 @@ fn setup_logging(logging_mode: LoggingMode) -> Option<impl Drop> {
             let (flame_layer, guard) = tracing_flame::FlameLayer::with_file("./tracing.folded").unwrap();
 
             let subscriber = tracing_subscriber::Registry::default()
+                .with(log_file)
                 .with(fmt_layer)
                 .with(flame_layer);
 
             tracing::subscriber::set_global_default(subscriber).expect("Could not set global default");
-            Some(guard)
+            Some(Box::new(guard))
+        }
+        // anything written to the terminal would end up over the tui, it shows the logs in a pane of its own
+        LoggingMode::Cli if tui => {
+            tracing_subscriber::registry()
+                .with(log_file)
+                .with(
+                    progress_bars_v2::bridge::ProgressBridge
+                        .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from_str("info").unwrap())),
+                )
+                .with(tui::logs::PaneLayer.with_filter(tui::logs::filter()))
+                .pipe(tracing::subscriber::set_global_default)
+                .context("Unable to set a global subscriber")
+                .expect("logging failed");
+            None
+        }
+        LoggingMode::Cli if progress_format == ProgressFormat::Json => {
+            tracing_subscriber::registry()
+                .with(log_file)
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .with_writer(std::io::stderr)
+                        .and_then(progress_bars_v2::bridge::ProgressBridge)
+                        .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from_str("info").unwrap())),
+                )
+                .pipe(tracing::subscriber::set_global_default)
+                .context("Unable to set a global subscriber")
+                .expect("logging failed");
+            Some(Box::new(progress_bars_v2::json_events::start()))
         }
         LoggingMode::Cli => {
             let indicatif_layer = console::Term::stdout()
@@ -211,10 +363,14 @@ fn setup_logging(logging_mode: LoggingMode) -> Option<impl Drop> {
                         )
                 });
             // let indicatif_layer = ;
-            let subscriber = tracing_subscriber::registry()
-                .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from_str("info").unwrap()))
-                .with(tracing_subscriber::fmt::layer().with_writer(indicatif_layer.get_stderr_writer()))
-                .with(indicatif_layer);
+            // the file gets its own level, RUST_LOG only filters what reaches the console
+            let subscriber = tracing_subscriber::registry().with(log_file).with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(indicatif_layer.get_stderr_writer())
+                    .and_then(indicatif_layer)
+                    .and_then(progress_bars_v2::bridge::ProgressBridge)
+                    .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::from_str("info").unwrap())),
+            );
             tracing::subscriber::set_global_default(subscriber)
                 .context("Unable to set a global subscriber")
                 .expect("logging failed");
@@ -230,6 +386,7 @@ fn setup_logging(logging_mode: LoggingMode) -> Option<impl Drop> {
             // build a `Subscriber` by combining layers with a
             // `tracing_subscriber::Registry`:
             tracing_subscriber::registry()
+                .with(log_file)
                 // add the console layer to the subscriber
                 .with(console_layer)
                 // add other layers...
@@ -247,10 +404,23 @@ fn async_main() -> Result<()> {
         command,
         hoolamike_config,
         logging_mode,
+        progress_format,
+        no_log_file,
+        non_interactive,
         nxm_link_handler_port,
         nxm_link,
     } = cli.clone();
-    let _guard = setup_logging(logging_mode);
+    if non_interactive {
+        non_interactive::enable();
+    }
+    let tui = matches!(command, Some(Commands::Install { tui: true, .. }));
+    // forwarding an nxm link to the running instance would rotate its log away
+    let mut guard = setup_logging(
+        logging_mode,
+        progress_format,
+        tui,
+        (!no_log_file && nxm_link.is_none()).then_some(hoolamike_config.as_path()),
+    );
     match (command, nxm_link) {
         (Some(command), _) => match command {
             Commands::FalloutNewVegasPatcher { at_path } => crate::extensions::fallout_new_vegas_4gb_patch::patch_fallout_new_vegas(&at_path)
@@ -260,38 +430,167 @@ fn async_main() -> Result<()> {
                 let (_config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
                 post_install_fixup::run_post_install_fixup(&config)
             }
+            Commands::Export(export) => {
+                let (_config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
+                transfer::run_export(export, config)
+            }
+            Commands::Bench(bench) => bench::run_bench(bench, &hoolamike_config),
+            Commands::WinePrefix(wine_prefix) => wine_prefix_cli::run(wine_prefix, &hoolamike_config),
+            Commands::Downloads(downloads) => {
+                let (config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
+                let config = downloads.paths.clone().apply(config)?;
+                project_root::enter_project_root(&config_path)?;
+                downloads_cli::run(downloads, config)
+            }
+            Commands::Import(import) => {
+                let (_config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
+                transfer::run_import(import, config)
+            }
             #[cfg(debug_assertions)]
-            Commands::ValidateModlist { path } => std::fs::read_to_string(&path)
-                .context("reading test file")
-                .and_then(|input| modlist_json::parsing_helpers::validate_modlist_file(&input))
-                .with_context(|| format!("testing file {}", path.display())),
-            Commands::ModlistInfo { path } => path
+            Commands::ValidateModlist { path, no_cache } => match path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("wabbajack"))
+            {
+                true => path
+                    .exists_utf8()
+                    .and_then(|path| {
+                        wabbajack_file::WabbajackFile::load_modlist_json(
+                            &path,
+                            (!no_cache)
+                                .then(|| ModlistCache::in_project_root(&project_root::project_root_for(&hoolamike_config)))
+                                .as_ref(),
+                        )
+                    })
+                    .map(|wabbajack| info!("{}", modlist_json::format_version::describe(Some(&wabbajack.modlist.wabbajack_version)))),
+                false => std::fs::read_to_string(&path)
+                    .context("reading test file")
+                    .and_then(|input| modlist_json::parsing_helpers::validate_modlist_file(&input)),
+            }
+            .with_context(|| format!("testing file {}", path.display())),
+            Commands::BrowseModlists(browse) => modlist_gallery::run_browse(browse),
+            Commands::FetchModlist(fetch) => modlist_gallery::run_fetch(fetch, &hoolamike_config),
+            Commands::NexusLogin(login) => {
+                non_interactive::ensure_interactive("logging in to nexus").and_then(|_| nexus_login::run_login(login, &hoolamike_config))
+            }
+            Commands::ModlistInfo {
+                path,
+                json,
+                deselect_tag,
+                archive,
+            } => path
                 .exists_utf8()
-                .and_then(|path| wabbajack_file::WabbajackFile::load_wabbajack_file(&path))
+                .and_then(|path| {
+                    wabbajack_file::WabbajackFile::load_wabbajack_file(
+                        &path,
+                        Some(&ModlistCache::in_project_root(&project_root::project_root_for(&hoolamike_config))),
+                    )
+                })
                 .context("reading modlist")
-                .map(|(_, modlist)| ModlistSummary::new(&modlist.modlist))
-                .map(|modlist| modlist.print())
-                .map(|modlist| println!("\n{modlist}")),
+                .and_then(|(_, modlist)| {
+                    let selection = deselect_tag
+                        .iter()
+                        .fold(TagSelection::default(), |mut selection, tag| {
+                            selection.deselect(tag);
+                            selection
+                        });
+                    match (archive, json) {
+                        (Some(archive), true) => ArchiveReport::new(&modlist.modlist, &archive)
+                            .and_then(|report| serde_json::to_string_pretty(&report).context("serializing archive report")),
+                        (Some(archive), false) => ArchiveReport::new(&modlist.modlist, &archive).map(|report| report.print()),
+                        (None, true) => ModlistReport::new(&modlist.modlist, &selection)
+                            .pipe_ref(serde_json::to_string_pretty)
+                            .context("serializing modlist report"),
+                        (None, false) => ModlistSummary::new(&modlist.modlist, &selection)
+                            .print()
+                            .pipe(|summary| format!("\n{summary}"))
+                            .pipe(Ok),
+                    }
+                })
+                .map(|modlist| println!("{modlist}")),
+            Commands::LintModlist { path } => path
+                .exists_utf8()
+                .and_then(|path| {
+                    wabbajack_file::WabbajackFile::load_modlist_json(
+                        &path,
+                        Some(&ModlistCache::in_project_root(&project_root::project_root_for(&hoolamike_config))),
+                    )
+                })
+                .context("reading modlist")
+                .map(|wabbajack| modlist_lint::LintReport::new(&wabbajack.modlist))
+                .and_then(|report| {
+                    println!("{}", report.print());
+                    match report.has_errors() {
+                        true => Err(anyhow::anyhow!("[{}] errors found", report.count(modlist_lint::Severity::Error))),
+                        false => Ok(()),
+                    }
+                }),
             Commands::PrintDefaultConfig => config_file::HoolamikeConfig::write_default().map(|config| println!("{config}")),
-            Commands::Install { debug } => {
+            Commands::Config(ConfigCli { command }) => match command {
+                ConfigCommand::Schema => config_file::schema::generate().map(|schema| println!("{schema}")),
+                ConfigCommand::Check => config_file::HoolamikeConfig::read(&hoolamike_config)
+                    .context("reading hoolamike config file")
+                    .and_then(|(config_path, config)| match config.problems() {
+                        problems if problems.is_empty() => {
+                            println!("[{}] is fine", config_path.display());
+                            Ok(())
+                        }
+                        problems => anyhow::anyhow!("{}", problems.join("\n"))
+                            .context(format!("[{}] problems found in [{}]", problems.len(), config_path.display()))
+                            .pipe(|error| Err(exit_codes::Failure::Config.mark(error))),
+                    }),
+            },
+            Commands::Install {
+                debug,
+                paths,
+                strict_case,
+                strict_version,
+                deterministic,
+                keep_going,
+                tui,
+            } => {
                 let (config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
+                let config = paths.apply(config)?;
                 tracing::info!("found config at [{}]", config_path.display());
+                project_root::enter_project_root(&config_path)?;
+                if debug.preset.as_deref() == Some(debug_presets::LIST_PRESETS) {
+                    return debug_presets::list_presets(&config.debug_presets).map(|presets| println!("{presets}"));
+                }
+                let debug = debug
+                    .with_preset(&config.debug_presets)
+                    .context("expanding debug preset")?;
 
-                install_modlist::install_modlist(config, debug)
-                    .map_err(|errors| {
-                        errors
-                            .iter()
-                            .enumerate()
-                            .for_each(|(idx, reason)| tracing::error!("{idx}. {reason:?}", idx = idx + 1));
+                let install = move || install_modlist::install_modlist(config, debug, strict_case, strict_version, deterministic, keep_going);
+                match tui {
+                    // reads the keys itself, ctrl-c included
+                    true => tui::run(install),
+                    false => {
+                        install_modlist::cancellation::cancel_on_ctrl_c()
+                            .unwrap_or_else(|reason| tracing::warn!("installation can't be cancelled with ctrl-c: {reason:?}"));
+                        install()
+                    }
+                }
+                .map_err(|errors| match install_modlist::cancellation::is_cancelled() {
+                    // whatever failed was stopped on purpose, the next run picks up where this one stopped
+                    true => {
+                        tracing::warn!("installation was cancelled, run the same command again to resume");
+                        install_modlist::cancellation::cancelled_error()
+                    }
+                    false => {
+                        tracing::error!("{}", errors_log::grouped(&errors));
 
-                        anyhow::anyhow!("could not finish installation due to [{}] errors", errors.len())
-                    })
-                    .map(|count| info!("successfully installed [{}] mods", count.len()))
+                        errors_log::AggregatedErrors {
+                            summary: errors_log::installation_failed(&errors),
+                            errors,
+                        }
+                        .pipe(anyhow::Error::new)
+                    }
+                })
+                .map(|count| info!("successfully installed [{}] mods", count.len()))
             }
             Commands::HoolamikeDebug(HoolamikeDebug { command }) => match command {
                 HoolamikeDebugCommand::ReserializeDirectives { modlist_file } => modlist_file
                     .exists_utf8()
-                    .and_then(|modlist_file| wabbajack_file::WabbajackFile::load_wabbajack_file(&modlist_file))
+                    .and_then(|modlist_file| wabbajack_file::WabbajackFile::load_wabbajack_file(&modlist_file, None))
                     .context("loading modlist file")
                     .and_then(|modlist| {
                         modlist
@@ -303,6 +602,7 @@ fn async_main() -> Result<()> {
                     .map(|directives| println!("{directives}")),
             },
             Commands::Archive(archive_cli_command) => archive_cli_command.run(),
+            Commands::Extract(extract) => extract.run(),
             Commands::Audio(audio_cli_command) => audio_cli_command
                 .command
                 .pipe(|c| c.clone().run().with_context(|| format!("running\n{c:#?}"))),
@@ -310,7 +610,13 @@ fn async_main() -> Result<()> {
                 let (_config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
                 crate::extensions::tale_of_two_wastelands_installer::install(cli_config, config)
             }
+            Commands::PrefixBootstrap(cli_config) => {
+                let (_config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
+                crate::extensions::prefix_bootstrap::run(cli_config, config)
+            }
+            Commands::HandleNxm(handle_nxm_cli) if handle_nxm_cli.register || handle_nxm_cli.unregister => nxm_handler::register::run(&handle_nxm_cli),
             Commands::HandleNxm(handle_nxm_cli) => {
+                non_interactive::ensure_interactive("handling nxm links")?;
                 let (_config_path, config) = config_file::HoolamikeConfig::read(&hoolamike_config).context("reading hoolamike config file")?;
                 tokio_runtime_multi(4).and_then(|rt| rt.block_on(nxm_handler::run(config, handle_nxm_cli)))
             }
@@ -320,7 +626,7 @@ fn async_main() -> Result<()> {
         },
         (None, Some(nxm_link)) => tokio_runtime_multi(4).and_then(|r| r.block_on(nxm_handler::handle_nxm_link(nxm_link_handler_port, nxm_link))),
 
-        (None, None) => crate::gui::run(cli),
+        (None, None) => non_interactive::ensure_interactive("the gui").and_then(|_| crate::gui::run(cli)),
         // _ => Cli::command()
         //     .error(clap::error::ErrorKind::ArgumentConflict, "bad usage")
         //     .exit(),
@@ -331,8 +637,14 @@ fn async_main() -> Result<()> {
             env!("CARGO_PKG_REPOSITORY")
         )
     })
+    .tap_ok(|_| {
+        // progress of the last spans goes out before the final marker
+        drop(guard.take());
+        progress_bars_v2::json_events::phase("finished");
+    })
     .tap_err(|e| {
-        drop(_guard);
+        drop(guard);
+        progress_bars_v2::json_events::error(e);
 
         eprintln!(" --- ");
         eprintln!(" --- ");
@@ -343,13 +655,27 @@ fn async_main() -> Result<()> {
         eprintln!(" --- ");
         eprintln!(" --- ");
         eprintln!(" --- ");
+        match errors_log::write_errors_log(e, &hoolamike_config) {
+            Ok(path) => eprintln!(
+                "\n\nfull error details (attach this file when reporting the problem):\n\n    {}\n",
+                path.display()
+            ),
+            Err(reason) => eprintln!("\n\ncould not save the full error details:\n{reason:?}"),
+        }
+        if let Some(log_file) = log_file::current() {
+            eprintln!("log of this run (attach it as well):\n\n    {}\n", log_file.display());
+        }
     })
 }
 
-fn main() -> Result<()> {
+/// exit codes are documented in [exit_codes::HELP]
+fn main() -> std::process::ExitCode {
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_cpus::get().saturating_sub(2).max(1))
         .build_global()
         .unwrap();
     async_main()
+        .tap(|_| temp_directory::remove_run_directory())
+        .map_or_else(|error| exit_codes::exit_code_of(&error), |_| exit_codes::SUCCESS)
+        .pipe(std::process::ExitCode::from)
 }
@@ -1,7 +1,25 @@
 use {
-    crate::{helpers::human_readable_size, modlist_json::Modlist},
+    crate::{
+        helpers::human_readable_size,
+        install_modlist::execution_plan::{destination, source_archive},
+        modlist_json::{
+            Archive,
+            Directive,
+            DirectiveKind,
+            DownloadKind,
+            GameName,
+            Modlist,
+            State,
+            archive_meta::{ArchiveMeta, TagSelection},
+        },
+    },
+    anyhow::{Context, Result},
     itertools::Itertools,
-    std::collections::BTreeMap,
+    serde::Serialize,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        fmt,
+    },
     tabled::{
         Tabled,
         settings::{Color, Rotate, Style, object::Columns},
@@ -11,31 +29,469 @@ use {
 
 #[derive(Tabled)]
 pub struct ModlistSummary {
+    pub name: String,
+    pub version: String,
     pub author: String,
+    pub website: String,
+    pub is_nsfw: bool,
+    pub wabbajack_version: String,
+    pub game: String,
+    pub games_referenced: String,
     pub total_mods: usize,
+    pub total_download_size: String,
+    pub sources: String,
+    pub manual_downloads: String,
+    pub optional_content_tags: String,
+    pub installers: String,
     pub total_directives: usize,
+    pub total_output_size: String,
     pub unique_directive_kinds: String,
     // pub unique_authors: usize,
-    pub sources: String,
-    pub name: String,
+    pub required_game_versions: String,
     // pub unique_headers: String,
-    pub website: String,
-    pub total_download_size: String,
     pub description: String,
     pub directive_examples: String,
+    /// archives the most output depends on
+    pub most_depended_on_archives: String,
+    #[tabled(skip)]
+    pub archive_dependents: ArchiveDependents,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceBreakdown {
+    pub source: DownloadKind,
+    pub files: usize,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectiveBreakdown {
+    pub kind: DirectiveKind,
+    pub count: usize,
+    /// size of the files the directives produce
+    pub total_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GameReference {
+    pub game: GameName,
+    /// game files the modlist takes from the game directory
+    pub files: usize,
+    pub versions: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManualDownload {
+    pub name: String,
+    pub url: String,
+    pub prompt: String,
+}
+
+/// archives sharing a `tag` in their meta, selected and deselected together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TagGroup {
+    pub tag: String,
+    pub archives: Vec<String>,
+    pub total_size: u64,
+    pub selected: bool,
+}
+
+/// flagged `installer` in its meta - Wabbajack runs these in a sandbox, hoolamike never executes them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstallerArchive {
+    pub name: String,
+    pub is_unsafe: bool,
+}
+
+/// everything `modlist-info` shows, serializable for tools wrapping hoolamike (`--json`)
+#[derive(Debug, Clone, Serialize)]
+pub struct ModlistReport {
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    pub website: String,
+    pub is_nsfw: bool,
+    pub wabbajack_version: String,
+    pub game_type: GameName,
+    pub total_archives: usize,
+    pub total_download_size: u64,
+    /// biggest first
+    pub sources: Vec<SourceBreakdown>,
+    pub manual_downloads: Vec<ManualDownload>,
+    /// alphabetical
+    pub tags: Vec<TagGroup>,
+    /// download size of the archives left after applying the tag selection
+    pub selected_download_size: u64,
+    pub installers: Vec<InstallerArchive>,
+    pub total_directives: usize,
+    pub total_output_size: u64,
+    /// biggest first
+    pub directives: Vec<DirectiveBreakdown>,
+    pub games: Vec<GameReference>,
+}
+
+/// directive reading from an archive, see [ArchiveDependents]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependentDirective {
+    pub kind: DirectiveKind,
+    pub to: String,
+    pub size: u64,
+}
+
+/// what an archive ends up in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveImpact {
+    pub files: usize,
+    pub total_size: u64,
+}
+
+impl fmt::Display for ArchiveImpact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} files ({})", with_thousands_separator(self.files), human_readable_size(self.total_size))
+    }
+}
+
+/// archive hash -> directives reading from that archive (FromArchive, PatchedFromArchive and TransformedTexture), for
+/// telling what breaks downstream when an archive fails
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveDependents(BTreeMap<String, Vec<DependentDirective>>);
+
+impl ArchiveDependents {
+    pub fn new(directives: &[Directive]) -> Self {
+        directives
+            .iter()
+            .filter_map(|directive| {
+                source_archive(directive).map(|archive_hash| {
+                    (
+                        archive_hash.to_string(),
+                        DependentDirective {
+                            kind: directive.directive_kind(),
+                            to: destination(directive).to_string(),
+                            size: directive.size(),
+                        },
+                    )
+                })
+            })
+            .into_group_map()
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .pipe(Self)
+    }
+
+    pub fn of(&self, archive_hash: &str) -> &[DependentDirective] {
+        self.0
+            .get(archive_hash)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn impact(&self, archive_hash: &str) -> ArchiveImpact {
+        self.of(archive_hash)
+            .iter()
+            .fold(ArchiveImpact::default(), |acc, directive| ArchiveImpact {
+                files: acc.files + 1,
+                total_size: acc.total_size + directive.size,
+            })
+    }
+
+    /// the `count` archives with the most output depending on them
+    pub fn largest_table(&self, archives: &[Archive], count: usize) -> String {
+        archives
+            .iter()
+            .map(|archive| (archive, self.impact(&archive.descriptor.hash)))
+            .filter(|(_, impact)| impact.files > 0)
+            .sorted_by(|(a, a_impact), (b, b_impact)| {
+                b_impact
+                    .total_size
+                    .cmp(&a_impact.total_size)
+                    .then(a.descriptor.name.cmp(&b.descriptor.name))
+            })
+            .take(count)
+            .map(|(archive, impact)| format!("{}: {impact}", archive.descriptor.name))
+            .join("\n")
+    }
+}
+
+/// how many archives [ModlistSummary] lists as the most depended on, `--archive` tells about any of them
+const MOST_DEPENDED_ON_LISTED: usize = 10;
+
+/// `modlist-info --archive`, an archive and everything built out of it
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveReport {
+    pub name: String,
+    pub hash: String,
+    pub size: u64,
+    pub impact: ArchiveImpact,
+    /// biggest first
+    pub directives: Vec<DependentDirective>,
 }
 
-fn summarize_value_count<'a, I: std::fmt::Display + Ord + Clone + Eq>(items: impl Iterator<Item = I> + 'a) -> String {
-    items
-        .fold(BTreeMap::new(), |acc, directive| {
-            acc.tap_mut(move |acc| {
-                *acc.entry(directive).or_insert(0) += 1;
+impl ArchiveReport {
+    /// `name_or_hash` is either the hash of the archive, or its name (case does not matter)
+    pub fn new(modlist: &Modlist, name_or_hash: &str) -> Result<Self> {
+        let archive = modlist
+            .archives
+            .iter()
+            .find(|archive| archive.descriptor.hash == name_or_hash)
+            .or_else(|| {
+                modlist
+                    .archives
+                    .iter()
+                    .find(|archive| archive.descriptor.name.eq_ignore_ascii_case(name_or_hash))
             })
+            .with_context(|| format!("modlist has no archive named (or hashed) [{name_or_hash}]"))?;
+        let dependents = ArchiveDependents::new(&modlist.directives);
+        Ok(Self {
+            name: archive.descriptor.name.clone(),
+            hash: archive.descriptor.hash.clone(),
+            size: archive.descriptor.size,
+            impact: dependents.impact(&archive.descriptor.hash),
+            directives: dependents
+                .of(&archive.descriptor.hash)
+                .iter()
+                .cloned()
+                .sorted_by(|a, b| b.size.cmp(&a.size).then(a.to.cmp(&b.to)))
+                .collect(),
         })
+    }
+
+    pub fn print(&self) -> String {
+        let Self {
+            name,
+            hash,
+            size,
+            impact,
+            directives,
+        } = self;
+        [format!("{name} [{hash}] ({}), {impact} depend on it:", human_readable_size(*size))]
+            .into_iter()
+            .chain(
+                directives
+                    .iter()
+                    .map(|DependentDirective { kind, to, size }| format!("  {:<20}{:>12}  {to}", kind.to_string(), human_readable_size(*size))),
+            )
+            .join("\n")
+    }
+}
+
+fn with_thousands_separator(value: usize) -> String {
+    value
+        .to_string()
+        .chars()
+        .rev()
+        .chunks(3)
+        .into_iter()
+        .map(|chunk| chunk.collect::<String>())
+        .join(",")
+        .chars()
+        .rev()
+        .collect()
+}
+
+fn sources(archives: &[Archive]) -> Vec<SourceBreakdown> {
+    archives
+        .iter()
+        .fold(BTreeMap::<DownloadKind, (usize, u64)>::new(), |acc, archive| {
+            acc.tap_mut(|acc| {
+                let (files, total_size) = acc.entry(archive.state.kind()).or_default();
+                *files += 1;
+                *total_size += archive.descriptor.size;
+            })
+        })
+        .into_iter()
+        .map(|(source, (files, total_size))| SourceBreakdown { source, files, total_size })
+        .sorted_by(|a, b| {
+            b.total_size
+                .cmp(&a.total_size)
+                .then(a.source.cmp(&b.source))
+        })
+        .collect()
+}
+
+fn games(archives: &[Archive]) -> Vec<GameReference> {
+    let mut required = crate::game_version::required_versions(archives);
+    archives
+        .iter()
+        .filter_map(|archive| match &archive.state {
+            State::GameFileSource(state) => Some(&state.game),
+            _ => None,
+        })
+        .counts()
+        .into_iter()
+        .sorted()
+        .map(|(game, files)| GameReference {
+            versions: required.remove(game).unwrap_or_default(),
+            game: game.clone(),
+            files,
+        })
+        .collect()
+}
+
+fn tag_groups(archives: &[(ArchiveMeta, &Archive)], selection: &TagSelection) -> Vec<TagGroup> {
+    archives
         .iter()
-        .map(|(k, v)| format!("{k}: {v}"))
-        .join("\n")
+        .flat_map(|(meta, archive)| meta.tags.iter().map(move |tag| (tag, *archive)))
+        .into_group_map()
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(tag, archives)| TagGroup {
+            tag: tag.clone(),
+            total_size: archives.iter().map(|archive| archive.descriptor.size).sum(),
+            archives: archives
+                .iter()
+                .map(|archive| archive.descriptor.name.clone())
+                .sorted()
+                .collect(),
+            selected: selection.is_tag_selected(tag),
+        })
+        .collect()
+}
+
+impl ModlistReport {
+    pub fn new(
+        Modlist {
+            archives,
+            author,
+            description: _,
+            directives,
+            game_type,
+            image: _,
+            is_nsfw,
+            name,
+            readme: _,
+            version,
+            wabbajack_version,
+            website,
+        }: &Modlist,
+        selection: &TagSelection,
+    ) -> Self {
+        let metas = archives
+            .iter()
+            .map(|archive| (archive.descriptor.parsed_meta(), archive))
+            .collect_vec();
+        Self {
+            name: name.clone(),
+            version: version.clone(),
+            author: author.clone(),
+            website: website.clone(),
+            is_nsfw: *is_nsfw,
+            wabbajack_version: wabbajack_version.clone(),
+            game_type: game_type.clone(),
+            total_archives: archives.len(),
+            total_download_size: archives.iter().map(|a| a.descriptor.size).sum(),
+            sources: sources(archives),
+            manual_downloads: archives
+                .iter()
+                .filter_map(|archive| match &archive.state {
+                    State::Manual(state) => Some(ManualDownload {
+                        name: archive.descriptor.name.clone(),
+                        url: state.url.to_string(),
+                        prompt: state.prompt.clone(),
+                    }),
+                    _ => None,
+                })
+                .sorted_by(|a, b| a.name.cmp(&b.name))
+                .collect(),
+            tags: tag_groups(&metas, selection),
+            selected_download_size: metas
+                .iter()
+                .filter(|(meta, _)| selection.is_selected(meta))
+                .map(|(_, archive)| archive.descriptor.size)
+                .sum(),
+            installers: metas
+                .iter()
+                .filter(|(meta, _)| meta.installer)
+                .map(|(meta, archive)| InstallerArchive {
+                    name: archive.descriptor.name.clone(),
+                    is_unsafe: meta.is_unsafe,
+                })
+                .sorted_by(|a, b| a.name.cmp(&b.name))
+                .collect(),
+            total_directives: directives.len(),
+            total_output_size: directives.iter().map(|d| d.size()).sum(),
+            directives: directives
+                .iter()
+                .fold(BTreeMap::<DirectiveKind, (usize, u64)>::new(), |acc, directive| {
+                    acc.tap_mut(|acc| {
+                        let (count, total_size) = acc.entry(directive.directive_kind()).or_default();
+                        *count += 1;
+                        *total_size += directive.size();
+                    })
+                })
+                .into_iter()
+                .map(|(kind, (count, total_size))| DirectiveBreakdown { kind, count, total_size })
+                .sorted_by(|a, b| b.total_size.cmp(&a.total_size).then(a.kind.cmp(&b.kind)))
+                .collect(),
+            games: games(archives),
+        }
+    }
+
+    pub fn sources_table(&self) -> String {
+        self.sources
+            .iter()
+            .map(|SourceBreakdown { source, files, total_size }| {
+                format!("{source}: {} files / {}", with_thousands_separator(*files), human_readable_size(*total_size))
+            })
+            .join("\n")
+    }
+
+    pub fn directives_table(&self) -> String {
+        self.directives
+            .iter()
+            .map(|DirectiveBreakdown { kind, count, total_size }| {
+                format!("{kind}: {} / {}", with_thousands_separator(*count), human_readable_size(*total_size))
+            })
+            .join("\n")
+    }
+
+    pub fn games_table(&self) -> String {
+        self.games
+            .iter()
+            .map(|GameReference { game, files, .. }| format!("{game}: {} files", with_thousands_separator(*files)))
+            .join("\n")
+    }
+
+    pub fn manual_downloads_table(&self) -> String {
+        self.manual_downloads
+            .iter()
+            .map(|ManualDownload { name, url, .. }| format!("{name} ({url})"))
+            .join("\n")
+    }
+
+    pub fn tags_table(&self) -> String {
+        self.tags
+            .iter()
+            .map(
+                |TagGroup {
+                     tag,
+                     archives,
+                     total_size,
+                     selected,
+                 }| {
+                    format!(
+                        "{tag}: {} archives / {}{}",
+                        with_thousands_separator(archives.len()),
+                        human_readable_size(*total_size),
+                        if *selected { "" } else { " (deselected)" }
+                    )
+                },
+            )
+            .join("\n")
+    }
+
+    pub fn installers_table(&self) -> String {
+        match self.installers.is_empty() {
+            true => String::new(),
+            false => self
+                .installers
+                .iter()
+                .map(|InstallerArchive { name, is_unsafe }| format!("{name}{}", if *is_unsafe { " (unsafe)" } else { "" }))
+                .chain(["note: hoolamike does not execute installers, only the files the modlist takes out of them are used".to_string()])
+                .join("\n"),
+        }
+    }
 }
+
 impl ModlistSummary {
     pub fn print(&self) -> String {
         tabled::Table::new([self])
@@ -45,23 +501,18 @@ impl ModlistSummary {
             .to_string()
     }
 
-    pub fn new(
-        Modlist {
+    pub fn new(modlist: &Modlist, selection: &TagSelection) -> Self {
+        let report = ModlistReport::new(modlist, selection);
+        let Modlist {
             archives,
-            author,
             description,
             directives,
-            name,
-            website,
-            is_nsfw: _,
-            game_type: _,
-            image: _,
-            readme: _,
-            version: _,
-            wabbajack_version: _,
-        }: &Modlist,
-    ) -> Self {
+            ..
+        } = modlist;
+        let archive_dependents = ArchiveDependents::new(directives);
         Self {
+            most_depended_on_archives: archive_dependents.largest_table(archives, MOST_DEPENDED_ON_LISTED),
+            archive_dependents,
             directive_examples: directives
                 .iter()
                 .unique_by(|d| d.directive_kind())
@@ -73,23 +524,21 @@ impl ModlistSummary {
                 })
                 .map(|(kind, directive)| format!("{kind}:\n{directive}"))
                 .join("\n\n"),
-            author: author.clone(),
-            sources: archives
-                .iter()
-                .map(|archive| archive.state.kind().to_string())
-                .pipe(summarize_value_count),
-            total_mods: archives.len(),
+            sources: report.sources_table(),
+            manual_downloads: report.manual_downloads_table(),
+            optional_content_tags: report.tags_table(),
+            installers: report.installers_table(),
+            games_referenced: report.games_table(),
+            unique_directive_kinds: report.directives_table(),
+            required_game_versions: crate::game_version::required_versions(archives).pipe_ref(crate::game_version::format_required_versions),
+            total_mods: report.total_archives,
             // unique_authors: archives
             //     .iter()
             //     .filter_map(|archive| archive.state.author.as_ref())
             //     .unique()
             //     .count(),
-            total_directives: directives.len(),
-            unique_directive_kinds: directives
-                .iter()
-                .map(|d| d.directive_kind())
-                .pipe(summarize_value_count),
-            name: name.clone(),
+            total_directives: report.total_directives,
+            total_output_size: human_readable_size(report.total_output_size),
             // unique_headers: archives
             //     .iter()
             //     .flat_map(|a| {
@@ -100,13 +549,238 @@ impl ModlistSummary {
             //     })
             //     .unique()
             //     .join(",\n"),
-            website: website.clone(),
-            total_download_size: archives
-                .iter()
-                .map(|a| a.descriptor.size)
-                .sum::<u64>()
-                .pipe(human_readable_size),
+            total_download_size: match report.selected_download_size == report.total_download_size {
+                true => human_readable_size(report.total_download_size),
+                false => format!(
+                    "{} ({} with the deselected tags)",
+                    human_readable_size(report.total_download_size),
+                    human_readable_size(report.selected_download_size)
+                ),
+            },
             description: description.clone(),
+            game: report.game_type.to_string(),
+            name: report.name.clone(),
+            version: report.version.clone(),
+            author: report.author.clone(),
+            website: report.website.clone(),
+            is_nsfw: report.is_nsfw,
+            wabbajack_version: report.wabbajack_version.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn archive(name: &str, size: u64, state: serde_json::Value) -> serde_json::Value {
+        archive_with_meta(name, size, state, "")
+    }
+
+    fn archive_with_meta(name: &str, size: u64, state: serde_json::Value, meta: &str) -> serde_json::Value {
+        json!({"Hash": "AAAAAAAAAAA=", "Meta": meta, "Name": name, "Size": size, "State": state})
+    }
+
+    fn modlist() -> Modlist {
+        let http = |url: &str| json!({"$type": "HttpDownloader, Wabbajack.Lib", "Url": url});
+        let manual = |url: &str| json!({"$type": "ManualDownloader, Wabbajack.Lib", "Prompt": "click the button", "Url": url});
+        let game_file = |version: &str| {
+            json!({
+                "$type": "GameFileSourceDownloader, Wabbajack.Lib",
+                "GameVersion": version,
+                "Hash": "AAAAAAAAAAA=",
+                "GameFile": "Data/Skyrim.esm",
+                "Game": "SkyrimSpecialEdition",
+            })
+        };
+        serde_json::from_value(json!({
+            "Archives": [
+                archive("small.7z", 10, http("https://example.com/small.7z")),
+                archive_with_meta(
+                    "big.7z",
+                    5000,
+                    http("https://example.com/big.7z"),
+                    "[General]\ndirectURL=https://example.com/big.7z\ntag=ENB, Textures 4K\n",
+                ),
+                archive_with_meta(
+                    "zeta.zip",
+                    700,
+                    manual("https://example.com/zeta"),
+                    "[General]\nmanualURL=https://example.com/zeta\ninstaller=true\nunsafe=true\ntag=ENB\n",
+                ),
+                archive("alpha.zip", 300, manual("https://example.com/alpha")),
+                archive("Skyrim.esm", 1, game_file("1.6.1170.0")),
+                archive("Update.esm", 1, game_file("1.6.1170.0")),
+            ],
+            "Author": "someone",
+            "Directives": [
+                {"$type": "FromArchive", "Hash": "AAAAAAAAAAA=", "Size": 2000, "To": "mods/a.nif", "ArchiveHashPath": ["AAAAAAAAAAA=", "a.nif"]},
+                {"$type": "FromArchive", "Hash": "AAAAAAAAAAA=", "Size": 3000, "To": "mods/b.nif", "ArchiveHashPath": ["AAAAAAAAAAA=", "b.nif"]},
+                {"$type": "InlineFile", "Hash": "AAAAAAAAAAA=", "Size": 10000, "SourceDataID": "a4d2f0d2-3b7c-4c2e-9d65-4ab1f0a1c001", "To": "mods/meta.ini"},
+            ],
+            "GameType": "SkyrimSpecialEdition",
+            "IsNSFW": true,
+            "Name": "Test List",
+            "Version": "1.2.3",
+            "WabbajackVersion": "3.7.0.0",
+            "Website": "",
+        }))
+        .expect("valid modlist")
+    }
+
+    #[test_log::test]
+    fn test_report_groups_and_sorts() {
+        let report = ModlistReport::new(&modlist(), &TagSelection::default());
+        assert_eq!(
+            report.sources,
+            [
+                SourceBreakdown {
+                    source: DownloadKind::Http,
+                    files: 2,
+                    total_size: 5010,
+                },
+                SourceBreakdown {
+                    source: DownloadKind::Manual,
+                    files: 2,
+                    total_size: 1000,
+                },
+                SourceBreakdown {
+                    source: DownloadKind::GameFileSource,
+                    files: 2,
+                    total_size: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            report
+                .manual_downloads
+                .iter()
+                .map(|manual| manual.name.as_str())
+                .collect_vec(),
+            ["alpha.zip", "zeta.zip"]
+        );
+        assert_eq!(
+            report
+                .directives
+                .iter()
+                .map(|d| (d.kind, d.count, d.total_size))
+                .collect_vec(),
+            [(DirectiveKind::InlineFile, 1, 10000), (DirectiveKind::FromArchive, 2, 5000)]
+        );
+        assert_eq!(report.total_output_size, 15000);
+        assert_eq!(report.games.len(), 1);
+        assert_eq!(report.games[0].files, 2);
+        assert_eq!(report.games[0].versions, BTreeSet::from(["1.6.1170.0".to_string()]));
+        assert!(report.is_nsfw);
+        assert_eq!(report.wabbajack_version, "3.7.0.0");
+    }
+
+    #[test_log::test]
+    fn test_tags_and_installers() {
+        let everything = ModlistReport::new(&modlist(), &TagSelection::default());
+        assert_eq!(
+            everything.tags,
+            [
+                TagGroup {
+                    tag: "ENB".into(),
+                    archives: vec!["big.7z".into(), "zeta.zip".into()],
+                    total_size: 5700,
+                    selected: true,
+                },
+                TagGroup {
+                    tag: "Textures 4K".into(),
+                    archives: vec!["big.7z".into()],
+                    total_size: 5000,
+                    selected: true,
+                },
+            ]
+        );
+        assert_eq!(everything.selected_download_size, everything.total_download_size);
+        assert_eq!(
+            everything.installers,
+            [InstallerArchive {
+                name: "zeta.zip".into(),
+                is_unsafe: true,
+            }]
+        );
+        assert!(
+            everything
+                .installers_table()
+                .contains("hoolamike does not execute installers")
+        );
+
+        let without_enb = ModlistReport::new(
+            &modlist(),
+            &TagSelection::default().tap_mut(|selection| {
+                selection.deselect("ENB");
+            }),
+        );
+        // big.7z is still needed by the selected 4K textures
+        assert_eq!(without_enb.selected_download_size, everything.total_download_size - 700);
+        assert!(without_enb.tags_table().starts_with("ENB: 2 archives / "));
+        assert!(
+            without_enb
+                .tags_table()
+                .contains("(deselected)\nTextures 4K")
+        );
+
+        let without_both = ModlistReport::new(
+            &modlist(),
+            &TagSelection::default().tap_mut(|selection| {
+                selection.deselect("ENB").deselect("Textures 4K");
+            }),
+        );
+        assert_eq!(without_both.selected_download_size, everything.total_download_size - 5700);
+    }
+
+    #[test_log::test]
+    fn test_human_output_and_json() -> anyhow::Result<()> {
+        let report = ModlistReport::new(&modlist(), &TagSelection::default());
+        assert_eq!(with_thousands_separator(1234567), "1,234,567");
+        assert_eq!(with_thousands_separator(999), "999");
+        assert!(report.sources_table().starts_with("Http: 2 files / "));
+        assert_eq!(report.games_table(), "SkyrimSpecialEdition: 2 files");
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(json["sources"][0]["source"], "Http");
+        assert_eq!(json["directives"][1]["kind"], "FromArchive");
+        assert_eq!(json["is_nsfw"], true);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_archive_dependents() -> anyhow::Result<()> {
+        let modlist = modlist()
+            .tap_mut(|modlist| modlist.archives[1].descriptor.hash = "BBBBBBBBBBB=".into())
+            .tap_mut(|modlist| match &mut modlist.directives[1] {
+                Directive::FromArchive(directive) => directive.archive_hash_path.source_hash = "BBBBBBBBBBB=".into(),
+                other => panic!("unexpected directive: {other:?}"),
+            });
+        let dependents = ArchiveDependents::new(&modlist.directives);
+        assert_eq!(dependents.impact("AAAAAAAAAAA="), ArchiveImpact { files: 1, total_size: 2000 });
+        assert_eq!(dependents.impact("BBBBBBBBBBB="), ArchiveImpact { files: 1, total_size: 3000 });
+        assert_eq!(
+            dependents.impact("CCCCCCCCCCC="),
+            ArchiveImpact::default(),
+            "inline files come from the modlist itself"
+        );
+        assert_eq!(
+            dependents.largest_table(&modlist.archives, 1),
+            format!("big.7z: {}", ArchiveImpact { files: 1, total_size: 3000 })
+        );
+
+        let by_name = ArchiveReport::new(&modlist, "BIG.7z")?;
+        assert_eq!(by_name.hash, "BBBBBBBBBBB=");
+        assert_eq!(
+            by_name.directives,
+            [DependentDirective {
+                kind: DirectiveKind::FromArchive,
+                to: "mods/b.nif".into(),
+                size: 3000,
+            }]
+        );
+        assert!(by_name.print().contains("1 files"), "{}", by_name.print());
+        assert_eq!(ArchiveReport::new(&modlist, "BBBBBBBBBBB=")?.name, "big.7z");
+        assert!(ArchiveReport::new(&modlist, "missing.7z").is_err());
+        Ok(())
+    }
+}
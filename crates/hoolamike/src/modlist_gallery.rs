@@ -0,0 +1,451 @@
+//! the official modlist gallery: every repository listed in the feed publishes its own modlists.json,
+//! entries point at the .wabbajack file uploaded to the authored-files CDN
+
+use {
+    crate::{
+        config_file::{edit_config, edit_config_file, yaml_section},
+        helpers::human_readable_size,
+        install_modlist::{download_cache::validate_hash_wabbajack, downloads::HTTP_CLIENT},
+        modlist_json::HumanUrl,
+        utils::write_atomically,
+    },
+    anyhow::{Context, Result},
+    case_insensitive_path::PathExistsUtf8Ext,
+    chrono::{DateTime, Utc},
+    futures::{FutureExt, StreamExt, TryFutureExt},
+    indexmap::IndexMap,
+    itertools::Itertools,
+    serde::{Deserialize, Serialize},
+    std::{
+        future::Future,
+        num::NonZeroUsize,
+        path::{Path, PathBuf},
+    },
+    tabled::{
+        Tabled,
+        settings::{Color, Style, object::Columns},
+    },
+    tap::prelude::*,
+    tracing::{info, warn},
+};
+
+/// repository name -> url of its modlists.json, the same feed the gallery of the official client is built from
+pub const REPOSITORIES_URL: &str = "https://raw.githubusercontent.com/wabbajack-tools/mod-lists/master/repositories.json";
+const CACHE_FILE_NAME: &str = "modlist-gallery.json";
+
+#[derive(clap::Args, Clone)]
+pub struct BrowseCli {
+    /// only lists modlists for this game (case insensitive, e.g. 'skyrimspecialedition')
+    #[arg(long)]
+    game: Option<String>,
+    /// only lists modlists with this phrase in the title, author or machine url (case insensitive)
+    #[arg(long)]
+    search: Option<String>,
+}
+
+#[derive(clap::Args, Clone)]
+pub struct FetchCli {
+    /// machine url as printed by 'browse-modlists' ('repository/name', the repository can be skipped when the name is unique)
+    machine_url: String,
+    /// where the .wabbajack file is written to, defaults to '<name>.wabbajack' in the current directory
+    #[arg(long)]
+    to: Option<PathBuf>,
+    /// points 'installation.wabbajack_file_path' in the config at the downloaded file
+    #[arg(long)]
+    set_config: bool,
+}
+
+/// the feed is not ours - fields hoolamike doesn't care about are ignored, so that new ones don't break browsing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Links {
+    #[serde(rename = "machineURL")]
+    pub machine_url: String,
+    pub download: String,
+    #[serde(default)]
+    pub readme: String,
+    /// thumbnail shown in the gui
+    #[serde(default)]
+    pub image: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DownloadMetadata {
+    /// wabbajack (xxhash64, base64) hash of the .wabbajack file
+    pub hash: String,
+    pub size: u64,
+    #[serde(default)]
+    pub size_of_archives: u64,
+    #[serde(default)]
+    pub size_of_installed_files: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GalleryEntry {
+    pub title: String,
+    #[serde(default)]
+    pub author: String,
+    pub game: String,
+    #[serde(default)]
+    pub nsfw: bool,
+    #[serde(default)]
+    pub version: Option<String>,
+    pub links: Links,
+    #[serde(default)]
+    pub download_metadata: Option<DownloadMetadata>,
+    /// not part of the feed, filled in after fetching the repository the entry comes from
+    #[serde(default)]
+    pub repository: String,
+}
+
+impl GalleryEntry {
+    pub fn machine_url(&self) -> String {
+        format!("{}/{}", self.repository, self.links.machine_url)
+    }
+
+    /// default name of the downloaded .wabbajack file
+    pub fn file_name(&self) -> String {
+        format!("{}.wabbajack", self.links.machine_url)
+    }
+
+    fn matches(&self, game: Option<&str>, search: Option<&str>) -> bool {
+        game.is_none_or(|game| self.game.eq_ignore_ascii_case(game))
+            && search.map(str::to_lowercase).is_none_or(|search| {
+                [self.title.as_str(), self.author.as_str(), self.machine_url().as_str()]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&search))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedGallery {
+    pub fetched_at: DateTime<Utc>,
+    pub entries: Vec<GalleryEntry>,
+}
+
+#[derive(Tabled)]
+pub struct GalleryRow {
+    pub title: String,
+    pub author: String,
+    pub game: String,
+    pub download_size: String,
+    pub installed_size: String,
+    pub nsfw: bool,
+    pub machine_url: String,
+}
+
+impl From<&GalleryEntry> for GalleryRow {
+    fn from(entry: &GalleryEntry) -> Self {
+        let size = |size: fn(&DownloadMetadata) -> u64| {
+            entry
+                .download_metadata
+                .as_ref()
+                .map(size)
+                .map(human_readable_size)
+                .unwrap_or_else(|| "?".to_string())
+        };
+        Self {
+            title: entry.title.clone(),
+            author: entry.author.clone(),
+            game: entry.game.clone(),
+            download_size: size(|metadata| metadata.size_of_archives),
+            installed_size: size(|metadata| metadata.size_of_installed_files),
+            nsfw: entry.nsfw,
+            machine_url: entry.machine_url(),
+        }
+    }
+}
+
+pub fn cache_path() -> PathBuf {
+    directories::ProjectDirs::from("", "", clap::crate_name!())
+        .map(|dirs| dirs.cache_dir().to_owned())
+        .unwrap_or_else(std::env::temp_dir)
+        .join(CACHE_FILE_NAME)
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T> {
+    HTTP_CLIENT
+        .get(url)
+        .send()
+        .map(|response| response.and_then(|response| response.error_for_status()))
+        .and_then(|response| response.text())
+        .await
+        .with_context(|| format!("fetching [{url}]"))
+        .and_then(|text| crate::utils::deserialize_json_with_error_location(&text).with_context(|| format!("parsing response from [{url}]")))
+}
+
+/// a single broken repository only hides its own modlists
+pub async fn fetch_gallery() -> Result<Vec<GalleryEntry>> {
+    get_json::<IndexMap<String, String>>(REPOSITORIES_URL)
+        .and_then(async |repositories| {
+            futures::stream::iter(repositories)
+                .map(async |(repository, url)| {
+                    get_json::<Vec<GalleryEntry>>(&url)
+                        .await
+                        .map(|entries| {
+                            entries
+                                .into_iter()
+                                .map(|entry| GalleryEntry {
+                                    repository: repository.clone(),
+                                    ..entry
+                                })
+                                .collect_vec()
+                        })
+                        .tap_err(|e| warn!("skipping repository [{repository}]: {e:?}"))
+                        .unwrap_or_default()
+                })
+                .buffered(8)
+                .concat()
+                .await
+                .pipe(|entries: Vec<_>| match entries.is_empty() {
+                    true => Err(anyhow::anyhow!("no repository could be fetched")),
+                    false => Ok(entries),
+                })
+        })
+        .await
+        .context("fetching modlist gallery")
+}
+
+/// falls back to the copy written by the last successful fetch when the gallery can't be reached
+pub async fn load_gallery(fetch: impl Future<Output = Result<Vec<GalleryEntry>>>, cache: &Path) -> Result<Vec<GalleryEntry>> {
+    match fetch.await {
+        Ok(entries) => {
+            write_cache(cache, &entries);
+            Ok(entries)
+        }
+        Err(fetching) => read_cache(cache)
+            .map(|CachedGallery { fetched_at, entries }| {
+                warn!("could not fetch the modlist gallery, showing the copy from [{fetched_at}]: {fetching:?}");
+                entries
+            })
+            .with_context(|| format!("{fetching:?}"))
+            .context("gallery could not be fetched and there is no usable cached copy"),
+    }
+}
+
+/// a gallery which can't be cached is still shown, so failures are only logged
+pub fn write_cache(cache: &Path, entries: &[GalleryEntry]) {
+    CachedGallery {
+        fetched_at: Utc::now(),
+        entries: entries.to_vec(),
+    }
+    .pipe_ref(serde_json::to_string)
+    .context("serializing gallery")
+    .and_then(|cached| {
+        cache
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .context("creating cache directory")
+            .and_then(|_| write_atomically(cache, cached))
+    })
+    .unwrap_or_else(|e| warn!("could not cache the modlist gallery: {e:?}"))
+}
+
+/// copy written by the last successful fetch
+pub fn read_cache(cache: &Path) -> Result<CachedGallery> {
+    std::fs::read_to_string(cache)
+        .with_context(|| format!("reading cached gallery at [{}]", cache.display()))
+        .and_then(|cached| serde_json::from_str::<CachedGallery>(&cached).context("parsing cached gallery"))
+}
+
+/// downloads the .wabbajack file of the entry and checks it against the published hash, returns its canonical path.
+/// `on_part` is called with the number of finished parts and the number of all parts
+pub async fn download_entry(entry: &GalleryEntry, to: PathBuf, on_part: impl Fn(usize, usize) + Clone + Send + 'static) -> Result<PathBuf> {
+    let url = entry
+        .links
+        .download
+        .parse::<HumanUrl>()
+        .with_context(|| format!("bad download url [{}]", entry.links.download))?;
+    info!("downloading [{}] ({}) to [{}]", entry.title, entry.machine_url(), to.display());
+    let downloaded = crate::download_wabbajack_cdn::CommandArgs {
+        url,
+        to,
+        download_concurrency: NonZeroUsize::new(16).expect("not zero"),
+    }
+    .download_with_progress(on_part)
+    .await?;
+    match entry.download_metadata.as_ref() {
+        Some(metadata) => downloaded
+            .exists_utf8()
+            .pipe(futures::future::ready)
+            .and_then(|downloaded| validate_hash_wabbajack(downloaded, metadata.hash.clone()))
+            .await
+            .context("downloaded modlist does not match the published hash")
+            .map(|_| ())?,
+        None => warn!("[{}] does not publish a hash, the download could not be verified", entry.machine_url()),
+    }
+    std::fs::canonicalize(&downloaded)
+        .with_context(|| format!("canonicalizing [{}]", downloaded.display()))
+        .tap_ok(|downloaded| info!("modlist is ready at [{}]", downloaded.display()))
+}
+
+pub fn filter<'a>(entries: &'a [GalleryEntry], game: Option<&str>, search: Option<&str>) -> Vec<&'a GalleryEntry> {
+    entries
+        .iter()
+        .filter(|entry| entry.matches(game, search))
+        .sorted_by(|a, b| a.game.cmp(&b.game).then_with(|| a.title.cmp(&b.title)))
+        .collect()
+}
+
+/// 'repository/name' has to match exactly, a bare name only when no other repository uses it
+pub fn resolve<'a>(entries: &'a [GalleryEntry], machine_url: &str) -> Result<&'a GalleryEntry> {
+    let found = match machine_url.split_once('/') {
+        Some(_) => entries
+            .iter()
+            .filter(|entry| entry.machine_url().eq_ignore_ascii_case(machine_url))
+            .collect_vec(),
+        None => entries
+            .iter()
+            .filter(|entry| entry.links.machine_url.eq_ignore_ascii_case(machine_url))
+            .collect_vec(),
+    };
+    match found.as_slice() {
+        [entry] => Ok(*entry),
+        [] => Err(anyhow::anyhow!(
+            "no modlist with machine url [{machine_url}], run 'hoolamike browse-modlists --search <phrase>' to look it up"
+        )),
+        many => Err(anyhow::anyhow!(
+            "[{machine_url}] is ambiguous, pick one of: [{}]",
+            many.iter().map(|entry| entry.machine_url()).join(", ")
+        )),
+    }
+}
+
+pub fn set_wabbajack_file_path(config: &str, wabbajack_file_path: &Path) -> Result<String> {
+    edit_config(config, |config| {
+        wabbajack_file_path
+            .to_str()
+            .with_context(|| format!("[{}] is not valid utf-8", wabbajack_file_path.display()))
+            .and_then(|path| {
+                yaml_section(config, "installation").map(|installation| {
+                    installation.insert("wabbajack_file_path".into(), path.into());
+                })
+            })
+    })
+}
+
+pub fn run_browse(BrowseCli { game, search }: BrowseCli) -> Result<()> {
+    crate::tokio_runtime_multi(2)
+        .and_then(|runtime| runtime.block_on(load_gallery(fetch_gallery(), &cache_path())))
+        .map(|entries| {
+            filter(&entries, game.as_deref(), search.as_deref())
+                .into_iter()
+                .map(GalleryRow::from)
+                .collect_vec()
+                .pipe(|rows| match rows.is_empty() {
+                    true => println!("no modlist matches"),
+                    false => println!(
+                        "{}\n[{}] modlists, download one with 'hoolamike fetch-modlist <machine_url>'",
+                        tabled::Table::new(&rows)
+                            .with(Style::modern())
+                            .modify(Columns::single(0), Color::FG_GREEN),
+                        rows.len()
+                    ),
+                })
+        })
+}
+
+pub fn run_fetch(FetchCli { machine_url, to, set_config }: FetchCli, config_path: &Path) -> Result<()> {
+    crate::tokio_runtime_multi(num_cpus::get()).and_then(|runtime| {
+        runtime.block_on(async {
+            let entries = load_gallery(fetch_gallery(), &cache_path()).await?;
+            let entry = resolve(&entries, &machine_url)?;
+            let to = to.unwrap_or_else(|| PathBuf::from(entry.file_name()));
+            let downloaded = download_entry(entry, to, |_, _| {}).await?;
+            match set_config {
+                true => edit_config_file(config_path, |config| set_wabbajack_file_path(config, &downloaded))
+                    .tap_ok(|_| info!("'installation.wabbajack_file_path' in [{}] now points at it", config_path.display())),
+                false => Ok(()),
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::config_file::HoolamikeConfig, serde_json::json};
+
+    fn entry(repository: &str, machine_url: &str, title: &str, game: &str) -> GalleryEntry {
+        serde_json::from_value::<GalleryEntry>(json!({
+            "title": title,
+            "description": "not used by hoolamike",
+            "author": "someone",
+            "game": game,
+            "official": false,
+            "tags": ["Graphics"],
+            "nsfw": false,
+            "utility_list": false,
+            "links": {
+                "image": "https://example.com/image.webp",
+                "readme": "https://example.com/readme",
+                "download": format!("https://authored-files.wabbajack.org/{machine_url}.wabbajack_abc"),
+                "machineURL": machine_url,
+            },
+            "download_metadata": {
+                "Hash": "eOiJRFeBuzY=",
+                "Size": 1234,
+                "NumberOfArchives": 10,
+                "SizeOfArchives": 2_000_000_000u64,
+                "NumberOfInstalledFiles": 100,
+                "SizeOfInstalledFiles": 4_000_000_000u64,
+            },
+            "version": "1.0.0",
+        }))
+        .expect("valid gallery entry")
+        .tap_mut(|entry| entry.repository = repository.to_string())
+    }
+
+    fn gallery() -> Vec<GalleryEntry> {
+        vec![
+            entry("wj-featured", "tuxborn", "Tuxborn", "skyrimspecialedition"),
+            entry("wj-featured", "magnum-opus", "Magnum Opus", "fallout4"),
+            entry("other-repo", "tuxborn", "Tuxborn (fork)", "skyrimspecialedition"),
+        ]
+    }
+
+    #[test_log::test]
+    fn test_filtering() {
+        let gallery = gallery();
+        let titles = |entries: Vec<&GalleryEntry>| entries.iter().map(|e| e.title.as_str()).collect_vec();
+        assert_eq!(titles(filter(&gallery, Some("Fallout4"), None)), ["Magnum Opus"]);
+        assert_eq!(titles(filter(&gallery, None, Some("FORK"))), ["Tuxborn (fork)"]);
+        assert_eq!(titles(filter(&gallery, None, Some("other-repo/"))), ["Tuxborn (fork)"]);
+        assert_eq!(titles(filter(&gallery, None, None)), ["Magnum Opus", "Tuxborn", "Tuxborn (fork)"]);
+        assert_eq!(GalleryRow::from(&gallery[1]).machine_url, "wj-featured/magnum-opus");
+    }
+
+    #[test_log::test]
+    fn test_resolving_machine_urls() -> Result<()> {
+        let gallery = gallery();
+        assert_eq!(resolve(&gallery, "magnum-opus")?.title, "Magnum Opus");
+        assert_eq!(resolve(&gallery, "other-repo/tuxborn")?.title, "Tuxborn (fork)");
+        assert!(format!("{:?}", resolve(&gallery, "tuxborn").unwrap_err()).contains("wj-featured/tuxborn, other-repo/tuxborn"));
+        assert!(resolve(&gallery, "nope").is_err());
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_gallery_falls_back_to_cache() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let cache = directory.path().join("cache").join(CACHE_FILE_NAME);
+        assert!(
+            load_gallery(async { anyhow::bail!("offline") }, &cache)
+                .await
+                .is_err()
+        );
+        assert_eq!(load_gallery(async { Ok(gallery()) }, &cache).await?, gallery());
+        assert_eq!(load_gallery(async { anyhow::bail!("offline") }, &cache).await?, gallery());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_set_wabbajack_file_path() -> Result<()> {
+        let updated = set_wabbajack_file_path(&HoolamikeConfig::write_default()?, Path::new("/modlists/tuxborn.wabbajack"))?;
+        let config = serde_yaml::from_str::<HoolamikeConfig>(&updated)?;
+        assert_eq!(config.installation.wabbajack_file_path, Path::new("/modlists/tuxborn.wabbajack"));
+        assert_eq!(config.installation.installation_path, HoolamikeConfig::default().installation.installation_path);
+        Ok(())
+    }
+}
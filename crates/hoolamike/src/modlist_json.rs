@@ -135,6 +135,9 @@ pub struct Archive {
     pub state: State,
 }
 
+pub mod about;
+pub mod archive_meta;
+pub mod format_version;
 pub mod type_guard;
 
 #[allow(clippy::large_enum_variant)]
@@ -172,10 +175,47 @@ impl State {
 #[serde(deny_unknown_fields)]
 pub struct HttpState {
     #[serde(default)]
-    pub headers: Vec<()>,
+    pub headers: Vec<HttpHeader>,
     pub url: HumanUrl,
 }
 
+/// sent along with the download (a `Referer`, an auth token...), wabbajack keeps them as `"Name: value"` strings.
+/// values are redacted when printed, they can be credentials
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl TryFrom<String> for HttpHeader {
+    type Error = anyhow::Error;
+
+    fn try_from(header: String) -> Result<Self, Self::Error> {
+        header
+            .split_once(':')
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, value)| Self {
+                name: name.to_string(),
+                value: value.to_string(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("not a [Name: value] header: [{}]", header.chars().take(32).collect::<String>()))
+    }
+}
+
+impl From<HttpHeader> for String {
+    fn from(HttpHeader { name, value }: HttpHeader) -> Self {
+        format!("{name}: {value}")
+    }
+}
+
+impl std::fmt::Debug for HttpHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: <redacted>", self.name)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "PascalCase")]
 #[serde(deny_unknown_fields)]
@@ -273,7 +313,10 @@ pub mod directive;
 #[derive(Debug, Serialize, Deserialize, enum_kinds::EnumKind)]
 #[serde(tag = "$type")]
 #[serde(deny_unknown_fields)]
-#[enum_kind(DirectiveKind, derive(Serialize, Deserialize, PartialOrd, Ord, derive_more::Display, Hash, clap::ValueEnum))]
+#[enum_kind(
+    DirectiveKind,
+    derive(Serialize, Deserialize, PartialOrd, Ord, derive_more::Display, Hash, clap::ValueEnum, schemars::JsonSchema)
+)]
 pub enum Directive {
     CreateBSA(directive::create_bsa_directive::CreateBSADirective),
     FromArchive(directive::FromArchiveDirective),
@@ -281,6 +324,9 @@ pub enum Directive {
     PatchedFromArchive(directive::PatchedFromArchiveDirective),
     RemappedInlineFile(directive::RemappedInlineFileDirective),
     TransformedTexture(directive::TransformedTextureDirective),
+    /// anything with a `$type` not listed above, tried only after the known kinds did not match
+    #[serde(untagged)]
+    Unknown(directive::unknown_directive::UnknownDirective),
 }
 
 impl Directive {
@@ -292,6 +338,7 @@ impl Directive {
             Directive::PatchedFromArchive(d) => d.size,
             Directive::RemappedInlineFile(d) => d.size,
             Directive::TransformedTexture(d) => d.size,
+            Directive::Unknown(d) => d.size,
         }
     }
     pub fn directive_hash(&self) -> String {
@@ -381,6 +428,38 @@ pub mod parsing_helpers {
         Other(&'a serde_json::Value),
     }
 
+    /// lines shown before (and after) the one that failed to parse
+    const CONTEXT_LINES: usize = 20;
+    /// modlists are usually written out as a single line, so long lines are cut to this many characters around the failing column
+    const CONTEXT_COLUMNS: usize = 400;
+
+    /// numbered lines around `line` (1-based, like [serde_json::Error::line]), with long ones cut around `column`
+    pub fn error_context(input: &str, line: usize, column: usize) -> String {
+        let around_column = |text: &str| match text.chars().count() > CONTEXT_COLUMNS {
+            false => text.to_string(),
+            true => {
+                let skipped = column.saturating_sub(CONTEXT_COLUMNS / 2);
+                let window = text
+                    .chars()
+                    .skip(skipped)
+                    .take(CONTEXT_COLUMNS)
+                    .collect::<String>();
+                let (before, after) = (
+                    if skipped > 0 { "..." } else { "" },
+                    if text.chars().count() > skipped + CONTEXT_COLUMNS { "..." } else { "" },
+                );
+                format!("{before}{window}{after}")
+            }
+        };
+        input
+            .lines()
+            .enumerate()
+            .skip(line.saturating_sub(CONTEXT_LINES))
+            .take(CONTEXT_LINES * 2)
+            .map(|(idx, text)| format!("{}. {}", idx + 1, around_column(text)))
+            .join("\n")
+    }
+
     pub fn validate_modlist_file(input: &str) -> Result<()> {
         input
             .tap(|input| {
@@ -393,21 +472,12 @@ pub mod parsing_helpers {
                 serde_json::from_str::<crate::modlist_json::Modlist>(&pretty_input)
                     .pipe(|res| match res.as_ref() {
                         Ok(_) => res.context(""),
-                        Err(e) => e.line().pipe(|line| {
-                            res.with_context(|| {
-                                pretty_input
-                                    .lines()
-                                    .enumerate()
-                                    .map(|(idx, line)| format!("{}. {line}", idx + 1))
-                                    .skip(line - 20)
-                                    .take(40)
-                                    .join("\n")
-                            })
-                        }),
+                        Err(e) => (e.line(), e.column()).pipe(|(line, column)| res.with_context(|| error_context(&pretty_input, line, column))),
                     })
+                    .with_context(|| super::format_version::describe(super::format_version::sniff(&pretty_input)))
                     .context("bad modlist")
             })
-            .map(|_| ())
+            .map(|modlist| info!("{}", super::format_version::describe(Some(&modlist.wabbajack_version))))
     }
 
     #[allow(unexpected_cfgs)]
@@ -442,3 +512,39 @@ pub mod parsing_helpers {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_http_state_headers() -> anyhow::Result<()> {
+        let state = serde_json::from_str::<State>(
+            r#"{
+                "$type": "HttpDownloader, Wabbajack.Lib",
+                "Headers": [
+                    "User-Agent: Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36",
+                    "Referer: https://www.moddb.com/mods/example/downloads/example-patch",
+                    "Authorization: Bearer c2VjcmV0LXRva2Vu"
+                ],
+                "Url": "https://www.moddb.com/downloads/mirror/123456/130/0123456789abcdef"
+            }"#,
+        )?;
+        let State::Http(HttpState { headers, .. }) = &state else {
+            anyhow::bail!("not an http state: {state:?}")
+        };
+        assert_eq!(
+            headers
+                .iter()
+                .map(|header| header.name.as_str())
+                .collect::<Vec<_>>(),
+            ["User-Agent", "Referer", "Authorization"]
+        );
+        assert_eq!(headers[1].value, "https://www.moddb.com/mods/example/downloads/example-patch");
+        assert_eq!(headers[2].value, "Bearer c2VjcmV0LXRva2Vu");
+        assert!(!format!("{state:?}").contains("c2VjcmV0LXRva2Vu"));
+        assert_eq!(serde_json::to_value(&headers[2])?, serde_json::json!("Authorization: Bearer c2VjcmV0LXRva2Vu"));
+        assert!(serde_json::from_str::<HttpHeader>(r#""no colon here""#).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! what the author of a modlist wants people to read before installing it - authors put the steps the installation can't
+//! do on its own in the readme. the gui shows it as soon as the modlist is loaded, the cli prints it once at the start
+
+use {super::Modlist, itertools::Itertools, std::fmt};
+
+pub const NSFW_WARNING: &str = "this modlist is marked as NSFW - it contains adult content";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct About<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub author: &'a str,
+    pub description: Option<&'a str>,
+    pub website: Option<&'a str>,
+    /// usually a link, sometimes just text
+    pub readme: Option<&'a str>,
+    pub is_nsfw: bool,
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|value| !value.is_empty())
+}
+
+/// only links can be opened, anything else is just shown
+pub fn as_link(value: &str) -> Option<&str> {
+    value
+        .parse::<url::Url>()
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(|_| value)
+}
+
+impl Modlist {
+    pub fn about(&self) -> About<'_> {
+        About {
+            name: &self.name,
+            version: &self.version,
+            author: &self.author,
+            description: non_empty(&self.description),
+            website: non_empty(&self.website),
+            readme: non_empty(&self.readme),
+            is_nsfw: self.is_nsfw,
+        }
+    }
+}
+
+impl fmt::Display for About<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            name,
+            version,
+            author,
+            description,
+            website,
+            readme,
+            is_nsfw,
+        } = self;
+        let about = [
+            Some(format!("\"{name}\" by {author} (v{version})")),
+            is_nsfw.then(|| format!("!!! {NSFW_WARNING} !!!")),
+            description.map(|description| description.to_string()),
+            website.map(|website| format!("website: {website}")),
+            readme.map(|readme| format!("readme (read it before installing): {readme}")),
+        ]
+        .into_iter()
+        .flatten()
+        .join("\n");
+        write!(f, "{about}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::Result};
+
+    fn modlist(readme: &str, is_nsfw: bool) -> Result<Modlist> {
+        serde_json::from_value(serde_json::json!({
+            "Archives": [],
+            "Author": "Someone",
+            "Description": "  ",
+            "Directives": [],
+            "GameType": "SkyrimSpecialEdition",
+            "IsNSFW": is_nsfw,
+            "Name": "Some Modlist",
+            "Readme": readme,
+            "Version": "1.2.3",
+            "WabbajackVersion": "3.7.0.0",
+            "Website": "https://example.com",
+        }))
+        .map_err(Into::into)
+    }
+
+    #[test_log::test]
+    fn test_about_mentions_the_readme_and_nsfw() -> Result<()> {
+        let modlist = modlist("https://github.com/someone/some-modlist#readme", true)?;
+        let about = modlist.about();
+        assert_eq!(about.description, None, "blank description is left out");
+        assert_eq!(about.readme.and_then(as_link), Some("https://github.com/someone/some-modlist#readme"));
+        let printed = about.to_string();
+        [
+            "\"Some Modlist\" by Someone (v1.2.3)",
+            NSFW_WARNING,
+            "website: https://example.com",
+            "readme (read it before installing): https://github.com/someone/some-modlist#readme",
+        ]
+        .iter()
+        .for_each(|expected| assert!(printed.contains(expected), "[{expected}] missing from:\n{printed}"));
+
+        let modlist = self::modlist("readme.md", false)?;
+        assert_eq!(modlist.about().readme.and_then(as_link), None, "not a link");
+        assert!(!modlist.about().to_string().contains(NSFW_WARNING));
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! `Archive.meta` is the `.meta` ini Wabbajack writes next to each download. newer lists put behavior hints in its `[General]` section:
+//! `unsafe` (the archive ships executables), `installer` (Wabbajack runs it in a sandbox) and `tag` (groups optional content)
+
+use {
+    super::ArchiveDescriptor,
+    crate::post_install_fixup::ini::IniDocument,
+    indexmap::IndexMap,
+    itertools::Itertools,
+    serde::Serialize,
+    std::collections::BTreeSet,
+    tap::prelude::*,
+};
+
+const GENERAL: &str = "General";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveMeta {
+    /// `unsafe=true`
+    pub is_unsafe: bool,
+    /// `installer=true` - hoolamike never executes these
+    pub installer: bool,
+    /// `tag=a, b` - repeated keys are merged, order of first appearance is kept
+    pub tags: Vec<String>,
+    /// every other `[General]` entry (`gameName`, `modID`, `directURL`...)
+    pub other: IndexMap<String, String>,
+}
+
+fn flag(value: &str) -> bool {
+    ["true", "1", "yes"]
+        .iter()
+        .any(|truthy| value.eq_ignore_ascii_case(truthy))
+}
+
+impl ArchiveMeta {
+    pub fn parse(meta: &str) -> Self {
+        IniDocument::parse(meta)
+            .entries(GENERAL)
+            .into_iter()
+            .fold(Self::default(), |mut parsed, (key, value)| {
+                match key.to_ascii_lowercase().as_str() {
+                    "unsafe" => parsed.is_unsafe = flag(&value),
+                    "installer" => parsed.installer = flag(&value),
+                    "tag" | "tags" => parsed.tags.extend(
+                        value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|tag| !tag.is_empty())
+                            .map(ToOwned::to_owned),
+                    ),
+                    _ => {
+                        parsed.other.insert(key, value);
+                    }
+                }
+                parsed
+            })
+            .tap_mut(|parsed| {
+                parsed.tags = std::mem::take(&mut parsed.tags)
+                    .into_iter()
+                    .unique()
+                    .collect()
+            })
+    }
+}
+
+impl ArchiveDescriptor {
+    pub fn parsed_meta(&self) -> ArchiveMeta {
+        ArchiveMeta::parse(&self.meta)
+    }
+}
+
+/// optional content is picked a whole tag at a time. an archive stays selected while any of its tags is,
+/// untagged archives are always needed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagSelection {
+    deselected: BTreeSet<String>,
+}
+
+impl TagSelection {
+    pub fn deselect(&mut self, tag: &str) -> &mut Self {
+        self.deselected.insert(tag.to_owned());
+        self
+    }
+
+    pub fn is_tag_selected(&self, tag: &str) -> bool {
+        !self.deselected.contains(tag)
+    }
+
+    pub fn is_selected(&self, meta: &ArchiveMeta) -> bool {
+        meta.tags.is_empty() || meta.tags.iter().any(|tag| self.is_tag_selected(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_plain_nexus_meta() {
+        let meta = ArchiveMeta::parse("[General]\r\ngameName=skyrimspecialedition\r\nmodID=12604\r\nfileID=429384\r\n");
+        assert_eq!(
+            meta,
+            ArchiveMeta {
+                other: [("gameName", "skyrimspecialedition"), ("modID", "12604"), ("fileID", "429384")]
+                    .into_iter()
+                    .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                    .collect(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test_log::test]
+    fn test_hints() {
+        let meta = ArchiveMeta::parse(
+            "[General]\ndirectURL=https://github.com/example/releases/download/1.0/setup.7z\nunsafe=True\ninstaller=true\ntag=Optional - ENB, \
+             Performance\nTag=Performance\n\n[installed]\ntag=ignored\n",
+        );
+        assert!(meta.is_unsafe);
+        assert!(meta.installer);
+        assert_eq!(meta.tags, ["Optional - ENB", "Performance"]);
+        assert_eq!(meta.other.keys().collect_vec(), ["directURL"]);
+        assert_eq!(ArchiveMeta::parse("[General]\ninstaller=false\nunsafe=no\ntag=\n"), ArchiveMeta::default());
+        assert_eq!(ArchiveMeta::parse(""), ArchiveMeta::default());
+    }
+
+    #[test_log::test]
+    fn test_whole_tags_are_deselected() {
+        let tagged = |tags: &[&str]| ArchiveMeta {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut selection = TagSelection::default();
+        selection.deselect("ENB").deselect("Textures 4K");
+        assert!(!selection.is_selected(&tagged(&["ENB"])));
+        assert!(!selection.is_selected(&tagged(&["ENB", "Textures 4K"])));
+        // still needed by a selected tag
+        assert!(selection.is_selected(&tagged(&["ENB", "Performance"])));
+        assert!(selection.is_selected(&tagged(&[])));
+        assert!(selection.is_tag_selected("Performance"));
+    }
+}
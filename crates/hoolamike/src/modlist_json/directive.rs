@@ -4,6 +4,11 @@ pub mod archive_hash_path;
 
 pub mod create_bsa_directive;
 
+pub mod unknown_directive;
+
+#[cfg(test)]
+pub mod fixtures;
+
 pub use archive_hash_path::ArchiveHashPath;
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
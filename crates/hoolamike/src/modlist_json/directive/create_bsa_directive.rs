@@ -49,4 +49,23 @@ impl CreateBSADirective {
             CreateBSADirective::Ba2(d) => d.size,
         }
     }
+
+    /// paths of the packed files, relative to the staging directory of the archive
+    pub fn member_paths(&self) -> Vec<&CaseInsensitivePathBuf> {
+        match self {
+            CreateBSADirective::Bsa(bsa) => bsa
+                .file_states
+                .iter()
+                .map(|bsa::FileState { inner, .. }| &inner.path)
+                .collect(),
+            CreateBSADirective::Ba2(ba2) => ba2
+                .file_states
+                .iter()
+                .map(|file_state| match file_state {
+                    ba2::FileState::BA2File(entry) => &entry.path,
+                    ba2::FileState::BA2DX10Entry(entry) => &entry.path,
+                })
+                .collect(),
+        }
+    }
 }
@@ -0,0 +1,34 @@
+//! directives for tests, only `To` (and the archive of [from_archive_in]) differ between them. file hashes are made up from
+//! the destination, so that every directive has one of its own
+
+use {crate::modlist_json::Directive, serde_json::json};
+
+pub fn directive(raw: serde_json::Value) -> Directive {
+    serde_json::from_value(raw).expect("bad directive fixture")
+}
+
+/// extracts `file.dds` out of a made up archive into `to`
+pub fn from_archive(to: &str) -> Directive {
+    from_archive_in("c291cmNlAAA=", to)
+}
+
+/// like [from_archive], out of the archive with hash `archive`
+pub fn from_archive_in(archive: &str, to: &str) -> Directive {
+    directive(json!({
+        "$type": "FromArchive",
+        "Hash": format!("file-{to}"),
+        "Size": 1,
+        "To": to,
+        "ArchiveHashPath": [archive, "file.dds"],
+    }))
+}
+
+pub fn inline_file(to: &str) -> Directive {
+    directive(json!({
+        "$type": "InlineFile",
+        "Hash": format!("file-{to}"),
+        "Size": 1,
+        "SourceDataID": "3f2f8e64-8c5b-4b6a-9a3b-8f1b7e1a0c11",
+        "To": to,
+    }))
+}
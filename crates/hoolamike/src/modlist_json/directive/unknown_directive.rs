@@ -0,0 +1,116 @@
+use {
+    crate::modlist_json::DirectiveKind,
+    case_insensitive_path::CaseInsensitivePathBuf,
+    clap::ValueEnum,
+    serde::{Deserialize, Serialize, de::Error as _},
+};
+
+/// directive of a kind hoolamike doesn't know (newer wabbajack versions add them from time to time).
+/// only the fields every wabbajack directive has are parsed, the rest is kept as is so it serializes back unchanged
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownDirective {
+    /// the `$type` of the directive
+    pub type_tag: String,
+    pub hash: String,
+    pub size: u64,
+    pub to: CaseInsensitivePathBuf,
+    /// the whole directive, `$type` included
+    pub raw: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CommonFields {
+    #[serde(rename = "$type")]
+    type_tag: String,
+    hash: String,
+    size: u64,
+    to: CaseInsensitivePathBuf,
+}
+
+fn is_known_kind(type_tag: &str) -> bool {
+    DirectiveKind::value_variants()
+        .iter()
+        .any(|kind| kind.to_string() == type_tag)
+}
+
+impl<'de> Deserialize<'de> for UnknownDirective {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        serde_json::Value::deserialize(deserializer).and_then(|raw| {
+            CommonFields::deserialize(&raw)
+                .map_err(D::Error::custom)
+                .and_then(|CommonFields { type_tag, hash, size, to }| match is_known_kind(&type_tag) {
+                    // a known kind only ends up here when its own fields did not parse, that's a broken modlist and not a new directive
+                    true => Err(D::Error::custom(format!("[{type_tag}] directive does not match its definition"))),
+                    false => Ok(Self { type_tag, hash, size, to, raw }),
+                })
+        })
+    }
+}
+
+impl Serialize for UnknownDirective {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.raw.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::modlist_json::Directive,
+        anyhow::{Context, Result},
+        tap::prelude::*,
+    };
+
+    const MERGED_PATCH: &str = r#"{
+        "$type": "MergedPatch",
+        "Hash": "eSIyd+KOG3s=",
+        "Size": 1024,
+        "To": "mods\\Merged\\merged.esp",
+        "Sources": ["AAAAAAAAAAA=", "BBBBBBBBBBB="]
+    }"#;
+
+    #[test_log::test]
+    fn test_unknown_kind_is_preserved() -> Result<()> {
+        let directive = serde_json::from_str::<Directive>(MERGED_PATCH).context("parsing")?;
+        let Directive::Unknown(unknown) = &directive else {
+            anyhow::bail!("expected an unknown directive, got {directive:?}")
+        };
+        assert_eq!(unknown.type_tag, "MergedPatch");
+        assert_eq!(unknown.size, 1024);
+        assert_eq!(directive.size(), 1024);
+        assert_eq!(directive.directive_kind(), DirectiveKind::Unknown);
+        assert_eq!(
+            unknown
+                .raw
+                .get("Sources")
+                .and_then(|sources| sources.as_array())
+                .map(Vec::len),
+            Some(2)
+        );
+        assert_eq!(
+            serde_json::to_value(&directive)?,
+            serde_json::from_str::<serde_json::Value>(MERGED_PATCH)?,
+            "serializes back to what was parsed"
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_broken_known_kind_is_not_swallowed() {
+        serde_json::from_str::<Directive>(r#"{"$type": "InlineFile", "Hash": "eSIyd+KOG3s=", "Size": 1, "To": "a.txt"}"#)
+            .pipe(|parsed| assert!(parsed.is_err(), "InlineFile without SourceDataID must not parse: {parsed:?}"));
+    }
+
+    #[test_log::test]
+    fn test_unknown_kind_needs_the_common_fields() {
+        serde_json::from_str::<Directive>(r#"{"$type": "MergedPatch", "Size": 1}"#).pipe(|parsed| assert!(parsed.is_err(), "{parsed:?}"));
+    }
+}
@@ -0,0 +1,136 @@
+//! `WabbajackVersion` of a modlist - the version of Wabbajack which compiled it. a modlist failing to parse is most of the
+//! time one made by a Wabbajack newer than hoolamike knows about, so the version is checked before any work and mentioned
+//! whenever parsing fails
+
+use {
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    tracing::warn,
+};
+
+/// newest `major.minor` modlists were tested with, patch releases don't change the format
+pub const MAX_TESTED_VERSION: (u64, u64) = (4, 0);
+
+/// dotted version like `3.7.0.0` - wabbajack uses four parts, so it's not quite semver
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WabbajackVersion(Vec<u64>);
+
+impl std::str::FromStr for WabbajackVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(version: &str) -> Result<Self> {
+        version
+            .trim()
+            .split('.')
+            .map(|part| {
+                part.parse::<u64>()
+                    .with_context(|| format!("[{part}] is not a number"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Self)
+            .with_context(|| format!("invalid wabbajack version [{version}]"))
+    }
+}
+
+impl std::fmt::Display for WabbajackVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.iter().join("."))
+    }
+}
+
+impl WabbajackVersion {
+    fn major_minor(&self) -> (u64, u64) {
+        (self.0.first().copied().unwrap_or(0), self.0.get(1).copied().unwrap_or(0))
+    }
+
+    pub fn is_newer_than_tested(&self) -> bool {
+        self.major_minor() > MAX_TESTED_VERSION
+    }
+}
+
+fn max_tested() -> String {
+    format!("{}.{}", MAX_TESTED_VERSION.0, MAX_TESTED_VERSION.1)
+}
+
+fn newer_than_tested(version: &WabbajackVersion) -> String {
+    format!(
+        "modlist was made with Wabbajack [{version}], newer than the newest one hoolamike was tested with ([{}]) - its format may have changed, check for a \
+         hoolamike update at {}/releases",
+        max_tested(),
+        env!("CARGO_PKG_REPOSITORY")
+    )
+}
+
+/// warns about modlists made by a Wabbajack newer than [MAX_TESTED_VERSION], `strict` turns the warning into an error
+pub fn check(wabbajack_version: &str, strict: bool) -> Result<()> {
+    match wabbajack_version.parse::<WabbajackVersion>() {
+        Err(reason) => {
+            warn!("{reason:#}, the modlist format can't be checked");
+            Ok(())
+        }
+        Ok(version) if version.is_newer_than_tested() => match strict {
+            true => Err(anyhow::anyhow!("{} (--strict-version is set)", newer_than_tested(&version))),
+            false => {
+                warn!("!!! {} !!!", newer_than_tested(&version));
+                Ok(())
+            }
+        },
+        Ok(_) => Ok(()),
+    }
+}
+
+/// `WabbajackVersion` out of raw modlist json - the one thing still readable when the rest of it can't be parsed
+pub fn sniff(json: &str) -> Option<&str> {
+    const KEY: &str = "\"WabbajackVersion\"";
+    json.rfind(KEY)
+        .map(|at| json[at + KEY.len()..].trim_start())
+        .and_then(|rest| rest.strip_prefix(':'))
+        .and_then(|rest| rest.trim_start().strip_prefix('"'))
+        .and_then(|rest| rest.split_once('"'))
+        .map(|(version, _)| version)
+}
+
+/// what is known about the version, for diagnostics of a modlist which could not be parsed
+pub fn describe(wabbajack_version: Option<&str>) -> String {
+    match wabbajack_version.map(|version| (version, version.parse::<WabbajackVersion>())) {
+        None => "the modlist does not say which Wabbajack made it".to_string(),
+        Some((_, Ok(version))) if version.is_newer_than_tested() => newer_than_tested(&version),
+        Some((version, _)) => format!("modlist was made with Wabbajack [{version}]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_versions_are_compared_by_major_and_minor() -> Result<()> {
+        let version = |version: &str| version.parse::<WabbajackVersion>();
+        assert!(version("3.7.0.0")? < version("3.10.0.0")?);
+        assert_eq!(version(" 3.7.0.0")?.to_string(), "3.7.0.0");
+        assert!(!version("3.7.0.0")?.is_newer_than_tested());
+        assert!(!version("4.0.9.1")?.is_newer_than_tested());
+        assert!(version("4.1.0.0")?.is_newer_than_tested());
+        assert!(version("5")?.is_newer_than_tested());
+        assert!(version("3.x").is_err());
+        assert!(version("").is_err());
+
+        assert!(check("3.7.0.0", true).is_ok());
+        assert!(check("not a version", true).is_ok());
+        assert!(check("4.1.0.0", false).is_ok());
+        let error = check("4.1.0.0", true).expect_err("newer than tested in strict mode");
+        assert!(error.to_string().contains("check for a hoolamike update"), "{error}");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_version_is_sniffed_out_of_broken_json() {
+        assert_eq!(
+            sniff(r#"{"Archives": [{"broken"}], "WabbajackVersion" : "4.2.0.0", "Website": ""}"#),
+            Some("4.2.0.0")
+        );
+        assert_eq!(sniff(r#"{"Archives": []}"#), None);
+        assert!(describe(Some("4.2.0.0")).contains("newer than the newest one"));
+        assert_eq!(describe(Some("3.7.0.0")), "modlist was made with Wabbajack [3.7.0.0]");
+    }
+}
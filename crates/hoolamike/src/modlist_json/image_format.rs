@@ -122,3 +122,66 @@ pub enum DXGIFormat {
     V208 = 131,                      // 0x00000083
     V408 = 132,                      // 0x00000084
 }
+
+impl DXGIFormat {
+    /// whether DirectXTex (the recompressor that's always available) can write textures in this format - it neither converts nor
+    /// compresses into typeless, depth/stencil, video or palettized formats
+    pub fn is_natively_producible(self) -> bool {
+        use DXGIFormat::*;
+        !matches!(
+            self,
+            UNKNOWN
+                | R32G32B32A32_TYPELESS
+                | R32G32B32_TYPELESS
+                | R16G16B16A16_TYPELESS
+                | R32G32_TYPELESS
+                | R32G8X24_TYPELESS
+                | D32_FLOAT_S8X24_UINT
+                | R32_FLOAT_X8X24_TYPELESS
+                | X32_TYPELESS_G8X24_UINT
+                | R10G10B10A2_TYPELESS
+                | R8G8B8A8_TYPELESS
+                | R16G16_TYPELESS
+                | R32_TYPELESS
+                | D32_FLOAT
+                | R24G8_TYPELESS
+                | D24_UNORM_S8_UINT
+                | R24_UNORM_X8_TYPELESS
+                | X24_TYPELESS_G8_UINT
+                | R8G8_TYPELESS
+                | R16_TYPELESS
+                | D16_UNORM
+                | R8_TYPELESS
+                | R1_UNORM
+                | R8G8_B8G8_UNORM
+                | G8R8_G8B8_UNORM
+                | BC1_TYPELESS
+                | BC2_TYPELESS
+                | BC3_TYPELESS
+                | BC4_TYPELESS
+                | BC5_TYPELESS
+                | B8G8R8A8_TYPELESS
+                | B8G8R8X8_TYPELESS
+                | BC6H_TYPELESS
+                | BC7_TYPELESS
+                | AYUV
+                | Y410
+                | Y416
+                | NV12
+                | P010
+                | P016
+                | OPAQUE_420
+                | YUY2
+                | Y210
+                | Y216
+                | NV11
+                | AI44
+                | IA44
+                | P8
+                | A8P8
+                | P208
+                | V208
+                | V408
+        )
+    }
+}
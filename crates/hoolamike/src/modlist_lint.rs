@@ -0,0 +1,428 @@
+//! problems hoolamike knows it will run into with a modlist, found by looking at the modlist alone - so that modlist testers can
+//! catch them in CI instead of halfway through an installation (`hoolamike lint-modlist`)
+
+use {
+    crate::{
+        install_modlist::{
+            directives::remapped_inline_file::wabbajack_consts::BSA_CREATION_DIR,
+            execution_plan::{destination, file_hash, staged_for, temp_id},
+        },
+        modlist_json::{Archive, Directive, Modlist, State, directive::TransformedTextureDirective},
+    },
+    case_insensitive_path::CaseInsensitivePathBuf,
+    itertools::Itertools,
+    std::collections::{BTreeMap, BTreeSet},
+    tap::prelude::*,
+};
+
+/// longest file name most linux filesystems (ext4, btrfs, xfs) accept, in bytes
+pub const MAX_COMPONENT_BYTES: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, derive_more::Display)]
+pub enum Severity {
+    /// the installation is going to fail (or produce something broken)
+    #[display("error")]
+    Error,
+    /// the installation needs something from the user, or might fail
+    #[display("warning")]
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, derive_more::Display)]
+pub enum LintKind {
+    #[display("unsupported-source")]
+    UnsupportedSource,
+    #[display("case-collision")]
+    CaseCollision,
+    #[display("long-path-component")]
+    LongPathComponent,
+    #[display("absolute-destination")]
+    AbsoluteDestination,
+    #[display("unproducible-texture-format")]
+    UnproducibleTextureFormat,
+    #[display("missing-bsa-source")]
+    MissingBsaSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    pub severity: Severity,
+    pub kind: LintKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.kind, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LintReport {
+    pub findings: Vec<Finding>,
+}
+
+fn unsupported_sources(archives: &[Archive]) -> impl Iterator<Item = Finding> + '_ {
+    archives.iter().filter_map(|Archive { descriptor, state }| {
+        match state {
+            State::Manual(_) => Some("it has to be downloaded manually"),
+            State::Mega(_) => Some("mega is not supported, it has to be downloaded manually"),
+            State::MediaFire(_) => Some("mediafire downloads often fail, it might have to be downloaded manually"),
+            _ => None,
+        }
+        .map(|reason| Finding {
+            severity: Severity::Warning,
+            kind: LintKind::UnsupportedSource,
+            message: format!("[{}] ({}): {reason}", descriptor.name, state.kind()),
+        })
+    })
+}
+
+/// destinations which differ only in case end up being the same file - on windows, and in hoolamike (which reuses whatever
+/// matches case-insensitively), but the modlist expects two files
+fn case_collisions(directives: &[Directive]) -> impl Iterator<Item = Finding> + '_ {
+    directives
+        .iter()
+        .into_group_map_by(|directive| destination(directive))
+        .into_iter()
+        .filter(|(_, colliding)| colliding.len() > 1)
+        .map(|(to, colliding)| {
+            let spellings = colliding
+                .iter()
+                .map(|directive| {
+                    destination(directive)
+                        .as_original_path()
+                        .as_str()
+                        .to_string()
+                })
+                .unique()
+                .collect_vec();
+            let hashes = colliding
+                .iter()
+                .map(|directive| file_hash(directive))
+                .unique()
+                .count();
+            Finding {
+                // same contents written twice are harmless
+                severity: match hashes > 1 {
+                    true => Severity::Error,
+                    false => Severity::Warning,
+                },
+                kind: LintKind::CaseCollision,
+                message: format!(
+                    "[{}] directives ({hashes} different files) write to [{to}]: {}",
+                    colliding.len(),
+                    spellings
+                        .iter()
+                        .map(|spelling| format!("[{spelling}]"))
+                        .join(", ")
+                ),
+            }
+        })
+}
+
+fn components(path: &CaseInsensitivePathBuf) -> impl Iterator<Item = &str> {
+    path.as_original_path()
+        .as_str()
+        .split(['\\', '/'])
+        .filter(|component| !component.is_empty())
+}
+
+fn long_components(directives: &[Directive]) -> impl Iterator<Item = Finding> + '_ {
+    directives.iter().map(destination).filter_map(|to| {
+        components(to)
+            .find(|component| component.len() > MAX_COMPONENT_BYTES)
+            .map(|component| Finding {
+                severity: Severity::Error,
+                kind: LintKind::LongPathComponent,
+                message: format!(
+                    "[{to}] has a [{}] byte long component (the limit is {MAX_COMPONENT_BYTES}): [{component}]",
+                    component.len()
+                ),
+            })
+    })
+}
+
+/// `C:\...`, `\\server\...` or `\...` - anything that isn't relative to the installation directory
+fn is_absolute_windows_path(path: &str) -> bool {
+    path.starts_with(['\\', '/'])
+        || path
+            .as_bytes()
+            .pipe(|bytes| bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+fn absolute_destinations(directives: &[Directive]) -> impl Iterator<Item = Finding> + '_ {
+    directives
+        .iter()
+        .map(destination)
+        .filter(|to| is_absolute_windows_path(to.as_original_path().as_str()))
+        .map(|to| Finding {
+            severity: Severity::Error,
+            kind: LintKind::AbsoluteDestination,
+            message: format!("[{}] is not relative to the installation directory", to.as_original_path()),
+        })
+}
+
+fn unproducible_textures(directives: &[Directive]) -> impl Iterator<Item = Finding> + '_ {
+    directives
+        .iter()
+        .filter_map(|directive| match directive {
+            Directive::TransformedTexture(TransformedTextureDirective { image_state, to, .. }) => Some((image_state.format, to)),
+            _ => None,
+        })
+        .filter(|(format, _)| !format.is_natively_producible())
+        .map(|(format, to)| Finding {
+            severity: Severity::Error,
+            kind: LintKind::UnproducibleTextureFormat,
+            message: format!("[{to}] has to be recompressed into [{format:?}], which DirectXTex can't produce"),
+        })
+}
+
+/// files packed into a BSA/BA2 are first staged in `TEMP_BSA_FILES/<temp id>/` by other directives
+fn missing_bsa_sources(directives: &[Directive]) -> impl Iterator<Item = Finding> + '_ {
+    let staged = directives
+        .iter()
+        .filter(|directive| staged_for(directive).is_some())
+        .map(|directive| {
+            destination(directive)
+                .as_original_path()
+                .as_str()
+                .replace('\\', "/")
+                .to_lowercase()
+        })
+        .collect::<BTreeSet<_>>();
+    let bsa_creation_dir = BSA_CREATION_DIR.with(|dir| dir.as_str().to_lowercase());
+    directives
+        .iter()
+        .filter_map(|directive| {
+            let paths = match directive {
+                Directive::CreateBSA(create_bsa) => create_bsa.member_paths(),
+                _ => return None,
+            };
+            temp_id(directive).map(|temp_id| (destination(directive), temp_id, paths))
+        })
+        .filter_map(move |(to, temp_id, paths)| {
+            paths
+                .into_iter()
+                .filter(|path| {
+                    let staged_path = format!(
+                        "{bsa_creation_dir}/{temp_id}/{}",
+                        path.as_original_path()
+                            .as_str()
+                            .replace('\\', "/")
+                            .to_lowercase()
+                    );
+                    !staged.contains(&staged_path)
+                })
+                .collect_vec()
+                .pipe(|missing| {
+                    (!missing.is_empty()).then(|| Finding {
+                        severity: Severity::Error,
+                        kind: LintKind::MissingBsaSource,
+                        message: format!(
+                            "[{to}] packs [{}] files no directive produces, e.g. [{}]",
+                            missing.len(),
+                            missing.iter().take(3).join("], [")
+                        ),
+                    })
+                })
+        })
+}
+
+impl LintReport {
+    pub fn new(Modlist { archives, directives, .. }: &Modlist) -> Self {
+        unsupported_sources(archives)
+            .chain(case_collisions(directives))
+            .chain(long_components(directives))
+            .chain(absolute_destinations(directives))
+            .chain(unproducible_textures(directives))
+            .chain(missing_bsa_sources(directives))
+            .sorted()
+            .collect_vec()
+            .pipe(|findings| Self { findings })
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+
+    /// findings grouped by severity, errors first, with a count of every kind
+    pub fn print(&self) -> String {
+        match self.findings.is_empty() {
+            true => "no problems found".to_string(),
+            false => self
+                .findings
+                .iter()
+                .chunk_by(|finding| finding.severity)
+                .into_iter()
+                .map(|(severity, findings)| {
+                    let findings = findings.collect_vec();
+                    let kinds = findings
+                        .iter()
+                        .counts_by(|finding| finding.kind)
+                        .into_iter()
+                        .collect::<BTreeMap<_, _>>()
+                        .into_iter()
+                        .map(|(kind, count)| format!("{kind}: {count}"))
+                        .join(", ");
+                    format!(
+                        "{severity}s ({}) - {kinds}\n{}",
+                        findings.len(),
+                        findings
+                            .iter()
+                            .map(|finding| format!("  {finding}"))
+                            .join("\n")
+                    )
+                })
+                .join("\n\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::Result, serde_json::json};
+
+    fn modlist(archives: serde_json::Value, directives: serde_json::Value) -> Result<Modlist> {
+        serde_json::from_value(json!({
+            "Archives": archives,
+            "Directives": directives,
+            "GameType": "SkyrimSpecialEdition",
+            "IsNSFW": false,
+            "Name": "Lint Me",
+            "Version": "1.0.0",
+            "WabbajackVersion": "3.7.0.0",
+        }))
+        .map_err(Into::into)
+    }
+
+    fn inline_file(to: &str, hash: &str) -> serde_json::Value {
+        json!({
+            "$type": "InlineFile",
+            "Hash": hash,
+            "Size": 1,
+            "SourceDataID": "3f2f8e64-8c5b-4b6a-9a3b-8f1b7e1a0c11",
+            "To": to,
+        })
+    }
+
+    fn kinds(report: &LintReport) -> Vec<(Severity, LintKind)> {
+        report
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.kind))
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_clean_modlist_has_no_findings() -> Result<()> {
+        let report = modlist(
+            json!([]),
+            json!([inline_file("Mods\\A\\a.esp", "AAAAAAAAAAA="), inline_file("Mods\\B\\a.esp", "AAAAAAAAAAA=")]),
+        )
+        .map(|modlist| LintReport::new(&modlist))?;
+        assert!(report.findings.is_empty(), "{}", report.print());
+        assert!(!report.has_errors());
+        assert_eq!(report.print(), "no problems found");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_destinations_are_linted() -> Result<()> {
+        let report = modlist(
+            json!([{
+                "Hash": "bWFudWFsAAA=",
+                "Meta": "",
+                "Name": "manual.7z",
+                "Size": 1,
+                "State": {
+                    "$type": "ManualDownloader, Wabbajack.Lib",
+                    "Prompt": "click the button",
+                    "Url": "https://example.com",
+                },
+            }]),
+            json!([
+                inline_file("Mods\\A\\Textures\\sky.dds", "AAAAAAAAAAA="),
+                inline_file("mods\\a\\textures\\SKY.dds", "BBBBBBBBBBB="),
+                inline_file(&format!("Mods\\{}\\long.esp", "x".repeat(256)), "AAAAAAAAAAA="),
+                inline_file("C:\\Games\\Skyrim\\skse64_loader.exe", "AAAAAAAAAAA="),
+            ]),
+        )
+        .map(|modlist| LintReport::new(&modlist))?;
+        assert_eq!(
+            kinds(&report),
+            [
+                (Severity::Error, LintKind::CaseCollision),
+                (Severity::Error, LintKind::LongPathComponent),
+                (Severity::Error, LintKind::AbsoluteDestination),
+                (Severity::Warning, LintKind::UnsupportedSource),
+            ]
+        );
+        assert!(report.has_errors());
+        let printed = report.print();
+        assert!(printed.starts_with("errors (3)"), "{printed}");
+        assert!(printed.contains("warnings (1) - unsupported-source: 1"), "{printed}");
+        assert!(printed.contains("[Mods\\A\\Textures\\sky.dds]"), "shows every spelling: {printed}");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_bsa_sources_and_texture_formats() -> Result<()> {
+        let report = modlist(
+            json!([]),
+            json!([
+                inline_file("TEMP_BSA_FILES\\bsa-1\\meshes\\present.nif", "AAAAAAAAAAA="),
+                {
+                    "$type": "CreateBSA",
+                    "Hash": "AAAAAAAAAAA=",
+                    "Size": 1,
+                    "To": "Mods\\A\\a.bsa",
+                    "TempID": "bsa-1",
+                    "FileStates": [
+                        {"$type": "BSAFileState, Compression.BSA", "FlipCompression": false, "Index": 0, "Path": "meshes\\present.nif"},
+                        {"$type": "BSAFileState, Compression.BSA", "FlipCompression": false, "Index": 1, "Path": "meshes\\missing.nif"},
+                    ],
+                    "State": {
+                        "$type": "BSAState, Compression.BSA",
+                        "ArchiveFlags": 3,
+                        "FileFlags": 0,
+                        "Magic": "BSA\u{0}",
+                        "Version": 105,
+                    },
+                },
+                {
+                    "$type": "TransformedTexture",
+                    "Hash": "AAAAAAAAAAA=",
+                    "Size": 1,
+                    "To": "Mods\\A\\textures\\video.dds",
+                    "ArchiveHashPath": ["c291cmNlAAA=", "video.dds"],
+                    "ImageState": {
+                        "Format": "NV12",
+                        "Height": 64,
+                        "MipLevels": 1,
+                        "PerceptualHash": "AAAA",
+                        "Width": 64,
+                    },
+                },
+            ]),
+        )
+        .map(|modlist| LintReport::new(&modlist))?;
+        assert_eq!(
+            kinds(&report),
+            [
+                (Severity::Error, LintKind::UnproducibleTextureFormat),
+                (Severity::Error, LintKind::MissingBsaSource),
+            ]
+        );
+        assert!(report.findings[1].message.contains("missing.nif"), "{:?}", report.findings);
+        assert!(!report.findings[1].message.contains("present.nif"), "{:?}", report.findings);
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! Nexus single sign-on - the user approves hoolamike on the Nexus website and the api key arrives over a websocket,
+//! no digging through the account settings
+
+use {
+    crate::{
+        config_file::{edit_config, edit_config_file, yaml_section},
+        downloaders::{helpers::FutureAnyhowExt, nexus::NexusDownloader},
+    },
+    anyhow::{Context, Result, anyhow},
+    clap::Args,
+    futures::{FutureExt, SinkExt, StreamExt, TryFutureExt},
+    serde::{Deserialize, Serialize},
+    std::{
+        future::{Future, ready},
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tokio_tungstenite::tungstenite::Message,
+    tracing::{info, warn},
+};
+
+const SSO_WEBSOCKET_URL: &str = "wss://sso.nexusmods.com";
+const SSO_PROTOCOL_VERSION: u8 = 2;
+/// application slug registered with Nexus, the approval page is titled after it
+const APPLICATION_SLUG: &str = "hoolamike";
+pub const DEFAULT_BROWSER: &str = "xdg-open";
+
+#[derive(Args, Clone)]
+pub struct NexusLoginCli {
+    /// it will be invoked as <use-browser> <url>
+    #[arg(long, default_value = DEFAULT_BROWSER)]
+    pub use_browser: String,
+}
+
+#[derive(Serialize)]
+struct SsoRequest<'a> {
+    id: &'a str,
+    /// only needed when reconnecting
+    token: Option<&'a str>,
+    protocol: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SsoData {
+    ApiKey {
+        api_key: String,
+    },
+    ConnectionToken {
+        #[allow(dead_code)]
+        connection_token: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct SsoResponse {
+    success: bool,
+    #[serde(default)]
+    data: Option<SsoData>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// [None] until the user approves the request on the website
+fn api_key_from_message(message: &str) -> Result<Option<String>> {
+    serde_json::from_str::<SsoResponse>(message)
+        .with_context(|| format!("bad sso message: [{message}]"))
+        .and_then(|SsoResponse { success, data, error }| match (success, data) {
+            (true, Some(SsoData::ApiKey { api_key })) => Ok(Some(api_key)),
+            (true, _) => Ok(None),
+            (false, _) => Err(anyhow!("nexus refused the login: {}", error.unwrap_or_else(|| "no reason given".into()))),
+        })
+}
+
+fn sso_url(id: &str) -> String {
+    format!("https://www.nexusmods.com/sso?id={id}&application={APPLICATION_SLUG}")
+}
+
+/// opens the approval page, waits for the api key and validates it. returns the key and the account name
+pub async fn login(use_browser: &str) -> Result<(String, String)> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let (mut socket, _) = tokio_tungstenite::connect_async(SSO_WEBSOCKET_URL)
+        .await
+        .context("connecting to nexus sso")?;
+    serde_json::to_string(&SsoRequest {
+        id: &id,
+        token: None,
+        protocol: SSO_PROTOCOL_VERSION,
+    })
+    .context("serializing sso request")
+    .pipe(ready)
+    .and_then(|request| {
+        socket
+            .send(Message::text(request))
+            .map_context("sending sso request")
+    })
+    .await?;
+
+    let url = sso_url(&id);
+    info!("approve hoolamike on the nexus website (open it by hand if the browser doesn't show up):\n\n    {url}\n");
+    std::process::Command::new(use_browser)
+        .arg(&url)
+        .spawn()
+        .with_context(|| format!("opening [{url}] with ({use_browser})"))
+        .tap_err(|reason| warn!("{reason:?}"))
+        .ok();
+
+    let api_key = loop {
+        match socket
+            .next()
+            .await
+            .context("nexus closed the connection before sending the api key")?
+            .context("reading sso message")?
+        {
+            Message::Text(message) => {
+                if let Some(api_key) = api_key_from_message(&message)? {
+                    break api_key;
+                }
+            }
+            Message::Close(_) => anyhow::bail!("nexus closed the connection before sending the api key"),
+            _ => {}
+        }
+    };
+    socket.close(None).await.ok();
+
+    NexusDownloader::new(api_key.clone())?
+        .validate()
+        .await
+        .context("validating the api key")
+        .tap_ok(|user| info!("logged in as [{}] (premium: {})", user.name, user.is_premium))
+        .map(|user| (api_key, user.name))
+}
+
+/// gui tasks are not guaranteed to run on tokio, so the login gets a runtime of its own
+pub fn login_in_background(use_browser: String) -> impl Future<Output = Result<(String, String)>> {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        crate::tokio_runtime_single()
+            .and_then(|runtime| runtime.block_on(login(&use_browser)))
+            .pipe(|result| tx.send(result).ok())
+    });
+    rx.map(|result| {
+        result
+            .context("login thread crashed")
+            .and_then(|result| result)
+    })
+}
+
+/// asks nexus about `api_key` on a runtime of its own, like [login_in_background], resolves to the account name
+pub fn validate_in_background(api_key: String) -> impl Future<Output = Result<String>> {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        crate::tokio_runtime_single()
+            .and_then(|runtime| {
+                runtime.block_on(
+                    NexusDownloader::new(api_key)
+                        .pipe(ready)
+                        .and_then(|nexus| async move { nexus.validate().await }),
+                )
+            })
+            .map(|user| user.name)
+            .pipe(|result| tx.send(result).ok())
+    });
+    rx.map(|result| {
+        result
+            .context("validation thread crashed")
+            .and_then(|result| result)
+    })
+}
+
+pub fn set_api_key(config: &str, api_key: &str) -> Result<String> {
+    edit_config(config, |config| {
+        yaml_section(config, "downloaders")
+            .and_then(|downloaders| yaml_section(downloaders, "nexus"))
+            .map(|nexus| {
+                nexus.insert("api_key".into(), api_key.into());
+            })
+    })
+}
+
+/// the config holds the api key now, other users on the machine have no business reading it
+#[cfg(unix)]
+pub fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).with_context(|| format!("restricting permissions of [{}]", path.display()))
+}
+
+#[cfg(not(unix))]
+pub fn restrict_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+pub fn run_login(NexusLoginCli { use_browser }: NexusLoginCli, config_path: &Path) -> Result<()> {
+    let (api_key, _name) = crate::tokio_runtime_single().and_then(|runtime| runtime.block_on(login(&use_browser)))?;
+    edit_config_file(config_path, |config| set_api_key(config, &api_key))
+        .and_then(|_| {
+            // the backup holds the previous key, if there was one
+            [
+                config_path.to_owned(),
+                config_path
+                    .as_os_str()
+                    .to_owned()
+                    .tap_mut(|backup| backup.push(".bak"))
+                    .pipe(PathBuf::from),
+            ]
+            .iter()
+            .try_for_each(|path| restrict_permissions(path))
+        })
+        .map(|_| info!("api key saved to [{}]", config_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_sso_messages() -> Result<()> {
+        assert_eq!(
+            api_key_from_message(r#"{"success":true,"data":{"connection_token":"abc"},"error":null}"#)?,
+            None
+        );
+        assert_eq!(
+            api_key_from_message(r#"{"success":true,"data":{"api_key":"secret"},"error":null}"#)?,
+            Some("secret".to_string())
+        );
+        assert!(
+            api_key_from_message(r#"{"success":false,"data":null,"error":"invalid application"}"#)
+                .unwrap_err()
+                .to_string()
+                .contains("invalid application")
+        );
+        assert!(api_key_from_message("not json").is_err());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_api_key_is_written_into_the_config() -> Result<()> {
+        let config = crate::config_file::HoolamikeConfig::default()
+            .pipe_ref(serde_yaml::to_string)
+            .context("serializing default config")?;
+        let updated = set_api_key(&config, "secret")?;
+        let parsed = serde_yaml::from_str::<crate::config_file::HoolamikeConfig>(&updated)?;
+        assert_eq!(parsed.downloaders.nexus.api_key.as_deref(), Some("secret"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_permissions_are_restricted() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let file = tempfile::NamedTempFile::new()?;
+        std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(0o644))?;
+        restrict_permissions(file.path())?;
+        assert_eq!(std::fs::metadata(file.path())?.permissions().mode() & 0o777, 0o600);
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+//! `--non-interactive` runs can't wait for someone to click through a browser or a window - everything which would, fails
+//! straight away (with the exit code of a run needing manual intervention) instead of blocking forever
+
+use {
+    crate::exit_codes::Failure,
+    anyhow::Result,
+    std::sync::atomic::{AtomicBool, Ordering},
+};
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    NON_INTERACTIVE.store(true, Ordering::Relaxed)
+}
+
+pub fn is_enabled() -> bool {
+    NON_INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// fails instead of waiting for someone to do `what` when running non-interactively
+pub fn ensure_interactive(what: &str) -> Result<()> {
+    match is_enabled() {
+        false => Ok(()),
+        true => Err(Failure::ManualIntervention.mark(anyhow::anyhow!("{what} needs someone to interact with it, but --non-interactive is set"))),
+    }
+}
@@ -2,11 +2,11 @@ use {
     crate::{
         config_file::{HoolamikeConfig, InstallationConfig},
         downloaders::{
-            DownloadTask,
             WithArchiveDescriptor,
             nexus::{DownloadFileRequest, NexusDownloader},
         },
         install_modlist::{
+            archive_meta::ArchiveMeta,
             download_cache::DownloadCache,
             downloads::{HTTP_CLIENT, stream_file},
         },
@@ -18,24 +18,27 @@ use {
     },
     anyhow::{Context, Result, anyhow},
     cli::HandleNxmCli,
-    futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt},
+    futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt, stream::FuturesUnordered},
     indicatif::ProgressBar,
     itertools::Itertools,
     notify::{Watcher, event::CreateKind},
+    queue::{Accepted, NxmQueue, key_of},
     serde::{Deserialize, Serialize},
     single_instance_server::listen_for_nxm_links,
-    std::{collections::HashMap, convert::identity, future::ready, sync::Arc},
+    std::{collections::HashMap, future::ready, sync::Arc},
     tap::prelude::*,
     tokio_stream::wrappers::UnboundedReceiverStream,
     tracing::{debug, info, warn},
     tracing_indicatif::span_ext::IndicatifSpanExt,
     typed_path::Utf8PlatformPathBuf,
-    utils::AbortOnDropExt,
 };
 
 pub mod cli;
+pub mod queue;
 pub mod register;
-pub mod utils;
+
+/// clicked links downloaded at the same time, the rest waits in the queue
+const DOWNLOAD_SLOTS: usize = 8;
 
 pub async fn handle_nxm_link(port: u16, nxm_link: HumanUrl) -> Result<()> {
     HTTP_CLIENT
@@ -114,15 +117,21 @@ pub async fn run(
         installation: InstallationConfig {
             wabbajack_file_path,
             installation_path: _,
+            copy_wabbajack_locally: _,
         },
         games: _,
         fixup: _,
         extras: _,
+        concurrency: _,
+        debug_presets: _,
+        advanced: _,
     }: HoolamikeConfig,
     HandleNxmCli {
         port,
         nxm_link,
         skip_nxm_register,
+        register: _,
+        unregister: _,
         use_browser,
     }: HandleNxmCli,
 ) -> Result<()> {
@@ -130,10 +139,11 @@ pub async fn run(
         Some(nxm_link) => handle_nxm_link(port, nxm_link).await,
         None => {
             if !skip_nxm_register {
-                self::register::register_nxm_handler().context("setting up nxm didn't work")?;
+                self::register::register_nxm_handler(port).context("setting up nxm didn't work")?;
                 info!("nxm is set up");
             }
             info!("starting to listen for nxm links");
+            crate::install_modlist::downloads::configure_proxy(downloaders.proxy.as_deref())?;
 
             let nexus_downloader = downloaders
                 .nexus
@@ -156,7 +166,7 @@ pub async fn run(
             ) = wabbajack_file_path
                 .exists_utf8()
                 .pipe(ready)
-                .and_then(|wabbajack_file_path| spawn_rayon(move || WabbajackFile::load_wabbajack_file(&wabbajack_file_path)))
+                .and_then(|wabbajack_file_path| spawn_rayon(move || WabbajackFile::load_wabbajack_file(&wabbajack_file_path, None)))
                 .await
                 .context("loading modlist file")
                 .tap_ok(|(_, wabbajack)| {
@@ -190,7 +200,7 @@ pub async fn run(
                 .context("initializing download cache")
                 .map(Arc::new)?;
 
-            let mut archive_lookup = {
+            let mut queue = {
                 let archives_pb = ProgressBar::new(archives.len() as _);
                 archives
                     .pipe(futures::stream::iter)
@@ -227,42 +237,9 @@ pub async fn run(
                             ready(Some(archive))
                         }
                     })
-                    .map(|archive| {
-                        (
-                            archive
-                                .inner
-                                .clone()
-                                .pipe(DownloadFileRequest::from_nexus_state)
-                                .nexus_website_url(),
-                            archive,
-                        )
-                    })
-                    .collect::<HashMap<_, _>>()
+                    .collect::<Vec<_>>()
                     .await
-            };
-
-            let (downloads_task, queue_download_task) = {
-                let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<DownloadTask>();
-                tokio::task::spawn(async move {
-                    UnboundedReceiverStream::new(rx)
-                        .map(
-                            |DownloadTask {
-                                 inner: (url, output_path),
-                                 descriptor,
-                             }| {
-                                stream_file(url.clone(), output_path.clone(), descriptor.size)
-                                    .inspect_err(move |reason| tracing::error!(?url, ?output_path, "could not finish download:\n\n{reason:?}"))
-                            },
-                        )
-                        .buffer_unordered(8)
-                        .try_for_each(|e| {
-                            info!("[OK] {e:?}");
-                            ready(Ok(()))
-                        })
-                        .await
-                })
-                .abort_on_drop()
-                .pipe(|task| (task, tx))
+                    .pipe(NxmQueue::new)
             };
 
             let (filesystem_changes, _guard) = {
@@ -297,15 +274,8 @@ pub async fn run(
                     single_instance_server::Message::NewNxm(human_url) => human_url,
                 })
                 .and_then(|url| NxmDownloadLink::parse_url(url).pipe(ready))
-                .and_then(move |request| {
-                    info!("new nxm request: {request:?}");
-                    nexus_downloader
-                        .clone()
-                        .download(request.clone())
-                        .map_ok(|url| (url, request.request))
-                })
-                .filter_map(|data| match data {
-                    Ok(data) => ready(Some(data)),
+                .filter_map(|link| match link {
+                    Ok(link) => ready(Some(link)),
                     Err(message) => {
                         warn!("something went wrong: {message:?}");
                         ready(None)
@@ -326,11 +296,9 @@ pub async fn run(
                 })
                 .boxed();
 
-            let initial_count = archive_lookup.len();
-
             #[derive(derive_more::From)]
             enum DownloaderEvent {
-                NxmClick((HumanUrl, DownloadFileRequest)),
+                NxmClick(NxmDownloadLink),
                 Newfile(Utf8PlatformPathBuf),
             }
 
@@ -343,80 +311,113 @@ pub async fn run(
             .pipe(futures::stream::iter)
             .flatten_unordered(100);
 
-            let mut filename_lookup = archive_lookup
-                .values()
+            let filename_lookup = queue
+                .awaiting_click()
                 .map(|archive| {
                     download_cache
-                        .download_output_path(archive.descriptor.name.as_str())
-                        .map(|name| (name, DownloadFileRequest::from_nexus_state(archive.inner.clone()).nexus_website_url()))
+                        .output_path_for(&archive.descriptor)
+                        .map(|name| (name, key_of(archive)))
                 })
                 .collect::<Result<HashMap<_, _>>>()
                 .context("building filename lookup")?;
 
-            while let Some(nexus_website_url) = archive_lookup.keys().next().cloned() {
-                info!("queued {}/{}", initial_count.saturating_sub(archive_lookup.len()), initial_count);
+            let mut downloads = FuturesUnordered::new();
+            let mut opened_in_browser = None;
+            loop {
+                while downloads.len() < DOWNLOAD_SLOTS
+                    && let Some((link, archive)) = queue.pop()
+                {
+                    let output_path = download_cache
+                        .output_path_for(&archive.descriptor)
+                        .with_context(|| format!("when queueing download task for {}", archive.inner.name))?;
+                    info!("downloading {}", archive.inner.name);
+                    downloads.push({
+                        cloned![nexus_downloader];
+                        async move {
+                            let size = archive.descriptor.size;
+                            nexus_downloader
+                                .download(link)
+                                .and_then(|url| stream_file(HTTP_CLIENT.clone(), url, output_path, size, vec![]))
+                                .await
+                                .tap_ok(|downloaded| {
+                                    ArchiveMeta::from_state(&State::Nexus(archive.inner.clone()))
+                                        .map(|meta| meta.write_next_to(std::path::Path::new(downloaded.as_path())))
+                                        .transpose()
+                                        .map(|_| ())
+                                        .unwrap_or_else(|reason| warn!(?reason, "could not write the .meta file of [{}]", archive.inner.name))
+                                })
+                                .pipe(|finished| (archive, finished))
+                        }
+                        .boxed()
+                    });
+                }
+                if queue.is_done() && downloads.is_empty() {
+                    break;
+                }
 
-                info!("opening {nexus_website_url} with {use_browser}");
-                tokio::process::Command::new(&use_browser)
-                    .arg(&nexus_website_url)
-                    .output()
-                    .await
-                    .context("spawning browser process")
-                    .and_then(|o| {
-                        o.status
-                            .success()
-                            .then_some(())
-                            .ok_or(o.status)
-                            .map_err(|s| anyhow!("bad status: {s}"))
-                    })
-                    .with_context(|| format!("opening [{nexus_website_url}] with ({use_browser}) failed: check 'hoolamike handle-nxm --help'"))?;
+                if let Some(next) = queue.next_awaiting_click()
+                    && opened_in_browser
+                        .as_ref()
+                        .is_none_or(|opened| !queue.is_awaiting_click(opened))
+                {
+                    let nexus_website_url = DownloadFileRequest::from_nexus_state(next.inner.clone()).nexus_website_url();
+                    opened_in_browser = Some(key_of(next));
+                    info!("opening {nexus_website_url} with {use_browser}");
+                    tokio::process::Command::new(&use_browser)
+                        .arg(&nexus_website_url)
+                        .output()
+                        .await
+                        .context("spawning browser process")
+                        .and_then(|o| {
+                            o.status
+                                .success()
+                                .then_some(())
+                                .ok_or(o.status)
+                                .map_err(|s| anyhow!("bad status: {s}"))
+                        })
+                        .with_context(|| format!("opening [{nexus_website_url}] with ({use_browser}) failed: check 'hoolamike handle-nxm --help'"))?;
+                }
+                info!("{}", queue.status(downloads.len()));
 
-                match downloader_events.next().await {
-                    Some(Ok(s)) => match s {
-                        DownloaderEvent::NxmClick((download_url, click)) => {
-                            let Some(archive) = archive_lookup.remove(&click.nexus_website_url()) else {
-                                warn!("not on the list: {click:?}");
-                                continue;
-                            };
-                            download_cache
-                                .download_output_path(archive.descriptor.name.as_str())
-                                .map(|name| DownloadTask {
-                                    inner: (download_url, name),
-                                    descriptor: archive.descriptor,
-                                })
-                                .and_then(|task| queue_download_task.send(task).context("sending task"))
-                                .with_context(|| format!("when queueing download task for {}", archive.inner.name))?;
+                tokio::select! {
+                    event = downloader_events.next() => match event {
+                        Some(Ok(DownloaderEvent::NxmClick(link))) => {
+                            let request = link.request.clone();
+                            match queue.push(link) {
+                                Accepted::Queued => info!("queued: {request:?}"),
+                                Accepted::Duplicate => debug!("already handled: {request:?}"),
+                                Accepted::Unknown => warn!("not on the list: {request:?} ({})", request.nexus_website_url()),
+                            }
+                        }
+                        Some(Ok(DownloaderEvent::Newfile(path_buf))) => match filename_lookup
+                            .get(&path_buf)
+                            .and_then(|key| queue.mark_downloaded(*key))
+                        {
+                            Some(removed) => info!("manual download detected: {} ({path_buf:?})", removed.descriptor.name),
+                            None => debug!("new file which does not await a click: {path_buf:?}"),
+                        },
+                        Some(Err(e)) => tracing::error!("what the hell?\n{e:?}"),
+                        None => anyhow::bail!("server stopped?"),
+                    },
+                    Some((archive, finished)) = downloads.next(), if !downloads.is_empty() => match finished {
+                        Ok(path) => info!("[OK] {path:?}"),
+                        Err(reason) => {
+                            tracing::error!("could not finish download of {}, click it again:\n\n{reason:?}", archive.descriptor.name);
+                            queue.click_again(archive);
                         }
-                        DownloaderEvent::Newfile(path_buf) => filename_lookup
-                            .remove(&path_buf)
-                            .context("unexpected path")
-                            .and_then(|nexus| {
-                                archive_lookup
-                                    .remove(&nexus)
-                                    .with_context(|| format!("no [{path_buf:?}] in nexus queue"))
-                            })
-                            .with_context(|| format!("removing from queue because of (presumed manual download) of {path_buf:?}"))
-                            .pipe(|r| match r {
-                                Ok(removed) => info!("manual download detected: {} ({path_buf:?})", removed.descriptor.name),
-                                Err(message) => {
-                                    debug!("looks like you were trying to download something manually at {path_buf:?}:\n{message:?}")
-                                }
-                            }),
                     },
-                    Some(Err(e)) => {
-                        tracing::error!("what the hell?\n{e:?}");
-                        continue;
-                    }
-                    None => {
-                        anyhow::bail!("server stopped?")
-                    }
                 }
             }
-            info!("You have queued all the files, awaiting for all downloads to finish");
-            downloads_task
-                .await
-                .context("download task has crashed")
-                .and_then(identity)?;
+            if !queue.unknown().is_empty() {
+                warn!(
+                    "these clicked links are not a part of the modlist and were not downloaded:\n{}",
+                    queue
+                        .unknown()
+                        .iter()
+                        .map(|unknown| format!("  - {}", unknown.nexus_website_url()))
+                        .join("\n")
+                );
+            }
             info!("All nexus links from modlists downloaded, you can now proceed with standard installation (nexus links will only get validated)");
 
             Ok(())
@@ -464,11 +465,12 @@ pub mod single_instance_server {
         }
     }
 
-    pub type Sender = tokio::sync::mpsc::Sender<Message>;
-    pub type Receiver = tokio::sync::mpsc::Receiver<Message>;
+    /// unbounded - links clicked in a quick succession are buffered instead of being turned away
+    pub type Sender = tokio::sync::mpsc::UnboundedSender<Message>;
+    pub type Receiver = tokio::sync::mpsc::UnboundedReceiver<Message>;
 
     pub fn create_channels() -> (Sender, Receiver) {
-        tokio::sync::mpsc::channel(9000)
+        tokio::sync::mpsc::unbounded_channel()
     }
     pub(super) fn server_address(port: u16) -> SocketAddr {
         SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), port)
@@ -500,7 +502,7 @@ pub mod single_instance_server {
     pub fn listen_for_nxm_links(port: u16) -> impl Stream<Item = ServerEvent> {
         let (tx, rx) = create_channels();
         [
-            tokio_stream::wrappers::ReceiverStream::new(rx)
+            tokio_stream::wrappers::UnboundedReceiverStream::new(rx)
                 .map(ServerEvent::Message)
                 .boxed(),
             run_server(tx, port)
@@ -515,7 +517,6 @@ pub mod single_instance_server {
     async fn handler(State(tx): State<Sender>, Json(message): Json<Message>) -> NxmApiResult<Html<&'static str>> {
         trace!("new message: {message:#?}");
         tx.send(message)
-            .await
             .context("communicating to channel failed")
             .map_err(NxmApiError)
             .map(|_| Html("<h1>Hoolamike says: roger that!</h1>"))
@@ -9,6 +9,12 @@ pub struct HandleNxmCli {
     /// use this if you want to set up the nxm handler manually
     #[arg(long)]
     pub skip_nxm_register: bool,
+    /// only makes hoolamike the handler of nxm:// links (desktop entry + xdg-mime default on linux) and exits
+    #[arg(long, conflicts_with = "unregister")]
+    pub register: bool,
+    /// removes what '--register' set up and exits
+    #[arg(long)]
+    pub unregister: bool,
     /// this is just a detail of the link handling protocol
     /// it should be included in the command that your system's dispatcher is gonna
     /// run
@@ -0,0 +1,241 @@
+//! clicking "download with manager" on a free account produces nxm links faster than they can be downloaded,
+//! so they are buffered here and handed out whenever a download slot frees up
+
+use {
+    super::NxmDownloadLink,
+    crate::{
+        downloaders::{WithArchiveDescriptor, nexus::DownloadFileRequest},
+        modlist_json::NexusState,
+    },
+    indexmap::IndexMap,
+    itertools::Itertools,
+    std::collections::{HashSet, VecDeque},
+    tap::prelude::*,
+};
+
+pub type NexusArchive = WithArchiveDescriptor<NexusState>;
+/// (mod id, file id)
+pub type NexusFileKey = (usize, usize);
+
+/// archives awaiting a click listed in the status line, the rest is only counted
+const LISTED_IN_STATUS: usize = 3;
+
+pub fn key_of(archive: &NexusArchive) -> NexusFileKey {
+    (archive.inner.mod_id, archive.inner.file_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accepted {
+    Queued,
+    /// the file was clicked (or downloaded by hand) already
+    Duplicate,
+    /// not a part of the modlist
+    Unknown,
+}
+
+#[derive(Debug, Default)]
+pub struct NxmQueue {
+    /// in modlist order, that's the order the pages are opened in
+    awaiting_click: IndexMap<NexusFileKey, NexusArchive>,
+    /// clicked, waiting for a download slot
+    queued: VecDeque<(NxmDownloadLink, NexusArchive)>,
+    /// file ids already taken care of, clicking them again does nothing
+    handled: HashSet<usize>,
+    unknown: Vec<DownloadFileRequest>,
+}
+
+impl NxmQueue {
+    pub fn new(archives: impl IntoIterator<Item = NexusArchive>) -> Self {
+        Self {
+            awaiting_click: archives
+                .into_iter()
+                .map(|archive| (key_of(&archive), archive))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, link: NxmDownloadLink) -> Accepted {
+        let DownloadFileRequest { mod_id, file_id, .. } = link.request;
+        if self.handled.contains(&file_id) {
+            return Accepted::Duplicate;
+        }
+        match self.awaiting_click.shift_remove(&(mod_id, file_id)) {
+            Some(archive) => {
+                self.handled.insert(file_id);
+                self.queued.push_back((link, archive));
+                Accepted::Queued
+            }
+            None => match self
+                .unknown
+                .iter()
+                .any(|unknown| unknown.file_id == file_id)
+            {
+                true => Accepted::Duplicate,
+                false => {
+                    self.unknown.push(link.request);
+                    Accepted::Unknown
+                }
+            },
+        }
+    }
+
+    /// next link to download, oldest click first
+    pub fn pop(&mut self) -> Option<(NxmDownloadLink, NexusArchive)> {
+        self.queued.pop_front()
+    }
+
+    /// the file showed up in the downloads directory without hoolamike downloading it
+    pub fn mark_downloaded(&mut self, key: NexusFileKey) -> Option<NexusArchive> {
+        self.awaiting_click.shift_remove(&key).inspect(|_| {
+            self.handled.insert(key.1);
+        })
+    }
+
+    /// the download failed, the link might have expired - the user has to click it again
+    pub fn click_again(&mut self, archive: NexusArchive) {
+        self.handled.remove(&archive.inner.file_id);
+        self.awaiting_click.insert(key_of(&archive), archive);
+    }
+
+    pub fn awaiting_click(&self) -> impl Iterator<Item = &NexusArchive> {
+        self.awaiting_click.values()
+    }
+
+    pub fn next_awaiting_click(&self) -> Option<&NexusArchive> {
+        self.awaiting_click().next()
+    }
+
+    pub fn is_awaiting_click(&self, key: &NexusFileKey) -> bool {
+        self.awaiting_click.contains_key(key)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.awaiting_click.is_empty() && self.queued.is_empty()
+    }
+
+    pub fn unknown(&self) -> &[DownloadFileRequest] {
+        &self.unknown
+    }
+
+    pub fn status(&self, downloading: usize) -> String {
+        let awaiting = self.awaiting_click.len();
+        format!(
+            "[{}] clicked links queued, [{downloading}] downloading, [{awaiting}] archives await a click{}",
+            self.queued.len(),
+            match awaiting {
+                0 => String::new(),
+                _ => self
+                    .awaiting_click
+                    .values()
+                    .take(LISTED_IN_STATUS)
+                    .map(|archive| archive.inner.name.as_str())
+                    .join(", ")
+                    .pipe(|listed| match awaiting.saturating_sub(LISTED_IN_STATUS) {
+                        0 => format!(": {listed}"),
+                        more => format!(": {listed} (+{more} more)"),
+                    }),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn archive(name: &str, mod_id: usize, file_id: usize) -> NexusArchive {
+        WithArchiveDescriptor {
+            inner: serde_json::from_value(json!({
+                "GameName": "SkyrimSpecialEdition",
+                "FileID": file_id,
+                "ModID": mod_id,
+                "Author": null,
+                "Description": null,
+                "ImageURL": null,
+                "IsNSFW": false,
+                "Name": name,
+                "Version": "1.0",
+            }))
+            .expect("valid nexus state"),
+            descriptor: serde_json::from_value(json!({"Hash": "AAAAAAAAAAA=", "Meta": "", "Name": format!("{name}.7z"), "Size": 1})).expect("valid descriptor"),
+        }
+    }
+
+    fn click(mod_id: usize, file_id: usize) -> NxmDownloadLink {
+        format!("nxm://skyrimspecialedition/mods/{mod_id}/files/{file_id}?key=abc&expires=1700000000&user_id=1")
+            .parse()
+            .map_err(anyhow::Error::msg)
+            .and_then(NxmDownloadLink::parse_url)
+            .expect("valid nxm link")
+    }
+
+    fn queue() -> NxmQueue {
+        NxmQueue::new([
+            archive("first", 1, 10),
+            archive("second", 2, 20),
+            archive("third", 3, 30),
+            archive("fourth", 4, 40),
+        ])
+    }
+
+    #[test_log::test]
+    fn test_rapid_clicks_are_buffered_in_order() {
+        let mut queue = queue();
+        assert_eq!(queue.push(click(3, 30)), Accepted::Queued);
+        assert_eq!(queue.push(click(1, 10)), Accepted::Queued);
+        assert_eq!(queue.push(click(1, 10)), Accepted::Duplicate);
+        assert_eq!(
+            queue.status(0),
+            "[2] clicked links queued, [0] downloading, [2] archives await a click: second, fourth"
+        );
+        assert_eq!(queue.pop().map(|(_, archive)| archive.inner.name), Some("third".to_string()));
+        assert_eq!(queue.pop().map(|(_, archive)| archive.inner.name), Some("first".to_string()));
+        assert!(queue.pop().is_none());
+        assert_eq!(queue.next_awaiting_click().map(key_of), Some((2, 20)));
+        assert!(!queue.is_done());
+    }
+
+    #[test_log::test]
+    fn test_unknown_links_are_reported_once() {
+        let mut queue = queue();
+        assert_eq!(queue.push(click(99, 990)), Accepted::Unknown);
+        assert_eq!(queue.push(click(99, 990)), Accepted::Duplicate);
+        // right file id, wrong mod - not what the modlist wants
+        assert_eq!(queue.push(click(2, 10)), Accepted::Unknown);
+        assert_eq!(
+            queue
+                .unknown()
+                .iter()
+                .map(|unknown| unknown.file_id)
+                .collect_vec(),
+            [990, 10]
+        );
+    }
+
+    #[test_log::test]
+    fn test_manual_downloads_and_retries() {
+        let mut queue = queue();
+        assert_eq!(
+            queue.status(1),
+            "[0] clicked links queued, [1] downloading, [4] archives await a click: first, second, third (+1 more)"
+        );
+        assert!(queue.mark_downloaded((2, 20)).is_some());
+        assert!(queue.mark_downloaded((2, 20)).is_none());
+        assert_eq!(queue.push(click(2, 20)), Accepted::Duplicate);
+
+        assert_eq!(queue.push(click(1, 10)), Accepted::Queued);
+        let (_, failed) = queue.pop().expect("queued");
+        queue.click_again(failed);
+        assert!(queue.is_awaiting_click(&(1, 10)));
+        assert_eq!(queue.push(click(1, 10)), Accepted::Queued);
+
+        [(3, 30), (4, 40)].into_iter().for_each(|key| {
+            queue.mark_downloaded(key);
+        });
+        assert!(!queue.is_done());
+        queue.pop();
+        assert!(queue.is_done());
+        assert_eq!(queue.status(0), "[0] clicked links queued, [0] downloading, [0] archives await a click");
+    }
+}
@@ -1,61 +1,242 @@
 use {
+    super::cli::HandleNxmCli,
     anyhow::{Context, Result},
     tap::prelude::*,
     tracing::{info, instrument},
 };
 
 mod linux {
-    use super::*;
-
-    #[instrument]
-    pub fn register_nxm_handler() -> Result<()> {
-        let current_exe = std::env::current_exe()
-            .context("cno current exe found")?
-            .display()
-            .to_string();
-        let crate_name = clap::crate_name!();
-
-        let desktop_path = directories::UserDirs::new()
-            .context("could not determine current user's directories")
-            .map(|directories| directories.home_dir().to_owned())
-            .context("figuring out home dir location")
-            .tap_ok(|home| info!(?home, "deduced home directory"))
-            .map(|home| home.join(".local").join("share").join("applications"))
-            .tap_ok(|desktop| info!(?desktop, "deduced desktop entry directory"))
-            .context("figuring out desktop directory")?;
+    use {
+        super::*,
+        crate::post_install_fixup::ini::IniDocument,
+        std::path::{Path, PathBuf},
+        tracing::warn,
+    };
 
-        let desktop_entry_path = desktop_path.join(format!("{crate_name}.desktop"));
-        info!(?desktop_entry_path, "deduced desktop entry path");
+    const MIME_TYPE: &str = "x-scheme-handler/nxm";
+    const DESKTOP_ENTRY: &str = "hoolamike-nxm.desktop";
+    /// written by older versions, it claims the same scheme
+    const LEGACY_DESKTOP_ENTRY: &str = concat!(env!("CARGO_PKG_NAME"), ".desktop");
+    /// sections of `mimeapps.list` which can point the scheme at a desktop entry
+    const MIMEAPPS_SECTIONS: [&str; 2] = ["Default Applications", "Added Associations"];
 
-        let desktop_entry = format!(
+    fn desktop_entry(current_exe: &Path, port: u16) -> String {
+        format!(
             r#"
 [Desktop Entry]
 Type=Application
 Categories=Game;
 Name={crate_name} NXM handler
-Exec="{current_exe}" %u
+Exec="{current_exe}" --nxm-link-handler-port {port} %u
 Terminal=true
 NoDisplay=true
-MimeType=x-scheme-handler/nxm;
-"#
+MimeType={MIME_TYPE};
+"#,
+            crate_name = clap::crate_name!(),
+            current_exe = current_exe.display(),
         )
         .trim()
-        .to_string();
-        info!("adding desktop entry:\n{desktop_entry}");
-        std::fs::write(&desktop_entry_path, desktop_entry).with_context(|| format!("writing desktop entry to {desktop_entry_path:?}"))?;
-        info!("wrote to {desktop_entry_path:?}");
-        info!("running `update-desktop-database`");
-        std::process::Command::new("update-desktop-database")
-            .arg(desktop_path)
+        .to_string()
+    }
+
+    fn is_hoolamike(entry: &str) -> bool {
+        [DESKTOP_ENTRY, LEGACY_DESKTOP_ENTRY].contains(&entry)
+    }
+
+    fn desktop_entries(entries: &str) -> impl Iterator<Item = &str> {
+        entries
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+    }
+
+    /// the desktop entry owning the scheme, when it's not hoolamike
+    fn foreign_owner(current_default: &str) -> Option<&str> {
+        desktop_entries(current_default)
+            .next()
+            .filter(|entry| !is_hoolamike(entry))
+    }
+
+    /// `mimeapps.list` with hoolamike dropped from the scheme associations, [None] when there was nothing to drop
+    fn without_hoolamike(mimeapps: &str) -> Option<String> {
+        let mut document = IniDocument::parse(mimeapps);
+        let updates = MIMEAPPS_SECTIONS
+            .iter()
+            .filter_map(|section| {
+                document
+                    .get(section, MIME_TYPE)
+                    .map(|entries| (*section, entries))
+            })
+            .filter(|(_, entries)| desktop_entries(entries).any(is_hoolamike))
+            .map(|(section, entries)| {
+                (
+                    section,
+                    desktop_entries(entries)
+                        .filter(|entry| !is_hoolamike(entry))
+                        .map(|entry| format!("{entry};"))
+                        .collect::<String>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        updates
+            .iter()
+            .for_each(|(section, kept)| match kept.is_empty() {
+                true => {
+                    document.remove(section, MIME_TYPE);
+                }
+                false => {
+                    document.set(section, MIME_TYPE, kept);
+                }
+            });
+        (!updates.is_empty()).then(|| document.to_string())
+    }
+
+    fn xdg_utils_available() -> bool {
+        ["xdg-mime", "update-desktop-database"]
+            .iter()
+            .all(|tool| which::which(tool).is_ok())
+            .tap(|available| {
+                if !available {
+                    warn!(
+                        "xdg-utils (xdg-mime, update-desktop-database) are not installed, nxm links can't be registered automatically - see 'hoolamike \
+                         handle-nxm --help'"
+                    )
+                }
+            })
+    }
+
+    fn run_command(command: &str, args: &[&str]) -> Result<String> {
+        std::process::Command::new(command)
+            .args(args)
             .output()
-            .context("bad status")
+            .with_context(|| format!("running `{command}`"))
             .and_then(|o| {
                 o.status
                     .success()
-                    .then_some(())
+                    .then(|| String::from_utf8_lossy(&o.stdout).trim().to_string())
                     .ok_or(o.status)
                     .map_err(|e| anyhow::anyhow!("Bad status: {e}"))
             })
+            .with_context(|| format!("`{command} {}` failed", args.join(" ")))
+    }
+
+    fn applications_directory() -> Result<PathBuf> {
+        directories::BaseDirs::new()
+            .context("could not determine current user's directories")
+            .map(|directories| directories.data_local_dir().join("applications"))
+            .tap_ok(|desktop| info!(?desktop, "deduced desktop entry directory"))
+            .context("figuring out desktop directory")
+    }
+
+    fn remove_desktop_entry(path: &Path) -> Result<()> {
+        match path.exists() {
+            true => std::fs::remove_file(path)
+                .with_context(|| format!("removing desktop entry at {path:?}"))
+                .tap_ok(|_| info!("removed {path:?}")),
+            false => Ok(()),
+        }
+    }
+
+    fn update_desktop_database(applications: &Path) -> Result<()> {
+        info!("running `update-desktop-database`");
+        run_command("update-desktop-database", &[&applications.display().to_string()]).map(|_| ())
+    }
+
+    #[instrument]
+    pub fn register_nxm_handler(port: u16) -> Result<()> {
+        if !xdg_utils_available() {
+            return Ok(());
+        }
+        let current_exe = std::env::current_exe().context("no current exe found")?;
+        let applications = applications_directory()?;
+        std::fs::create_dir_all(&applications).with_context(|| format!("creating {applications:?}"))?;
+
+        if let Some(owner) = run_command("xdg-mime", &["query", "default", MIME_TYPE])
+            .tap_err(|reason| warn!("could not check the current nxm handler: {reason:?}"))
+            .ok()
+            .as_deref()
+            .and_then(foreign_owner)
+        {
+            warn!(
+                "nxm links are currently handled by [{owner}] (Vortex running through wine, Mod Organizer 2...) - hoolamike takes them over, 'hoolamike \
+                 handle-nxm --unregister' removes hoolamike again"
+            )
+        }
+
+        remove_desktop_entry(&applications.join(LEGACY_DESKTOP_ENTRY))?;
+        let desktop_entry_path = applications.join(DESKTOP_ENTRY);
+        let contents = desktop_entry(&current_exe, port);
+        info!("adding desktop entry:\n{contents}");
+        std::fs::write(&desktop_entry_path, contents).with_context(|| format!("writing desktop entry to {desktop_entry_path:?}"))?;
+        info!("wrote to {desktop_entry_path:?}");
+        update_desktop_database(&applications)?;
+        run_command("xdg-mime", &["default", DESKTOP_ENTRY, MIME_TYPE]).map(|_| info!("hoolamike now handles {MIME_TYPE}"))
+    }
+
+    #[instrument]
+    pub fn unregister_nxm_handler() -> Result<()> {
+        if !xdg_utils_available() {
+            return Ok(());
+        }
+        let applications = applications_directory()?;
+        [DESKTOP_ENTRY, LEGACY_DESKTOP_ENTRY]
+            .iter()
+            .try_for_each(|entry| remove_desktop_entry(&applications.join(entry)))?;
+
+        directories::BaseDirs::new()
+            .context("could not determine current user's directories")
+            .map(|directories| directories.config_dir().join("mimeapps.list"))
+            .and_then(|mimeapps| match mimeapps.exists() {
+                false => Ok(()),
+                true => std::fs::read_to_string(&mimeapps)
+                    .with_context(|| format!("reading {mimeapps:?}"))
+                    .and_then(|contents| match without_hoolamike(&contents) {
+                        Some(updated) => std::fs::write(&mimeapps, updated)
+                            .with_context(|| format!("writing {mimeapps:?}"))
+                            .tap_ok(|_| info!("removed hoolamike from {mimeapps:?}")),
+                        None => Ok(()),
+                    }),
+            })
+            .context("dropping the nxm association")?;
+        update_desktop_database(&applications).map(|_| info!("hoolamike no longer handles {MIME_TYPE}"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test_log::test]
+        fn test_desktop_entry_passes_the_port() {
+            let entry = desktop_entry(Path::new("/opt/hoola mike/hoolamike"), 8123);
+            assert!(entry.starts_with("[Desktop Entry]\n"));
+            assert!(entry.contains("\nExec=\"/opt/hoola mike/hoolamike\" --nxm-link-handler-port 8123 %u\n"));
+            assert!(entry.ends_with("MimeType=x-scheme-handler/nxm;"));
+        }
+
+        #[test_log::test]
+        fn test_foreign_owners() {
+            assert_eq!(foreign_owner("wine-extension-nxm.desktop"), Some("wine-extension-nxm.desktop"));
+            assert_eq!(foreign_owner("modorganizer2-nxm-handler.desktop;"), Some("modorganizer2-nxm-handler.desktop"));
+            assert_eq!(foreign_owner("hoolamike-nxm.desktop"), None);
+            assert_eq!(foreign_owner("hoolamike.desktop"), None);
+            assert_eq!(foreign_owner(""), None);
+        }
+
+        #[test_log::test]
+        fn test_unregister_keeps_other_associations() {
+            assert_eq!(
+                without_hoolamike(
+                    "[Default Applications]\ntext/html=firefox.desktop\nx-scheme-handler/nxm=hoolamike-nxm.desktop\n\n[Added \
+                     Associations]\nx-scheme-handler/nxm=hoolamike.desktop;modorganizer2-nxm-handler.desktop;\n"
+                )
+                .as_deref(),
+                Some("[Default Applications]\ntext/html=firefox.desktop\n\n[Added Associations]\nx-scheme-handler/nxm=modorganizer2-nxm-handler.desktop;\n")
+            );
+            assert_eq!(
+                without_hoolamike("[Default Applications]\nx-scheme-handler/nxm=wine-extension-nxm.desktop\n"),
+                None
+            );
+        }
     }
 }
 
@@ -66,12 +247,12 @@ mod windows {
         winreg::{RegKey, enums::*},
     };
 
+    const NXM_CLASS: &str = "Software\\Classes\\nxm";
+
     #[instrument]
-    pub fn register_nxm_handler() -> Result<()> {
+    pub fn register_nxm_handler(port: u16) -> Result<()> {
         let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-        let (nxm_key, _) = hkcu
-            .create_subkey("Software\\Classes\\nxm")
-            .context("creating subkey")?;
+        let (nxm_key, _) = hkcu.create_subkey(NXM_CLASS).context("creating subkey")?;
 
         nxm_key
             .set_value("", &"URL:NXM Protocol")
@@ -86,13 +267,24 @@ mod windows {
             .create_subkey("command")
             .context("command subkey")?;
 
-        let exe_path = format!(r#""{}" "%1""#, std::env::current_exe().context("no current exe")?.display());
+        let exe_path = format!(
+            r#""{}" --nxm-link-handler-port {port} "%1""#,
+            std::env::current_exe().context("no current exe")?.display()
+        );
         command_key
             .set_value("", &exe_path)
             .context("setting final value")?;
         info!("windows registry updated - current exe now handles nxm links");
         Ok(())
     }
+
+    #[instrument]
+    pub fn unregister_nxm_handler() -> Result<()> {
+        RegKey::predef(HKEY_CURRENT_USER)
+            .delete_subkey_all(NXM_CLASS)
+            .context("removing nxm protocol key")
+            .map(|_| info!("windows registry updated - nxm links are no longer handled by hoolamike"))
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -100,14 +292,32 @@ mod macos {
     use super::*;
 
     #[instrument]
-    pub fn register_nxm_handler() -> Result<()> {
-        todo!("setting up nxm handler is not implemented on this platform (macos)")
+    pub fn register_nxm_handler(_port: u16) -> Result<()> {
+        anyhow::bail!("nxm handler registration is not supported on macOS")
+    }
+
+    #[instrument]
+    pub fn unregister_nxm_handler() -> Result<()> {
+        anyhow::bail!("nxm handler registration is not supported on macOS")
+    }
+}
+
+/// `--register` / `--unregister`, neither needs the config
+pub fn run(
+    HandleNxmCli {
+        port, register, unregister, ..
+    }: &HandleNxmCli,
+) -> Result<()> {
+    match (register, unregister) {
+        (true, _) => register_nxm_handler(*port).context("setting up nxm didn't work"),
+        (_, true) => unregister_nxm_handler().context("removing nxm handler didn't work"),
+        _ => Ok(()),
     }
 }
 
 #[cfg(target_os = "linux")]
-pub use linux::register_nxm_handler;
+pub use linux::{register_nxm_handler, unregister_nxm_handler};
 #[cfg(target_os = "macos")]
-pub use macos::register_nxm_handler;
+pub use macos::{register_nxm_handler, unregister_nxm_handler};
 #[cfg(target_os = "windows")]
-pub use windows::register_nxm_handler;
+pub use windows::{register_nxm_handler, unregister_nxm_handler};
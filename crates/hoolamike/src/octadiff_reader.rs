@@ -1,5 +1,5 @@
 use {
-    crate::utils::StreamLenExt,
+    crate::{compression::forward_only_seek::ForwardOnlySeek, install_modlist::download_cache::to_base_64_from_u64, utils::StreamLenExt},
     anyhow::{Context, Result},
     binrw::{
         meta::{ReadEndian, WriteEndian},
@@ -9,10 +9,9 @@ use {
     serde::ser::Error,
     std::{
         fmt,
-        io::{self, Read, Seek, SeekFrom},
-        num::NonZeroUsize,
+        hash::Hasher,
+        io::{self, Read, Seek, SeekFrom, Write},
     },
-    tap::prelude::*,
 };
 
 const BINARY_VERSION: BinaryVersion = [0x01];
@@ -28,26 +27,9 @@ pub const BINARY_END_OF_METADATA: BinaryEndOfMetadata = *b">>>";
 
 type BinaryVersion = Keyword<1>;
 
-#[macro_export]
-macro_rules! zip_results {
-    (Error = $ret:ty, $($result:expr),*) => {
-        {
-            let __extract = move || -> std::result::Result<_, _> {
-                std::result::Result::<_, $ret>::Ok(($($result?),*))
-            };
-            __extract()
-        }
-    };
-    ($($result:expr),*) => {
-        {
-            let mut __extract = || -> std::result::Result<_, _> {
-                Ok(($($result?),*))
-            };
-            __extract()
-        }
-    };
-
-}
+const MAGIC: &[u8; 9] = b"OCTODELTA";
+const COPY_COMMAND: u8 = 0x60;
+const WRITE_COMMAND: u8 = 0x80;
 
 #[binrw::binrw]
 pub struct LengthPrefixedString {
@@ -128,41 +110,6 @@ pub enum OctodiffCommand {
     Copy(CopyDataCommand),
 }
 
-pub struct ApplyDetla<S: Read + Seek, D: Read + Seek> {
-    #[allow(dead_code)]
-    pub metadata: OctodiffMetadata,
-    current_command: Option<OctodiffCommandProgress>,
-    source: S,
-    delta: D,
-}
-
-fn must_be_usize(value: i64) -> Result<usize> {
-    value.to_usize().context("expected value to be usize")
-}
-fn must_be_u64(value: i64) -> Result<u64> {
-    value.to_u64().context("expected value to be usize")
-}
-
-fn must_be_non_zero_usize(value: i64) -> Result<NonZeroUsize> {
-    must_be_usize(value)
-        .context("not even a usize")
-        .and_then(|value| NonZeroUsize::new(value).context("must be non-zero"))
-}
-
-fn read_at_most<R: Seek + Read>(mut source: R, mut buf: &mut [u8], remaining_length: usize) -> std::io::Result<Option<NonZeroUsize>> {
-    buf.split_off_mut(..remaining_length)
-        .unwrap_or(buf)
-        .pipe(|buf| source.read_exact(buf).map(|_| buf.len()))
-        .map(NonZeroUsize::new)
-        .with_context(|| format!("reading remaining length [{remaining_length}]"))
-        .map_err(std::io::Error::other)
-}
-
-// fn reached_end_of_stream<T: Seek + Read>(source: &mut T) -> std::io::Result<bool> {
-//     let (position, len) = (source.stream_position()?, source.stream_len()?);
-//     Ok(position == len)
-// }
-
 fn read_next_command<T: Read + Seek>(mut source: T) -> Result<Option<OctodiffCommand>> {
     use omnom::prelude::ReadExt;
 
@@ -171,11 +118,11 @@ fn read_next_command<T: Read + Seek>(mut source: T) -> Result<Option<OctodiffCom
         Err(_err) => return Ok(None),
     };
     match code {
-        0x60 => CopyDataCommand::read_le(&mut source)
+        COPY_COMMAND => CopyDataCommand::read_le(&mut source)
             .map(OctodiffCommand::Copy)
             .map(Some)
             .context("reading copy"),
-        0x80 => WriteDataCommand::read_le(&mut source)
+        WRITE_COMMAND => WriteDataCommand::read_le(&mut source)
             .map(OctodiffCommand::Write)
             .map(Some)
             .context("reading write"),
@@ -232,141 +179,225 @@ impl OctodiffMetadata {
     }
 }
 
-impl<S, D> ApplyDetla<S, D>
-where
-    S: Read + Seek,
-    D: Read + Seek,
-{
-    pub fn new_from_readers(source: S, mut delta: D) -> Result<Option<Self>> {
-        WithEof::<OctodiffMetadata>::read_le(&mut delta)
-            .context("reading metadata of delta file with eof")
-            .map(|WithEof { inner, eof: _ }| inner)
-            .tap_ok(|metadata| tracing::debug!(?metadata, "metadata parsed correctly"))
-            .map(|metadata| Self {
-                metadata,
-                source,
-                delta,
-                current_command: None,
-            })
-            .and_then(|mut delta| {
-                delta
-                    .read_next_command_progress()
-                    .map(|current_command| {
-                        current_command.map(|current_command| {
-                            delta.tap_mut(|delta| {
-                                delta.current_command = Some(current_command);
-                            })
-                        })
-                    })
-                    .context("preparing reader with first command")
-            })
-            .context("creating a new instance of octodiff reader")
-    }
+/// everything that can go wrong when applying a delta - kept apart from io errors of the output,
+/// so that a corrupted patch can be told apart from a full disk
+#[derive(Debug)]
+pub enum ApplyError {
+    BadMagic {
+        found: Vec<u8>,
+    },
+    InvalidMetadata(binrw::Error),
+    UnknownCommand {
+        code: u8,
+        position: u64,
+    },
+    /// delta ended in the middle of a command header
+    TruncatedCommand {
+        position: u64,
+    },
+    InvalidCommand {
+        start: i64,
+        length: i64,
+        position: u64,
+    },
+    /// source file is shorter than the copy command expects
+    TruncatedCopy {
+        start: u64,
+        length: u64,
+        copied: u64,
+    },
+    /// delta ended before all bytes of a write command were read
+    TruncatedWrite {
+        length: u64,
+        copied: u64,
+        position: u64,
+    },
+    SizeMismatch {
+        expected: u64,
+        found: u64,
+    },
+    ChecksumMismatch {
+        expected: u64,
+        found: u64,
+    },
+    Io(io::Error),
+}
 
-    fn read_next_command(&mut self) -> Result<Option<OctodiffCommand>> {
-        read_next_command(&mut self.delta).context("reading next command")
+impl std::fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::BadMagic { found } => write!(f, "not an octodiff delta, expected b'{}', got b'{}'", dbg_bytes(MAGIC), dbg_bytes(found)),
+            ApplyError::InvalidMetadata(error) => write!(f, "invalid delta metadata: {error}"),
+            ApplyError::UnknownCommand { code, position } => write!(f, "unknown command [{code:x}] at [{position}]"),
+            ApplyError::TruncatedCommand { position } => write!(f, "delta ends in the middle of a command at [{position}]"),
+            ApplyError::InvalidCommand { start, length, position } => write!(f, "invalid command at [{position}] (start={start}, length={length})"),
+            ApplyError::TruncatedCopy { start, length, copied } => write!(
+                f,
+                "source file is too short: copy of [{length}] bytes starting at [{start}] ended after [{copied}] bytes"
+            ),
+            ApplyError::TruncatedWrite { length, copied, position } => {
+                write!(
+                    f,
+                    "delta ends in the middle of a write of [{length}] bytes at [{position}] (only [{copied}] bytes available)"
+                )
+            }
+            ApplyError::SizeMismatch { expected, found } => write!(f, "patched file has size [{found}], expected [{expected}]"),
+            ApplyError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "patched file has hash [{}], expected [{}]",
+                to_base_64_from_u64(*found),
+                to_base_64_from_u64(*expected)
+            ),
+            ApplyError::Io(error) => write!(f, "io error: {error}"),
+        }
     }
+}
 
-    fn read_next_command_progress(&mut self) -> Result<Option<OctodiffCommandProgress>> {
-        self.read_next_command()
-            .and_then(|next_command| {
-                next_command
-                    .map(|next_command| match next_command {
-                        OctodiffCommand::Copy(CopyDataCommand { start, length }) => {
-                            let (start, length) =
-                                zip_results![Error = anyhow::Error, must_be_u64(start), must_be_non_zero_usize(length)].context("validating next command")?;
-                            self.source
-                                .seek(SeekFrom::Start(start))
-                                .context("seeking")
-                                .map(|_| OctodiffCommandProgress::Copy { remaining_length: length })
-                        }
-                        OctodiffCommand::Write(WriteDataCommand { length }) => {
-                            must_be_non_zero_usize(length).map(|length| OctodiffCommandProgress::Write { remaining_length: length })
-                        }
-                    })
-                    .transpose()
-            })
-            .context("reading next command progress")
+impl std::error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApplyError::InvalidMetadata(error) => Some(error),
+            ApplyError::Io(error) => Some(error),
+            _ => None,
+        }
     }
+}
 
-    fn handle_progress(&mut self, buf: &mut [u8], progress: OctodiffCommandProgress) -> Result<Option<(NonZeroUsize, Option<OctodiffCommandProgress>)>> {
-        fn read_with_progress<T: Read + Seek>(
-            mut source: T,
-            buf: &mut [u8],
-            remaining_length: NonZeroUsize,
-        ) -> Result<Option<(NonZeroUsize, Option<NonZeroUsize>)>> {
-            read_at_most(&mut source, buf, remaining_length.get())
-                .with_context(|| format!("reading at most [{remaining_length}] bytes"))
-                .and_then(|read| {
-                    read.and_then(|read| remaining_length.get().checked_sub(read.get()))
-                        .context("read too much")
-                        .map(|new_remaining_length| {
-                            new_remaining_length
-                                .pipe(NonZeroUsize::new)
-                                .pipe(|remaining_length| read.map(|read| (read, remaining_length)))
-                        })
-                })
-        }
-        match progress {
-            OctodiffCommandProgress::Copy { remaining_length } => read_with_progress(&mut self.source, buf, remaining_length)
-                .map(|progress| {
-                    progress.map(|(progress, remaining)| (progress, remaining.map(|remaining_length| OctodiffCommandProgress::Copy { remaining_length })))
-                })
-                .context("performing copy command (original file)"),
-            OctodiffCommandProgress::Write { remaining_length } => read_with_progress(&mut self.delta, buf, remaining_length)
-                .map(|progress| {
-                    progress.map(|(progress, remaining)| (progress, remaining.map(|remaining_length| OctodiffCommandProgress::Write { remaining_length })))
-                })
-                .context("performing write command (delta file)"),
-        }
+impl From<io::Error> for ApplyError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
     }
-    pub fn continue_read(&mut self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>> {
-        match self
-            .current_command
-            .take()
-            .map(Ok)
-            .or_else(|| self.read_next_command_progress().transpose())
-        {
-            Some(progress) => progress
-                .and_then(|progress| self.handle_progress(buf, progress))
-                .map(|progress| match progress {
-                    Some((progress, next_command)) => {
-                        self.current_command = next_command;
-                        Some(progress)
-                    }
-                    None => None,
-                }),
-            None => Ok(None),
+}
+
+/// size and wabbajack (xxhash64) hash of the produced file, computed while it's being written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Applied {
+    pub size: u64,
+    pub hash: u64,
+}
+
+impl Applied {
+    pub fn verify(self, expected_size: u64, expected_hash: u64) -> std::result::Result<Self, ApplyError> {
+        match (self.size == expected_size, self.hash == expected_hash) {
+            (false, _) => Err(ApplyError::SizeMismatch {
+                expected: expected_size,
+                found: self.size,
+            }),
+            (true, false) => Err(ApplyError::ChecksumMismatch {
+                expected: expected_hash,
+                found: self.hash,
+            }),
+            (true, true) => Ok(self),
         }
     }
 }
 
-enum OctodiffCommandProgress {
-    Copy { remaining_length: NonZeroUsize },
-    Write { remaining_length: NonZeroUsize },
+struct HashingWriter<W> {
+    inner: W,
+    size: u64,
+    hash_state: xxhash_rust::xxh64::Xxh64,
 }
 
-#[allow(dead_code)]
-#[extension_traits::extension(pub trait NonZeroUsizeExt)]
-impl NonZeroUsize
-where
-    Self: Sized,
-{
-    fn checked_sub(&self, other: usize) -> Option<NonZeroUsize> {
-        self.get().checked_sub(other).and_then(Self::new)
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hash_state.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// reads into `buf` until it's full or the reader ends, returns how many bytes were read
+fn read_fully<R: Read>(mut reader: R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
     }
+    Ok(read)
 }
 
-impl<S, D> Read for ApplyDetla<S, D>
+/// applies the delta to `source`, streaming the result into `out` - the delta is only ever read forward,
+/// so it can come straight out of an archive
+pub fn apply<D, S, W>(delta: D, mut source: S, out: W) -> std::result::Result<Applied, ApplyError>
 where
+    D: Read,
     S: Read + Seek,
-    D: Read + Seek,
+    W: Write,
 {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.continue_read(buf)
-            .map_err(std::io::Error::other)
-            .map(|read| read.map(NonZeroUsize::get).unwrap_or(0))
+    let mut delta = ForwardOnlySeek::new(delta);
+    let mut out = HashingWriter {
+        inner: out,
+        size: 0,
+        hash_state: xxhash_rust::xxh64::Xxh64::new(0),
+    };
+    let mut magic = [0; MAGIC.len()];
+    let read = read_fully(&mut delta, &mut magic)?;
+    if &magic[..read] != MAGIC {
+        return Err(ApplyError::BadMagic { found: magic[..read].to_vec() });
+    }
+    let metadata = WithEof::<OctodiffMetadata>::read_le(&mut ForwardOnlySeek::new(io::Cursor::new(magic).chain(&mut delta)))
+        .map(|WithEof { inner, eof: _ }| inner)
+        .map_err(ApplyError::InvalidMetadata)?;
+    tracing::trace!(?metadata, "applying delta");
+
+    loop {
+        let position = delta.stream_position()?;
+        let mut code = [0; 1];
+        if read_fully(&mut delta, &mut code)? == 0 {
+            break;
+        }
+        // copy is (start, length), write is just (length)
+        let mut header = [0; 16];
+        let header = match code[0] {
+            COPY_COMMAND => &mut header[..],
+            WRITE_COMMAND => &mut header[..8],
+            code => return Err(ApplyError::UnknownCommand { code, position }),
+        };
+        if read_fully(&mut delta, header)? != header.len() {
+            return Err(ApplyError::TruncatedCommand { position });
+        }
+        let mut fields = header
+            .chunks_exact(8)
+            .map(|field| i64::from_le_bytes(field.try_into().expect("chunks are 8 bytes long")));
+        match code[0] {
+            COPY_COMMAND => {
+                let (start, length) = (fields.next().unwrap_or_default(), fields.next().unwrap_or_default());
+                let (start, length) = start
+                    .to_u64()
+                    .zip(length.to_u64())
+                    .ok_or(ApplyError::InvalidCommand { start, length, position })?;
+                source.seek(SeekFrom::Start(start))?;
+                let copied = io::copy(&mut (&mut source).take(length), &mut out)?;
+                if copied != length {
+                    return Err(ApplyError::TruncatedCopy { start, length, copied });
+                }
+            }
+            _ => {
+                let length = fields.next().unwrap_or_default();
+                let length = length
+                    .to_u64()
+                    .ok_or(ApplyError::InvalidCommand { start: 0, length, position })?;
+                let copied = io::copy(&mut (&mut delta).take(length), &mut out)?;
+                if copied != length {
+                    return Err(ApplyError::TruncatedWrite { length, copied, position });
+                }
+            }
+        }
     }
+    out.flush()?;
+    Ok(Applied {
+        size: out.size,
+        hash: out.hash_state.finish(),
+    })
 }
 
 #[cfg(test)]
@@ -1,5 +1,5 @@
 use {
-    crate::octadiff_reader::CommandSummary,
+    crate::octadiff_reader::{Applied, ApplyError, CommandSummary},
     anyhow::{Context, Result},
     binrw::io::NoSeek,
     std::io::{Cursor, Read, Seek},
@@ -56,12 +56,9 @@ fn decode_string(input: &str) -> Vec<u8> {
 fn test_works(bytes: &[u8]) -> Result<()> {
     let mut dummy_original = ZeroReader::new(1024 * 1024 * 5);
     let delta = || NoSeek::new(Cursor::new(bytes));
-    let size = {
-        let mut delta = delta();
-        let mut reader = super::ApplyDetla::new_from_readers(&mut dummy_original, &mut delta)?.context("empty delta")?;
-        tracing::info!(metadata=?reader.metadata);
-        std::io::copy(&mut reader, &mut std::io::sink()).context("copying data")?
-    };
+    let size = super::apply(delta(), &mut dummy_original, std::io::sink())
+        .context("applying delta")?
+        .size;
 
     let expected_size = {
         super::OctodiffMetadata::explain(&mut delta())
@@ -173,3 +170,118 @@ fn test_reads_delta_file_with_small_changes_larger_file_disjoint_changes() {
         log_delta_file(input)
     )
 }
+
+/// header of a delta made against a basis file with a zeroed sha1
+fn crafted_delta(commands: &[u8]) -> Vec<u8> {
+    std::iter::empty()
+        .chain(b"OCTODELTA".iter().copied())
+        .chain([0x01, 4])
+        .chain(b"SHA1".iter().copied())
+        .chain(20_i32.to_le_bytes())
+        .chain([0; 20])
+        .chain(b">>>".iter().copied())
+        .chain(commands.iter().copied())
+        .collect()
+}
+
+fn copy_command(start: i64, length: i64) -> Vec<u8> {
+    std::iter::once(0x60)
+        .chain(start.to_le_bytes())
+        .chain(length.to_le_bytes())
+        .collect()
+}
+
+fn write_command(bytes: &[u8]) -> Vec<u8> {
+    std::iter::once(0x80)
+        .chain((bytes.len() as i64).to_le_bytes())
+        .chain(bytes.iter().copied())
+        .collect()
+}
+
+fn apply_crafted(commands: &[Vec<u8>], source: &[u8]) -> Result<(Applied, Vec<u8>), ApplyError> {
+    let mut out = vec![];
+    super::apply(NoSeek::new(Cursor::new(crafted_delta(&commands.concat()))), Cursor::new(source), &mut out).map(|applied| (applied, out))
+}
+
+#[test_log::test]
+fn test_apply_crafted_delta() -> Result<()> {
+    let (applied, out) = apply_crafted(&[copy_command(6, 5), write_command(b", hello "), copy_command(0, 5)], b"hello world")?;
+    assert_eq!(out, b"world, hello hello");
+    assert_eq!(
+        applied,
+        Applied {
+            size: 18,
+            hash: xxhash_rust::xxh64::xxh64(b"world, hello hello", 0),
+        }
+    );
+    applied.verify(18, xxhash_rust::xxh64::xxh64(b"world, hello hello", 0))?;
+    Ok(())
+}
+
+#[test_log::test]
+fn test_apply_empty_delta_produces_empty_file() -> Result<()> {
+    let (applied, out) = apply_crafted(&[], b"hello world")?;
+    assert!(out.is_empty());
+    assert_eq!(applied.size, 0);
+    Ok(())
+}
+
+#[test_log::test]
+fn test_apply_bad_magic() {
+    let mut input = crafted_delta(&copy_command(0, 1));
+    input[0] = b'X';
+    assert!(matches!(
+        super::apply(NoSeek::new(Cursor::new(input)), Cursor::new(b"a"), std::io::sink()),
+        Err(ApplyError::BadMagic { found }) if found == b"XCTODELTA"
+    ));
+    assert!(matches!(
+        super::apply(NoSeek::new(Cursor::new(b"OCTO")), Cursor::new(b"a"), std::io::sink()),
+        Err(ApplyError::BadMagic { .. })
+    ));
+}
+
+#[test_log::test]
+fn test_apply_truncated_commands() {
+    let header_len = crafted_delta(&[]).len() as u64;
+    let mut truncated = copy_command(0, 5);
+    truncated.truncate(10);
+    assert!(matches!(
+        apply_crafted(&[write_command(b"ok"), truncated], b"hello"),
+        Err(ApplyError::TruncatedCommand { position }) if position == header_len + 11
+    ));
+
+    let mut truncated = write_command(b"hello");
+    truncated.truncate(12);
+    assert!(matches!(
+        apply_crafted(&[truncated], b""),
+        Err(ApplyError::TruncatedWrite { length: 5, copied: 3, .. })
+    ));
+
+    assert!(matches!(
+        apply_crafted(&[copy_command(3, 5)], b"hello"),
+        Err(ApplyError::TruncatedCopy {
+            start: 3,
+            length: 5,
+            copied: 2
+        })
+    ));
+    assert!(matches!(
+        apply_crafted(&[copy_command(-1, 5)], b"hello"),
+        Err(ApplyError::InvalidCommand { start: -1, .. })
+    ));
+    assert!(matches!(
+        apply_crafted(&[vec![0x42]], b"hello"),
+        Err(ApplyError::UnknownCommand { code: 0x42, .. })
+    ));
+}
+
+#[test_log::test]
+fn test_apply_checksum_mismatch() -> Result<()> {
+    let (applied, _) = apply_crafted(&[copy_command(0, 5)], b"hello")?;
+    assert!(matches!(applied.verify(5, 0xdead), Err(ApplyError::ChecksumMismatch { expected: 0xdead, .. })));
+    assert!(matches!(
+        applied.verify(4, applied.hash),
+        Err(ApplyError::SizeMismatch { expected: 4, found: 5 })
+    ));
+    Ok(())
+}
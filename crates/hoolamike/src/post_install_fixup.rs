@@ -1,10 +1,15 @@
 use {
-    crate::config_file::HoolamikeConfig,
+    crate::{
+        config_file::{FixupConfig, HoolamikeConfig},
+        modlist_json::GameName,
+        wabbajack_file::WabbajackFile,
+    },
     anyhow::{Context, Result},
+    case_insensitive_path::PathExistsUtf8Ext,
     common::set_resolution,
     std::path::{Path, PathBuf},
     tap::prelude::*,
-    tracing::{info, instrument},
+    tracing::{info, instrument, warn},
 };
 
 #[instrument]
@@ -31,6 +36,9 @@ macro_rules! target_os_only {
 // }
 
 pub mod diffing;
+pub mod ini;
+pub mod ini_templates;
+pub mod mod_organizer;
 
 #[extension_traits::extension(pub trait LinesPreservePlatform)]
 impl str {
@@ -67,7 +75,7 @@ pub mod common {
             .with_context(|| format!("patching file at [{path:?}]"))
     }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct Resolution {
         pub x: u16,
         pub y: u16,
@@ -117,7 +125,6 @@ pub mod common {
         use {
             super::*,
             itertools::Itertools,
-            std::borrow::Cow,
             tracing::{debug, info_span},
         };
 
@@ -139,108 +146,84 @@ pub mod common {
                         .unwrap_or_default()
                 })
             };
-            Ok(())
-                .and_then(|_| {
-                    info_span!("SSEDisplayTweaks.ini").in_scope(|| {
-                        all_files_with_name("SSEDisplayTweaks.ini").try_for_each(|file| {
-                            patch_file(&file, |contents| {
-                                contents
-                                    .lines_preserve_platform()
-                                    .pipe(|(sep, lines)| {
-                                        lines
-                                            .map(|line| {
-                                                if RESOLUTION.is_match(line) {
-                                                    format!("Resolution={resolution}")
-                                                } else if FULLSCREEN.is_match(line) {
-                                                    "Fullscreen=false".to_string()
-                                                } else if COMMENTED_FULLSCREEN.is_match(line) {
-                                                    "#Fullscreen=false".to_string()
-                                                } else if BORDERLESS.is_match(line) {
-                                                    "Borderless=true".to_string()
-                                                } else if COMMENTED_BORDERLESS.is_match(line) {
-                                                    "#Borderless=true".to_string()
-                                                } else {
-                                                    line.to_string()
-                                                }
-                                            })
-                                            .join(sep)
-                                    })
-                                    .pipe(Ok)
-                            })
-                            .tap_ok(|_| debug!("patched resolution to [{resolution}] at [{file:#?}]"))
-                        })
-                    })
-                })
-                .and_then(|_| {
-                    info_span!("skyrimprefs.ini").in_scope(|| {
-                        all_files_with_name("skyrimprefs.ini").try_for_each(|file| {
-                            patch_file(&file, |contents| {
-                                contents
-                                    .lines_preserve_platform()
-                                    .pipe(|(sep, lines)| {
-                                        lines
-                                            .map(|line| {
-                                                if line.starts_with("iSize W") {
-                                                    format!("iSize W = {}", resolution.x).pipe(Cow::Owned)
-                                                } else if line.starts_with("iSize H") {
-                                                    format!("iSize H = {}", resolution.y).pipe(Cow::Owned)
-                                                } else {
-                                                    line.pipe(Cow::Borrowed)
-                                                }
-                                            })
-                                            .join(sep)
-                                    })
-                                    .pipe(Ok)
-                            })
-                            .tap_ok(|_| debug!("patched resolution to [{resolution}] at [{file:#?}]"))
-                        })
-                    })
-                })
-                .and_then(|_| {
-                    info_span!("Fallout4Prefs.ini").in_scope(|| {
-                        all_files_with_name("Fallout4Prefs.ini").try_for_each(|file| {
-                            patch_file(&file, |contents| {
-                                contents
-                                    .lines_preserve_platform()
-                                    .pipe(|(sep, lines)| {
-                                        lines
-                                            .map(|line| {
-                                                if line.starts_with("iSize W") {
-                                                    format!("iSize W = {}", resolution.x).pipe(Cow::Owned)
-                                                } else if line.starts_with("iSize H") {
-                                                    format!("iSize H = {}", resolution.y).pipe(Cow::Owned)
-                                                } else {
-                                                    line.pipe(Cow::Borrowed)
-                                                }
-                                            })
-                                            .join(sep)
-                                    })
-                                    .pipe(Ok)
-                            })
-                            .tap_ok(|_| debug!("patched resolution to [{resolution}] at [{file:#?}]"))
+            Ok(()).and_then(|_| {
+                info_span!("SSEDisplayTweaks.ini").in_scope(|| {
+                    all_files_with_name("SSEDisplayTweaks.ini").try_for_each(|file| {
+                        patch_file(&file, |contents| {
+                            contents
+                                .lines_preserve_platform()
+                                .pipe(|(sep, lines)| {
+                                    lines
+                                        .map(|line| {
+                                            if RESOLUTION.is_match(line) {
+                                                format!("Resolution={resolution}")
+                                            } else if FULLSCREEN.is_match(line) {
+                                                "Fullscreen=false".to_string()
+                                            } else if COMMENTED_FULLSCREEN.is_match(line) {
+                                                "#Fullscreen=false".to_string()
+                                            } else if BORDERLESS.is_match(line) {
+                                                "Borderless=true".to_string()
+                                            } else if COMMENTED_BORDERLESS.is_match(line) {
+                                                "#Borderless=true".to_string()
+                                            } else {
+                                                line.to_string()
+                                            }
+                                        })
+                                        .join(sep)
+                                })
+                                .pipe(Ok)
                         })
+                        .tap_ok(|_| debug!("patched resolution to [{resolution}] at [{file:#?}]"))
                     })
                 })
+            })
         }
     }
 }
 
+pub(crate) fn modlist_game_type(config: &HoolamikeConfig) -> Result<GameName> {
+    config
+        .installation
+        .wabbajack_file_path
+        .exists_utf8()
+        .and_then(|wabbajack_file_path| WabbajackFile::load_modlist_json(&wabbajack_file_path, None))
+        .map(|wabbajack_file| wabbajack_file.modlist.game_type)
+        .context("reading game type from the modlist")
+}
+
 #[instrument]
 fn post_install_fixup_common(config: &HoolamikeConfig) -> Result<()> {
     info!("common");
+    let Some(fixup) = config.fixup.as_ref() else {
+        return Ok(());
+    };
+    let game_type = modlist_game_type(config)
+        .tap_err(|reason| warn!(?reason, "game specific fixes will be skipped"))
+        .ok();
+    apply_fixups(config, fixup, game_type.as_ref())
+}
+
+fn apply_fixups(config: &HoolamikeConfig, fixup: &FixupConfig, game_type: Option<&GameName>) -> Result<()> {
     Ok(())
         //
-        .and_then(|_| {
-            config
-                .fixup
-                .as_ref()
-                .map(|crate::config_file::FixupConfig { game_resolution }| {
-                    set_resolution::update_resolution(&config.installation.installation_path, *game_resolution)
-                })
-                .unwrap_or(Ok(()))
+        .and_then(|_| ini_templates::apply_ini_templates(&config.installation.installation_path, game_type, fixup))
+        .and_then(|_| set_resolution::update_resolution(&config.installation.installation_path, fixup.game_resolution))
+        .and_then(|_| match fixup.fix_mod_organizer_paths {
+            true => mod_organizer::fixup_mod_organizer(config, game_type),
+            false => Ok(()),
         })
 }
 
+/// re-applies everything tied to the machine the installation lives on, after it was moved over from another one.
+/// mod organizer paths always need rewriting, the rest only when fixups are configured
+#[instrument(skip(config))]
+pub(crate) fn adapt_moved_installation(config: &HoolamikeConfig, game_type: Option<&GameName>) -> Result<()> {
+    match config.fixup.as_ref() {
+        Some(fixup) => apply_fixups(config, fixup, game_type),
+        None => mod_organizer::fixup_mod_organizer(config, game_type),
+    }
+}
+
 #[instrument]
 pub(crate) fn run_post_install_fixup(config: &HoolamikeConfig) -> Result<()> {
     info!("running post install fixup");
@@ -0,0 +1,184 @@
+//! minimal line-based ini editor - keeps comments, ordering, unknown keys and the original line endings intact
+
+use {super::LinesPreservePlatform, itertools::Itertools, std::fmt, tap::prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IniDocument {
+    separator: &'static str,
+    trailing_newline: bool,
+    lines: Vec<String>,
+}
+
+fn section_name(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .map(str::trim)
+}
+
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    match line.trim_start() {
+        comment if comment.starts_with(';') || comment.starts_with('#') => None,
+        line => line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim())),
+    }
+}
+
+impl IniDocument {
+    pub fn parse(contents: &str) -> Self {
+        let (separator, lines) = contents.lines_preserve_platform();
+        Self {
+            separator: if separator == "\r\n" { "\r\n" } else { "\n" },
+            trailing_newline: contents.ends_with('\n'),
+            lines: lines.map(ToOwned::to_owned).collect(),
+        }
+    }
+
+    /// (start, end) line range of the section body, case insensitive
+    fn section_range(&self, section: &str) -> Option<(usize, usize)> {
+        self.lines
+            .iter()
+            .position(|line| section_name(line).is_some_and(|name| name.eq_ignore_ascii_case(section)))
+            .map(|header| {
+                (
+                    header + 1,
+                    self.lines
+                        .iter()
+                        .enumerate()
+                        .skip(header + 1)
+                        .find(|(_, line)| section_name(line).is_some())
+                        .map(|(idx, _)| idx)
+                        .unwrap_or(self.lines.len()),
+                )
+            })
+    }
+
+    fn find_key(&self, section: &str, key: &str) -> Option<usize> {
+        self.section_range(section)
+            .and_then(|(start, end)| (start..end).find(|idx| key_value(&self.lines[*idx]).is_some_and(|(existing, _)| existing.eq_ignore_ascii_case(key))))
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.find_key(section, key)
+            .and_then(|idx| key_value(&self.lines[idx]))
+            .map(|(_, value)| value)
+    }
+
+    /// all key-value pairs of a section, in file order
+    pub fn entries(&self, section: &str) -> Vec<(String, String)> {
+        self.section_range(section)
+            .map(|(start, end)| {
+                self.lines[start..end]
+                    .iter()
+                    .filter_map(|line| key_value(line))
+                    .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// sets the value, creating the section and/or key when missing. returns the previous value
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> Option<String> {
+        match self.find_key(section, key) {
+            Some(idx) => {
+                let (prefix, raw_previous) = self.lines[idx]
+                    .split_once('=')
+                    .expect("found by key lookup");
+                // preserve the `key = value` vs `key=value` style of the file
+                let spacing = if raw_previous.starts_with(' ') { " " } else { "" };
+                let previous = raw_previous.trim().to_owned();
+                self.lines[idx] = format!("{prefix}={spacing}{value}");
+                Some(previous)
+            }
+            None => {
+                let entry = format!("{key}={value}");
+                match self.section_range(section) {
+                    Some((start, end)) => {
+                        // keep trailing empty lines between sections
+                        let insert_at = (start..end)
+                            .rev()
+                            .find(|idx| !self.lines[*idx].trim().is_empty())
+                            .map(|idx| idx + 1)
+                            .unwrap_or(start);
+                        self.lines.insert(insert_at, entry);
+                    }
+                    None => {
+                        if self
+                            .lines
+                            .last()
+                            .is_some_and(|line| !line.trim().is_empty())
+                        {
+                            self.lines.push(String::new());
+                        }
+                        self.lines.push(format!("[{section}]"));
+                        self.lines.push(entry);
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// removes the key, returns its value
+    pub fn remove(&mut self, section: &str, key: &str) -> Option<String> {
+        self.find_key(section, key).map(|idx| {
+            self.lines
+                .remove(idx)
+                .pipe_deref(key_value)
+                .map(|(_, value)| value.to_owned())
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Default for IniDocument {
+    fn default() -> Self {
+        Self {
+            separator: "\r\n",
+            trailing_newline: true,
+            lines: vec![],
+        }
+    }
+}
+
+impl fmt::Display for IniDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lines.iter().join(self.separator))?;
+        if self.trailing_newline && !self.lines.is_empty() {
+            write!(f, "{}", self.separator)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::Result};
+
+    #[test_log::test]
+    fn test_set_preserves_unknown_keys_and_style() -> Result<()> {
+        let mut ini = IniDocument::parse("[Display]\r\niSize W = 800\r\n; comment\r\nfoo=bar\r\n\r\n[General]\r\nsLanguage=ENGLISH\r\n");
+        assert_eq!(ini.set("display", "isize w", "1280"), Some("800".to_string()));
+        assert_eq!(ini.set("Display", "iSize H", "800"), None);
+        assert_eq!(ini.set("Archive", "bInvalidateOlderFiles", "1"), None);
+        assert_eq!(
+            ini.to_string(),
+            "[Display]\r\niSize W = 1280\r\n; comment\r\nfoo=bar\r\niSize \
+             H=800\r\n\r\n[General]\r\nsLanguage=ENGLISH\r\n\r\n[Archive]\r\nbInvalidateOlderFiles=1\r\n"
+        );
+        assert_eq!(ini.get("general", "slanguage"), Some("ENGLISH"));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_remove() {
+        let mut ini = IniDocument::parse("[Default Applications]\ntext/html=firefox.desktop\nx-scheme-handler/nxm=hoolamike-nxm.desktop\n");
+        assert_eq!(
+            ini.remove("default applications", "X-Scheme-Handler/nxm"),
+            Some("hoolamike-nxm.desktop".to_string())
+        );
+        assert_eq!(ini.remove("Default Applications", "x-scheme-handler/nxm"), None);
+        assert_eq!(ini.to_string(), "[Default Applications]\ntext/html=firefox.desktop\n");
+    }
+}
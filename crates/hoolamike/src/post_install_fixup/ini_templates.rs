@@ -0,0 +1,318 @@
+//! per-game ini settings lists expect to be present after installation (resolution, window mode, archive invalidation),
+//! plus user-provided overrides from the fixup config
+
+use {
+    super::{common::patch_file, ini::IniDocument, mod_organizer},
+    crate::{config_file::FixupConfig, modlist_json::GameName},
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    std::path::{Path, PathBuf},
+    tap::prelude::*,
+    tracing::{info, info_span, instrument, warn},
+};
+
+/// (file name, section, key, value)
+pub type IniEntry = (String, String, String, String);
+
+fn flag(value: bool) -> String {
+    match value {
+        true => "1",
+        false => "0",
+    }
+    .to_string()
+}
+
+fn entry(file: &str, section: &str, key: &str, value: &str) -> IniEntry {
+    (file.to_string(), section.to_string(), key.to_string(), value.to_string())
+}
+
+fn resolution_entries(prefs: &str, fixup: &FixupConfig) -> Vec<IniEntry> {
+    vec![
+        entry(prefs, "Display", "iSize W", &fixup.game_resolution.x.to_string()),
+        entry(prefs, "Display", "iSize H", &fixup.game_resolution.y.to_string()),
+    ]
+}
+
+/// fallout 3 and new vegas have no borderless window mode, so `bBorderless` is only set for the games which do
+fn display_entries(prefs: &str, fixup: &FixupConfig, borderless: bool) -> Vec<IniEntry> {
+    resolution_entries(prefs, fixup)
+        .into_iter()
+        .chain([entry(prefs, "Display", "bFull Screen", &flag(fixup.fullscreen))])
+        .chain(borderless.then(|| entry(prefs, "Display", "bBorderless", &flag(fixup.borderless))))
+        .collect()
+}
+
+/// prefs the resolution is patched in when the game of the modlist is not known - only where they exist already
+const FALLBACK_PREFS: &[&str] = &["SkyrimPrefs.ini", "Fallout4Prefs.ini"];
+
+/// ini files (and their settings) a given game expects, [None] for games without a template
+pub fn template_entries(game: &GameName, fixup: &FixupConfig) -> Option<Vec<IniEntry>> {
+    match game.to_string().as_str() {
+        "Skyrim" | "SkyrimSpecialEdition" | "SkyrimVR" | "Enderal" | "EnderalSpecialEdition" => display_entries("SkyrimPrefs.ini", fixup, true)
+            .into_iter()
+            .chain([
+                entry("Skyrim.ini", "Archive", "bInvalidateOlderFiles", "1"),
+                entry("Skyrim.ini", "General", "bAllowMultipleMasterLoads", "1"),
+            ])
+            .collect_vec()
+            .pipe(Some),
+        "Fallout4" | "Fallout4VR" => display_entries("Fallout4Prefs.ini", fixup, true)
+            .into_iter()
+            .chain([
+                entry("Fallout4Custom.ini", "Archive", "bInvalidateOlderFiles", "1"),
+                entry("Fallout4Custom.ini", "Archive", "sResourceDataDirsFinal", ""),
+            ])
+            .collect_vec()
+            .pipe(Some),
+        "FalloutNewVegas" | "Fallout3" => display_entries("FalloutPrefs.ini", fixup, false)
+            .into_iter()
+            .chain([
+                entry("FalloutCustom.ini", "Archive", "bInvalidateOlderFiles", "1"),
+                entry("FalloutCustom.ini", "General", "bAllowMultipleMasterLoads", "1"),
+            ])
+            .collect_vec()
+            .pipe(Some),
+        _ => None,
+    }
+}
+
+/// `ini_overrides` are keyed by file name, entries are `Section.key: value`
+pub fn override_entries(fixup: &FixupConfig) -> Result<Vec<IniEntry>> {
+    fixup
+        .ini_overrides
+        .iter()
+        .flat_map(|(file, overrides)| overrides.iter().map(move |(key, value)| (file, key, value)))
+        .map(|(file, section_key, value)| {
+            section_key
+                .split_once('.')
+                .with_context(|| format!("bad override [{section_key}] for [{file}], expected 'Section.key'"))
+                .map(|(section, key)| (file.clone(), section.to_string(), key.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!(
+        "{}.bak",
+        path.file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default()
+    ))
+}
+
+/// edits the ini in place (writing a `.bak` the first time), or creates it when missing
+pub fn merge_into_ini_file(path: &Path, entries: &[(String, String, String)]) -> Result<()> {
+    let apply = |ini: &mut IniDocument| {
+        entries
+            .iter()
+            .for_each(|(section, key, value)| match ini.set(section, key, value) {
+                Some(previous) if &previous == value => {}
+                previous => info!(
+                    "[{}] [{section}] {key}: [{}] -> [{value}]",
+                    path.display(),
+                    previous.unwrap_or_else(|| "<missing>".to_string())
+                ),
+            })
+    };
+    match path.exists() {
+        true => backup_path(path)
+            .pipe(|backup| match backup.exists() {
+                true => Ok(()),
+                false => std::fs::copy(path, &backup)
+                    .with_context(|| format!("backing up [{}] to [{}]", path.display(), backup.display()))
+                    .map(|_| info!("backed up [{}] to [{}]", path.display(), backup.display())),
+            })
+            .and_then(|_| {
+                patch_file(path, |contents| {
+                    IniDocument::parse(contents)
+                        .tap_mut(apply)
+                        .to_string()
+                        .pipe(Ok)
+                })
+            }),
+        false => IniDocument::default()
+            .tap_mut(apply)
+            .to_string()
+            .pipe(|contents| std::fs::write(path, contents))
+            .with_context(|| format!("creating [{}]", path.display()))
+            .tap_ok(|_| info!("created [{}]", path.display())),
+    }
+}
+
+fn find_case_insensitive(directory: &Path, file_name: &str) -> Option<PathBuf> {
+    std::fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(file_name)
+        })
+        .map(|entry| entry.path())
+}
+
+/// without mod organizer profiles we only touch the files that already exist somewhere in the installation
+fn existing_files_named(root: &Path, file_name: &str) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(file_name)
+        })
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// the resolution in [FALLBACK_PREFS] files found anywhere in the installation, none of them is created
+fn patch_existing_prefs(installation_path: &Path, fixup: &FixupConfig) -> Result<()> {
+    FALLBACK_PREFS.iter().try_for_each(|prefs| {
+        let entries = resolution_entries(prefs, fixup)
+            .into_iter()
+            .map(|(_, section, key, value)| (section, key, value))
+            .collect_vec();
+        existing_files_named(installation_path, prefs)
+            .into_iter()
+            .try_for_each(|path| merge_into_ini_file(&path, &entries))
+    })
+}
+
+#[instrument(skip(fixup))]
+pub fn apply_ini_templates(installation_path: &Path, game: Option<&GameName>, fixup: &FixupConfig) -> Result<()> {
+    let profiles = mod_organizer::find_mod_organizer_ini(installation_path)
+        .and_then(|ini| ini.parent().map(mod_organizer::profile_directories))
+        .unwrap_or_default();
+    let templates = match game.map(|game| (game, template_entries(game, fixup))) {
+        Some((_, Some(templates))) => templates,
+        unknown => {
+            match unknown {
+                Some((game, _)) => warn!("no ini template for [{game}], only the resolution and ini_overrides will be applied"),
+                None => warn!("game of the modlist is not known, only the resolution and ini_overrides will be applied"),
+            }
+            patch_existing_prefs(installation_path, fixup)?;
+            vec![]
+        }
+    };
+    override_entries(fixup)
+        .map(|overrides| templates.into_iter().chain(overrides).collect_vec())
+        .context("collecting ini entries")?
+        .into_iter()
+        .into_group_map_by(|(file, ..)| file.to_lowercase())
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .try_for_each(|(_, entries)| {
+            let file_name = entries[0].0.clone();
+            let entries = entries
+                .into_iter()
+                .map(|(_, section, key, value)| (section, key, value))
+                .collect_vec();
+            info_span!("ini", file=%file_name).in_scope(|| match profiles.is_empty() {
+                false => profiles.iter().try_for_each(|profile| {
+                    find_case_insensitive(profile, &file_name)
+                        .unwrap_or_else(|| profile.join(&file_name))
+                        .pipe(|path| merge_into_ini_file(&path, &entries))
+                }),
+                true => existing_files_named(installation_path, &file_name)
+                    .into_iter()
+                    .try_for_each(|path| merge_into_ini_file(&path, &entries)),
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::post_install_fixup::common::Resolution};
+
+    #[test_log::test]
+    fn test_merge_preserves_existing_and_backs_up_once() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = directory.path().join("SkyrimPrefs.ini");
+        std::fs::write(&path, "[Display]\r\niSize W=800\r\nfUnknownSetting=2.5\r\n")?;
+        let entries = FixupConfig {
+            game_resolution: Resolution { x: 1920, y: 1080 },
+            ..Default::default()
+        }
+        .pipe(|fixup| template_entries(&GameName::new("SkyrimSpecialEdition".into()), &fixup))
+        .into_iter()
+        .flatten()
+        .filter(|(file, ..)| file == "SkyrimPrefs.ini")
+        .map(|(_, section, key, value)| (section, key, value))
+        .collect_vec();
+
+        merge_into_ini_file(&path, &entries)?;
+        std::fs::write(&path, std::fs::read_to_string(&path)?.replace("1920", "2560"))?;
+        merge_into_ini_file(&path, &entries)?;
+
+        let ini = IniDocument::parse(&std::fs::read_to_string(&path)?);
+        assert_eq!(ini.get("Display", "iSize W"), Some("1920"));
+        assert_eq!(ini.get("Display", "iSize H"), Some("1080"));
+        assert_eq!(ini.get("Display", "fUnknownSetting"), Some("2.5"));
+        assert_eq!(
+            std::fs::read_to_string(backup_path(&path))?,
+            "[Display]\r\niSize W=800\r\nfUnknownSetting=2.5\r\n"
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_fallout_prefs_get_no_borderless() {
+        let keys = |game: &str| {
+            template_entries(&GameName::new(game.into()), &FixupConfig::default())
+                .into_iter()
+                .flatten()
+                .filter(|(_, section, ..)| section == "Display")
+                .map(|(_, _, key, _)| key)
+                .collect_vec()
+        };
+        assert_eq!(keys("FalloutNewVegas"), ["iSize W", "iSize H", "bFull Screen"]);
+        assert_eq!(keys("Fallout3"), ["iSize W", "iSize H", "bFull Screen"]);
+        assert_eq!(keys("Fallout4"), ["iSize W", "iSize H", "bFull Screen", "bBorderless"]);
+        assert!(template_entries(&GameName::new("Oblivion".into()), &FixupConfig::default()).is_none());
+    }
+
+    #[test_log::test]
+    fn test_unknown_game_still_gets_the_resolution_in_existing_prefs() -> Result<()> {
+        let installation = tempfile::tempdir()?;
+        let prefs = installation.path().join("profiles/Default/skyrimprefs.ini");
+        std::fs::create_dir_all(prefs.parent().expect("has a parent"))?;
+        std::fs::write(&prefs, "[Display]\r\niSize W=800\r\niSize H=600\r\n")?;
+        let fixup = FixupConfig {
+            game_resolution: Resolution { x: 2560, y: 1440 },
+            ..Default::default()
+        };
+
+        apply_ini_templates(installation.path(), None, &fixup)?;
+        let ini = IniDocument::parse(&std::fs::read_to_string(&prefs)?);
+        assert_eq!(ini.get("Display", "iSize W"), Some("2560"));
+        assert_eq!(ini.get("Display", "iSize H"), Some("1440"));
+        assert_eq!(ini.get("Display", "bBorderless"), None);
+        assert!(
+            !installation
+                .path()
+                .join("profiles/Default/Fallout4Prefs.ini")
+                .exists()
+        );
+
+        apply_ini_templates(installation.path(), Some(&GameName::new("Oblivion".into())), &fixup)?;
+        assert_eq!(IniDocument::parse(&std::fs::read_to_string(&prefs)?).get("Display", "iSize W"), Some("2560"));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_overrides_require_section() {
+        let fixup = FixupConfig::default().tap_mut(|fixup| {
+            fixup
+                .ini_overrides
+                .entry("Skyrim.ini".into())
+                .or_default()
+                .insert("bNoSection".into(), "1".into());
+        });
+        assert!(override_entries(&fixup).is_err());
+    }
+}
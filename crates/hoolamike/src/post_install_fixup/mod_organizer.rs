@@ -0,0 +1,251 @@
+//! Wabbajack rewrites ModOrganizer.ini on install so that it points to the directories picked by the user.
+//! Without that MO2 running under wine keeps looking at `C:\Users\author\...` from the modlist author's machine.
+
+use {
+    super::{common::patch_file, ini::IniDocument},
+    crate::{config_file::HoolamikeConfig, modlist_json::GameName},
+    anyhow::{Context, Result},
+    std::path::{Path, PathBuf},
+    tap::prelude::*,
+    tracing::{info, info_span, instrument, warn},
+};
+
+pub const MOD_ORGANIZER_INI: &str = "ModOrganizer.ini";
+pub const PORTABLE_MARKER: &str = "portable.txt";
+const PROFILES_DIRECTORY: &str = "profiles";
+const PROFILE_SETTINGS_INI: &str = "settings.ini";
+
+/// `/home/user/games/skyrim` -> `Z:/home/user/games/skyrim`
+pub fn wine_path(path: &Path) -> Result<String> {
+    std::path::absolute(path)
+        .with_context(|| format!("making [{}] absolute", path.display()))
+        .and_then(|path| {
+            path.to_str()
+                .map(|path| format!("Z:{}", path.replace('\\', "/")))
+                .with_context(|| format!("[{}] is not valid utf8", path.display()))
+        })
+}
+
+fn normalize_separators(path: &str) -> String {
+    path.replace(r"\\", "/").replace('\\', "/")
+}
+
+/// moves `value` from `old_base` onto `new_base` when it lives under it (windows paths are case insensitive)
+fn rebase(value: &str, old_base: &str, new_base: &str) -> Option<String> {
+    let value = normalize_separators(value);
+    let old_base = normalize_separators(old_base)
+        .trim_end_matches('/')
+        .to_owned();
+    value
+        .get(..old_base.len())
+        .filter(|prefix| !old_base.is_empty() && prefix.eq_ignore_ascii_case(&old_base))
+        .and_then(|_| value.get(old_base.len()..))
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .map(|rest| format!("{new_base}{rest}"))
+}
+
+/// MO2 stores gamePath as a QByteArray with escaped backslashes
+fn game_path_value(game: &str) -> String {
+    format!("@ByteArray({})", game.replace('/', r"\\"))
+}
+
+#[derive(Debug, Clone)]
+pub struct WinePaths {
+    pub base: String,
+    pub downloads: String,
+    pub game: Option<String>,
+}
+
+fn set_logged(ini: &mut IniDocument, file: &Path, section: &str, key: &str, value: &str) {
+    match ini.set(section, key, value) {
+        Some(previous) if previous == value => {}
+        previous => info!(
+            "[{}] [{section}] {key}: [{}] -> [{value}]",
+            file.display(),
+            previous.unwrap_or_else(|| "<missing>".to_string())
+        ),
+    }
+}
+
+pub fn fixup_mod_organizer_ini(ini: &mut IniDocument, file: &Path, WinePaths { base, downloads, game }: &WinePaths) {
+    let old_base = ini
+        .get("Settings", "base_directory")
+        .map(normalize_separators);
+    if let Some(game) = game {
+        set_logged(ini, file, "General", "gamePath", &game_path_value(game));
+    }
+    set_logged(ini, file, "Settings", "base_directory", base);
+    set_logged(ini, file, "Settings", "download_directory", downloads);
+    if let Some(old_base) = old_base {
+        ini.entries("Settings")
+            .into_iter()
+            .filter(|(key, _)| key.ends_with("_directory"))
+            .filter(|(key, _)| !["base_directory", "download_directory"].contains(&key.as_str()))
+            .filter_map(|(key, value)| rebase(&value, &old_base, base).map(|value| (key, value)))
+            .for_each(|(key, value)| set_logged(ini, file, "Settings", &key, &value));
+    }
+}
+
+pub fn fixup_profile_settings(ini: &mut IniDocument, file: &Path, old_base: &str, new_base: &str) {
+    ini.entries("General")
+        .into_iter()
+        .filter_map(|(key, value)| rebase(&value, old_base, new_base).map(|value| (key, value)))
+        .for_each(|(key, value)| set_logged(ini, file, "General", &key, &value));
+}
+
+pub fn find_mod_organizer_ini(installation_path: &Path) -> Option<PathBuf> {
+    walkdir::WalkDir::new(installation_path)
+        .max_depth(2)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(MOD_ORGANIZER_INI)
+        })
+        .min_by_key(|entry| entry.depth())
+        .map(|entry| entry.into_path())
+}
+
+pub fn profile_directories(mod_organizer_root: &Path) -> Vec<PathBuf> {
+    mod_organizer_root
+        .join(PROFILES_DIRECTORY)
+        .pipe(std::fs::read_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|profile| profile.is_dir())
+        .collect()
+}
+
+fn modlist_game_directory(config: &HoolamikeConfig, game_type: Option<&GameName>) -> Result<PathBuf> {
+    game_type
+        .context("game type of the modlist is unknown")
+        .and_then(|game_type| {
+            config
+                .games
+                .get(game_type)
+                .with_context(|| format!("[{game_type}] is not configured in games section"))
+                .map(|game| game.root_directory.clone())
+        })
+        .context("figuring out game directory for the modlist")
+}
+
+#[instrument(skip(config))]
+pub fn fixup_mod_organizer(config: &HoolamikeConfig, game_type: Option<&GameName>) -> Result<()> {
+    let Some(mod_organizer_ini) = find_mod_organizer_ini(&config.installation.installation_path) else {
+        warn!(
+            "no [{MOD_ORGANIZER_INI}] found in [{}], skipping mod organizer fixup",
+            config.installation.installation_path.display()
+        );
+        return Ok(());
+    };
+    let mod_organizer_root = mod_organizer_ini
+        .parent()
+        .context("ini file must have a parent")?
+        .to_owned();
+    let paths = WinePaths {
+        base: wine_path(&mod_organizer_root)?,
+        downloads: wine_path(&config.downloaders.downloads_directory)?,
+        game: modlist_game_directory(config, game_type)
+            .tap_err(|reason| warn!(?reason, "gamePath will not be updated"))
+            .ok()
+            .map(|game| wine_path(&game))
+            .transpose()?,
+    };
+    let old_base = std::fs::read_to_string(&mod_organizer_ini)
+        .with_context(|| format!("reading [{}]", mod_organizer_ini.display()))
+        .map(|contents| {
+            IniDocument::parse(&contents)
+                .get("Settings", "base_directory")
+                .map(normalize_separators)
+        })?;
+
+    info_span!("ModOrganizer.ini")
+        .in_scope(|| {
+            patch_file(&mod_organizer_ini, |contents| {
+                IniDocument::parse(contents)
+                    .tap_mut(|ini| fixup_mod_organizer_ini(ini, &mod_organizer_ini, &paths))
+                    .to_string()
+                    .pipe(Ok)
+            })
+        })
+        .and_then(|_| match old_base {
+            Some(old_base) => info_span!("profiles").in_scope(|| {
+                profile_directories(&mod_organizer_root)
+                    .into_iter()
+                    .map(|profile| profile.join(PROFILE_SETTINGS_INI))
+                    .filter(|settings| settings.is_file())
+                    .try_for_each(|settings| {
+                        patch_file(&settings, |contents| {
+                            IniDocument::parse(contents)
+                                .tap_mut(|ini| fixup_profile_settings(ini, &settings, &old_base, &paths.base))
+                                .to_string()
+                                .pipe(Ok)
+                        })
+                    })
+            }),
+            None => Ok(()),
+        })
+        .and_then(|_| {
+            mod_organizer_root
+                .join(PORTABLE_MARKER)
+                .pipe(|marker| match marker.exists() {
+                    true => Ok(()),
+                    false => std::fs::write(&marker, "")
+                        .with_context(|| format!("creating [{}]", marker.display()))
+                        .tap_ok(|_| info!("created portable instance marker at [{}]", marker.display())),
+                })
+        })
+        .context("fixing up mod organizer paths")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_rebase() {
+        assert_eq!(
+            rebase(
+                r"C:\\Users\\author\\Lists\\Tuxborn\\mods",
+                "C:/Users/author/Lists/Tuxborn",
+                "Z:/home/user/tuxborn"
+            ),
+            Some("Z:/home/user/tuxborn/mods".to_string())
+        );
+        assert_eq!(
+            rebase("c:/users/author/lists/tuxborn", "C:/Users/author/Lists/Tuxborn/", "Z:/x"),
+            Some("Z:/x".to_string())
+        );
+        assert_eq!(rebase("C:/Users/author/Lists/TuxbornOther", "C:/Users/author/Lists/Tuxborn", "Z:/x"), None);
+        assert_eq!(rebase("D:/elsewhere", "C:/Users/author/Lists/Tuxborn", "Z:/x"), None);
+    }
+
+    #[test_log::test]
+    fn test_fixup_mod_organizer_ini() {
+        let mut ini = IniDocument::parse(
+            "[General]\r\ngameName=Skyrim Special \
+             Edition\r\ngamePath=@ByteArray(C:\\\\Games\\\\Skyrim)\r\n\r\n[Settings]\r\nbase_directory=C:/Lists/Tuxborn\r\ndownload_directory=C:/Lists/\
+             downloads\r\nmod_directory=C:/Lists/Tuxborn/mods\r\nstyle=dark\r\n",
+        );
+        fixup_mod_organizer_ini(
+            &mut ini,
+            Path::new(MOD_ORGANIZER_INI),
+            &WinePaths {
+                base: "Z:/home/user/tuxborn".into(),
+                downloads: "Z:/home/user/downloads".into(),
+                game: Some("Z:/home/user/skyrim".into()),
+            },
+        );
+        assert_eq!(ini.get("General", "gamePath"), Some(r"@ByteArray(Z:\\home\\user\\skyrim)"));
+        assert_eq!(ini.get("Settings", "base_directory"), Some("Z:/home/user/tuxborn"));
+        assert_eq!(ini.get("Settings", "download_directory"), Some("Z:/home/user/downloads"));
+        assert_eq!(ini.get("Settings", "mod_directory"), Some("Z:/home/user/tuxborn/mods"));
+        assert_eq!(ini.get("Settings", "style"), Some("dark"));
+    }
+}
@@ -1,6 +1,13 @@
+pub mod bridge;
 pub mod hooks;
+pub mod json_events;
 pub use hooks::{read::ReadHookExt, write::WriteHookExt};
-use {hooks::IoHook, indicatif::ProgressStyle, tracing_indicatif::span_ext::IndicatifSpanExt};
+use {
+    hooks::IoHook,
+    hoola_progress::{ProgressKind, ProgressMap, SpanPath},
+    indicatif::{HumanBytes, HumanDuration, ProgressStyle},
+    tracing_indicatif::span_ext::IndicatifSpanExt,
+};
 
 pub(crate) fn io_progress_style() -> ProgressStyle {
     #[allow(clippy::literal_string_with_formatting_args)]
@@ -21,24 +28,72 @@ pub(crate) fn count_progress_style() -> ProgressStyle {
     .progress_chars("█▇▆▅▄▃▂▁  ")
 }
 
+/// like [count_progress_style], but with items per second - for long batches of similar work
+pub(crate) fn rate_progress_style() -> ProgressStyle {
+    #[allow(clippy::literal_string_with_formatting_args)]
+    ProgressStyle::with_template(
+        "{span_child_prefix:.bold}▕{bar:.green}▏({pos}/{len} {per_sec:.cyan} ETA {eta:.grey} ELAPSED {elapsed:.yellow}) \
+         {span_name:.green}({span_fields:.yellow})",
+    )
+    .unwrap()
+    .progress_chars("█▇▆▅▄▃▂▁  ")
+}
+
+/// shown next to a span by the gui and the tui. parents with byte counters below them show those, the rate of a parent is the one of the byte counters as well
+pub(crate) fn amount(progress: &ProgressMap, path: &SpanPath) -> String {
+    let Some((kind, state)) = progress.displayed(path) else {
+        return String::new();
+    };
+    let done = match kind {
+        ProgressKind::Bytes => format!("{}/{}", HumanBytes(state.current.max(0) as u64), HumanBytes(state.total.max(0) as u64)),
+        ProgressKind::Iter | ProgressKind::Parent => format!("{}/{}", state.current, state.total),
+    };
+    progress
+        .throughput(path)
+        .filter(|throughput| throughput.rate > 0.)
+        .map(|throughput| {
+            let rate = match kind {
+                ProgressKind::Bytes | ProgressKind::Parent => format!("{}/s", HumanBytes(throughput.rate as u64)),
+                ProgressKind::Iter => format!("{:.1}/s", throughput.rate),
+            };
+            throughput
+                .eta()
+                .map(|eta| format!("{done} {rate} ETA {}", HumanDuration(eta)))
+                .unwrap_or_else(|| format!("{done} {rate}"))
+        })
+        .unwrap_or(done)
+}
+
 #[extension_traits::extension(pub trait IndicatifWrapIoExt)]
 impl tracing::Span {
     fn wrap_read<R: std::io::Read>(self, expected_size: u64, read: R) -> IoHook<R, impl Fn(usize)> {
         self.pb_set_style(&io_progress_style());
         self.pb_set_length(expected_size);
-        read.hook_read(move |size| self.pb_inc(size as _))
+        let bridged = bridge::bytes(&self, expected_size);
+        read.hook_read(move |size| {
+            self.pb_inc(size as _);
+            bridge::inc(&bridged, size);
+        })
     }
     fn wrap_write<W: std::io::Write>(self, expected_size: u64, write: W) -> IoHook<W, impl Fn(usize)> {
         self.pb_set_style(&io_progress_style());
         self.pb_set_length(expected_size);
-        write.hook_write(move |size| self.pb_inc(size as _))
+        let bridged = bridge::bytes(&self, expected_size);
+        write.hook_write(move |size| {
+            self.pb_inc(size as _);
+            bridge::inc(&bridged, size);
+        })
     }
     fn wrap_async_write<W: tokio::io::AsyncWrite + Unpin>(self, expected_size: u64, write: W) -> IoHook<W, impl Fn(usize)> {
         self.pb_set_style(&io_progress_style());
         self.pb_set_length(expected_size);
+        let bridged = bridge::bytes(&self, expected_size);
         IoHook {
             inner: write,
-            callback: move |size| self.pb_inc(size as _),
+            callback: move |size| {
+                self.pb_inc(size as _);
+                bridge::inc(&bridged, size);
+            },
         }
     }
 }
@@ -0,0 +1,171 @@
+//! the cli renders install progress from tracing spans (through `tracing_indicatif`). while an install runs inside the gui,
+//! [ProgressBridge] mirrors those spans into [hoola_progress] messages - every span becomes a node of the progress tree,
+//! its fields name the file being worked on and the io hooks report bytes
+
+use {
+    hoola_progress::{
+        Progress,
+        ProgressCommunicator,
+        ProgressKind,
+        ProgressSpan,
+        Update,
+        progress_span::{ProgressDelta, ProgressState},
+    },
+    itertools::Itertools,
+    parking_lot::Mutex,
+    std::fmt::Debug,
+    tap::prelude::*,
+    tracing::{
+        Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id},
+    },
+    tracing_subscriber::{Registry, layer::Context, registry::LookupSpan},
+};
+
+static ATTACHED: Mutex<Option<ProgressCommunicator>> = Mutex::new(None);
+
+/// long field values (directive dumps...) are cut, the view only needs to tell spans apart
+const MAX_FIELD_LENGTH: usize = 96;
+
+/// spans created from now on are reported to `root`, until [detach] is called
+pub fn attach(root: ProgressCommunicator) {
+    *ATTACHED.lock() = Some(root);
+}
+
+pub fn detach() {
+    ATTACHED.lock().take();
+}
+
+struct BridgedSpan(ProgressCommunicator);
+
+#[derive(Default)]
+struct Fields(Vec<String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        format!("{value:?}")
+            .chars()
+            .take(MAX_FIELD_LENGTH)
+            .collect::<String>()
+            .pipe(|value| self.0.push(format!("{}={value}", field.name())))
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"))
+    }
+}
+
+fn label(attributes: &Attributes<'_>) -> String {
+    Fields::default()
+        .tap_mut(|fields| attributes.record(fields))
+        .pipe(|Fields(fields)| match fields.is_empty() {
+            true => attributes.metadata().name().to_string(),
+            false => format!("{}({})", attributes.metadata().name(), fields.iter().join(", ")),
+        })
+}
+
+pub struct ProgressBridge;
+
+impl<S> tracing_subscriber::Layer<S> for ProgressBridge
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let bridged = span
+            .parent()
+            .and_then(|parent| {
+                parent
+                    .extensions()
+                    .get::<BridgedSpan>()
+                    .map(|BridgedSpan(parent)| parent.child(label(attributes)))
+            })
+            .or_else(|| {
+                ATTACHED
+                    .lock()
+                    .as_ref()
+                    .map(|root| root.child(label(attributes)))
+            });
+        // dropped together with the span, which reports it as finished
+        if let Some(bridged) = bridged {
+            span.extensions_mut().insert(BridgedSpan(bridged));
+        }
+    }
+}
+
+/// byte counter under `span`, [None] when the span is not bridged
+pub fn bytes(span: &tracing::Span, expected_size: u64) -> Option<ProgressCommunicator> {
+    span.with_subscriber(|(id, dispatch)| {
+        dispatch
+            .downcast_ref::<Registry>()
+            .and_then(|registry| registry.span(id))
+            .and_then(|span| {
+                span.extensions()
+                    .get::<BridgedSpan>()
+                    .map(|BridgedSpan(bridged)| {
+                        bridged.span_raw(ProgressSpan {
+                            name: "bytes".into(),
+                            state: ProgressState {
+                                total: expected_size as _,
+                                current: 0,
+                            },
+                            kind: ProgressKind::Bytes,
+                            samples: Default::default(),
+                        })
+                    })
+            })
+    })
+    .flatten()
+}
+
+pub fn inc(bytes: &Option<ProgressCommunicator>, by: usize) {
+    if let Some(bytes) = bytes {
+        Progress::send(bytes, Update::Update(ProgressDelta { total: 0, current: by as _ }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, futures::StreamExt, hoola_progress::ProgressMap, tracing::info_span, tracing_subscriber::layer::SubscriberExt};
+
+    #[test_log::test]
+    fn test_spans_are_mirrored() {
+        let (mut progress, mut messages, root) = ProgressMap::new();
+        let subscriber = Registry::default().with(ProgressBridge);
+        tracing::subscriber::with_default(subscriber, || {
+            attach(root);
+            let _before = info_span!("sync_downloads", archives = 2).entered();
+            let file = info_span!("downloading", name = "a.7z");
+            let counter = file.in_scope(|| bytes(&file, 100));
+            inc(&counter, 40);
+            // updates are coalesced by the sender
+            counter.iter().for_each(ProgressCommunicator::flush);
+            info_span!("finished_right_away").in_scope(|| {});
+            detach();
+            messages.close();
+            futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+                .into_iter()
+                .for_each(|message| progress.handle(message));
+
+            let names = progress
+                .progress
+                .iter()
+                // the root only collects the top level spans
+                .filter(|(path, _)| !path.is_empty())
+                .map(|(path, span)| (path.len(), span.name.to_string(), span.state.current, span.state.total))
+                .collect_vec();
+            assert_eq!(
+                names,
+                [
+                    (1, "sync_downloads(archives=2)".to_string(), 1, 2),
+                    (2, "downloading(name=a.7z)".to_string(), 0, 1),
+                    (3, "bytes".to_string(), 40, 100),
+                ]
+            );
+            drop(counter);
+        });
+    }
+}
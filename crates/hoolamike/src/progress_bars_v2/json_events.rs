@@ -0,0 +1,434 @@
+//! `--progress-format json` - instead of progress bars, stdout gets one json object per line for frontends wrapping
+//! hoolamike (logs stay on stderr). the events come from the [hoola_progress] messages [super::bridge] produces,
+//! every line is a [Line] and [SCHEMA_VERSION] is bumped whenever a field changes meaning or goes away
+
+use {
+    super::bridge,
+    anyhow::{Context, Result},
+    futures::StreamExt,
+    hoola_progress::{
+        ProgressKind,
+        ProgressMap,
+        ProgressMessage,
+        ProgressSpan,
+        SpanPath,
+        Update,
+        progress_span::ProgressDelta,
+        throughput::{Samples, Throughput, combined},
+    },
+    serde::Serialize,
+    std::{
+        collections::BTreeMap,
+        io::Write,
+        sync::atomic::{AtomicBool, Ordering},
+        thread::JoinHandle,
+        time::{Duration, Instant},
+    },
+    tap::prelude::*,
+    tracing::warn,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// progress of a single span is reported at most this often (~4 times a second)
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// spans still open when the command is done are not waited for longer than this
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Line {
+    pub version: u32,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// span was opened, `parent` is missing for the top level ones. ids are not reused within a run
+    Start {
+        id: u64,
+        parent: Option<u64>,
+        name: String,
+        kind: SpanKind,
+        total: i64,
+    },
+    /// current state of the span (not the difference since the last one). `rate` (per second, rounded) and `eta_seconds`
+    /// (rounded up) follow the last few seconds, for parents they combine the byte counters below them
+    Progress {
+        id: u64,
+        current: i64,
+        total: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rate: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_seconds: Option<u64>,
+    },
+    /// span is done, its last state was reported right before
+    Finish { id: u64 },
+    /// span was given up on (an error), nothing more is reported for it or for any span below it
+    Abandon { id: u64 },
+    /// the command moved on to its next stage, `finished` is the last line of a successful run
+    Phase { name: String },
+    /// numbers of an installation (bytes per source, time per phase...), right before `finished` or `error`. see
+    /// [crate::install_modlist::run_stats::RunSummary]
+    Summary { stats: serde_json::Value },
+    /// the command failed, nothing follows
+    Error { message: String },
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+    /// `current` and `total` are bytes
+    Bytes,
+    /// `current` and `total` are items of work
+    Items,
+    /// `current` and `total` count the finished and opened child spans
+    Parent,
+}
+
+impl From<&ProgressKind> for SpanKind {
+    fn from(kind: &ProgressKind) -> Self {
+        match kind {
+            ProgressKind::Bytes => Self::Bytes,
+            ProgressKind::Iter => Self::Items,
+            ProgressKind::Parent => Self::Parent,
+        }
+    }
+}
+
+pub fn write_event(output: &mut impl Write, event: Event) -> Result<()> {
+    Line {
+        version: SCHEMA_VERSION,
+        event,
+    }
+    .pipe_ref(serde_json::to_string)
+    .context("serializing progress event")
+    .and_then(|line| {
+        writeln!(output, "{line}")
+            .and_then(|_| output.flush())
+            .context("writing progress event")
+    })
+}
+
+fn emit(event: Event) {
+    if ENABLED.load(Ordering::Relaxed) {
+        if let Err(reason) = write_event(&mut std::io::stdout().lock(), event) {
+            warn!("{reason:?}")
+        }
+    }
+}
+
+/// marks the start of the next stage of the command, does nothing unless json progress is on
+pub fn phase(name: &str) {
+    emit(Event::Phase { name: name.to_string() })
+}
+
+pub fn summary(stats: serde_json::Value) {
+    emit(Event::Summary { stats })
+}
+
+pub fn error(error: &anyhow::Error) {
+    emit(Event::Error { message: format!("{error:?}") })
+}
+
+struct TrackedSpan {
+    id: u64,
+    span: ProgressSpan,
+    last_sent: Instant,
+    pending: bool,
+}
+
+/// turns progress messages into events, throttling the updates
+pub struct Emitter<W> {
+    output: W,
+    spans: BTreeMap<SpanPath, TrackedSpan>,
+    next_id: u64,
+}
+
+impl<W: Write> Emitter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            spans: Default::default(),
+            next_id: 0,
+        }
+    }
+
+    /// same as [hoola_progress::ProgressMap::throughput]
+    fn throughput(&self, path: &SpanPath) -> Option<Throughput> {
+        self.spans
+            .get(path)
+            .and_then(|tracked| match tracked.span.kind {
+                ProgressKind::Parent => self
+                    .spans
+                    .range(path..)
+                    .skip(1)
+                    .take_while(|(descendant, _)| descendant.starts_with(path))
+                    .filter(|(_, descendant)| matches!(descendant.span.kind, ProgressKind::Bytes))
+                    .filter_map(|(_, descendant)| descendant.span.throughput())
+                    .pipe(combined),
+                ProgressKind::Bytes | ProgressKind::Iter => tracked.span.throughput(),
+            })
+    }
+
+    fn progress(&self, path: &SpanPath) -> Option<Event> {
+        self.spans.get(path).map(|tracked| {
+            let throughput = self.throughput(path);
+            Event::Progress {
+                id: tracked.id,
+                current: tracked.span.state.current,
+                total: tracked.span.state.total,
+                rate: throughput.map(|throughput| throughput.rate.round() as u64),
+                eta_seconds: throughput
+                    .and_then(|throughput| throughput.eta())
+                    .map(|eta| eta.as_secs_f64().ceil() as u64),
+            }
+        })
+    }
+
+    fn update(&mut self, path: &SpanPath, ProgressDelta { total, current }: ProgressDelta, now: Instant) -> Result<()> {
+        let Some(tracked) = self.spans.get_mut(path) else {
+            return Ok(());
+        };
+        tracked.span.state.total += total;
+        tracked.span.state.current += current;
+        tracked.span.samples.record(now, tracked.span.state.current);
+        match now.duration_since(tracked.last_sent) >= UPDATE_INTERVAL {
+            true => {
+                tracked.last_sent = now;
+                tracked.pending = false;
+                self.progress(path)
+                    .map_or(Ok(()), |event| write_event(&mut self.output, event))
+            }
+            false => {
+                tracked.pending = true;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn handle(&mut self, ProgressMessage { span: path, update }: ProgressMessage, now: Instant) -> Result<()> {
+        let parent = path.parent();
+        match update {
+            // started below an abandoned span
+            Update::Start(_)
+                if parent
+                    .as_ref()
+                    .is_some_and(|parent| !parent.is_empty() && !self.spans.contains_key(parent)) =>
+            {
+                Ok(())
+            }
+            Update::Start(started) => {
+                let id = self.next_id;
+                self.next_id += 1;
+                write_event(
+                    &mut self.output,
+                    Event::Start {
+                        id,
+                        parent: parent
+                            .as_ref()
+                            .and_then(|parent| self.spans.get(parent))
+                            .map(|parent| parent.id),
+                        name: started.name.to_string(),
+                        kind: SpanKind::from(&started.kind),
+                        total: started.state.total,
+                    },
+                )?;
+                self.spans.insert(
+                    path,
+                    TrackedSpan {
+                        id,
+                        span: started.tap_mut(|started| started.samples = Samples::started_at(now, started.state.current)),
+                        last_sent: now,
+                        pending: false,
+                    },
+                );
+                parent.map_or(Ok(()), |parent| self.update(&parent, ProgressDelta { total: 1, current: 0 }, now))
+            }
+            Update::Update(delta) => self.update(&path, delta, now),
+            Update::Finish => match self
+                .spans
+                .get(&path)
+                .map(|tracked| (tracked.id, tracked.pending))
+            {
+                Some((id, pending)) => pending
+                    .then(|| self.progress(&path))
+                    .flatten()
+                    .into_iter()
+                    .chain([Event::Finish { id }])
+                    .try_for_each(|event| write_event(&mut self.output, event))
+                    .tap(|_| {
+                        self.spans.remove(&path);
+                    })
+                    .and_then(|_| parent.map_or(Ok(()), |parent| self.update(&parent, ProgressDelta { total: 0, current: 1 }, now))),
+                None => Ok(()),
+            },
+            Update::Abandon => match self.spans.get(&path).map(|tracked| tracked.id) {
+                Some(id) => write_event(&mut self.output, Event::Abandon { id })
+                    .tap(|_| {
+                        self.spans
+                            .range(&path..)
+                            .take_while(|(abandoned, _)| abandoned.starts_with(&path))
+                            .map(|(abandoned, _)| abandoned.clone())
+                            .collect::<Vec<_>>()
+                            .into_iter()
+                            .for_each(|abandoned| {
+                                self.spans.remove(&abandoned);
+                            })
+                    })
+                    .and_then(|_| parent.map_or(Ok(()), |parent| self.update(&parent, ProgressDelta { total: 0, current: 1 }, now))),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// json progress is written for as long as this is alive
+pub struct JsonProgress {
+    worker: Option<JoinHandle<()>>,
+}
+
+pub fn start() -> JsonProgress {
+    let (_, messages, root) = ProgressMap::new();
+    bridge::attach(root);
+    ENABLED.store(true, Ordering::Relaxed);
+    let mut emitter = Emitter::new(std::io::stdout());
+    std::thread::spawn(move || {
+        futures::executor::block_on(messages.for_each(|message| {
+            if let Err(reason) = emitter.handle(message, Instant::now()) {
+                warn!("{reason:?}")
+            }
+            futures::future::ready(())
+        }))
+    })
+    .pipe(|worker| JsonProgress { worker: Some(worker) })
+}
+
+impl Drop for JsonProgress {
+    fn drop(&mut self) {
+        bridge::detach();
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        if let Some(worker) = self.worker.take() {
+            while !worker.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            if worker.is_finished() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        hoola_progress::{Progress, progress_span::ProgressState},
+        itertools::Itertools,
+    };
+
+    fn events(output: &[u8]) -> Result<Vec<serde_json::Value>> {
+        std::str::from_utf8(output)?
+            .lines()
+            .map(|line| serde_json::from_str(line).context("not a json line"))
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_spans_become_events() -> Result<()> {
+        let (_, mut messages, root) = ProgressMap::new();
+        let phase = root.child("sync_downloads");
+        let file = phase.span_raw(ProgressSpan {
+            name: "a.7z".into(),
+            state: ProgressState { total: 100, current: 0 },
+            kind: ProgressKind::Bytes,
+            samples: Default::default(),
+        });
+        [10, 20, 30].into_iter().for_each(|by| {
+            Progress::send(&file, Update::Update(ProgressDelta { total: 0, current: by }));
+            // otherwise the sender coalesces them into one
+            file.flush()
+        });
+        drop((file, phase, root));
+        messages.close();
+
+        let start = Instant::now();
+        let mut emitter = Emitter::new(Vec::new());
+        futures::executor::block_on(messages.collect::<Vec<_>>())
+            .into_iter()
+            // the second update comes late enough to be reported, the others are throttled
+            .zip([0, 0, 0, 300, 350, 400, 400, 400])
+            .try_for_each(|(message, after)| emitter.handle(message, start + Duration::from_millis(after)))?;
+
+        assert_eq!(
+            events(&emitter.output)?,
+            [
+                serde_json::json!({"version": 1, "event": "start", "id": 0, "parent": null, "name": "sync_downloads", "kind": "parent", "total": 0}),
+                serde_json::json!({"version": 1, "event": "start", "id": 1, "parent": 0, "name": "a.7z", "kind": "bytes", "total": 100}),
+                // 30 bytes in 0.3s, 70 left
+                serde_json::json!({"version": 1, "event": "progress", "id": 1, "current": 30, "total": 100, "rate": 100, "eta_seconds": 1}),
+                serde_json::json!({"version": 1, "event": "progress", "id": 1, "current": 60, "total": 100, "rate": 171, "eta_seconds": 1}),
+                serde_json::json!({"version": 1, "event": "finish", "id": 1}),
+                serde_json::json!({"version": 1, "event": "progress", "id": 0, "current": 1, "total": 1}),
+                serde_json::json!({"version": 1, "event": "finish", "id": 0}),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_abandoned_spans_go_quiet() -> Result<()> {
+        let (_, mut messages, root) = ProgressMap::new();
+        let archive = root.child("archive");
+        let file = archive.child("file");
+        archive.abandon();
+        // the rest of the subtree is dropped later, it's not reported anymore
+        let nested = file.child("nested");
+        drop((nested, file));
+        messages.close();
+
+        let now = Instant::now();
+        let mut emitter = Emitter::new(Vec::new());
+        futures::executor::block_on(messages.by_ref().collect::<Vec<_>>())
+            .into_iter()
+            .try_for_each(|message| emitter.handle(message, now))?;
+        assert_eq!(
+            events(&emitter.output)?
+                .iter()
+                .map(|event| (event["event"].clone(), event["id"].clone()))
+                .collect_vec(),
+            [
+                (serde_json::json!("start"), serde_json::json!(0)),
+                (serde_json::json!("start"), serde_json::json!(1)),
+                (serde_json::json!("abandon"), serde_json::json!(0)),
+            ]
+        );
+        drop(root);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_phase_and_error_lines() -> Result<()> {
+        let mut output = Vec::new();
+        write_event(&mut output, Event::Phase { name: "downloads".into() })?;
+        write_event(
+            &mut output,
+            Event::Error {
+                message: "could not finish installation".into(),
+            },
+        )?;
+        assert_eq!(
+            std::str::from_utf8(&output)?.lines().collect_vec(),
+            [
+                r#"{"version":1,"event":"phase","name":"downloads"}"#,
+                r#"{"version":1,"event":"error","message":"could not finish installation"}"#,
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! paths in the config are written relative to the directory holding it (the project root), so that the whole folder can be moved around.
+//! the same directory is often reachable through more than one path - symlinked segments (`/home -> /var/home` on Silverblue)
+//! or bind mounts - so every form of both paths is compared before giving up and keeping the path absolute
+
+use {
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    normalize_path::NormalizePath,
+    std::path::{Path, PathBuf},
+    tap::prelude::*,
+    tracing::info,
+};
+
+/// absolute and normalized, but with symlinks left in place - that's the form the user picked and sees
+fn literal(path: &Path) -> PathBuf {
+    match path.is_absolute() {
+        true => path.to_owned(),
+        false => std::env::current_dir()
+            .map(|current| current.join(path))
+            .unwrap_or_else(|_| path.to_owned()),
+    }
+    .normalize()
+}
+
+/// symlinks resolved, a path which doesn't exist yet gets its closest existing ancestor resolved
+fn canonical(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find_map(|ancestor| {
+            ancestor
+                .canonicalize()
+                .ok()
+                .map(|canonical| (ancestor, canonical))
+        })
+        .and_then(|(ancestor, canonical)| {
+            path.strip_prefix(ancestor)
+                .ok()
+                .map(|rest| canonical.join(rest))
+        })
+}
+
+/// bind mounts don't show up as symlinks, the only way to tell it's the same directory is to ask the filesystem
+#[cfg(unix)]
+fn same_directory(left: &Path, right: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(left), std::fs::metadata(right)) {
+        (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_directory(_left: &Path, _right: &Path) -> bool {
+    false
+}
+
+/// [None] when the path lives outside of the root (in every form of both)
+fn relativize_with(path: &Path, root: &Path, same_directory: impl Fn(&Path, &Path) -> bool) -> Option<PathBuf> {
+    let (path, root) = (literal(path), literal(root));
+    [Some(path.clone()), canonical(&path)]
+        .into_iter()
+        .flatten()
+        .cartesian_product(
+            [Some(root.clone()), canonical(&root)]
+                .into_iter()
+                .flatten()
+                .collect_vec(),
+        )
+        .find_map(|(path, root)| path.strip_prefix(&root).ok().map(Path::to_owned))
+        .or_else(|| {
+            path.ancestors()
+                .find(|ancestor| same_directory(ancestor, &root))
+                .and_then(|ancestor| path.strip_prefix(ancestor).ok().map(Path::to_owned))
+        })
+        .map(|relative| match relative.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => relative,
+        })
+}
+
+#[extension_traits::extension(pub trait MaybeRelativeTo)]
+impl<P: AsRef<Path>> P {
+    /// relative to `parent` whenever any form of the two paths matches, otherwise the path as it was picked
+    fn maybe_relative_to<Parent: AsRef<Path>>(&self, parent: Parent) -> PathBuf {
+        relativize_with(self.as_ref(), parent.as_ref(), same_directory).unwrap_or_else(|| self.as_ref().normalize())
+    }
+    /// like [MaybeRelativeTo::maybe_relative_to], but the relative path has to point at an existing file from within `parent`
+    fn maybe_relative_to_exists<Parent: AsRef<Path>>(&self, parent: Parent) -> PathBuf {
+        relativize_with(self.as_ref(), parent.as_ref(), same_directory)
+            .filter(|relative| parent.as_ref().join(relative).exists())
+            .unwrap_or_else(|| literal(self.as_ref()))
+    }
+}
+
+/// parent directory of the config file, in the literal form (canonicalizing would bake the resolved symlinks into the config)
+pub fn project_root_for(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+        .pipe(literal)
+}
+
+/// relative paths in the config are relative to the config file, no matter where hoolamike was started from
+pub fn enter_project_root(config_path: &Path) -> Result<PathBuf> {
+    project_root_for(config_path).pipe(|root| {
+        std::env::set_current_dir(&root)
+            .with_context(|| format!("failed to set current working directory to [{}]", root.display()))
+            .tap_ok(|_| info!("working directory: [{}]", root.display()))
+            .map(|_| root)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `<tmp>/var/home/user/project` reachable through `<tmp>/home -> <tmp>/var/home`
+    #[cfg(unix)]
+    fn symlinked_home() -> Result<(tempfile::TempDir, PathBuf, PathBuf)> {
+        let directory = tempfile::tempdir()?;
+        let real = directory.path().join("var/home/user/project");
+        std::fs::create_dir_all(real.join("downloads"))?;
+        std::os::unix::fs::symlink(directory.path().join("var/home"), directory.path().join("home"))?;
+        let through_symlink = directory.path().join("home/user/project");
+        Ok((directory, real, through_symlink))
+    }
+
+    #[cfg(unix)]
+    fn never_same(_: &Path, _: &Path) -> bool {
+        false
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_symlinked_parents_match_in_either_direction() -> Result<()> {
+        let (_directory, real, through_symlink) = symlinked_home()?;
+        assert_eq!(relativize_with(&real.join("downloads"), &through_symlink, never_same), Some("downloads".into()));
+        assert_eq!(relativize_with(&through_symlink.join("downloads"), &real, never_same), Some("downloads".into()));
+        assert_eq!(relativize_with(&through_symlink, &through_symlink, never_same), Some(".".into()));
+        assert_eq!(
+            through_symlink
+                .join("downloads")
+                .maybe_relative_to_exists(&real),
+            PathBuf::from("downloads")
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_non_existent_targets_are_relativized() -> Result<()> {
+        let (_directory, real, through_symlink) = symlinked_home()?;
+        assert_eq!(
+            relativize_with(&real.join("installed/not/yet"), &through_symlink, never_same),
+            Some("installed/not/yet".into())
+        );
+        assert_eq!(real.join("installed").maybe_relative_to(&through_symlink), PathBuf::from("installed"));
+        // the relative path would point at nothing, so it stays absolute - in the form it was picked
+        assert_eq!(
+            through_symlink
+                .join("missing.wabbajack")
+                .maybe_relative_to_exists(&real),
+            through_symlink.join("missing.wabbajack")
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_bind_mounts_and_other_mounts() {
+        let bind_mounted = |left: &Path, right: &Path| left == Path::new("/mnt/bind/project") && right == Path::new("/home/user/project");
+        assert_eq!(
+            relativize_with(Path::new("/mnt/bind/project/downloads/a.7z"), Path::new("/home/user/project"), bind_mounted),
+            Some("downloads/a.7z".into())
+        );
+        assert_eq!(
+            relativize_with(Path::new("/mnt/other-disk/downloads"), Path::new("/home/user/project"), bind_mounted),
+            None
+        );
+        assert_eq!(
+            Path::new("/mnt/other-disk/./downloads").maybe_relative_to("/home/user/project"),
+            PathBuf::from("/mnt/other-disk/downloads")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_bind_mount_detection_on_the_same_directory() -> Result<()> {
+        let (_directory, real, through_symlink) = symlinked_home()?;
+        assert!(same_directory(&real, &through_symlink));
+        assert!(!same_directory(&real, &real.join("downloads")));
+        assert!(!same_directory(&real, &real.join("missing")));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_project_root_keeps_symlinks() -> Result<()> {
+        let (_directory, _real, through_symlink) = symlinked_home()?;
+        assert_eq!(project_root_for(&through_symlink.join("hoolamike.yaml")), through_symlink);
+        Ok(())
+    }
+}
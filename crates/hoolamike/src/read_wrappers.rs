@@ -102,8 +102,44 @@ pub mod validate_hash {
     }
 }
 
+pub mod hashing {
+    use std::{
+        hash::Hasher,
+        io::{Read, Result},
+    };
+
+    /// computes the wabbajack (xxhash64) hash of everything read through it
+    pub struct HashingReader<R> {
+        inner: R,
+        hash_state: xxhash_rust::xxh64::Xxh64,
+    }
+
+    impl<R: Read> HashingReader<R> {
+        pub fn new(inner: R) -> Self {
+            HashingReader {
+                inner,
+                hash_state: xxhash_rust::xxh64::Xxh64::new(0),
+            }
+        }
+        pub fn hash(&self) -> u64 {
+            self.hash_state.finish()
+        }
+    }
+
+    impl<R: Read> Read for HashingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.hash_state.update(&buf[..n]);
+            Ok(n)
+        }
+    }
+}
+
 #[extension_traits::extension(pub trait ReadExt)]
 impl<R: Read> R {
+    fn and_hash(self) -> hashing::HashingReader<R> {
+        hashing::HashingReader::new(self)
+    }
     fn and_validate_hash(self, expected_hash: u64) -> ValidateHashReader<R> {
         ValidateHashReader::new(self, expected_hash)
     }
@@ -0,0 +1,386 @@
+//! intermediate files (extracted archive entries, textures being recompressed, wine prefixes) live in a directory of their own
+//! for every run, under a configurable root. `/tmp` is often a small tmpfs, so installs keep it on the installation's filesystem
+//! instead. a run removes its directory when it exits - and since a crashed run can't clean up after itself, directories of
+//! runs which are gone are swept on startup
+
+use {
+    anyhow::{Context, Result},
+    itertools::Itertools,
+    once_cell::sync::OnceCell,
+    std::{
+        io::ErrorKind,
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tempfile::{NamedTempFile, PersistError},
+    tracing::{debug, info, warn},
+};
+
+/// root installs use (inside the installation directory) unless `advanced.temp_directory` says otherwise
+pub const DEFAULT_DIRECTORY_NAME: &str = ".hoolamike-tmp";
+/// root of the commands which don't install anything
+const FALLBACK_ROOT: &str = "HOOLAMIKE_TEMP_FILES";
+/// every run gets `<root>/hoolamike-run-<pid>`, only directories named like that are ever swept
+const RUN_PREFIX: &str = "hoolamike-run-";
+const PID_FILE: &str = "hoolamike.pid";
+
+static ROOT: OnceCell<PathBuf> = OnceCell::new();
+static RUN_DIRECTORY: OnceCell<PathBuf> = OnceCell::new();
+
+/// picks the root for this run, it has to happen before the first temporary file is created
+pub fn configure(root: PathBuf) {
+    match ROOT.try_insert(root) {
+        Ok(root) => info!("temporary files go to [{}]", root.display()),
+        Err((current, requested)) if *current != requested => warn!(
+            "temporary files keep going to [{}] until hoolamike is restarted (instead of [{}])",
+            current.display(),
+            requested.display()
+        ),
+        Err(_) => {}
+    }
+}
+
+fn root() -> &'static Path {
+    ROOT.get_or_init(|| PathBuf::from(FALLBACK_ROOT))
+}
+
+/// directory of this run, created (after sweeping the ones of dead runs) on first use
+pub fn run_directory() -> Result<&'static Path> {
+    RUN_DIRECTORY
+        .get_or_try_init(|| {
+            let root = root();
+            sweep_orphans(root);
+            let run_directory = root.join(format!("{RUN_PREFIX}{}", std::process::id()));
+            std::fs::create_dir_all(&run_directory)
+                .and_then(|_| std::fs::write(run_directory.join(PID_FILE), std::process::id().to_string()))
+                .with_context(|| format!("creating temporary directory [{}]", run_directory.display()))
+                .map(|_| run_directory)
+        })
+        .map(PathBuf::as_path)
+}
+
+/// removes the directory of this run (pidfile included) if it was created, called on the way out
+pub fn remove_run_directory() {
+    if let Some(run_directory) = RUN_DIRECTORY.get() {
+        remove(run_directory)
+            .map(|_| debug!("removed [{}]", run_directory.display()))
+            .unwrap_or_else(|reason| warn!("{reason:?}"))
+    }
+}
+
+fn remove(run_directory: &Path) -> Result<()> {
+    match std::fs::remove_dir_all(run_directory) {
+        Err(reason) if reason.kind() != ErrorKind::NotFound => {
+            Err(reason).with_context(|| format!("could not remove temporary directory [{}]", run_directory.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// bytes taken by the files of this run so far, nothing when no temporary file was created yet
+pub fn usage() -> u64 {
+    RUN_DIRECTORY.get().map_or(0, |run_directory| {
+        walkdir::WalkDir::new(run_directory)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    })
+}
+
+/// [false] only when the process is known to be gone - or to be something other than hoolamike now that its pid was reused
+fn is_alive(pid: u32) -> bool {
+    match Path::new("/proc/self").exists() {
+        // no procfs, no way to tell
+        false => true,
+        true => match std::fs::read_to_string(format!("/proc/{pid}/comm")) {
+            Ok(command) => command.trim().starts_with(clap::crate_name!()),
+            Err(reason) => reason.kind() != ErrorKind::NotFound,
+        },
+    }
+}
+
+/// pid from the pidfile, or from the directory name when the run crashed before writing it
+fn owner(run_directory: &Path) -> Option<u32> {
+    std::fs::read_to_string(run_directory.join(PID_FILE))
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok())
+        .or_else(|| {
+            run_directory
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(RUN_PREFIX))
+                .and_then(|pid| pid.parse().ok())
+        })
+}
+
+/// removes the run directories of processes which are gone, returning the removed ones
+pub fn sweep_orphans(root: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(RUN_PREFIX))
+        })
+        .filter(|path| owner(path).is_some_and(|pid| !is_alive(pid)))
+        .filter(|path| {
+            std::fs::remove_dir_all(path)
+                .map_err(|reason| warn!("could not remove [{}] left behind by an earlier run: {reason}", path.display()))
+                .is_ok()
+        })
+        .collect_vec()
+        .tap(|removed| {
+            if !removed.is_empty() {
+                info!("removed [{}] temporary directories left behind by earlier runs", removed.len())
+            }
+        })
+}
+
+/// bytes available to unprivileged users on the filesystem holding `path` (or its closest existing parent)
+pub fn available_space(path: &Path) -> Result<u64> {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .with_context(|| format!("no part of [{}] exists", path.display()))
+        .and_then(|existing| free_space(existing).with_context(|| format!("checking free space at [{}]", existing.display())))
+}
+
+#[cfg(unix)]
+fn free_space(existing: &Path) -> std::io::Result<u64> {
+    nix::sys::statvfs::statvfs(existing)
+        // the widths of these differ between platforms
+        .map(|stats| {
+            #[allow(clippy::unnecessary_cast)]
+            let (blocks, block_size) = (stats.blocks_available() as u64, stats.fragment_size() as u64);
+            blocks.saturating_mul(block_size)
+        })
+        .map_err(std::io::Error::from)
+}
+
+#[cfg(windows)]
+fn free_space(existing: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    let directory = existing
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect::<Vec<u16>>();
+    let mut available = 0u64;
+    // SAFETY: the name is nul terminated, the totals nobody asks for are allowed to be null
+    match unsafe {
+        windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW(directory.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut())
+    } {
+        0 => Err(std::io::Error::last_os_error()),
+        _ => Ok(available),
+    }
+}
+
+/// fails when the temp root can't fit `required` bytes - an archive being extracted there takes at least that much
+pub fn ensure_space(required: u64) -> Result<()> {
+    let root = root();
+    available_space(root).and_then(|available| match available >= required {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!(
+            "only [{}] is free at [{}] which holds temporary files, but at least [{}] is needed - free some space or point 'advanced.temp_directory' in the \
+             config at a bigger filesystem",
+            crate::helpers::human_readable_size(available),
+            root.display(),
+            crate::helpers::human_readable_size(required),
+        )),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Promotion {
+    /// same filesystem, nothing was written
+    Renamed,
+    /// across filesystems, [std::fs::copy] still reflinks when the filesystem supports it
+    Copied,
+}
+
+/// copies `source` into a temp file next to `destination` first, so that the destination is either complete or untouched
+fn copy_into_place(source: &Path, destination: &Path) -> Result<()> {
+    crate::atomic_write::temp_file_next_to(destination)
+        .and_then(|staged| {
+            std::fs::copy(source, staged.path())
+                .with_context(|| format!("copying [{}]", source.display()))
+                .and_then(|_| {
+                    staged
+                        .persist(destination)
+                        .with_context(|| format!("renaming temp file to [{}]", destination.display()))
+                })
+        })
+        .map(drop)
+}
+
+/// moves a finished temp file to `destination` - a rename when the temp root is on the same filesystem (the default), a
+/// copy otherwise. the temp file is gone either way
+pub fn promote(temp_file: NamedTempFile, destination: &Path) -> Result<Promotion> {
+    temp_file
+        .persist(destination)
+        .map(|_| Promotion::Renamed)
+        .or_else(|PersistError { error, file }| {
+            debug!(
+                "could not rename [{}] to [{}] ({error}), copying instead",
+                file.path().display(),
+                destination.display()
+            );
+            copy_into_place(file.path(), destination).map(|_| Promotion::Copied)
+        })
+        .with_context(|| format!("moving temporary file to [{}]", destination.display()))
+}
+
+/// [promote] for files written by other programs into a directory of their own, `source` is gone either way
+pub fn move_into_place(source: &Path, destination: &Path) -> Result<Promotion> {
+    std::fs::rename(source, destination)
+        .map(|_| Promotion::Renamed)
+        .or_else(|error| {
+            debug!(
+                "could not rename [{}] to [{}] ({error}), copying instead",
+                source.display(),
+                destination.display()
+            );
+            copy_into_place(source, destination)
+                .and_then(|_| std::fs::remove_file(source).with_context(|| format!("removing [{}]", source.display())))
+                .map(|_| Promotion::Copied)
+        })
+        .with_context(|| format!("moving [{}] to [{}]", source.display(), destination.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs::create_dir_all};
+
+    fn run_directory_in(root: &Path, name: &str, pid: Option<u32>) -> Result<PathBuf> {
+        let run_directory = root.join(name);
+        create_dir_all(&run_directory)?;
+        std::fs::write(run_directory.join("leftover.tmp"), "partially extracted")?;
+        if let Some(pid) = pid {
+            std::fs::write(run_directory.join(PID_FILE), pid.to_string())?;
+        }
+        Ok(run_directory)
+    }
+
+    #[test_log::test]
+    fn test_only_directories_of_dead_runs_are_swept() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        // pids never go this high
+        let dead = u32::MAX - 1;
+        let ours = std::process::id();
+        let alive = run_directory_in(root.path(), &format!("{RUN_PREFIX}{ours}"), Some(ours))?;
+        let orphaned = run_directory_in(root.path(), &format!("{RUN_PREFIX}{dead}"), Some(dead))?;
+        let no_pidfile = run_directory_in(root.path(), &format!("{RUN_PREFIX}{}", dead - 1), None)?;
+        let unknown_owner = run_directory_in(root.path(), &format!("{RUN_PREFIX}something"), None)?;
+        let unrelated = run_directory_in(root.path(), "user-stuff", Some(dead))?;
+
+        let mut removed = sweep_orphans(root.path());
+        removed.sort();
+        assert_eq!(removed, [orphaned.clone(), no_pidfile.clone()].tap_mut(|expected| expected.sort()));
+        assert!(!orphaned.exists() && !no_pidfile.exists());
+        assert!(alive.exists() && unknown_owner.exists() && unrelated.exists());
+        assert!(sweep_orphans(&root.path().join("missing")).is_empty());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_run_directory_is_removed_with_its_pidfile() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let ours = std::process::id();
+        let run_directory = run_directory_in(root.path(), &format!("{RUN_PREFIX}{ours}"), Some(ours))?;
+        create_dir_all(run_directory.join("pfx-wine"))?;
+        remove(&run_directory)?;
+        assert!(!run_directory.exists());
+        // removing it twice is fine
+        remove(&run_directory)?;
+        assert!(root.path().exists());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_available_space_of_missing_directory() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        // checked on the closest existing parent
+        assert!(available_space(&root.path().join("not/created/yet"))? > 0);
+        assert!(available_space(Path::new("relative/and/missing")).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn device(path: &Path) -> std::io::Result<u64> {
+        std::os::unix::fs::MetadataExt::dev(&std::fs::metadata(path)?).pipe(Ok)
+    }
+
+    fn temp_file_in(directory: &Path, contents: &str) -> Result<(NamedTempFile, PathBuf)> {
+        create_dir_all(directory)?;
+        let mut temp_file = NamedTempFile::new_in(directory)?;
+        std::io::Write::write_all(&mut temp_file, contents.as_bytes())?;
+        let path = temp_file.path().to_owned();
+        Ok((temp_file, path))
+    }
+
+    #[test_log::test]
+    fn test_promotion_on_the_same_filesystem_renames() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let (temp_file, temp_path) = temp_file_in(&root.path().join(DEFAULT_DIRECTORY_NAME), "extracted entry")?;
+        let destination = root.path().join("mods/foo.esp");
+        create_dir_all(root.path().join("mods"))?;
+        std::fs::write(&destination, "previous contents")?;
+
+        assert_eq!(promote(temp_file, &destination)?, Promotion::Renamed);
+        assert_eq!(std::fs::read_to_string(&destination)?, "extracted entry");
+        assert!(!temp_path.exists());
+
+        let written = root.path().join(DEFAULT_DIRECTORY_NAME).join("0.dds");
+        std::fs::write(&written, "converted texture")?;
+        assert_eq!(move_into_place(&written, &destination)?, Promotion::Renamed);
+        assert_eq!(std::fs::read_to_string(&destination)?, "converted texture");
+        assert!(!written.exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_promotion_across_filesystems_copies() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let destination = root.path().join("foo.esp");
+        let (staged, staged_path) = temp_file_in(&root.path().join("staged"), "extracted entry")?;
+        copy_into_place(&staged_path, &destination)?;
+        assert_eq!(std::fs::read_to_string(&destination)?, "extracted entry");
+        drop(staged);
+
+        // a real device boundary, when there is one around
+        let other_device = tempfile::tempdir_in("/dev/shm")
+            .ok()
+            .filter(|other| device(other.path()).ok() != device(root.path()).ok());
+        match other_device {
+            None => info!("no second filesystem to promote across, only the copy itself was checked"),
+            Some(other_device) => {
+                let (temp_file, temp_path) = temp_file_in(other_device.path(), "another entry")?;
+                assert_eq!(promote(temp_file, &destination)?, Promotion::Copied);
+                assert_eq!(std::fs::read_to_string(&destination)?, "another entry");
+                assert!(!temp_path.exists());
+                let written = other_device.path().join("0.dds");
+                std::fs::write(&written, "converted texture")?;
+                assert_eq!(move_into_place(&written, &destination)?, Promotion::Copied);
+                assert_eq!(std::fs::read_to_string(&destination)?, "converted texture");
+                assert!(!written.exists());
+            }
+        }
+        assert_eq!(
+            std::fs::read_dir(root.path())?
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name())
+                .filter(|name| name.to_string_lossy().starts_with(".hoolamike-"))
+                .count(),
+            0,
+            "staged copies are renamed into place"
+        );
+        Ok(())
+    }
+}
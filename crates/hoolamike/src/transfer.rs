@@ -0,0 +1,455 @@
+//! moves a finished installation to another machine (e.g. desktop -> Steam Deck) without downloading everything again.
+//! `export` splits the installation into hash-verified chunks, `import` puts it back together on the target machine
+//! and adapts it to the new location. Both can be interrupted and rerun - finished chunks are not redone.
+
+use {
+    crate::{config_file::HoolamikeConfig, progress_bars_v2::count_progress_style},
+    anyhow::{Context, Result},
+    format::{ChunkRecord, ExportIndex, IMPORT_PROGRESS_FILE_NAME, InstallationManifest, chunk_path, copy_hashed, finish_hash, read_json},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeSet,
+        fs::{File, OpenOptions},
+        io::{BufWriter, Read, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+    tracing::{info, info_span, instrument, warn},
+    tracing_indicatif::span_ext::IndicatifSpanExt,
+};
+
+pub mod format;
+
+const MEBIBYTE: u64 = 1024 * 1024;
+
+#[derive(clap::Args, Clone)]
+pub struct ExportCli {
+    /// directory the export is written to, rerun with the same directory to resume
+    #[arg(long)]
+    to: PathBuf,
+    /// size of a single chunk, in MiB
+    #[arg(long, default_value_t = 1024)]
+    chunk_size_mib: u64,
+}
+
+#[derive(clap::Args, Clone)]
+pub struct ImportCli {
+    /// directory created by 'hoolamike export', chunks which are not copied over yet are picked up by the next run
+    source: PathBuf,
+}
+
+pub fn run_export(ExportCli { to, chunk_size_mib }: ExportCli, config: HoolamikeConfig) -> Result<()> {
+    let game = crate::post_install_fixup::modlist_game_type(&config)
+        .tap_err(|reason| warn!(?reason, "game type will not be recorded, game specific fixes will be skipped after import"))
+        .ok();
+    InstallationManifest::collect(&config.installation.installation_path, game)
+        .and_then(|manifest| export(manifest, &config.installation.installation_path, &to, chunk_size_mib * MEBIBYTE))
+        .map(|written| info!("export at [{}] is complete ([{written}] chunks written in this run)", to.display()))
+}
+
+pub fn run_import(ImportCli { source }: ImportCli, config: HoolamikeConfig) -> Result<()> {
+    import(&source, &config).map(|imported| {
+        info!(
+            "installation at [{}] is ready ([{imported}] chunks imported in this run)",
+            config.installation.installation_path.display()
+        )
+    })
+}
+
+/// chunk files which are still there with the recorded size are trusted, they were hashed when written
+fn still_exported(export_directory: &Path, index: usize, record: &ChunkRecord) -> bool {
+    std::fs::metadata(chunk_path(export_directory, index)).is_ok_and(|metadata| metadata.len() == record.size)
+}
+
+fn write_chunk(installation_path: &Path, index: &ExportIndex, chunk: usize, export_directory: &Path) -> Result<ChunkRecord> {
+    let output = chunk_path(export_directory, chunk);
+    let temp = output.with_extension("bin.tmp");
+    let mut hasher = xxhash_rust::xxh64::Xxh64::new(0);
+    File::create(&temp)
+        .with_context(|| format!("creating [{}]", temp.display()))
+        .map(BufWriter::new)
+        .and_then(|mut writer| {
+            index
+                .segments(index.chunk_range(chunk))
+                .into_iter()
+                .try_fold(0, |size, segment| {
+                    let source = installation_path.join(&index.manifest.files[segment.file].path);
+                    File::open(&source)
+                        .and_then(|mut file| file.seek(SeekFrom::Start(segment.offset)).map(|_| file))
+                        .and_then(|file| copy_hashed(file.take(segment.length), &mut writer, &mut hasher))
+                        .with_context(|| format!("reading [{}]", source.display()))
+                        .and_then(|copied| {
+                            (copied == segment.length)
+                                .then_some(size + copied)
+                                .with_context(|| format!("[{}] changed since the export started", source.display()))
+                        })
+                })
+                .and_then(|size| writer.flush().context("flushing").map(|_| size))
+        })
+        .and_then(|size| {
+            std::fs::rename(&temp, &output)
+                .with_context(|| format!("moving [{}] into place", output.display()))
+                .map(|_| ChunkRecord {
+                    size,
+                    hash: finish_hash(&hasher),
+                })
+        })
+        .with_context(|| format!("writing chunk [{chunk}]"))
+}
+
+/// returns the number of chunks written in this run
+#[instrument(skip(manifest))]
+pub fn export(manifest: InstallationManifest, installation_path: &Path, export_directory: &Path, chunk_size: u64) -> Result<usize> {
+    anyhow::ensure!(chunk_size > 0, "chunk size must be greater than zero");
+    let fresh = ExportIndex::new(manifest, chunk_size);
+    let mut index = match ExportIndex::read(export_directory) {
+        Ok(existing) if existing.same_layout(&fresh) => existing.tap_mut(|existing| {
+            existing
+                .chunks
+                .retain(|chunk, record| still_exported(export_directory, *chunk, record))
+        }),
+        Ok(_) => fresh.tap(|_| {
+            warn!(
+                "[{}] holds an export of a different installation, starting from scratch",
+                export_directory.display()
+            )
+        }),
+        Err(_) => fresh,
+    };
+    std::fs::create_dir_all(export_directory.join(format::CHUNKS_DIRECTORY)).context("creating export directory")?;
+    index.write(export_directory)?;
+
+    let remaining = (0..index.chunk_count())
+        .filter(|chunk| !index.chunks.contains_key(chunk))
+        .collect::<Vec<_>>();
+    info!("[{}] out of [{}] chunks left to export", remaining.len(), index.chunk_count());
+    let exporting = info_span!("exporting_chunks").tap(|pb| {
+        pb.pb_set_style(&count_progress_style());
+        pb.pb_set_length(remaining.len() as u64);
+    });
+    exporting
+        .in_scope(|| {
+            remaining.iter().try_for_each(|chunk| {
+                write_chunk(installation_path, &index, *chunk, export_directory)
+                    .and_then(|record| {
+                        index.chunks.insert(*chunk, record);
+                        index.write(export_directory)
+                    })
+                    .tap_ok(|_| exporting.pb_inc(1))
+            })
+        })
+        .map(|_| remaining.len())
+}
+
+/// chunks of a specific export already written into the installation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+struct ImportProgress {
+    export_digest: String,
+    completed: BTreeSet<usize>,
+}
+
+impl ImportProgress {
+    fn path(installation_path: &Path) -> PathBuf {
+        installation_path.join(IMPORT_PROGRESS_FILE_NAME)
+    }
+
+    fn open(installation_path: &Path, export_digest: String) -> Self {
+        match read_json::<Self>(&Self::path(installation_path)) {
+            Ok(progress) if progress.export_digest == export_digest => {
+                progress.tap(|progress| info!("resuming import, [{}] chunks are already in place", progress.completed.len()))
+            }
+            _ => Self {
+                export_digest,
+                completed: Default::default(),
+            },
+        }
+    }
+
+    fn save(&self, installation_path: &Path) -> Result<()> {
+        serde_json::to_string(self)
+            .context("serializing import progress")
+            .and_then(|progress| std::fs::write(Self::path(installation_path), progress).context("writing import progress"))
+    }
+}
+
+enum ChunkStatus {
+    Imported,
+    /// not copied over yet (or still being copied)
+    NotThereYet,
+}
+
+/// writes the chunk into the installation while hashing it, a chunk which doesn't match its record is not marked as imported
+fn import_chunk(source: &Path, index: &ExportIndex, chunk: usize, installation_path: &Path) -> Result<ChunkStatus> {
+    let record = index
+        .chunks
+        .get(&chunk)
+        .with_context(|| format!("chunk [{chunk}] is missing from the index"))?;
+    if !still_exported(source, chunk, record) {
+        return Ok(ChunkStatus::NotThereYet);
+    }
+    let mut hasher = xxhash_rust::xxh64::Xxh64::new(0);
+    let mut reader = File::open(chunk_path(source, chunk)).context("opening chunk")?;
+    index
+        .segments(index.chunk_range(chunk))
+        .into_iter()
+        .try_for_each(|segment| {
+            let target = installation_path.join(&index.manifest.files[segment.file].path);
+            target
+                .parent()
+                .map(std::fs::create_dir_all)
+                .transpose()
+                .context("creating parent directory")
+                .and_then(|_| {
+                    OpenOptions::new()
+                        .create(true)
+                        .truncate(false)
+                        .write(true)
+                        .open(&target)
+                        .and_then(|mut file| file.seek(SeekFrom::Start(segment.offset)).map(|_| file))
+                        .and_then(|file| {
+                            let mut writer = BufWriter::new(file);
+                            copy_hashed((&mut reader).take(segment.length), &mut writer, &mut hasher).and_then(|copied| writer.flush().map(|_| copied))
+                        })
+                        .with_context(|| format!("writing [{}]", target.display()))
+                })
+                .and_then(|copied| {
+                    (copied == segment.length)
+                        .then_some(())
+                        .context("chunk ended too early")
+                })
+        })
+        .and_then(|_| match finish_hash(&hasher) == record.hash {
+            true => Ok(ChunkStatus::Imported),
+            false => Err(anyhow::anyhow!(
+                "chunk [{}] is corrupted (expected hash [{}], got [{}]) - copy it over again and rerun the import",
+                format::chunk_file_name(chunk),
+                record.hash,
+                finish_hash(&hasher)
+            )),
+        })
+        .with_context(|| format!("importing chunk [{chunk}]"))
+}
+
+/// sizes are set explicitly, so that empty files exist and leftovers of a previous installation get cut off
+fn finalize_files(index: &ExportIndex, installation_path: &Path) -> Result<()> {
+    index.manifest.files.iter().try_for_each(|file| {
+        let target = installation_path.join(&file.path);
+        target
+            .parent()
+            .map(std::fs::create_dir_all)
+            .transpose()
+            .and_then(|_| {
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(&target)
+            })
+            .and_then(|handle| handle.set_len(file.size))
+            .with_context(|| format!("finalizing [{}]", target.display()))
+    })
+}
+
+/// returns the number of chunks imported in this run
+#[instrument(skip(config))]
+pub fn import(source: &Path, config: &HoolamikeConfig) -> Result<usize> {
+    let installation_path = &config.installation.installation_path;
+    let index = ExportIndex::read(source).context("reading export index")?;
+    anyhow::ensure!(
+        index.is_complete(),
+        "export at [{}] is not finished yet, rerun 'hoolamike export' on the source machine first",
+        source.display()
+    );
+    std::fs::create_dir_all(installation_path).context("creating installation directory")?;
+    let mut progress = ImportProgress::open(installation_path, index.digest()?);
+    let remaining = (0..index.chunk_count())
+        .filter(|chunk| !progress.completed.contains(chunk))
+        .collect::<Vec<_>>();
+    let importing = info_span!("importing_chunks").tap(|pb| {
+        pb.pb_set_style(&count_progress_style());
+        pb.pb_set_length(remaining.len() as u64);
+    });
+    let (imported, problems) = importing.in_scope(|| {
+        remaining
+            .iter()
+            .map(|chunk| {
+                import_chunk(source, &index, *chunk, installation_path)
+                    .and_then(|status| match status {
+                        ChunkStatus::Imported => {
+                            progress.completed.insert(*chunk);
+                            progress.save(installation_path).map(|_| Some(*chunk))
+                        }
+                        ChunkStatus::NotThereYet => Ok(None),
+                    })
+                    .tap(|_| importing.pb_inc(1))
+            })
+            .fold((0, vec![]), |(imported, problems), status| match status {
+                Ok(Some(_)) => (imported + 1, problems),
+                Ok(None) => (imported, problems),
+                Err(error) => (imported, problems.tap_mut(|problems| problems.push(error))),
+            })
+    });
+    problems
+        .iter()
+        .for_each(|problem| tracing::error!("{problem:?}"));
+    let missing = index.chunk_count() - progress.completed.len();
+    anyhow::ensure!(
+        missing == 0,
+        "[{missing}] chunks are not imported yet ([{}] corrupted), rerun the import once they are copied over",
+        problems.len()
+    );
+
+    finalize_files(&index, installation_path)
+        .and_then(|_| crate::post_install_fixup::adapt_moved_installation(config, index.manifest.game.as_ref()))
+        .context("adapting installation to this machine")
+        .and_then(|_| InstallationManifest::collect(installation_path, index.manifest.game.clone()))
+        .and_then(|manifest| manifest.write(installation_path))
+        .and_then(|_| std::fs::remove_file(ImportProgress::path(installation_path)).context("cleaning up import progress"))
+        .map(|_| imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::post_install_fixup::{ini::IniDocument, mod_organizer::wine_path},
+    };
+
+    /// minimal portable MO2 installation, with files large enough to span several chunks
+    fn fixture(root: &Path) -> Result<()> {
+        [
+            (
+                "ModOrganizer.ini",
+                b"[Settings]\r\nbase_directory=C:/Users/author/Modlist\r\nmod_directory=C:/Users/author/Modlist/mods\r\n".to_vec(),
+            ),
+            ("profiles/Default/modlist.txt", b"+A\r\n+B\r\n".to_vec()),
+            ("mods/A/plugin.esp", (0..3000_u32).map(|byte| byte as u8).collect()),
+            ("mods/B/empty.txt", vec![]),
+            ("mods/B/textures/big.dds", (0..10_000_u32).map(|byte| (byte * 7) as u8).collect()),
+        ]
+        .into_iter()
+        .try_for_each(|(path, contents)| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| std::fs::write(path, contents))
+        })
+        .context("creating fixture")
+    }
+
+    fn config(installation_path: &Path, downloads_directory: &Path) -> HoolamikeConfig {
+        HoolamikeConfig::default().tap_mut(|config| {
+            config.installation.installation_path = installation_path.to_owned();
+            config.downloaders.downloads_directory = downloads_directory.to_owned();
+        })
+    }
+
+    fn export_fixture(source: &Path, export_directory: &Path) -> Result<usize> {
+        InstallationManifest::collect(source, None).and_then(|manifest| export(manifest, source, export_directory, 1024))
+    }
+
+    #[test_log::test]
+    fn test_round_trip() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let (source, exported, target) = (
+            directory.path().join("source"),
+            directory.path().join("export"),
+            directory.path().join("target"),
+        );
+        fixture(&source)?;
+        assert_eq!(export_fixture(&source, &exported)?, 13);
+        let config = config(&target, &directory.path().join("downloads"));
+        assert_eq!(import(&exported, &config)?, 13);
+
+        [
+            "profiles/Default/modlist.txt",
+            "mods/A/plugin.esp",
+            "mods/B/empty.txt",
+            "mods/B/textures/big.dds",
+        ]
+        .iter()
+        .try_for_each(|path| {
+            std::fs::read(source.join(path))
+                .and_then(|expected| std::fs::read(target.join(path)).map(|found| (expected, found)))
+                .map(|(expected, found)| assert!(expected == found, "[{path}] differs after import"))
+        })?;
+        assert!(target.join(format::MANIFEST_FILE_NAME).exists());
+        assert!(!target.join(IMPORT_PROGRESS_FILE_NAME).exists());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_path_adaptation() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let (source, exported, target) = (
+            directory.path().join("source"),
+            directory.path().join("export"),
+            directory.path().join("target"),
+        );
+        fixture(&source)?;
+        export_fixture(&source, &exported)?;
+        import(&exported, &config(&target, &directory.path().join("downloads")))?;
+
+        let ini = std::fs::read_to_string(target.join("ModOrganizer.ini")).map(|ini| IniDocument::parse(&ini))?;
+        let base = wine_path(&target)?;
+        assert_eq!(ini.get("Settings", "base_directory"), Some(base.as_str()));
+        assert_eq!(ini.get("Settings", "mod_directory"), Some(format!("{base}/mods").as_str()));
+        assert_eq!(
+            ini.get("Settings", "download_directory"),
+            Some(wine_path(&directory.path().join("downloads"))?.as_str())
+        );
+        assert!(target.join("portable.txt").exists());
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_export_resumes_by_chunk() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let (source, exported) = (directory.path().join("source"), directory.path().join("export"));
+        fixture(&source)?;
+        export_fixture(&source, &exported)?;
+        std::fs::remove_file(chunk_path(&exported, 4))?;
+        // cut short, e.g. by a copy that got interrupted
+        std::fs::write(chunk_path(&exported, 7), b"partial")?;
+        assert_eq!(export_fixture(&source, &exported)?, 2);
+        assert_eq!(export_fixture(&source, &exported)?, 0);
+
+        // a different installation can't reuse the chunks
+        std::fs::write(source.join("mods/A/new.esp"), b"new")?;
+        assert_eq!(export_fixture(&source, &exported)?, 13);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_import_verifies_and_resumes_by_chunk() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let (source, exported, target) = (
+            directory.path().join("source"),
+            directory.path().join("export"),
+            directory.path().join("target"),
+        );
+        fixture(&source)?;
+        export_fixture(&source, &exported)?;
+        let config = config(&target, &directory.path().join("downloads"));
+
+        let corrupted = chunk_path(&exported, 3);
+        let intact = std::fs::read(&corrupted)?;
+        std::fs::write(&corrupted, intact.clone().tap_mut(|chunk| chunk[10] ^= 0xff))?;
+        let not_there_yet = chunk_path(&exported, 9);
+        let in_flight = std::fs::read(&not_there_yet)?;
+        std::fs::write(&not_there_yet, &in_flight[..100])?;
+
+        let error = import(&exported, &config)
+            .err()
+            .expect("corrupted and missing chunks must fail the import");
+        assert!(format!("{error:?}").contains("[2] chunks are not imported yet ([1] corrupted)"), "{error:?}");
+        assert!(!target.join(format::MANIFEST_FILE_NAME).exists());
+
+        std::fs::write(&corrupted, intact)?;
+        std::fs::write(&not_there_yet, in_flight)?;
+        assert_eq!(import(&exported, &config)?, 2);
+        assert_eq!(
+            std::fs::read(target.join("mods/B/textures/big.dds"))?,
+            std::fs::read(source.join("mods/B/textures/big.dds"))?
+        );
+        Ok(())
+    }
+}
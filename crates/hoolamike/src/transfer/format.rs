@@ -0,0 +1,334 @@
+//! on-disk format of an export: every installed file is concatenated (in manifest order) into a single stream,
+//! which is then split into fixed-size chunks. Each chunk is hashed separately, so that a transfer can be verified
+//! (and resumed) chunk by chunk without caring which files it happens to cover.
+
+use {
+    crate::{install_modlist::download_cache::to_base_64_from_u64, modlist_json::GameName},
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        hash::Hasher,
+        io::{self, Read, Write},
+        ops::Range,
+        path::{Path, PathBuf},
+    },
+    tap::prelude::*,
+};
+
+pub const INDEX_FILE_NAME: &str = "hoolamike-export.json";
+pub const CHUNKS_DIRECTORY: &str = "chunks";
+pub const FORMAT_VERSION: u32 = 1;
+
+/// written into the installation after import
+pub const MANIFEST_FILE_NAME: &str = ".hoolamike-manifest.json";
+/// tracks chunks already written into the installation by an interrupted import
+pub const IMPORT_PROGRESS_FILE_NAME: &str = ".hoolamike-import.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// relative to the installation root, always separated with '/'
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallationManifest {
+    /// needed to adapt the installation on the target machine, where the .wabbajack file might not be around
+    pub game: Option<GameName>,
+    pub files: Vec<ManifestFile>,
+}
+
+impl InstallationManifest {
+    /// every file in the installation, sorted by path (hoolamike's own bookkeeping and temporary files are skipped)
+    pub fn collect(installation_path: &Path, game: Option<GameName>) -> Result<Self> {
+        walkdir::WalkDir::new(installation_path)
+            .sort_by_file_name()
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() != 1
+                    || ![MANIFEST_FILE_NAME, IMPORT_PROGRESS_FILE_NAME, crate::temp_directory::DEFAULT_DIRECTORY_NAME]
+                        .contains(&entry.file_name().to_string_lossy().as_ref())
+            })
+            .filter(|entry| match entry {
+                Ok(entry) => entry.file_type().is_file(),
+                Err(_) => true,
+            })
+            .map(|entry| {
+                entry.context("walking installation").and_then(|entry| {
+                    entry
+                        .path()
+                        .strip_prefix(installation_path)
+                        .context("file outside of installation")
+                        .and_then(|relative| {
+                            relative
+                                .components()
+                                .map(|component| {
+                                    component
+                                        .as_os_str()
+                                        .to_str()
+                                        .with_context(|| format!("[{}] is not valid utf8", relative.display()))
+                                })
+                                .collect::<Result<Vec<_>>>()
+                                .map(|components| components.join("/"))
+                        })
+                        .and_then(|path| {
+                            entry
+                                .metadata()
+                                .context("reading metadata")
+                                .map(|metadata| ManifestFile { path, size: metadata.len() })
+                        })
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|files| Self { game, files })
+            .with_context(|| format!("collecting installation manifest of [{}]", installation_path.display()))
+    }
+
+    pub fn write(&self, installation_path: &Path) -> Result<()> {
+        write_json_atomically(&installation_path.join(MANIFEST_FILE_NAME), self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRecord {
+    pub size: u64,
+    /// wabbajack (xxhash64) hash, base64 encoded
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportIndex {
+    pub version: u32,
+    pub chunk_size: u64,
+    pub manifest: InstallationManifest,
+    /// keyed by chunk index, filled in as chunks get written
+    pub chunks: BTreeMap<usize, ChunkRecord>,
+}
+
+/// part of a single file covered by a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// index into [InstallationManifest::files]
+    pub file: usize,
+    /// offset within the file
+    pub offset: u64,
+    pub length: u64,
+}
+
+pub fn chunk_file_name(index: usize) -> String {
+    format!("chunk-{index:06}.bin")
+}
+
+pub fn chunk_path(export_directory: &Path, index: usize) -> PathBuf {
+    export_directory
+        .join(CHUNKS_DIRECTORY)
+        .join(chunk_file_name(index))
+}
+
+fn write_json_atomically<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    serde_json::to_string_pretty(value)
+        .context("serializing")
+        .and_then(|serialized| crate::utils::write_atomically(path, serialized))
+}
+
+pub fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("reading [{}]", path.display()))
+        .and_then(|contents| serde_json::from_str(&contents).with_context(|| format!("parsing [{}]", path.display())))
+}
+
+impl ExportIndex {
+    pub fn new(manifest: InstallationManifest, chunk_size: u64) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            chunk_size,
+            manifest,
+            chunks: Default::default(),
+        }
+    }
+
+    pub fn read(export_directory: &Path) -> Result<Self> {
+        read_json(&export_directory.join(INDEX_FILE_NAME))
+    }
+
+    pub fn write(&self, export_directory: &Path) -> Result<()> {
+        write_json_atomically(&export_directory.join(INDEX_FILE_NAME), self)
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.manifest.files.iter().map(|file| file.size).sum()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.total_size().div_ceil(self.chunk_size) as usize
+    }
+
+    pub fn chunk_range(&self, index: usize) -> Range<u64> {
+        let start = index as u64 * self.chunk_size;
+        start..(start + self.chunk_size).min(self.total_size())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        (0..self.chunk_count()).all(|index| self.chunks.contains_key(&index))
+    }
+
+    /// chunks recorded for a different set of files (or chunk size) don't line up with the current ones
+    pub fn same_layout(&self, other: &Self) -> bool {
+        self.version == other.version && self.chunk_size == other.chunk_size && self.manifest == other.manifest
+    }
+
+    /// every file overlapping the range, in stream order
+    pub fn segments(&self, range: Range<u64>) -> Vec<Segment> {
+        self.manifest
+            .files
+            .iter()
+            .scan(0, |start, file| {
+                let file_range = *start..(*start + file.size);
+                *start = file_range.end;
+                Some(file_range)
+            })
+            .enumerate()
+            .filter_map(|(file, file_range)| {
+                let overlap = file_range.start.max(range.start)..file_range.end.min(range.end);
+                (overlap.start < overlap.end).then(|| Segment {
+                    file,
+                    offset: overlap.start - file_range.start,
+                    length: overlap.end - overlap.start,
+                })
+            })
+            .collect()
+    }
+
+    /// identifies the export an import progress belongs to
+    pub fn digest(&self) -> Result<String> {
+        serde_json::to_vec(self)
+            .context("serializing export index")
+            .map(|serialized| xxhash_rust::xxh64::xxh64(&serialized, 0).pipe(to_base_64_from_u64))
+    }
+}
+
+/// like [std::io::copy], but feeds everything copied into the hasher
+pub fn copy_hashed<R: Read, W: Write>(mut reader: R, mut writer: W, hasher: &mut xxhash_rust::xxh64::Xxh64) -> io::Result<u64> {
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut copied = 0;
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => {
+                writer.write_all(&buffer[..read])?;
+                hasher.update(&buffer[..read]);
+                copied += read as u64;
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+pub fn finish_hash(hasher: &xxhash_rust::xxh64::Xxh64) -> String {
+    hasher.finish().pipe(to_base_64_from_u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(sizes: &[u64], chunk_size: u64) -> ExportIndex {
+        InstallationManifest {
+            game: None,
+            files: sizes
+                .iter()
+                .enumerate()
+                .map(|(idx, size)| ManifestFile {
+                    path: format!("file-{idx}"),
+                    size: *size,
+                })
+                .collect(),
+        }
+        .pipe(|manifest| ExportIndex::new(manifest, chunk_size))
+    }
+
+    #[test_log::test]
+    fn test_chunk_layout() {
+        let index = index(&[5, 0, 12, 3], 8);
+        assert_eq!(index.total_size(), 20);
+        assert_eq!(index.chunk_count(), 3);
+        assert_eq!(index.chunk_range(2), 16..20);
+        assert!(!index.is_complete());
+        assert_eq!(self::index(&[], 8).chunk_count(), 0);
+        assert!(self::index(&[0, 0], 8).is_complete());
+    }
+
+    #[test_log::test]
+    fn test_segments_cross_file_boundaries() {
+        let index = index(&[5, 0, 12, 3], 8);
+        assert_eq!(
+            index.segments(index.chunk_range(0)),
+            [Segment { file: 0, offset: 0, length: 5 }, Segment { file: 2, offset: 0, length: 3 },]
+        );
+        assert_eq!(index.segments(index.chunk_range(1)), [Segment { file: 2, offset: 3, length: 8 }]);
+        assert_eq!(
+            index.segments(index.chunk_range(2)),
+            [
+                Segment {
+                    file: 2,
+                    offset: 11,
+                    length: 1
+                },
+                Segment { file: 3, offset: 0, length: 3 },
+            ]
+        );
+        // every byte is covered exactly once
+        assert_eq!(
+            (0..index.chunk_count())
+                .flat_map(|chunk| index.segments(index.chunk_range(chunk)))
+                .map(|segment| segment.length)
+                .sum::<u64>(),
+            index.total_size()
+        );
+    }
+
+    #[test_log::test]
+    fn test_layout_and_digest_change_with_files() -> Result<()> {
+        let (a, b) = (index(&[5, 12], 8), index(&[5, 13], 8));
+        assert!(a.same_layout(&a.clone().tap_mut(|a| {
+            a.chunks.insert(
+                0,
+                ChunkRecord {
+                    size: 8,
+                    hash: "x".to_string(),
+                },
+            );
+        })));
+        assert!(!a.same_layout(&b));
+        assert!(!a.same_layout(&index(&[5, 12], 4)));
+        assert_ne!(a.digest()?, b.digest()?);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_manifest_skips_bookkeeping_files() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        std::fs::create_dir_all(directory.path().join("mods/a"))?;
+        std::fs::write(directory.path().join("mods/a/plugin.esp"), b"plugin")?;
+        std::fs::write(directory.path().join("ModOrganizer.ini"), b"ini")?;
+        std::fs::write(directory.path().join(MANIFEST_FILE_NAME), b"{}")?;
+        std::fs::write(directory.path().join(IMPORT_PROGRESS_FILE_NAME), b"{}")?;
+        assert_eq!(
+            InstallationManifest::collect(directory.path(), None)?.files,
+            [
+                ManifestFile {
+                    path: "ModOrganizer.ini".to_string(),
+                    size: 3,
+                },
+                ManifestFile {
+                    path: "mods/a/plugin.esp".to_string(),
+                    size: 6,
+                },
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+//! `install --tui` - a full screen view of the installation: totals and ETA of every phase, the tree of what's running (its
+//! branches can be folded) and the logs below it. it's fed by the same [hoola_progress] messages the gui gets (see
+//! [crate::progress_bars_v2::bridge]), the installation itself runs on a thread of its own
+
+pub mod logs;
+
+use {
+    crate::{
+        error::TotalResult,
+        errors_log::installation_failed,
+        install_modlist::cancellation,
+        progress_bars_v2::{amount, bridge},
+    },
+    anyhow::{Context, Result},
+    futures::{FutureExt, StreamExt},
+    hoola_progress::{Aggregation, ProgressMap, ProgressMessage, SpanPath, stream_compat::ReceiverStream},
+    itertools::Itertools,
+    logs::LogLine,
+    ratatui::{
+        DefaultTerminal,
+        Frame,
+        crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        layout::{Constraint, Layout},
+        style::{Style, Stylize},
+        text::Line,
+        widgets::{Block, Gauge, List, ListState, Paragraph},
+    },
+    std::{collections::BTreeSet, sync::mpsc::Receiver, time::Duration},
+    tap::prelude::*,
+    tracing::Level,
+};
+
+/// the view is redrawn at least this often
+const TICK: Duration = Duration::from_millis(100);
+/// phases past these are still in the tree
+const MAX_PHASES: usize = 6;
+const LOG_PANE_HEIGHT: u16 = 12;
+const HELP: &str = "↑/↓ select  ←/→/enter fold  pgup/pgdn/end scroll logs  v verbosity  p pause  q quit";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    /// cancelled, waiting for the work in flight
+    Pausing,
+    /// like [Status::Pausing], but the view is left once the installation stops
+    Quitting,
+    Finished(String),
+}
+
+pub struct InstallView {
+    pub progress: ProgressMap,
+    pub status: Status,
+    folded: BTreeSet<SpanPath>,
+    selected: Option<SpanPath>,
+    /// log lines scrolled up from the newest one
+    log_scroll: usize,
+}
+
+impl InstallView {
+    pub fn new(progress: ProgressMap) -> Self {
+        Self {
+            progress,
+            status: Status::Running,
+            folded: Default::default(),
+            selected: None,
+            log_scroll: 0,
+        }
+    }
+
+    /// spans which finished stop being folded, a selected one hands the selection over to its closest parent still running
+    pub fn handle(&mut self, messages: impl IntoIterator<Item = ProgressMessage>) {
+        messages
+            .into_iter()
+            .for_each(|message| self.progress.handle(message));
+        let running = &self.progress.progress;
+        self.folded.retain(|path| running.contains_key(path));
+        self.selected = self
+            .selected
+            .take()
+            .and_then(|selected| std::iter::successors(Some(selected), SpanPath::parent).find(|path| !path.is_empty() && running.contains_key(path)));
+    }
+
+    fn is_hidden(&self, path: &SpanPath) -> bool {
+        std::iter::successors(path.parent(), SpanPath::parent).any(|parent| self.folded.contains(&parent))
+    }
+
+    /// spans shown in the tree, the root only collects the phases
+    pub fn rows(&self) -> Vec<&SpanPath> {
+        self.progress
+            .progress
+            .keys()
+            .filter(|path| !path.is_empty() && !self.is_hidden(path))
+            .collect()
+    }
+
+    fn selected_row(&self, rows: &[&SpanPath]) -> Option<usize> {
+        self.selected
+            .as_ref()
+            .and_then(|selected| rows.iter().position(|row| *row == selected))
+    }
+
+    fn select(&mut self, by: isize) {
+        let rows = self.rows();
+        let selected = match self.selected_row(&rows) {
+            Some(row) => row
+                .saturating_add_signed(by)
+                .min(rows.len().saturating_sub(1)),
+            None => 0,
+        }
+        .pipe(|row| rows.get(row).map(|path| (*path).clone()));
+        self.selected = selected;
+    }
+
+    /// spans with nothing below them can't be folded
+    fn fold(&mut self, fold: impl FnOnce(bool) -> bool) {
+        if let Some(selected) = self
+            .selected
+            .clone()
+            .filter(|selected| self.progress.has_children(selected))
+        {
+            match fold(self.folded.contains(&selected)) {
+                true => self.folded.insert(selected),
+                false => self.folded.remove(&selected),
+            };
+        }
+    }
+
+    fn scroll_logs(&mut self, by: isize) {
+        self.log_scroll = self
+            .log_scroll
+            .saturating_add_signed(by)
+            .min(logs::len().saturating_sub(1));
+    }
+
+    fn pause(&mut self) {
+        if self.status == Status::Running {
+            cancellation::cancel();
+            self.status = Status::Pausing;
+        }
+    }
+
+    /// the first quit stops the installation and leaves once it's done, the second one leaves right away
+    fn quit(&mut self) -> bool {
+        match self.status {
+            Status::Running => {
+                cancellation::cancel();
+                self.status = Status::Quitting;
+                false
+            }
+            Status::Pausing => {
+                self.status = Status::Quitting;
+                false
+            }
+            Status::Quitting | Status::Finished(_) => true,
+        }
+    }
+
+    /// true once the view should be left
+    pub fn key(&mut self, key: KeyEvent) -> bool {
+        let page = (LOG_PANE_HEIGHT / 2) as isize;
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return self.quit(),
+            KeyCode::Char('q') | KeyCode::Esc => return self.quit(),
+            KeyCode::Char('p') => self.pause(),
+            KeyCode::Char('v') => logs::set_verbosity(logs::verbosity().next()),
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Left | KeyCode::Char('h') => self.fold(|_| true),
+            KeyCode::Right | KeyCode::Char('l') => self.fold(|_| false),
+            KeyCode::Enter | KeyCode::Char(' ') => self.fold(|folded| !folded),
+            KeyCode::PageUp => self.scroll_logs(page),
+            KeyCode::PageDown => self.scroll_logs(-page),
+            KeyCode::End => self.log_scroll = 0,
+            _ => {}
+        }
+        false
+    }
+
+    pub fn finish<T>(&mut self, result: &TotalResult<T>) {
+        self.status = Status::Finished(match result {
+            Ok(_) => "installation finished successfully".to_string(),
+            Err(_) if cancellation::is_cancelled() => "installation was paused, run the same command again to resume".to_string(),
+            Err(errors) => format!("{}, they're listed once the view is left", installation_failed(errors)),
+        });
+    }
+
+    fn phase(&self, path: &SpanPath) -> Gauge<'static> {
+        let (current, total) = self
+            .progress
+            .displayed(path)
+            .map_or((0, 0), |(_, state)| (state.current, state.total));
+        let name = self
+            .progress
+            .progress
+            .get(path)
+            .map(|span| span.name.to_string())
+            .unwrap_or_default();
+        Gauge::default()
+            .ratio(match total > 0 {
+                true => (current as f64 / total as f64).clamp(0., 1.),
+                false => 0.,
+            })
+            .label(format!("{name} {}", amount(&self.progress, path)))
+            .gauge_style(Style::new().blue())
+    }
+
+    fn row(&self, path: &SpanPath) -> String {
+        let marker = match (self.progress.has_children(path), self.folded.contains(path)) {
+            (false, _) => "  ",
+            (true, false) => "▾ ",
+            (true, true) => "▸ ",
+        };
+        let name = self
+            .progress
+            .progress
+            .get(path)
+            .map(|span| span.name.to_string())
+            .unwrap_or_default();
+        format!("{}{marker}{name}  {}", "  ".repeat(path.len().saturating_sub(1)), amount(&self.progress, path))
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let phases = self
+            .progress
+            .progress
+            .keys()
+            .filter(|path| path.len() == 1)
+            .take(MAX_PHASES)
+            .collect_vec();
+        let [status, phases_area, tree, log_pane, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(phases.len() as u16),
+            Constraint::Min(3),
+            Constraint::Length(LOG_PANE_HEIGHT),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        match &self.status {
+            Status::Running => "installing...".to_string(),
+            Status::Pausing => "pausing, waiting for the running tasks to stop...".to_string(),
+            Status::Quitting => "quitting, waiting for the running tasks to stop (q again to leave right away)...".to_string(),
+            Status::Finished(summary) => format!("{summary} - q to leave"),
+        }
+        .pipe(|status| Line::from(status).bold())
+        .pipe(|line| frame.render_widget(line, status));
+
+        Layout::vertical(phases.iter().map(|_| Constraint::Length(1)))
+            .split(phases_area)
+            .iter()
+            .zip(&phases)
+            .for_each(|(area, path)| frame.render_widget(self.phase(path), *area));
+
+        let rows = self.rows();
+        let mut tree_state = ListState::default().with_selected(self.selected_row(&rows));
+        rows.iter()
+            .map(|path| self.row(path))
+            .collect::<List>()
+            .block(Block::bordered().title(format!("running [{}]", rows.len())))
+            .highlight_style(Style::new().reversed())
+            .pipe(|list| frame.render_stateful_widget(list, tree, &mut tree_state));
+
+        logs::tail(log_pane.height.saturating_sub(2) as usize, self.log_scroll)
+            .into_iter()
+            .map(|LogLine { level, text }| {
+                Line::styled(
+                    text,
+                    match level {
+                        Level::ERROR => Style::new().red(),
+                        Level::WARN => Style::new().yellow(),
+                        Level::INFO => Style::new(),
+                        _ => Style::new().dark_gray(),
+                    },
+                )
+            })
+            .collect_vec()
+            .pipe(Paragraph::new)
+            .block(Block::bordered().title(match self.log_scroll {
+                0 => format!("logs ({})", logs::verbosity().name()),
+                scrolled => format!("logs ({}, [{scrolled}] lines up)", logs::verbosity().name()),
+            }))
+            .pipe(|logs| frame.render_widget(logs, log_pane));
+
+        frame.render_widget(Line::from(HELP).dim(), help);
+    }
+}
+
+/// [None] when the view was left before the installation stopped
+fn event_loop<T>(
+    terminal: &mut DefaultTerminal,
+    mut view: InstallView,
+    mut messages: ReceiverStream<ProgressMessage>,
+    finished: Receiver<TotalResult<T>>,
+) -> Result<Option<TotalResult<T>>> {
+    let mut outcome = None;
+    loop {
+        view.handle(std::iter::from_fn(|| messages.next().now_or_never().flatten()));
+        if let Some(result) = outcome
+            .is_none()
+            .then(|| finished.try_recv().ok())
+            .flatten()
+        {
+            let quitting = view.status == Status::Quitting;
+            view.finish(&result);
+            outcome = Some(result);
+            if quitting {
+                return Ok(outcome);
+            }
+        }
+        terminal
+            .draw(|frame| view.render(frame))
+            .context("drawing the view")?;
+        let leave = match event::poll(TICK).context("waiting for keys")? {
+            true => match event::read().context("reading keys")? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => view.key(key),
+                _ => false,
+            },
+            false => false,
+        };
+        if leave {
+            return Ok(outcome);
+        }
+    }
+}
+
+/// runs `install` on a thread of its own and shows its progress until it's done and the view is left. logs go to the view
+/// in the meantime, the keys are read by it (ctrl-c included)
+pub fn run<T: Send + 'static>(install: impl FnOnce() -> TotalResult<T> + Send + 'static) -> TotalResult<T> {
+    let mut terminal = ratatui::try_init()
+        .context("setting up the terminal for --tui")
+        .map_err(|reason| vec![reason])?;
+    logs::capture();
+    let (mut progress, messages, root) = ProgressMap::new();
+    // the phases show the bytes downloaded/written under them, same as in the gui
+    progress.set_aggregation(Aggregation::Bytes);
+    bridge::attach(root);
+    let (finished_tx, finished) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        install().pipe(|result| {
+            bridge::detach();
+            finished_tx.send(result).ok()
+        })
+    });
+    let outcome = event_loop(&mut terminal, InstallView::new(progress), messages, finished);
+    ratatui::restore();
+    logs::release();
+    outcome
+        .map_err(|reason| vec![reason])
+        .and_then(|outcome| outcome.unwrap_or_else(|| Err(vec![cancellation::cancelled_error()])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(view: &mut InstallView, code: KeyCode) {
+        assert!(!view.key(KeyEvent::from(code)), "nothing should leave the view");
+    }
+
+    fn names(view: &InstallView) -> Vec<String> {
+        view.rows()
+            .into_iter()
+            .map(|path| view.progress.progress[path].name.to_string())
+            .collect()
+    }
+
+    #[test_log::test]
+    fn test_branches_fold() {
+        let (progress, mut messages, root) = ProgressMap::new();
+        let mut view = InstallView::new(progress);
+        let downloads = root.child("sync_downloads");
+        let archive = downloads.child("a.7z");
+        let directives = root.child("directives");
+        messages.close();
+        view.handle(futures::executor::block_on(messages.by_ref().collect::<Vec<_>>()));
+        assert_eq!(names(&view), ["sync_downloads", "a.7z", "directives"]);
+
+        press(&mut view, KeyCode::Down);
+        press(&mut view, KeyCode::Left);
+        assert_eq!(names(&view), ["sync_downloads", "directives"]);
+        assert!(view.row(view.rows()[0]).starts_with("▸ sync_downloads"));
+        // nothing below it
+        press(&mut view, KeyCode::Down);
+        press(&mut view, KeyCode::Left);
+        assert_eq!(names(&view), ["sync_downloads", "directives"]);
+        press(&mut view, KeyCode::Up);
+        press(&mut view, KeyCode::Enter);
+        assert_eq!(names(&view), ["sync_downloads", "a.7z", "directives"]);
+        assert!(view.row(view.rows()[1]).starts_with("    a.7z"));
+        drop((archive, downloads, directives));
+    }
+}
@@ -0,0 +1,175 @@
+//! while the tui is up, log lines land in its pane instead of being written over it. the pane keeps the newest [MAX_LINES],
+//! `v` switches between the levels it takes in - lines left out before are not brought back, the log file has them all
+
+use {
+    parking_lot::Mutex,
+    std::{
+        collections::VecDeque,
+        fmt::{Debug, Write},
+        sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    },
+    tap::prelude::*,
+    tracing::{
+        Event,
+        Level,
+        Metadata,
+        Subscriber,
+        field::{Field, Visit},
+        level_filters::LevelFilter,
+    },
+    tracing_subscriber::{
+        filter::{DynFilterFn, dynamic_filter_fn},
+        layer::Context,
+    },
+};
+
+pub const MAX_LINES: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Warnings,
+    Info,
+    Debug,
+}
+
+impl Verbosity {
+    const ALL: [Self; 3] = [Self::Warnings, Self::Info, Self::Debug];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn level(self) -> Level {
+        match self {
+            Self::Warnings => Level::WARN,
+            Self::Info => Level::INFO,
+            Self::Debug => Level::DEBUG,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Warnings => "warnings",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Info as u8);
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+static LINES: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+pub fn verbosity() -> Verbosity {
+    Verbosity::ALL[VERBOSITY.load(Ordering::Relaxed) as usize]
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// lines go to the pane from now on
+pub fn capture() {
+    CAPTURING.store(true, Ordering::Relaxed);
+}
+
+/// lines go to stderr again, the pane is emptied
+pub fn release() {
+    CAPTURING.store(false, Ordering::Relaxed);
+    LINES.lock().clear();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub level: Level,
+    pub text: String,
+}
+
+pub fn len() -> usize {
+    LINES.lock().len()
+}
+
+/// oldest first, `count` lines ending `scrolled` lines above the newest one
+pub fn tail(count: usize, scrolled: usize) -> Vec<LogLine> {
+    LINES
+        .lock()
+        .iter()
+        .rev()
+        .skip(scrolled)
+        .take(count)
+        .cloned()
+        .collect::<Vec<_>>()
+        .tap_mut(|lines| lines.reverse())
+}
+
+fn push(level: Level, message: &str) {
+    let mut lines = LINES.lock();
+    message
+        .lines()
+        .for_each(|line| lines.push_back(LogLine { level, text: line.to_string() }));
+    let excess = lines.len().saturating_sub(MAX_LINES);
+    lines.drain(..excess);
+}
+
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.0.insert_str(0, &format!("{value:?}")),
+            name => write!(self.0, " {name}={value:?}").expect("writing to a string"),
+        }
+    }
+}
+
+pub struct PaneLayer;
+
+impl<S: Subscriber> tracing_subscriber::Layer<S> for PaneLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        let Message(message) = Message::default().tap_mut(|message| event.record(message));
+        match CAPTURING.load(Ordering::Relaxed) {
+            true => push(level, &message),
+            false => eprintln!("{level} {message}"),
+        }
+    }
+}
+
+/// what reaches [PaneLayer], follows [verbosity]
+pub fn filter<S: Subscriber>() -> DynFilterFn<S, impl Fn(&Metadata<'_>, &Context<'_, S>) -> bool> {
+    dynamic_filter_fn(|metadata: &Metadata<'_>, _: &Context<'_, S>| metadata.is_event() && *metadata.level() <= verbosity().level())
+        .with_max_level_hint(LevelFilter::DEBUG)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        tracing_subscriber::{Layer, Registry, layer::SubscriberExt},
+    };
+
+    #[test_log::test]
+    fn test_lines_are_taken_in_at_the_chosen_verbosity() {
+        let subscriber = Registry::default().with(PaneLayer.with_filter(filter()));
+        tracing::subscriber::with_default(subscriber, || {
+            capture();
+            tracing::info!(archive = "a.7z", "downloading");
+            tracing::debug!("left out");
+            set_verbosity(verbosity().next());
+            tracing::debug!("first\nsecond");
+            let texts = |lines: Vec<LogLine>| {
+                lines
+                    .into_iter()
+                    .map(|LogLine { text, .. }| text)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(texts(tail(10, 0)), [r#"downloading archive="a.7z""#, "first", "second"]);
+            assert_eq!(texts(tail(1, 1)), ["first"]);
+            assert_eq!(tail(10, 0)[0].level, Level::INFO);
+            set_verbosity(Verbosity::Info);
+            release();
+            assert_eq!(len(), 0);
+        });
+    }
+}
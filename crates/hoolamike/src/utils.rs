@@ -160,6 +160,11 @@ where
             })
             .with_context(|| format!("opening file for writing at {self:?}"))
     }
+
+    /// writes through a partial file renamed into place once complete, see [crate::atomic_write]
+    fn write_atomically<O>(&self, write: impl FnOnce(&mut std::fs::File) -> anyhow::Result<O>) -> anyhow::Result<O> {
+        crate::atomic_write::write_atomically(self.as_ref(), write)
+    }
 }
 
 // #[tracing::instrument(skip(task_fn))]
@@ -203,7 +208,7 @@ fn test_chunk_while() {
 pub fn scoped_temp_file() -> anyhow::Result<NamedTempFile> {
     tempfile::Builder::new()
         .prefix("seeked-file-")
-        .tempfile_in(*crate::consts::TEMP_FILE_DIR)
+        .tempfile_in(crate::temp_directory::run_directory()?)
         .context("creating temp file")
 }
 
@@ -218,6 +223,17 @@ pub fn with_scoped_temp_path<T, F: FnOnce(&TempPath) -> anyhow::Result<T>>(with:
         .context("performing operation on a scoped temp file")
 }
 
+/// writes next to the target first and renames it over, so that an interrupted write never leaves a truncated file behind
+pub fn write_atomically(path: &std::path::Path, contents: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let temp = path
+        .as_os_str()
+        .to_owned()
+        .tap_mut(|temp| temp.push(".tmp"));
+    std::fs::write(&temp, contents)
+        .and_then(|_| std::fs::rename(&temp, path))
+        .with_context(|| format!("writing [{}]", path.display()))
+}
+
 pub fn deserialize_json_with_error_location<T: serde::de::DeserializeOwned>(text: &str) -> anyhow::Result<T> {
     serde_json::from_str(text)
         .pipe(|res| {
@@ -290,6 +306,7 @@ impl<P: AsRef<Path>> P {
                 Cow::Owned(o) => o.as_str(),
             }
         }
+        let temp_directory = crate::temp_directory::run_directory()?;
         (
             self.file_stem_opt().unwrap_or(Cow::Borrowed("unnamed")),
             self.extension_opt().map(|ext| format!(".{ext}")),
@@ -302,13 +319,8 @@ impl<P: AsRef<Path>> P {
                             b.suffix(extension);
                         }
                     })
-                    .tempfile_in(*crate::consts::TEMP_FILE_DIR)
-                    .with_context(|| {
-                        format!(
-                            "creating temp file in {} (prefix: {stem}, suffix: .{extension:?})",
-                            crate::consts::TEMP_FILE_DIR.display()
-                        )
-                    })
+                    .tempfile_in(temp_directory)
+                    .with_context(|| format!("creating temp file in {} (prefix: {stem}, suffix: .{extension:?})", temp_directory.display()))
             })
     }
 }
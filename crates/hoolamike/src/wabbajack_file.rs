@@ -1,59 +1,238 @@
 use {
-    crate::{compression::ProcessArchive, install_modlist::directives::wabbajack_file_handle::WabbajackFileHandle, utils::ExistingPathRead},
+    crate::{
+        compression::ProcessArchive,
+        install_modlist::directives::wabbajack_file_handle::WabbajackFileHandle,
+        modlist_json::{Modlist, format_version, parsing_helpers},
+        progress_bars_v2::IndicatifWrapIoExt,
+        utils::ExistingPathRead,
+    },
     anyhow::{Context, Result},
     case_insensitive_path::{CaseInsensitivePathBuf, ExistingPath, ExistingPathBuf},
-    std::{io::Read, str::FromStr},
+    modlist_cache::{CacheKey, ModlistCache},
+    std::{
+        io::{BufReader, Read},
+        path::{Path, PathBuf},
+    },
     tap::prelude::*,
+    tracing::{info, warn},
 };
 
+pub mod modlist_cache;
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct WabbajackFile {
     pub wabbajack_file_path: ExistingPathBuf,
     pub wabbajack_entries: Vec<CaseInsensitivePathBuf>,
-    pub modlist: super::modlist_json::Modlist,
+    pub modlist: Modlist,
 }
 
 const MODLIST_JSON_FILENAME: &str = "modlist";
 
 impl WabbajackFile {
-    #[tracing::instrument]
-    pub fn load_modlist_json(at_path: &ExistingPath) -> Result<Self> {
+    /// opens the `modlist` entry of the .wabbajack file (matching its name case-insensitively) and hands it to `read`
+    /// together with its uncompressed size
+    fn with_modlist_entry<T>(at_path: &ExistingPath, read: impl FnOnce(&mut dyn Read, u64) -> Result<T>) -> Result<T> {
         at_path
             .open_file_read()
-            .and_then(|(_, file)| crate::compression::compress_tools::ArchiveHandle::new(file))
-            .context("reading archive")
+            .and_then(|(_, file)| ::zip::ZipArchive::new(file).context("opening file as zip"))
             .and_then(|mut archive| {
-                archive.list_paths().and_then(|entries| {
-                    archive
-                        .get_handle(
-                            &MODLIST_JSON_FILENAME
-                                .pipe(CaseInsensitivePathBuf::from_str)
-                                .expect("bad modlist json filename"),
-                        )
-                        .context("looking up file by name")
-                        .and_then(|mut handle| {
-                            String::new()
-                                .pipe(|mut out| handle.read_to_string(&mut out).map(|_| out))
-                                .context("reading modlist json to string")
-                        })
-                        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).context("reading archive json contents"))
-                        .and_then(|json| {
-                            serde_json::to_string_pretty(&json)
-                                .context("serializing json")
-                                .and_then(|output| serde_json::from_str(&output).context("output is a valid json but not a valid modlist file"))
-                        })
-                        .with_context(|| format!("reading [{MODLIST_JSON_FILENAME}]"))
-                        .map(|modlist| Self {
-                            wabbajack_file_path: at_path.to_owned(),
-                            wabbajack_entries: entries,
-                            modlist,
-                        })
+                archive
+                    .file_names()
+                    .find(|name| name.eq_ignore_ascii_case(MODLIST_JSON_FILENAME))
+                    .map(ToOwned::to_owned)
+                    .with_context(|| format!("no [{MODLIST_JSON_FILENAME}] in archive"))
+                    .and_then(|name| {
+                        archive
+                            .by_name(&name)
+                            .with_context(|| format!("opening [{name}]"))
+                            .and_then(|mut entry| {
+                                let size = entry.size();
+                                read(&mut entry, size)
+                            })
+                    })
+            })
+    }
+
+    /// parse errors of a streamed modlist only know their position, the json around it is read again to show where it went wrong
+    fn describe_parse_error(at_path: &ExistingPath, error: serde_json::Error) -> anyhow::Error {
+        let (line, column) = (error.line(), error.column());
+        match error.is_io() {
+            true => anyhow::Error::new(error).context("reading modlist json"),
+            false => Self::with_modlist_entry(at_path, |entry, _| {
+                String::new().pipe(|mut out| {
+                    entry
+                        .read_to_string(&mut out)
+                        .map(|_| out)
+                        .context("reading modlist json to string")
+                })
+            })
+            .map(|json| {
+                (
+                    parsing_helpers::error_context(&json, line, column),
+                    format_version::describe(format_version::sniff(&json)),
+                )
+            })
+            .pipe(|context| match context {
+                Ok((context, version)) => {
+                    anyhow::Error::new(error).context(format!("not a valid modlist file (line {line}, column {column}, {version}):\n{context}"))
+                }
+                Err(reason) => anyhow::Error::new(error).context(format!(
+                    "not a valid modlist file (line {line}, column {column}), could not read the json around it: {reason:?}"
+                )),
+            }),
+        }
+    }
+
+    /// the modlist as cached in `cache` when it was already parsed out of this exact file, parsed (and cached) otherwise
+    #[tracing::instrument(skip(cache))]
+    pub fn load_modlist_json(at_path: &ExistingPath, cache: Option<&ModlistCache>) -> Result<Self> {
+        match cache {
+            None => Self::parse_modlist_json(at_path),
+            Some(cache) => {
+                let key = CacheKey::of(at_path.as_ref())
+                    .map_err(|reason| warn!("not caching the modlist: {reason:?}"))
+                    .ok();
+                match key.and_then(|key| cache.load(at_path.as_ref(), &key)) {
+                    Some((wabbajack_entries, modlist)) => Ok(Self {
+                        wabbajack_file_path: at_path.to_owned(),
+                        wabbajack_entries,
+                        modlist,
+                    })
+                    .tap_ok(|_| info!("loaded cached modlist")),
+                    None => Self::parse_modlist_json(at_path).tap_ok(|parsed| {
+                        if let Some(key) = key {
+                            cache
+                                .store(at_path.as_ref(), &key, &parsed.wabbajack_entries, &parsed.modlist)
+                                .unwrap_or_else(|reason| warn!("could not cache the modlist: {reason:?}"))
+                        }
+                    }),
+                }
+            }
+        }
+    }
+
+    #[tracing::instrument]
+    fn parse_modlist_json(at_path: &ExistingPath) -> Result<Self> {
+        crate::compression::zip::ZipArchive::new(at_path)
+            .and_then(|mut archive| archive.list_paths())
+            .context("reading archive")
+            .and_then(|entries| {
+                Self::with_modlist_entry(at_path, |entry, size| {
+                    tracing::Span::current()
+                        .wrap_read(size, entry)
+                        .pipe(BufReader::new)
+                        .pipe(serde_json::from_reader::<_, Modlist>)
+                        .pipe(Ok)
+                })
+                .and_then(|parsed| parsed.map_err(|error| Self::describe_parse_error(at_path, error)))
+                .with_context(|| format!("reading [{MODLIST_JSON_FILENAME}]"))
+                .map(|modlist| Self {
+                    wabbajack_file_path: at_path.to_owned(),
+                    wabbajack_entries: entries,
+                    modlist,
                 })
             })
     }
-    #[tracing::instrument(fields(at_path=%at_path))]
-    pub fn load_wabbajack_file(at_path: &ExistingPath) -> Result<(WabbajackFileHandle, Self)> {
-        Self::load_modlist_json(at_path).and_then(|data| WabbajackFileHandle::from_archive(at_path).map(|archive| (archive, data)))
+    /// copies the file into `state_directory` (unless an identical-looking copy is already there), returning the path of the copy
+    #[tracing::instrument]
+    pub fn copy_locally(wabbajack_file_path: &Path, state_directory: &Path) -> Result<PathBuf> {
+        let file_name = wabbajack_file_path
+            .file_name()
+            .context("wabbajack file must have a name")?;
+        let local = state_directory.join(file_name);
+        let size = |path: &Path| std::fs::metadata(path).map(|metadata| metadata.len()).ok();
+        match size(wabbajack_file_path).is_some() && size(wabbajack_file_path) == size(&local) {
+            true => Ok(local).tap_ok(|local| info!("using existing local copy at [{}]", local.display())),
+            false => std::fs::create_dir_all(state_directory)
+                .with_context(|| format!("creating [{}]", state_directory.display()))
+                .and_then(|_| {
+                    let temp = local.with_extension("wabbajack.tmp");
+                    std::fs::copy(wabbajack_file_path, &temp)
+                        .and_then(|_| std::fs::rename(&temp, &local))
+                        .with_context(|| format!("copying [{}] to [{}]", wabbajack_file_path.display(), local.display()))
+                })
+                .map(|_| local)
+                .tap_ok(|local| info!("copied the modlist file to [{}]", local.display())),
+        }
+    }
+    #[tracing::instrument(skip(cache), fields(at_path=%at_path))]
+    pub fn load_wabbajack_file(at_path: &ExistingPath, cache: Option<&ModlistCache>) -> Result<(WabbajackFileHandle, Self)> {
+        Self::load_modlist_json(at_path, cache).and_then(|data| WabbajackFileHandle::from_archive(at_path).map(|archive| (archive, data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    fn modlist_json(name: &str) -> String {
+        serde_json::json!({
+            "Archives": [],
+            "Author": "someone",
+            "Directives": [],
+            "GameType": "SkyrimSpecialEdition",
+            "IsNSFW": false,
+            "Name": name,
+            "Version": "1.0.0",
+            "WabbajackVersion": "3.7.0.0",
+        })
+        .to_string()
+    }
+
+    /// entries are written with zip64 extra fields and records (what wabbajack produces for files over 4GiB),
+    /// with the modlist in between inline data like in real modlists
+    fn zip64_wabbajack_file(at: &Path, modlist: &str) -> Result<ExistingPathBuf> {
+        let options = ::zip::write::SimpleFileOptions::default()
+            .compression_method(::zip::CompressionMethod::Stored)
+            .large_file(true);
+        std::fs::File::create(at)
+            .context("creating archive")
+            .map(::zip::ZipWriter::new)
+            .and_then(|mut writer| {
+                [
+                    ("3f2f8e64-8c5b-4b6a-9a3b-8f1b7e1a0c11", "inline data".as_bytes()),
+                    ("modlist", modlist.as_bytes()),
+                    ("modlist-image.png", b"not really a png".as_slice()),
+                ]
+                .into_iter()
+                .try_for_each(|(name, contents)| {
+                    writer
+                        .start_file(name, options)
+                        .context("starting entry")
+                        .and_then(|_| writer.write_all(contents).context("writing entry"))
+                })
+                .and_then(|_| writer.finish().context("finishing archive"))
+            })
+            .and_then(|_| ExistingPathBuf::new(at))
+    }
+
+    #[test_log::test]
+    fn test_modlist_is_streamed_out_of_zip64_archive() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let path = zip64_wabbajack_file(&directory.path().join("zip64.wabbajack"), &modlist_json("Zip64 Modlist"))?;
+        let WabbajackFile {
+            wabbajack_entries, modlist, ..
+        } = WabbajackFile::load_modlist_json(&path, None)?;
+        assert_eq!(modlist.name, "Zip64 Modlist");
+        assert_eq!(modlist.author, "someone");
+        assert_eq!(wabbajack_entries.len(), 3);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_parse_error_shows_surrounding_json() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let broken = modlist_json("Broken Modlist").replace(r#""IsNSFW":false"#, r#""IsNSFW":"maybe""#);
+        assert!(broken.contains("maybe"), "json layout changed: {broken}");
+        let path = zip64_wabbajack_file(&directory.path().join("broken.wabbajack"), &broken)?;
+        let error = WabbajackFile::load_modlist_json(&path, None)
+            .map(|_| ())
+            .expect_err("IsNSFW is not a bool");
+        let error = format!("{error:?}");
+        assert!(error.contains("line 1"), "{error}");
+        assert!(error.contains(r#""IsNSFW":"maybe""#), "shows the json around the error: {error}");
+        assert!(error.contains("made with Wabbajack [3.7.0.0]"), "{error}");
+        Ok(())
     }
 }
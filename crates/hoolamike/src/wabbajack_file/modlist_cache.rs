@@ -0,0 +1,227 @@
+//! parsed modlists are cached in the project root, so that loading a big modlist again (`modlist-info` before installing,
+//! every start of the GUI) doesn't parse the json all over. the cache is only ever a shortcut - when anything about it is
+//! off the modlist is parsed out of the .wabbajack file as if there was no cache
+
+use {
+    crate::modlist_json::Modlist,
+    anyhow::{Context, Result},
+    case_insensitive_path::CaseInsensitivePathBuf,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs::File,
+        io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+        time::UNIX_EPOCH,
+    },
+    tap::prelude::*,
+    tracing::{debug, warn},
+};
+
+pub const CACHE_DIRECTORY: &str = ".hoolamike-cache";
+/// bumped whenever the layout of a cache entry changes, entries written by other versions are treated as stale
+const CACHE_VERSION: u32 = 1;
+/// how much of the start and of the end of the .wabbajack file is hashed - the zip central directory (with the crc of every
+/// entry) lives at the end, so together with the size and mtime that's enough to tell the files apart without reading gigabytes
+const HASHED_EDGE: u64 = 1024 * 1024;
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// identifies the exact .wabbajack file a cache entry was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    size: u64,
+    modified_seconds: u64,
+    modified_nanos: u32,
+    xxh64: u64,
+}
+
+impl CacheKey {
+    pub fn of(wabbajack_file: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(wabbajack_file).context("reading metadata")?;
+        let modified = metadata
+            .modified()
+            .context("reading modification time")?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let size = metadata.len();
+        let mut edges = Vec::new();
+        File::open(wabbajack_file)
+            .context("opening file")
+            .and_then(|mut file| {
+                (&mut file)
+                    .take(HASHED_EDGE)
+                    .read_to_end(&mut edges)
+                    .and_then(|_| file.seek(SeekFrom::Start(size.saturating_sub(HASHED_EDGE).max(HASHED_EDGE.min(size)))))
+                    .and_then(|_| file.read_to_end(&mut edges))
+                    .context("reading file")
+            })
+            .map(|_| Self {
+                size,
+                modified_seconds: modified.as_secs(),
+                modified_nanos: modified.subsec_nanos(),
+                xxh64: xxhash_rust::xxh64::xxh64(&edges, 0),
+            })
+            .with_context(|| format!("identifying [{}]", wabbajack_file.display()))
+    }
+}
+
+/// first line of a cache entry, checked before the (big) rest of it is parsed
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    key: CacheKey,
+}
+
+#[derive(Serialize)]
+struct BodyRef<'a> {
+    entries: &'a [CaseInsensitivePathBuf],
+    modlist: &'a Modlist,
+}
+
+#[derive(Deserialize)]
+struct Body {
+    entries: Vec<CaseInsensitivePathBuf>,
+    modlist: Modlist,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModlistCache {
+    directory: PathBuf,
+}
+
+impl ModlistCache {
+    pub fn in_project_root(project_root: &Path) -> Self {
+        Self {
+            directory: project_root.join(CACHE_DIRECTORY),
+        }
+    }
+
+    /// one entry per .wabbajack file name, a newer version of the modlist replaces the entry of the older one
+    fn entry_path(&self, wabbajack_file: &Path) -> Result<PathBuf> {
+        wabbajack_file
+            .file_name()
+            .context("wabbajack file must have a name")
+            .map(|file_name| {
+                file_name
+                    .to_owned()
+                    .tap_mut(|name| name.push(".json.zst"))
+                    .pipe(|name| self.directory.join(name))
+            })
+    }
+
+    /// entries and modlist cached for exactly this file, [None] when there are none (or they can't be used)
+    pub fn load(&self, wabbajack_file: &Path, key: &CacheKey) -> Option<(Vec<CaseInsensitivePathBuf>, Modlist)> {
+        self.entry_path(wabbajack_file)
+            .and_then(|entry_path| match entry_path.exists() {
+                false => Ok(None),
+                true => File::open(&entry_path)
+                    .and_then(zstd::Decoder::new)
+                    .map(BufReader::new)
+                    .context("opening cache entry")
+                    .and_then(|mut reader| {
+                        String::new()
+                            .pipe(|mut header| reader.read_line(&mut header).map(|_| header))
+                            .context("reading header")
+                            .and_then(|header| serde_json::from_str::<Header>(&header).context("parsing header"))
+                            .and_then(|header| match header.version == CACHE_VERSION && header.key == *key {
+                                false => Ok(None).tap_ok(|_| debug!(?header, ?key, "cached modlist is stale")),
+                                true => serde_json::from_reader::<_, Body>(reader)
+                                    .context("parsing cached modlist")
+                                    .map(|Body { entries, modlist }| Some((entries, modlist))),
+                            })
+                    })
+                    .with_context(|| format!("reading [{}]", entry_path.display())),
+            })
+            .unwrap_or_else(|reason| {
+                warn!("could not use the cached modlist, parsing it again: {reason:?}");
+                None
+            })
+    }
+
+    pub fn store(&self, wabbajack_file: &Path, key: &CacheKey, entries: &[CaseInsensitivePathBuf], modlist: &Modlist) -> Result<()> {
+        self.entry_path(wabbajack_file).and_then(|entry_path| {
+            let temp = entry_path.with_extension("zst.tmp");
+            std::fs::create_dir_all(&self.directory)
+                .with_context(|| format!("creating [{}]", self.directory.display()))
+                .and_then(|_| {
+                    File::create(&temp)
+                        .and_then(|file| zstd::Encoder::new(BufWriter::new(file), COMPRESSION_LEVEL))
+                        .context("creating cache entry")
+                })
+                .and_then(|mut encoder| {
+                    serde_json::to_writer(
+                        &mut encoder,
+                        &Header {
+                            version: CACHE_VERSION,
+                            key: *key,
+                        },
+                    )
+                    .context("writing header")
+                    .and_then(|_| encoder.write_all(b"\n").context("writing header"))
+                    .and_then(|_| serde_json::to_writer(&mut encoder, &BodyRef { entries, modlist }).context("writing modlist"))
+                    .and_then(|_| encoder.finish().context("finishing compression"))
+                    .and_then(|mut writer| writer.flush().context("flushing cache entry"))
+                })
+                .and_then(|_| std::fs::rename(&temp, &entry_path).context("moving cache entry in place"))
+                .with_context(|| format!("caching modlist at [{}]", entry_path.display()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::str::FromStr};
+
+    fn modlist(name: &str) -> Result<Modlist> {
+        serde_json::from_value(serde_json::json!({
+            "Archives": [],
+            "Directives": [],
+            "GameType": "SkyrimSpecialEdition",
+            "IsNSFW": false,
+            "Name": name,
+            "Version": "1.0.0",
+            "WabbajackVersion": "3.7.0.0",
+        }))
+        .context("building modlist")
+    }
+
+    #[test_log::test]
+    fn test_cached_modlist_is_loaded_back() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let wabbajack_file = directory.path().join("Some Modlist.wabbajack");
+        std::fs::write(&wabbajack_file, "pretend this is a zip")?;
+        let cache = ModlistCache::in_project_root(directory.path());
+        let key = CacheKey::of(&wabbajack_file)?;
+        assert!(cache.load(&wabbajack_file, &key).is_none(), "nothing cached yet");
+
+        let entries = vec![CaseInsensitivePathBuf::from_str("modlist")?];
+        cache.store(&wabbajack_file, &key, &entries, &modlist("Cached")?)?;
+        let (cached_entries, cached) = cache
+            .load(&wabbajack_file, &key)
+            .context("entry was just stored")?;
+        assert_eq!(cached_entries, entries);
+        assert_eq!(cached.name, "Cached");
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_stale_and_corrupt_entries_are_ignored() -> Result<()> {
+        let directory = tempfile::tempdir()?;
+        let wabbajack_file = directory.path().join("Some Modlist.wabbajack");
+        std::fs::write(&wabbajack_file, "version one")?;
+        let cache = ModlistCache::in_project_root(directory.path());
+        let key = CacheKey::of(&wabbajack_file)?;
+        cache.store(&wabbajack_file, &key, &[], &modlist("Version One")?)?;
+
+        // same size, but different contents
+        std::fs::write(&wabbajack_file, "version two")?;
+        let updated = CacheKey::of(&wabbajack_file)?;
+        assert_ne!(updated, key);
+        assert!(cache.load(&wabbajack_file, &updated).is_none(), "entry of the previous version is stale");
+
+        cache
+            .entry_path(&wabbajack_file)
+            .and_then(|entry_path| std::fs::write(entry_path, "definitely not zstd").context("corrupting entry"))?;
+        assert!(cache.load(&wabbajack_file, &key).is_none(), "corrupt entry is ignored");
+        Ok(())
+    }
+}
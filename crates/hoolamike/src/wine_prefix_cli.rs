@@ -0,0 +1,97 @@
+//! a prefix kept between runs (the one MO2 runs in) slowly fills up with logs of failed commands, `status` tells how much
+//! space it takes
+
+use {
+    crate::config_file::HoolamikeConfig,
+    anyhow::{Context, Result},
+    indicatif::HumanBytes,
+    std::path::{Path, PathBuf},
+    tracing::info,
+    wine_wrapper::prefix_logs::{self, PrefixStatus},
+};
+
+#[derive(clap::Args, Clone)]
+pub struct WinePrefixCli {
+    #[command(subcommand)]
+    command: WinePrefixCommand,
+}
+
+#[derive(clap::Subcommand, Clone)]
+pub enum WinePrefixCommand {
+    /// prints how much space the prefix takes, and how much of it are logs of failed commands
+    Status(StatusCli),
+}
+
+#[derive(clap::Args, Clone)]
+pub struct StatusCli {
+    /// prefix to look at, extras.prefix_bootstrap.prefix by default
+    prefix: Option<PathBuf>,
+}
+
+fn configured_prefix(config_path: &Path) -> Result<PathBuf> {
+    HoolamikeConfig::read(config_path)
+        .context("reading hoolamike config file")
+        .and_then(|(config_path, config)| crate::project_root::enter_project_root(&config_path).map(|_| config))
+        .and_then(|config| {
+            config
+                .extras
+                .as_ref()
+                .and_then(|extras| extras.prefix_bootstrap.as_ref())
+                .map(|extension| {
+                    extension
+                        .prefix
+                        .resolve(&config.installation.installation_path)
+                })
+                .context("no prefix given and no extras.prefix_bootstrap section in config")
+        })
+}
+
+fn format_status(
+    prefix: &Path,
+    PrefixStatus {
+        prefix_size,
+        failed_logs,
+        logs_size,
+    }: PrefixStatus,
+) -> String {
+    [
+        format!("  {:<28}{}", "prefix", prefix.display()),
+        format!("  {:<28}{:>12}", "size", HumanBytes(prefix_size).to_string()),
+        format!("  {:<28}{failed_logs:>12}", "failed command logs"),
+        format!(
+            "  {:<28}{:>12}  (in {})",
+            "logs size",
+            HumanBytes(logs_size).to_string(),
+            prefix_logs::logs_directory(prefix).display()
+        ),
+    ]
+    .join("\n")
+}
+
+pub fn run(WinePrefixCli { command }: WinePrefixCli, config_path: &Path) -> Result<()> {
+    match command {
+        WinePrefixCommand::Status(StatusCli { prefix }) => prefix
+            .map(Ok)
+            .unwrap_or_else(|| configured_prefix(config_path))
+            .and_then(|prefix| {
+                info!("checking prefix at [{}]", prefix.display());
+                prefix_logs::status(&prefix).map(|status| println!("{}", format_status(&prefix, status)))
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_status_is_reported() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        std::fs::create_dir_all(prefix.path().join("drive_c"))?;
+        std::fs::write(prefix.path().join("drive_c/file"), vec![0u8; 2048])?;
+        let report = prefix_logs::status(prefix.path()).map(|status| format_status(prefix.path(), status))?;
+        assert!(report.contains("2.00 KiB"), "{report}");
+        assert!(report.contains(prefix_logs::LOGS_DIRECTORY), "{report}");
+        Ok(())
+    }
+}
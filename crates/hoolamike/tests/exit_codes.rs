@@ -0,0 +1,108 @@
+//! the exit codes scripts rely on, checked against the actual binary (see `hoolamike --help`)
+
+use {
+    anyhow::{Context, Result},
+    std::{
+        io::Write,
+        path::{Path, PathBuf},
+        process::Command,
+    },
+};
+
+fn hoolamike(directory: &Path, args: &[&str]) -> Result<i32> {
+    Command::new(env!("CARGO_BIN_EXE_hoolamike"))
+        .current_dir(directory)
+        .env_remove("HOOLAMIKE_NON_INTERACTIVE")
+        .args(["--hoolamike-config", &directory.join("hoolamike.yaml").display().to_string()])
+        .args(args)
+        .output()
+        .context("running hoolamike")
+        .and_then(|output| {
+            output
+                .status
+                .code()
+                .with_context(|| format!("killed by a signal\n{}", String::from_utf8_lossy(&output.stderr)))
+        })
+}
+
+/// a modlist with a single archive which has to be downloaded by hand
+fn manual_download_install(directory: &Path) -> Result<PathBuf> {
+    let modlist = serde_json::json!({
+        "Archives": [{
+            "Hash": "AAAAAAAAAAA=",
+            "Meta": "",
+            "Name": "by-hand.7z",
+            "Size": 1,
+            "State": {
+                "$type": "ManualDownloader, Wabbajack.Lib",
+                "Prompt": "click the blue button",
+                "Url": "https://example.com/by-hand.7z",
+            },
+        }],
+        "Author": "someone",
+        "Directives": [],
+        "GameType": "SkyrimSpecialEdition",
+        "IsNSFW": false,
+        "Name": "manual",
+        "Version": "1.0.0",
+        "WabbajackVersion": "3.7.0.0",
+    });
+    let wabbajack_file = directory.join("manual.wabbajack");
+    std::fs::File::create(&wabbajack_file)
+        .map(zip::ZipWriter::new)
+        .context("creating modlist file")
+        .and_then(|mut writer| {
+            writer
+                .start_file("modlist", zip::write::SimpleFileOptions::default())
+                .context("starting entry")?;
+            writer
+                .write_all(modlist.to_string().as_bytes())
+                .context("writing entry")?;
+            writer.finish().context("finishing modlist file").map(drop)
+        })?;
+    let game = directory.join("game");
+    std::fs::create_dir_all(&game)?;
+    std::fs::write(
+        directory.join("hoolamike.yaml"),
+        format!(
+            "downloaders:\n  downloads_directory: {downloads}\n  nexus:\n    api_key: null\ninstallation:\n  wabbajack_file_path: {wabbajack_file}\n  \
+             installation_path: {installation}\ngames:\n  SkyrimSpecialEdition:\n    root_directory: {game}\n",
+            downloads = directory.join("downloads").display(),
+            wabbajack_file = wabbajack_file.display(),
+            installation = directory.join("installed").display(),
+            game = game.display(),
+        ),
+    )?;
+    Ok(wabbajack_file)
+}
+
+#[test_log::test]
+fn test_success_and_bad_usage() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    assert_eq!(hoolamike(directory.path(), &["print-default-config"])?, 0);
+    assert_eq!(hoolamike(directory.path(), &["--no-such-flag"])?, 2);
+    Ok(())
+}
+
+#[test_log::test]
+fn test_missing_config_is_a_config_error() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    assert_eq!(hoolamike(directory.path(), &["install"])?, 3);
+    Ok(())
+}
+
+#[test_log::test]
+fn test_non_interactive_refuses_to_wait_for_a_browser() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    assert_eq!(hoolamike(directory.path(), &["--non-interactive", "nexus-login"])?, 6);
+    assert_eq!(hoolamike(directory.path(), &["--non-interactive"])?, 6, "the gui needs someone in front of it");
+    Ok(())
+}
+
+#[test_log::test]
+fn test_manual_downloads_need_manual_intervention() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    manual_download_install(directory.path())?;
+    assert_eq!(hoolamike(directory.path(), &["--non-interactive", "install"])?, 6);
+    Ok(())
+}
@@ -1,7 +1,14 @@
 use {
     bon::Builder,
-    std::{num::NonZeroU32, path::PathBuf, process::Command},
+    std::{fmt, num::NonZeroU32, path::PathBuf, process::Command},
 };
+pub use {
+    texassemble::{AssembleCommand, Texassemble},
+    texdiag::{DiagCommand, Texdiag, TexdiagInfo},
+};
+
+pub mod texassemble;
+pub mod texdiag;
 
 /// Enum for output file types supported by texconv.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -254,6 +261,16 @@ pub enum FeatureLevel {
 }
 
 impl FeatureLevel {
+    /// Maximum texture dimension supported at this feature level.
+    pub fn max_texture_size(&self) -> u32 {
+        match self {
+            Self::Fl9_1 | Self::Fl9_2 => 2048,
+            Self::Fl9_3 => 4096,
+            Self::Fl10_0 | Self::Fl10_1 => 8192,
+            Self::Fl11_0 | Self::Fl11_1 | Self::Fl12_0 | Self::Fl12_1 | Self::Fl12_2 => 16384,
+        }
+    }
+
     fn as_str(&self) -> &'static str {
         match self {
             Self::Fl9_1 => "9.1",
@@ -279,6 +296,61 @@ pub enum RecursiveMode {
     Keep,
 }
 
+/// A constraint of texconv the configured options break, naming the offending fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TexconvConfigError {
+    /// Both options are set, but texconv can't honor them together.
+    Conflicting {
+        first: &'static str,
+        second: &'static str,
+        reason: &'static str,
+    },
+    /// The option is set, but it only makes sense together with another one.
+    Requires {
+        field: &'static str,
+        requires: &'static str,
+        reason: &'static str,
+    },
+    /// The value is outside of what texconv accepts.
+    OutOfRange { field: &'static str, value: f64, min: f64, max: f64 },
+    /// The value is malformed.
+    Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for TexconvConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Conflicting { first, second, reason } => write!(f, "`{first}` conflicts with `{second}`: {reason}"),
+            Self::Requires { field, requires, reason } => write!(f, "`{field}` requires `{requires}`: {reason}"),
+            Self::OutOfRange { field, value, min, max } => write!(f, "`{field}` is {value}, but it has to be between {min} and {max}"),
+            Self::Invalid { field, reason } => write!(f, "`{field}` is invalid: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TexconvConfigError {}
+
+/// Every constraint the configuration breaks, so that all of them can be fixed at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidTexconvConfig(pub Vec<TexconvConfigError>);
+
+impl fmt::Display for InvalidTexconvConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid texconv configuration:")?;
+        self.0
+            .iter()
+            .try_for_each(|error| write!(f, "\n  - {error}"))
+    }
+}
+
+impl std::error::Error for InvalidTexconvConfig {}
+
+/// DX9 headers can't describe these formats.
+fn requires_dx10_header(format: &str) -> bool {
+    let format = format.to_ascii_uppercase();
+    format.starts_with("BC6H") || format.starts_with("BC7") || format.ends_with("_UINT") || format.ends_with("_SINT")
+}
+
 /// Builder for constructing a `texconv` command with type-safe options.
 ///
 /// This struct uses the `bon` crate to generate a builder pattern for configuring
@@ -856,18 +928,158 @@ impl<S: texconv_builder::State> TexconvBuilder<S> {
 }
 
 impl Texconv {
-    /// Builds a `std::process::Command` for executing `texconv`.
+    /// Checks the configured options against the constraints documented for `texconv`.
+    ///
+    /// # Returns
+    /// Every violated constraint, each naming the fields involved.
+    ///
+    /// # Aliases
+    /// - `check`
+    pub fn validate(&self) -> Result<(), Vec<TexconvConfigError>> {
+        use TexconvConfigError::*;
+        let conflicts = [
+            (self.dx9, self.dx10, "dx9", "dx10", "a DDS file has either a DX9 or a DX10 header"),
+            (self.srgb, self.srgb_in, "srgb", "srgb_in", "srgb already covers the input"),
+            (self.srgb, self.srgb_out, "srgb", "srgb_out", "srgb already covers the output"),
+            (
+                self.premultiplied_alpha,
+                self.straight_alpha,
+                "premultiplied_alpha",
+                "straight_alpha",
+                "alpha can't be converted both ways",
+            ),
+            (self.wrap, self.mirror, "wrap", "mirror", "only one addressing mode can be used"),
+            (
+                self.typeless_unorm,
+                self.typeless_float,
+                "typeless_unorm",
+                "typeless_float",
+                "TYPELESS formats are read either as UNORM or as FLOAT",
+            ),
+            (
+                self.no_gpu,
+                self.gpu.is_some(),
+                "no_gpu",
+                "gpu",
+                "a GPU adapter is picked, but the GPU is disabled",
+            ),
+            (
+                self.file_list.is_some(),
+                self.recursive.is_some(),
+                "file_list",
+                "recursive",
+                "file lists don't support wildcards",
+            ),
+            (
+                self.bc_flags.contains(&BcFlag::Quick),
+                self.bc_flags.contains(&BcFlag::Exhaustive),
+                "bc_flags(Quick)",
+                "bc_flags(Exhaustive)",
+                "BC7 compression is either minimal or maximal",
+            ),
+            (
+                self.dx9,
+                self.format.as_deref().is_some_and(requires_dx10_header),
+                "dx9",
+                "format",
+                "DX9 headers can't describe BC6H, BC7, UINT or SINT formats",
+            ),
+        ]
+        .into_iter()
+        .filter(|(first, second, ..)| *first && *second)
+        .map(|(_, _, first, second, reason)| Conflicting { first, second, reason });
+
+        let requirements = [
+            (
+                self.fit_power_of_2 && self.width.is_some() && self.height.is_none(),
+                "width",
+                "height",
+                "fit_power_of_2 keeps the aspect ratio of both dimensions",
+            ),
+            (
+                self.fit_power_of_2 && self.height.is_some() && self.width.is_none(),
+                "height",
+                "width",
+                "fit_power_of_2 keeps the aspect ratio of both dimensions",
+            ),
+        ]
+        .into_iter()
+        .filter(|(broken, ..)| *broken)
+        .map(|(_, field, requires, reason)| Requires { field, requires, reason });
+
+        let max_texture_size = f64::from(
+            self.feature_level
+                .unwrap_or(FeatureLevel::Fl11_0)
+                .max_texture_size(),
+        );
+        let ranges = [
+            ("width", self.width.map(f64::from), 1.0, max_texture_size),
+            ("height", self.height.map(f64::from), 1.0, max_texture_size),
+            ("wic_quality", self.wic_quality.map(f64::from), 0.0, 1.0),
+            ("paper_white_nits", self.paper_white_nits.map(f64::from), 0.0, 10000.0),
+            ("alpha_threshold", self.alpha_threshold.map(f64::from), 0.0, 1.0),
+            ("keep_coverage", self.keep_coverage.map(f64::from), 0.0, 1.0),
+            ("alpha_weight", self.alpha_weight.map(f64::from), 0.0, f64::MAX),
+        ]
+        .into_iter()
+        .filter_map(|(field, value, min, max)| value.map(|value| (field, value, min, max)))
+        // NaN is never in range
+        .filter(|&(_, value, min, max)| !(min..=max).contains(&value))
+        .map(|(field, value, min, max)| OutOfRange { field, value, min, max });
+
+        let malformed = [
+            self.swizzle
+                .as_deref()
+                .filter(|swizzle| !(1..=4).contains(&swizzle.len()) || !swizzle.chars().all(|c| "rgbaxyzw01".contains(c)))
+                .map(|_| Invalid {
+                    field: "swizzle",
+                    reason: "expected 1 to 4 of the characters 'rgbaxyzw01'".to_string(),
+                }),
+            self.color_key
+                .as_deref()
+                .filter(|color_key| color_key.len() != 6 || !color_key.chars().all(|c| c.is_ascii_hexdigit()))
+                .map(|color_key| Invalid {
+                    field: "color_key",
+                    reason: format!("[{color_key}] is not an RRGGBB hexadecimal color"),
+                }),
+            (self.input_files.is_empty() && self.file_list.is_none()).then(|| Invalid {
+                field: "input_files",
+                reason: "there is nothing to convert, neither input files nor a file list are given".to_string(),
+            }),
+        ]
+        .into_iter()
+        .flatten();
+
+        let errors: Vec<_> = conflicts
+            .chain(requirements)
+            .chain(ranges)
+            .chain(malformed)
+            .collect();
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Validates the options and builds a `std::process::Command` for executing `texconv`.
     ///
     /// Constructs the command with all configured options and input files,
     /// ready to be executed or further modified.
     ///
     /// # Returns
-    /// A `std::process::Command` instance configured with all `texconv` options.
+    /// A `std::process::Command` instance configured with all `texconv` options, or every
+    /// constraint the options break (see [`Texconv::validate`]).
     ///
     /// # Aliases
     /// - `construct_command`
     /// - `to_command`
-    pub fn command(self) -> Command {
+    pub fn try_command(self) -> Result<Command, InvalidTexconvConfig> {
+        self.validate()
+            .map_err(InvalidTexconvConfig)
+            .map(|()| self.into_command())
+    }
+
+    fn into_command(self) -> Command {
         let mut cmd = Command::new(self.texconv_path);
 
         if let Some(rec) = self.recursive {
@@ -1137,3 +1349,59 @@ impl Texconv {
         cmd
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(texconv: Texconv) -> Vec<String> {
+        texconv
+            .validate()
+            .err()
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_configuration() {
+        let command = Texconv::builder("texconv.exe")
+            .input_file("input.dds")
+            .format("BC7_UNORM")
+            .width(512)
+            .height(512)
+            .bc_flag(BcFlag::Quick)
+            .build()
+            .try_command()
+            .expect("valid configuration");
+        assert_eq!(command.get_program(), "texconv.exe");
+    }
+
+    #[test]
+    fn test_every_broken_constraint_is_named() {
+        let errors = errors(
+            Texconv::builder("texconv.exe")
+                .input_file("input.dds")
+                .dx9(true)
+                .format("bc7_unorm")
+                .fit_power_of_2(true)
+                .width(512)
+                .wic_quality(1.5)
+                .paper_white_nits(20000.0)
+                .srgb(true)
+                .srgb_in(true)
+                .build(),
+        );
+        assert_eq!(
+            errors,
+            [
+                "`srgb` conflicts with `srgb_in`: srgb already covers the input",
+                "`dx9` conflicts with `format`: DX9 headers can't describe BC6H, BC7, UINT or SINT formats",
+                "`width` requires `height`: fit_power_of_2 keeps the aspect ratio of both dimensions",
+                "`wic_quality` is 1.5, but it has to be between 0 and 1",
+                "`paper_white_nits` is 20000, but it has to be between 0 and 10000",
+            ]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use {
+    crate::{ImageFilter, InvalidTexconvConfig, TexconvConfigError},
+    bon::Builder,
+    std::{path::PathBuf, process::Command},
+};
+
+/// Enum for the kinds of textures `texassemble` builds out of its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssembleCommand {
+    /// Cube map out of exactly 6 images (+X, -X, +Y, -Y, +Z, -Z).
+    Cube,
+    /// Texture array, the inputs become its slices in order.
+    Array,
+    /// Array of cube maps, every 6 consecutive images make a cube.
+    CubeArray,
+    /// Volume (3D) texture, the inputs become its depth slices in order.
+    Volume,
+    /// Merges the RGB channels of the first image with the alpha of the second one.
+    Merge,
+}
+
+impl AssembleCommand {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cube => "cube",
+            Self::Array => "array",
+            Self::CubeArray => "cubearray",
+            Self::Volume => "volume",
+            Self::Merge => "merge",
+        }
+    }
+
+    /// How many inputs the command takes, if it's constrained at all.
+    fn input_count_is_valid(&self, count: usize) -> bool {
+        match self {
+            Self::Cube => count == 6,
+            Self::CubeArray => count > 0 && count.is_multiple_of(6),
+            Self::Merge => count == 2,
+            Self::Array | Self::Volume => count > 0,
+        }
+    }
+}
+
+/// Builder for constructing a `texassemble` command, DirectXTex's companion to `texconv`
+/// for building cube maps, arrays and volume textures out of separate images.
+#[derive(Builder, Debug)]
+#[builder(derive(Debug))]
+pub struct Texassemble {
+    /// Path to the `texassemble.exe` executable.
+    ///
+    /// Must point to the Windows `texassemble.exe`, typically run via Wine on Linux.
+    ///
+    /// # Aliases
+    /// - `executable`
+    #[builder(start_fn, into)]
+    texassemble_path: PathBuf,
+
+    /// Kind of texture to assemble.
+    ///
+    /// # Aliases
+    /// - `subcommand`
+    #[builder(start_fn)]
+    command: AssembleCommand,
+
+    /// List of input images, in the order they are assembled.
+    ///
+    /// # Aliases
+    /// - `files`
+    /// - `input`
+    #[builder(field)]
+    input_files: Vec<PathBuf>,
+
+    /// Path to a text file containing a list of input files (one per line).
+    ///
+    /// # Aliases
+    /// - `filelist`
+    #[builder(into)]
+    file_list: Option<PathBuf>,
+
+    /// Path of the assembled DDS file.
+    ///
+    /// # Aliases
+    /// - `output`
+    #[builder(into)]
+    output_file: Option<PathBuf>,
+
+    /// Overwrite the output file if it exists.
+    ///
+    /// # Aliases
+    /// - `force`
+    #[builder(default)]
+    overwrite: bool,
+
+    /// Force the output path and filename to lowercase.
+    ///
+    /// # Aliases
+    /// - `lowercase`
+    #[builder(default)]
+    to_lowercase: bool,
+
+    /// Output DXGI format (e.g., `R8G8B8A8_UNORM`), defaults to the format of the first input.
+    ///
+    /// # Aliases
+    /// - `dxgi_format`
+    #[builder(into)]
+    format: Option<String>,
+
+    /// Width of the output texture, every input is resized to it.
+    ///
+    /// # Aliases
+    /// - `w`
+    width: Option<u32>,
+
+    /// Height of the output texture, every input is resized to it.
+    ///
+    /// # Aliases
+    /// - `h`
+    height: Option<u32>,
+
+    /// Image filter for resizing.
+    ///
+    /// # Aliases
+    /// - `filter`
+    image_filter: Option<ImageFilter>,
+
+    /// Use sRGB for both input and output.
+    #[builder(default)]
+    srgb: bool,
+
+    /// Input is in sRGB format.
+    #[builder(default)]
+    srgb_in: bool,
+
+    /// Output is in sRGB format.
+    #[builder(default)]
+    srgb_out: bool,
+
+    /// Separate alpha channel for resizing.
+    ///
+    /// # Aliases
+    /// - `sep_alpha`
+    #[builder(default)]
+    separate_alpha: bool,
+
+    /// Force non-WIC-based code paths for filtering.
+    ///
+    /// # Aliases
+    /// - `disable_wic`
+    #[builder(default)]
+    no_wic: bool,
+
+    /// Use only the top-level mip of every input.
+    ///
+    /// # Aliases
+    /// - `top_mip_only`
+    #[builder(default)]
+    strip_mips: bool,
+
+    /// Force the output to use the DX10 header extension.
+    ///
+    /// # Aliases
+    /// - `dx10_header`
+    #[builder(default)]
+    dx10: bool,
+
+    /// Suppress the copyright message.
+    ///
+    /// # Aliases
+    /// - `hide_logo`
+    #[builder(default)]
+    no_logo: bool,
+}
+
+impl<S: texassemble_builder::State> TexassembleBuilder<S> {
+    /// Adds an input image, inputs are assembled in the order they are added.
+    ///
+    /// # Aliases
+    /// - `add_file`
+    /// - `add_input`
+    pub fn input_file(mut self, input_file: impl Into<PathBuf>) -> Self {
+        self.input_files.push(input_file.into());
+        self
+    }
+}
+
+impl Texassemble {
+    /// Checks the configured options against the constraints documented for `texassemble`.
+    ///
+    /// # Aliases
+    /// - `check`
+    pub fn validate(&self) -> Result<(), Vec<TexconvConfigError>> {
+        use TexconvConfigError::*;
+        let errors: Vec<_> = [
+            (self.srgb && self.srgb_in).then_some(Conflicting {
+                first: "srgb",
+                second: "srgb_in",
+                reason: "srgb already covers the input",
+            }),
+            (self.srgb && self.srgb_out).then_some(Conflicting {
+                first: "srgb",
+                second: "srgb_out",
+                reason: "srgb already covers the output",
+            }),
+            // a file list is only read by texassemble itself
+            (self.file_list.is_none() && !self.command.input_count_is_valid(self.input_files.len())).then(|| Invalid {
+                field: "input_files",
+                reason: format!("[{}] images can't be assembled with `{}`", self.input_files.len(), self.command.as_str()),
+            }),
+            self.output_file.is_none().then_some(Requires {
+                field: "command",
+                requires: "output_file",
+                reason: "texassemble writes a single file, it has to be named",
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(
+            [("width", self.width), ("height", self.height)]
+                .into_iter()
+                .filter(|(_, value)| *value == Some(0))
+                .map(|(field, _)| OutOfRange {
+                    field,
+                    value: 0.0,
+                    min: 1.0,
+                    max: f64::from(u32::MAX),
+                }),
+        )
+        .collect();
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Validates the options and builds a `std::process::Command` for executing `texassemble`.
+    ///
+    /// # Aliases
+    /// - `construct_command`
+    /// - `to_command`
+    pub fn try_command(self) -> Result<Command, InvalidTexconvConfig> {
+        self.validate()
+            .map_err(InvalidTexconvConfig)
+            .map(|()| self.into_command())
+    }
+
+    fn into_command(self) -> Command {
+        let mut cmd = Command::new(self.texassemble_path);
+        cmd.arg(self.command.as_str());
+
+        if let Some(fl) = self.file_list {
+            cmd.arg("-flist").arg(fl);
+        }
+
+        if let Some(o) = self.output_file {
+            cmd.arg("-o").arg(o);
+        }
+
+        if self.overwrite {
+            cmd.arg("-y");
+        }
+
+        if self.to_lowercase {
+            cmd.arg("-l");
+        }
+
+        if let Some(f) = self.format {
+            cmd.arg("-f").arg(f);
+        }
+
+        if let Some(w) = self.width {
+            cmd.arg("-w").arg(w.to_string());
+        }
+
+        if let Some(h) = self.height {
+            cmd.arg("-h").arg(h.to_string());
+        }
+
+        if let Some(ifilter) = self.image_filter {
+            cmd.arg("-if").arg(ifilter.as_str());
+        }
+
+        if self.srgb {
+            cmd.arg("-srgb");
+        }
+
+        if self.srgb_in {
+            cmd.arg("-srgbi");
+        }
+
+        if self.srgb_out {
+            cmd.arg("-srgbo");
+        }
+
+        if self.separate_alpha {
+            cmd.arg("-sepalpha");
+        }
+
+        if self.no_wic {
+            cmd.arg("-nowic");
+        }
+
+        if self.strip_mips {
+            cmd.arg("-stripmips");
+        }
+
+        if self.dx10 {
+            cmd.arg("-dx10");
+        }
+
+        if self.no_logo {
+            cmd.arg("-nologo");
+        }
+
+        for file in self.input_files {
+            cmd.arg(file);
+        }
+
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cube_command() {
+        let command = (0..6)
+            .fold(Texassemble::builder("texassemble.exe", AssembleCommand::Cube), |builder, face| {
+                builder.input_file(format!("face{face}.dds"))
+            })
+            .output_file("cube.dds")
+            .overwrite(true)
+            .no_logo(true)
+            .build()
+            .try_command()
+            .expect("valid configuration");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "cube",
+                "-o",
+                "cube.dds",
+                "-y",
+                "-nologo",
+                "face0.dds",
+                "face1.dds",
+                "face2.dds",
+                "face3.dds",
+                "face4.dds",
+                "face5.dds"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cube_needs_six_faces() {
+        let errors = Texassemble::builder("texassemble.exe", AssembleCommand::Cube)
+            .input_file("face0.dds")
+            .build()
+            .validate()
+            .expect_err("not a cube")
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "`input_files` is invalid: [1] images can't be assembled with `cube`",
+                "`command` requires `output_file`: texassemble writes a single file, it has to be named",
+            ]
+        );
+    }
+}
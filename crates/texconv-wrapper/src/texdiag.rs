@@ -0,0 +1,342 @@
+use {
+    crate::{InvalidTexconvConfig, TexconvConfigError},
+    anyhow::{Context, Result},
+    bon::Builder,
+    std::{collections::BTreeMap, path::PathBuf, process::Command},
+};
+
+/// Enum for the diagnostics `texdiag` runs on its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagCommand {
+    /// Prints the metadata of every input (dimensions, format, mip levels), see [`TexdiagInfo::parse`].
+    Info,
+    /// Prints statistics of the pixel values of every input.
+    Analyze,
+}
+
+impl DiagCommand {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Analyze => "analyze",
+        }
+    }
+}
+
+/// Builder for constructing a `texdiag` command, DirectXTex's companion to `texconv`
+/// for inspecting textures.
+#[derive(Builder, Debug)]
+#[builder(derive(Debug))]
+pub struct Texdiag {
+    /// Path to the `texdiag.exe` executable.
+    ///
+    /// Must point to the Windows `texdiag.exe`, typically run via Wine on Linux.
+    ///
+    /// # Aliases
+    /// - `executable`
+    #[builder(start_fn, into)]
+    texdiag_path: PathBuf,
+
+    /// Diagnostic to run.
+    ///
+    /// # Aliases
+    /// - `subcommand`
+    #[builder(start_fn)]
+    command: DiagCommand,
+
+    /// List of textures to inspect.
+    ///
+    /// # Aliases
+    /// - `files`
+    /// - `input`
+    #[builder(field)]
+    input_files: Vec<PathBuf>,
+
+    /// Path to a text file containing a list of input files (one per line).
+    ///
+    /// # Aliases
+    /// - `filelist`
+    #[builder(into)]
+    file_list: Option<PathBuf>,
+
+    /// Search subdirectories for the inputs given with wildcards.
+    ///
+    /// # Aliases
+    /// - `recurse`
+    #[builder(default)]
+    recursive: bool,
+
+    /// Treat DDS TYPELESS formats as UNORM.
+    ///
+    /// # Aliases
+    /// - `unorm`
+    #[builder(default)]
+    typeless_unorm: bool,
+
+    /// Treat DDS TYPELESS formats as FLOAT.
+    ///
+    /// # Aliases
+    /// - `float`
+    #[builder(default)]
+    typeless_float: bool,
+
+    /// Allow loading of malformed or variant DDS header files.
+    ///
+    /// # Aliases
+    /// - `permissive_headers`
+    #[builder(default)]
+    permissive: bool,
+
+    /// Load only the top-level mipmap.
+    ///
+    /// # Aliases
+    /// - `skip_mips`
+    #[builder(default)]
+    ignore_mips: bool,
+
+    /// Suppress the copyright message.
+    ///
+    /// # Aliases
+    /// - `hide_logo`
+    #[builder(default)]
+    no_logo: bool,
+}
+
+impl<S: texdiag_builder::State> TexdiagBuilder<S> {
+    /// Adds a texture to inspect.
+    ///
+    /// # Aliases
+    /// - `add_file`
+    /// - `add_input`
+    pub fn input_file(mut self, input_file: impl Into<PathBuf>) -> Self {
+        self.input_files.push(input_file.into());
+        self
+    }
+}
+
+impl Texdiag {
+    /// Checks the configured options against the constraints documented for `texdiag`.
+    ///
+    /// # Aliases
+    /// - `check`
+    pub fn validate(&self) -> Result<(), Vec<TexconvConfigError>> {
+        use TexconvConfigError::*;
+        let errors: Vec<_> = [
+            (self.typeless_unorm && self.typeless_float).then_some(Conflicting {
+                first: "typeless_unorm",
+                second: "typeless_float",
+                reason: "TYPELESS formats are read either as UNORM or as FLOAT",
+            }),
+            (self.file_list.is_some() && self.recursive).then_some(Conflicting {
+                first: "file_list",
+                second: "recursive",
+                reason: "file lists don't support wildcards",
+            }),
+            (self.input_files.is_empty() && self.file_list.is_none()).then(|| Invalid {
+                field: "input_files",
+                reason: "there is nothing to inspect, neither input files nor a file list are given".to_string(),
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Validates the options and builds a `std::process::Command` for executing `texdiag`.
+    ///
+    /// # Aliases
+    /// - `construct_command`
+    /// - `to_command`
+    pub fn try_command(self) -> Result<Command, InvalidTexconvConfig> {
+        self.validate()
+            .map_err(InvalidTexconvConfig)
+            .map(|()| self.into_command())
+    }
+
+    fn into_command(self) -> Command {
+        let mut cmd = Command::new(self.texdiag_path);
+        cmd.arg(self.command.as_str());
+
+        if self.recursive {
+            cmd.arg("-r");
+        }
+
+        if let Some(fl) = self.file_list {
+            cmd.arg("-flist").arg(fl);
+        }
+
+        if self.typeless_unorm {
+            cmd.arg("-tu");
+        }
+
+        if self.typeless_float {
+            cmd.arg("-tf");
+        }
+
+        if self.permissive {
+            cmd.arg("-permissive");
+        }
+
+        if self.ignore_mips {
+            cmd.arg("-ignoremips");
+        }
+
+        if self.no_logo {
+            cmd.arg("-nologo");
+        }
+
+        for file in self.input_files {
+            cmd.arg(file);
+        }
+
+        cmd
+    }
+}
+
+/// Metadata `texdiag info` prints for a single texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TexdiagInfo {
+    /// The input as texdiag names it.
+    pub file: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub mip_levels: u32,
+    pub array_size: u32,
+    /// DXGI format name, without the `DXGI_FORMAT_` prefix (e.g. `BC7_UNORM`).
+    pub format: String,
+}
+
+impl TexdiagInfo {
+    /// Parses the output of `texdiag info`: a line naming every input, followed by its
+    /// `field = value` lines (indented, unless the output was trimmed on the way).
+    ///
+    /// Inputs texdiag could not load (it prints an error instead of their fields) are left out.
+    ///
+    /// # Aliases
+    /// - `from_output`
+    pub fn parse(output: &str) -> Result<Vec<Self>> {
+        let mut textures: Vec<(&str, BTreeMap<&str, &str>)> = vec![];
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .for_each(|line| match line.split_once(" = ") {
+                Some((field, value)) => {
+                    if let Some((_, fields)) = textures.last_mut() {
+                        fields.insert(field.trim(), value.trim());
+                    }
+                }
+                None => textures.push((line, BTreeMap::new())),
+            });
+        textures
+            .into_iter()
+            .filter(|(_, fields)| !fields.is_empty())
+            .map(|(file, fields)| {
+                let field = |name: &str| {
+                    fields
+                        .get(name)
+                        .copied()
+                        .with_context(|| format!("no `{name}` in the info of [{file}]"))
+                };
+                let number = |name: &str| {
+                    field(name).and_then(|value| {
+                        value
+                            .parse::<u32>()
+                            .with_context(|| format!("`{name}` of [{file}] is not a number: [{value}]"))
+                    })
+                };
+                Ok(Self {
+                    file: file.to_string(),
+                    width: number("width")?,
+                    height: number("height")?,
+                    depth: fields
+                        .contains_key("depth")
+                        .then(|| number("depth"))
+                        .transpose()?
+                        .unwrap_or(1),
+                    mip_levels: number("mipLevels")?,
+                    array_size: fields
+                        .contains_key("arraySize")
+                        .then(|| number("arraySize"))
+                        .transpose()?
+                        .unwrap_or(1),
+                    format: field("format")?.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO: &str = "
+Microsoft (R) DirectX Texture Diagnostic Tool [DirectXTex] Version 2024.10.29.1
+Copyright (C) Microsoft Corp.
+
+Z:\\tmp\\outputs\\0.dds
+        width = 512
+        height = 256
+        depth = 1
+        mipLevels = 10
+        arraySize = 1
+        format = BC7_UNORM
+        dimension = 2D
+        alpha mode = Unknown
+        images = 10
+        pixel size = 170 (KB)
+
+Z:\\tmp\\outputs\\1.dds
+FAILED (80070002)
+";
+
+    #[test]
+    fn test_info_is_parsed() {
+        assert_eq!(
+            TexdiagInfo::parse(INFO).expect("valid output"),
+            [TexdiagInfo {
+                file: "Z:\\tmp\\outputs\\0.dds".to_string(),
+                width: 512,
+                height: 256,
+                depth: 1,
+                mip_levels: 10,
+                array_size: 1,
+                format: "BC7_UNORM".to_string(),
+            }]
+        );
+        assert_eq!(
+            TexdiagInfo::parse(&INFO.lines().map(str::trim).collect::<Vec<_>>().join("\n")).expect("valid output"),
+            TexdiagInfo::parse(INFO).expect("valid output"),
+            "trimmed by the wine wrapper"
+        );
+        assert!(
+            TexdiagInfo::parse("a.dds\n        width = 512\n        height = wide\n")
+                .expect_err("bad number")
+                .to_string()
+                .contains("`height` of [a.dds]")
+        );
+    }
+
+    #[test]
+    fn test_info_command() {
+        let command = Texdiag::builder("texdiag.exe", DiagCommand::Info)
+            .file_list("list.txt")
+            .no_logo(true)
+            .build()
+            .try_command()
+            .expect("valid configuration");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["info", "-flist", "list.txt", "-nologo"]);
+        assert!(
+            Texdiag::builder("texdiag.exe", DiagCommand::Analyze)
+                .build()
+                .validate()
+                .is_err()
+        );
+    }
+}
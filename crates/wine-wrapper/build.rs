@@ -0,0 +1,57 @@
+//! picks the wine-wrapper-shell binary embedded in the crate. by default it's the prebuilt `wine-wrapper-shell.exe` checked in
+//! next to this file (see `build-proton-wrapper-shell.sh`), with the `build-shell` feature it's built from source for windows
+//! (which needs the `x86_64-pc-windows-gnu` target and mingw)
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const SHELL_TARGET: &str = "x86_64-pc-windows-gnu";
+
+fn env(name: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| panic!("[{name}] is not set"))
+}
+
+fn build_shell(manifest_dir: &Path) -> PathBuf {
+    let workspace_manifest = manifest_dir.join("../../Cargo.toml");
+    // a separate target dir, the outer build holds the lock of the shared one
+    let target_dir = PathBuf::from(env("OUT_DIR")).join("wine-wrapper-shell-target");
+    let status = Command::new(env("CARGO"))
+        .arg("build")
+        .arg("--release")
+        .arg("--package")
+        .arg("wine-wrapper-shell")
+        .arg("--target")
+        .arg(SHELL_TARGET)
+        .arg("--manifest-path")
+        .arg(&workspace_manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        // the shell depends on this crate, it must not try to build itself again
+        .env_remove("CARGO_FEATURE_BUILD_SHELL")
+        .status()
+        .expect("spawning cargo to build wine-wrapper-shell");
+    assert!(
+        status.success(),
+        "building wine-wrapper-shell for [{SHELL_TARGET}] failed ({status}), is the target installed (rustup target add {SHELL_TARGET})?"
+    );
+    target_dir
+        .join(SHELL_TARGET)
+        .join("release")
+        .join("wine-wrapper-shell.exe")
+}
+
+fn main() {
+    let manifest_dir = PathBuf::from(env("CARGO_MANIFEST_DIR"));
+    let shell = match std::env::var_os("CARGO_FEATURE_BUILD_SHELL").is_some() {
+        true => {
+            println!("cargo:rerun-if-changed=../wine-wrapper-shell/src");
+            println!("cargo:rerun-if-changed=src/ipc.rs");
+            build_shell(&manifest_dir)
+        }
+        false => manifest_dir.join("wine-wrapper-shell.exe"),
+    };
+    println!("cargo:rerun-if-changed={}", shell.display());
+    println!("cargo:rustc-env=WINE_WRAPPER_SHELL_PATH={}", shell.display());
+}
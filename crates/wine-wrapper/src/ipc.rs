@@ -49,6 +49,8 @@ impl std::fmt::Debug for WineWrapperShellBin {
     }
 }
 
+/// the argv of a command run by the shell. it's handed over verbatim and spawned directly (no cmd.exe or bat file in between), so
+/// spaces, quotes and the like reach the program untouched
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SerializedCommand {
     pub bin: PathBuf,
@@ -57,15 +59,21 @@ pub struct SerializedCommand {
 }
 
 impl SerializedCommand {
-    pub fn from_command(command: &std::process::Command, stdio: WrappedStdout<String>) -> Self {
-        SerializedCommand {
-            bin: command.get_program().pipe(PathBuf::from),
-            args: command
-                .get_args()
-                .map(|arg| arg.to_string_lossy().to_string())
-                .collect(),
-            stdio,
-        }
+    /// arguments which aren't valid unicode can't be passed to a windows program, they're rejected instead of being mangled
+    pub fn from_command(command: &std::process::Command, stdio: WrappedStdout<String>) -> Result<Self> {
+        command
+            .get_args()
+            .map(|arg| {
+                arg.to_str()
+                    .map(ToOwned::to_owned)
+                    .with_context(|| format!("argument [{}] is not valid unicode", arg.to_string_lossy()))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|args| SerializedCommand {
+                bin: command.get_program().pipe(PathBuf::from),
+                args,
+                stdio,
+            })
     }
 
     pub fn to_command(&self) -> std::process::Command {
@@ -98,3 +106,44 @@ impl SerializedCommand {
             .map(|s| BASE64_STANDARD.encode(&s))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::process::Command};
+
+    fn stdio() -> WrappedStdout<String> {
+        WrappedStdout::in_directory(Path::new("C:\\logs")).map(|path| path.to_string_lossy().to_string())
+    }
+
+    #[test_log::test]
+    fn test_arguments_survive_verbatim() -> Result<()> {
+        let args = [r"Z:\tmp\Mod (Special Édition)\tex file.dds", r#"say "hi" & exit ^| %PATH%"#, "", "ąęść 日本語"];
+        let command = Command::new(r"Z:\tools\texconv.exe")
+            .tap_mut(|command| {
+                command.args(args);
+            })
+            .pipe_ref(|command| SerializedCommand::from_command(command, stdio()))?;
+        let decoded = command
+            .serialize()
+            .and_then(|encoded| SerializedCommand::decode(&encoded))?;
+        assert_eq!(
+            decoded
+                .to_command()
+                .get_args()
+                .map(|arg| arg.to_str().expect("unicode"))
+                .collect::<Vec<_>>(),
+            args
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_non_unicode_arguments_are_rejected() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let command = Command::new("texconv.exe").tap_mut(|command| {
+            command.arg(OsStr::from_bytes(b"tex\xfffile.dds"));
+        });
+        assert!(SerializedCommand::from_command(&command, stdio()).is_err());
+    }
+}
@@ -1,4 +1,7 @@
 pub mod ipc;
+pub mod prefix_logs;
 
-#[cfg(not(all(target_os = "windows", not(debug_assertions))))]
+#[cfg(unix)]
+pub mod prefix_lock;
+#[cfg(unix)]
 pub mod wine_context;
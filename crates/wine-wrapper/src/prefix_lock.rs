@@ -0,0 +1,139 @@
+//! advisory lock over a wine prefix. commands which only run a program (texconv) share it, the ones which change the prefix
+//! (installers) take it exclusively - two of them racing in a single prefix leave the wineserver in a broken state
+
+use {
+    anyhow::{Context, Result},
+    nix::fcntl::{Flock, FlockArg},
+    std::{
+        fs::File,
+        os::unix::fs::MetadataExt,
+        path::{Path, PathBuf},
+    },
+    tracing::debug,
+};
+
+pub const LOCK_FILE_NAME: &str = ".wine-wrapper.lock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrefixLock {
+    /// any number of commands running programs in the prefix
+    #[default]
+    Shared,
+    /// a single command changing the prefix, nothing else runs alongside it
+    Exclusive,
+}
+
+impl PrefixLock {
+    fn args(self) -> (FlockArg, FlockArg) {
+        match self {
+            PrefixLock::Shared => (FlockArg::LockSharedNonblock, FlockArg::LockShared),
+            PrefixLock::Exclusive => (FlockArg::LockExclusiveNonblock, FlockArg::LockExclusive),
+        }
+    }
+}
+
+/// held until dropped
+#[derive(Debug)]
+pub struct PrefixLockGuard {
+    _lock: Flock<File>,
+}
+
+/// blocks until the lock is acquired, saying so when it has to wait
+pub fn lock_prefix(prefix: &Path, lock: PrefixLock) -> Result<PrefixLockGuard> {
+    let path = prefix.join(LOCK_FILE_NAME);
+    let (try_lock, wait_for_lock) = lock.args();
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("opening [{}]", path.display()))
+        .and_then(|file| {
+            Flock::lock(file, try_lock).or_else(|(file, _busy)| {
+                debug!(?lock, "prefix is busy, waiting for [{}]", path.display());
+                Flock::lock(file, wait_for_lock).map_err(|(_, errno)| anyhow::anyhow!("locking failed: {errno}"))
+            })
+        })
+        .map(|lock| PrefixLockGuard { _lock: lock })
+        .with_context(|| format!("acquiring {lock:?} lock of prefix [{}]", prefix.display()))
+}
+
+/// socket of the wineserver serving `prefix`, wine keeps it in `/tmp/.wine-<uid>/server-<dev>-<inode>/` of the prefix directory
+fn wineserver_socket(prefix: &Path) -> Result<PathBuf> {
+    std::fs::metadata(prefix)
+        .with_context(|| format!("reading metadata of [{}]", prefix.display()))
+        .map(|metadata| {
+            Path::new("/tmp")
+                .join(format!(".wine-{}", metadata.uid()))
+                .join(format!("server-{:x}-{:x}", metadata.dev(), metadata.ino()))
+                .join("socket")
+        })
+}
+
+/// a wineserver already serving a prefix this process is about to set up was started by someone else
+pub fn ensure_no_wineserver(prefix: &Path) -> Result<()> {
+    wineserver_socket(prefix).and_then(|socket| match socket.exists() {
+        false => Ok(()),
+        true => Err(anyhow::anyhow!(
+            "a wineserver is already running for prefix [{}] (its socket is at [{}]). another process is using the prefix - wait for it to finish, or stop it \
+             with `WINEPREFIX={} wineserver -k`",
+            prefix.display(),
+            socket.display(),
+            prefix.display(),
+        )),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            sync::{Arc, Barrier},
+            time::{Duration, Instant},
+        },
+    };
+
+    /// (start, end) of every holder of the lock
+    fn hold_concurrently(prefix: &Path, locks: [PrefixLock; 2]) -> Vec<(Instant, Instant)> {
+        let barrier = Arc::new(Barrier::new(locks.len()));
+        std::thread::scope(|scope| {
+            locks
+                .map(|lock| {
+                    let barrier = barrier.clone();
+                    scope.spawn(move || {
+                        barrier.wait();
+                        let _guard = lock_prefix(prefix, lock).expect("locking");
+                        let start = Instant::now();
+                        std::thread::sleep(Duration::from_millis(200));
+                        (start, Instant::now())
+                    })
+                })
+                .into_iter()
+                .map(|holder| holder.join().expect("holder panicked"))
+                .collect()
+        })
+    }
+
+    fn overlap(holders: &[(Instant, Instant)]) -> bool {
+        let [(first_start, first_end), (second_start, second_end)] = holders else {
+            panic!("expected two holders")
+        };
+        first_start < second_end && second_start < first_end
+    }
+
+    #[test_log::test]
+    fn test_exclusive_lock_keeps_everything_else_out() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        assert!(overlap(&hold_concurrently(prefix.path(), [PrefixLock::Shared, PrefixLock::Shared])));
+        assert!(!overlap(&hold_concurrently(prefix.path(), [PrefixLock::Exclusive, PrefixLock::Shared])));
+        assert!(!overlap(&hold_concurrently(prefix.path(), [PrefixLock::Exclusive, PrefixLock::Exclusive])));
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_fresh_prefix_has_no_wineserver() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        ensure_no_wineserver(prefix.path())
+    }
+}
@@ -0,0 +1,204 @@
+//! stdout/stderr of wrapped commands live in a single `hoolamike-logs` directory inside the prefix. logs of commands which
+//! succeeded are removed right away, the ones which failed are kept for inspection - the oldest of them go once they take
+//! up more than [MAX_KEPT_BYTES], so a prefix which outlives many runs doesn't keep growing
+
+use {
+    anyhow::{Context, Result},
+    std::{
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+    tap::prelude::*,
+    tempfile::TempDir,
+    tracing::{debug, warn},
+};
+
+pub const LOGS_DIRECTORY: &str = "hoolamike-logs";
+
+/// set it to `1` to get the full (untrimmed) output of the last command in `hoolamike-logs/DUMP_STDOUT`
+pub const DUMP_STDOUT_ENV: &str = "HOOLAMIKE_DUMP_WINE_STDOUT";
+
+pub const DUMP_STDOUT_FILE_NAME: &str = "DUMP_STDOUT";
+
+/// logs of failed commands are removed (oldest first) past this size, the newest one always stays
+pub const MAX_KEPT_BYTES: u64 = 16 * 1024 * 1024;
+
+const COMMAND_LOG_PREFIX: &str = "command-";
+
+pub fn logs_directory(prefix: &Path) -> PathBuf {
+    prefix.join(LOGS_DIRECTORY)
+}
+
+pub fn dump_stdout_enabled() -> bool {
+    std::env::var(DUMP_STDOUT_ENV).is_ok_and(|value| value == "1")
+}
+
+/// removed when dropped, see [keep_failed]
+pub fn new_log_directory(prefix: &Path) -> Result<TempDir> {
+    let logs = logs_directory(prefix);
+    std::fs::create_dir_all(&logs)
+        .with_context(|| format!("creating [{}]", logs.display()))
+        .and_then(|_| {
+            tempfile::Builder::new()
+                .prefix(COMMAND_LOG_PREFIX)
+                .tempdir_in(&logs)
+                .context("creating log directory")
+        })
+}
+
+/// size of everything under `path`, symlinks are counted as themselves and never followed (`dosdevices/z:` points at `/`)
+pub fn disk_usage(path: &Path) -> Result<u64> {
+    std::fs::symlink_metadata(path)
+        .with_context(|| format!("reading metadata of [{}]", path.display()))
+        .and_then(|metadata| match metadata.is_dir() {
+            false => Ok(metadata.len()),
+            true => std::fs::read_dir(path)
+                .with_context(|| format!("reading [{}]", path.display()))?
+                .map(|entry| {
+                    entry
+                        .context("listing directory")
+                        .and_then(|entry| disk_usage(&entry.path()))
+                })
+                .sum::<Result<u64>>(),
+        })
+}
+
+/// logs of failed commands, newest first
+fn kept_logs(prefix: &Path) -> Result<Vec<(PathBuf, SystemTime)>> {
+    let logs = logs_directory(prefix);
+    match logs.exists() {
+        false => Ok(vec![]),
+        true => std::fs::read_dir(&logs)
+            .with_context(|| format!("reading [{}]", logs.display()))?
+            .map(|entry| {
+                entry
+                    .and_then(|entry| entry.metadata().map(|metadata| (entry.path(), metadata)))
+                    .context("listing logs")
+            })
+            .filter(|entry| {
+                !matches!(entry, Ok((path, metadata)) if !metadata.is_dir() || !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(COMMAND_LOG_PREFIX)))
+            })
+            .map(|entry| entry.and_then(|(path, metadata)| metadata.modified().context("reading modification time").map(|modified| (path, modified))))
+            .collect::<Result<Vec<_>>>()
+            .map(|logs| logs.tap_mut(|logs| logs.sort_by(|(_, a), (_, b)| b.cmp(a)))),
+    }
+}
+
+/// removes the oldest logs of failed commands once all of them take more than `max_bytes`, returns how many were removed
+pub fn rotate(prefix: &Path, max_bytes: u64) -> Result<usize> {
+    kept_logs(prefix).and_then(|logs| {
+        logs.into_iter()
+            .map(|(path, _)| disk_usage(&path).map(|size| (path, size)))
+            .collect::<Result<Vec<_>>>()
+            .map(|logs| {
+                logs.into_iter()
+                    .scan(0, |total, (path, size)| {
+                        *total += size;
+                        Some((path, *total))
+                    })
+                    .skip(1)
+                    .filter(|(_, total)| *total > max_bytes)
+                    .map(|(path, _)| path)
+                    .collect::<Vec<_>>()
+            })
+            .and_then(|outdated| {
+                outdated
+                    .iter()
+                    .try_for_each(|path| std::fs::remove_dir_all(path).with_context(|| format!("removing [{}]", path.display())))
+                    .map(|_| outdated.len())
+            })
+            .tap_ok(|removed| debug!(%removed, "rotated command logs"))
+    })
+}
+
+/// keeps the logs of a failed command around, returns where they are
+pub fn keep_failed(prefix: &Path, log_directory: TempDir) -> PathBuf {
+    log_directory.keep().tap(|_| {
+        rotate(prefix, MAX_KEPT_BYTES)
+            .map(|_| ())
+            .unwrap_or_else(|reason| warn!("could not rotate command logs: {reason:?}"))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixStatus {
+    pub prefix_size: u64,
+    pub failed_logs: usize,
+    pub logs_size: u64,
+}
+
+pub fn status(prefix: &Path) -> Result<PrefixStatus> {
+    let logs = logs_directory(prefix);
+    (|| {
+        anyhow::Ok(PrefixStatus {
+            prefix_size: disk_usage(prefix)?,
+            failed_logs: kept_logs(prefix)?.len(),
+            logs_size: match logs.exists() {
+                true => disk_usage(&logs)?,
+                false => 0,
+            },
+        })
+    })()
+    .with_context(|| format!("checking prefix at [{}]", prefix.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_log(prefix: &Path, size: usize) -> Result<PathBuf> {
+        new_log_directory(prefix).and_then(|log| {
+            std::fs::write(log.path().join("stdout"), vec![b'x'; size])
+                .context("writing stdout")
+                .map(|_| log.keep())
+        })
+    }
+
+    #[test_log::test]
+    fn test_successful_logs_are_removed() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        let log = new_log_directory(prefix.path())?;
+        assert!(log.path().starts_with(logs_directory(prefix.path())));
+        drop(log);
+        assert_eq!(status(prefix.path())?.failed_logs, 0);
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_oldest_failed_logs_are_rotated_out() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        let oldest = failed_log(prefix.path(), 600)?;
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let older = failed_log(prefix.path(), 600)?;
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let newest = failed_log(prefix.path(), 600)?;
+        assert_eq!(status(prefix.path())?.failed_logs, 3);
+
+        assert_eq!(rotate(prefix.path(), 1000)?, 2);
+        assert!(newest.exists());
+        assert!(!older.exists());
+        assert!(!oldest.exists());
+
+        // the newest one stays even when it's over the cap on its own
+        assert_eq!(rotate(prefix.path(), 1)?, 0);
+        assert_eq!(
+            status(prefix.path())?,
+            PrefixStatus {
+                prefix_size: 600,
+                failed_logs: 1,
+                logs_size: 600,
+            }
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test_log::test]
+    fn test_symlinks_are_not_followed() -> Result<()> {
+        let prefix = tempfile::tempdir()?;
+        std::fs::create_dir_all(prefix.path().join("dosdevices"))?;
+        std::os::unix::fs::symlink("/", prefix.path().join("dosdevices/z:"))?;
+        assert!(disk_usage(prefix.path())? < 4096);
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 use {
-    crate::ipc::{SerializedCommand, WineWrapperShellBin, WrappedStdout},
+    crate::{
+        ipc::{SerializedCommand, WineWrapperShellBin, WrappedStdout},
+        prefix_lock::{PrefixLock, ensure_no_wineserver, lock_prefix},
+        prefix_logs,
+    },
     anyhow::{Context, Result, anyhow},
     itertools::Itertools,
     std::{
@@ -12,25 +16,46 @@ use {
     },
     tap::{Pipe, Tap, TapFallible},
     tempfile::TempDir,
-    tracing::{debug, info, instrument},
+    tracing::{debug, info, instrument, warn},
     typed_path::{Utf8UnixPath, Utf8WindowsPath, Utf8WindowsPathBuf},
 };
 
-static WINE_WRAPPER_SHELL: WineWrapperShellBin = WineWrapperShellBin(include_bytes!("../wine-wrapper-shell.exe"));
+/// picked by build.rs, see the `build-shell` feature
+static WINE_WRAPPER_SHELL: WineWrapperShellBin = WineWrapperShellBin(include_bytes!(env!("WINE_WRAPPER_SHELL_PATH")));
 
 static SHELL_NAME: &str = "wine-wrapper-shell.exe";
 
+/// what the shell answers to `--version` when it comes from the same workspace as this crate
+fn expected_shell_version() -> String {
+    format!("wine-wrapper-shell {}", env!("CARGO_PKG_VERSION"))
+}
+
+const REBUILD_HINT: &str = "rebuild it with crates/wine-wrapper/build-proton-wrapper-shell.sh, or build with the `build-shell` feature of wine-wrapper";
+
 #[derive(Debug, Clone)]
 pub struct MoutnedWineWrapperShell {
     pub bin_path: PathBuf,
 }
 
 impl WineWrapperShellBin {
+    /// every windows executable starts with the `MZ` of its DOS header
+    fn check(self) -> Result<Self> {
+        match self.0.starts_with(b"MZ") {
+            true => Ok(self),
+            false => Err(anyhow!(
+                "embedded wine-wrapper-shell is not a windows executable ([{} bytes]), {REBUILD_HINT}",
+                self.0.len()
+            )),
+        }
+    }
+
     pub fn mount(self, at: &Path) -> Result<MoutnedWineWrapperShell> {
-        at.join(SHELL_NAME).pipe(|bin_path| {
-            std::fs::write(&bin_path, self.0)
-                .context("injecting the binary")
-                .map(|_| MoutnedWineWrapperShell { bin_path })
+        self.check().and_then(|shell| {
+            at.join(SHELL_NAME).pipe(|bin_path| {
+                std::fs::write(&bin_path, shell.0)
+                    .context("injecting the binary")
+                    .map(|_| MoutnedWineWrapperShell { bin_path })
+            })
         })
     }
 }
@@ -106,12 +131,21 @@ impl<T: std::fmt::Display> std::fmt::Display for WrappedStdout<T> {
 }
 
 impl WrappedCommand {
+    /// the command changes the prefix (installers), nothing else may run in it at the same time
+    pub fn exclusive(self) -> Self {
+        Self {
+            lock: PrefixLock::Exclusive,
+            ..self
+        }
+    }
+
+    /// logs of the command are removed when it succeeds and kept in `hoolamike-logs` when it fails
     #[instrument]
     pub fn output_blocking(mut self) -> Result<String> {
         debug!("running command: [{:?}]", self.serialized_command);
 
-        self.wrapped_command
-            .stdout_ok()
+        let output = lock_prefix(self.context.prefix_dir.path(), self.lock)
+            .and_then(|_guard| self.wrapped_command.stdout_ok())
             .map(|out| debug!("{out}"))
             .and_then(|_| {
                 std::fs::read_to_string(&self.wrapped_stdio.stdout)
@@ -119,8 +153,12 @@ impl WrappedCommand {
                     .map(|all_output| {
                         debug!(%all_output);
 
-                        #[cfg(debug_assertions)]
-                        std::fs::write(self.context.prefix_dir.path().join("DUMP_STDOUT"), &all_output).expect("dumping output");
+                        if prefix_logs::dump_stdout_enabled() {
+                            prefix_logs::logs_directory(self.context.prefix_dir.path())
+                                .join(prefix_logs::DUMP_STDOUT_FILE_NAME)
+                                .pipe(|dump| std::fs::write(&dump, &all_output).with_context(|| format!("dumping output to [{}]", dump.display())))
+                                .unwrap_or_else(|reason| warn!("{reason:?}"));
+                        }
 
                         all_output
                             .lines()
@@ -155,7 +193,12 @@ impl WrappedCommand {
                     .context("reading emergency stdio")
                     .map(|output| format!("wrapper crash:\n{output}"))
                     .unwrap_or_else(|fetching_emergency_stderr| format!("could not even read emergency stdio, reason:\n{fetching_emergency_stderr:?}"))
-            })
+            });
+        match output {
+            Ok(output) => Ok(output),
+            Err(error) => prefix_logs::keep_failed(self.context.prefix_dir.path(), self.log_directory)
+                .pipe(|kept| Err(error.context(format!("logs of the command are kept at [{}]", kept.display())))),
+        }
     }
 }
 
@@ -190,7 +233,7 @@ impl WineContext {
                                         std::process::Command::new(path.as_path())
                                             .args(*args)
                                             .wrap_in_wine(&context)
-                                            .and_then(|command| command.output_blocking().map(|_| ()))
+                                            .and_then(|command| command.exclusive().output_blocking().map(|_| ()))
                                             .and_then(|_| context.0.wait_wineserver_idle())
                                     })
                                     .with_context(|| format!("installing [{path:?}]"))
@@ -200,6 +243,47 @@ impl WineContext {
             })
             .tap_ok(|_| info!("[OK] wine context initialized"))
     }
+    /// asks the mounted shell for its version directly (its output isn't redirected for that), so that a shell which can't run at
+    /// all is told apart from one failing the IPC protocol. a shell of another version is only warned about, the protocol rarely
+    /// changes
+    #[instrument(skip(self))]
+    fn handshake(&self, mounted: &MoutnedWineWrapperShell) -> Result<()> {
+        let expected = expected_shell_version();
+        Command::new("wine")
+            .arg(
+                mounted
+                    .bin_path
+                    .pipe_deref(host_to_pfx_path)
+                    .context("converting binary name to host path")?,
+            )
+            .arg("--version")
+            .pipe(|c| match self.show_gui {
+                true => c,
+                false => c.env("WINEDLLOVERRIDES", WINE_HIDE_GUI_FLAGS),
+            })
+            .env("WINEPREFIX", self.prefix_dir.path())
+            .stdout_ok()
+            .context("running the shell")
+            .and_then(|output| {
+                output
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| line.starts_with("wine-wrapper-shell "))
+                    .map(ToOwned::to_owned)
+                    .with_context(|| format!("the shell did not report its version, it answered:\n{output}"))
+            })
+            .map(|version| match version == expected {
+                true => debug!("[OK] {version}"),
+                false => warn!("embedded shell reports [{version}], expected [{expected}] - it might be stale, {REBUILD_HINT}"),
+            })
+            .with_context(|| {
+                format!(
+                    "mounted wine-wrapper-shell at [{}] does not respond to --version, it's either corrupt or wine can't run it, {REBUILD_HINT}",
+                    mounted.bin_path.display()
+                )
+            })
+    }
+
     #[instrument]
     pub fn initialize(self) -> Result<Initialized<Self>> {
         debug!("initializing wine context");
@@ -210,9 +294,10 @@ impl WineContext {
             prefix_dir,
             show_gui: _,
         } = &self;
-        WINE_WRAPPER_SHELL
-            .mount(prefix_dir.path())
+        ensure_no_wineserver(prefix_dir.path())
+            .and_then(|()| WINE_WRAPPER_SHELL.mount(prefix_dir.path()))
             .context("mounting wine wrapper shell")
+            .and_then(|mounted| self.handshake(&mounted).map(|()| mounted))
             .and_then(|mounted| {
                 let mut command = Command::new("cmd.exe");
                 command
@@ -231,7 +316,9 @@ impl WineContext {
                         debug!("output: {output}");
                         match output.as_str().trim().eq("TEST") {
                             true => Ok(()),
-                            false => Err(anyhow!("expected 'TEST', found '{output}'")),
+                            false => Err(anyhow!(
+                                "expected 'TEST', found '{output}' - the shell runs, but does not speak the IPC protocol of this version"
+                            )),
                         }
                     })
                     .with_context(|| format!("initializing wine context for {self:#?}"))
@@ -242,10 +329,9 @@ impl WineContext {
 
 #[derive(Debug)]
 pub struct WrappedCommand {
-    #[allow(dead_code)]
     log_directory: TempDir,
-    #[allow(dead_code)]
     context: WineContext,
+    lock: PrefixLock,
     wrapped_command: Command,
     serialized_command: SerializedCommand,
     wrapped_stdio: WrappedStdout<PathBuf>,
@@ -273,19 +359,15 @@ impl WineContext {
         // let mut wrapped = Command::new(wine_path);
         let mut wrapped = Command::new("wine");
 
-        let log_directory = tempfile::Builder::new()
-            .prefix("log_directory")
-            .tempdir_in(prefix_dir.path())
-            .context("creating temporary log directory")?;
+        let log_directory = prefix_logs::new_log_directory(prefix_dir.path()).context("creating temporary log directory")?;
 
         let wrapped_stdio = WrappedStdout::in_directory(log_directory.path());
-        let serialized_command = SerializedCommand::from_command(
-            command,
-            wrapped_stdio
-                .clone()
-                .try_map(|path| host_to_pfx_path(&path))
-                .map(|paths| paths.map(|p| p.to_string()))?,
-        );
+        let serialized_command = wrapped_stdio
+            .clone()
+            .try_map(|path| host_to_pfx_path(&path))
+            .map(|paths| paths.map(|p| p.to_string()))
+            .and_then(|stdio| SerializedCommand::from_command(command, stdio))
+            .context("serializing command for the shell")?;
 
         wrapped
             .stdout(Stdio::null())
@@ -316,6 +398,7 @@ impl WineContext {
 
         Ok(WrappedCommand {
             context: self.clone(),
+            lock: PrefixLock::default(),
             wrapped_command: wrapped,
             serialized_command,
             wrapped_stdio,
@@ -327,10 +410,11 @@ impl WineContext {
 
 pub fn host_to_pfx_path(path: &Path) -> Result<Utf8WindowsPathBuf> {
     const ROOT: &str = "Z:\\";
-    Utf8UnixPath::new(&path.to_string_lossy())
-        .normalize()
-        .absolutize()
-        .context("could not make path absolute")
+    path.to_str()
+        .context("path is not valid unicode")
+        .map(Utf8UnixPath::new)
+        .map(|path| path.normalize())
+        .and_then(|path| path.absolutize().context("could not make path absolute"))
         .and_then(|path| {
             path.with_windows_encoding_checked()
                 .context("converting stdout to windows encofing")
@@ -374,6 +458,22 @@ impl Initialized<WineContext> {
 mod tests {
     use super::*;
 
+    #[test_log::test]
+    fn test_paths_with_spaces_and_unicode_are_translated() -> Result<()> {
+        assert_eq!(
+            host_to_pfx_path(Path::new("/tmp/Mod (Special Édition)/tex file.dds"))?.as_str(),
+            r"Z:\tmp\Mod (Special Édition)\tex file.dds"
+        );
+        Ok(())
+    }
+
+    #[test_log::test]
+    fn test_embedded_shell_is_a_windows_executable() -> Result<()> {
+        WINE_WRAPPER_SHELL.check().map(|_| ())?;
+        assert!(WineWrapperShellBin(&[]).check().is_err());
+        Ok(())
+    }
+
     #[test_log::test]
     fn test_it_works() -> Result<()> {
         debug!("testing if it works");
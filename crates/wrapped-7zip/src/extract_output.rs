@@ -0,0 +1,176 @@
+//! output of `7z x -bsp1 -bso2`: progress lines (`" 42% 3 - some/file"`, redrawn in place with backspaces) on stdout,
+//! the usual messages and the footer (`Everything is Ok`, `Sub items Errors: 2`...) on stderr
+
+use {
+    anyhow::{Result, anyhow},
+    std::fmt::Display,
+};
+
+/// picks percentages out of stdout as it arrives, a line may be split across reads
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    pending: Vec<u8>,
+    last: Option<u8>,
+}
+
+fn percentage(segment: &[u8]) -> Option<u8> {
+    String::from_utf8_lossy(segment)
+        .trim()
+        .split_once('%')
+        .and_then(|(percentage, _)| percentage.trim().parse::<u8>().ok())
+        .filter(|percentage| *percentage <= 100)
+}
+
+fn is_separator(byte: &u8) -> bool {
+    matches!(byte, b'\r' | b'\n' | b'\x08')
+}
+
+impl ProgressParser {
+    /// percentages which changed since the last one reported
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(bytes);
+        let complete = self
+            .pending
+            .iter()
+            .rposition(is_separator)
+            .map(|last_separator| self.pending.drain(..=last_separator).collect::<Vec<_>>())
+            .unwrap_or_default();
+        complete
+            .split(is_separator)
+            .filter_map(percentage)
+            .filter(|percentage| {
+                let changed = self.last != Some(*percentage);
+                self.last = Some(*percentage);
+                changed
+            })
+            .collect()
+    }
+}
+
+/// what a single `7z x` invocation reported about itself
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExtractionSummary {
+    pub exit_code: Option<i32>,
+    /// `Everything is Ok`
+    pub everything_is_ok: bool,
+    /// `Archives with Errors: N`
+    pub archives_with_errors: u64,
+    /// `Sub items Errors: N` - entries which could not be extracted
+    pub sub_items_errors: u64,
+    /// `Warnings: N`
+    pub warnings: u64,
+    /// `ERROR: ...` lines, naming the entries that failed
+    pub errors: Vec<String>,
+}
+
+impl ExtractionSummary {
+    pub fn parse(exit_code: Option<i32>, output: &str) -> Self {
+        let count = |line: &str, key: &str| {
+            line.strip_prefix(key)
+                .and_then(|count| count.trim().parse::<u64>().ok())
+        };
+        output.lines().map(str::trim).fold(
+            Self {
+                exit_code,
+                ..Default::default()
+            },
+            |mut summary, line| {
+                if line == "Everything is Ok" {
+                    summary.everything_is_ok = true;
+                } else if let Some(count) = count(line, "Archives with Errors:") {
+                    summary.archives_with_errors = count;
+                } else if let Some(count) = count(line, "Sub items Errors:") {
+                    summary.sub_items_errors = count;
+                } else if let Some(count) = count(line, "Warnings:") {
+                    summary.warnings = count;
+                } else if line.starts_with("ERROR:") {
+                    summary.errors.push(line.to_string());
+                }
+                summary
+            },
+        )
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// some entries failed, the rest got extracted
+    pub fn is_partial_failure(&self) -> bool {
+        !self.is_success() && self.sub_items_errors > 0 && self.archives_with_errors == 0
+    }
+
+    pub fn ensure_success(self) -> Result<Self> {
+        match self.is_success() {
+            true => Ok(self),
+            false => Err(anyhow!("{self}")),
+        }
+    }
+}
+
+impl Display for ExtractionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.is_success(), self.is_partial_failure()) {
+            (true, _) => write!(f, "extraction succeeded")?,
+            (false, true) => write!(f, "extraction partially failed ([{}] entries could not be extracted)", self.sub_items_errors)?,
+            (false, false) => write!(
+                f,
+                "extraction failed with status [{}]",
+                self.exit_code
+                    .map(|code| code.to_string())
+                    .unwrap_or_else(|| "killed".into())
+            )?,
+        }
+        if self.warnings > 0 {
+            write!(f, ", [{}] warnings", self.warnings)?;
+        }
+        self.errors
+            .iter()
+            .try_for_each(|error| write!(f, "\n  {error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_is_parsed_across_reads() {
+        let mut parser = ProgressParser::default();
+        let output =
+            b"  0%\x08\x08\x08\x08    \x08\x08\x08\x08 42% 1 - long path/small-file.json\x08\x08\x08\x08\x08\x08\x08\x08 42% 2 - other\x08\x08\x08\x08100%\r\n";
+        let (first, second) = output.split_at(30);
+        let mut reported = parser.feed(first);
+        reported.extend(parser.feed(second));
+        assert_eq!(reported, [0, 42, 100]);
+        assert!(parser.feed(b"Everything is Ok\n").is_empty());
+    }
+
+    #[test]
+    fn test_footers() {
+        let ok = ExtractionSummary::parse(Some(0), "\n7-Zip 23.01\n\nExtracting archive: a.7z\n\nEverything is Ok\n\nSize:       12\n");
+        assert!(ok.everything_is_ok);
+        assert!(ok.clone().ensure_success().is_ok());
+
+        let partial = ExtractionSummary::parse(
+            Some(2),
+            "ERROR: CRC Failed : broken.dds\n\nSub items Errors: 1\n\nArchives with Errors: 0\n\nWarnings: 2\n",
+        );
+        assert!(partial.is_partial_failure());
+        assert_eq!(partial.errors, ["ERROR: CRC Failed : broken.dds"]);
+        let reason = partial
+            .ensure_success()
+            .expect_err("partial failure was accepted")
+            .to_string();
+        assert!(reason.contains("[1] entries could not be extracted"), "{reason}");
+        assert!(reason.contains("[2] warnings"), "{reason}");
+
+        let failed = ExtractionSummary::parse(Some(2), "ERROR: a.7z\nCan not open the file as archive\n\nArchives with Errors: 1\n");
+        assert!(!failed.is_partial_failure());
+        assert!(
+            failed
+                .to_string()
+                .starts_with("extraction failed with status [2]")
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! extraction straight into the destinations picked by the caller. 7z can't rename entries while extracting, so they land in
+//! a staging directory next to the first destination (on the same filesystem) and are renamed into place from there - no
+//! temp file handed over just to be copied once more
+
+use {super::*, std::collections::btree_map::Entry};
+
+/// moves `from` to `to`, copying when they turn out to be on different filesystems after all
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    std::fs::rename(from, to)
+        .or_else(|_| {
+            std::fs::copy(from, to)
+                .and_then(|_| std::fs::remove_file(from))
+                .map(|_| ())
+        })
+        .with_context(|| format!("moving [{}] to [{}]", from.display(), to.display()))
+}
+
+fn lookup_key(path: &Path) -> String {
+    path.display().to_string().to_lowercase()
+}
+
+impl ArchiveHandle {
+    /// extracts every `(entry, destination)` pair, creating the parent directories of destinations. the outer error means
+    /// nothing got extracted, otherwise there's a result for each pair (in order) - entries 7z reported an error for fail,
+    /// the rest are in place. an entry can be mapped to many destinations
+    #[instrument(skip(mappings, on_progress), fields(mappings=%mappings.len()))]
+    pub fn extract_to(
+        &self,
+        mappings: &[(&Path, &Path)],
+        concurrency: Option<NonZeroUsize>,
+        on_progress: impl FnMut(u8),
+    ) -> Result<Vec<Result<ListOutputEntry>>> {
+        let staging_parent = mappings
+            .first()
+            .and_then(|(_, destination)| destination.parent())
+            .context("nothing to extract")?;
+        std::fs::create_dir_all(staging_parent).with_context(|| format!("creating [{}]", staging_parent.display()))?;
+        let listed = self.list_files().map(|entries| {
+            entries
+                .into_iter()
+                .map(|entry| (lookup_key(&entry.path), entry))
+                .collect::<BTreeMap<_, _>>()
+        })?;
+        let entries = mappings
+            .iter()
+            .map(|(entry, _)| {
+                listed
+                    .get(&lookup_key(entry))
+                    .cloned()
+                    .with_context(|| format!("[{}] not found in archive", entry.display()))
+            })
+            .collect::<Vec<_>>();
+        let unique = entries
+            .iter()
+            .filter_map(|entry| entry.as_ref().ok())
+            .map(|entry| (lookup_key(&entry.path), entry.clone()))
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect::<Vec<_>>();
+        let staging = tempfile::Builder::new()
+            .prefix(".wrapped-7zip-")
+            .tempdir_in(staging_parent)
+            .context("creating staging directory")?;
+        let summary = self
+            .extract(&unique, staging.path(), concurrency, on_progress)
+            .with_context(|| format!("extracting from [{}]", self.archive.display()))?;
+        if !summary.is_success() && !summary.is_partial_failure() {
+            return Err(anyhow!("{summary}")).with_context(|| format!("extracting from [{}]", self.archive.display()));
+        }
+        let failed = |entry: &ListOutputEntry| {
+            summary
+                .errors
+                .iter()
+                .find(|error| error.contains(&entry.original_path))
+        };
+        // entries mapped more than once are moved to the first destination and copied from there
+        let mut placed = BTreeMap::<String, PathBuf>::new();
+        entries
+            .into_iter()
+            .zip(mappings)
+            .map(|(entry, (_, destination))| {
+                entry.and_then(|entry| {
+                    if let Some(error) = failed(&entry) {
+                        return Err(anyhow!("{error}")).with_context(|| format!("7z could not extract [{}]", entry.original_path));
+                    }
+                    destination
+                        .parent()
+                        .map(|parent| std::fs::create_dir_all(parent).with_context(|| format!("creating [{}]", parent.display())))
+                        .transpose()?;
+                    match placed.entry(lookup_key(&entry.path)) {
+                        Entry::Vacant(vacant) => move_file(&staging.path().join(&entry.original_path), destination).map(|_| {
+                            vacant.insert(destination.to_path_buf());
+                        }),
+                        Entry::Occupied(occupied) => std::fs::copy(occupied.get(), destination)
+                            .with_context(|| format!("copying [{}] to [{}]", occupied.get().display(), destination.display()))
+                            .map(|_| ()),
+                    }
+                    .map(|_| entry)
+                })
+            })
+            .collect::<Vec<_>>()
+            .pipe(Ok)
+    }
+}
@@ -0,0 +1,20 @@
+//! archives from `test-data`, known to extract cleanly with a healthy 7z build
+
+pub struct Fixture {
+    pub name: &'static str,
+    pub extension: &'static str,
+    pub bytes: &'static [u8],
+}
+
+pub const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "example-small-file.7z",
+        extension: "7z",
+        bytes: include_bytes!("../test-data/example-small-file.7z"),
+    },
+    Fixture {
+        name: "example-1.rar",
+        extension: "rar",
+        bytes: include_bytes!("../test-data/example-1.rar"),
+    },
+];
@@ -3,19 +3,22 @@
 pub use which;
 use {
     anyhow::{Context, Result, anyhow},
+    extract_output::{ExtractionSummary, ProgressParser},
     list_output::{ListOutput, ListOutputEntry},
     std::{
         collections::BTreeMap,
+        io::Read,
         iter::once,
         num::NonZeroUsize,
         path::{Path, PathBuf},
-        process::{Command, Output, Stdio},
+        process::{Command, ExitStatus, Output, Stdio},
         str::FromStr,
         sync::Arc,
     },
     tap::prelude::*,
     tempfile::{TempDir, TempPath},
     tracing::instrument,
+    typed_path::{Utf8Encoding, Utf8PathBuf, Utf8PlatformEncoding, Utf8UnixEncoding, Utf8WindowsPath},
 };
 
 #[derive(Clone, Debug)]
@@ -80,6 +83,46 @@ impl Command {
             })
             .with_context(|| format!("when executing [{dbg}]"))
     }
+    /// like [CommandExt::read_stdout_ok], but stdout is handed over as it arrives (and not kept), stderr is collected on the side.
+    /// the exit status is left for the caller to judge
+    fn stream_stdout(mut self, mut on_stdout: impl FnMut(&[u8])) -> Result<(ExitStatus, String)> {
+        let dbg = self.command_debug();
+        self.stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("spawning command")
+            .and_then(|mut child| {
+                let stderr = child
+                    .stderr
+                    .take()
+                    .context("no stderr")?
+                    .pipe(|mut stderr| {
+                        std::thread::spawn(move || {
+                            let mut output = Vec::new();
+                            stderr
+                                .read_to_end(&mut output)
+                                .map(|_| String::from_utf8_lossy(&output).to_string())
+                        })
+                    });
+                let mut stdout = child.stdout.take().context("no stdout")?;
+                let mut buffer = [0; 4096];
+                loop {
+                    match stdout.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(read) => on_stdout(&buffer[..read]),
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e).context("reading stdout"),
+                    }
+                }
+                let status = child.wait().context("waiting for command")?;
+                stderr
+                    .join()
+                    .map_err(|_| anyhow!("stderr reader panicked"))
+                    .and_then(|stderr| stderr.context("reading stderr"))
+                    .map(|stderr| (status, stderr))
+            })
+            .with_context(|| format!("when executing [{dbg}]"))
+    }
 }
 
 impl Wrapped7Zip {
@@ -113,8 +156,9 @@ impl Wrapped7Zip {
 }
 
 impl Wrapped7Zip {
+    /// the official 7-Zip build (7zz) goes first, distro 7z packages are often old p7zip forks
     pub fn find_bin(temp_files_dir: &Path) -> Result<Self> {
-        ["7z", "7z.exe"]
+        ["7zz", "7z", "7z.exe"]
             .into_iter()
             .find_map(|bin| which::which(bin).ok())
             .context("no 7z binary")
@@ -132,23 +176,28 @@ pub struct ArchiveFileHandle {
     pub file: std::fs::File,
 }
 
+pub mod extract_output;
+pub mod extract_to;
 pub mod list_output;
 
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+
 #[derive(Debug, PartialEq, PartialOrd, Hash)]
 pub(crate) struct MaybeWindowsPath(pub String);
 
 impl MaybeWindowsPath {
+    /// 7z lists entries with `\` (sometimes doubled) or `/`, both are separators - rebuilt with the ones of `T`
+    pub fn into_typed<T: Utf8Encoding>(self) -> Utf8PathBuf<T> {
+        Utf8WindowsPath::new(&self.0)
+            .with_encoding::<Utf8UnixEncoding>()
+            .with_encoding::<T>()
+    }
+
     pub fn into_path(self) -> PathBuf {
-        let s = self.0;
-        let s = match s.contains("\\\\") {
-            true => s.split("\\\\").collect::<Vec<_>>().join("/"),
-            false => s,
-        };
-        let s = match s.contains("\\") {
-            true => s.split("\\").collect::<Vec<_>>().join("/"),
-            false => s,
-        };
-        PathBuf::from(s)
+        self.into_typed::<Utf8PlatformEncoding>()
+            .into_string()
+            .pipe(PathBuf::from)
     }
 }
 
@@ -169,6 +218,63 @@ impl ArchiveHandle {
 
     #[instrument]
     pub fn get_many_handles(&self, paths: &[&Path], concurrency: Option<NonZeroUsize>) -> Result<Vec<(ListOutputEntry, ArchiveFileHandle)>> {
+        self.get_many_handles_with_progress(paths, concurrency, |_| ())
+    }
+
+    /// extracts `entries` into `directory`, reporting the percentage done as 7z prints it
+    #[instrument(skip(entries, on_progress), fields(entries=%entries.len()))]
+    pub fn extract(
+        &self,
+        entries: &[ListOutputEntry],
+        directory: &Path,
+        concurrency: Option<NonZeroUsize>,
+        mut on_progress: impl FnMut(u8),
+    ) -> Result<ExtractionSummary> {
+        self.binary
+            .command(|c| {
+                c.arg("x")
+                    .arg(&self.archive)
+                    // progress goes to stdout, the messages (and the summary) to stderr - with -bso0 the summary would be lost
+                    .arg("-bsp1")
+                    .arg("-bso2")
+            })
+            .pipe(|c| match concurrency {
+                Some(concurrency) => c.tap_mut(|c| match concurrency.get() {
+                    1 => {
+                        c.arg("-mmt=off");
+                    }
+                    more => {
+                        c.arg(format!("-mmt={more}"));
+                    }
+                }),
+                None => c,
+            })
+            .pipe(|c| {
+                let mut c = entries.iter().fold(c, |c, entry| {
+                    c.tap_mut(|c| {
+                        c.arg(&entry.original_path);
+                    })
+                });
+                c.arg(format!("-o{}", directory.display()));
+                c.arg(directory);
+                c
+            })
+            .pipe(|c| {
+                let mut progress = ProgressParser::default();
+                c.stream_stdout(|stdout| progress.feed(stdout).into_iter().for_each(&mut on_progress))
+            })
+            .map(|(status, output)| ExtractionSummary::parse(status.code(), &output))
+            .tap_ok(|summary| tracing::debug!(%summary))
+    }
+
+    /// like [ArchiveHandle::get_many_handles], `on_progress` gets the percentage of the extraction done
+    #[instrument(skip(on_progress))]
+    pub fn get_many_handles_with_progress(
+        &self,
+        paths: &[&Path],
+        concurrency: Option<NonZeroUsize>,
+        on_progress: impl FnMut(u8),
+    ) -> Result<Vec<(ListOutputEntry, ArchiveFileHandle)>> {
         let mut lookup = paths
             .iter()
             .copied()
@@ -196,31 +302,9 @@ impl ArchiveHandle {
                             .with_context(|| format!("some paths were not found: {lookup:#?}"))
                     })
                     .and_then(|entries| {
-                        self.binary
-                            .command(|c| c.arg("x").arg(&self.archive))
-                            .pipe(|c| match concurrency {
-                                Some(concurrency) => c.tap_mut(|c| match concurrency.get() {
-                                    1 => {
-                                        c.arg("-mmt=off");
-                                    }
-                                    more => {
-                                        c.arg(format!("-mmt={more}"));
-                                    }
-                                }),
-                                None => c,
-                            })
-                            .pipe(|c| {
-                                let mut c = entries.iter().fold(c, |c, entry| {
-                                    c.tap_mut(|c| {
-                                        c.arg(&entry.original_path);
-                                    })
-                                });
-                                c.arg(format!("-o{}", temp_dir.path().display()));
-                                c.arg(temp_dir.path());
-                                c
-                            })
-                            .read_stdout_ok()
-                            .tap_ok(|res| tracing::debug!(%res))
+                        self.extract(&entries, temp_dir.path(), concurrency, on_progress)
+                            .and_then(ExtractionSummary::ensure_success)
+                            .with_context(|| format!("extracting from [{}]", self.archive.display()))
                             .and_then(|_| {
                                 entries
                                     .into_iter()
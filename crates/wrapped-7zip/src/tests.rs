@@ -1,4 +1,4 @@
-use super::*;
+use {super::*, typed_path::Utf8WindowsEncoding};
 
 #[test]
 fn test_stat_example_file() -> Result<()> {
@@ -51,3 +51,63 @@ async fn extract_example_file() -> Result<()> {
 
     Ok(())
 }
+
+#[test_log::test]
+fn test_extract_to_destinations() -> Result<()> {
+    let archive = Wrapped7Zip::find_bin(Path::new("."))?.open_file(Path::new("test-data/example-small-file.7z"))?;
+    let output = tempfile::tempdir()?;
+    let [first, nested, copy] = ["first.json", "nested/directory/second.json", "copy.json"].map(|name| output.path().join(name));
+    let nested_entry = Path::new("long path/with some whitespace/lets add some more/small-file.json");
+    let extracted = archive.extract_to(
+        &[
+            (Path::new("small-file.json"), &first),
+            (nested_entry, &nested),
+            (Path::new("missing.json"), &output.path().join("missing.json")),
+            (Path::new("small-file.json"), &copy),
+        ],
+        None,
+        |_| (),
+    )?;
+    assert!(extracted[2].is_err());
+    [(0, &first), (1, &nested), (3, &copy)]
+        .into_iter()
+        .try_for_each(|(idx, destination)| {
+            let entry = extracted[idx].as_ref().map_err(|e| anyhow!("{e:?}"))?;
+            assert_eq!(std::fs::metadata(destination)?.len(), entry.size);
+            Ok::<_, anyhow::Error>(())
+        })?;
+    // the staging directory is gone, only the destinations are left
+    assert_eq!(std::fs::read_dir(output.path())?.count(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_entry_paths_under_both_path_semantics() {
+    let typed = |path: &str| {
+        (
+            MaybeWindowsPath(path.to_string())
+                .into_typed::<Utf8UnixEncoding>()
+                .into_string(),
+            MaybeWindowsPath(path.to_string())
+                .into_typed::<Utf8WindowsEncoding>()
+                .into_string(),
+        )
+    };
+    assert_eq!(
+        typed(r"textures\armor\iron.dds"),
+        ("textures/armor/iron.dds".into(), r"textures\armor\iron.dds".into())
+    );
+    assert_eq!(
+        typed(r"textures\\armor\\iron.dds"),
+        ("textures/armor/iron.dds".into(), r"textures\armor\iron.dds".into())
+    );
+    assert_eq!(
+        typed("meshes/mixed\\file.nif"),
+        ("meshes/mixed/file.nif".into(), r"meshes\mixed\file.nif".into())
+    );
+    assert_eq!(
+        typed("with some whitespace/file.json"),
+        ("with some whitespace/file.json".into(), r"with some whitespace\file.json".into())
+    );
+    assert_eq!(typed("plain.esp"), ("plain.esp".into(), "plain.esp".into()));
+}